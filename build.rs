@@ -0,0 +1,5 @@
+fn main() {
+    // Only the gRPC server needs generated code, so default builds do not need protoc
+    #[cfg(feature = "grpc")]
+    tonic_build::compile_protos("proto/node.proto").expect("Could not compile proto/node.proto");
+}
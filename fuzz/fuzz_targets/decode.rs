@@ -0,0 +1,12 @@
+// Gossip frames come straight from peers. Decoding must fail cleanly on anything malformed, and
+// whatever decodes is a canonical encoding, so it encodes back to the same bytes
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use zk_consensus::types::ConsensusMessage;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(message) = ConsensusMessage::from_wire(data) {
+        assert_eq!(message.to_wire(), data);
+    }
+});
@@ -0,0 +1,117 @@
+use crate::consensus::ConsensusError;
+use crate::logging::LogFilterHandle;
+use crate::network::NetworkError;
+use crate::rpc;
+use crate::types::BlockHash;
+use anyhow::Result;
+use serde_json::{json, Value};
+use std::io::Write;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::sync::{mpsc, oneshot};
+
+const HELP: &str = "\
+peers                    connected peers and their scores
+ban <peer-id> [secs]     disconnect a peer and refuse it for secs (default 3600)
+mempool                  pending transactions
+propose-now              propose at once if we are the elected proposer, instead of on the next tick
+resume-production        propose and vote again after halting on a safety violation
+set-log-filter <filter>  a level or RUST_LOG-style directives, e.g. info,zk_consensus::network=debug
+dump-state [height]      live consensus and round state, or the block, finality and epoch of a height
+help                     this list
+quit                     leave the console";
+
+// Handled by the consensus engine between messages
+pub enum ConsensusCommand {
+    // Answers with the height of the block being proposed
+    ProposeNow(oneshot::Sender<Result<u64, ConsensusError>>),
+    RoundState(oneshot::Sender<RoundInfo>),
+    // Lifts a halt after a safety violation; answers with the violations on record
+    ResumeProduction(oneshot::Sender<Result<usize, ConsensusError>>),
+}
+
+// Handled by the network manager
+pub enum NetworkCommand {
+    Ban {
+        peer: String,
+        duration: Duration,
+        reply: oneshot::Sender<Result<(), NetworkError>>,
+    },
+}
+
+// The height being decided and where the BFT protocol stands in it
+#[derive(Debug, Clone)]
+pub struct RoundInfo {
+    pub height: u64,
+    pub round: u64,
+    pub step: String,
+    pub proposed: bool,
+    pub locked: Option<(BlockHash, u64)>,
+    pub valid: Option<(BlockHash, u64)>,
+}
+
+// What the admin_* RPC methods act on; only given to the RPC server with network.admin enabled
+#[derive(Clone)]
+pub struct AdminHandle {
+    pub consensus: mpsc::Sender<ConsensusCommand>,
+    pub network: mpsc::Sender<NetworkCommand>,
+    // None when the program embedding the node did not hand its logging to it
+    pub log_filter: Option<LogFilterHandle>,
+}
+
+// Reads commands from stdin and runs them against the node's JSON-RPC
+pub async fn run_console(url: &str) -> Result<()> {
+    let status = rpc::call(url, "system_status", Value::Null).await?;
+    println!("Connected to {} node {} at {}; type `help` for commands",
+        status["mode"].as_str().unwrap_or("unknown"), status["node_id"].as_str().unwrap_or("-"), url);
+
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    loop {
+        print!("zk> ");
+        std::io::stdout().flush()?;
+        let line = match lines.next_line().await? {
+            Some(line) => line,
+            None => return Ok(()),
+        };
+
+        let (method, params) = match parse_command(&line) {
+            Ok(Some(call)) => call,
+            Ok(None) => continue,
+            Err(message) => {
+                println!("{}", message);
+                continue;
+            }
+        };
+        if method == "quit" {
+            return Ok(());
+        }
+
+        match rpc::call(url, method, params).await {
+            Ok(result) => println!("{}", serde_json::to_string_pretty(&result)?),
+            Err(e) => println!("error: {}", e),
+        }
+    }
+}
+
+// The RPC method and params a console line maps to; None for blank lines
+fn parse_command(line: &str) -> Result<Option<(&'static str, Value)>, String> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let number = |word: &str| word.parse::<u64>().map_err(|_| format!("`{}` is not a number", word));
+
+    let call = match words.as_slice() {
+        [] => return Ok(None),
+        ["help"] => return Err(HELP.to_string()),
+        ["quit"] | ["exit"] => ("quit", Value::Null),
+        ["peers"] => ("system_peers", Value::Null),
+        ["ban", peer] => ("admin_banPeer", json!({ "peer": peer })),
+        ["ban", peer, secs] => ("admin_banPeer", json!({ "peer": peer, "secs": number(secs)? })),
+        ["mempool"] => ("system_mempool", Value::Null),
+        ["propose-now"] => ("admin_proposeNow", Value::Null),
+        ["resume-production"] => ("admin_resumeProduction", Value::Null),
+        ["set-log-filter", filter] => ("admin_setLogFilter", json!({ "filter": filter })),
+        ["dump-state"] => ("admin_dumpState", json!({})),
+        ["dump-state", height] => ("admin_dumpState", json!({ "height": number(height)? })),
+        _ => return Err(format!("Unknown command `{}`, type `help` for the list", line.trim())),
+    };
+    Ok(Some(call))
+}
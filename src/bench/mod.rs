@@ -0,0 +1,250 @@
+use crate::crypto::NodeKeypair;
+use crate::state::{Account, AccountState};
+use crate::types::{self, calculate_merkle_root, Block, BlockHeader, HashFunction, ProofType, Transaction, TransactionKind, ZKProof};
+use crate::zk_proof::{PublicInputs, ZKProofGenerator};
+use anyhow::{bail, Result};
+use chrono::{TimeZone, Utc};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::time::Instant;
+use tracing::{info, warn};
+
+// Values of `bench --backend`; the chain proof backend is measured under "nova"
+pub const BENCH_BACKENDS: [&str; 4] = ["mock", "groth16", "plonk", "nova"];
+
+#[derive(Debug, Clone)]
+pub struct BenchConfig {
+    // Empty runs every backend this build has
+    pub backends: Vec<String>,
+    pub tx_counts: Vec<usize>,
+    pub iterations: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Latency {
+    pub mean_ms: f64,
+    pub min_ms: f64,
+    pub max_ms: f64,
+    pub per_sec: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BenchResult {
+    pub backend: String,
+    pub proof_type: ProofType,
+    pub transactions: usize,
+    pub iterations: usize,
+    pub proof_bytes: usize,
+    pub prove: Latency,
+    pub verify: Latency,
+    // Transactions proven per second
+    pub tx_per_sec: f64,
+}
+
+// Backends compiled into this build. Mock proofs cover both block and chain proofs
+pub fn available_backends() -> Vec<&'static str> {
+    let mut backends = vec!["mock"];
+    if cfg!(feature = "groth16") {
+        backends.push("groth16");
+    }
+    if cfg!(feature = "plonk") {
+        backends.push("plonk");
+    }
+    if cfg!(feature = "nova") {
+        backends.push("nova");
+    }
+    backends
+}
+
+pub async fn run(config: &BenchConfig) -> Result<Vec<BenchResult>> {
+    if config.iterations == 0 {
+        bail!("at least one iteration is needed");
+    }
+    let available = available_backends();
+    let backends: Vec<&str> = match config.backends.is_empty() {
+        true => available.clone(),
+        false => config.backends.iter().map(String::as_str).collect(),
+    };
+    for backend in &backends {
+        if !BENCH_BACKENDS.contains(backend) {
+            bail!("unknown backend {}, expected one of {}", backend, BENCH_BACKENDS.join(", "));
+        }
+        if !available.contains(backend) {
+            bail!("backend {} is not compiled into this build", backend);
+        }
+    }
+    // Groth16 circuits hash the trees with Poseidon, PLONK and Nova ones with SHA-256, and a
+    // process hashes them one way only
+    if backends.contains(&"groth16") {
+        if backends.iter().any(|backend| matches!(*backend, "plonk" | "nova")) {
+            bail!("groth16 proves Poseidon trees, which plonk and nova do not take; benchmark it with --backend groth16 on its own");
+        }
+        types::set_merkle_hash_function(HashFunction::Poseidon)?;
+    }
+
+    let mut results = Vec::new();
+    for backend in backends {
+        let zk_generator = generator(backend)?;
+        let proof_types = match backend {
+            "mock" => vec![ProofType::Groth16, ProofType::Nova],
+            "groth16" => vec![ProofType::Groth16],
+            "plonk" => vec![ProofType::Plonk],
+            _ => vec![ProofType::Nova],
+        };
+
+        for proof_type in proof_types {
+            for &tx_count in &config.tx_counts {
+                let max = zk_generator.max_transactions().unwrap_or(usize::MAX);
+                if tx_count > max {
+                    warn!("⏭️ Skipping {} transactions on {}: its circuits take at most {}", tx_count, backend, max);
+                    continue;
+                }
+                info!("⏱️ Benchmarking {:?} proofs on {} with {} transactions", proof_type, backend, tx_count);
+                results.push(bench(&zk_generator, backend, &proof_type, tx_count, config.iterations).await?);
+            }
+        }
+    }
+    Ok(results)
+}
+
+fn generator(backend: &str) -> Result<ZKProofGenerator> {
+    let zk_generator = match backend {
+        "mock" => ZKProofGenerator::mock(),
+        "plonk" => ZKProofGenerator::new(ProofType::Plonk),
+        // Chain proofs come with the generator of any block backend once nova is compiled in
+        "nova" if cfg!(feature = "plonk") && !cfg!(feature = "groth16") => ZKProofGenerator::new(ProofType::Plonk),
+        _ => ZKProofGenerator::new(ProofType::Groth16),
+    };
+    Ok(zk_generator?)
+}
+
+async fn bench(zk_generator: &ZKProofGenerator, backend: &str, proof_type: &ProofType, tx_count: usize, iterations: usize) -> Result<BenchResult> {
+    // Derived from their index, so every run proves the same transfers
+    let senders: Vec<_> = (0..tx_count)
+        .map(|i| NodeKeypair::from_secret_bytes(&types::hasher().hash(&[b"bench", &(i as u64).to_le_bytes()])))
+        .collect();
+
+    let mut prove_ms = Vec::with_capacity(iterations);
+    let mut verify_ms = Vec::with_capacity(iterations);
+    let mut proof_bytes = 0;
+    for iteration in 0..iterations {
+        // A new block every iteration, so no proof or verification is served from the cache
+        let (block, before, after) = synthetic_block(&senders, iteration as u64);
+        let witness = before.transition(&after);
+        // Outside a chain there is no validator set to commit to
+        let inputs = PublicInputs::new(&block, witness.pre_root, [0; 32]);
+
+        let started = Instant::now();
+        let zk_proof = match proof_type {
+            ProofType::Nova => zk_generator.generate_recursive_proof(None, std::slice::from_ref(&block)).await?,
+            _ => zk_generator.generate_proof(&block, &inputs, &witness).await?,
+        };
+        prove_ms.push(started.elapsed().as_secs_f64() * 1000.0);
+        proof_bytes = zk_proof.proof_data.len();
+
+        let started = Instant::now();
+        let is_valid = match proof_type {
+            ProofType::Nova => zk_generator.verify_chain_proof(&zk_proof).await?,
+            _ => zk_generator.verify_block_proof(&Block { zk_proof, ..block }).await?,
+        };
+        verify_ms.push(started.elapsed().as_secs_f64() * 1000.0);
+        if !is_valid {
+            bail!("{:?} proof on {} did not verify", proof_type, backend);
+        }
+    }
+
+    let prove = latency(&prove_ms);
+    Ok(BenchResult {
+        backend: backend.to_string(),
+        proof_type: proof_type.clone(),
+        transactions: tx_count,
+        iterations,
+        proof_bytes,
+        tx_per_sec: prove.per_sec * tx_count as f64,
+        prove,
+        verify: latency(&verify_ms),
+    })
+}
+
+// Block 1 on a made-up parent in which every sender pays one transfer, with the accounts
+// before and after it
+fn synthetic_block(senders: &[NodeKeypair], nonce: u64) -> (Block, AccountState, AccountState) {
+    let proposer = [0xbe; 32];
+    let parent_hash = [0x01; 32];
+    let timestamp = Utc.timestamp_opt(1_700_000_000 + nonce as i64, 0).unwrap();
+
+    let funded: BTreeMap<_, _> = senders.iter()
+        .map(|sender| (sender.node_id(), Account { balance: 1_000_000, nonce: 0 }))
+        .collect();
+    let before = AccountState::new(funded);
+    let mut after = before.clone();
+    after.begin_block(&parent_hash, &proposer, &[]);
+
+    let mut transactions = Vec::with_capacity(senders.len());
+    for (index, sender) in senders.iter().enumerate() {
+        let mut tx = Transaction {
+            id: [0; 32],
+            from: sender.node_id(),
+            to: senders[(index + 1) % senders.len()].node_id(),
+            amount: 10,
+            fee: 1,
+            nonce: 0,
+            chain_id: types::chain_id(),
+            timestamp,
+            kind: TransactionKind::Transfer,
+            valid_until: None,
+            signature: vec![],
+        };
+        tx.id = types::hasher().hash(&[&tx.from, &nonce.to_le_bytes()]);
+        tx.signature = sender.sign(&tx.signing_hash());
+        // Every sender is funded and spends its nonce 0, so each transfer applies
+        if after.apply_transaction(&tx, &proposer).is_ok() {
+            transactions.push(tx);
+        }
+    }
+
+    let block = Block {
+        header: BlockHeader {
+            block_number: 1,
+            parent_hash,
+            timestamp,
+            merkle_root: calculate_merkle_root(&transactions),
+            state_root: after.root(),
+            votes_root: types::votes_root(&[]),
+            validator: proposer,
+            difficulty: 1000,
+            nonce,
+        },
+        transactions,
+        parent_votes: Vec::new(),
+        zk_proof: ZKProof {
+            proof_data: vec![],
+            public_inputs: vec![],
+            circuit_version: 0,
+            proof_type: ProofType::Groth16,
+        },
+        signature: vec![],
+    };
+    (block, before, after)
+}
+
+fn latency(samples_ms: &[f64]) -> Latency {
+    let mean_ms = samples_ms.iter().sum::<f64>() / samples_ms.len() as f64;
+    Latency {
+        mean_ms,
+        min_ms: samples_ms.iter().copied().fold(f64::INFINITY, f64::min),
+        max_ms: samples_ms.iter().copied().fold(0.0, f64::max),
+        per_sec: if mean_ms > 0.0 { 1000.0 / mean_ms } else { f64::INFINITY },
+    }
+}
+
+pub fn print_table(results: &[BenchResult]) {
+    println!("{:<8} {:<8} {:>6} {:>11} {:>11} {:>10} {:>11} {:>10} {:>9}",
+        "backend", "proof", "txs", "prove ms", "prove max", "proofs/s", "verify ms", "verifies/s", "bytes");
+    for result in results {
+        println!("{:<8} {:<8} {:>6} {:>11.2} {:>11.2} {:>10.2} {:>11.2} {:>10.2} {:>9}",
+            result.backend, format!("{:?}", result.proof_type), result.transactions,
+            result.prove.mean_ms, result.prove.max_ms, result.prove.per_sec,
+            result.verify.mean_ms, result.verify.per_sec, result.proof_bytes);
+    }
+}
@@ -0,0 +1,123 @@
+use crate::storage::{StorageError, StorageManager};
+use crate::types::{self, BlockHash, Decode, Encode, FinalityCertificate, LightBlock, NodeId, ProofType};
+use crate::zk_proof::ProofError;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+mod verifier;
+
+pub use verifier::{BridgeVerifier, ForeignHeader};
+
+// Leads the encoded proof, and changes whenever its layout does
+pub const BRIDGE_PROOF_VERSION: u8 = 1;
+
+#[derive(Debug, Error)]
+pub enum BridgeError {
+    #[error("block {0} is not finalized yet")]
+    NotFinalized(u64),
+    #[error("block {0} is not stored")]
+    UnknownBlock(u64),
+    // Blocks the proof policy skips only name the last proven block
+    #[error("block {0} carries no ZK proof, the last proven block before it is {1}")]
+    Unproven(u64, u64),
+    #[error("no finality certificate is stored for block {0}")]
+    NoCertificate(u64),
+    // Pruned along with the block's account history
+    #[error("no validator set is kept for block {0}")]
+    NoValidatorSet(u64),
+    #[error("unsupported bridge proof version {0}, expected {BRIDGE_PROOF_VERSION}")]
+    UnsupportedVersion(u8),
+    #[error("malformed bridge proof: {0:#}")]
+    Malformed(anyhow::Error),
+    #[error("validator set {} is not the trusted one", hex::encode(.0))]
+    UntrustedValidators(BlockHash),
+    #[error("block {0} is not above the verified head #{1}")]
+    Stale(u64, u64),
+    #[error("the finality certificate is not for block {0}")]
+    CertificateMismatch(u64),
+    #[error("invalid finality certificate for block {0}: {1:#}")]
+    InvalidCertificate(u64, anyhow::Error),
+    #[error("block {0} has an invalid proposer signature")]
+    InvalidSignature(u64),
+    #[error("{1:?} proofs of block {0} can not be verified by this build")]
+    UnsupportedProof(u64, ProofType),
+    #[error("the ZK proof of block {0} does not hold")]
+    InvalidProof(u64),
+    #[error(transparent)]
+    Proof(#[from] ProofError),
+    #[error(transparent)]
+    Storage(#[from] StorageError),
+}
+
+pub type Result<T, E = BridgeError> = std::result::Result<T, E>;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct BridgeValidator {
+    pub node_id: NodeId,
+    pub stake: u64,
+}
+
+// Everything a verifier on another chain needs to accept one of our finalized blocks: the
+// header with its ZK proof and proposer signature, the precommits it was finalized with, and
+// the validator set they are counted against. The set hashes to the validator_set_hash of
+// chain_getCheckpoint, which is what the verifier is configured with.
+//
+// Encoded as BRIDGE_PROOF_VERSION followed by the canonical encoding of types::codec, so a
+// verifier contract parses fixed-width little endian fields and u32-prefixed lists
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct BridgeProof {
+    pub block: LightBlock,
+    pub certificate: FinalityCertificate,
+    // Active validators of the consensus state stored with the block, sorted by node id
+    pub validators: Vec<BridgeValidator>,
+}
+
+impl BridgeProof {
+    // Proof for a finalized block that carries a ZK proof of its own
+    pub async fn export(storage: &StorageManager, height: u64) -> Result<Self> {
+        let finalized = storage.get_finalized_block().await?.map_or(0, |block| block.header.block_number);
+        if height > finalized {
+            return Err(BridgeError::NotFinalized(height));
+        }
+        let block = match storage.get_block(height).await? {
+            Some(block) => block,
+            None => return Err(BridgeError::UnknownBlock(height)),
+        };
+        if let Some((proven, _)) = block.zk_proof.checkpoint_reference() {
+            return Err(BridgeError::Unproven(height, proven));
+        }
+        let certificate = match storage.get_certificate(&block.hash()).await? {
+            Some(certificate) => certificate,
+            None => return Err(BridgeError::NoCertificate(height)),
+        };
+        let state = match storage.get_consensus_state_at(height).await? {
+            Some(state) => state,
+            None => return Err(BridgeError::NoValidatorSet(height)),
+        };
+
+        let mut validators: Vec<BridgeValidator> = state.validators.iter()
+            .filter(|(_, info)| info.is_active)
+            .map(|(node_id, info)| BridgeValidator { node_id: *node_id, stake: info.stake })
+            .collect();
+        validators.sort_by_key(|validator| validator.node_id);
+        Ok(Self { block: block.light(), certificate, validators })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = vec![BRIDGE_PROOF_VERSION];
+        self.encode_to(&mut out);
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        match bytes.split_first() {
+            Some((&BRIDGE_PROOF_VERSION, proof)) => Self::decode(proof).map_err(BridgeError::Malformed),
+            Some((version, _)) => Err(BridgeError::UnsupportedVersion(*version)),
+            None => Err(BridgeError::Malformed(anyhow::anyhow!("empty proof"))),
+        }
+    }
+
+    pub fn validator_set_hash(&self) -> BlockHash {
+        types::hash_validator_set(self.validators.iter().map(|validator| (validator.node_id, validator.stake)).collect())
+    }
+}
@@ -0,0 +1,96 @@
+use super::{BridgeError, BridgeProof, Result};
+use crate::consensus;
+use crate::crypto;
+use crate::types::{BlockHash, ConsensusParams, ProofType, TrustedCheckpoint};
+use crate::zk_proof::ZKProofGenerator;
+use serde::Serialize;
+use std::collections::HashMap;
+use tracing::info;
+
+// A header of the other chain whose finality and proof were checked
+#[derive(Debug, Clone, Serialize)]
+pub struct ForeignHeader {
+    pub block_number: u64,
+    pub block_hash: BlockHash,
+    // Account state after the block, for checking account proofs against
+    pub state_root: BlockHash,
+}
+
+// Imports headers of another chain running this protocol from relayed bridge proofs, as a
+// verifier contract would. It starts from a trusted checkpoint of that chain and accepts any
+// later block whose certificate carries more than 2/3 of the checkpoint's validator set and
+// whose ZK proof holds; blocks in between may be skipped. The chain must use the same hash
+// functions as this node. Validator set changes are not followed: proofs are counted against
+// the checkpoint's set only, so once it changes a newer checkpoint has to be trusted
+pub struct BridgeVerifier {
+    zk_generator: ZKProofGenerator,
+    checkpoint: TrustedCheckpoint,
+    head: ForeignHeader,
+}
+
+impl BridgeVerifier {
+    pub fn new(zk_generator: ZKProofGenerator, checkpoint: TrustedCheckpoint) -> Self {
+        let head = ForeignHeader {
+            block_number: checkpoint.height,
+            block_hash: checkpoint.block_hash,
+            state_root: [0; 32],
+        };
+        Self { zk_generator, checkpoint, head }
+    }
+
+    // Latest imported header; the checkpoint until a proof was imported
+    pub fn head(&self) -> &ForeignHeader {
+        &self.head
+    }
+
+    pub async fn import(&mut self, proof: &BridgeProof) -> Result<ForeignHeader> {
+        let header = &proof.block.header;
+        let block_number = header.block_number;
+        let block_hash = header.hash();
+
+        let validator_set_hash = proof.validator_set_hash();
+        if validator_set_hash != self.checkpoint.validator_set_hash {
+            return Err(BridgeError::UntrustedValidators(validator_set_hash));
+        }
+        if block_number <= self.head.block_number {
+            return Err(BridgeError::Stale(block_number, self.head.block_number));
+        }
+
+        let certificate = &proof.certificate;
+        if certificate.block_hash != block_hash || certificate.block_number != block_number {
+            return Err(BridgeError::CertificateMismatch(block_number));
+        }
+        let stakes: HashMap<_, _> = proof.validators.iter()
+            .map(|validator| (validator.node_id, validator.stake))
+            .collect();
+        let total = stakes.values().sum();
+        // Another chain's parameters are not known here, so its quorum is taken to be two
+        // thirds, the least any chain may set
+        let params = ConsensusParams::default();
+        if let Err(e) = consensus::verify_precommits(certificate, |node_id| stakes.get(node_id).copied(), total, &params) {
+            return Err(BridgeError::InvalidCertificate(block_number, e));
+        }
+
+        if !crypto::verify_signature(&header.validator, &proof.block.signing_hash(), &proof.block.signature) {
+            return Err(BridgeError::InvalidSignature(block_number));
+        }
+        let proof_type = &proof.block.zk_proof.proof_type;
+        if *proof_type == ProofType::Checkpoint {
+            let proven = proof.block.zk_proof.checkpoint_reference().map_or(0, |(proven, _)| proven);
+            return Err(BridgeError::Unproven(block_number, proven));
+        }
+        if !self.zk_generator.can_verify(proof_type) {
+            return Err(BridgeError::UnsupportedProof(block_number, proof_type.clone()));
+        }
+        // The state root is only known for headers imported after the checkpoint
+        let parent_state_root = (header.parent_hash == self.head.block_hash && self.head.block_number != self.checkpoint.height)
+            .then_some(&self.head.state_root);
+        if !self.zk_generator.verify_header_proof(header, parent_state_root, &proof.block.zk_proof).await? {
+            return Err(BridgeError::InvalidProof(block_number));
+        }
+
+        self.head = ForeignHeader { block_number, block_hash, state_root: header.state_root };
+        info!("🌉 Imported foreign block #{} {}", block_number, hex::encode(block_hash));
+        Ok(self.head.clone())
+    }
+}
@@ -0,0 +1,334 @@
+use crate::shutdown::ShutdownSignal;
+use anyhow::{bail, Result};
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::net::{lookup_host, UdpSocket};
+use tokio::sync::RwLock;
+use tokio::task::JoinSet;
+use tracing::{info, debug, warn};
+
+// Seconds between the NTP epoch (1900) and the Unix epoch (1970)
+const NTP_UNIX_OFFSET: i64 = 2_208_988_800;
+const NTP_PACKET_SIZE: usize = 48;
+const NTP_TIMEOUT_SECS: u64 = 5;
+const NTP_PORT: u16 = 123;
+// Addresses queried per configured server, so a pool name alone gives several answers to take
+// the median of
+const ADDRESSES_PER_SERVER: usize = 4;
+// A clock this far off is more likely a wrong answer than a wrong clock, so it is reported but
+// not corrected
+const MAX_CORRECTION_SECS: i64 = 600;
+const CHECK_INTERVAL_SECS: u64 = 60;
+
+// Time as the node sees it. Consensus reads it through this trait, so the simulator can move
+// time itself instead of the system clock
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+// The system time read once at startup and advanced by a monotonic timer from then on, so
+// setting the system clock does not move it. NTP measurements correct it, but it never goes
+// back: after a correction towards the past it stands still until real time catches up
+pub struct SystemClock {
+    started_at: Instant,
+    started: DateTime<Utc>,
+    // From the last NTP measurement
+    correction_ms: AtomicI64,
+    // Latest time handed out, in milliseconds since the Unix epoch
+    last_ms: AtomicI64,
+}
+
+impl SystemClock {
+    pub fn new() -> Self {
+        Self {
+            started_at: Instant::now(),
+            started: Utc::now(),
+            correction_ms: AtomicI64::new(0),
+            last_ms: AtomicI64::new(i64::MIN),
+        }
+    }
+
+    // What NTP offsets are measured against
+    fn uncorrected(&self) -> DateTime<Utc> {
+        self.started + Duration::from_std(self.started_at.elapsed()).unwrap_or_else(|_| Duration::zero())
+    }
+
+    fn correct(&self, offset: Duration) {
+        self.correction_ms.store(offset.num_milliseconds(), Ordering::Relaxed);
+    }
+}
+
+impl Default for SystemClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        let now = self.uncorrected() + Duration::milliseconds(self.correction_ms.load(Ordering::Relaxed));
+        let ms = now.timestamp_millis();
+        let last = self.last_ms.fetch_max(ms, Ordering::Relaxed);
+        match Utc.timestamp_millis_opt(last.max(ms)).single() {
+            Some(now) => now,
+            None => now,
+        }
+    }
+}
+
+// Stands still until set_time moves it
+#[cfg(feature = "testing")]
+pub struct SimulatedClock {
+    now: std::sync::Mutex<DateTime<Utc>>,
+}
+
+#[cfg(feature = "testing")]
+impl SimulatedClock {
+    pub fn new(start: DateTime<Utc>) -> Self {
+        Self { now: std::sync::Mutex::new(start) }
+    }
+
+    pub fn set_time(&self, now: DateTime<Utc>) {
+        if let Ok(mut current) = self.now.lock() {
+            *current = now;
+        }
+    }
+}
+
+#[cfg(feature = "testing")]
+impl Clock for SimulatedClock {
+    fn now(&self) -> DateTime<Utc> {
+        match self.now.lock() {
+            Ok(now) => *now,
+            Err(poisoned) => *poisoned.into_inner(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum HealthStatus {
+    Healthy,
+    Warning,
+    Unknown,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClockHealth {
+    pub status: HealthStatus,
+    pub offset_ms: Option<i64>,
+    pub server: Option<String>,
+    pub last_checked: Option<DateTime<Utc>>,
+    pub message: String,
+}
+
+#[derive(Debug, Clone)]
+struct SkewSample {
+    offset: Duration,
+    server: String,
+    checked_at: DateTime<Utc>,
+}
+
+// Measures the offset of our clock to NTP servers, corrects the clock by it, and reports it
+pub struct ClockSkewMonitor {
+    servers: Vec<String>,
+    warn_threshold: Duration,
+    latest: Arc<RwLock<Option<SkewSample>>>,
+    clock: Arc<SystemClock>,
+}
+
+impl ClockSkewMonitor {
+    pub fn new(servers: Vec<String>, warn_threshold: Duration) -> Self {
+        info!("🕰️ Initializing clock skew monitor ({} NTP servers)", servers.len());
+
+        Self {
+            servers,
+            warn_threshold,
+            latest: Arc::new(RwLock::new(None)),
+            clock: Arc::new(SystemClock::new()),
+        }
+    }
+
+    // Corrected by the last measured NTP offset
+    pub fn clock(&self) -> Arc<SystemClock> {
+        self.clock.clone()
+    }
+
+    pub async fn health_check(&self) -> ClockHealth {
+        let latest = self.latest.read().await;
+
+        match latest.as_ref() {
+            None => ClockHealth {
+                status: HealthStatus::Unknown,
+                offset_ms: None,
+                server: None,
+                last_checked: None,
+                message: "clock skew has not been measured yet".to_string(),
+            },
+            Some(sample) => {
+                let skewed = sample.offset.num_milliseconds().abs() > self.warn_threshold.num_milliseconds();
+                ClockHealth {
+                    status: if skewed { HealthStatus::Warning } else { HealthStatus::Healthy },
+                    offset_ms: Some(sample.offset.num_milliseconds()),
+                    server: Some(sample.server.clone()),
+                    last_checked: Some(sample.checked_at),
+                    message: if skewed {
+                        format!("local clock is off by {}ms (threshold {}ms)",
+                            sample.offset.num_milliseconds(), self.warn_threshold.num_milliseconds())
+                    } else {
+                        "local clock is in sync".to_string()
+                    },
+                }
+            }
+        }
+    }
+
+    // Queries every server at once and goes by the median offset, which a minority of wrong
+    // or lying servers can not move far
+    pub async fn check(&self) -> Result<Duration> {
+        let mut queries = JoinSet::new();
+        for server in &self.servers {
+            let addrs = match resolve(server).await {
+                Ok(addrs) => addrs,
+                Err(e) => {
+                    debug!("NTP server {} does not resolve: {}", server, e);
+                    continue;
+                }
+            };
+            for addr in addrs.into_iter().take(ADDRESSES_PER_SERVER) {
+                let (server, clock) = (format!("{} ({})", server, addr), self.clock.clone());
+                queries.spawn(async move { (query_ntp_offset(addr, &clock).await, server) });
+            }
+        }
+
+        let mut samples = Vec::new();
+        while let Some(joined) = queries.join_next().await {
+            match joined {
+                Ok((Ok(offset), server)) => {
+                    debug!("NTP offset from {}: {}ms", server, offset.num_milliseconds());
+                    samples.push((offset, server));
+                }
+                Ok((Err(e), server)) => debug!("NTP query to {} failed: {}", server, e),
+                Err(e) => debug!("NTP query did not finish: {}", e),
+            }
+        }
+        samples.sort_by_key(|(offset, _)| *offset);
+        let (offset, server) = match samples.get(samples.len().saturating_sub(1) / 2) {
+            Some(median) => median.clone(),
+            None => bail!("no NTP server responded"),
+        };
+
+        if offset.num_seconds().abs() > MAX_CORRECTION_SECS {
+            warn!("⚠️ Not correcting the clock by {}ms, more than {}s: check the system clock and NTP servers",
+                offset.num_milliseconds(), MAX_CORRECTION_SECS);
+        } else {
+            self.clock.correct(offset);
+        }
+
+        let mut latest = self.latest.write().await;
+        *latest = Some(SkewSample {
+            offset,
+            server,
+            checked_at: Utc::now(),
+        });
+        Ok(offset)
+    }
+
+    pub async fn run(&self, mut shutdown: ShutdownSignal) -> Result<()> {
+        if self.servers.is_empty() {
+            info!("🕰️ No NTP servers configured, the clock is not corrected");
+            return Ok(());
+        }
+        loop {
+            match self.check().await {
+                Ok(_) => {
+                    let health = self.health_check().await;
+                    if health.status == HealthStatus::Warning {
+                        warn!("⚠️ Clock skew detected: {}", health.message);
+                    }
+                }
+                Err(e) => warn!("⚠️ Clock skew check failed: {}", e),
+            }
+
+            tokio::select! {
+                _ = tokio::time::sleep(tokio::time::Duration::from_secs(CHECK_INTERVAL_SECS)) => {}
+                _ = shutdown.recv() => return Ok(()),
+            }
+        }
+    }
+}
+
+// Servers are given as `host`, `host:port`, an IP address, or `[ipv6]:port`
+async fn resolve(server: &str) -> Result<Vec<SocketAddr>> {
+    if let Ok(addr) = server.parse::<SocketAddr>() {
+        return Ok(vec![addr]);
+    }
+    if let Ok(ip) = server.parse::<IpAddr>() {
+        return Ok(vec![SocketAddr::new(ip, NTP_PORT)]);
+    }
+    let (host, port) = match server.rsplit_once(':') {
+        Some((host, port)) => (host, port.parse()?),
+        None => (server, NTP_PORT),
+    };
+    Ok(lookup_host((host, port)).await?.collect())
+}
+
+async fn query_ntp_offset(addr: SocketAddr, clock: &SystemClock) -> Result<Duration> {
+    let socket = UdpSocket::bind(if addr.is_ipv6() { "[::]:0" } else { "0.0.0.0:0" }).await?;
+    socket.connect(addr).await?;
+
+    // LI = 0, VN = 3, Mode = 3 (client). The transmit timestamp is random rather than our
+    // time: the server echoes it as the origin timestamp, which tells its reply from a forged one
+    let mut request = [0u8; NTP_PACKET_SIZE];
+    request[0] = 0x1B;
+    let origin: [u8; 8] = rand::random();
+    request[40..48].copy_from_slice(&origin);
+
+    let sent_at = clock.uncorrected();
+    socket.send(&request).await?;
+
+    let mut response = [0u8; NTP_PACKET_SIZE];
+    let received = tokio::time::timeout(
+        tokio::time::Duration::from_secs(NTP_TIMEOUT_SECS),
+        socket.recv(&mut response),
+    ).await??;
+    let received_at = clock.uncorrected();
+
+    if received < NTP_PACKET_SIZE {
+        bail!("short NTP response ({} bytes)", received);
+    }
+    if response[24..32] != origin {
+        bail!("NTP response does not answer our request");
+    }
+    // Mode 4 is a server's reply; leap indicator 3 and stratum 0 (a kiss-o'-death) or above 15
+    // mean the server has no time to give
+    if response[0] & 0x07 != 4 {
+        bail!("NTP response has mode {}", response[0] & 0x07);
+    }
+    if response[0] >> 6 == 3 {
+        bail!("NTP server is not synchronized");
+    }
+    if !(1..=15).contains(&response[1]) {
+        bail!("NTP response has stratum {}", response[1]);
+    }
+
+    let server_received = parse_ntp_timestamp(&response[32..40])?;
+    let server_transmitted = parse_ntp_timestamp(&response[40..48])?;
+
+    // Standard SNTP offset: ((t2 - t1) + (t3 - t4)) / 2
+    Ok(((server_received - sent_at) + (server_transmitted - received_at)) / 2)
+}
+
+fn parse_ntp_timestamp(bytes: &[u8]) -> Result<DateTime<Utc>> {
+    let seconds = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as i64;
+    let fraction = u32::from_be_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]) as u64;
+    let nanos = ((fraction * 1_000_000_000) >> 32) as u32;
+
+    match Utc.timestamp_opt(seconds - NTP_UNIX_OFFSET, nanos).single() {
+        Some(timestamp) => Ok(timestamp),
+        None => bail!("invalid NTP timestamp"),
+    }
+}
@@ -0,0 +1,543 @@
+use crate::consensus;
+use crate::genesis::Genesis;
+use crate::logging;
+use crate::network::{self, TransportSecurity};
+use crate::staking;
+use crate::storage;
+use crate::types::{BlockLimits, LivenessPolicy, ProofPolicy, TrustedCheckpoint};
+use crate::zk_proof;
+use anyhow::{bail, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+pub const NODE_MODES: [&str; 3] = ["validator", "full_node", "light_client"];
+
+// Every key is optional; missing sections and keys fall back to the defaults below
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct NodeConfig {
+    pub mode: String,
+    pub key_file: PathBuf,
+    // Encrypted validator key written by `keys generate` or `keys import`; replaces key_file when set
+    pub keystore: Option<PathBuf>,
+    // genesis.json of the chain to join; without one the first validator bootstraps a new chain
+    pub genesis: Option<PathBuf>,
+    // Trusted checkpoint as height:block_hash:validator_set_hash, from chain_getCheckpoint on a
+    // node you trust. New nodes sync from it instead of genesis and refuse chains without it
+    pub checkpoint: Option<String>,
+    pub consensus: ConsensusConfig,
+    pub network: NetworkConfig,
+    pub storage: StorageConfig,
+    pub zk_proof: ZkProofConfig,
+    pub mempool: MempoolConfig,
+    pub clock: ClockConfig,
+    pub snapshot: SnapshotConfig,
+    pub log: LogConfig,
+    // Set by dev, for the checks only a development chain is exempt from
+    #[serde(skip)]
+    pub dev: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ConsensusConfig {
+    // Seconds between blocks, which is also the length of a BFT round
+    pub block_time: u64,
+    // How far ahead of our clock a block timestamp may be, in seconds
+    pub max_future_drift: u64,
+    // Stake bonded when running as a validator
+    pub stake: u64,
+    // max_block_bytes, max_txs_per_block and max_tx_bytes; a genesis file sets them for the chain
+    #[serde(flatten)]
+    pub limits: BlockLimits,
+    // proof_interval and deferred_proofs, which blocks carry a proof; a genesis file sets them for the chain
+    #[serde(flatten)]
+    pub proofs: ProofPolicy,
+    // max_missed_slots, max_missed_votes and jail_epochs, when offline validators are jailed; a
+    // genesis file sets them for the chain
+    #[serde(flatten)]
+    pub liveness: LivenessPolicy,
+    // Threads checking block, transaction and vote signatures off the consensus loop
+    pub signature_workers: usize,
+    // Propose a block only once transactions are pending instead of in every slot; for chains
+    // with this node as their only validator, so only --dev or a single-validator genesis take it
+    pub instant_seal: bool,
+    // With instant_seal, seconds after the last block when an empty one is sealed anyway; 0
+    // never seals empty blocks
+    pub seal_interval: u64,
+    // "halt" stops proposing and voting once the node finds the chain's safety broken, until an
+    // operator resumes it; "alarm" only reports it
+    pub on_safety_violation: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct NetworkConfig {
+    pub port: u16,
+    pub bootstrap_nodes: Vec<String>,
+    // JSON-RPC listens on localhost only
+    pub rpc_port: u16,
+    // Read-only explorer REST API, also on localhost only; 0 disables it
+    pub explorer_port: u16,
+    // gRPC API on localhost, in builds with the grpc feature; 0 disables it
+    pub grpc_port: u16,
+    // Serve the admin_* JSON-RPC methods used by the `console` subcommand: banning peers,
+    // forcing a proposal, changing the log level and dumping consensus state
+    pub admin: bool,
+    // Connections kept at most, inbound and outbound together
+    pub max_peers: u32,
+    // Find peers on the local network through mDNS, next to bootstrap nodes and the peers
+    // remembered from earlier runs
+    pub mdns: bool,
+    // Deflate gossiped messages once every connected peer accepts it
+    pub compression: bool,
+    // Largest message sent or accepted, after decompression; blocks must fit in it
+    pub max_message_bytes: usize,
+    // Connection encryption offered to peers, preferred first: "noise", "tls" (in builds with
+    // the tls feature) or both. Either authenticates peers with their node key
+    pub security: Vec<String>,
+    pub rate_limits: RateLimitConfig,
+    // Addresses peers reach us at from outside, such as /ip4/203.0.113.7/tcp/8080 for a port
+    // forwarded by hand; advertised to peers next to the ones we listen on
+    pub external_addresses: Vec<String>,
+    pub nat: NatConfig,
+}
+
+// Getting through a NAT for nodes that can not forward a port themselves, such as validators
+// on a home connection
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct NatConfig {
+    // Ask the gateway to map our port over UPnP, and over NAT-PMP if no UPnP gateway answers
+    pub upnp: bool,
+    pub nat_pmp: bool,
+    // Relays to reserve a slot on and be reachable through, as multiaddrs ending in
+    // /p2p/<peer id>; peers that can not dial us reach us over a circuit instead
+    pub relays: Vec<String>,
+    // Relay circuits for other peers; for nodes with a public address
+    pub relay_server: bool,
+    // Replace relayed connections with direct ones by dialing each other at the same time (DCUtR)
+    pub hole_punching: bool,
+}
+
+// Gossip budgets in messages per second, for each peer and for all peers together; 0 disables
+// a limit. Blocks include compact blocks, votes include proposals and round changes, and
+// transactions are requests for a block's transactions and the replies
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct RateLimitConfig {
+    pub blocks_per_peer: u32,
+    pub blocks_global: u32,
+    pub votes_per_peer: u32,
+    pub votes_global: u32,
+    pub proof_requests_per_peer: u32,
+    pub proof_requests_global: u32,
+    pub transactions_per_peer: u32,
+    pub transactions_global: u32,
+    // Messages over budget a peer may send within ten seconds before it is banned for ban_secs
+    pub violations_before_ban: u32,
+    pub ban_secs: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct StorageConfig {
+    pub db_path: String,
+    // RocksDB's limit on open table files, -1 for unlimited
+    pub max_open_files: i32,
+    // "archive" keeps every block; "pruned" drops the transactions, receipts and votes of
+    // finalized blocks more than keep_blocks deep, keeping headers and proofs
+    pub pruning: String,
+    pub keep_blocks: u64,
+    // Seconds between full compactions, which return space freed by pruning to the disk; 0
+    // leaves compaction to RocksDB
+    pub compaction_interval_secs: u64,
+    // Database size in bytes above which a warning is logged; 0 disables the alarm
+    pub disk_alarm_bytes: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ZkProofConfig {
+    // Use mock proofs even in builds with a proving feature
+    pub mock: bool,
+    // Proof system for our own blocks; blocks from others are verified with any compiled-in backend
+    pub backend: String,
+    // Threads generating block proofs, and how many jobs may wait for one
+    pub workers: usize,
+    pub queue_size: usize,
+    // Proofs generated for peers that ask for one: blocks waiting at most, requests each peer
+    // may have open, and proof workers they may take at once
+    pub request_queue: usize,
+    pub requests_per_peer: usize,
+    pub request_workers: usize,
+    // "prover" loads proving keys, "verifier" only verifying keys; "auto" proves on validators
+    pub role: String,
+    // Groth16 key files written by `export-keys`, such as those of a trusted setup; without
+    // it the keys come from the development setup
+    pub key_dir: Option<PathBuf>,
+    // http(s) base URLs the key files key_dir lacks are downloaded from, as <url>/<file>
+    pub key_mirrors: Vec<String>,
+    // SHA-256 of the key files, from the genesis file
+    #[serde(skip)]
+    pub artifact_hashes: BTreeMap<String, [u8; 32]>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ClockConfig {
+    pub ntp_servers: Vec<String>,
+    // Skew above which health checks report a warning
+    pub max_skew_ms: i64,
+}
+
+// Only applies to transactions submitted over the APIs; the node's own staking
+// transactions skip both checks
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct MempoolConfig {
+    // Lowest fee a transaction has to pay to be accepted, 0 accepts any
+    pub min_fee: u64,
+    // Once this many transactions are pending, a new one has to outbid the cheapest to get in
+    pub max_transactions: usize,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct SnapshotConfig {
+    // http(s)://host/path or ipfs://api-host:port[/ipns-name]
+    pub mirrors: Vec<String>,
+    // Publish a snapshot every N blocks, 0 disables publishing
+    pub interval: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct LogConfig {
+    // RUST_LOG-style directives such as "info,zk_consensus::network=debug"; RUST_LOG replaces them
+    pub filter: String,
+    // "text" or "json", one object per line for log aggregation
+    pub format: String,
+    // Also write the log to files in this directory, rotated "hourly", "daily" or "never"
+    pub dir: Option<PathBuf>,
+    pub rotation: String,
+}
+
+impl Default for NodeConfig {
+    fn default() -> Self {
+        Self {
+            mode: "validator".to_string(),
+            key_file: PathBuf::from("node_key"),
+            keystore: None,
+            genesis: None,
+            checkpoint: None,
+            consensus: ConsensusConfig::default(),
+            network: NetworkConfig::default(),
+            storage: StorageConfig::default(),
+            zk_proof: ZkProofConfig::default(),
+            mempool: MempoolConfig::default(),
+            clock: ClockConfig::default(),
+            snapshot: SnapshotConfig::default(),
+            log: LogConfig::default(),
+            dev: false,
+        }
+    }
+}
+
+impl Default for ConsensusConfig {
+    fn default() -> Self {
+        Self {
+            block_time: 12,
+            max_future_drift: 15,
+            stake: staking::MIN_VALIDATOR_STAKE,
+            limits: BlockLimits::default(),
+            proofs: ProofPolicy::default(),
+            liveness: LivenessPolicy::default(),
+            signature_workers: std::thread::available_parallelism().map_or(1, |n| n.get()),
+            instant_seal: false,
+            seal_interval: 0,
+            on_safety_violation: "halt".to_string(),
+        }
+    }
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        Self {
+            port: 8080,
+            bootstrap_nodes: Vec::new(),
+            rpc_port: 9933,
+            explorer_port: 9934,
+            grpc_port: 9935,
+            admin: false,
+            max_peers: 50,
+            mdns: true,
+            compression: true,
+            max_message_bytes: 4 * 1024 * 1024,
+            security: vec!["noise".to_string()],
+            rate_limits: RateLimitConfig::default(),
+            external_addresses: Vec::new(),
+            nat: NatConfig::default(),
+        }
+    }
+}
+
+impl Default for NatConfig {
+    fn default() -> Self {
+        Self {
+            upnp: true,
+            nat_pmp: true,
+            relays: Vec::new(),
+            relay_server: false,
+            hole_punching: true,
+        }
+    }
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            blocks_per_peer: 5,
+            blocks_global: 50,
+            votes_per_peer: 50,
+            votes_global: 1000,
+            proof_requests_per_peer: 2,
+            proof_requests_global: 20,
+            transactions_per_peer: 20,
+            transactions_global: 200,
+            violations_before_ban: 100,
+            ban_secs: 10 * 60,
+        }
+    }
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        Self {
+            db_path: "zk_consensus.db".to_string(),
+            max_open_files: -1,
+            pruning: "archive".to_string(),
+            keep_blocks: 10_000,
+            compaction_interval_secs: 24 * 60 * 60,
+            disk_alarm_bytes: 0,
+        }
+    }
+}
+
+impl Default for ZkProofConfig {
+    fn default() -> Self {
+        Self {
+            mock: false,
+            backend: "groth16".to_string(),
+            workers: std::thread::available_parallelism().map_or(1, |n| n.get()),
+            queue_size: 16,
+            request_queue: 32,
+            requests_per_peer: 2,
+            request_workers: 1,
+            role: "auto".to_string(),
+            key_dir: None,
+            key_mirrors: Vec::new(),
+            artifact_hashes: BTreeMap::new(),
+        }
+    }
+}
+
+impl Default for ClockConfig {
+    fn default() -> Self {
+        Self {
+            ntp_servers: vec!["pool.ntp.org".to_string()],
+            max_skew_ms: 500,
+        }
+    }
+}
+
+impl Default for MempoolConfig {
+    fn default() -> Self {
+        Self {
+            min_fee: 0,
+            max_transactions: 10_000,
+        }
+    }
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            filter: "info".to_string(),
+            format: "text".to_string(),
+            dir: None,
+            rotation: "daily".to_string(),
+        }
+    }
+}
+
+impl NodeConfig {
+    // Reads a TOML file, or returns the defaults when no file is given
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let path = match path {
+            Some(path) => path,
+            None => return Ok(Self::default()),
+        };
+
+        let config: Self = ::config::Config::builder()
+            .add_source(::config::File::from(path).format(::config::FileFormat::Toml))
+            .build()?
+            .try_deserialize()?;
+        Ok(config)
+    }
+
+    // Validators prove their own blocks; other nodes only verify unless configured to prove
+    pub fn zk_role(&self) -> zk_proof::KeyRole {
+        match (self.zk_proof.role.as_str(), self.mode.as_str()) {
+            ("prover", _) | ("auto", "validator") => zk_proof::KeyRole::Prover,
+            _ => zk_proof::KeyRole::Verifier,
+        }
+    }
+
+    pub fn trusted_checkpoint(&self) -> Result<Option<TrustedCheckpoint>> {
+        self.checkpoint.as_deref().map(TrustedCheckpoint::parse).transpose()
+    }
+
+    // A development chain of its own with this node as the only validator: no peers or
+    // genesis file, mock proofs, one second slots sealed as transactions arrive and no jailing.
+    // It gets its own database and key so the regular ones are left alone
+    pub fn dev(&mut self) {
+        self.dev = true;
+        self.mode = "validator".to_string();
+        self.key_file = PathBuf::from("dev_node_key");
+        self.keystore = None;
+        self.genesis = None;
+        self.checkpoint = None;
+        self.consensus.block_time = 1;
+        self.consensus.instant_seal = true;
+        self.consensus.liveness.max_missed_slots = 0;
+        self.consensus.liveness.max_missed_votes = 0;
+        self.network.bootstrap_nodes.clear();
+        self.network.mdns = false;
+        self.network.nat = NatConfig { upnp: false, nat_pmp: false, hole_punching: false, ..NatConfig::default() };
+        self.storage.db_path = "zk_consensus-dev.db".to_string();
+        self.zk_proof.mock = true;
+        self.clock.ntp_servers.clear();
+        self.snapshot.mirrors.clear();
+    }
+
+    // Checked after CLI overrides are applied, since those may fix or break a file value
+    pub fn validate(&self) -> Result<()> {
+        if !NODE_MODES.contains(&self.mode.as_str()) {
+            bail!("Unknown mode '{}', expected one of {:?}", self.mode, NODE_MODES);
+        }
+        if self.consensus.block_time == 0 {
+            bail!("consensus.block_time must be at least one second");
+        }
+        if self.consensus.signature_workers == 0 {
+            bail!("consensus.signature_workers must be at least 1");
+        }
+        if self.consensus.limits.max_txs_per_block == 0 || self.consensus.limits.max_tx_bytes == 0 {
+            bail!("consensus.max_txs_per_block and consensus.max_tx_bytes must be at least 1");
+        }
+        if self.consensus.instant_seal && self.mode != "validator" {
+            bail!("consensus.instant_seal needs mode validator");
+        }
+        // Rounds never time out with it, so a chain with other validators would stall on them
+        if self.consensus.instant_seal && !self.dev {
+            let validators = match &self.genesis {
+                Some(path) => Genesis::load(path)?.validators.len(),
+                None => 0,
+            };
+            if validators != 1 {
+                bail!("consensus.instant_seal needs --dev or a genesis file with a single validator");
+            }
+        }
+        if !consensus::SAFETY_ACTIONS.contains(&self.consensus.on_safety_violation.as_str()) {
+            bail!("Unknown consensus.on_safety_violation '{}', expected one of {:?}",
+                self.consensus.on_safety_violation, consensus::SAFETY_ACTIONS);
+        }
+        if self.consensus.proofs.proof_interval == 0 {
+            bail!("consensus.proof_interval must be at least 1");
+        }
+        if self.mode == "validator" && self.consensus.stake < staking::MIN_VALIDATOR_STAKE {
+            bail!("consensus.stake of {} is below the minimum validator stake of {}",
+                self.consensus.stake, staking::MIN_VALIDATOR_STAKE);
+        }
+        if !zk_proof::BLOCK_PROOF_BACKENDS.contains(&self.zk_proof.backend.as_str()) {
+            bail!("Unknown zk_proof.backend '{}', expected one of {:?}",
+                self.zk_proof.backend, zk_proof::BLOCK_PROOF_BACKENDS);
+        }
+        if !zk_proof::KEY_ROLES.contains(&self.zk_proof.role.as_str()) {
+            bail!("Unknown zk_proof.role '{}', expected one of {:?}", self.zk_proof.role, zk_proof::KEY_ROLES);
+        }
+        if self.mode == "validator" && self.zk_proof.role == "verifier" {
+            bail!("Validators prove the blocks they propose, so zk_proof.role can not be verifier");
+        }
+        if self.trusted_checkpoint()?.is_some_and(|checkpoint| checkpoint.height == 0) {
+            bail!("checkpoint height must be at least 1");
+        }
+        if self.network.max_peers == 0 {
+            bail!("network.max_peers must be at least 1");
+        }
+        if self.network.security.is_empty() {
+            bail!("network.security must name at least one of {:?}", network::SECURITY_PROTOCOLS);
+        }
+        for (i, name) in self.network.security.iter().enumerate() {
+            if !network::SECURITY_PROTOCOLS.contains(&name.as_str()) {
+                bail!("Unknown network.security '{}', expected one of {:?}", name, network::SECURITY_PROTOCOLS);
+            }
+            if !TransportSecurity::is_compiled_in(name) {
+                bail!("network.security '{}' needs a build with the {} feature", name, name);
+            }
+            if self.network.security[..i].contains(name) {
+                bail!("network.security lists '{}' twice", name);
+            }
+        }
+        for address in &self.network.external_addresses {
+            if address.parse::<libp2p::Multiaddr>().is_err() {
+                bail!("network.external_addresses: '{}' is not a multiaddr", address);
+            }
+        }
+        for relay in &self.network.nat.relays {
+            if network::relay_peer(relay).is_none() {
+                bail!("network.nat.relays: '{}' is not a multiaddr ending in /p2p/<peer id>", relay);
+            }
+        }
+        if !self.zk_proof.key_mirrors.is_empty() && self.zk_proof.key_dir.is_none() {
+            bail!("zk_proof.key_mirrors needs zk_proof.key_dir to download the key files into");
+        }
+        for mirror in &self.zk_proof.key_mirrors {
+            if !mirror.starts_with("http://") && !mirror.starts_with("https://") {
+                bail!("zk_proof.key_mirrors: '{}' is not an http(s) URL", mirror);
+            }
+        }
+        if self.zk_proof.workers == 0 || self.zk_proof.queue_size == 0 {
+            bail!("zk_proof.workers and zk_proof.queue_size must be at least 1");
+        }
+        if self.zk_proof.request_queue == 0 || self.zk_proof.requests_per_peer == 0 || self.zk_proof.request_workers == 0 {
+            bail!("zk_proof.request_queue, zk_proof.requests_per_peer and zk_proof.request_workers must be at least 1");
+        }
+        if self.zk_proof.request_workers > self.zk_proof.workers {
+            bail!("zk_proof.request_workers ({}) can not exceed zk_proof.workers ({})",
+                self.zk_proof.request_workers, self.zk_proof.workers);
+        }
+        if self.mempool.max_transactions == 0 {
+            bail!("mempool.max_transactions must be at least 1");
+        }
+        if self.storage.db_path.is_empty() {
+            bail!("storage.db_path must not be empty");
+        }
+        if !storage::PRUNING_MODES.contains(&self.storage.pruning.as_str()) {
+            bail!("Unknown storage.pruning '{}', expected one of {:?}", self.storage.pruning, storage::PRUNING_MODES);
+        }
+        if self.storage.pruning == "pruned" && self.storage.keep_blocks < storage::MIN_KEEP_BLOCKS {
+            bail!("storage.keep_blocks must be at least {} when pruning", storage::MIN_KEEP_BLOCKS);
+        }
+        if !logging::LOG_FORMATS.contains(&self.log.format.as_str()) {
+            bail!("Unknown log.format '{}', expected one of {:?}", self.log.format, logging::LOG_FORMATS);
+        }
+        if !logging::LOG_ROTATIONS.contains(&self.log.rotation.as_str()) {
+            bail!("Unknown log.rotation '{}', expected one of {:?}", self.log.rotation, logging::LOG_ROTATIONS);
+        }
+        logging::parse_filter(&self.log.filter)?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,657 @@
+use crate::types::{
+    Block, BlockHeader, BlockVote, VoteType, VoteStep, Transaction, TransactionKind, ZKProof, ProofType, MerkleProof, Expiry,
+    BlockHash, Encode, Decode, ConsensusParams, calculate_merkle_root, hash_validator_set, merkle_proof, DEFAULT_CHAIN_ID,
+};
+use crate::crypto::{self, NodeKeypair};
+use crate::staking::{ProposalAction, ProposalTransaction, ProposalVoteTransaction, UnjailTransaction};
+use crate::state::StateTransition;
+use crate::zk_proof::{self, PublicInputs, ZKProofGenerator};
+use anyhow::Result;
+use chrono::{TimeZone, Utc};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use tracing::{info, warn};
+
+const VECTOR_VERSION: u32 = 11;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlockVector {
+    pub version: u32,
+    pub name: String,
+    pub description: String,
+    pub block: Block,
+    pub encoded_header: String,
+    pub encoded_transactions: String,
+    pub encoded_block: String,
+    pub header_hash: String,
+    pub block_hash: String,
+    pub merkle_root: String,
+    pub transaction_hashes: Vec<String>,
+    pub public_inputs: String,
+    pub signing_hash: String,
+    pub expected_signature_valid: bool,
+    pub expected_proof_valid: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VoteVector {
+    pub version: u32,
+    pub name: String,
+    pub description: String,
+    pub vote: BlockVote,
+    pub encoded_vote: String,
+    pub signing_hash: String,
+    pub expected_signature_valid: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MerkleVector {
+    pub version: u32,
+    pub name: String,
+    pub root: String,
+    pub proofs: Vec<MerkleProofVector>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MerkleProofVector {
+    pub proof: MerkleProof,
+    pub expected_valid: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChainVector {
+    pub version: u32,
+    pub name: String,
+    pub description: String,
+    pub blocks: Vec<Block>,
+    pub chain_proof: ZKProof,
+    pub public_inputs: String,
+    pub expected_proof_valid: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HeaderVector {
+    pub version: u32,
+    pub name: String,
+    pub description: String,
+    pub header: BlockHeader,
+    // Proof of the whole block, checked with the header alone
+    pub zk_proof: ZKProof,
+    pub expected_proof_valid: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InputsVector {
+    pub version: u32,
+    pub name: String,
+    pub description: String,
+    pub encoded: String,
+    // None when the encoding must be rejected
+    pub inputs: Option<PublicInputs>,
+    // Decimal, as JSON numbers do not reach 2^128
+    pub field_elements: Vec<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct ConformanceReport {
+    pub passed: usize,
+    pub failed: Vec<String>,
+}
+
+impl ConformanceReport {
+    fn record(&mut self, vector: &str, check: &str, ok: bool) {
+        if ok {
+            self.passed += 1;
+        } else {
+            self.failed.push(format!("{}: {}", vector, check));
+        }
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.failed.is_empty()
+    }
+}
+
+fn fixture_transaction(seed: u8, amount: u64) -> Transaction {
+    Transaction {
+        id: [seed; 32],
+        from: [seed.wrapping_add(1); 32],
+        to: [seed.wrapping_add(2); 32],
+        amount,
+        fee: seed as u64,
+        nonce: seed as u64,
+        chain_id: DEFAULT_CHAIN_ID,
+        timestamp: Utc.timestamp_opt(1_700_000_000 + seed as i64, 0).unwrap(),
+        kind: TransactionKind::Transfer,
+        valid_until: None,
+        signature: vec![seed; 64],
+    }
+}
+
+// Ed25519 signatures are deterministic, so a fixed key yields reproducible vectors
+fn fixture_keypair() -> NodeKeypair {
+    NodeKeypair::from_secret_bytes(&[0x42; 32])
+}
+
+fn fixture_block(block_number: u64, parent_hash: [u8; 32], transactions: Vec<Transaction>) -> Block {
+    Block {
+        header: BlockHeader {
+            block_number,
+            parent_hash,
+            timestamp: Utc.timestamp_opt(1_700_000_000 + block_number as i64 * 12, 0).unwrap(),
+            merkle_root: calculate_merkle_root(&transactions),
+            state_root: [block_number as u8; 32],
+            votes_root: [0; 32],
+            validator: fixture_keypair().node_id(),
+            difficulty: 1000,
+            nonce: block_number,
+        },
+        transactions,
+        parent_votes: Vec::new(),
+        zk_proof: ZKProof {
+            proof_data: vec![],
+            public_inputs: vec![],
+            circuit_version: 0,
+            proof_type: ProofType::Groth16,
+        },
+        signature: vec![],
+    }
+}
+
+// The signature covers the proof, so blocks are signed once their proof is in place
+fn sign_fixture(mut block: Block) -> Block {
+    block.signature = fixture_keypair().sign(&block.signing_hash());
+    block
+}
+
+fn fixture_votes(block: &Block) -> Vec<(String, String, BlockVote)> {
+    let keypair = fixture_keypair();
+    let mut approve = BlockVote {
+        block_hash: block.hash(),
+        validator: keypair.node_id(),
+        vote: VoteType::Approve,
+        height: block.header.block_number,
+        round: 0,
+        step: VoteStep::Precommit,
+        timestamp: Utc.timestamp_opt(1_700_000_100, 0).unwrap(),
+        signature: vec![],
+    };
+    approve.signature = keypair.sign(&approve.signing_hash());
+
+    let mut flipped = approve.clone();
+    flipped.vote = VoteType::Reject;
+
+    let mut replayed_round = approve.clone();
+    replayed_round.round = 1;
+
+    let mut wrong_signer = approve.clone();
+    wrong_signer.signature = NodeKeypair::from_secret_bytes(&[0x43; 32]).sign(&approve.signing_hash());
+
+    vec![
+        ("approve".into(), "validly signed approve vote".into(), approve),
+        ("flipped_vote".into(), "vote type changed after signing".into(), flipped),
+        ("replayed_round".into(), "vote replayed into a different round".into(), replayed_round),
+        ("wrong_signer".into(), "vote signed by a key other than the validator's".into(), wrong_signer),
+    ]
+}
+
+// The fixture key as the only validator
+fn fixture_validator_set() -> BlockHash {
+    hash_validator_set(vec![(fixture_keypair().node_id(), 1000)])
+}
+
+// Fixture state roots are made up, so the blocks are proven as changing no account
+async fn fixture_proof(zk_generator: &ZKProofGenerator, block: &Block) -> Result<ZKProof> {
+    let inputs = PublicInputs::new(block, block.header.state_root, fixture_validator_set());
+    Ok(zk_generator.generate_proof(block, &inputs, &StateTransition::unchanged(block.header.state_root)).await?)
+}
+
+async fn fixture_blocks(zk_generator: &ZKProofGenerator) -> Result<Vec<(String, String, Block)>> {
+    let mut genesis = fixture_block(0, [0; 32], vec![]);
+    genesis.zk_proof = fixture_proof(zk_generator, &genesis).await?;
+    let genesis = sign_fixture(genesis);
+
+    let mut single = fixture_block(1, genesis.hash(), vec![fixture_transaction(1, 100)]);
+    single.zk_proof = fixture_proof(zk_generator, &single).await?;
+    let single = sign_fixture(single);
+
+    let odd_txs = (1..=5).map(|i| fixture_transaction(i, i as u64 * 100)).collect();
+    let mut odd = fixture_block(2, single.hash(), odd_txs);
+    odd.zk_proof = fixture_proof(zk_generator, &odd).await?;
+    let odd = sign_fixture(odd);
+
+    // Both kinds of expiry are part of the transaction encoding
+    let mut by_height = fixture_transaction(6, 600);
+    by_height.valid_until = Some(Expiry::Height(10));
+    let mut by_time = fixture_transaction(7, 700);
+    by_time.valid_until = Some(Expiry::Time(Utc.timestamp_opt(1_800_000_000, 0).unwrap()));
+    let mut expiring = fixture_block(3, odd.hash(), vec![by_height, by_time]);
+    expiring.zk_proof = fixture_proof(zk_generator, &expiring).await?;
+    let expiring = sign_fixture(expiring);
+
+    // Unjail transactions move no funds and name the validator they let back in
+    let mut unjail = fixture_transaction(8, 0);
+    unjail.kind = TransactionKind::Unjail(UnjailTransaction { validator: unjail.from });
+    let mut unjailing = fixture_block(3, odd.hash(), vec![unjail]);
+    unjailing.zk_proof = fixture_proof(zk_generator, &unjailing).await?;
+    let unjailing = sign_fixture(unjailing);
+
+    // Governance transactions move no funds either; proposals carry the whole parameter set or
+    // a validator to remove, and votes name the proposal transaction
+    let params = ConsensusParams { block_time: 6, max_validators: 50, ..ConsensusParams::default() };
+    let mut change_params = fixture_transaction(10, 0);
+    change_params.kind = TransactionKind::Proposal(ProposalTransaction {
+        proposer: change_params.from,
+        action: ProposalAction::ChangeParams(params),
+    });
+    let mut remove_validator = fixture_transaction(11, 0);
+    remove_validator.kind = TransactionKind::Proposal(ProposalTransaction {
+        proposer: remove_validator.from,
+        action: ProposalAction::RemoveValidator([13; 32]),
+    });
+    let mut approve = fixture_transaction(12, 0);
+    approve.kind = TransactionKind::ProposalVote(ProposalVoteTransaction { voter: approve.from, proposal: change_params.id, approve: true });
+    let mut governing = fixture_block(3, odd.hash(), vec![change_params, remove_validator, approve]);
+    governing.zk_proof = fixture_proof(zk_generator, &governing).await?;
+    let governing = sign_fixture(governing);
+
+    // Proof generated for a different block must not verify
+    let mut wrong_proof = fixture_block(3, odd.hash(), vec![fixture_transaction(9, 900)]);
+    wrong_proof.zk_proof = odd.zk_proof.clone();
+    let wrong_proof = sign_fixture(wrong_proof);
+
+    let mut truncated_proof = fixture_block(3, odd.hash(), vec![fixture_transaction(9, 900)]);
+    truncated_proof.zk_proof = fixture_proof(zk_generator, &truncated_proof).await?;
+    truncated_proof.zk_proof.proof_data.truncate(32);
+    let truncated_proof = sign_fixture(truncated_proof);
+
+    // Proofs are checked against the keys registered for the circuit they name
+    let mut unknown_circuit = fixture_block(3, odd.hash(), vec![fixture_transaction(9, 900)]);
+    unknown_circuit.zk_proof = fixture_proof(zk_generator, &unknown_circuit).await?;
+    unknown_circuit.zk_proof.circuit_version += 1;
+    let unknown_circuit = sign_fixture(unknown_circuit);
+
+    let mut bad_signature = fixture_block(3, odd.hash(), vec![fixture_transaction(9, 900)]);
+    bad_signature.zk_proof = fixture_proof(zk_generator, &bad_signature).await?;
+    bad_signature.signature = odd.signature.clone();
+
+    Ok(vec![
+        ("genesis".into(), "empty genesis block".into(), genesis),
+        ("single_tx".into(), "block with a single transaction".into(), single),
+        ("odd_txs".into(), "block with an odd number of transactions".into(), odd),
+        ("expiring_txs".into(), "block with transactions expiring at a height and at a time".into(), expiring),
+        ("unjail_tx".into(), "block with a validator's unjail transaction".into(), unjailing),
+        ("governance_txs".into(), "block with governance proposals and a vote on one".into(), governing),
+        ("wrong_proof".into(), "block carrying another block's proof".into(), wrong_proof),
+        ("truncated_proof".into(), "block with a truncated proof".into(), truncated_proof),
+        ("unknown_circuit".into(), "block whose proof names an unregistered circuit version".into(), unknown_circuit),
+        ("bad_signature".into(), "block carrying another block's proposer signature".into(), bad_signature),
+    ])
+}
+
+async fn block_vector(
+    zk_generator: &ZKProofGenerator,
+    name: String,
+    description: String,
+    block: Block,
+) -> Result<BlockVector> {
+    Ok(BlockVector {
+        version: VECTOR_VERSION,
+        name,
+        description,
+        encoded_header: hex::encode(block.header.encode()),
+        encoded_transactions: hex::encode(block.transactions.encode()),
+        encoded_block: hex::encode(block.encode()),
+        header_hash: hex::encode(block.header.hash()),
+        block_hash: hex::encode(block.hash()),
+        merkle_root: hex::encode(calculate_merkle_root(&block.transactions)),
+        transaction_hashes: block.transactions.iter().map(|tx| hex::encode(tx.hash())).collect(),
+        public_inputs: hex::encode(&block.zk_proof.public_inputs),
+        signing_hash: hex::encode(block.signing_hash()),
+        expected_signature_valid: crypto::verify_signature(&block.header.validator, &block.signing_hash(), &block.signature),
+        expected_proof_valid: zk_generator.verify_block_proof(&block).await?,
+        block,
+    })
+}
+
+fn vote_vector(name: String, description: String, vote: BlockVote) -> Result<VoteVector> {
+    Ok(VoteVector {
+        version: VECTOR_VERSION,
+        name,
+        description,
+        encoded_vote: hex::encode(vote.encode()),
+        signing_hash: hex::encode(vote.signing_hash()),
+        expected_signature_valid: crypto::verify_signature(&vote.validator, &vote.signing_hash(), &vote.signature),
+        vote,
+    })
+}
+
+async fn chain_vectors(zk_generator: &ZKProofGenerator) -> Result<Vec<ChainVector>> {
+    let mut blocks = Vec::new();
+    let mut parent_hash = [0; 32];
+    for number in 1..=3u64 {
+        let transactions = (0..number as u8).map(|i| fixture_transaction(i, 10)).collect();
+        let block = fixture_block(number, parent_hash, transactions);
+        parent_hash = block.hash();
+        blocks.push(block);
+    }
+
+    // Extending in two steps must land on the same accumulator as a single batch
+    let first = zk_generator.generate_recursive_proof(None, &blocks[..1]).await?;
+    let chain_proof = zk_generator.generate_recursive_proof(Some(&first), &blocks[1..]).await?;
+
+    let mut tampered = chain_proof.clone();
+    tampered.public_inputs[8] ^= 0xFF;
+
+    let public_inputs = hex::encode(&chain_proof.public_inputs);
+    let mut vectors = Vec::new();
+    for (name, description, chain_proof) in [
+        ("chain_3", "chain proof over three blocks", chain_proof.clone()),
+        ("chain_tampered", "chain proof with a modified accumulator", tampered),
+    ] {
+        vectors.push(ChainVector {
+            version: VECTOR_VERSION,
+            name: name.to_string(),
+            description: description.to_string(),
+            blocks: blocks.clone(),
+            public_inputs: public_inputs.clone(),
+            expected_proof_valid: zk_generator.verify_chain_proof(&chain_proof).await?,
+            chain_proof,
+        });
+    }
+
+    Ok(vectors)
+}
+
+async fn header_vectors(zk_generator: &ZKProofGenerator) -> Result<Vec<HeaderVector>> {
+    let block = fixture_block(2, [2; 32], vec![fixture_transaction(3, 30), fixture_transaction(4, 40)]);
+    let zk_proof = fixture_proof(zk_generator, &block).await?;
+
+    // Same roots, but the proof was made for a header with another parent
+    let mut other_parent = block.header.clone();
+    other_parent.parent_hash = [3; 32];
+
+    let mut vectors = Vec::new();
+    for (name, description, header) in [
+        ("header_2", "block proof checked against the header of a block with two transactions", block.header.clone()),
+        ("header_other_parent", "block proof checked against a header with another parent", other_parent),
+    ] {
+        vectors.push(HeaderVector {
+            version: VECTOR_VERSION,
+            name: name.to_string(),
+            description: description.to_string(),
+            expected_proof_valid: zk_generator.verify_header_proof(&header, None, &zk_proof).await?,
+            header,
+            zk_proof: zk_proof.clone(),
+        });
+    }
+
+    Ok(vectors)
+}
+
+fn inputs_vectors() -> Vec<InputsVector> {
+    let single = fixture_block(1, [1; 32], vec![fixture_transaction(1, 100)]);
+    let inputs = PublicInputs::new(&single, [2; 32], fixture_validator_set());
+    // Every hash half and integer at its largest
+    let saturated = PublicInputs {
+        block_number: u64::MAX,
+        tx_count: u64::MAX,
+        parent_hash: [0xFF; 32],
+        merkle_root: [0xFF; 32],
+        state_root: [0xFF; 32],
+        validator_set_hash: [0xFF; 32],
+        parent_state_root: [0xFF; 32],
+    };
+
+    let mut unknown_version = inputs.to_bytes();
+    unknown_version[0] = zk_proof::PUBLIC_INPUTS_VERSION + 1;
+    let mut truncated = inputs.to_bytes();
+    truncated.pop();
+
+    [
+        ("inputs_single_tx", "public inputs of a block with a single transaction", inputs.to_bytes()),
+        ("inputs_saturated", "public inputs with every field at its largest value", saturated.to_bytes()),
+        ("inputs_unknown_version", "public inputs of a layout version this node does not know", unknown_version),
+        ("inputs_truncated", "public inputs missing their last byte", truncated),
+    ].into_iter().map(|(name, description, encoded)| {
+        let inputs = PublicInputs::from_bytes(&encoded).ok();
+        InputsVector {
+            version: VECTOR_VERSION,
+            name: name.to_string(),
+            description: description.to_string(),
+            encoded: hex::encode(&encoded),
+            field_elements: inputs.iter().flat_map(|inputs| inputs.field_elements()).map(|element| element.to_string()).collect(),
+            inputs,
+        }
+    }).collect()
+}
+
+fn merkle_vector(name: &str, transactions: &[Transaction]) -> MerkleVector {
+    let root = calculate_merkle_root(transactions);
+    let mut proofs = Vec::new();
+
+    for index in 0..transactions.len() {
+        let proof = merkle_proof(transactions, index).unwrap();
+
+        let mut tampered = proof.clone();
+        tampered.leaf[0] ^= 0xFF;
+
+        proofs.push(MerkleProofVector { expected_valid: proof.verify(&root), proof });
+        proofs.push(MerkleProofVector { expected_valid: tampered.verify(&root), proof: tampered });
+    }
+
+    MerkleVector {
+        version: VECTOR_VERSION,
+        name: name.to_string(),
+        root: hex::encode(root),
+        proofs,
+    }
+}
+
+pub async fn generate(out_dir: &Path) -> Result<usize> {
+    let zk_generator = ZKProofGenerator::mock()?;
+    let mut written = 0;
+
+    fs::create_dir_all(out_dir.join("blocks"))?;
+    fs::create_dir_all(out_dir.join("merkle"))?;
+    fs::create_dir_all(out_dir.join("votes"))?;
+    fs::create_dir_all(out_dir.join("chains"))?;
+    fs::create_dir_all(out_dir.join("headers"))?;
+    fs::create_dir_all(out_dir.join("inputs"))?;
+
+    let blocks = fixture_blocks(&zk_generator).await?;
+    for (name, description, vote) in fixture_votes(&blocks[1].2) {
+        let vector = vote_vector(name, description, vote)?;
+        let path = out_dir.join("votes").join(format!("{}.json", vector.name));
+        fs::write(path, serde_json::to_string_pretty(&vector)?)?;
+        written += 1;
+    }
+
+    for (name, description, block) in blocks {
+        let vector = block_vector(&zk_generator, name, description, block).await?;
+        let path = out_dir.join("blocks").join(format!("{}.json", vector.name));
+        fs::write(path, serde_json::to_string_pretty(&vector)?)?;
+        written += 1;
+    }
+
+    for count in [1u8, 2, 3, 4, 7] {
+        let transactions: Vec<_> = (0..count).map(|i| fixture_transaction(i, 10)).collect();
+        let vector = merkle_vector(&format!("merkle_{}", count), &transactions);
+        let path = out_dir.join("merkle").join(format!("{}.json", vector.name));
+        fs::write(path, serde_json::to_string_pretty(&vector)?)?;
+        written += 1;
+    }
+
+    for vector in chain_vectors(&zk_generator).await? {
+        let path = out_dir.join("chains").join(format!("{}.json", vector.name));
+        fs::write(path, serde_json::to_string_pretty(&vector)?)?;
+        written += 1;
+    }
+
+    for vector in header_vectors(&zk_generator).await? {
+        let path = out_dir.join("headers").join(format!("{}.json", vector.name));
+        fs::write(path, serde_json::to_string_pretty(&vector)?)?;
+        written += 1;
+    }
+
+    for vector in inputs_vectors() {
+        let path = out_dir.join("inputs").join(format!("{}.json", vector.name));
+        fs::write(path, serde_json::to_string_pretty(&vector)?)?;
+        written += 1;
+    }
+
+    info!("🧪 Wrote {} conformance vectors to {}", written, out_dir.display());
+    Ok(written)
+}
+
+pub async fn check(dir: &Path) -> Result<ConformanceReport> {
+    let zk_generator = ZKProofGenerator::mock()?;
+    let mut report = ConformanceReport::default();
+
+    for path in sorted_json_files(&dir.join("blocks"))? {
+        let vector: BlockVector = match load_vector(&path, &mut report, |vector: &BlockVector| vector.version) {
+            Some(vector) => vector,
+            None => continue,
+        };
+
+        let block = &vector.block;
+        let name = &vector.name;
+        report.record(name, "encoded_header", hex::encode(block.header.encode()) == vector.encoded_header);
+        report.record(name, "encoded_transactions", hex::encode(block.transactions.encode()) == vector.encoded_transactions);
+        report.record(name, "encoded_block", hex::encode(block.encode()) == vector.encoded_block);
+        let decoded = hex::decode(&vector.encoded_block).ok().and_then(|bytes| Block::decode(&bytes).ok());
+        report.record(name, "decoded_block", decoded.is_some_and(|decoded| decoded.hash() == block.hash()));
+        report.record(name, "header_hash", hex::encode(block.header.hash()) == vector.header_hash);
+        report.record(name, "block_hash", hex::encode(block.hash()) == vector.block_hash);
+        report.record(name, "merkle_root", hex::encode(calculate_merkle_root(&block.transactions)) == vector.merkle_root);
+
+        let tx_hashes: Vec<_> = block.transactions.iter().map(|tx| hex::encode(tx.hash())).collect();
+        report.record(name, "transaction_hashes", tx_hashes == vector.transaction_hashes);
+        report.record(name, "public_inputs", hex::encode(&block.zk_proof.public_inputs) == vector.public_inputs);
+        report.record(name, "signing_hash", hex::encode(block.signing_hash()) == vector.signing_hash);
+        report.record(name, "signature_valid",
+            crypto::verify_signature(&block.header.validator, &block.signing_hash(), &block.signature) == vector.expected_signature_valid);
+        report.record(name, "proof_valid",
+            zk_generator.verify_block_proof(block).await.is_ok_and(|valid| valid == vector.expected_proof_valid));
+    }
+
+    for path in sorted_json_files(&dir.join("votes"))? {
+        let vector: VoteVector = match load_vector(&path, &mut report, |vector: &VoteVector| vector.version) {
+            Some(vector) => vector,
+            None => continue,
+        };
+        let vote = &vector.vote;
+        let name = &vector.name;
+        report.record(name, "encoded_vote", hex::encode(vote.encode()) == vector.encoded_vote);
+        let decoded = hex::decode(&vector.encoded_vote).ok().and_then(|bytes| BlockVote::decode(&bytes).ok());
+        report.record(name, "decoded_vote", decoded.as_ref() == Some(vote));
+        report.record(name, "signing_hash", hex::encode(vote.signing_hash()) == vector.signing_hash);
+        report.record(name, "signature_valid",
+            crypto::verify_signature(&vote.validator, &vote.signing_hash(), &vote.signature) == vector.expected_signature_valid);
+    }
+
+    for path in sorted_json_files(&dir.join("merkle"))? {
+        let vector: MerkleVector = match load_vector(&path, &mut report, |vector: &MerkleVector| vector.version) {
+            Some(vector) => vector,
+            None => continue,
+        };
+        let root: [u8; 32] = match hex::decode(&vector.root).ok().and_then(|root| root.try_into().ok()) {
+            Some(root) => root,
+            None => {
+                report.record(&vector.name, "root", false);
+                continue;
+            }
+        };
+
+        for (i, entry) in vector.proofs.iter().enumerate() {
+            let check = format!("proof[{}]", i);
+            report.record(&vector.name, &check, entry.proof.verify(&root) == entry.expected_valid);
+        }
+    }
+
+    for path in sorted_json_files(&dir.join("chains"))? {
+        let vector: ChainVector = match load_vector(&path, &mut report, |vector: &ChainVector| vector.version) {
+            Some(vector) => vector,
+            None => continue,
+        };
+        let name = &vector.name;
+        let rebuilt = zk_generator.generate_recursive_proof(None, &vector.blocks).await;
+        report.record(name, "accumulator", rebuilt.is_ok_and(|rebuilt| hex::encode(&rebuilt.public_inputs) == vector.public_inputs));
+        report.record(name, "chain_proof_valid",
+            zk_generator.verify_chain_proof(&vector.chain_proof).await.is_ok_and(|valid| valid == vector.expected_proof_valid));
+    }
+
+    for path in sorted_json_files(&dir.join("headers"))? {
+        let vector: HeaderVector = match load_vector(&path, &mut report, |vector: &HeaderVector| vector.version) {
+            Some(vector) => vector,
+            None => continue,
+        };
+        let name = &vector.name;
+        report.record(name, "proof_valid",
+            zk_generator.verify_header_proof(&vector.header, None, &vector.zk_proof).await.is_ok_and(|valid| valid == vector.expected_proof_valid));
+    }
+
+    for path in sorted_json_files(&dir.join("inputs"))? {
+        let vector: InputsVector = match load_vector(&path, &mut report, |vector: &InputsVector| vector.version) {
+            Some(vector) => vector,
+            None => continue,
+        };
+        let name = &vector.name;
+        let encoded = match hex::decode(&vector.encoded) {
+            Ok(encoded) => encoded,
+            Err(_) => {
+                report.record(name, "encoded", false);
+                continue;
+            }
+        };
+        let decoded = PublicInputs::from_bytes(&encoded).ok();
+        report.record(name, "decoded_inputs", decoded == vector.inputs);
+        if let Some(inputs) = &vector.inputs {
+            report.record(name, "encoded_inputs", hex::encode(inputs.to_bytes()) == vector.encoded);
+            let elements: Vec<_> = inputs.field_elements().iter().map(|element| element.to_string()).collect();
+            report.record(name, "field_elements", elements == vector.field_elements);
+        }
+    }
+
+    if report.is_success() {
+        info!("✅ All {} conformance checks passed", report.passed);
+    } else {
+        for failure in &report.failed {
+            warn!("❌ Conformance check failed: {}", failure);
+        }
+    }
+
+    Ok(report)
+}
+
+// A vector that can not be read, or is of another version, fails as a whole instead of ending
+// the run
+fn load_vector<T: DeserializeOwned>(path: &Path, report: &mut ConformanceReport, version: impl Fn(&T) -> u32) -> Option<T> {
+    let file = path.display().to_string();
+    let read = fs::read_to_string(path).map_err(anyhow::Error::from)
+        .and_then(|json| Ok(serde_json::from_str(&json)?));
+    let vector: T = match read {
+        Ok(vector) => vector,
+        Err(e) => {
+            warn!("❌ Can not read conformance vector {}: {}", file, e);
+            report.record(&file, "readable", false);
+            return None;
+        }
+    };
+    if version(&vector) != VECTOR_VERSION {
+        warn!("❌ {} is a version {} vector, this node checks version {}", file, version(&vector), VECTOR_VERSION);
+        report.record(&file, "version", false);
+        return None;
+    }
+    Some(vector)
+}
+
+fn sorted_json_files(dir: &Path) -> Result<Vec<std::path::PathBuf>> {
+    let mut files: Vec<_> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .collect();
+    files.sort();
+    Ok(files)
+}
@@ -0,0 +1,267 @@
+use crate::crypto;
+use crate::types::{BlockHash, BlockVote, ConsensusParams, ConsensusState, FinalityCertificate, NodeId, RoundChange, RoundCheckpoint, VoteStep};
+use anyhow::{bail, Result};
+use std::collections::{HashMap, HashSet};
+use tracing::warn;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Step {
+    Propose,
+    Prevote,
+    Precommit,
+}
+
+// Outcome of a step once a quorum of the active stake has voted in it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Quorum {
+    Block(BlockHash),
+    Nil,
+    // Enough stake voted, but not for any single value
+    Split,
+}
+
+// Per-height state of the prevote/precommit protocol
+pub struct RoundState {
+    pub height: u64,
+    pub round: u64,
+    pub step: Step,
+    pub proposed: bool,
+    // Block we precommitted and the round of its polka; we only prevote other blocks
+    // once they have a polka in a later round
+    pub locked: Option<(BlockHash, u64)>,
+    // Most recent block with a polka, which we re-propose when it is our turn
+    pub valid: Option<(BlockHash, u64)>,
+    votes: HashMap<(u64, VoteStep), HashMap<NodeId, BlockVote>>,
+    // Validators asking to move to each round
+    round_changes: HashMap<u64, HashSet<NodeId>>,
+}
+
+pub enum VoteOutcome {
+    Added,
+    Duplicate,
+    // The validator already voted for something else in this round and step
+    Conflict(BlockVote),
+}
+
+impl RoundState {
+    pub fn new(height: u64) -> Self {
+        Self {
+            height,
+            round: 0,
+            step: Step::Propose,
+            proposed: false,
+            locked: None,
+            valid: None,
+            votes: HashMap::new(),
+            round_changes: HashMap::new(),
+        }
+    }
+
+    // Resumes a height from before a restart, with our own votes counted again
+    pub fn restore(checkpoint: RoundCheckpoint) -> Self {
+        let mut round_state = Self::new(checkpoint.height);
+        round_state.round = checkpoint.round;
+        round_state.proposed = checkpoint.proposed;
+        round_state.locked = checkpoint.locked;
+        round_state.valid = checkpoint.valid;
+        for vote in &checkpoint.votes {
+            round_state.add_vote(vote);
+        }
+
+        // Pick up after the last step we voted in
+        let voted = |step| checkpoint.votes.iter().any(|vote| vote.round == checkpoint.round && vote.step == step);
+        if voted(VoteStep::Precommit) {
+            round_state.step = Step::Precommit;
+        } else if voted(VoteStep::Prevote) {
+            round_state.step = Step::Prevote;
+        }
+        round_state
+    }
+
+    pub fn checkpoint(&self, validator: &NodeId) -> RoundCheckpoint {
+        RoundCheckpoint {
+            height: self.height,
+            round: self.round,
+            proposed: self.proposed,
+            locked: self.locked,
+            valid: self.valid,
+            votes: self.votes.values()
+                .filter_map(|votes| votes.get(validator).cloned())
+                .collect(),
+        }
+    }
+
+    pub fn enter_round(&mut self, round: u64) {
+        self.round = round;
+        self.step = Step::Propose;
+        self.proposed = false;
+    }
+
+    // Records a vote; duplicates and equivocations are not counted
+    pub fn add_vote(&mut self, vote: &BlockVote) -> VoteOutcome {
+        let votes = self.votes.entry((vote.round, vote.step)).or_default();
+
+        match votes.get(&vote.validator) {
+            Some(existing) if existing.value() != vote.value() => {
+                warn!("⚠️ Validator {} equivocated in round {} {:?}",
+                    hex::encode(vote.validator), vote.round, vote.step);
+                VoteOutcome::Conflict(existing.clone())
+            }
+            Some(_) => VoteOutcome::Duplicate,
+            None => {
+                votes.insert(vote.validator, vote.clone());
+                VoteOutcome::Added
+            }
+        }
+    }
+
+    pub fn vote_of(&self, validator: &NodeId, round: u64, step: VoteStep) -> Option<&BlockVote> {
+        self.votes.get(&(round, step))?.get(validator)
+    }
+
+    // Whether the validator's vote for this round and step is already counted as cast
+    pub fn has_vote(&self, vote: &BlockVote) -> bool {
+        self.votes.get(&(vote.round, vote.step))
+            .and_then(|votes| votes.get(&vote.validator))
+            .is_some_and(|existing| existing.value() == vote.value())
+    }
+
+    // Returns false when the validator already asked for this round
+    pub fn add_round_change(&mut self, change: &RoundChange) -> bool {
+        self.round_changes.entry(change.round).or_default().insert(change.validator)
+    }
+
+    // Latest round a quorum of the active stake asked to move to
+    pub fn round_change_quorum(&self, state: &ConsensusState) -> Option<u64> {
+        let total = active_stake(state);
+        self.round_changes.iter()
+            .filter(|(_, validators)| {
+                let weight = validators.iter()
+                    .filter_map(|validator| state.validators.get(validator))
+                    .filter(|info| info.is_active)
+                    .map(|info| info.stake)
+                    .sum();
+                state.params.has_quorum(weight, total)
+            })
+            .map(|(round, _)| *round)
+            .max()
+    }
+
+    pub fn quorum(&self, round: u64, step: VoteStep, state: &ConsensusState) -> Option<Quorum> {
+        let votes = self.votes.get(&(round, step))?;
+        let total = active_stake(state);
+
+        let mut weights: HashMap<Option<BlockHash>, u64> = HashMap::new();
+        let mut voted = 0;
+        for (validator, vote) in votes {
+            let stake = match state.validators.get(validator) {
+                Some(info) if info.is_active => info.stake,
+                _ => continue,
+            };
+            *weights.entry(vote.value()).or_default() += stake;
+            voted += stake;
+        }
+
+        if let Some((value, _)) = weights.iter().find(|(_, weight)| state.params.has_quorum(**weight, total)) {
+            return Some(value.map_or(Quorum::Nil, Quorum::Block));
+        }
+
+        state.params.has_quorum(voted, total).then_some(Quorum::Split)
+    }
+
+    // Active validators whose vote in the round and step went to the value, with their stake
+    pub fn voters(&self, round: u64, step: VoteStep, value: Option<BlockHash>, state: &ConsensusState) -> Vec<(NodeId, u64)> {
+        let mut voters: Vec<(NodeId, u64)> = self.votes.get(&(round, step))
+            .into_iter()
+            .flatten()
+            .filter(|(_, vote)| vote.value() == value)
+            .filter_map(|(validator, _)| match state.validators.get(validator) {
+                Some(info) if info.is_active => Some((*validator, info.stake)),
+                _ => None,
+            })
+            .collect();
+        voters.sort_unstable();
+        voters
+    }
+
+    pub fn rounds(&self, step: VoteStep) -> Vec<u64> {
+        let mut rounds: Vec<u64> = self.votes.keys()
+            .filter(|(_, s)| *s == step)
+            .map(|(round, _)| *round)
+            .collect();
+        rounds.sort_unstable();
+        rounds
+    }
+
+    // Latest round in which the block gathered a prevote quorum
+    pub fn polka_round(&self, block_hash: &BlockHash, state: &ConsensusState) -> Option<u64> {
+        self.rounds(VoteStep::Prevote).into_iter()
+            .filter(|round| self.quorum(*round, VoteStep::Prevote, state) == Some(Quorum::Block(*block_hash)))
+            .max()
+    }
+}
+
+// Checks that the certificate holds precommits for its block by a quorum of the active stake of
+// `state`, the consensus state stored with the block, whose signatures the caller verified.
+// Returns their weight
+pub fn weigh_certificate(certificate: &FinalityCertificate, state: &ConsensusState) -> Result<u64> {
+    let stake_of = |validator: &NodeId| state.validators.get(validator)
+        .filter(|info| info.is_active)
+        .map(|info| info.stake);
+    weigh_precommits(certificate, stake_of, active_stake(state), &state.params)
+}
+
+// Checks signed precommits against a validator set known only by the stake of each active
+// validator, under the parameters of the state that set comes from
+pub fn verify_precommits(
+    certificate: &FinalityCertificate,
+    stake_of: impl Fn(&NodeId) -> Option<u64>,
+    total: u64,
+    params: &ConsensusParams,
+) -> Result<u64> {
+    if let Some(vote) = certificate.precommits.iter()
+        .find(|vote| !crypto::verify_signature(&vote.validator, &vote.signing_hash(), &vote.signature))
+    {
+        bail!("precommit of {} has an invalid signature", hex::encode(vote.validator));
+    }
+    weigh_precommits(certificate, stake_of, total, params)
+}
+
+fn weigh_precommits(
+    certificate: &FinalityCertificate,
+    stake_of: impl Fn(&NodeId) -> Option<u64>,
+    total: u64,
+    params: &ConsensusParams,
+) -> Result<u64> {
+    if !certificate.precommits.windows(2).all(|pair| pair[0].validator < pair[1].validator) {
+        bail!("precommits are not sorted by validator or repeat one");
+    }
+
+    let mut weight = 0;
+    for vote in &certificate.precommits {
+        if vote.step != VoteStep::Precommit
+            || vote.value() != Some(certificate.block_hash)
+            || vote.height != certificate.block_number
+            || vote.round != certificate.round
+        {
+            bail!("vote of {} is not a precommit for the block in round {}", hex::encode(vote.validator), certificate.round);
+        }
+        weight += match stake_of(&vote.validator) {
+            Some(stake) => stake,
+            None => bail!("{} is not an active validator", hex::encode(vote.validator)),
+        };
+    }
+
+    if !params.has_quorum(weight, total) {
+        bail!("precommits carry {} of {} stake, not more than {}/{}",
+            weight, total, params.quorum_numerator, params.quorum_denominator);
+    }
+    Ok(weight)
+}
+
+pub fn active_stake(state: &ConsensusState) -> u64 {
+    state.validators.values()
+        .filter(|info| info.is_active)
+        .map(|info| info.stake)
+        .sum()
+}
@@ -0,0 +1,97 @@
+use crate::types::{GetFinalityCertificate, NodeId};
+use chrono::{DateTime, Duration, Utc};
+use std::collections::BTreeMap;
+use tracing::debug;
+
+// Certificates asked for at once; each holds a precommit per validator, so this stays far below
+// the gossip message size limit
+pub const MAX_CERTIFICATES_PER_REQUEST: u64 = 32;
+const REQUEST_TIMEOUT_SECS: i64 = 10;
+// Requests for the same blocks before they are given up on: one to the peer that served them,
+// then to anyone
+const MAX_ATTEMPTS: u32 = 3;
+
+struct Request {
+    from_block: u64,
+    attempts: u32,
+    sent_at: DateTime<Utc>,
+}
+
+// Synced blocks are final without us having seen their precommits, so their certificates are
+// fetched afterwards, one request at a time. The peer that served a block is asked first, as
+// it finalized it before us
+pub struct CertificateBackfill {
+    // Runs of blocks without a certificate, by first block: the last block and the peer that
+    // served them
+    missing: BTreeMap<u64, (u64, NodeId)>,
+    request: Option<Request>,
+}
+
+impl CertificateBackfill {
+    pub fn new() -> Self {
+        Self { missing: BTreeMap::new(), request: None }
+    }
+
+    // Blocks are synced in order, so consecutive blocks from one peer make up a single run
+    pub fn add(&mut self, block_number: u64, peer: NodeId) {
+        if let Some(mut last) = self.missing.last_entry() {
+            let (end, served_by) = last.get_mut();
+            if *end + 1 == block_number && *served_by == peer {
+                *end = block_number;
+                return;
+            }
+        }
+        self.missing.insert(block_number, (block_number, peer));
+    }
+
+    pub fn is_missing(&self, block_number: u64) -> bool {
+        self.missing.range(..=block_number).next_back().is_some_and(|(_, (end, _))| block_number <= *end)
+    }
+
+    pub fn received(&mut self, block_number: u64) {
+        self.remove(block_number, block_number);
+    }
+
+    // The request was answered, so the next poll asks for whatever is still missing
+    pub fn answered(&mut self) {
+        self.request = None;
+    }
+
+    // The next request, once the last one was answered or timed out
+    pub fn poll(&mut self, requester: NodeId, now: DateTime<Utc>) -> Option<GetFinalityCertificate> {
+        if self.request.as_ref().is_some_and(|request| now - request.sent_at < Duration::seconds(REQUEST_TIMEOUT_SECS)) {
+            return None;
+        }
+        let (from_block, (end, peer)) = self.missing.first_key_value().map(|(from, run)| (*from, *run))?;
+        let to_block = end.min(from_block + MAX_CERTIFICATES_PER_REQUEST - 1);
+
+        let attempts = match self.request.take() {
+            Some(previous) if previous.from_block == from_block => previous.attempts + 1,
+            _ => 1,
+        };
+        if attempts > MAX_ATTEMPTS {
+            debug!("Giving up on the certificates of blocks #{}..#{}", from_block, to_block);
+            self.remove(from_block, to_block);
+            return self.poll(requester, now);
+        }
+
+        let target = (attempts == 1).then_some(peer);
+        self.request = Some(Request { from_block, attempts, sent_at: now });
+        Some(GetFinalityCertificate { from_block, to_block, requester, target })
+    }
+
+    // Takes blocks `from..=to`, which lie in one run, out of it
+    fn remove(&mut self, from: u64, to: u64) {
+        let (start, (end, peer)) = match self.missing.range(..=from).next_back() {
+            Some((start, run)) if from <= run.0 => (*start, *run),
+            _ => return,
+        };
+        self.missing.remove(&start);
+        if start < from {
+            self.missing.insert(start, (from - 1, peer));
+        }
+        if to < end {
+            self.missing.insert(to + 1, (end, peer));
+        }
+    }
+}
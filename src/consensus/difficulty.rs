@@ -0,0 +1,28 @@
+use chrono::Duration;
+
+// Difficulty of the first block on a chain, and after the genesis block
+pub const INITIAL_DIFFICULTY: u64 = 1000;
+// Blocks the moving average spans; each block moves difficulty 1/RETARGET_WINDOW of the way
+// towards what its own interval asks for
+const RETARGET_WINDOW: i128 = 32;
+// Bound on the change from one block to the next, as a fraction of the parent's difficulty
+const MAX_ADJUSTMENT_DIVISOR: u64 = 8;
+const MIN_DIFFICULTY: u64 = 1;
+
+// Exponential moving average over block intervals: blocks slower than the target lower the
+// difficulty, faster ones raise it. Only the parent is needed, so nodes started from a snapshot
+// or with pruned history compute the same value
+pub fn next_difficulty(parent_difficulty: u64, interval: Duration, target: Duration) -> u64 {
+    // The genesis block carries no difficulty
+    if parent_difficulty == 0 {
+        return INITIAL_DIFFICULTY;
+    }
+
+    let target_ms = target.num_milliseconds().max(1) as i128;
+    let interval_ms = interval.num_milliseconds().max(0) as i128;
+    let adjustment = parent_difficulty as i128 * (target_ms - interval_ms) / (target_ms * RETARGET_WINDOW);
+
+    let max_step = (parent_difficulty / MAX_ADJUSTMENT_DIVISOR).max(1) as i128;
+    let difficulty = parent_difficulty as i128 + adjustment.clamp(-max_step, max_step);
+    (difficulty.min(u64::MAX as i128) as u64).max(MIN_DIFFICULTY)
+}
@@ -0,0 +1,11 @@
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ConsensusError {
+    // Not an active validator, not our turn, too early, still syncing or already proposed; the
+    // debug log says which
+    #[error("not proposing block #{height} in round {round}")]
+    NotProposing { height: u64, round: u64 },
+    #[error("block production is not halted")]
+    NotHalted,
+}
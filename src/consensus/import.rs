@@ -0,0 +1,182 @@
+use crate::crypto::{SignatureVerifier, SignedMessage};
+use crate::types::{calculate_merkle_root, Block, BlockLimits, ProofPolicy, ProofType};
+use crate::zk_proof::ZKProofGenerator;
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashSet};
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::RwLock;
+use tracing::warn;
+
+// Synced blocks whose decode, signature and proof stages run ahead of the one being committed
+pub const MAX_CONCURRENT_IMPORTS: usize = 8;
+
+// What a block goes through on import, in this order. Gossiped proposals are stored after the
+// proof stage and executed and finalized as consensus decides on them; synced blocks are final
+// already and go through every stage at once
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Stage {
+    // Well formed on its own: within the limits, matching its roots
+    Decode,
+    // Signed by its proposer, the senders of its transactions and the validators of its parent votes
+    Signatures,
+    // Fits on its parent: number, time, difficulty, proposer, parent votes and evidence
+    Header,
+    Proof,
+    // Executes to its state root on the finalized accounts
+    Execute,
+    Store,
+    Finalize,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct StageStats {
+    pub passed: u64,
+    pub rejected: u64,
+    // Stages that could not run, such as on a storage error
+    pub failed: u64,
+    pub total_micros: u64,
+    pub max_micros: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ImportStats {
+    pub stages: BTreeMap<Stage, StageStats>,
+    // Blocks the execute stage rejected for a transaction spending funds its sender did not
+    // have, such as funds an earlier transaction of the block spent already
+    pub overdrawn: u64,
+}
+
+// Runs import stages and keeps count of how each one went. Clones share the counters, so
+// stages can run on other tasks
+#[derive(Clone, Default)]
+pub struct ImportPipeline {
+    stats: Arc<RwLock<ImportStats>>,
+}
+
+impl ImportPipeline {
+    // Shared with the RPC server
+    pub fn stats_handle(&self) -> Arc<RwLock<ImportStats>> {
+        self.stats.clone()
+    }
+
+    // Runs one stage of a block's import; false means the block was rejected
+    pub async fn run(&self, stage: Stage, check: impl Future<Output = Result<bool>>) -> Result<bool> {
+        let started = Instant::now();
+        let result = check.await;
+        let micros = started.elapsed().as_micros() as u64;
+
+        let mut stats = self.stats.write().await;
+        let stats = stats.stages.entry(stage).or_default();
+        match &result {
+            Ok(true) => stats.passed += 1,
+            Ok(false) => stats.rejected += 1,
+            Err(_) => stats.failed += 1,
+        }
+        stats.total_micros += micros;
+        stats.max_micros = stats.max_micros.max(micros);
+        result
+    }
+
+    pub async fn record_overdraw(&self) {
+        self.stats.write().await.overdrawn += 1;
+    }
+
+    // The stages that only need the block itself, which synced blocks run concurrently
+    pub async fn precheck(
+        self,
+        zk_generator: Arc<ZKProofGenerator>,
+        signatures: SignatureVerifier,
+        limits: BlockLimits,
+        proofs: ProofPolicy,
+        block: Block,
+    ) -> Result<bool> {
+        if !self.run(Stage::Decode, async { Ok(decode(&block, &limits, &proofs)) }).await? {
+            return Ok(false);
+        }
+        if !self.run(Stage::Signatures, verify_signatures(&signatures, &block)).await? {
+            return Ok(false);
+        }
+        self.run(Stage::Proof, verify_proof(&zk_generator, &proofs, &block)).await
+    }
+}
+
+pub fn decode(block: &Block, limits: &BlockLimits, proofs: &ProofPolicy) -> bool {
+    // Blocks the proof policy does not prove carry a checkpoint reference, which is checked
+    // against the parent with the header
+    if proofs.proves(block.header.block_number) && block.zk_proof.proof_type == ProofType::Checkpoint {
+        warn!("Block {} carries a checkpoint reference instead of a proof", block.header.block_number);
+        return false;
+    }
+
+    if let Err(e) = limits.check_block(block) {
+        warn!("📦 Block {} is over the size limits: {}", block.header.block_number, e);
+        return false;
+    }
+
+    // The merkle tree duplicates odd leaves, so a repeated transaction would give a second
+    // transaction list with the same root and block hash
+    let mut tx_ids = HashSet::new();
+    if !block.transactions.iter().all(|tx| tx_ids.insert(tx.id)) {
+        warn!("🚫 Block {} contains duplicate transactions", block.header.block_number);
+        return false;
+    }
+
+    if let Some(tx) = block.transactions.iter().find(|tx| tx.is_expired(block.header.block_number, &block.header.timestamp)) {
+        warn!("⌛ Block {} contains expired transaction {}", block.header.block_number, hex::encode(tx.id));
+        return false;
+    }
+
+    if block.header.merkle_root != calculate_merkle_root(&block.transactions) {
+        warn!("🌳 Block {} does not match its merkle root", block.header.block_number);
+        return false;
+    }
+
+    true
+}
+
+// All signatures of the block go to the verifier at once
+pub async fn verify_signatures(signatures: &SignatureVerifier, block: &Block) -> Result<bool> {
+    let mut signed = vec![SignedMessage::new(block.header.validator, block.signing_hash(), &block.signature)];
+    signed.extend(block.transactions.iter().map(|tx| SignedMessage::new(tx.from, tx.signing_hash(), &tx.signature)));
+    signed.extend(block.parent_votes.iter().map(|vote| SignedMessage::new(vote.validator, vote.signing_hash(), &vote.signature)));
+
+    let verdicts = signatures.verify_all(signed).await?;
+    let transactions = block.transactions.len();
+    match verdicts.iter().position(|valid| !valid) {
+        None => Ok(true),
+        Some(0) => {
+            warn!("Invalid proposer signature for block {}", block.header.block_number);
+            Ok(false)
+        }
+        Some(index) if index <= transactions => {
+            warn!("Transaction {} of block {} is not signed by its sender",
+                hex::encode(block.transactions[index - 1].id), block.header.block_number);
+            Ok(false)
+        }
+        Some(index) => {
+            warn!("🗳️ Parent vote of {} in block {} has an invalid signature",
+                hex::encode(block.parent_votes[index - 1 - transactions].validator), block.header.block_number);
+            Ok(false)
+        }
+    }
+}
+
+// Blocks without a proof of their own pass; their checkpoint reference is part of the header
+pub async fn verify_proof(zk_generator: &ZKProofGenerator, proofs: &ProofPolicy, block: &Block) -> Result<bool> {
+    if !proofs.proves(block.header.block_number) {
+        return Ok(true);
+    }
+    if !zk_generator.can_verify(&block.zk_proof.proof_type) && !zk_generator.is_proven(block).await? {
+        warn!("Can not verify the {:?} proof of block {}", block.zk_proof.proof_type, block.header.block_number);
+        return Ok(false);
+    }
+    if !zk_generator.verify_block_proof(block).await? {
+        warn!("Invalid ZK proof for block {}", block.header.block_number);
+        return Ok(false);
+    }
+    Ok(true)
+}
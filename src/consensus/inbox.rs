@@ -0,0 +1,223 @@
+use crate::types::ConsensusMessage;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tokio::sync::mpsc::{self, error::{SendError, TrySendError}};
+use tracing::warn;
+
+// Messages each lane holds before senders are pushed back on, in Priority order
+const CAPACITY: [usize; 4] = [256, 1024, 256, 64];
+// Messages a lane may take in a row while others wait, in Priority order. A busy lane gets its
+// share, but never the turns of a busier one of higher priority
+const WEIGHTS: [u32; 4] = [8, 4, 2, 1];
+
+// Lanes of the engine's inbox, from the one handled first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Priority {
+    // Proposals and blocks, which everything else waits on
+    Blocks,
+    Votes,
+    // Sync, snapshots, certificates and the rest of what keeps nodes in step
+    State,
+    Proofs,
+}
+
+impl Priority {
+    pub const ALL: [Priority; 4] = [Priority::Blocks, Priority::Votes, Priority::State, Priority::Proofs];
+
+    pub fn of(message: &ConsensusMessage) -> Self {
+        match message {
+            ConsensusMessage::NewBlock(_)
+            | ConsensusMessage::Proposal(_)
+            | ConsensusMessage::CompactBlock(_)
+            | ConsensusMessage::BlockTxs(_)
+            | ConsensusMessage::BlocksResponse(_) => Priority::Blocks,
+            ConsensusMessage::BlockVote(_)
+            | ConsensusMessage::VoteBundle(_)
+            | ConsensusMessage::RoundChange(_) => Priority::Votes,
+            ConsensusMessage::ZKProofRequest(_)
+            | ConsensusMessage::ZKProofResponse(_) => Priority::Proofs,
+            _ => Priority::State,
+        }
+    }
+}
+
+#[derive(Default)]
+struct LaneCounters {
+    received: AtomicU64,
+    handled: AtomicU64,
+    // Messages turned away because the lane was full
+    dropped: AtomicU64,
+    // Sync responses whose sender had to wait for room
+    waited: AtomicU64,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct LaneStats {
+    pub queued: u64,
+    pub capacity: usize,
+    pub received: u64,
+    pub dropped: u64,
+    pub waited: u64,
+}
+
+// Counters of every lane, shared with the RPC server
+#[derive(Clone, Default)]
+pub struct InboxMonitor {
+    lanes: Arc<[LaneCounters; 4]>,
+}
+
+impl InboxMonitor {
+    pub fn stats(&self) -> BTreeMap<Priority, LaneStats> {
+        Priority::ALL.into_iter()
+            .map(|priority| {
+                let counters = &self.lanes[priority as usize];
+                let received = counters.received.load(Ordering::Relaxed);
+                let stats = LaneStats {
+                    queued: received.saturating_sub(counters.handled.load(Ordering::Relaxed)),
+                    capacity: CAPACITY[priority as usize],
+                    received,
+                    dropped: counters.dropped.load(Ordering::Relaxed),
+                    waited: counters.waited.load(Ordering::Relaxed),
+                };
+                (priority, stats)
+            })
+            .collect()
+    }
+}
+
+// Hands messages to the engine on the lane of their priority
+#[derive(Clone)]
+pub struct ConsensusSender {
+    lanes: [mpsc::Sender<ConsensusMessage>; 4],
+    monitor: InboxMonitor,
+}
+
+impl ConsensusSender {
+    // Dropped when its lane is full, so a flood of gossip, blocks included, can not hold up the
+    // network loop while the engine catches up
+    pub async fn send(&self, message: ConsensusMessage) -> Result<(), SendError<ConsensusMessage>> {
+        self.enqueue(message, false).await
+    }
+
+    // For blocks answering one of our sync requests, which wait for room since a lost one
+    // costs a sync round trip
+    pub async fn send_awaited(&self, message: ConsensusMessage) -> Result<(), SendError<ConsensusMessage>> {
+        self.enqueue(message, true).await
+    }
+
+    async fn enqueue(&self, message: ConsensusMessage, wait: bool) -> Result<(), SendError<ConsensusMessage>> {
+        let priority = Priority::of(&message);
+        let counters = &self.monitor.lanes[priority as usize];
+        match self.lanes[priority as usize].try_send(message) {
+            Ok(()) => {}
+            Err(TrySendError::Full(message)) if wait => {
+                counters.waited.fetch_add(1, Ordering::Relaxed);
+                self.lanes[priority as usize].send(message).await?;
+            }
+            Err(TrySendError::Full(_)) => {
+                counters.dropped.fetch_add(1, Ordering::Relaxed);
+                return Ok(());
+            }
+            Err(TrySendError::Closed(message)) => return Err(SendError(message)),
+        }
+        counters.received.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+// The engine's side of the lanes. Takes messages by weighted round robin in priority order, so
+// blocks go first but votes, state and proof traffic still get their turns under load
+pub struct Inbox {
+    lanes: [mpsc::Receiver<ConsensusMessage>; 4],
+    // Turns each lane has left in this round
+    credits: [u32; 4],
+    monitor: InboxMonitor,
+    // Dropped and waited counts as of the last report
+    reported: [(u64, u64); 4],
+}
+
+pub fn channel() -> (ConsensusSender, Inbox) {
+    let (blocks_tx, blocks_rx) = mpsc::channel(CAPACITY[0]);
+    let (votes_tx, votes_rx) = mpsc::channel(CAPACITY[1]);
+    let (state_tx, state_rx) = mpsc::channel(CAPACITY[2]);
+    let (proofs_tx, proofs_rx) = mpsc::channel(CAPACITY[3]);
+    let monitor = InboxMonitor::default();
+
+    let sender = ConsensusSender { lanes: [blocks_tx, votes_tx, state_tx, proofs_tx], monitor: monitor.clone() };
+    let inbox = Inbox {
+        lanes: [blocks_rx, votes_rx, state_rx, proofs_rx],
+        credits: WEIGHTS,
+        monitor,
+        reported: [(0, 0); 4],
+    };
+    (sender, inbox)
+}
+
+impl Inbox {
+    pub fn monitor(&self) -> InboxMonitor {
+        self.monitor.clone()
+    }
+
+    // Cancel safe, so it can be raced against other events
+    pub async fn recv(&mut self) -> Option<ConsensusMessage> {
+        if let Some(message) = self.try_recv() {
+            return Some(message);
+        }
+
+        // Every lane is empty; whichever fills first starts the next round
+        let [blocks, votes, state, proofs] = &mut self.lanes;
+        let (priority, message) = tokio::select! {
+            biased;
+            message = blocks.recv() => (Priority::Blocks, message),
+            message = votes.recv() => (Priority::Votes, message),
+            message = state.recv() => (Priority::State, message),
+            message = proofs.recv() => (Priority::Proofs, message),
+        };
+        let message = message?;
+        self.take(priority);
+        Some(message)
+    }
+
+    pub fn try_recv(&mut self) -> Option<ConsensusMessage> {
+        // Once the lanes with turns left are empty, a new round starts
+        for _ in 0..2 {
+            for priority in Priority::ALL {
+                if self.credits[priority as usize] == 0 {
+                    continue;
+                }
+                if let Ok(message) = self.lanes[priority as usize].try_recv() {
+                    self.take(priority);
+                    return Some(message);
+                }
+            }
+            self.credits = WEIGHTS;
+        }
+        None
+    }
+
+    fn take(&mut self, priority: Priority) {
+        self.credits[priority as usize] = self.credits[priority as usize].saturating_sub(1);
+        self.monitor.lanes[priority as usize].handled.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // Warns about messages dropped, and blocks held up, since the last report
+    pub fn report_backpressure(&mut self) {
+        let stats = self.monitor.stats();
+        for priority in Priority::ALL {
+            let lane = &stats[&priority];
+            let (dropped, waited) = self.reported[priority as usize];
+            if lane.dropped > dropped {
+                warn!("🚦 Consensus inbox dropped {} {:?} messages, {} of {} queued",
+                    lane.dropped - dropped, priority, lane.queued, lane.capacity);
+            }
+            if lane.waited > waited {
+                warn!("🚦 Network waited {} times for room in the consensus inbox for {:?}, {} of {} queued",
+                    lane.waited - waited, priority, lane.queued, lane.capacity);
+            }
+            self.reported[priority as usize] = (lane.dropped, lane.waited);
+        }
+    }
+}
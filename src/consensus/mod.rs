@@ -1,109 +1,463 @@
 use crate::types::{
-    Block, BlockHeader, BlockHash, NodeId, ConsensusState, ConsensusMessage, 
-    BlockVote, VoteType, ValidatorInfo, ZKProof
+    Block, BlockHeader, BlockHash, TransactionKind, NodeId, ConsensusState, ConsensusParams, ConsensusMessage, 
+    BlockVote, VoteType, VoteStep, Proposal, ValidatorInfo, ZKProof, HeaderRequest, HeaderResponse, CertifiedHeader,
+    GetBlocks, BlocksResponse, EpochChange, SnapshotRequest, SnapshotChunk, BlockFinalized,
+    ChainEvent, CompactBlock, GetBlockTxs, BlockTxs, RoundChange, Transaction, Encode,
+    GetFinalityCertificate, CertificateResponse, FinalityCertificate, ProofRequest, ProofResponse, EpochStats, BlockLimits, LivenessPolicy, ProofPolicy, TrustedCheckpoint, votes_root,
+    validator_set_hash
 };
-use crate::zk_proof::ZKProofGenerator;
-use crate::storage::StorageManager;
+use crate::admin::{ConsensusCommand, RoundInfo};
+use crate::zk_proof::{ProofJob, ProofService, PublicInputs, ZKProofGenerator};
+use crate::storage::{DropReason, MempoolView, StorageManager};
+use crate::clock::Clock;
+use crate::crypto::{self, NodeKeypair, SignatureVerifier, SignedMessage};
+use crate::config::{ConsensusConfig, ZkProofConfig};
+use crate::staking::{self, Evidence, StakeChange};
+use crate::state::{AccountId, AccountState, StateTransition, TransactionError};
+use crate::shutdown::ShutdownSignal;
+use crate::sync::{self, BlockSync, SyncStatus};
+use crate::network::{self, MessageHash, Misbehaviour, PeerHead, PeerReport};
+use crate::snapshot::{self, Snapshot, SnapshotDownload, SnapshotTrust};
+#[cfg(feature = "testing")]
+use crate::testing::ByzantineBehavior;
 use chrono::{DateTime, Utc, Duration};
-use sha2::{Sha256, Digest};
-use anyhow::Result;
+use anyhow::{anyhow, bail, Result};
+use rand::{rngs::StdRng, Rng, SeedableRng};
 use tracing::{info, debug, warn, error};
 use std::sync::Arc;
-use tokio::sync::{RwLock, mpsc};
-use std::collections::HashMap;
+use tokio::sync::{Mutex, RwLock, broadcast, mpsc};
+use tokio::task::JoinHandle;
+use std::cmp::Reverse;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use sha2::{Sha256, Digest};
+
+mod bft;
+mod certificates;
+mod difficulty;
+mod error;
+mod import;
+mod inbox;
+mod proof_queue;
+mod proof_requests;
+mod safety;
+mod slots;
+
+pub use error::ConsensusError;
+pub use import::{ImportStats, Stage, StageStats};
+pub use inbox::{channel as inbox_channel, ConsensusSender, Inbox, InboxMonitor, LaneStats, Priority};
+pub use bft::verify_precommits;
+pub use safety::{DetectedViolation, SafetyAlarm, SafetyViolation, SAFETY_ACTIONS};
+use import::{ImportPipeline, MAX_CONCURRENT_IMPORTS};
+use bft::{Quorum, RoundState, Step, VoteOutcome};
+use certificates::{CertificateBackfill, MAX_CERTIFICATES_PER_REQUEST};
+use proof_queue::ProofRequestQueue;
+use proof_requests::{OpenRequest, ProofRequests};
+use slots::Slots;
+
+// Keeps header responses well below the gossipsub message size limit
+const MAX_HEADERS_PER_RESPONSE: u64 = 32;
+const MAX_BLOCKS_RESPONSE_BYTES: u64 = 48 * 1024;
+// Fresh nodes further behind than this fetch a snapshot from a peer instead of replaying the chain
+const FAST_SYNC_MIN_BLOCKS: u64 = 256;
+const SNAPSHOT_TIMEOUT_SECS: i64 = 60;
+// At most one snapshot is built and served in this time, whoever asks
+const SNAPSHOT_SERVE_INTERVAL_SECS: i64 = 30;
+// and at most one at an older height, for a node syncing to a trusted checkpoint
+const SNAPSHOT_REBUILD_INTERVAL_SECS: i64 = 300;
+// Compact blocks kept while their missing transactions are fetched
+const MAX_INCOMPLETE_BLOCKS: usize = 16;
+// Blocks kept while a validator proves them in a proof system we can verify
+const MAX_UNPROVEN_BLOCKS: usize = 8;
+// Longest the consensus loop sleeps between ticks
+const MAX_TICK_INTERVAL: tokio::time::Duration = tokio::time::Duration::from_secs(1);
+// Round changes further ahead of our round than this are dropped, so a validator can not make us
+// track arbitrarily many rounds
+const MAX_ROUND_CHANGE_AHEAD: u64 = 8;
+// Chain events a slow subscriber may fall behind by before it misses some
+const CHAIN_EVENT_BUFFER: usize = 256;
+// Rounds before a block's own that count as missed proposals; after a long stall every active
+// validator missed a slot already
+const MAX_SCORED_ROUNDS: u64 = 64;
+
+// Our proposal for the current round, waiting on its proof
+struct PendingProof {
+    block: Block,
+    round: u64,
+    job: ProofJob,
+}
 
 pub struct ConsensusEngine {
     zk_generator: Arc<ZKProofGenerator>,
+    proof_service: ProofService,
+    pending_proof: Option<PendingProof>,
+    signatures: SignatureVerifier,
     storage: Arc<StorageManager>,
+    clock: Arc<dyn Clock>,
     state: Arc<RwLock<ConsensusState>>,
+    keypair: NodeKeypair,
     node_id: NodeId,
-    message_tx: mpsc::Sender<ConsensusMessage>,
-    message_rx: mpsc::Receiver<ConsensusMessage>,
+    message_tx: ConsensusSender,
+    inbox: Inbox,
+    network_tx: Option<mpsc::Sender<ConsensusMessage>>,
+    peer_report_tx: Option<mpsc::Sender<PeerReport>>,
+    admin_tx: mpsc::Sender<ConsensusCommand>,
+    admin_rx: mpsc::Receiver<ConsensusCommand>,
+    // Finalized heads peers announced when connecting
+    peer_head_tx: mpsc::Sender<PeerHead>,
+    peer_head_rx: mpsc::Receiver<PeerHead>,
+    // Gossip id of the message being handled, for blaming its sender
+    current_message: Option<MessageHash>,
+    validator_stake: Option<u64>,
     block_time: Duration,
-    min_validators: usize,
+    // Slots of one block_time from the genesis time, which rounds are counted in
+    slots: Slots,
+    max_future_drift: Duration,
+    limits: BlockLimits,
+    proofs: ProofPolicy,
+    liveness: LivenessPolicy,
+    // Set when blocks are only sealed for pending transactions, or seal_interval after the last
+    // one when it is not zero
+    instant_seal: bool,
+    seal_interval: Duration,
+    round_state: RoundState,
+    chain_proof_tx: Option<mpsc::UnboundedSender<u64>>,
+    events_tx: broadcast::Sender<ChainEvent>,
+    chain_prover: Option<JoinHandle<()>>,
+    sync: BlockSync,
+    import: ImportPipeline,
+    // Pending pool the proposer picks transactions from, kept current from mempool events
+    mempool: MempoolView,
+    accounts: AccountState,
+    // Offenses we already submitted evidence for
+    reported: HashSet<BlockHash>,
+    snapshot_download: Option<SnapshotDownload>,
+    snapshot_served_at: Option<DateTime<Utc>>,
+    snapshot_rebuilt_at: Option<DateTime<Utc>>,
+    fast_sync_tried: bool,
+    // Compact blocks waiting on transactions we asked peers for
+    incomplete_blocks: HashMap<BlockHash, CompactBlock>,
+    // Blocks with a proof of a type we have no backend for, waiting on one we can verify
+    unproven_blocks: HashMap<BlockHash, Block>,
+    proof_requests: ProofRequests,
+    // Proof requests from peers, shared with the tasks answering them
+    proof_queue: Arc<Mutex<ProofRequestQueue>>,
+    // Synced blocks whose finality certificate we still have to fetch
+    certificates: CertificateBackfill,
+    // Hash of block 0 when the chain was started from a genesis file
+    genesis_hash: Option<BlockHash>,
+    // Block we start from instead of genesis; no chain with another block at its height is followed
+    checkpoint: Option<TrustedCheckpoint>,
+    // Stop proposing and voting on a safety violation rather than only reporting it
+    halt_on_violation: bool,
+    safety: SafetyAlarm,
+    // Request ids; seeded by the simulator so runs repeat exactly
+    rng: StdRng,
+    #[cfg(feature = "testing")]
+    byzantine: Option<Box<dyn ByzantineBehavior>>,
 }
 
 impl ConsensusEngine {
     pub fn new(
-        zk_generator: ZKProofGenerator,
+        zk_generator: Arc<ZKProofGenerator>,
+        proof_service: ProofService,
         storage: StorageManager,
+        clock: Arc<dyn Clock>,
+        keypair: NodeKeypair,
+        config: &ConsensusConfig,
     ) -> Result<Self> {
         info!("🔧 Initializing ZK-PoV Consensus Engine");
         
-        let node_id = Self::generate_node_id();
-        let (message_tx, message_rx) = mpsc::channel(1000);
-        
-        // Initialize as validator with some stake
-        let mut validators = HashMap::new();
-        validators.insert(node_id, ValidatorInfo {
-            stake: 1000,
-            is_active: true,
-            last_block_time: Utc::now(),
-            performance_score: 1.0,
-        });
+        let node_id = keypair.node_id();
+        let (message_tx, inbox) = inbox::channel();
+        let (admin_tx, admin_rx) = mpsc::channel(16);
+        let (peer_head_tx, peer_head_rx) = mpsc::channel(64);
         
+        // Validators are registered on-chain through staking transactions
         let state = ConsensusState {
-            current_block: 0,
-            validators,
-            total_stake: 1000,
+            head: 0,
+            justified: 0,
+            finalized: 0,
+            validators: HashMap::new(),
+            total_stake: 0,
             epoch: 0,
+            pending_stake_changes: Vec::new(),
+            slashed: Vec::new(),
+            // Chains started from a genesis file take the parameters stored with it instead
+            params: ConsensusParams { block_time: config.block_time, ..ConsensusParams::default() },
+            proposals: Vec::new(),
         };
         
         info!("👤 Node ID: {}", hex::encode(node_id));
-        info!("🏛️ Initialized as validator with 1000 stake");
         
         Ok(Self {
-            zk_generator: Arc::new(zk_generator),
+            zk_generator,
+            proof_service,
+            pending_proof: None,
+            signatures: SignatureVerifier::start(config.signature_workers)?,
+            mempool: MempoolView::new(storage.clone()),
             storage: Arc::new(storage),
+            clock,
             state: Arc::new(RwLock::new(state)),
+            keypair,
             node_id,
             message_tx,
-            message_rx,
-            block_time: Duration::seconds(12), // 12 second block time
-            min_validators: 1, // Allow single validator for testing
+            inbox,
+            network_tx: None,
+            peer_report_tx: None,
+            admin_tx,
+            admin_rx,
+            peer_head_tx,
+            peer_head_rx,
+            current_message: None,
+            validator_stake: None,
+            block_time: Duration::seconds(config.block_time as i64),
+            // Chains bootstrapped without a genesis file count slots from the Unix epoch
+            slots: Slots::new(DateTime::UNIX_EPOCH, Duration::seconds(config.block_time as i64)),
+            max_future_drift: Duration::seconds(config.max_future_drift as i64),
+            limits: config.limits,
+            proofs: config.proofs,
+            liveness: config.liveness,
+            instant_seal: config.instant_seal,
+            seal_interval: Duration::seconds(config.seal_interval as i64),
+            round_state: RoundState::new(1),
+            chain_proof_tx: None,
+            events_tx: broadcast::channel(CHAIN_EVENT_BUFFER).0,
+            chain_prover: None,
+            sync: BlockSync::new(),
+            import: ImportPipeline::default(),
+            accounts: AccountState::default(),
+            reported: HashSet::new(),
+            snapshot_download: None,
+            snapshot_served_at: None,
+            snapshot_rebuilt_at: None,
+            fast_sync_tried: false,
+            incomplete_blocks: HashMap::new(),
+            unproven_blocks: HashMap::new(),
+            proof_requests: ProofRequests::new(),
+            proof_queue: Arc::new(Mutex::new(ProofRequestQueue::new(&ZkProofConfig::default()))),
+            certificates: CertificateBackfill::new(),
+            genesis_hash: None,
+            checkpoint: None,
+            halt_on_violation: config.on_safety_violation == "halt",
+            safety: SafetyAlarm::default(),
+            rng: StdRng::from_entropy(),
+            #[cfg(feature = "testing")]
+            byzantine: None,
         })
     }
     
-    fn generate_node_id() -> NodeId {
-        let mut hasher = Sha256::new();
-        hasher.update(&rand::random::<[u8; 32]>());
-        hasher.update(&chrono::Utc::now().timestamp().to_le_bytes());
-        hasher.finalize().into()
+    pub async fn start(&mut self, shutdown: ShutdownSignal) -> Result<()> {
+        info!("🚀 Starting ZK-PoV Consensus Engine");
+        self.resume().await?;
+        // Extending the chain proof takes the proving parameters verifiers do not load
+        if self.zk_generator.can_prove() {
+            self.spawn_chain_prover();
+        }
+        
+        // Start consensus loop; what we have is persisted even when it fails
+        let result = self.consensus_loop(shutdown).await;
+        match (result, self.stop().await) {
+            (Err(e), Err(stop_error)) => {
+                error!("Failed to stop the consensus engine cleanly: {}", stop_error);
+                Err(e)
+            }
+            (result, stopped) => result.and(stopped),
+        }
     }
     
-    pub async fn start(&mut self) -> Result<()> {
-        info!("🚀 Starting ZK-PoV Consensus Engine");
+    // Loads the chain and round we left off at, and joins the validator set if configured to
+    pub async fn resume(&mut self) -> Result<()> {
+        // Resume from whatever is already in storage (e.g. an imported snapshot)
+        let latest = self.storage.get_latest_block().await?;
+        if let Some(checkpoint) = &self.checkpoint {
+            if let Some(block) = self.storage.get_block(checkpoint.height).await? {
+                if checkpoint.conflicts_with(&block) {
+                    bail!("the database has block {} at height {}, which conflicts with the trusted checkpoint {}",
+                        hex::encode(block.hash()), checkpoint.height, hex::encode(checkpoint.block_hash));
+                }
+            }
+        }
+        self.accounts = AccountState::new(self.storage.get_accounts().await?);
+        if let Some(stored_state) = self.storage.get_consensus_state().await? {
+            info!("⏩ Restored consensus state ({} validators, epoch {})",
+                stored_state.validators.len(), stored_state.epoch);
+            *self.state.write().await = stored_state;
+        }
+        if let Some(latest) = &latest {
+            let mut state = self.state.write().await;
+            if latest.header.block_number > state.head {
+                info!("⏩ Resuming from stored block #{}", latest.header.block_number);
+                state.head = latest.header.block_number;
+            }
+        }
+        // Genesis and snapshot imports finalize a block without going through consensus
+        if let Some(finalized) = self.storage.get_finalized_block().await? {
+            let mut state = self.state.write().await;
+            state.finalized = state.finalized.max(finalized.header.block_number);
+            state.justified = state.justified.max(state.finalized);
+        }
+        self.apply_params().await;
+        self.restore_round().await?;
+        if let Some(safety) = self.storage.get_safety_alarm().await? {
+            if safety.halted {
+                error!("🚨 Block production is halted after {} safety violations; admin_resumeProduction resumes it",
+                    safety.violations.len());
+            }
+            self.safety = safety;
+        }
         
-        // Start consensus loop
-        self.consensus_loop().await?;
+        if let Some(stake) = self.validator_stake {
+            if latest.is_none() && self.state.read().await.validators.is_empty() {
+                self.bootstrap_validator_set(stake).await?;
+            } else {
+                self.register_validator(stake).await?;
+                self.request_unjail().await?;
+            }
+        }
+        Ok(())
+    }
+    
+    // Picks the height we were deciding back up, unless it was finalized while we were down
+    async fn restore_round(&mut self) -> Result<()> {
+        let checkpoint = match self.storage.get_round_checkpoint().await? {
+            Some(checkpoint) => checkpoint,
+            None => return Ok(()),
+        };
+        let height = self.storage.get_finalized_block().await?
+            .map_or(1, |parent| parent.header.block_number + 1);
+        if checkpoint.height != height {
+            return Ok(());
+        }
         
+        info!("⏩ Resuming height {} in round {} with {} of our votes",
+            checkpoint.height, checkpoint.round, checkpoint.votes.len());
+        self.round_state = RoundState::restore(checkpoint);
         Ok(())
     }
     
-    async fn consensus_loop(&mut self) -> Result<()> {
+    async fn consensus_loop(&mut self, mut shutdown: ShutdownSignal) -> Result<()> {
         info!("🔄 Starting consensus loop");
         let mut tick_counter = 0u64;
+        let mut next_tick = tokio::time::Instant::now();
         
         loop {
             tokio::select! {
-                message = self.message_rx.recv() => {
+                _ = shutdown.recv() => {
+                    return Ok(());
+                }
+                message = self.inbox.recv() => {
                     if let Some(msg) = message {
                         debug!("📨 Received message: {:?}", msg);
                         self.handle_message(msg).await?;
                     }
                 }
-                _ = tokio::time::sleep(tokio::time::Duration::from_secs(1)) => {
+                result = wait_for_proof(&mut self.pending_proof) => {
+                    self.on_proof_ready(result).await?;
+                }
+                command = self.admin_rx.recv() => {
+                    if let Some(command) = command {
+                        self.handle_admin_command(command).await?;
+                    }
+                }
+                head = self.peer_head_rx.recv() => {
+                    if let Some(PeerHead { node_id, finalized_height }) = head {
+                        self.sync.peer_head(node_id, finalized_height);
+                    }
+                }
+                _ = tokio::time::sleep_until(next_tick) => {
                     tick_counter += 1;
                     if tick_counter % 10 == 0 {
                         info!("⏱️ Consensus tick #{}", tick_counter);
+                        self.inbox.report_backpressure();
                     }
                     self.tick().await?;
+                    
+                    // Round timeouts fall on thirds of a slot, so wake up right at the next one
+                    let now = self.clock.now();
+                    let wait = (self.slots.next_boundary(now) - now).to_std().unwrap_or_default();
+                    next_tick = tokio::time::Instant::now() + wait.min(MAX_TICK_INTERVAL);
+                }
+            }
+        }
+    }
+    
+    // Handles what was already received, then persists everything needed to resume
+    async fn stop(&mut self) -> Result<()> {
+        info!("🛑 Stopping consensus engine");
+        
+        let mut handled = 0;
+        while let Some(message) = self.inbox.try_recv() {
+            self.handle_message(message).await?;
+            handled += 1;
+        }
+        if handled > 0 {
+            info!("📨 Handled {} queued messages before stopping", handled);
+        }
+        
+        self.storage.store_consensus_state(&*self.state.read().await).await?;
+        self.checkpoint_round().await?;
+        
+        // Closing the queue lets the prover finish the heights it was already given
+        self.chain_proof_tx = None;
+        if let Some(chain_prover) = self.chain_prover.take() {
+            info!("⏳ Waiting for the chain prover to finish");
+            chain_prover.await?;
+        }
+        
+        self.storage.flush().await?;
+        info!("💾 Consensus state saved at block #{}", self.round_state.height - 1);
+        Ok(())
+    }
+    
+    async fn handle_admin_command(&mut self, command: ConsensusCommand) -> Result<()> {
+        match command {
+            ConsensusCommand::ProposeNow(reply) => {
+                let now = self.clock.now();
+                self.update_round(now).await?;
+                let (height, round) = (self.round_state.height, self.round_state.round);
+                
+                if !self.should_propose_block(now).await? {
+                    let _ = reply.send(Err(ConsensusError::NotProposing { height, round }));
+                    return Ok(());
+                }
+                info!("🛠️ Proposing block #{} on operator request", height);
+                self.propose(now).await?;
+                let _ = reply.send(Ok(height));
+            }
+            ConsensusCommand::ResumeProduction(reply) => {
+                if !self.safety.halted {
+                    let _ = reply.send(Err(ConsensusError::NotHalted));
+                    return Ok(());
                 }
+                // The violations stay on record
+                self.safety.halted = false;
+                self.storage.store_safety_alarm(&self.safety).await?;
+                warn!("🛠️ Block production resumed on operator request after {} safety violations",
+                    self.safety.violations.len());
+                let _ = reply.send(Ok(self.safety.violations.len()));
+            }
+            ConsensusCommand::RoundState(reply) => {
+                let _ = reply.send(RoundInfo {
+                    height: self.round_state.height,
+                    round: self.round_state.round,
+                    step: format!("{:?}", self.round_state.step),
+                    proposed: self.round_state.proposed,
+                    locked: self.round_state.locked,
+                    valid: self.round_state.valid,
+                });
             }
         }
+        Ok(())
     }
     
     async fn handle_message(&mut self, message: ConsensusMessage) -> Result<()> {
+        self.current_message = self.peer_report_tx.as_ref().map(|_| network::message_id(&message));
+        let result = self.dispatch_message(message).await;
+        self.current_message = None;
+        result
+    }
+    
+    async fn dispatch_message(&mut self, message: ConsensusMessage) -> Result<()> {
         match message {
             ConsensusMessage::NewBlock(block) => {
                 self.handle_new_block(block).await?;
@@ -111,15 +465,59 @@ impl ConsensusEngine {
             ConsensusMessage::BlockVote(vote) => {
                 self.handle_block_vote(vote).await?;
             }
-            ConsensusMessage::ConsensusState(state) => {
-                self.handle_consensus_state(state).await?;
-            }
             ConsensusMessage::ZKProofRequest(request) => {
                 self.handle_proof_request(request).await?;
             }
             ConsensusMessage::ZKProofResponse(response) => {
                 self.handle_proof_response(response).await?;
             }
+            ConsensusMessage::Proposal(proposal) => {
+                self.handle_proposal(proposal).await?;
+            }
+            ConsensusMessage::HeaderRequest(request) => {
+                self.handle_header_request(request).await?;
+            }
+            // Only light clients act on header responses
+            ConsensusMessage::HeaderResponse(_) => {}
+            ConsensusMessage::GetBlocks(request) => {
+                self.handle_get_blocks(request).await?;
+            }
+            ConsensusMessage::BlocksResponse(response) => {
+                self.sync.on_response(response, self.round_state.height - 1).await;
+                // The first responses tell us how far behind we are, before anything is imported
+                if !self.start_fast_sync().await {
+                    self.import_synced_blocks().await?;
+                }
+            }
+            ConsensusMessage::EpochChange(change) => {
+                self.handle_epoch_change(change).await?;
+            }
+            ConsensusMessage::GetSnapshot(request) => {
+                self.handle_get_snapshot(request).await?;
+            }
+            ConsensusMessage::SnapshotChunk(chunk) => {
+                self.handle_snapshot_chunk(chunk).await?;
+            }
+            ConsensusMessage::CompactBlock(compact) => {
+                self.handle_compact_block(compact).await?;
+            }
+            ConsensusMessage::GetBlockTxs(request) => {
+                self.handle_get_block_txs(request).await?;
+            }
+            ConsensusMessage::BlockTxs(response) => {
+                self.handle_block_txs(response).await?;
+            }
+            ConsensusMessage::RoundChange(change) => {
+                self.handle_round_change(change).await?;
+            }
+            // The network manager hands bundled votes over one by one
+            ConsensusMessage::VoteBundle(_) => {}
+            ConsensusMessage::GetFinalityCertificate(request) => {
+                self.handle_get_finality_certificate(request).await?;
+            }
+            ConsensusMessage::FinalityCertificate(response) => {
+                self.handle_finality_certificates(response).await?;
+            }
         }
         Ok(())
     }
@@ -127,310 +525,2596 @@ impl ConsensusEngine {
     async fn handle_new_block(&mut self, block: Block) -> Result<()> {
         debug!("Received new block {}", block.header.block_number);
         
-        // Verify ZK proof
-        if !self.zk_generator.verify_proof(&block.zk_proof).await? {
-            warn!("Invalid ZK proof for block {}", block.header.block_number);
+        // Heights below the one being decided are final; replays of them are not worth verifying
+        if block.header.block_number < self.round_state.height {
+            debug!("Ignoring block {} below height {}", block.header.block_number, self.round_state.height);
             return Ok(());
         }
         
-        // Verify block structure
-        if !self.verify_block_structure(&block).await? {
-            warn!("Invalid block structure for block {}", block.header.block_number);
+        let block_hash = block.hash();
+        if self.storage.get_block_by_hash(&block_hash).await?.is_some() {
+            debug!("Already have block {}", block.header.block_number);
             return Ok(());
         }
         
-        // Store block
-        self.storage.store_block(&block).await?;
+        let import = self.import.clone();
+        if !import.run(Stage::Decode, async { Ok(import::decode(&block, &self.limits, &self.proofs)) }).await? {
+            self.report_peer(Misbehaviour::InvalidBlock).await;
+            return Ok(());
+        }
+        if !import.run(Stage::Signatures, import::verify_signatures(&self.signatures, &block)).await? {
+            self.report_peer(Misbehaviour::InvalidBlock).await;
+            return Ok(());
+        }
         
-        // Vote on block
-        let vote = BlockVote {
-            block_hash: block.hash(),
-            validator: self.node_id,
-            vote: VoteType::Approve,
-            timestamp: Utc::now(),
-            signature: vec![], // TODO: Implement proper signing
+        // The parent may be on any branch of the block tree; genesis blocks have none
+        let parent = if block.header.parent_hash == [0; 32] {
+            None
+        } else {
+            match self.storage.get_block_by_hash(&block.header.parent_hash).await? {
+                Some(parent) => Some(parent),
+                None => {
+                    debug!("Unknown parent for block {}, ignoring", block.header.block_number);
+                    return Ok(());
+                }
+            }
         };
         
-        // Broadcast vote
-        self.broadcast_vote(vote).await?;
+        if !import.run(Stage::Header, self.verify_block_header(&block, parent.as_ref())).await? {
+            warn!("Invalid header for block {}", block.header.block_number);
+            self.report_peer(Misbehaviour::InvalidBlock).await;
+            return Ok(());
+        }
+        
+        // A proof system we were built without is not the proposer's fault; have a validator
+        // prove the block in one we can verify
+        let proven = self.proofs.proves(block.header.block_number);
+        if proven && !self.zk_generator.can_verify(&block.zk_proof.proof_type) && !self.zk_generator.is_proven(&block).await? {
+            return self.request_proof(block).await;
+        }
         
+        // The proposer signed the proof, so a bad one is evidence against the proposer
+        if !import.run(Stage::Proof, import::verify_proof(&self.zk_generator, &self.proofs, &block)).await? {
+            self.report_peer(Misbehaviour::InvalidProof).await;
+            self.report_evidence(Evidence::InvalidProof(block.light())).await?;
+            return Ok(());
+        }
+        
+        import.run(Stage::Store, async { self.store_proposal(&block).await.map(|_| true) }).await?;
         info!("Processed new block {}", block.header.block_number);
+        
+        // A valid block for the height we are deciding is this round's proposal
+        if block.header.block_number == self.round_state.height {
+            let round = self.proposal_round(parent.as_ref(), block.header.timestamp);
+            self.on_proposal(block_hash, round).await?;
+        }
+        
         Ok(())
     }
     
-    async fn handle_block_vote(&mut self, vote: BlockVote) -> Result<()> {
-        debug!("Received vote for block {:?}", vote.block_hash);
-        
-        // Verify vote signature
-        if !self.verify_vote_signature(&vote).await? {
-            warn!("Invalid vote signature");
+    // Rebuilds a gossiped block from the transactions we already know, asking for the rest
+    async fn handle_compact_block(&mut self, compact: CompactBlock) -> Result<()> {
+        let block_number = compact.header.block_number;
+        let block_hash = compact.hash();
+        if block_number < self.round_state.height || self.incomplete_blocks.contains_key(&block_hash) {
+            return Ok(());
+        }
+        if self.storage.get_block_by_hash(&block_hash).await?.is_some() {
+            debug!("Already have block {}", block_number);
             return Ok(());
         }
         
-        // Store vote
-        self.storage.store_vote(&vote).await?;
+        // Checked before asking peers for anything on the proposer's behalf
+        if !crypto::verify_signature(&compact.header.validator, &compact.signing_hash(), &compact.signature) {
+            warn!("Invalid proposer signature for compact block {}", block_number);
+            self.report_peer(Misbehaviour::InvalidBlock).await;
+            return Ok(());
+        }
+        // The rest of the limits are checked once the transactions are in
+        if compact.tx_ids.len() as u64 > self.limits.max_txs_per_block {
+            warn!("📦 Compact block {} lists {} transactions, over the limit of {}",
+                block_number, compact.tx_ids.len(), self.limits.max_txs_per_block);
+            self.report_peer(Misbehaviour::InvalidBlock).await;
+            return Ok(());
+        }
+
+        let mut missing = Vec::new();
+        for tx_id in &compact.tx_ids {
+            if self.storage.get_transaction(tx_id).await?.is_none() {
+                missing.push(*tx_id);
+            }
+        }
+        if missing.is_empty() {
+            return self.complete_block(compact, Vec::new()).await;
+        }
         
-        // Check if we have enough votes for finality
-        self.check_block_finality(vote.block_hash).await?;
+        if self.incomplete_blocks.len() >= MAX_INCOMPLETE_BLOCKS {
+            debug!("Too many incomplete blocks, dropping compact block {}", block_number);
+            return Ok(());
+        }
+        debug!("Compact block {} is missing {} of {} transactions", block_number, missing.len(), compact.tx_ids.len());
+        self.incomplete_blocks.insert(block_hash, compact);
         
+        let request = GetBlockTxs { block_hash, tx_ids: missing, requester: self.node_id };
+        self.send_to_network(ConsensusMessage::GetBlockTxs(request)).await;
         Ok(())
     }
     
-    async fn handle_consensus_state(&mut self, state: ConsensusState) -> Result<()> {
-        debug!("Received consensus state update");
+    async fn handle_get_block_txs(&mut self, request: GetBlockTxs) -> Result<()> {
+        let block = match self.storage.get_block_by_hash(&request.block_hash).await? {
+            Some(block) => block,
+            None => return Ok(()),
+        };
+        if self.storage.is_header_only(block.header.block_number).await? {
+            return Ok(());
+        }
         
-        let mut current_state = self.state.write().await;
-        *current_state = state;
+        let transactions: Vec<Transaction> = block.transactions.into_iter()
+            .filter(|tx| request.tx_ids.contains(&tx.id))
+            .collect();
+        debug!("Serving {} transactions of block {}", transactions.len(), block.header.block_number);
         
+        // Everyone holding the block sends the same bytes, which gossip delivers once
+        let response = BlockTxs { block_hash: request.block_hash, transactions, target: request.requester };
+        self.send_to_network(ConsensusMessage::BlockTxs(response)).await;
         Ok(())
     }
     
-    async fn handle_proof_request(&mut self, request: crate::types::ProofRequest) -> Result<()> {
-        debug!("Received ZK proof request for block {}", request.block_number);
+    async fn handle_get_finality_certificate(&mut self, request: GetFinalityCertificate) -> Result<()> {
+        if request.requester == self.node_id || request.target.is_some_and(|target| target != self.node_id) {
+            return Ok(());
+        }
         
-        // Generate proof for requested block
-        if let Some(block) = self.storage.get_block(request.block_number).await? {
-            let proof = self.zk_generator.generate_proof(&block).await?;
-            
-            let response = crate::types::ProofResponse {
-                request_id: request.request_id,
-                proof,
-                responder: self.node_id,
-            };
-            
-            // Send response back to requester
-            // TODO: Implement network response
+        let to_block = request.to_block.min(request.from_block.saturating_add(MAX_CERTIFICATES_PER_REQUEST - 1));
+        let mut certificates = Vec::new();
+        for block in self.storage.get_block_range(request.from_block, to_block).await? {
+            if let Some(certificate) = self.storage.get_certificate(&block.hash()).await? {
+                certificates.push(certificate);
+            }
+        }
+        if certificates.is_empty() {
+            return Ok(());
         }
+        debug!("Serving {} finality certificates from block #{}", certificates.len(), request.from_block);
         
+        let response = CertificateResponse { certificates, target: request.requester };
+        self.send_to_network(ConsensusMessage::FinalityCertificate(response)).await;
         Ok(())
     }
     
-    async fn handle_proof_response(&mut self, response: crate::types::ProofResponse) -> Result<()> {
-        debug!("Received ZK proof response");
-        
-        // Verify the proof
-        if self.zk_generator.verify_proof(&response.proof).await? {
-            info!("Verified ZK proof response");
-        } else {
-            warn!("Invalid ZK proof response");
+    // Certificates are checked against the validator set stored with each block, which the
+    // precommits were weighed under when it was committed
+    async fn handle_finality_certificates(&mut self, response: CertificateResponse) -> Result<()> {
+        if response.target != self.node_id {
+            return Ok(());
         }
         
+        // The precommits of all certificates are verified together
+        let certificates: Vec<_> = response.certificates.iter()
+            .take(MAX_CERTIFICATES_PER_REQUEST as usize)
+            .filter(|certificate| self.certificates.is_missing(certificate.block_number))
+            .collect();
+        let precommits = certificates.iter()
+            .flat_map(|certificate| &certificate.precommits)
+            .map(|vote| SignedMessage::new(vote.validator, vote.signing_hash(), &vote.signature))
+            .collect();
+        let mut verdicts = self.signatures.verify_all(precommits).await?.into_iter();
+        
+        let mut stored = 0;
+        for certificate in certificates {
+            let block_number = certificate.block_number;
+            let signed: Vec<bool> = verdicts.by_ref().take(certificate.precommits.len()).collect();
+            let canonical = self.storage.get_block(block_number).await?.map(|block| block.hash());
+            // Pruned along with the block's account history
+            let state = match self.storage.get_consensus_state_at(block_number).await? {
+                Some(state) => state,
+                None if canonical == Some(certificate.block_hash) => {
+                    self.certificates.received(block_number);
+                    continue;
+                }
+                None => {
+                    warn!("🚫 Finality certificate for block #{} names another block", block_number);
+                    self.report_peer(Misbehaviour::InvalidVote).await;
+                    return Ok(());
+                }
+            };
+            if let Some(index) = signed.iter().position(|valid| !valid) {
+                warn!("🚫 Finality certificate for block #{} has an invalid precommit from {}",
+                    block_number, hex::encode(certificate.precommits[index].validator));
+                self.report_peer(Misbehaviour::InvalidVote).await;
+                return Ok(());
+            }
+            if let Err(e) = bft::weigh_certificate(certificate, &state) {
+                warn!("🚫 Invalid finality certificate for block #{}: {}", block_number, e);
+                self.report_peer(Misbehaviour::InvalidVote).await;
+                return Ok(());
+            }
+            // A quorum of the validators of the time signed it, so the peer only passed it on
+            match canonical {
+                Some(ours) if ours != certificate.block_hash => {
+                    let theirs = certificate.block_hash;
+                    self.raise_safety_violation(SafetyViolation::ConflictingFinality { block_number, ours, theirs }).await?;
+                    return Ok(());
+                }
+                Some(_) => {}
+                None => {
+                    warn!("🚫 Finality certificate for block #{} we do not have", block_number);
+                    self.report_peer(Misbehaviour::InvalidVote).await;
+                    return Ok(());
+                }
+            }
+            
+            self.storage.store_certificate(certificate).await?;
+            self.certificates.received(block_number);
+            stored += 1;
+        }
+        if stored > 0 {
+            debug!("Stored {} backfilled finality certificates", stored);
+            self.certificates.answered();
+        }
         Ok(())
     }
     
-    async fn tick(&mut self) -> Result<()> {
-        // Check if it's time to propose a new block
-        if self.should_propose_block().await? {
-            self.propose_new_block().await?;
+    async fn handle_block_txs(&mut self, response: BlockTxs) -> Result<()> {
+        if response.target != self.node_id {
+            return Ok(());
+        }
+        match self.incomplete_blocks.remove(&response.block_hash) {
+            Some(compact) => self.complete_block(compact, response.transactions).await,
+            None => Ok(()),
+        }
+    }
+    
+    // Assembles the block from `received` and the transactions in storage, then handles it like a full block
+    async fn complete_block(&mut self, compact: CompactBlock, received: Vec<Transaction>) -> Result<()> {
+        let mut received: HashMap<[u8; 32], Transaction> = received.into_iter().map(|tx| (tx.id, tx)).collect();
+        let mut transactions = Vec::with_capacity(compact.tx_ids.len());
+        for tx_id in &compact.tx_ids {
+            let tx = match received.remove(tx_id) {
+                Some(tx) => tx,
+                None => match self.storage.get_transaction(tx_id).await? {
+                    Some(tx) => tx,
+                    None => {
+                        warn!("Peers did not send transaction {} of block {}", hex::encode(tx_id), compact.header.block_number);
+                        return Ok(());
+                    }
+                },
+            };
+            transactions.push(tx);
         }
         
-        // Update consensus state
-        self.update_consensus_state().await?;
+        // Ids are chosen by senders, so a transaction we hold may not be the one the proposer included
+        if crate::types::calculate_merkle_root(&transactions) != compact.header.merkle_root {
+            warn!("Transactions of compact block {} do not match its merkle root", compact.header.block_number);
+            return Ok(());
+        }
         
-        Ok(())
+        self.handle_new_block(compact.into_block(transactions)).await
     }
     
-    async fn should_propose_block(&self) -> Result<bool> {
-        let state = self.state.read().await;
+    async fn handle_proposal(&mut self, proposal: Proposal) -> Result<()> {
+        debug!("Received re-proposal of {} for round {}", hex::encode(proposal.block_hash), proposal.round);
         
-        // Check if we're a validator
-        if !state.validators.contains_key(&self.node_id) {
-            debug!("❌ Not a validator, cannot propose block");
-            return Ok(false);
+        if !crypto::verify_signature(&proposal.proposer, &proposal.signing_hash(), &proposal.signature) {
+            warn!("Invalid proposal signature");
+            self.report_peer(Misbehaviour::InvalidVote).await;
+            return Ok(());
+        }
+        if proposal.height != self.round_state.height {
+            return Ok(());
         }
         
-        // Check if enough time has passed since last block
-        if let Some(last_block) = self.storage.get_latest_block().await? {
-            let time_since_last = Utc::now() - last_block.header.timestamp;
-            if time_since_last < self.block_time {
-                debug!("⏳ Too early to propose block, {} seconds left", 
-                    (self.block_time - time_since_last).num_seconds());
-                return Ok(false);
+        // The block must already be known and build on the last committed block
+        let parent = self.storage.get_finalized_block().await?;
+        let parent_hash = parent.as_ref().map_or([0; 32], |parent| parent.hash());
+        match self.storage.get_block_by_hash(&proposal.block_hash).await? {
+            Some(block) if block.header.parent_hash == parent_hash => {}
+            _ => {
+                debug!("Re-proposed block {} is unknown or stale", hex::encode(proposal.block_hash));
+                return Ok(());
             }
-            debug!("✅ Time to propose new block (last block was {} seconds ago)", 
-                time_since_last.num_seconds());
-        } else {
-            info!("🌟 No previous block found, proposing genesis block");
         }
         
-        Ok(true)
+        let elected = Self::elected_proposer(
+            &*self.state.read().await, &parent_hash, proposal.height, proposal.round);
+        if elected != Some(proposal.proposer) {
+            warn!("🗳️ Re-proposal for round {} from a validator out of turn", proposal.round);
+            return Ok(());
+        }
+        
+        self.on_proposal(proposal.block_hash, proposal.round).await
     }
     
-    async fn propose_new_block(&mut self) -> Result<()> {
-        let state = self.state.read().await;
-        let block_number = state.current_block + 1;
-        
-        info!("📦 Proposing new block #{}", block_number);
+    async fn handle_round_change(&mut self, change: RoundChange) -> Result<()> {
+        let (height, round) = (self.round_state.height, self.round_state.round);
+        if change.height != height || change.round <= round || change.round > round + MAX_ROUND_CHANGE_AHEAD {
+            debug!("Ignoring round change to {} at height {} in round {}", change.round, change.height, round);
+            return Ok(());
+        }
+        if !self.state.read().await.validators.get(&change.validator).is_some_and(|v| v.is_active) {
+            return Ok(());
+        }
+        if !crypto::verify_signature(&change.validator, &change.signing_hash(), &change.signature) {
+            warn!("Invalid round change signature");
+            self.report_peer(Misbehaviour::InvalidVote).await;
+            return Ok(());
+        }
         
-        // Get pending transactions
-        let transactions = self.storage.get_pending_transactions().await?;
-        info!("📋 Found {} pending transactions", transactions.len());
+        self.record_round_change(&change).await
+    }
+    
+    async fn handle_block_vote(&mut self, vote: BlockVote) -> Result<()> {
+        debug!("Received {:?} for block {:?}", vote.step, vote.block_hash);
         
-        // Create block header
-        let parent_hash = if let Some(last_block) = self.storage.get_latest_block().await? {
-            last_block.hash()
-        } else {
-            [0; 32] // Genesis block
-        };
+        // Checked before the signature so replays of old votes cost nothing
+        if vote.height != self.round_state.height {
+            debug!("Ignoring vote for height {} while deciding {}", vote.height, self.round_state.height);
+            return Ok(());
+        }
+        if self.round_state.has_vote(&vote) {
+            debug!("Already counted {:?} from {}", vote.step, hex::encode(vote.validator));
+            return Ok(());
+        }
         
-        let merkle_root = self.calculate_merkle_root(&transactions);
+        // Verify vote signature
+        if !self.verify_vote_signature(&vote).await? {
+            warn!("Invalid vote signature");
+            self.report_peer(Misbehaviour::InvalidVote).await;
+            return Ok(());
+        }
         
-        let header = BlockHeader {
-            block_number,
-            parent_hash,
-            timestamp: Utc::now(),
-            merkle_root,
-            validator: self.node_id,
-            difficulty: self.calculate_difficulty().await?,
-            nonce: 0,
-        };
+        self.process_vote(vote).await
+    }
+    
+    async fn handle_proof_request(&mut self, request: ProofRequest) -> Result<()> {
+        if request.target != self.node_id {
+            return Ok(());
+        }
+        if !crypto::verify_signature(&request.requester, &request.signing_hash(), &request.signature) {
+            self.report_peer(Misbehaviour::Undecodable).await;
+            return Ok(());
+        }
+        debug!("Received ZK proof request for block {}", request.block_number);
         
-        // Create block
-        let mut block = Block {
-            header,
-            transactions,
-            zk_proof: ZKProof {
-                proof_data: vec![],
-                public_inputs: vec![],
-                verification_key: vec![],
-                proof_type: crate::types::ProofType::Groth16,
-            },
+        let block = match self.storage.get_block_by_hash(&request.block_hash).await? {
+            Some(block) => block,
+            None => return Ok(()),
         };
+        // Pruned blocks lost their transactions and can not be proven again
+        if self.storage.is_header_only(block.header.block_number).await? {
+            return Ok(());
+        }
         
-        info!("🔐 Generating ZK proof for block #{}", block_number);
-        // Generate ZK proof
-        block.zk_proof = self.zk_generator.generate_proof(&block).await?;
-        info!("✅ ZK proof generated ({} bytes)", block.zk_proof.proof_data.len());
+        // Blocks proven before are answered right away, without waiting for a proof worker or
+        // counting against the peer's quota
+        if let Some(proof) = self.zk_generator.cached_proof(&block).await? {
+            let network_tx = self.network_tx.clone();
+            let responder = self.node_id;
+            tokio::spawn(send_proof_response(network_tx, request, proof, responder));
+            return Ok(());
+        }
         
-        // Store block
-        self.storage.store_block(&block).await?;
+        // Verifier-only nodes have no proving keys to answer with
+        if !self.zk_generator.can_prove() {
+            debug!("Not answering proof request for block {}: this node only verifies", request.block_number);
+            return Ok(());
+        }
         
-        // Broadcast block (mock for now)
-        self.broadcast_block(block.clone()).await?;
+        let validator = self.state.read().await.validators.get(&request.requester).is_some_and(|v| v.is_active);
+        // The witness below costs a scan of every account, so it is only computed for requests
+        // the queue takes
+        let queued = {
+            let mut proof_queue = self.proof_queue.lock().await;
+            match proof_queue.join(&request, validator) {
+                Ok(false) => proof_queue.can_accept(&request.requester, validator).map(|_| false),
+                joined => joined,
+            }
+        };
+        match queued {
+            Ok(true) => return Ok(()),
+            Ok(false) => {}
+            Err(reason) => {
+                debug!("Not answering proof request for block {} from {}: {}",
+                    request.block_number, hex::encode(request.requester), reason);
+                return Ok(());
+            }
+        }
         
-        // Update consensus state
-        let mut state = self.state.write().await;
-        state.current_block = block_number;
+        // The proof has to prove what the block's own proof does, validator set included
+        let inputs = match PublicInputs::from_bytes(&block.zk_proof.public_inputs) {
+            Ok(inputs) => inputs,
+            Err(e) => {
+                debug!("Not answering proof request for block {}: {}", request.block_number, e);
+                return Ok(());
+            }
+        };
+        // Proving needs the state the block was executed on
+        let state = match self.state_transition(&block).await? {
+            Some(state) => state,
+            None => {
+                debug!("Not answering proof request for block {}: its parent state is not kept", request.block_number);
+                return Ok(());
+            }
+        };
+        let (block_number, requester) = (request.block_number, request.requester);
+        match self.proof_queue.lock().await.push(block, inputs, state, request, validator) {
+            Ok(evicted) => {
+                for dropped in evicted {
+                    debug!("Dropped proof request for block {} from {} for a validator's", dropped.block_number, hex::encode(dropped.requester));
+                }
+            }
+            Err(reason) => {
+                debug!("Not answering proof request for block {} from {}: {}", block_number, hex::encode(requester), reason);
+                return Ok(());
+            }
+        }
         
-        info!("🎉 Successfully proposed and stored block #{}", block_number);
+        // Proven in the background so it does not hold up the consensus loop; the task returns
+        // right away when the request workers are all busy, and one of them picks the block up
+        tokio::spawn(prove_queued_requests(self.proof_queue.clone(), self.proof_service.clone(), self.network_tx.clone(), self.node_id));
         Ok(())
     }
     
-    async fn verify_block_structure(&self, block: &Block) -> Result<bool> {
-        // Verify block number is sequential
-        let state = self.state.read().await;
-        if block.header.block_number != state.current_block + 1 {
-            return Ok(false);
+    // Witness for proving a block: the finalized state at its parent, and what the block does
+    // to it. None when that state is not kept, e.g. for blocks above the finalized one
+    async fn state_transition(&self, block: &Block) -> Result<Option<StateTransition>> {
+        let before = match block.header.block_number {
+            0 => AccountState::default(),
+            height => AccountState::new(self.storage.get_accounts_at(height - 1).await?),
+        };
+        let rewards = self.block_rewards(block.header.block_number, &block.header.validator, &block.parent_votes).await?;
+        let unbonded = self.unbonded_stake(block).await?;
+        let mut after = before.clone();
+        if after.apply_verified_block(block, &rewards, &unbonded).is_err() || after.root() != block.header.state_root {
+            return Ok(None);
         }
+        Ok(Some(before.transition(&after)))
+    }
+    
+    // Serves light clients the headers and proofs of finalized blocks from the requested height
+    async fn handle_header_request(&mut self, request: HeaderRequest) -> Result<()> {
+        debug!("Received header request from block {}", request.from_block);
         
-        // Verify parent hash
-        if let Some(last_block) = self.storage.get_latest_block().await? {
-            if block.header.parent_hash != last_block.hash() {
-                return Ok(false);
-            }
+        let finalized = match self.storage.get_finalized_block().await? {
+            Some(finalized) => finalized.header.block_number,
+            None => return Ok(()),
+        };
+        let last = finalized.min(request.from_block.saturating_add(MAX_HEADERS_PER_RESPONSE - 1));
+        if request.from_block == 0 || request.from_block > last {
+            return Ok(());
         }
         
-        // Verify merkle root
-        let calculated_root = self.calculate_merkle_root(&block.transactions);
-        if block.header.merkle_root != calculated_root {
-            return Ok(false);
+        // Served up to the first block whose certificate or validator set is no longer kept
+        let mut headers = Vec::new();
+        let mut previous = None;
+        for block in self.storage.get_block_range(request.from_block, last).await? {
+            let certificate = match self.storage.get_certificate(&block.hash()).await? {
+                Some(certificate) => certificate,
+                None => break,
+            };
+            let state = match self.storage.get_consensus_state_at(block.header.block_number).await? {
+                Some(state) => state,
+                None => break,
+            };
+            let current = Some((validator_set_hash(&state), state.params));
+            let changed = previous != current;
+            previous = current;
+            headers.push(CertifiedHeader {
+                block: block.light(),
+                certificate,
+                validators: changed.then(|| staking::active_validators(&state)),
+                params: changed.then_some(state.params),
+            });
+        }
+        if headers.is_empty() {
+            return Ok(());
         }
+        let response = HeaderResponse {
+            request_id: request.request_id,
+            headers,
+            responder: self.node_id,
+        };
         
-        Ok(true)
+        self.send_to_network(ConsensusMessage::HeaderResponse(response)).await;
+        Ok(())
+    }
+    
+    async fn handle_get_blocks(&mut self, request: GetBlocks) -> Result<()> {
+        if request.target.is_some_and(|target| target != self.node_id) {
+            return Ok(());
+        }
+        
+        let finalized = match self.storage.get_finalized_block().await? {
+            Some(finalized) => finalized.header.block_number,
+            None => return Ok(()),
+        };
+        let last = finalized
+            .min(request.to_block)
+            .min(request.from_block.saturating_add(sync::BATCH_SIZE - 1));
+        if request.from_block == 0 || request.from_block > last {
+            return Ok(());
+        }
+        // Pruned blocks no longer match their merkle root; archive peers serve those
+        if self.storage.is_header_only(request.from_block).await? {
+            return Ok(());
+        }
+        
+        // Stay below the gossipsub message size limit; the requester asks again for the rest
+        let mut blocks = Vec::new();
+        let mut size = 0;
+        for block in self.storage.get_block_range(request.from_block, last).await? {
+            size += block.encode().len() as u64;
+            if !blocks.is_empty() && size > MAX_BLOCKS_RESPONSE_BYTES {
+                break;
+            }
+            blocks.push(block);
+        }
+        
+        debug!("Serving {} blocks from #{}", blocks.len(), request.from_block);
+        let response = BlocksResponse {
+            request_id: request.request_id,
+            blocks,
+            finalized_height: finalized,
+            responder: self.node_id,
+        };
+        self.send_to_network(ConsensusMessage::BlocksResponse(response)).await;
+        Ok(())
+    }
+    
+    // Imports downloaded blocks in order on top of the last finalized block. Peers only serve
+    // finalized blocks, so each one is final once it passed the import stages. The stages that
+    // only need the block itself run concurrently for a batch; the rest run block by block
+    async fn import_synced_blocks(&mut self) -> Result<()> {
+        loop {
+            let height = self.storage.get_finalized_block().await?.map_or(0, |parent| parent.header.block_number);
+            let batch = self.sync.next_blocks(height, MAX_CONCURRENT_IMPORTS);
+            if batch.is_empty() {
+                return Ok(());
+            }
+            
+            let prechecks: Vec<_> = batch.iter()
+                .map(|(block, _)| tokio::spawn(self.import.clone().precheck(
+                    self.zk_generator.clone(), self.signatures.clone(), self.limits, self.proofs, block.clone())))
+                .collect();
+            
+            let mut pending = batch.into_iter().zip(prechecks);
+            while let Some(((block, peer), precheck)) = pending.next() {
+                let imported = match precheck.await {
+                    Ok(Ok(true)) => self.import_synced_block(&block).await,
+                    Ok(passed) => passed,
+                    Err(e) => Err(anyhow!("Checking synced block #{} panicked: {}", block.header.block_number, e)),
+                };
+                
+                match imported {
+                    Ok(true) => {}
+                    Ok(false) => {
+                        warn!("🚫 Rejected synced block #{} from {}", block.header.block_number, hex::encode(peer));
+                        // Later blocks another peer served are still good
+                        self.sync.requeue(unimported(pending));
+                        self.sync.block_rejected(&peer).await;
+                        return Ok(());
+                    }
+                    // The error need not be the block's fault, so it is imported again on the next try
+                    Err(e) => {
+                        let mut rest = unimported(pending);
+                        rest.push((block, peer));
+                        self.sync.requeue(rest);
+                        return Err(e);
+                    }
+                }
+                self.sync.block_imported().await;
+                let certificate = match self.storage.get_certificate(&block.hash()).await {
+                    Ok(certificate) => certificate,
+                    Err(e) => {
+                        self.sync.requeue(unimported(pending));
+                        return Err(e.into());
+                    }
+                };
+                if certificate.is_none() {
+                    self.certificates.add(block.header.block_number, peer);
+                }
+                debug!("Imported synced block #{}", block.header.block_number);
+            }
+        }
+    }
+    
+    // The in-order stages of importing a synced block, which has passed decode, signatures and proof
+    async fn import_synced_block(&mut self, block: &Block) -> Result<bool> {
+        let import = self.import.clone();
+        let parent = self.storage.get_finalized_block().await?;
+        let parent_hash = parent.as_ref().map_or([0; 32], |parent| parent.hash());
+        if block.header.parent_hash != parent_hash {
+            warn!("🔗 Synced block #{} does not extend the finalized chain", block.header.block_number);
+            return Ok(false);
+        }
+        
+        if !import.run(Stage::Header, self.verify_block_header(block, parent.as_ref())).await? {
+            return Ok(false);
+        }
+        if !import.run(Stage::Execute, self.verify_block_state(block)).await? {
+            return Ok(false);
+        }
+        if self.storage.get_block_by_hash(&block.hash()).await?.is_none() {
+            import.run(Stage::Store, async { self.store_proposal(block).await.map(|_| true) }).await?;
+        }
+        import.run(Stage::Finalize, self.finalize(block)).await
+    }
+    
+    // Replays blocks another database finalized, in order, through the stages synced blocks go
+    // through, so the state, receipts and indexes are rebuilt from them. Returns the number of
+    // the first block that is rejected
+    pub async fn reindex_blocks(&mut self, blocks: Vec<Block>) -> Result<Option<u64>> {
+        let prechecks: Vec<_> = blocks.iter()
+            .map(|block| tokio::spawn(self.import.clone().precheck(
+                self.zk_generator.clone(), self.signatures.clone(), self.limits, self.proofs, block.clone())))
+            .collect();
+        
+        for (block, precheck) in blocks.iter().zip(prechecks) {
+            let passed = match precheck.await {
+                Ok(passed) => passed?,
+                Err(e) => bail!("Checking block #{} panicked: {}", block.header.block_number, e),
+            };
+            if !(passed && self.import_synced_block(block).await?) {
+                return Ok(Some(block.header.block_number));
+            }
+        }
+        Ok(None)
+    }
+    
+    // Asks the best peer for a snapshot once, when we have no blocks and are far behind, or
+    // for one of the trusted checkpoint once a peer has finalized it. Returns whether a
+    // snapshot is downloading
+    async fn start_fast_sync(&mut self) -> bool {
+        if self.snapshot_download.is_some() {
+            return true;
+        }
+        let min_blocks = self.checkpoint.map_or(FAST_SYNC_MIN_BLOCKS, |checkpoint| checkpoint.height);
+        if self.fast_sync_tried
+            || self.round_state.height > 1
+            || self.sync.target_height() < min_blocks
+        {
+            return false;
+        }
+        let peer = match self.sync.best_peer() {
+            Some(peer) => peer,
+            None => return false,
+        };
+        
+        self.fast_sync_tried = true;
+        let request = SnapshotRequest {
+            request_id: self.rng.gen(),
+            requester: self.node_id,
+            target: peer,
+            height: self.checkpoint.map(|checkpoint| checkpoint.height),
+        };
+        let deadline = self.clock.now() + Duration::seconds(SNAPSHOT_TIMEOUT_SECS);
+        self.snapshot_download = Some(SnapshotDownload::new(request.request_id, peer, deadline));
+        
+        info!("📸 {} blocks behind, requesting a snapshot from {}", self.sync.target_height(), hex::encode(peer));
+        self.send_to_network(ConsensusMessage::GetSnapshot(request)).await;
+        true
+    }
+    
+    async fn handle_get_snapshot(&mut self, request: SnapshotRequest) -> Result<()> {
+        if request.target != self.node_id {
+            return Ok(());
+        }
+        // Requests are unsigned and each one costs a full snapshot, so few are served
+        let now = self.clock.now();
+        if self.snapshot_served_at.is_some_and(|served_at| now - served_at < Duration::seconds(SNAPSHOT_SERVE_INTERVAL_SECS)) {
+            debug!("Not serving a snapshot to {}, one was served less than {}s ago",
+                hex::encode(request.requester), SNAPSHOT_SERVE_INTERVAL_SECS);
+            return Ok(());
+        }
+        // Snapshots at an older height are rebuilt from the account history, at a far higher cost
+        if request.height.is_some() && self.snapshot_rebuilt_at.is_some_and(|rebuilt_at| now - rebuilt_at < Duration::seconds(SNAPSHOT_REBUILD_INTERVAL_SECS)) {
+            debug!("Not rebuilding a snapshot for {}, one was rebuilt less than {}s ago",
+                hex::encode(request.requester), SNAPSHOT_REBUILD_INTERVAL_SECS);
+            return Ok(());
+        }
+        self.snapshot_served_at = Some(now);
+        if request.height.is_some() {
+            self.snapshot_rebuilt_at = Some(now);
+        }
+        
+        let snapshot = match request.height {
+            Some(height) => Snapshot::at_height(&self.storage, height).await?,
+            None => match Snapshot::from_storage(&self.storage).await? {
+                Some(mut snapshot) => {
+                    // The stored consensus state is only written on shutdown
+                    snapshot.consensus_state = Some(self.state.read().await.clone());
+                    Some(snapshot)
+                }
+                None => None,
+            },
+        };
+        // Snapshots of blocks without a proof of their own are rejected on import
+        let snapshot = match snapshot {
+            Some(snapshot) if snapshot.block.zk_proof.checkpoint_reference().is_none() => snapshot,
+            _ => return Ok(()),
+        };
+        
+        let archive = snapshot.encode()?;
+        let chunks = snapshot::split_archive(&archive, request.request_id, self.node_id, snapshot.height);
+        info!("📸 Serving snapshot #{} to {} ({} bytes in {} chunks)",
+            snapshot.height, hex::encode(request.requester), archive.len(), chunks.len());
+        for chunk in chunks {
+            self.send_to_network(ConsensusMessage::SnapshotChunk(chunk)).await;
+        }
+        Ok(())
+    }
+    
+    async fn handle_snapshot_chunk(&mut self, chunk: SnapshotChunk) -> Result<()> {
+        let download = match &mut self.snapshot_download {
+            Some(download) if download.request_id == chunk.request_id => download,
+            _ => return Ok(()),
+        };
+        
+        let archive = match download.add_chunk(chunk) {
+            Ok(Some(archive)) => archive,
+            Ok(None) => return Ok(()),
+            Err(e) => {
+                warn!("❌ Abandoning snapshot download: {}", e);
+                self.snapshot_download = None;
+                return Ok(());
+            }
+        };
+        self.snapshot_download = None;
+        
+        let snapshot = match Snapshot::decode(&archive) {
+            Ok(snapshot) => snapshot,
+            Err(e) => {
+                warn!("❌ Peer snapshot does not decode: {}", e);
+                return Ok(());
+            }
+        };
+        // Without a trusted checkpoint the validators we start out with, the genesis set, must
+        // have finalized the snapshot's block
+        let state = self.state.read().await.clone();
+        let trust = match &self.checkpoint {
+            Some(checkpoint) => SnapshotTrust::Checkpoint(checkpoint),
+            None => SnapshotTrust::Validators(&state),
+        };
+        if let Err(e) = snapshot.verify(&self.zk_generator, trust).await {
+            warn!("❌ Peer snapshot rejected: {}", e);
+            return Ok(());
+        }
+        // Blocks may have been imported while the snapshot downloaded
+        if self.round_state.height > 1 {
+            debug!("Discarding peer snapshot, blocks were imported meanwhile");
+            return Ok(());
+        }
+        
+        self.load_snapshot(snapshot).await?;
+        self.import_synced_blocks().await
+    }
+    
+    // Continues from an imported snapshot as if its block had just been finalized
+    async fn load_snapshot(&mut self, snapshot: Snapshot) -> Result<()> {
+        snapshot.import_into(&self.storage).await?;
+        
+        self.accounts = AccountState::new(snapshot.accounts.iter().copied().collect());
+        if let Some(state) = snapshot.consensus_state {
+            *self.state.write().await = state;
+        }
+        {
+            let mut state = self.state.write().await;
+            state.head = snapshot.height;
+            state.justified = snapshot.height;
+            state.finalized = snapshot.height;
+        }
+        // The snapshot's state may run on another block time than the one we started with
+        self.apply_params().await;
+        self.round_state = RoundState::new(snapshot.height + 1);
+        
+        info!("⏩ Fast-synced to block #{} from a peer snapshot", snapshot.height);
+        Ok(())
+    }
+    
+    // Proposals are executed on the accounts of the finalized block they build on
+    async fn verify_block_state(&self, block: &Block) -> Result<bool> {
+        let parent_hash = self.storage.get_finalized_block().await?.map_or([0; 32], |parent| parent.hash());
+        if block.header.parent_hash != parent_hash {
+            debug!("Block #{} does not build on the finalized block", block.header.block_number);
+            return Ok(false);
+        }
+        
+        let rewards = self.block_rewards(block.header.block_number, &block.header.validator, &block.parent_votes).await?;
+        let unbonded = self.unbonded_stake(block).await?;
+        let mut accounts = self.accounts.clone();
+        if let Err(e) = accounts.apply_verified_block(block, &rewards, &unbonded) {
+            warn!("💸 Block #{} does not execute: {}", block.header.block_number, e);
+            if matches!(e.error, TransactionError::Overdraw { .. }) {
+                self.import.record_overdraw().await;
+            }
+            return Ok(false);
+        }
+        
+        let state_root = accounts.root();
+        if state_root != block.header.state_root {
+            warn!("💸 Block #{} claims state root {} but executes to {}",
+                block.header.block_number, hex::encode(block.header.state_root), hex::encode(state_root));
+            return Ok(false);
+        }
+        
+        Ok(true)
+    }
+    
+    // What the block mints, paid out by the active set of the consensus state finalized at its
+    // parent. A block without parent votes only pays its proposer, whatever the set
+    async fn block_rewards(&self, block_number: u64, proposer: &NodeId, parent_votes: &[BlockVote]) -> Result<Vec<(NodeId, u64)>> {
+        if parent_votes.is_empty() {
+            return Ok(staking::block_rewards(&*self.state.read().await, proposer, parent_votes));
+        }
+        match self.storage.get_consensus_state_at(block_number.saturating_sub(1)).await? {
+            Some(parent) => Ok(staking::block_rewards(&parent, proposer, parent_votes)),
+            None => bail!("No consensus state is kept for block #{}, the parent of #{}", block_number.saturating_sub(1), block_number),
+        }
+    }
+    
+    // Stake the block gives back to validators' balances: the unbonds falling due when it opens
+    // an epoch, as applied to the consensus state finalized at its parent. Unbonds are never due
+    // in a chain's first block
+    async fn unbonded_stake(&self, block: &Block) -> Result<Vec<(NodeId, u64)>> {
+        let block_number = block.header.block_number;
+        if block_number <= 1 || !staking::opens_epoch(block) {
+            return Ok(Vec::new());
+        }
+        match self.storage.get_consensus_state_at(block_number - 1).await? {
+            Some(parent) => Ok(staking::unbonded_stake(&parent, block, &self.liveness)),
+            None => bail!("No consensus state is kept for block #{}, the parent of #{}", block_number - 1, block_number),
+        }
+    }
+    
+    // Keeps a block whose proof we can not verify and asks a validator to prove it
+    async fn request_proof(&mut self, block: Block) -> Result<()> {
+        let block_number = block.header.block_number;
+        let block_hash = block.hash();
+        if self.unproven_blocks.contains_key(&block_hash) {
+            return Ok(());
+        }
+        if self.unproven_blocks.len() >= MAX_UNPROVEN_BLOCKS {
+            debug!("Too many unproven blocks, dropping block {}", block_number);
+            return Ok(());
+        }
+        
+        let candidates = self.proof_candidates(&block).await;
+        let now = self.clock.now();
+        match self.proof_requests.request(block_hash, block_number, &candidates, &self.keypair, now) {
+            Some(request) => {
+                debug!("No {:?} backend for block {}, asking {} for a proof",
+                    block.zk_proof.proof_type, block_number, hex::encode(request.target));
+                self.unproven_blocks.insert(block_hash, block);
+                self.send_to_network(ConsensusMessage::ZKProofRequest(request)).await;
+            }
+            None => warn!("No validator to prove block {} with a {:?} proof we can not verify",
+                block_number, block.zk_proof.proof_type),
+        }
+        Ok(())
+    }
+    
+    // Active validators other than us and the proposer, best performing first
+    async fn proof_candidates(&self, block: &Block) -> Vec<NodeId> {
+        let state = self.state.read().await;
+        let mut candidates: Vec<_> = state.validators.iter()
+            .filter(|(node_id, info)| {
+                info.is_active && **node_id != self.node_id && **node_id != block.header.validator
+            })
+            .collect();
+        candidates.sort_by(|(a_id, a), (b_id, b)| {
+            b.performance_score.total_cmp(&a.performance_score).then(a_id.cmp(b_id))
+        });
+        candidates.into_iter().map(|(node_id, _)| *node_id).collect()
+    }
+    
+    async fn handle_proof_response(&mut self, response: ProofResponse) -> Result<()> {
+        if response.target != self.node_id {
+            return Ok(());
+        }
+        let open = match self.proof_requests.take(&response) {
+            Some(open) => open,
+            None => return Ok(()),
+        };
+        let block = match self.unproven_blocks.get(&open.block_hash) {
+            Some(block) => block.clone(),
+            None => return Ok(()),
+        };
+        debug!("Received ZK proof response for block {}", open.block_number);
+        
+        if !self.zk_generator.can_verify(&response.proof.proof_type) {
+            debug!("{} proved block {} with a {:?} proof, which we can not verify either",
+                hex::encode(response.responder), open.block_number, response.proof.proof_type);
+        } else if self.zk_generator.verify_block_proof_with(&block, &response.proof).await? {
+            info!("✅ Block {} proven by {}", open.block_number, hex::encode(response.responder));
+            self.unproven_blocks.remove(&open.block_hash);
+            
+            let latency = (self.clock.now() - open.sent_at).num_milliseconds().max(0) as u64;
+            let epoch = self.state.read().await.epoch;
+            self.update_epoch_stats(epoch, |stats| {
+                let responder = stats.validators.entry(response.responder).or_default();
+                responder.proof_responses += 1;
+                responder.proof_latency_ms += latency;
+            }).await?;
+            return self.handle_new_block(block).await;
+        } else {
+            warn!("Invalid ZK proof response for block {}", open.block_number);
+            self.report_peer(Misbehaviour::InvalidProof).await;
+        }
+        
+        self.retry_proof_request(open).await;
+        Ok(())
+    }
+    
+    async fn retry_proof_request(&mut self, open: OpenRequest) {
+        let block = match self.unproven_blocks.get(&open.block_hash) {
+            Some(block) => block.clone(),
+            None => return,
+        };
+        
+        let candidates = self.proof_candidates(&block).await;
+        let now = self.clock.now();
+        match self.proof_requests.retry(open, &candidates, &self.keypair, now) {
+            Some(request) => {
+                debug!("Asking {} for a proof of block {} instead", hex::encode(request.target), request.block_number);
+                self.send_to_network(ConsensusMessage::ZKProofRequest(request)).await;
+            }
+            None => {
+                warn!("⌛ Giving up on a proof for block {}", block.header.block_number);
+                self.unproven_blocks.remove(&block.hash());
+            }
+        }
+    }
+    
+    async fn tick(&mut self) -> Result<()> {
+        // The timestamp fixes the round we are in and, when proposing, the round we were elected for
+        let now = self.clock.now();
+        self.update_round(now).await?;
+        
+        if self.snapshot_download.as_ref().is_some_and(|download| now >= download.deadline) {
+            warn!("⌛ Snapshot download timed out, syncing from genesis instead");
+            self.snapshot_download = None;
+        }
+        
+        for request in self.sync.poll(self.round_state.height - 1, now).await {
+            // Only probe while a snapshot downloads, so blocks from genesis are not fetched alongside
+            if self.snapshot_download.is_some() && request.target.is_some() {
+                continue;
+            }
+            self.send_to_network(ConsensusMessage::GetBlocks(request)).await;
+        }
+        if let Some(request) = self.certificates.poll(self.node_id, now) {
+            debug!("Requesting finality certificates of blocks #{}..#{}", request.from_block, request.to_block);
+            self.send_to_network(ConsensusMessage::GetFinalityCertificate(request)).await;
+        }
+        
+        // Check if it's time to propose a new block
+        if self.should_propose_block(now).await? && self.seal_due(now).await? {
+            self.propose(now).await?;
+        }
+        
+        // With instant sealing rounds pass idle while nothing is pending, and nobody else could
+        // take over anyway
+        if !self.instant_seal {
+            self.check_round_timeouts(now).await?;
+        }
+        
+        let height = self.round_state.height;
+        self.unproven_blocks.retain(|_, block| block.header.block_number >= height);
+        for open in self.proof_requests.expire(now) {
+            self.retry_proof_request(open).await;
+        }
+        
+        Ok(())
+    }
+    
+    async fn should_propose_block(&self, now: DateTime<Utc>) -> Result<bool> {
+        let state = self.state.read().await;
+        
+        // Check if we're an active validator
+        if !state.validators.get(&self.node_id).is_some_and(|v| v.is_active) {
+            debug!("❌ Not an active validator, cannot propose block");
+            return Ok(false);
+        }
+        
+        if self.round_state.proposed {
+            return Ok(false);
+        }
+        
+        if self.safety.halted {
+            debug!("🚨 Halted on a safety violation, not proposing");
+            return Ok(false);
+        }
+        
+        // Peers have already finalized this height, so a proposal would only be a stale branch
+        if self.sync.is_behind(self.round_state.height - 1) {
+            debug!("⏬ Still syncing, not proposing");
+            return Ok(false);
+        }
+        
+        // New blocks build on the last committed block from the slot after its own
+        let parent = self.storage.get_finalized_block().await?;
+        if let Some(parent) = &parent {
+            let first_round = self.round_start(parent, 0);
+            if now < first_round {
+                debug!("⏳ Too early to propose block, {} seconds left", (first_round - now).num_seconds());
+                return Ok(false);
+            }
+            debug!("✅ Time to propose new block in slot {}", self.slots.slot_at(now));
+        } else {
+            info!("🌟 No previous block found, proposing genesis block");
+        }
+        
+        // Only the elected proposer for this height and round may propose
+        let parent_hash = parent.as_ref().map_or([0; 32], |block| block.hash());
+        let (height, round) = (self.round_state.height, self.round_state.round);
+        if Self::elected_proposer(&state, &parent_hash, height, round) != Some(self.node_id) {
+            debug!("🗳️ Not our turn for block #{} round {}", height, round);
+            return Ok(false);
+        }
+        
+        Ok(true)
+    }
+    
+    // Without instant sealing there is a block every slot. Transactions waiting on an earlier
+    // nonce do not call for one
+    async fn seal_due(&mut self, now: DateTime<Utc>) -> Result<bool> {
+        if !self.instant_seal {
+            return Ok(true);
+        }
+        let parent = match self.storage.get_finalized_block().await? {
+            Some(parent) => parent,
+            None => return Ok(true),
+        };
+        if self.seal_interval > Duration::zero() && now - parent.header.timestamp >= self.seal_interval {
+            return Ok(true);
+        }
+        self.mempool.refresh().await?;
+        Ok(self.mempool.transactions().any(|tx| tx.nonce <= self.accounts.account(&tx.from).nonce))
+    }
+    
+    // Rounds advance every slot past the parent's, so a silent proposer is skipped
+    fn proposal_round(&self, parent: Option<&Block>, timestamp: DateTime<Utc>) -> u64 {
+        match parent {
+            Some(parent) => self.slots.round(parent.header.timestamp, timestamp).unwrap_or(0),
+            None => 0,
+        }
+    }
+    
+    // Choice over active validators weighted by stake and performance, seeded by the parent hash,
+    // height and round
+    fn elected_proposer(state: &ConsensusState, parent_hash: &BlockHash, height: u64, round: u64) -> Option<NodeId> {
+        let mut candidates: Vec<_> = state.validators.iter()
+            .filter(|(_, info)| info.is_active)
+            .map(|(node_id, info)| (*node_id, staking::proposer_weight(info)))
+            .filter(|(_, weight)| *weight > 0)
+            .collect();
+        candidates.sort_by_key(|(node_id, _)| *node_id);
+        
+        let total_weight: u64 = candidates.iter().map(|(_, weight)| weight).sum();
+        if total_weight == 0 {
+            return None;
+        }
+        
+        let mut hasher = Sha256::new();
+        hasher.update(parent_hash);
+        hasher.update(height.to_le_bytes());
+        hasher.update(round.to_le_bytes());
+        let seed: [u8; 32] = hasher.finalize().into();
+        let mut target = u64::from_le_bytes(seed[..8].try_into().unwrap()) % total_weight;
+        
+        for (node_id, weight) in candidates {
+            if target < weight {
+                return Some(node_id);
+            }
+            target -= weight;
+        }
+        
+        None
+    }
+    
+    async fn propose(&mut self, now: DateTime<Utc>) -> Result<()> {
+        self.round_state.proposed = true;
+        self.checkpoint_round().await?;
+        let (height, round) = (self.round_state.height, self.round_state.round);
+        
+        // A block that already gathered a polka is re-proposed instead of a new one
+        if let Some((block_hash, polka_round)) = self.round_state.valid {
+            info!("📦 Re-proposing block {} (polka in round {}) for round {}",
+                hex::encode(block_hash), polka_round, round);
+            let mut proposal = Proposal {
+                height,
+                round,
+                block_hash,
+                proposer: self.node_id,
+                signature: vec![],
+            };
+            proposal.signature = self.keypair.sign(&proposal.signing_hash());
+            self.send_to_network(ConsensusMessage::Proposal(proposal)).await;
+            return self.on_proposal(block_hash, round).await;
+        }
+        
+        // After a round change quorum the round can start ahead of our clock, and the
+        // timestamp is what tells other validators which round the block was proposed in
+        let timestamp = match self.storage.get_finalized_block().await? {
+            Some(parent) => now.max(self.round_start(&parent, round)),
+            None => now,
+        };
+        self.propose_new_block(timestamp, round).await
+    }
+    
+    // Builds the block and queues its proof; it is proposed once the proof arrives. Blocks the
+    // proof policy does not prove are proposed right away
+    async fn propose_new_block(&mut self, timestamp: DateTime<Utc>, round: u64) -> Result<()> {
+        let block_number = self.round_state.height;
+        
+        info!("📦 Proposing new block #{}", block_number);
+        
+        // Get pending transactions, highest fee first; staking transactions go first so the
+        // proof backend's cap never drops the genesis bond
+        self.mempool.refresh().await?;
+        let pending = self.mempool.by_fee();
+        info!("📋 Found {} pending transactions", pending.len());
+        
+        // New blocks build on the last committed block
+        let parent = self.storage.get_finalized_block().await?;
+        let parent_hash = match &parent {
+            Some(parent) => parent.hash(),
+            None => [0; 32], // Bootstrapping a chain without a genesis file
+        };
+        let parent_votes = match &parent {
+            Some(parent) => self.collect_parent_votes(parent).await?,
+            None => Vec::new(),
+        };
+        
+        // Validators whose clocks run ahead of ours may have committed the parent after the time
+        // we would give the block
+        let timestamp = match Self::median_vote_time(&*self.state.read().await, &parent_votes) {
+            Some(median) if median > timestamp => median,
+            _ => timestamp,
+        };
+        if self.proposal_round(parent.as_ref(), timestamp) != round {
+            warn!("⏰ Can not propose block #{}: the parent was committed after round {}", block_number, round);
+            return Ok(());
+        }
+        
+        // Include what executes on the finalized state, up to what the proving backend can handle
+        // and the block limits allow
+        let max_transactions = self.zk_generator.max_transactions().unwrap_or(usize::MAX)
+            .min(self.limits.max_txs_per_block as usize);
+        // Checkpoints are proven, now or once final, by circuits of bounded state changes
+        let capacity = self.zk_generator.state_capacity().filter(|_| self.proofs.is_checkpoint(block_number));
+        let rewards = self.block_rewards(block_number, &self.node_id, &parent_votes).await?;
+        let mut accounts = self.accounts.clone();
+        accounts.begin_block(&parent_hash, &self.node_id, &rewards);
+        // Every account the block changes, so the capacity is checked without comparing whole
+        // states, and what each transaction taken overwrote, so it can be taken back out
+        let mut touched: BTreeSet<AccountId> = rewards.iter().map(|(id, _)| *id).chain([self.node_id]).collect();
+        let mut undo = Vec::new();
+        let mut transactions = Vec::new();
+        let mut block_bytes = 0;
+        let mut stale = Vec::new();
+        let mut expired = Vec::new();
+        let mut overdrawn = Vec::new();
+        for tx in pending {
+            if transactions.len() == max_transactions {
+                break;
+            }
+            if tx.is_expired(block_number, &timestamp) {
+                expired.push(tx.id);
+                continue;
+            }
+            if tx.nonce < accounts.account(&tx.from).nonce {
+                stale.push(tx.id);
+                continue;
+            }
+            // Transactions too large for any block would otherwise sit in the mempool for good
+            if let Err(e) = tx.validate().and_then(|()| self.limits.check_transaction(&tx)) {
+                debug!("Dropping transaction {}: {}", hex::encode(tx.id), e);
+                stale.push(tx.id);
+                continue;
+            }
+            let tx_bytes = tx.encode().len() as u64;
+            if block_bytes + tx_bytes > self.limits.max_block_bytes {
+                debug!("Leaving out transaction {}: the block is full", hex::encode(tx.id));
+                continue;
+            }
+            // Evidence we can not confirm would make the whole block invalid to other validators
+            if let TransactionKind::Evidence(report) = &tx.kind {
+                if !self.verify_evidence(&report.evidence).await? {
+                    stale.push(tx.id);
+                    continue;
+                }
+            }
+            // Executed on what the transactions taken so far left, so of two spending the same
+            // funds only the first goes in. The other leaves the pool, as funds arriving later
+            // would not make it any less of a double spend
+            let ids = AccountState::touched_by(&tx, &self.node_id);
+            let saved = accounts.save(&ids);
+            match accounts.apply_transaction(&tx, &self.node_id) {
+                Ok(()) => {
+                    if let Some(capacity) = capacity {
+                        touched.extend(ids);
+                        if !capacity.fits(&self.accounts, &accounts, &touched) {
+                            debug!("Leaving out transaction {}: the block would change more state than its proof covers", hex::encode(tx.id));
+                            accounts.restore(saved);
+                            continue;
+                        }
+                        undo.push(saved);
+                    }
+                    block_bytes += tx_bytes;
+                    transactions.push(tx);
+                }
+                Err(e @ TransactionError::Overdraw { .. }) => {
+                    debug!("Dropping transaction {} of {}: {}", hex::encode(tx.id), hex::encode(tx.from), e);
+                    overdrawn.push(tx.id);
+                }
+                Err(e) => debug!("Leaving out transaction {}: {}", hex::encode(tx.id), e),
+            }
+        }
+        self.storage.remove_pending_transactions(&stale, DropReason::Invalid).await?;
+        self.storage.remove_pending_transactions(&expired, DropReason::Expired).await?;
+        self.storage.remove_pending_transactions(&overdrawn, DropReason::Overdraw).await?;
+        
+        let merkle_root = self.calculate_merkle_root(&transactions);
+        
+        // Filled in once the stake the block unbonds is credited
+        let header = BlockHeader {
+            block_number,
+            parent_hash,
+            timestamp,
+            merkle_root,
+            state_root: [0; 32],
+            votes_root: votes_root(&parent_votes),
+            validator: self.node_id,
+            difficulty: self.expected_difficulty(parent.as_ref(), timestamp),
+            nonce: 0,
+        };
+        
+        // Create block
+        let mut block = Block {
+            header,
+            transactions,
+            parent_votes,
+            zk_proof: ZKProof {
+                proof_data: vec![],
+                public_inputs: vec![],
+                circuit_version: 0,
+                proof_type: crate::types::ProofType::Groth16,
+            },
+            signature: vec![],
+        };
+        let mut unbonded = self.unbonded_stake(&block).await?;
+        let mut ids: Vec<AccountId> = unbonded.iter().map(|(id, _)| *id).collect();
+        let mut saved = accounts.save(&ids);
+        accounts.end_block(&unbonded);
+        touched.extend(ids);
+        // The stake an epoch's first block unbonds counts towards the capacity too, so
+        // transactions are left out from the end until it fits
+        while capacity.is_some_and(|capacity| !capacity.fits(&self.accounts, &accounts, &touched)) && block.transactions.pop().is_some() {
+            block.header.merkle_root = self.calculate_merkle_root(&block.transactions);
+            accounts.restore(saved);
+            if let Some(tx_saved) = undo.pop() {
+                accounts.restore(tx_saved);
+            }
+            unbonded = self.unbonded_stake(&block).await?;
+            ids = unbonded.iter().map(|(id, _)| *id).collect();
+            saved = accounts.save(&ids);
+            accounts.end_block(&unbonded);
+            touched.extend(ids);
+        }
+        block.header.state_root = accounts.root();
+        
+        // Between checkpoints nothing is proven, so the block is proposed right away
+        if !self.proofs.proves(block_number) {
+            block.zk_proof = match self.proofs.checkpoint_reference(block_number, &parent_hash, parent.as_ref().map(|parent| &parent.zk_proof)) {
+                Some((checkpoint, checkpoint_hash)) => ZKProof::checkpoint(checkpoint, checkpoint_hash),
+                None => {
+                    warn!("❌ Can not propose block #{}: the finalized block does not reference the last checkpoint", block_number);
+                    return Ok(());
+                }
+            };
+            return self.publish_proposal(block, round).await;
+        }
+        
+        info!("🔐 Queueing ZK proof for block #{}", block_number);
+        let state = self.accounts.transition(&accounts);
+        let inputs = PublicInputs::new(&block, state.pre_root, validator_set_hash(&*self.state.read().await));
+        match self.proof_service.submit(block.clone(), inputs, state).await {
+            Ok(job) => self.pending_proof = Some(PendingProof { block, round, job }),
+            Err(e) => warn!("❌ Could not queue proof for block #{}: {}", block_number, e),
+        }
+        Ok(())
+    }
+    
+    async fn on_proof_ready(&mut self, result: Result<ZKProof>) -> Result<()> {
+        let PendingProof { mut block, round, .. } = match self.pending_proof.take() {
+            Some(pending) => pending,
+            None => return Ok(()),
+        };
+        let block_number = block.header.block_number;
+        
+        if block_number != self.round_state.height || round != self.round_state.round {
+            info!("🗑️ Dropping proof for block #{} round {}, the round is over", block_number, round);
+            return Ok(());
+        }
+        block.zk_proof = match result {
+            Ok(proof) => proof,
+            Err(e) => {
+                warn!("❌ Failed to prove block #{}: {}", block_number, e);
+                return Ok(());
+            }
+        };
+        info!("✅ ZK proof generated ({} bytes)", block.zk_proof.proof_data.len());
+        self.publish_proposal(block, round).await
+    }
+    
+    // Signs our proposal and sends it out
+    async fn publish_proposal(&mut self, mut block: Block, round: u64) -> Result<()> {
+        let block_number = block.header.block_number;
+        #[cfg(feature = "testing")]
+        if let Some(byzantine) = &self.byzantine {
+            byzantine.tamper_proposal(&mut block);
+        }
+        block.signature = self.keypair.sign(&block.signing_hash());
+        
+        self.store_proposal(&block).await?;
+        self.broadcast_block(block.clone()).await?;
+        
+        info!("🎉 Successfully proposed and stored block #{}", block_number);
+        self.on_proposal(block.hash(), round).await
+    }
+    
+    // Blocks extending the head are applied optimistically; others wait on a side branch
+    async fn store_proposal(&mut self, block: &Block) -> Result<()> {
+        let head_hash = self.storage.get_latest_block().await?.map_or([0; 32], |head| head.hash());
+        
+        if block.header.parent_hash == head_hash {
+            // Store block and drop its transactions from the pending pool
+            self.storage.store_block(block).await?;
+            self.storage.remove_included_transactions(block).await?;
+            self.state.write().await.head = block.header.block_number;
+            self.apply_block_to_state(block).await?;
+        } else {
+            // Competing branch: keep it in the tree and let fork choice decide
+            self.storage.store_block_in_tree(block).await?;
+            info!("🌿 Block #{} extends a side branch", block.header.block_number);
+            self.apply_fork_choice().await?;
+        }
+        
+        self.emit(ChainEvent::BlockImported { block_hash: block.hash(), block_number: block.header.block_number });
+        Ok(())
+    }
+    
+    // Queues staking changes from the block, applies due ones on epoch boundaries and persists the result
+    async fn apply_block_to_state(&self, block: &Block) -> Result<()> {
+        let mut state = self.state.write().await;
+        self.storage.store_state_before(&block.hash(), &state).await?;
+        let missed = self.missed_proposers(&state, block).await?;
+        staking::apply_block(&mut state, block, &missed, &self.liveness);
+        Ok(self.storage.store_consensus_state(&state).await?)
+    }
+    
+    // Validators elected for the rounds of the block's height that passed without a block from
+    // them; `state` must be the one the block was proposed on
+    async fn missed_proposers(&self, state: &ConsensusState, block: &Block) -> Result<Vec<NodeId>> {
+        let parent = match self.storage.get_block_by_hash(&block.header.parent_hash).await? {
+            Some(parent) => parent,
+            None => return Ok(Vec::new()),
+        };
+        
+        let round = self.proposal_round(Some(&parent), block.header.timestamp).min(MAX_SCORED_ROUNDS);
+        Ok((0..round)
+            .filter_map(|round| Self::elected_proposer(state, &block.header.parent_hash, block.header.block_number, round))
+            .collect())
+    }
+    
+    // Read, changed and written back, so stats survive restarts mid-epoch
+    async fn update_epoch_stats(&self, epoch: u64, update: impl FnOnce(&mut EpochStats)) -> Result<()> {
+        let mut stats = self.storage.get_epoch_stats(epoch).await?
+            .unwrap_or_else(|| EpochStats { epoch, ..EpochStats::default() });
+        update(&mut stats);
+        Ok(self.storage.store_epoch_stats(&stats).await?)
+    }
+    
+    // A brand new chain has nobody to include a stake transaction, so the first node seeds the set
+    async fn bootstrap_validator_set(&self, stake: u64) -> Result<()> {
+        let mut state = self.state.write().await;
+        state.validators.insert(self.node_id, ValidatorInfo {
+            stake,
+            is_active: stake >= staking::MIN_VALIDATOR_STAKE,
+            last_block_time: self.clock.now(),
+            performance_score: 1.0,
+            proposed: 0,
+            missed: 0,
+            rewards: 0,
+            missed_votes: 0,
+            jailed_at: None,
+        });
+        state.total_stake = stake;
+        
+        // The genesis block carries the bond so other nodes learn the validator set from it
+        self.storage.store_transaction(&staking::stake_transaction(&self.keypair, stake, 0)?).await?;
+        
+        info!("🏛️ Bootstrapped empty chain with this node as validator ({} stake)", stake);
+        Ok(self.storage.store_consensus_state(&state).await?)
+    }
+    
+    async fn register_validator(&self, stake: u64) -> Result<()> {
+        if staking::is_registered(&*self.state.read().await, &self.node_id) {
+            return Ok(());
+        }
+        // The stake is paid from our balance, so a node without funds can not join
+        let balance = self.accounts.account(&self.node_id).balance;
+        if balance < stake {
+            warn!("🏛️ Not registering as validator: staking {} needs that much balance, this node has {}", stake, balance);
+            return Ok(());
+        }
+        
+        let tx = staking::stake_transaction(&self.keypair, stake, self.next_nonce().await?)?;
+        self.storage.store_transaction(&tx).await?;
+        info!("🏛️ Submitted stake transaction for {} stake, activation after the next epoch", stake);
+        Ok(())
+    }
+    
+    // A jailed validator asks to be let back in once its cooldown allows it
+    async fn request_unjail(&self) -> Result<()> {
+        {
+            let state = self.state.read().await;
+            let queued = state.pending_stake_changes.iter()
+                .any(|pending| pending.validator == self.node_id && pending.change == StakeChange::Unjail);
+            if queued || !staking::can_unjail(&state, &self.node_id, &self.liveness) {
+                return Ok(());
+            }
+        }
+        let pending = self.storage.get_pending_transactions().await?;
+        if pending.iter().any(|tx| tx.from == self.node_id && matches!(tx.kind, TransactionKind::Unjail(_))) {
+            return Ok(());
+        }
+        
+        let tx = staking::unjail_transaction(&self.keypair, self.next_nonce().await?);
+        self.storage.store_transaction(&tx).await?;
+        info!("🔓 Submitted unjail transaction, this node rejoins the validator set at the next epoch");
+        Ok(())
+    }
+    
+    // The header stage of an import; the checks that need nothing but the block are in import::decode
+    async fn verify_block_header(&self, block: &Block, parent: Option<&Block>) -> Result<bool> {
+        // Verify block number is sequential
+        let expected_number = parent.map_or(1, |parent| parent.header.block_number + 1);
+        if block.header.block_number != expected_number {
+            return Ok(false);
+        }
+        
+        if self.checkpoint.is_some_and(|checkpoint| checkpoint.conflicts_with(block)) {
+            warn!("🧭 Block {} conflicts with the trusted checkpoint", block.header.block_number);
+            return Ok(false);
+        }
+        
+        if !self.proofs.proves(block.header.block_number) {
+            let expected = self.proofs.checkpoint_reference(
+                block.header.block_number, &block.header.parent_hash, parent.map(|parent| &parent.zk_proof));
+            if expected.is_none() || block.zk_proof.checkpoint_reference() != expected {
+                warn!("🔖 Block {} does not reference the last checkpoint", block.header.block_number);
+                return Ok(false);
+            }
+        }
+        
+        // Reject blocks for slots that have not started on our (NTP-corrected) clock
+        let slot = self.slots.slot_at(block.header.timestamp);
+        let current_slot = self.slots.slot_at(self.clock.now() + self.max_future_drift);
+        if slot > current_slot {
+            warn!("⏰ Block {} is for slot {}, ahead of our slot {}", block.header.block_number, slot, current_slot);
+            return Ok(false);
+        }
+        
+        // One block per slot at most, so each block is in a later slot than its parent
+        if let Some(parent) = parent {
+            if self.slots.round(parent.header.timestamp, block.header.timestamp).is_none() {
+                warn!("⏰ Block {} was proposed in the slot of its parent", block.header.block_number);
+                return Ok(false);
+            }
+        }
+        
+        let expected_difficulty = self.expected_difficulty(parent, block.header.timestamp);
+        if block.header.difficulty != expected_difficulty {
+            warn!("⛏️ Block {} has difficulty {}, expected {}",
+                block.header.block_number, block.header.difficulty, expected_difficulty);
+            return Ok(false);
+        }
+        
+        // Reject out-of-turn proposals
+        let round = self.proposal_round(parent, block.header.timestamp);
+        let elected = match parent {
+            Some(_) => Self::elected_proposer(
+                &*self.state.read().await, &block.header.parent_hash, block.header.block_number, round),
+            // Chains with a genesis block are never bootstrapped by a parentless block
+            None if self.genesis_hash.is_some() => None,
+            None => staking::genesis_proposer(block),
+        };
+        if elected != Some(block.header.validator) {
+            warn!("🗳️ Block {} proposed out of turn (round {})", block.header.block_number, round);
+            return Ok(false);
+        }
+        
+        // The proof commits to the validator set the block was proposed under, which is the one
+        // that elected its proposer, and to a state transition from its parent's state root
+        if let Some(parent) = parent.filter(|_| self.proofs.proves(block.header.block_number)) {
+            let expected = validator_set_hash(&*self.state.read().await);
+            let inputs = match PublicInputs::from_bytes(&block.zk_proof.public_inputs) {
+                Ok(inputs) if inputs.validator_set_hash == expected => inputs,
+                _ => {
+                    warn!("🗳️ Block {} was proven under another validator set", block.header.block_number);
+                    return Ok(false);
+                }
+            };
+            if inputs.parent_state_root != parent.header.state_root {
+                warn!("🔐 Block {} was proven from another state than its parent's", block.header.block_number);
+                return Ok(false);
+            }
+        }
+        
+        if let Err(e) = self.check_parent_votes(block, parent).await {
+            warn!("🗳️ Block {} carries invalid parent votes: {}", block.header.block_number, e);
+            return Ok(false);
+        }
+        
+        // Median time past: the proposer's clock alone can not date the block before most of the
+        // stake saw its parent committed
+        if let Some(median) = Self::median_vote_time(&*self.state.read().await, &block.parent_votes) {
+            if block.header.timestamp < median {
+                warn!("⏰ Block {} is dated {}s before the median time its parent was committed",
+                    block.header.block_number, (median - block.header.timestamp).num_seconds());
+                return Ok(false);
+            }
+        }
+        
+        for tx in &block.transactions {
+            if let TransactionKind::Evidence(report) = &tx.kind {
+                if !self.verify_evidence(&report.evidence).await? {
+                    warn!("🚫 Block {} carries evidence that does not hold", block.header.block_number);
+                    return Ok(false);
+                }
+            }
+        }
+        
+        Ok(true)
+    }
+    
+    // Parent votes are sorted by validator, one per validator, and each one earns its reward
+    async fn check_parent_votes(&self, block: &Block, parent: Option<&Block>) -> Result<()> {
+        if block.header.votes_root != votes_root(&block.parent_votes) {
+            bail!("votes root does not match the votes");
+        }
+        if !block.parent_votes.windows(2).all(|pair| pair[0].validator < pair[1].validator) {
+            bail!("votes are not sorted by validator or repeat one");
+        }
+        
+        let parent = match parent {
+            Some(parent) => parent,
+            None if block.parent_votes.is_empty() => return Ok(()),
+            None => bail!("a block without a parent carries votes"),
+        };
+        let state = self.state.read().await;
+        if let Some(vote) = block.parent_votes.iter().find(|vote| !Self::is_reward_vote(&state, vote, parent)) {
+            bail!("vote of {} is not a precommit for the parent by a bonded validator", hex::encode(vote.validator));
+        }
+        Ok(())
+    }
+    
+    // Precommits for the parent that we know of and that earn a reward, one per validator
+    async fn collect_parent_votes(&self, parent: &Block) -> Result<Vec<BlockVote>> {
+        let state = self.state.read().await;
+        let mut votes: Vec<_> = self.storage.get_votes_for_block(parent.hash()).await?
+            .into_iter()
+            .filter(|vote| Self::is_reward_vote(&state, vote, parent))
+            .collect();
+        votes.sort_by_key(|vote| vote.validator);
+        votes.dedup_by_key(|vote| vote.validator);
+        Ok(votes)
+    }
+    
+    // Stake-weighted median of the times validators signed their votes at. Validators holding
+    // more than half the stake have to agree to move it, so one proposer or a few skewed clocks can not
+    fn median_vote_time(state: &ConsensusState, votes: &[BlockVote]) -> Option<DateTime<Utc>> {
+        let mut times: Vec<_> = votes.iter()
+            .filter_map(|vote| state.validators.get(&vote.validator).map(|info| (vote.timestamp, info.stake)))
+            .collect();
+        times.sort();
+        
+        let total: u64 = times.iter().map(|(_, stake)| stake).sum();
+        let mut seen = 0;
+        for (timestamp, stake) in times {
+            seen += stake;
+            if seen * 2 >= total {
+                return Some(timestamp);
+            }
+        }
+        None
+    }
+    
+    // Precommits from any round count, as long as their validator has the minimum stake bonded.
+    // Signatures were checked when the vote was received, or with the block carrying it
+    fn is_reward_vote(state: &ConsensusState, vote: &BlockVote, parent: &Block) -> bool {
+        vote.step == VoteStep::Precommit
+            && vote.height == parent.header.block_number
+            && vote.value() == Some(parent.hash())
+            && state.validators.get(&vote.validator).is_some_and(|info| info.stake >= staking::MIN_VALIDATOR_STAKE)
+    }
+    
+    async fn verify_evidence(&self, evidence: &Evidence) -> Result<bool> {
+        if !evidence.is_well_formed() {
+            return Ok(false);
+        }
+        
+        match evidence {
+            Evidence::DoubleVote(..) => Ok(true),
+            // A proof we can not check is no evidence against its proposer
+            Evidence::InvalidProof(block) => {
+                Ok(self.zk_generator.can_verify(&block.zk_proof.proof_type)
+                    && !self.zk_generator.verify_header_proof(&block.header, None, &block.zk_proof).await?)
+            }
+        }
+    }
+    
+    // The evidence goes into the pending pool and is included in one of our own proposals
+    async fn report_evidence(&mut self, evidence: Evidence) -> Result<()> {
+        let offender = evidence.offender();
+        let offense = evidence.offense_id();
+        {
+            let state = self.state.read().await;
+            if self.reported.contains(&offense)
+                || staking::is_slashed(&state, &evidence)
+                || !state.validators.contains_key(&offender) {
+                return Ok(());
+            }
+        }
+        
+        // Only report what every other validator will accept
+        if !self.verify_evidence(&evidence).await? {
+            debug!("Not reporting {}: evidence does not hold", hex::encode(offender));
+            return Ok(());
+        }
+        
+        let offense_name = match &evidence {
+            Evidence::DoubleVote(..) => "double vote",
+            Evidence::InvalidProof(_) => "invalid proof",
+        };
+        let tx = staking::evidence_transaction(&self.keypair, evidence, self.next_nonce().await?);
+        self.storage.store_transaction(&tx).await?;
+        self.reported.insert(offense);
+        
+        warn!("🚨 Reported {} by validator {}", offense_name, hex::encode(offender));
+        Ok(())
+    }
+    
+    // Our pending transactions have not executed yet, so the next nonce comes after them
+    async fn next_nonce(&self) -> Result<u64> {
+        let nonce = self.accounts.account(&self.node_id).nonce;
+        Ok(self.storage.get_pending_transactions().await?.iter()
+            .filter(|tx| tx.from == self.node_id)
+            .map(|tx| tx.nonce + 1)
+            .fold(nonce, u64::max))
     }
     
     async fn verify_vote_signature(&self, vote: &BlockVote) -> Result<bool> {
-        // TODO: Implement proper signature verification
+        let signed = SignedMessage::new(vote.validator, vote.signing_hash(), &vote.signature);
+        Ok(self.signatures.verify_all(vec![signed]).await?.first() == Some(&true))
+    }
+    
+    // Moves to the next height after a commit and to later rounds as time passes
+    async fn update_round(&mut self, now: DateTime<Utc>) -> Result<()> {
+        let parent = self.storage.get_finalized_block().await?;
+        let height = parent.as_ref().map_or(1, |parent| parent.header.block_number + 1);
+        
+        if height != self.round_state.height {
+            self.round_state = RoundState::new(height);
+        }
+        
+        let round = self.proposal_round(parent.as_ref(), now);
+        if round > self.round_state.round {
+            if self.instant_seal {
+                debug!("🔁 Height {} moved to round {}", height, round);
+            } else {
+                info!("🔁 Height {} moved to round {}", height, round);
+            }
+            self.round_state.enter_round(round);
+        }
+        
+        Ok(())
+    }
+    
+    // Rounds last one slot: prevote nil after the first third without a proposal,
+    // precommit nil after two thirds without a polka
+    async fn check_round_timeouts(&mut self, now: DateTime<Utc>) -> Result<()> {
+        let parent_time = match self.storage.get_finalized_block().await? {
+            Some(parent) => parent.header.timestamp,
+            // Nothing is finalized before the first block, whose rounds count from genesis. A
+            // chain bootstrapped without a genesis file has no genesis time and one validator
+            None if self.genesis_hash.is_none() => return Ok(()),
+            None => self.slots.genesis_time(),
+        };
+        
+        let round_start = self.slots.round_start(parent_time, self.round_state.round);
+        if now < round_start {
+            return Ok(());
+        }
+        
+        let elapsed = now - round_start;
+        match self.round_state.step {
+            Step::Propose if elapsed >= self.block_time / 3 => {
+                debug!("⌛ No proposal in round {}, prevoting nil", self.round_state.round);
+                self.cast_vote(VoteStep::Prevote, None).await?;
+                self.request_round_change().await?;
+            }
+            Step::Prevote if elapsed >= self.block_time * 2 / 3 => {
+                debug!("⌛ No polka in round {}, precommitting nil", self.round_state.round);
+                self.cast_vote(VoteStep::Precommit, None).await?;
+            }
+            _ => return Ok(()),
+        }
+        
+        self.evaluate_votes().await
+    }
+    
+    fn round_start(&self, parent: &Block, round: u64) -> DateTime<Utc> {
+        self.slots.round_start(parent.header.timestamp, round)
+    }
+    
+    // Asks the other validators to skip the rest of this round and move on to the next proposer
+    async fn request_round_change(&mut self) -> Result<()> {
+        if !self.state.read().await.validators.get(&self.node_id).is_some_and(|v| v.is_active) {
+            return Ok(());
+        }
+        
+        let mut change = RoundChange {
+            height: self.round_state.height,
+            round: self.round_state.round + 1,
+            validator: self.node_id,
+            signature: vec![],
+        };
+        change.signature = self.keypair.sign(&change.signing_hash());
+        
+        self.send_to_network(ConsensusMessage::RoundChange(change.clone())).await;
+        self.record_round_change(&change).await
+    }
+    
+    async fn record_round_change(&mut self, change: &RoundChange) -> Result<()> {
+        if !self.round_state.add_round_change(change) {
+            return Ok(());
+        }
+        
+        let quorum = self.round_state.round_change_quorum(&*self.state.read().await);
+        if let Some(round) = quorum.filter(|round| *round > self.round_state.round) {
+            info!("🔁 Height {} moved to round {} on a round change quorum", self.round_state.height, round);
+            self.round_state.enter_round(round);
+            self.checkpoint_round().await?;
+        }
+        Ok(())
+    }
+    
+    async fn checkpoint_round(&self) -> Result<()> {
+        Ok(self.storage.store_round_checkpoint(&self.round_state.checkpoint(&self.node_id)).await?)
+    }
+    
+    async fn on_proposal(&mut self, block_hash: BlockHash, round: u64) -> Result<()> {
+        if round != self.round_state.round || self.round_state.step != Step::Propose {
+            debug!("Ignoring proposal for round {} in round {} {:?}",
+                round, self.round_state.round, self.round_state.step);
+            return Ok(());
+        }
+        
+        // Blocks that do not execute on the finalized state get a nil prevote
+        let executes = match self.storage.get_block_by_hash(&block_hash).await? {
+            Some(block) => self.import.run(Stage::Execute, self.verify_block_state(&block)).await?,
+            None => false,
+        };
+        
+        // While locked, only prevote the locked block or one with a newer polka
+        let vote_for = executes && match self.round_state.locked {
+            None => true,
+            Some((locked, _)) if locked == block_hash => true,
+            Some((_, locked_round)) => {
+                let state = self.state.read().await;
+                self.round_state.polka_round(&block_hash, &state)
+                    .is_some_and(|polka| polka > locked_round && polka < round)
+            }
+        };
+        
+        self.cast_vote(VoteStep::Prevote, vote_for.then_some(block_hash)).await?;
+        self.evaluate_votes().await
+    }
+    
+    async fn cast_vote(&mut self, step: VoteStep, block_hash: Option<BlockHash>) -> Result<()> {
+        let mut vote = BlockVote {
+            block_hash: block_hash.unwrap_or([0; 32]),
+            validator: self.node_id,
+            vote: if block_hash.is_some() { VoteType::Approve } else { VoteType::Reject },
+            height: self.round_state.height,
+            round: self.round_state.round,
+            step,
+            timestamp: self.clock.now(),
+            signature: vec![],
+        };
+        vote.signature = self.keypair.sign(&vote.signing_hash());
+        
+        self.round_state.step = match step {
+            VoteStep::Prevote => Step::Prevote,
+            VoteStep::Precommit => Step::Precommit,
+        };
+        
+        // Only active validators vote, and not while halted, but the step still advances so
+        // timeouts do not repeat
+        let is_validator = self.state.read().await.validators.get(&self.node_id).is_some_and(|v| v.is_active);
+        if !is_validator || self.safety.halted {
+            return Ok(());
+        }
+        
+        #[cfg(feature = "testing")]
+        if let Some(byzantine) = &self.byzantine {
+            let mut votes = byzantine.votes(vote);
+            for vote in votes.iter_mut() {
+                vote.signature = self.keypair.sign(&vote.signing_hash());
+                self.broadcast_vote(vote.clone()).await?;
+            }
+            // Our own tally only counts the first, so we do not report ourselves
+            if let Some(vote) = votes.first() {
+                self.record_vote(vote).await?;
+            }
+            return Ok(());
+        }
+        
+        // A vote we cast before a restart is sent again rather than signing a conflicting one
+        if let Some(earlier) = self.round_state.vote_of(&self.node_id, vote.round, step).cloned() {
+            debug!("Repeating our {:?} from round {}", step, vote.round);
+            return self.broadcast_vote(earlier).await;
+        }
+        
+        // Checkpointed before it goes out, so a crash can not make us forget a vote peers have seen
+        self.record_vote(&vote).await?;
+        self.checkpoint_round().await?;
+        self.broadcast_vote(vote).await
+    }
+    
+    async fn record_vote(&mut self, vote: &BlockVote) -> Result<bool> {
+        match self.round_state.add_vote(vote) {
+            VoteOutcome::Added => {}
+            VoteOutcome::Duplicate => return Ok(false),
+            VoteOutcome::Conflict(earlier) => {
+                self.report_evidence(Evidence::DoubleVote(earlier, vote.clone())).await?;
+                return Ok(false);
+            }
+        }
+        self.storage.store_vote(vote).await?;
+        self.emit(ChainEvent::VoteReceived(vote.clone()));
         Ok(true)
     }
     
-    async fn check_block_finality(&self, block_hash: BlockHash) -> Result<()> {
-        let votes = self.storage.get_votes_for_block(block_hash).await?;
-        let state = self.state.read().await;
+    async fn process_vote(&mut self, vote: BlockVote) -> Result<()> {
+        if !self.record_vote(&vote).await? {
+            return Ok(());
+        }
         
-        let approve_votes = votes.iter()
-            .filter(|v| matches!(v.vote, VoteType::Approve))
-            .count();
+        self.evaluate_votes().await?;
         
-        if approve_votes >= self.min_validators {
-            info!("Block {:?} reached finality with {} votes", block_hash, approve_votes);
-            
-            // Update consensus state
+        // Votes add weight to their branch, which may change the preferred head
+        if matches!(vote.vote, VoteType::Approve)
+            && vote.height == self.round_state.height
+            && !self.storage.is_canonical(&vote.block_hash).await? {
+            self.apply_fork_choice().await?;
+        }
+        
+        Ok(())
+    }
+    
+    // Our own precommit can complete a quorum, so keep going until nothing changes
+    async fn evaluate_votes(&mut self) -> Result<()> {
+        while self.evaluate_step().await? {}
+        Ok(())
+    }
+    
+    // Returns true when a new vote was cast and the tally needs another look
+    async fn evaluate_step(&mut self) -> Result<bool> {
+        let state = self.state.read().await.clone();
+        
+        // A quorum of precommits for a block in any round commits it
+        for round in self.round_state.rounds(VoteStep::Precommit) {
+            if let Some(Quorum::Block(block_hash)) = self.round_state.quorum(round, VoteStep::Precommit, &state) {
+                self.commit_block(block_hash, round).await?;
+                return Ok(false);
+            }
+        }
+        
+        // Remember the most recent polka so it can be re-proposed. It justifies the height,
+        // though a later round may still commit another block at it
+        for round in self.round_state.rounds(VoteStep::Prevote) {
+            if let Some(Quorum::Block(block_hash)) = self.round_state.quorum(round, VoteStep::Prevote, &state) {
+                if self.round_state.valid.is_none_or(|(_, valid_round)| round > valid_round) {
+                    self.round_state.valid = Some((block_hash, round));
+                }
+                if state.justified < self.round_state.height && self.storage.get_block_by_hash(&block_hash).await?.is_some() {
+                    self.state.write().await.justified = self.round_state.height;
+                }
+            }
+        }
+        
+        if self.round_state.step != Step::Prevote {
+            return Ok(false);
+        }
+        
+        match self.round_state.quorum(self.round_state.round, VoteStep::Prevote, &state) {
+            Some(Quorum::Block(block_hash)) if self.storage.get_block_by_hash(&block_hash).await?.is_some() => {
+                self.round_state.locked = Some((block_hash, self.round_state.round));
+                self.cast_vote(VoteStep::Precommit, Some(block_hash)).await?;
+                Ok(true)
+            }
+            Some(Quorum::Nil) => {
+                self.cast_vote(VoteStep::Precommit, None).await?;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+    
+    async fn commit_block(&mut self, block_hash: BlockHash, round: u64) -> Result<()> {
+        let block = match self.storage.get_block_by_hash(&block_hash).await? {
+            Some(block) => block,
+            None => {
+                debug!("Precommit quorum for unknown block {}, waiting for it", hex::encode(block_hash));
+                return Ok(());
+            }
+        };
+        
+        // Taken before finalizing, which may rotate the validator set
+        let state = self.state.read().await.clone();
+        let validators = self.round_state.voters(round, VoteStep::Precommit, Some(block_hash), &state);
+        let finalized = BlockFinalized {
+            block_hash,
+            block_number: block.header.block_number,
+            round,
+            weight: validators.iter().map(|(_, stake)| stake).sum(),
+            total_stake: bft::active_stake(&state),
+            validators,
+        };
+        // Finalizing clears the votes of the height
+        let certificate = FinalityCertificate {
+            block_hash,
+            block_number: block.header.block_number,
+            round,
+            precommits: finalized.validators.iter()
+                .filter_map(|(node_id, _)| self.round_state.vote_of(node_id, round, VoteStep::Precommit).cloned())
+                .collect(),
+        };
+        
+        let import = self.import.clone();
+        if !import.run(Stage::Finalize, self.finalize(&block)).await? {
+            return Ok(());
+        }
+        self.storage.store_finality(&finalized).await?;
+        self.storage.store_certificate(&certificate).await?;
+        self.update_epoch_stats(staking::epoch_of(block.header.block_number), |stats| {
+            for (node_id, _) in &finalized.validators {
+                stats.validators.entry(*node_id).or_default().votes += 1;
+            }
+        }).await?;
+        info!("🔒 Block #{} committed in round {} by {} validators ({}/{} stake)",
+            block.header.block_number, round, finalized.validators.len(), finalized.weight, finalized.total_stake);
+        
+        self.emit(ChainEvent::BlockFinalized(finalized));
+        Ok(())
+    }
+    
+    // The proof ends up in the proof cache, where proof requests for the block are answered from
+    fn prove_checkpoint(&self, block: Block, inputs: PublicInputs, state: StateTransition) {
+        let proof_service = self.proof_service.clone();
+        tokio::spawn(async move {
+            let block_number = block.header.block_number;
+            match proof_service.prove(block, inputs, state).await {
+                Ok(proof) => info!("✅ Proved checkpoint #{} ({} bytes)", block_number, proof.proof_data.len()),
+                Err(e) => warn!("❌ Failed to prove checkpoint #{}: {}", block_number, e),
+            }
+        });
+    }
+    
+    // Makes a stored block canonical and final and moves consensus on to the next height. False
+    // when that would break safety, which raises the alarm instead
+    async fn finalize(&mut self, block: &Block) -> Result<bool> {
+        if !self.check_safety(block).await? {
+            return Ok(false);
+        }
+        
+        // Stored blocks passed the signature stage, and our own carry transactions checked when
+        // they were admitted
+        let rewards = self.block_rewards(block.header.block_number, &block.header.validator, &block.parent_votes).await?;
+        let unbonded = self.unbonded_stake(block).await?;
+        let mut accounts = self.accounts.clone();
+        let receipts = match accounts.apply_verified_block(block, &rewards, &unbonded) {
+            Ok(receipts) => receipts,
+            Err(e) => bail!("Finalized block #{} does not execute: {}", block.header.block_number, e),
+        };
+        
+        let block_hash = block.hash();
+        if !self.storage.is_canonical(&block_hash).await? {
+            self.reorg_to(block_hash).await?;
+        }
+        let changes = accounts.changes_since(&self.accounts);
+        // Replaying the chain through the checkpoint has to end up with the validators it names,
+        // or the chain we follow is not the one it vouches for
+        if let Some(checkpoint) = self.checkpoint.filter(|checkpoint| checkpoint.height == block.header.block_number) {
+            let validator_set_hash = validator_set_hash(&*self.state.read().await);
+            if validator_set_hash != checkpoint.validator_set_hash {
+                bail!("Validator set {} at the trusted checkpoint #{} differs from the configured {}",
+                    hex::encode(validator_set_hash), checkpoint.height, hex::encode(checkpoint.validator_set_hash));
+            }
+        }
+        // Deferred checkpoints are proven by their proposer once they are final
+        if self.proofs.deferred_proofs && self.proofs.is_checkpoint(block.header.block_number) && block.header.validator == self.node_id {
+            // Our state already took the block in; the one finalized at its parent is what it was proposed on
+            let proposed_on = match self.storage.get_consensus_state_at(block.header.block_number - 1).await? {
+                Some(state) => validator_set_hash(&state),
+                None => validator_set_hash(&*self.state.read().await),
+            };
+            let inputs = PublicInputs::new(block, self.accounts.root(), proposed_on);
+            self.prove_checkpoint(block.clone(), inputs, self.accounts.transition(&accounts));
+        }
+        self.storage.set_finalized_block(block, &changes).await?;
+        self.storage.delete_state_before(&block_hash).await?;
+        {
             let mut state = self.state.write().await;
-            state.current_block += 1;
+            state.finalized = block.header.block_number;
+            state.justified = state.justified.max(state.finalized);
+            self.storage.store_consensus_state_at(block.header.block_number, &state).await?;
+            self.storage.store_consensus_state(&state).await?;
+        }
+        self.storage.store_receipts(&receipts).await?;
+        self.storage.index_transactions(block).await?;
+        self.accounts = accounts;
+        self.round_state = RoundState::new(block.header.block_number + 1);
+        self.incomplete_blocks.retain(|_, compact| compact.header.block_number > block.header.block_number);
+        
+        if staking::opens_epoch(block) {
+            self.record_epoch_change(block).await?;
+            self.apply_params().await;
+            if self.validator_stake.is_some() {
+                self.request_unjail().await?;
+            }
+        }
+        
+        // The state was applied up to this block, so its counters are the epoch's so far
+        let state = self.state.read().await.clone();
+        self.update_epoch_stats(staking::epoch_of(block.header.block_number), |stats| {
+            stats.blocks += 1;
+            for (node_id, info) in state.validators.iter().filter(|(_, info)| info.is_active) {
+                let validator = stats.validators.entry(*node_id).or_default();
+                validator.proposed = info.proposed;
+                validator.missed = info.missed;
+                validator.missed_votes = info.missed_votes;
+                validator.performance_score = info.performance_score;
+            }
+        }).await?;
+        
+        if let Some(chain_proof_tx) = &self.chain_proof_tx {
+            let _ = chain_proof_tx.send(block.header.block_number);
+        }
+        Ok(true)
+    }
+    
+    // A block to finalize has to extend our finalized chain, and the proof it carries has to
+    // hold. A block proof failing here passed verification earlier, so unless we halt on it the
+    // block is finalized anyway
+    async fn check_safety(&mut self, block: &Block) -> Result<bool> {
+        let block_number = block.header.block_number;
+        let block_hash = block.hash();
+        if let Some(finalized) = self.storage.get_finalized_block().await? {
+            let finalized_number = finalized.header.block_number;
+            if block_number <= finalized_number {
+                // Pruned, so there is nothing to compare with
+                let ours = match self.storage.get_block(block_number).await? {
+                    Some(ours) => ours.hash(),
+                    None => {
+                        warn!("Not finalizing block #{} again below our finalized #{}", block_number, finalized_number);
+                        return Ok(false);
+                    }
+                };
+                if ours != block_hash {
+                    self.raise_safety_violation(SafetyViolation::ConflictingFinality { block_number, ours, theirs: block_hash }).await?;
+                    return Ok(false);
+                }
+                return Ok(true);
+            }
+            
+            let mut ancestor = block.header.parent_hash;
+            let mut number = block_number - 1;
+            while number > finalized_number {
+                match self.storage.get_block_by_hash(&ancestor).await? {
+                    Some(parent) => ancestor = parent.header.parent_hash,
+                    // Reorging onto a branch we do not have fails on its own
+                    None => break,
+                }
+                number -= 1;
+            }
+            if number == finalized_number && ancestor != finalized.hash() {
+                self.raise_safety_violation(SafetyViolation::FinalizedReorg { finalized: finalized_number, block_number, block_hash }).await?;
+                return Ok(false);
+            }
+        }
+        
+        if self.proofs.proves(block_number) && self.zk_generator.can_verify(&block.zk_proof.proof_type)
+            && !self.zk_generator.verify_block_proof(block).await?
+        {
+            self.raise_safety_violation(SafetyViolation::InvalidProof { block_number, block_hash }).await?;
+            return Ok(!self.halt_on_violation);
+        }
+        Ok(true)
+    }
+    
+    // Rounds follow the block time of the chain, which validators can vote to change at an
+    // epoch boundary; it applies from the block after the one opening the epoch
+    async fn apply_params(&mut self) {
+        let mut state = self.state.write().await;
+        // States migrated from before on-chain parameters ran on the configured block time
+        if state.params.block_time == 0 {
+            state.params.block_time = self.block_time.num_seconds() as u64;
         }
+        let block_time = Duration::seconds(state.params.block_time as i64);
+        if block_time != self.block_time {
+            info!("⏱️ Block time is now {}s, from {}s", block_time.num_seconds(), self.block_time.num_seconds());
+            self.block_time = block_time;
+            self.slots = self.slots.with_slot_time(block_time);
+        }
+    }
+    
+    // Snapshots the validator set the block rotated in and announces it, unless we are still catching up
+    async fn record_epoch_change(&self, block: &Block) -> Result<()> {
+        let change = staking::epoch_change(&*self.state.read().await, block);
+        self.storage.store_epoch_change(&change).await?;
+        info!("🗓️ Epoch {} started at block #{} with {} validators ({} stake)",
+            change.epoch, change.start_block, change.validators.len(), change.total_stake);
         
+        if !self.sync.is_behind(block.header.block_number) {
+            self.send_to_network(ConsensusMessage::EpochChange(change.clone())).await;
+        }
+        self.emit(ChainEvent::EpochChanged(change));
+        Ok(())
+    }
+    
+    // Every node derives the set itself, so a peer's announcement only serves as a cross-check
+    async fn handle_epoch_change(&mut self, change: EpochChange) -> Result<()> {
+        match self.storage.get_epoch_change(change.epoch).await? {
+            Some(ours) if ours == change => {
+                debug!("Peer agrees on the validator set of epoch {}", change.epoch);
+            }
+            Some(ours) => {
+                warn!("⚠️ Peer announced a different validator set for epoch {} ({} validators, {} stake; ours has {}, {})",
+                    change.epoch, change.validators.len(), change.total_stake,
+                    ours.validators.len(), ours.total_stake);
+            }
+            None => {
+                debug!("Epoch {} announced before we finalized its first block", change.epoch);
+            }
+        }
         Ok(())
     }
     
-    async fn calculate_difficulty(&self) -> Result<u64> {
-        // Simple difficulty calculation based on block time
-        // In practice, this would be more sophisticated
-        Ok(1000)
+    // Folds committed blocks into the recursive chain proof light clients verify. Proving takes
+    // much longer than a block, so it runs in the background and catches up in batches
+    fn spawn_chain_prover(&mut self) {
+        let (tx, mut rx) = mpsc::unbounded_channel::<u64>();
+        let zk_generator = self.zk_generator.clone();
+        let storage = self.storage.clone();
+        
+        self.chain_proof_tx = Some(tx);
+        self.chain_prover = Some(tokio::spawn(async move {
+            while let Some(mut target) = rx.recv().await {
+                while let Ok(height) = rx.try_recv() {
+                    target = target.max(height);
+                }
+                
+                let previous = match storage.get_chain_proof().await {
+                    Ok(previous) => previous,
+                    Err(e) => {
+                        error!("❌ Failed to load chain proof: {}", e);
+                        continue;
+                    }
+                };
+                
+                let result = match extend_chain_proof(&zk_generator, &storage, previous.clone(), target).await {
+                    // The prover may have lost its accumulator (e.g. after a restart), so refold from genesis
+                    Err(e) if previous.is_some() => {
+                        warn!("⚠️ Rebuilding chain proof from genesis: {}", e);
+                        extend_chain_proof(&zk_generator, &storage, None, target).await
+                    }
+                    result => result,
+                };
+                if let Err(e) = result {
+                    warn!("⚠️ Chain proof not extended to block #{}: {}", target, e);
+                }
+            }
+        }));
     }
     
-    fn calculate_merkle_root(&self, transactions: &[crate::types::Transaction]) -> BlockHash {
-        if transactions.is_empty() {
-            return [0; 32];
+    // Heaviest branch descending from the finalized block wins. Weight is the approving
+    // stake summed over the branch; ties go to the longer branch, then to the lower tip hash
+    async fn choose_head(&self) -> Result<Option<BlockHash>> {
+        let (root, root_height) = match self.storage.get_finalized_block().await? {
+            Some(finalized) => (finalized.hash(), finalized.header.block_number),
+            None => ([0; 32], 0),
+        };
+        
+        let mut best: Option<(u64, u64, Reverse<BlockHash>)> = None;
+        let mut stack = vec![(root, 0u64, root_height)];
+        
+        while let Some((hash, weight, height)) = stack.pop() {
+            let children = self.storage.get_children(&hash).await?;
+            if children.is_empty() && hash != [0; 32] {
+                let candidate = (weight, height, Reverse(hash));
+                if best.as_ref().is_none_or(|best| candidate > *best) {
+                    best = Some(candidate);
+                }
+            }
+            
+            for child in children {
+                let child_weight = weight + self.approving_stake(&child).await?;
+                stack.push((child, child_weight, height + 1));
+            }
         }
         
-        let mut hashes: Vec<BlockHash> = transactions.iter()
-            .map(|tx| {
-                let mut hasher = Sha256::new();
-                hasher.update(&bincode::serialize(tx).unwrap());
-                hasher.finalize().into()
-            })
+        Ok(best.map(|(_, _, Reverse(hash))| hash))
+    }
+    
+    async fn approving_stake(&self, block_hash: &BlockHash) -> Result<u64> {
+        let votes = self.storage.get_votes_for_block(*block_hash).await?;
+        let state = self.state.read().await;
+        
+        Ok(votes.iter()
+            .filter(|vote| matches!(vote.vote, VoteType::Approve))
+            .filter_map(|vote| state.validators.get(&vote.validator))
+            .map(|validator| validator.stake)
+            .sum())
+    }
+    
+    async fn apply_fork_choice(&mut self) -> Result<()> {
+        let best = match self.choose_head().await? {
+            Some(best) => best,
+            None => return Ok(()),
+        };
+        
+        let head_hash = self.storage.get_latest_block().await?.map(|head| head.hash());
+        if head_hash != Some(best) {
+            if let Err(e) = self.reorg_to(best).await {
+                warn!("❌ Fork choice could not switch to {}: {}", hex::encode(best), e);
+            }
+        }
+        
+        Ok(())
+    }
+    
+    // Switches the canonical chain to the branch ending in `tip`. The consensus state goes back
+    // to the one the first dropped block was applied on, so whatever the dropped blocks changed,
+    // epoch transitions and liveness counters included, is undone exactly
+    pub async fn reorg_to(&mut self, tip: BlockHash) -> Result<()> {
+        let reorg = self.storage.reorg_to(&tip).await?;
+        
+        // Transactions of dropped blocks go back to the pool unless the new branch includes them
+        let added_ids: HashSet<_> = reorg.added.iter()
+            .flat_map(|block| block.transactions.iter().map(|tx| tx.id))
+            .collect();
+        let restored: Vec<_> = reorg.removed.iter()
+            .flat_map(|block| block.transactions.iter())
+            .filter(|tx| !added_ids.contains(&tx.id))
+            .cloned()
             .collect();
+        self.storage.restore_pending_transactions(&restored).await?;
+        for block in &reorg.added {
+            self.storage.remove_included_transactions(block).await?;
+        }
         
-        // Build merkle tree
-        while hashes.len() > 1 {
-            let mut new_hashes = Vec::new();
-            for chunk in hashes.chunks(2) {
-                let mut hasher = Sha256::new();
-                hasher.update(&chunk[0]);
-                if chunk.len() > 1 {
-                    hasher.update(&chunk[1]);
-                } else {
-                    hasher.update(&chunk[0]); // Duplicate for odd number
+        let mut state = self.state.write().await;
+        let before = match reorg.removed.first() {
+            Some(first) => self.storage.get_state_before(&first.hash()).await?,
+            None => None,
+        };
+        match before {
+            // Finality only moves forward, and never past the fork
+            Some(mut before) => {
+                before.finalized = state.finalized;
+                before.justified = state.justified;
+                *state = before;
+            }
+            // Blocks applied before the states they were applied on were kept
+            None => {
+                for block in reorg.removed.iter().rev() {
+                    let missed = self.missed_proposers(&state, block).await?;
+                    staking::revert_block(&mut state, block, &missed);
                 }
-                new_hashes.push(hasher.finalize().into());
             }
-            hashes = new_hashes;
         }
+        for block in &reorg.removed {
+            self.storage.delete_state_before(&block.hash()).await?;
+        }
+        for block in &reorg.added {
+            self.storage.store_state_before(&block.hash(), &state).await?;
+            let missed = self.missed_proposers(&state, block).await?;
+            staking::apply_block(&mut state, block, &missed, &self.liveness);
+        }
+        if let Some(new_head) = reorg.added.last() {
+            state.head = new_head.header.block_number;
+        }
+        self.storage.store_consensus_state(&state).await?;
         
-        hashes[0]
+        info!("🔀 Reorganized to block #{} from ancestor {} ({} blocks reverted, {} applied)",
+            state.head, hex::encode(reorg.common_ancestor), reorg.removed.len(), reorg.added.len());
+        Ok(())
+    }
+    
+    // Difficulty a block proposed at `timestamp` on top of `parent` has to carry
+    fn expected_difficulty(&self, parent: Option<&Block>, timestamp: DateTime<Utc>) -> u64 {
+        match parent {
+            Some(parent) => difficulty::next_difficulty(
+                parent.header.difficulty, timestamp - parent.header.timestamp, self.block_time),
+            None => difficulty::INITIAL_DIFFICULTY,
+        }
+    }
+    
+    fn calculate_merkle_root(&self, transactions: &[crate::types::Transaction]) -> BlockHash {
+        crate::types::calculate_merkle_root(transactions)
     }
     
     async fn broadcast_block(&self, block: Block) -> Result<()> {
-        // TODO: Implement network broadcasting
         debug!("Broadcasting block {}", block.header.block_number);
+        self.send_to_network(ConsensusMessage::CompactBlock(CompactBlock::new(&block))).await;
         Ok(())
     }
     
     async fn broadcast_vote(&self, vote: BlockVote) -> Result<()> {
-        // TODO: Implement network broadcasting
         debug!("Broadcasting vote for block {:?}", vote.block_hash);
+        self.send_to_network(ConsensusMessage::BlockVote(vote)).await;
         Ok(())
     }
     
-    async fn update_consensus_state(&mut self) -> Result<()> {
-        // Update validator performance scores
-        let mut state = self.state.write().await;
-        
-        for validator in state.validators.values_mut() {
-            // Simple performance scoring based on recent activity
-            let time_since_last = Utc::now() - validator.last_block_time;
-            if time_since_last < Duration::minutes(5) {
-                validator.performance_score = (validator.performance_score + 0.1).min(1.0);
-            } else {
-                validator.performance_score = (validator.performance_score - 0.05).max(0.0);
+    async fn send_to_network(&self, message: ConsensusMessage) {
+        #[cfg(feature = "testing")]
+        if let (Some(byzantine), Some(network_tx)) = (&self.byzantine, &self.network_tx) {
+            let delay = byzantine.delay(&message);
+            if !delay.is_zero() {
+                let network_tx = network_tx.clone();
+                tokio::spawn(async move {
+                    tokio::time::sleep(delay).await;
+                    let _ = network_tx.send(message).await;
+                });
+                return;
             }
         }
         
-        Ok(())
+        if let Some(network_tx) = &self.network_tx {
+            if let Err(e) = network_tx.send(message).await {
+                error!("Failed to hand message to network manager: {}", e);
+            }
+        }
+    }
+    
+    // Lets the network lower the score of whoever gossiped the message being handled
+    async fn report_peer(&self, misbehaviour: Misbehaviour) {
+        if let (Some(peer_report_tx), Some(message_id)) = (&self.peer_report_tx, self.current_message) {
+            if let Err(e) = peer_report_tx.send(PeerReport { message_id, misbehaviour }).await {
+                error!("Failed to hand peer report to network manager: {}", e);
+            }
+        }
     }
     
-    pub fn get_message_sender(&self) -> mpsc::Sender<ConsensusMessage> {
+    pub fn get_message_sender(&self) -> ConsensusSender {
         self.message_tx.clone()
     }
-} 
\ No newline at end of file
+    
+    // Shared with the RPC server
+    pub fn inbox_monitor(&self) -> InboxMonitor {
+        self.inbox.monitor()
+    }
+    
+    // Shared view of the consensus state for read-only consumers such as the RPC server
+    pub fn state_handle(&self) -> Arc<RwLock<ConsensusState>> {
+        self.state.clone()
+    }
+    
+    // Chain events for the snapshot publisher, the pruner and anyone else following consensus
+    pub fn subscribe(&self) -> broadcast::Receiver<ChainEvent> {
+        self.events_tx.subscribe()
+    }
+    
+    // Nobody listening is fine
+    fn emit(&self, event: ChainEvent) {
+        let _ = self.events_tx.send(event);
+    }
+    
+    // Records the violation and, in halt mode, stops block production until an operator resumes it
+    async fn raise_safety_violation(&mut self, violation: SafetyViolation) -> Result<()> {
+        let detected = match self.safety.record(violation, self.clock.now()) {
+            Some(detected) => detected,
+            None => return Ok(()),
+        };
+        error!("🚨 Safety violation: {}", detected.violation);
+        if self.halt_on_violation && !self.safety.halted {
+            error!("🚨 Halting block production; admin_resumeProduction resumes it");
+            self.safety.halted = true;
+        }
+        self.storage.store_safety_alarm(&self.safety).await?;
+        self.emit(ChainEvent::SafetyViolation(detected));
+        Ok(())
+    }
+    
+    pub fn sync_status_handle(&self) -> Arc<RwLock<SyncStatus>> {
+        self.sync.status_handle()
+    }
+    
+    pub fn import_stats_handle(&self) -> Arc<RwLock<ImportStats>> {
+        self.import.stats_handle()
+    }
+    
+    // Makes this node a validator candidate that bonds `stake` on start unless already registered
+    pub fn set_validator_stake(&mut self, stake: u64) {
+        self.validator_stake = Some(stake);
+    }
+    
+    // Sender for operator commands from the admin RPC
+    pub fn get_admin_sender(&self) -> mpsc::Sender<ConsensusCommand> {
+        self.admin_tx.clone()
+    }
+    
+    // Sender used by the network manager for the heads peers announce in their handshake
+    pub fn get_peer_head_sender(&self) -> mpsc::Sender<PeerHead> {
+        self.peer_head_tx.clone()
+    }
+    
+    pub fn set_network_sender(&mut self, network_tx: mpsc::Sender<ConsensusMessage>) {
+        self.network_tx = Some(network_tx);
+    }
+    
+    pub fn set_peer_report_sender(&mut self, peer_report_tx: mpsc::Sender<PeerReport>) {
+        self.peer_report_tx = Some(peer_report_tx);
+    }
+    
+    pub fn set_checkpoint(&mut self, checkpoint: TrustedCheckpoint) {
+        self.checkpoint = Some(checkpoint);
+    }
+    
+    pub fn set_genesis(&mut self, genesis_hash: BlockHash, genesis_time: DateTime<Utc>) {
+        self.genesis_hash = Some(genesis_hash);
+        self.slots = Slots::new(genesis_time, self.block_time);
+    }
+    
+    // Limits on proving blocks for peers
+    pub fn set_proof_request_limits(&mut self, config: &ZkProofConfig) {
+        self.proof_queue = Arc::new(Mutex::new(ProofRequestQueue::new(config)));
+    }
+    
+    // Makes this validator misbehave, for tests of the vote and finality logic
+    #[cfg(feature = "testing")]
+    pub fn set_byzantine(&mut self, behavior: Box<dyn ByzantineBehavior>) {
+        warn!("😈 Running with byzantine behavior enabled");
+        self.byzantine = Some(behavior);
+    }
+    
+    // Makes the request ids we pick, and with them our messages, the same on every run
+    #[cfg(feature = "testing")]
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+        self.sync.set_rng(StdRng::seed_from_u64(self.rng.gen()));
+        self.proof_requests.set_rng(StdRng::seed_from_u64(self.rng.gen()));
+    }
+    
+    // The simulator drives the engine through these in place of consensus_loop, handing out
+    // messages and ticks in its own order under its own clock
+    #[cfg(feature = "testing")]
+    pub async fn deliver(&mut self, message: ConsensusMessage) -> Result<()> {
+        self.handle_message(message).await?;
+        self.finish_proof().await
+    }
+    
+    #[cfg(feature = "testing")]
+    pub async fn step(&mut self) -> Result<()> {
+        self.tick().await?;
+        self.finish_proof().await
+    }
+    
+    // Our proposal's proof is taken to be ready at the instant it was queued, so how fast the
+    // workers are does not change the run
+    #[cfg(feature = "testing")]
+    async fn finish_proof(&mut self) -> Result<()> {
+        if self.pending_proof.is_some() {
+            let result = wait_for_proof(&mut self.pending_proof).await;
+            self.on_proof_ready(result).await?;
+        }
+        Ok(())
+    }
+} 
+
+// Blocks of a synced batch that are handed back, with their prechecks stopped
+fn unimported(pending: impl Iterator<Item = ((Block, NodeId), JoinHandle<Result<bool>>)>) -> Vec<(Block, NodeId)> {
+    pending.map(|(next, precheck)| {
+        precheck.abort();
+        next
+    }).collect()
+}
+
+// Never completes while no proposal waits on a proof
+async fn wait_for_proof(pending: &mut Option<PendingProof>) -> Result<ZKProof> {
+    match pending {
+        Some(pending) => Ok(pending.job.wait().await?),
+        None => std::future::pending().await,
+    }
+}
+
+async fn extend_chain_proof(
+    zk_generator: &Arc<ZKProofGenerator>,
+    storage: &StorageManager,
+    previous: Option<ZKProof>,
+    target: u64,
+) -> Result<()> {
+    let height = previous.as_ref().and_then(crate::zk_proof::chain_proof_height).unwrap_or(0);
+    if height >= target {
+        return Ok(());
+    }
+    
+    let mut blocks = Vec::new();
+    for number in height + 1..=target {
+        match storage.get_block(number).await? {
+            Some(block) => blocks.push(block),
+            None => anyhow::bail!("Block #{} is not in storage", number),
+        }
+    }
+    
+    // Proving is CPU-bound; keep it off the async workers
+    let zk_generator = zk_generator.clone();
+    let proof = tokio::task::spawn_blocking(move || {
+        tokio::runtime::Handle::current().block_on(zk_generator.generate_recursive_proof(previous.as_ref(), &blocks))
+    }).await??;
+    
+    storage.store_chain_proof(&proof).await?;
+    debug!("Chain proof extended to block #{}", target);
+    Ok(())
+}
+
+// Proves queued blocks for peers until no block waits or the request workers are all busy
+async fn prove_queued_requests(
+    queue: Arc<Mutex<ProofRequestQueue>>,
+    proof_service: ProofService,
+    network_tx: Option<mpsc::Sender<ConsensusMessage>>,
+    responder: NodeId,
+) {
+    loop {
+        let (block_hash, block, inputs, state) = match queue.lock().await.next() {
+            Some(job) => job,
+            None => return,
+        };
+        let block_number = block.header.block_number;
+        let result = proof_service.prove(block, inputs, state).await;
+        let requests = queue.lock().await.finish(&block_hash);
+        match result {
+            Ok(proof) => {
+                for request in requests {
+                    send_proof_response(network_tx.clone(), request, proof.clone(), responder).await;
+                }
+            }
+            Err(e) => debug!("Not answering {} proof requests for block {}: {}", requests.len(), block_number, e),
+        }
+    }
+}
+
+async fn send_proof_response(
+    network_tx: Option<mpsc::Sender<ConsensusMessage>>,
+    request: ProofRequest,
+    proof: ZKProof,
+    responder: NodeId,
+) {
+    let response = ProofResponse {
+        request_id: request.request_id,
+        proof,
+        responder,
+        target: request.requester,
+    };
+    
+    if let Some(network_tx) = network_tx {
+        if let Err(e) = network_tx.send(ConsensusMessage::ZKProofResponse(response)).await {
+            error!("Failed to hand message to network manager: {}", e);
+        }
+    }
+}
@@ -0,0 +1,167 @@
+use crate::config::ZkProofConfig;
+use crate::state::StateTransition;
+use crate::types::{Block, BlockHash, NodeId, ProofRequest};
+use crate::zk_proof::PublicInputs;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+
+// Why a peer's proof request was turned down
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rejection {
+    // The peer already has requests_per_peer requests waiting or being proven
+    OverQuota,
+    // request_queue blocks are waiting, none of them for a peer of lower priority
+    QueueFull,
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::OverQuota => write!(f, "the peer is over its quota"),
+            Self::QueueFull => write!(f, "the proof request queue is full"),
+        }
+    }
+}
+
+// A block proven for peers, and the requests its proof answers
+struct QueuedProof {
+    // Taken once a worker starts on the block
+    job: Option<(Block, PublicInputs, StateTransition)>,
+    requests: Vec<ProofRequest>,
+    priority: bool,
+}
+
+// Proof requests from peers for blocks we have no proof of yet. Each peer may have a few
+// requests waiting or being proven, requests from active validators are proven first, and only
+// `workers` blocks are proven for peers at a time so our own blocks still get proof workers.
+// Peers asking for a block that is already queued share its proof
+pub struct ProofRequestQueue {
+    jobs: HashMap<BlockHash, QueuedProof>,
+    // Blocks waiting for a worker, oldest first
+    priority: VecDeque<BlockHash>,
+    normal: VecDeque<BlockHash>,
+    // Requests each peer has waiting or being proven
+    outstanding: HashMap<NodeId, usize>,
+    running: usize,
+    max_queued: usize,
+    per_peer: usize,
+    workers: usize,
+}
+
+impl ProofRequestQueue {
+    pub fn new(config: &ZkProofConfig) -> Self {
+        Self {
+            jobs: HashMap::new(),
+            priority: VecDeque::new(),
+            normal: VecDeque::new(),
+            outstanding: HashMap::new(),
+            running: 0,
+            max_queued: config.request_queue,
+            per_peer: config.requests_per_peer,
+            workers: config.request_workers,
+        }
+    }
+
+    // Adds the request to a block already waiting or being proven. Returns false when the block
+    // is not queued, and it has to be pushed with its witness
+    pub fn join(&mut self, request: &ProofRequest, priority: bool) -> Result<bool, Rejection> {
+        if !self.jobs.contains_key(&request.block_hash) {
+            return Ok(false);
+        }
+        self.charge(&request.requester)?;
+        let mut promote = false;
+        if let Some(queued) = self.jobs.get_mut(&request.block_hash) {
+            queued.requests.push(request.clone());
+            promote = priority && !queued.priority && queued.job.is_some();
+            queued.priority |= priority;
+        }
+        // A validator waiting on a block moves it ahead of the other peers' blocks
+        if promote {
+            self.normal.retain(|hash| hash != &request.block_hash);
+            self.priority.push_back(request.block_hash);
+        }
+        Ok(true)
+    }
+
+    // Whether push would take a new block for the peer's request, checked before its witness is
+    // computed. Only an active validator's request can take the place of another peer's block
+    pub fn can_accept(&self, requester: &NodeId, priority: bool) -> Result<(), Rejection> {
+        if self.outstanding.get(requester).copied().unwrap_or(0) >= self.per_peer {
+            return Err(Rejection::OverQuota);
+        }
+        let full = self.priority.len() + self.normal.len() >= self.max_queued;
+        if full && (!priority || self.normal.is_empty()) {
+            return Err(Rejection::QueueFull);
+        }
+        Ok(())
+    }
+
+    // Queues a block to prove for the request. When the queue is full, a request from an active
+    // validator evicts the newest block only other peers wait on; returns the requests dropped
+    pub fn push(
+        &mut self,
+        block: Block,
+        inputs: PublicInputs,
+        state: StateTransition,
+        request: ProofRequest,
+        priority: bool,
+    ) -> Result<Vec<ProofRequest>, Rejection> {
+        self.can_accept(&request.requester, priority)?;
+        let mut evicted = Vec::new();
+        if self.priority.len() + self.normal.len() >= self.max_queued {
+            if let Some(queued) = self.normal.pop_back().and_then(|newest| self.jobs.remove(&newest)) {
+                queued.requests.iter().for_each(|request| self.release(&request.requester));
+                evicted = queued.requests;
+            }
+        }
+
+        self.charge(&request.requester)?;
+        let block_hash = request.block_hash;
+        match priority {
+            true => self.priority.push_back(block_hash),
+            false => self.normal.push_back(block_hash),
+        }
+        self.jobs.insert(block_hash, QueuedProof { job: Some((block, inputs, state)), requests: vec![request], priority });
+        Ok(evicted)
+    }
+
+    // The next block to prove, if a worker is free for it
+    pub fn next(&mut self) -> Option<(BlockHash, Block, PublicInputs, StateTransition)> {
+        if self.running >= self.workers {
+            return None;
+        }
+        let block_hash = self.priority.pop_front().or_else(|| self.normal.pop_front())?;
+        let (block, inputs, state) = self.jobs.get_mut(&block_hash)?.job.take()?;
+        self.running += 1;
+        Some((block_hash, block, inputs, state))
+    }
+
+    // The block was proven, or failed to; returns the requests to answer
+    pub fn finish(&mut self, block_hash: &BlockHash) -> Vec<ProofRequest> {
+        let queued = match self.jobs.remove(block_hash) {
+            Some(queued) => queued,
+            None => return Vec::new(),
+        };
+        self.running = self.running.saturating_sub(1);
+        queued.requests.iter().for_each(|request| self.release(&request.requester));
+        queued.requests
+    }
+
+    fn charge(&mut self, peer: &NodeId) -> Result<(), Rejection> {
+        let outstanding = self.outstanding.entry(*peer).or_default();
+        if *outstanding >= self.per_peer {
+            return Err(Rejection::OverQuota);
+        }
+        *outstanding += 1;
+        Ok(())
+    }
+
+    fn release(&mut self, peer: &NodeId) {
+        if let Some(outstanding) = self.outstanding.get_mut(peer) {
+            *outstanding -= 1;
+            if *outstanding == 0 {
+                self.outstanding.remove(peer);
+            }
+        }
+    }
+}
@@ -0,0 +1,115 @@
+use crate::crypto::NodeKeypair;
+use crate::types::{BlockHash, NodeId, ProofRequest, ProofResponse};
+use chrono::{DateTime, Duration, Utc};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::collections::HashMap;
+use tracing::debug;
+
+// Proving a block takes a while, so peers get longer to answer than for block downloads
+const REQUEST_TIMEOUT_SECS: i64 = 30;
+// Validators asked for one block before giving up on it
+const MAX_ATTEMPTS: usize = 3;
+
+// Proof request we sent and are waiting on an answer to
+pub struct OpenRequest {
+    pub block_hash: BlockHash,
+    pub block_number: u64,
+    target: NodeId,
+    // Validators asked so far, the current target included
+    tried: Vec<NodeId>,
+    pub sent_at: DateTime<Utc>,
+}
+
+// Proof requests are addressed to one validator; when it does not answer in time or its proof
+// does not hold, the next candidate is asked
+pub struct ProofRequests {
+    requests: HashMap<[u8; 32], OpenRequest>,
+    // Request ids
+    rng: StdRng,
+}
+
+impl ProofRequests {
+    pub fn new() -> Self {
+        Self { requests: HashMap::new(), rng: StdRng::from_entropy() }
+    }
+
+    #[cfg(feature = "testing")]
+    pub fn set_rng(&mut self, rng: StdRng) {
+        self.rng = rng;
+    }
+
+    // Asks the first candidate for a proof of the block
+    pub fn request(
+        &mut self,
+        block_hash: BlockHash,
+        block_number: u64,
+        candidates: &[NodeId],
+        keypair: &NodeKeypair,
+        now: DateTime<Utc>,
+    ) -> Option<ProofRequest> {
+        let open = OpenRequest { block_hash, block_number, target: [0; 32], tried: Vec::new(), sent_at: now };
+        self.send(open, candidates, keypair, now)
+    }
+
+    // Asks the next candidate that was not asked yet; None once the block is given up on
+    pub fn retry(
+        &mut self,
+        open: OpenRequest,
+        candidates: &[NodeId],
+        keypair: &NodeKeypair,
+        now: DateTime<Utc>,
+    ) -> Option<ProofRequest> {
+        if open.tried.len() >= MAX_ATTEMPTS {
+            return None;
+        }
+        self.send(open, candidates, keypair, now)
+    }
+
+    // The request a response answers; responses from anyone but the validator asked are dropped
+    pub fn take(&mut self, response: &ProofResponse) -> Option<OpenRequest> {
+        match self.requests.get(&response.request_id) {
+            Some(open) if open.target == response.responder => self.requests.remove(&response.request_id),
+            _ => None,
+        }
+    }
+
+    // Requests the asked validator did not answer in time
+    pub fn expire(&mut self, now: DateTime<Utc>) -> Vec<OpenRequest> {
+        let expired: Vec<_> = self.requests.iter()
+            .filter(|(_, open)| now - open.sent_at >= Duration::seconds(REQUEST_TIMEOUT_SECS))
+            .map(|(request_id, _)| *request_id)
+            .collect();
+
+        expired.into_iter()
+            .filter_map(|request_id| self.requests.remove(&request_id))
+            .inspect(|open| debug!("Proof request for block {} to {} timed out",
+                open.block_number, hex::encode(open.target)))
+            .collect()
+    }
+
+    fn send(
+        &mut self,
+        mut open: OpenRequest,
+        candidates: &[NodeId],
+        keypair: &NodeKeypair,
+        now: DateTime<Utc>,
+    ) -> Option<ProofRequest> {
+        let target = *candidates.iter().find(|candidate| !open.tried.contains(candidate))?;
+        let request_id = self.rng.gen();
+        let mut request = ProofRequest {
+            block_number: open.block_number,
+            block_hash: open.block_hash,
+            request_id,
+            requester: keypair.node_id(),
+            target,
+            signature: vec![],
+        };
+        request.signature = keypair.sign(&request.signing_hash());
+
+        open.target = target;
+        open.tried.push(target);
+        open.sent_at = now;
+        self.requests.insert(request_id, open);
+        Some(request)
+    }
+}
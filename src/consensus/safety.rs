@@ -0,0 +1,64 @@
+use crate::types::BlockHash;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+// What consensus.on_safety_violation may be set to: stop producing blocks, or go on and only
+// raise the alarm
+pub const SAFETY_ACTIONS: [&str; 2] = ["halt", "alarm"];
+// Violations an alarm keeps; the first ones, which later ones usually follow from
+const MAX_VIOLATIONS: usize = 64;
+
+// What BFT promises never happens unless more than a third of the stake equivocates, or our own
+// node is broken. Either way the chain can not be trusted until an operator has looked
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SafetyViolation {
+    // A valid finality certificate for another block at a height we finalized
+    ConflictingFinality { block_number: u64, ours: BlockHash, theirs: BlockHash },
+    // A block to finalize that does not descend from our finalized block
+    FinalizedReorg { finalized: u64, block_number: u64, block_hash: BlockHash },
+    // A block proof that failed verification after the block was accepted
+    InvalidProof { block_number: u64, block_hash: BlockHash },
+}
+
+impl fmt::Display for SafetyViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SafetyViolation::ConflictingFinality { block_number, ours, theirs } => write!(f,
+                "block {} was finalized at #{}, where we finalized {}", hex::encode(theirs), block_number, hex::encode(ours)),
+            SafetyViolation::FinalizedReorg { finalized, block_number, block_hash } => write!(f,
+                "finalizing block #{} ({}) would revert finalized block #{}", block_number, hex::encode(block_hash), finalized),
+            SafetyViolation::InvalidProof { block_number, block_hash } => write!(f,
+                "the proof of accepted block #{} ({}) does not verify", block_number, hex::encode(block_hash)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DetectedViolation {
+    pub violation: SafetyViolation,
+    pub detected_at: DateTime<Utc>,
+}
+
+// Stored, so a halted node stays halted across restarts until an operator resumes it
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SafetyAlarm {
+    pub violations: Vec<DetectedViolation>,
+    // Set in halt mode; we then neither propose nor vote
+    pub halted: bool,
+}
+
+impl SafetyAlarm {
+    // None for a violation already recorded
+    pub fn record(&mut self, violation: SafetyViolation, now: DateTime<Utc>) -> Option<DetectedViolation> {
+        if self.violations.iter().any(|detected| detected.violation == violation) {
+            return None;
+        }
+        let detected = DetectedViolation { violation, detected_at: now };
+        if self.violations.len() < MAX_VIOLATIONS {
+            self.violations.push(detected.clone());
+        }
+        Some(detected)
+    }
+}
@@ -0,0 +1,58 @@
+use chrono::{DateTime, Duration, Utc};
+
+// Time cut into slots of one block_time counted from the genesis time. A block belongs to the
+// slot its timestamp falls in, and round r of a height is the (r+1)th slot after the parent's,
+// so every validator starts a round at the same instant however late it saw the parent
+#[derive(Debug, Clone, Copy)]
+pub struct Slots {
+    genesis_time: DateTime<Utc>,
+    slot_ms: i64,
+}
+
+impl Slots {
+    pub fn new(genesis_time: DateTime<Utc>, slot_time: Duration) -> Self {
+        Self { genesis_time, slot_ms: slot_time.num_milliseconds().max(1) }
+    }
+
+    // Slots of another length from the same genesis time, after the chain changed its block time.
+    // Rounds only compare a block with its parent, so the grid can be redrawn between heights
+    pub fn with_slot_time(&self, slot_time: Duration) -> Self {
+        Self::new(self.genesis_time, slot_time)
+    }
+    
+    pub fn genesis_time(&self) -> DateTime<Utc> {
+        self.genesis_time
+    }
+
+    // Times before genesis fall in slot 0
+    pub fn slot_at(&self, time: DateTime<Utc>) -> u64 {
+        ((time - self.genesis_time).num_milliseconds().max(0) / self.slot_ms) as u64
+    }
+
+    pub fn slot_start(&self, slot: u64) -> DateTime<Utc> {
+        let offset = Duration::milliseconds(self.slot_ms.saturating_mul(slot.min(i64::MAX as u64) as i64));
+        match self.genesis_time.checked_add_signed(offset) {
+            Some(start) => start,
+            None => DateTime::<Utc>::MAX_UTC,
+        }
+    }
+
+    // Round of a block dated `time` on a parent dated `parent_time`; None when both fall in
+    // the same slot
+    pub fn round(&self, parent_time: DateTime<Utc>, time: DateTime<Utc>) -> Option<u64> {
+        self.slot_at(time).checked_sub(self.slot_at(parent_time) + 1)
+    }
+
+    pub fn round_start(&self, parent_time: DateTime<Utc>, round: u64) -> DateTime<Utc> {
+        self.slot_start(self.slot_at(parent_time).saturating_add(round).saturating_add(1))
+    }
+
+    // Rounds time out after a third and two thirds of their slot, so these are the instants
+    // the consensus loop has to wake up at
+    pub fn next_boundary(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+        let third = (self.slot_ms / 3).max(1);
+        let elapsed = (now - self.genesis_time).num_milliseconds().max(0);
+        let next = elapsed - elapsed % self.slot_ms + (elapsed % self.slot_ms / third + 1) * third;
+        self.genesis_time + Duration::milliseconds(next.min(elapsed - elapsed % self.slot_ms + self.slot_ms))
+    }
+}
@@ -0,0 +1,133 @@
+use crate::types::NodeId;
+use anyhow::{anyhow, bail, Result};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use ed25519_dalek::SigningKey;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::fs;
+use std::path::Path;
+
+use super::NodeKeypair;
+
+// Read instead of prompting, for nodes started by a service manager
+const PASSWORD_ENV: &str = "ZK_KEYSTORE_PASSWORD";
+
+const KEYSTORE_VERSION: u32 = 1;
+const KDF_ROUNDS: u32 = 600_000;
+
+// Validator key encrypted with ChaCha20-Poly1305 under a PBKDF2-SHA256 key derived from a password.
+// The node id is stored in the clear so the key can be identified without unlocking it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Keystore {
+    pub version: u32,
+    #[serde(with = "hex_bytes")]
+    pub node_id: Vec<u8>,
+    pub kdf_rounds: u32,
+    #[serde(with = "hex_bytes")]
+    pub salt: Vec<u8>,
+    #[serde(with = "hex_bytes")]
+    pub nonce: Vec<u8>,
+    #[serde(with = "hex_bytes")]
+    pub ciphertext: Vec<u8>,
+}
+
+impl Keystore {
+    pub fn encrypt(keypair: &NodeKeypair, password: &str) -> Result<Self> {
+        let mut salt = vec![0u8; 16];
+        let mut nonce = vec![0u8; 12];
+        rand::rngs::OsRng.fill_bytes(&mut salt);
+        rand::rngs::OsRng.fill_bytes(&mut nonce);
+
+        let node_id = keypair.node_id();
+        let cipher = cipher(password, &salt, KDF_ROUNDS);
+        let ciphertext = cipher
+            .encrypt(Nonce::from_slice(&nonce), Payload { msg: &keypair.signing_key.to_bytes(), aad: &node_id })
+            .map_err(|_| anyhow!("Failed to encrypt the validator key"))?;
+
+        Ok(Self {
+            version: KEYSTORE_VERSION,
+            node_id: node_id.to_vec(),
+            kdf_rounds: KDF_ROUNDS,
+            salt,
+            nonce,
+            ciphertext,
+        })
+    }
+
+    pub fn decrypt(&self, password: &str) -> Result<NodeKeypair> {
+        if self.version != KEYSTORE_VERSION {
+            bail!("Unsupported keystore version {}", self.version);
+        }
+        if self.nonce.len() != 12 {
+            bail!("Keystore nonce must be 12 bytes");
+        }
+
+        let cipher = cipher(password, &self.salt, self.kdf_rounds);
+        let secret = cipher
+            .decrypt(Nonce::from_slice(&self.nonce), Payload { msg: &self.ciphertext, aad: &self.node_id })
+            .map_err(|_| anyhow!("Wrong keystore password"))?;
+        let secret: [u8; 32] = match secret.try_into() {
+            Ok(secret) => secret,
+            Err(_) => bail!("Keystore does not contain a 32-byte key"),
+        };
+
+        let keypair = NodeKeypair { signing_key: SigningKey::from_bytes(&secret) };
+        if keypair.node_id().as_slice() != self.node_id {
+            bail!("Keystore key does not match its node id {}", hex::encode(&self.node_id));
+        }
+        Ok(keypair)
+    }
+
+    pub fn node_id(&self) -> Result<NodeId> {
+        self.node_id.as_slice().try_into().map_err(|_| anyhow!("Keystore node id must be 32 bytes"))
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| anyhow!("Failed to read keystore {}: {}", path.display(), e))?;
+        Ok(serde_json::from_str(&contents)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        super::write_secret(path, serde_json::to_string_pretty(self)?.as_bytes())
+    }
+}
+
+// Takes the password from the environment, or asks for it on the terminal. New passwords are
+// asked for twice
+pub fn password(confirm: bool) -> Result<String> {
+    if let Ok(password) = std::env::var(PASSWORD_ENV) {
+        return Ok(password);
+    }
+
+    let password = rpassword::prompt_password("Keystore password: ")?;
+    if confirm {
+        if password.is_empty() {
+            bail!("Keystore password must not be empty");
+        }
+        if rpassword::prompt_password("Repeat password: ")? != password {
+            bail!("Passwords do not match");
+        }
+    }
+    Ok(password)
+}
+
+fn cipher(password: &str, salt: &[u8], rounds: u32) -> ChaCha20Poly1305 {
+    let mut key = [0u8; 32];
+    pbkdf2::pbkdf2_hmac::<Sha256>(password.as_bytes(), salt, rounds, &mut key);
+    ChaCha20Poly1305::new(Key::from_slice(&key))
+}
+
+mod hex_bytes {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&hex::encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        hex::decode(String::deserialize(deserializer)?).map_err(D::Error::custom)
+    }
+}
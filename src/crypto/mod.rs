@@ -0,0 +1,100 @@
+use crate::types::NodeId;
+use anyhow::{bail, Result};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use std::fs;
+use std::path::Path;
+use tracing::info;
+
+mod keystore;
+mod verifier;
+
+pub use keystore::{password as keystore_password, Keystore};
+pub use verifier::{SignatureVerifier, SignedMessage};
+
+pub struct NodeKeypair {
+    signing_key: SigningKey,
+}
+
+impl NodeKeypair {
+    pub fn generate() -> Self {
+        Self {
+            signing_key: SigningKey::generate(&mut rand::rngs::OsRng),
+        }
+    }
+
+    pub fn from_secret_bytes(secret: &[u8; 32]) -> Self {
+        Self {
+            signing_key: SigningKey::from_bytes(secret),
+        }
+    }
+
+    pub fn load_or_generate(path: &Path) -> Result<Self> {
+        if path.exists() {
+            let keypair = Self::load(path)?;
+            info!("🔑 Loaded node key from {}", path.display());
+            return Ok(keypair);
+        }
+
+        let keypair = Self::generate();
+        keypair.save(path)?;
+        info!("🔑 Generated new node key at {}", path.display());
+        Ok(keypair)
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let secret = hex::decode(fs::read_to_string(path)?.trim())?;
+        let secret: [u8; 32] = match secret.try_into() {
+            Ok(secret) => secret,
+            Err(_) => bail!("Node key file {} does not contain a 32-byte key", path.display()),
+        };
+        Ok(Self::from_secret_bytes(&secret))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        write_secret(path, hex::encode(self.signing_key.to_bytes()).as_bytes())
+    }
+
+    // The node id is the Ed25519 public key, so any signature can be checked against it directly
+    pub fn node_id(&self) -> NodeId {
+        self.signing_key.verifying_key().to_bytes()
+    }
+
+    // libp2p identity from the same key, so the peer id survives restarts along with the node id
+    pub fn network_identity(&self) -> Result<libp2p::identity::Keypair> {
+        Ok(libp2p::identity::Keypair::ed25519_from_bytes(self.signing_key.to_bytes())?)
+    }
+
+    pub fn sign(&self, message: &[u8]) -> Vec<u8> {
+        self.signing_key.sign(message).to_bytes().to_vec()
+    }
+}
+
+// Key material is only readable by the owner
+fn write_secret(path: &Path, contents: &[u8]) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, contents)?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+    }
+
+    Ok(())
+}
+
+pub fn verify_signature(node_id: &NodeId, message: &[u8], signature: &[u8]) -> bool {
+    let verifying_key = match VerifyingKey::from_bytes(node_id) {
+        Ok(key) => key,
+        Err(_) => return false,
+    };
+
+    let signature = match Signature::from_slice(signature) {
+        Ok(signature) => signature,
+        Err(_) => return false,
+    };
+
+    verifying_key.verify(message, &signature).is_ok()
+}
@@ -0,0 +1,142 @@
+use crate::types::NodeId;
+use anyhow::{bail, Result};
+use rayon::prelude::*;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use std::sync::Arc;
+use tokio::sync::{mpsc, oneshot};
+use tracing::info;
+
+// Signatures gathered from the queue into one job at most
+const MAX_BATCH: usize = 4096;
+// Signatures a pool thread checks together, in one batch verification where possible
+const CHUNK_SIZE: usize = 64;
+
+// A 32-byte signing hash and the signature its signer put on it
+#[derive(Debug, Clone)]
+pub struct SignedMessage {
+    pub signer: NodeId,
+    pub message: [u8; 32],
+    pub signature: Vec<u8>,
+}
+
+impl SignedMessage {
+    pub fn new(signer: NodeId, message: [u8; 32], signature: &[u8]) -> Self {
+        Self { signer, message, signature: signature.to_vec() }
+    }
+}
+
+struct Request {
+    signatures: Vec<SignedMessage>,
+    result_tx: oneshot::Sender<Vec<bool>>,
+}
+
+// Checks signatures on a pool of threads of its own, off the consensus loop. Requests queued
+// while the pool is busy are verified together, so the signatures of blocks and vote bundles
+// arriving at once share batches
+#[derive(Clone)]
+pub struct SignatureVerifier {
+    queue: mpsc::UnboundedSender<Request>,
+}
+
+impl SignatureVerifier {
+    pub fn start(workers: usize) -> Result<Self> {
+        let pool = ThreadPoolBuilder::new()
+            .num_threads(workers)
+            .thread_name(|index| format!("signature-verifier-{}", index))
+            .build()?;
+        let (queue, rx) = mpsc::unbounded_channel();
+        tokio::spawn(dispatch(Arc::new(pool), rx));
+        info!("✍️ Started {} signature verification threads{}", workers,
+            if cfg!(feature = "batch-verify") { " with batch verification" } else { "" });
+        Ok(Self { queue })
+    }
+
+    // Whether each signature holds, in the order given
+    pub async fn verify_all(&self, signatures: Vec<SignedMessage>) -> Result<Vec<bool>> {
+        if signatures.is_empty() {
+            return Ok(Vec::new());
+        }
+        let (result_tx, result) = oneshot::channel();
+        if let Err(e) = self.queue.send(Request { signatures, result_tx }) {
+            let signatures: Vec<_> = e.0.signatures.iter().collect();
+            return Ok(verify_chunk(&signatures));
+        }
+        match result.await {
+            Ok(verdicts) => Ok(verdicts),
+            Err(_) => bail!("signature verification was dropped"),
+        }
+    }
+}
+
+async fn dispatch(pool: Arc<ThreadPool>, mut rx: mpsc::UnboundedReceiver<Request>) {
+    while let Some(request) = rx.recv().await {
+        let mut count = request.signatures.len();
+        let mut requests = vec![request];
+        while count < MAX_BATCH {
+            match rx.try_recv() {
+                Ok(request) => {
+                    count += request.signatures.len();
+                    requests.push(request);
+                }
+                Err(_) => break,
+            }
+        }
+        pool.spawn(move || verify_requests(requests));
+    }
+}
+
+fn verify_requests(requests: Vec<Request>) {
+    let signatures: Vec<&SignedMessage> = requests.iter().flat_map(|request| &request.signatures).collect();
+    let verdicts: Vec<Vec<bool>> = signatures.par_chunks(CHUNK_SIZE).map(verify_chunk).collect();
+
+    let mut verdicts = verdicts.into_iter().flatten();
+    for request in requests {
+        let own = verdicts.by_ref().take(request.signatures.len()).collect();
+        let _ = request.result_tx.send(own);
+    }
+}
+
+// A chunk that fails as a batch, or can not be batched, is checked one signature at a time
+fn verify_chunk(signatures: &[&SignedMessage]) -> Vec<bool> {
+    #[cfg(feature = "batch-verify")]
+    if verify_batch(signatures) {
+        return vec![true; signatures.len()];
+    }
+    signatures.iter()
+        .map(|signed| super::verify_signature(&signed.signer, &signed.message, &signed.signature))
+        .collect()
+}
+
+// Batch verification accepts a combination of the signatures, which a torsion component in a key
+// or R point can make hold for a signature that fails on its own. Only chunks of torsion-free,
+// canonically encoded points are batched, so a batch passes exactly when every signature would
+#[cfg(feature = "batch-verify")]
+fn verify_batch(signatures: &[&SignedMessage]) -> bool {
+    use curve25519_dalek::edwards::CompressedEdwardsY;
+    use ed25519_dalek::{Signature, VerifyingKey};
+
+    let mut keys = Vec::with_capacity(signatures.len());
+    let mut parsed = Vec::with_capacity(signatures.len());
+    for signed in signatures {
+        let key = match VerifyingKey::from_bytes(&signed.signer) {
+            Ok(key) => key,
+            Err(_) => return false,
+        };
+        let signature = match Signature::from_slice(&signed.signature) {
+            Ok(signature) => signature,
+            Err(_) => return false,
+        };
+        let r = match CompressedEdwardsY(*signature.r_bytes()).decompress() {
+            Some(r) => r,
+            None => return false,
+        };
+        if !key.to_edwards().is_torsion_free() || !r.is_torsion_free() || r.compress().as_bytes() != signature.r_bytes() {
+            return false;
+        }
+        keys.push(key);
+        parsed.push(signature);
+    }
+
+    let messages: Vec<&[u8]> = signatures.iter().map(|signed| signed.message.as_slice()).collect();
+    ed25519_dalek::verify_batch(&messages, &parsed, &keys).is_ok()
+}
@@ -0,0 +1,290 @@
+use crate::shutdown::ShutdownSignal;
+use crate::storage::{AddressHistory, HistoryPage, StorageError, StorageManager};
+use crate::sync::SyncStatus;
+use crate::types::{Block, BlockHash, ConsensusState, Transaction, TransactionReceipt};
+use anyhow::Result;
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::{routing::get, Json, Router};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tokio::sync::RwLock;
+use tracing::{info, debug};
+
+// Page size for /blocks when the request gives none, and the most one page may hold
+const DEFAULT_PAGE_SIZE: u64 = 20;
+const MAX_PAGE_SIZE: u64 = 100;
+
+// Read-only REST API for explorer frontends and dashboards; writes go through JSON-RPC
+pub struct ExplorerServer {
+    addr: SocketAddr,
+    storage: StorageManager,
+    state: Arc<RwLock<ConsensusState>>,
+    sync: Arc<RwLock<SyncStatus>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct BlocksQuery {
+    // Lowest block to list, defaults to the first
+    from: Option<u64>,
+    // Highest block to list, defaults to the latest
+    to: Option<u64>,
+    // next_cursor of the previous page
+    cursor: Option<u64>,
+    limit: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct HistoryQuery {
+    // next_cursor of the previous page
+    cursor: Option<u64>,
+    limit: Option<u64>,
+}
+
+// Blocks are listed newest first; next_cursor is absent on the last page
+#[derive(Debug, Serialize)]
+struct BlockPage {
+    blocks: Vec<BlockView>,
+    next_cursor: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+struct BlockView {
+    hash: String,
+    #[serde(flatten)]
+    block: Block,
+}
+
+impl From<Block> for BlockView {
+    fn from(block: Block) -> Self {
+        Self {
+            hash: hex::encode(block.hash()),
+            block,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum TransactionStatus {
+    Pending,
+    Included,
+}
+
+#[derive(Debug, Serialize)]
+struct TransactionView {
+    status: TransactionStatus,
+    // Once the including block is finalized
+    receipt: Option<TransactionReceipt>,
+    #[serde(flatten)]
+    transaction: Transaction,
+}
+
+#[derive(Debug)]
+struct ApiError {
+    status: StatusCode,
+    message: String,
+}
+
+impl ApiError {
+    fn bad_request(message: impl Into<String>) -> Self {
+        Self { status: StatusCode::BAD_REQUEST, message: message.into() }
+    }
+
+    fn not_found(message: impl Into<String>) -> Self {
+        Self { status: StatusCode::NOT_FOUND, message: message.into() }
+    }
+}
+
+impl From<anyhow::Error> for ApiError {
+    fn from(e: anyhow::Error) -> Self {
+        Self { status: StatusCode::INTERNAL_SERVER_ERROR, message: e.to_string() }
+    }
+}
+
+impl From<StorageError> for ApiError {
+    fn from(e: StorageError) -> Self {
+        let status = match e {
+            StorageError::NotFound(_) => StatusCode::NOT_FOUND,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        Self { status, message: e.to_string() }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.status, Json(json!({ "error": self.message }))).into_response()
+    }
+}
+
+type ApiResult<T> = Result<Json<T>, ApiError>;
+
+impl ExplorerServer {
+    pub fn new(
+        port: u16,
+        storage: StorageManager,
+        state: Arc<RwLock<ConsensusState>>,
+        sync: Arc<RwLock<SyncStatus>>,
+    ) -> Self {
+        Self {
+            addr: SocketAddr::from(([127, 0, 0, 1], port)),
+            storage,
+            state,
+            sync,
+        }
+    }
+
+    pub async fn start(self, mut shutdown: ShutdownSignal) -> Result<()> {
+        let listener = TcpListener::bind(self.addr).await?;
+        info!("🔭 Explorer API listening on http://{}", self.addr);
+
+        let app = Router::new()
+            .route("/blocks", get(get_blocks))
+            .route("/blocks/:id", get(get_block))
+            .route("/txs/:id", get(get_transaction))
+            .route("/accounts/:id/txs", get(get_account_transactions))
+            .route("/validators", get(get_validators))
+            .route("/stats", get(get_stats))
+            .with_state(Arc::new(self));
+        axum::serve(listener, app)
+            .with_graceful_shutdown(async move { shutdown.recv().await })
+            .await?;
+
+        Ok(())
+    }
+}
+
+async fn get_blocks(
+    State(server): State<Arc<ExplorerServer>>,
+    Query(query): Query<BlocksQuery>,
+) -> ApiResult<BlockPage> {
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE);
+    if limit == 0 || limit > MAX_PAGE_SIZE {
+        return Err(ApiError::bad_request(format!("limit must be between 1 and {}", MAX_PAGE_SIZE)));
+    }
+
+    let latest = server.storage.get_latest_block().await?.map_or(0, |block| block.header.block_number);
+    let from = query.from.unwrap_or(1).max(1);
+    let to = [query.to, query.cursor].into_iter().flatten().fold(latest, u64::min);
+    debug!("Explorer listing blocks {}..={} (limit {})", from, to, limit);
+
+    if to < from {
+        return Ok(Json(BlockPage { blocks: Vec::new(), next_cursor: None }));
+    }
+
+    let start = to.saturating_sub(limit - 1).max(from);
+    let mut blocks = server.storage.get_block_range(start, to).await?;
+    blocks.reverse();
+
+    Ok(Json(BlockPage {
+        blocks: blocks.into_iter().map(BlockView::from).collect(),
+        next_cursor: (start > from).then(|| start - 1),
+    }))
+}
+
+// Accepts a block hash, or a block number for the canonical chain
+async fn get_block(
+    State(server): State<Arc<ExplorerServer>>,
+    Path(id): Path<String>,
+) -> ApiResult<BlockView> {
+    let block = match id.parse::<u64>() {
+        Ok(number) => server.storage.get_block(number).await?,
+        Err(_) => server.storage.get_block_by_hash(&parse_hash(&id)?).await?,
+    };
+
+    match block {
+        Some(block) if server.storage.is_header_only(block.header.block_number).await? => {
+            Err(ApiError::not_found(format!("block {} is pruned to its header", id)))
+        }
+        Some(block) => Ok(Json(block.into())),
+        None => Err(ApiError::not_found(format!("block {} not found", id))),
+    }
+}
+
+async fn get_transaction(
+    State(server): State<Arc<ExplorerServer>>,
+    Path(id): Path<String>,
+) -> ApiResult<TransactionView> {
+    let tx_id = parse_hash(&id)?;
+    let transaction = match server.storage.get_transaction(&tx_id).await? {
+        Some(transaction) => transaction,
+        None => return Err(ApiError::not_found(format!("transaction {} not found", id))),
+    };
+
+    let status = if server.storage.is_transaction_pending(&tx_id).await? {
+        TransactionStatus::Pending
+    } else {
+        TransactionStatus::Included
+    };
+
+    let receipt = server.storage.get_receipt(&tx_id).await?;
+    Ok(Json(TransactionView { status, receipt, transaction }))
+}
+
+// Finalized transactions sent or received by the account, newest first
+async fn get_account_transactions(
+    State(server): State<Arc<ExplorerServer>>,
+    Path(id): Path<String>,
+    Query(query): Query<HistoryQuery>,
+) -> ApiResult<AddressHistory> {
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE);
+    if limit == 0 || limit > MAX_PAGE_SIZE {
+        return Err(ApiError::bad_request(format!("limit must be between 1 and {}", MAX_PAGE_SIZE)));
+    }
+
+    let page = HistoryPage { cursor: query.cursor, limit: limit as usize };
+    Ok(Json(server.storage.get_transactions_by_address(&parse_hash(&id)?, &page).await?))
+}
+
+// Highest stake first
+async fn get_validators(State(server): State<Arc<ExplorerServer>>) -> ApiResult<Vec<Value>> {
+    let state = server.state.read().await;
+    let mut validators: Vec<_> = state.validators.iter().collect();
+    validators.sort_by(|a, b| b.1.stake.cmp(&a.1.stake).then(a.0.cmp(b.0)));
+
+    Ok(Json(validators.into_iter()
+        .map(|(node_id, info)| json!({
+            "node_id": hex::encode(node_id),
+            "stake": info.stake,
+            "is_active": info.is_active,
+            "last_block_time": info.last_block_time,
+            "performance_score": info.performance_score,
+            "jailed_at": info.jailed_at,
+        }))
+        .collect()))
+}
+
+async fn get_stats(State(server): State<Arc<ExplorerServer>>) -> ApiResult<Value> {
+    let latest = server.storage.get_latest_block().await?;
+    let finalized = server.storage.get_finalized_block().await?;
+    let pending = server.storage.get_pending_transactions().await?.len();
+
+    let state = server.state.read().await;
+    let active_validators = state.validators.values().filter(|info| info.is_active).count();
+
+    Ok(Json(json!({
+        "latest_block": latest.as_ref().map(|block| block.header.block_number),
+        "latest_block_time": latest.as_ref().map(|block| block.header.timestamp),
+        "justified_block": state.justified,
+        "finalized_block": finalized.as_ref().map(|block| block.header.block_number),
+        "epoch": state.epoch,
+        "total_stake": state.total_stake,
+        "validators": state.validators.len(),
+        "active_validators": active_validators,
+        "pending_transactions": pending,
+        "sync": &*server.sync.read().await,
+    })))
+}
+
+fn parse_hash(hex_str: &str) -> Result<BlockHash, ApiError> {
+    let bytes = hex::decode(hex_str.trim_start_matches("0x"))
+        .map_err(|e| ApiError::bad_request(format!("invalid hex: {}", e)))?;
+
+    bytes.try_into()
+        .map_err(|_| ApiError::bad_request("expected a 32-byte hex string"))
+}
@@ -0,0 +1,439 @@
+use crate::config::ConsensusConfig;
+use crate::snapshot::Snapshot;
+use crate::staking::{self, MIN_VALIDATOR_STAKE};
+use crate::state::{self, Account, AccountId};
+use crate::storage::StorageManager;
+use crate::types::{Block, BlockHash, BlockHeader, BlockLimits, ConsensusParams, ConsensusState, HashFunction, LivenessPolicy, NodeId, ProofPolicy, ProofType, ZKProof};
+use crate::zk_proof::{CircuitKey, VerifyingKeys};
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Sha256, Digest};
+use std::collections::{BTreeMap, HashSet};
+use std::path::Path;
+use tracing::info;
+
+const GENESIS_DOMAIN: &[u8] = b"zk-consensus/genesis/v1";
+
+// Contents of genesis.json. Every node of a chain must start from the same file, since block 0
+// and with it every later block hash commit to it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Genesis {
+    pub chain_id: u64,
+    // Timestamp of block 0; block 1 may be proposed one block time later
+    pub timestamp: DateTime<Utc>,
+    #[serde(default)]
+    pub consensus: GenesisParams,
+    pub validators: Vec<GenesisValidator>,
+    #[serde(default)]
+    pub accounts: Vec<GenesisAccount>,
+}
+
+// Parameters every node has to agree on; they override the node's own configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GenesisParams {
+    pub block_time: u64,
+    pub max_future_drift: u64,
+    // For block hashes, merkle roots and proof inputs. Left out of the file, and of the genesis
+    // hash, when it is the default, so chains created before it was configurable keep their hash
+    #[serde(skip_serializing_if = "HashFunction::is_default")]
+    pub hash_function: HashFunction,
+    // For transaction merkle roots only, when they should use another one than hash_function,
+    // such as poseidon on chains proving blocks with groth16. Left out the same way
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub merkle_hash_function: Option<HashFunction>,
+    // Left out the same way, for the same reason
+    #[serde(skip_serializing_if = "BlockLimits::is_default")]
+    pub limits: BlockLimits,
+    #[serde(skip_serializing_if = "ProofPolicy::is_default")]
+    pub proofs: ProofPolicy,
+    // The one proof system block and header proofs are accepted in, so a proposer can not pick
+    // a weaker one. Left out the same way when it is groth16
+    #[serde(skip_serializing_if = "is_default_proof_type")]
+    pub proof_type: ProofType,
+    #[serde(skip_serializing_if = "LivenessPolicy::is_default")]
+    pub liveness: LivenessPolicy,
+    // Every circuit version proofs on the chain may name. Left out the same way; without any,
+    // nodes verify against the keys they set up themselves
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub verifying_keys: Vec<GenesisVerifyingKey>,
+    // SHA-256 of the key files nodes read from zk_proof.key_dir, as `export-keys` prints them.
+    // Left out the same way; only pinned files are downloaded from zk_proof.key_mirrors
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub artifacts: Vec<GenesisArtifact>,
+    // The rest of the on-chain consensus parameters, each left out the same way
+    #[serde(skip_serializing_if = "is_default_min_validators")]
+    pub min_validators: u64,
+    #[serde(skip_serializing_if = "is_default_max_validators")]
+    pub max_validators: u64,
+    #[serde(skip_serializing_if = "is_default_quorum_numerator")]
+    pub quorum_numerator: u64,
+    #[serde(skip_serializing_if = "is_default_quorum_denominator")]
+    pub quorum_denominator: u64,
+}
+
+fn is_default_proof_type(value: &ProofType) -> bool {
+    *value == ProofType::Groth16
+}
+
+fn is_default_min_validators(value: &u64) -> bool {
+    *value == ConsensusParams::default().min_validators
+}
+
+fn is_default_max_validators(value: &u64) -> bool {
+    *value == ConsensusParams::default().max_validators
+}
+
+fn is_default_quorum_numerator(value: &u64) -> bool {
+    *value == ConsensusParams::default().quorum_numerator
+}
+
+fn is_default_quorum_denominator(value: &u64) -> bool {
+    *value == ConsensusParams::default().quorum_denominator
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GenesisValidator {
+    #[serde(with = "hex_id")]
+    pub node_id: NodeId,
+    pub stake: u64,
+}
+
+// Hashes of the verifying keys of one circuit version, as nodes log them on startup
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GenesisVerifyingKey {
+    pub proof_type: ProofType,
+    pub circuit_version: u32,
+    #[serde(with = "hex_id")]
+    pub key: [u8; 32],
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GenesisArtifact {
+    // Name of the file in zk_proof.key_dir, such as groth16_block.pk
+    pub file: String,
+    #[serde(with = "hex_id")]
+    pub sha256: [u8; 32],
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GenesisAccount {
+    #[serde(with = "hex_id")]
+    pub account: AccountId,
+    pub balance: u64,
+}
+
+impl Default for GenesisParams {
+    fn default() -> Self {
+        let defaults = ConsensusConfig::default();
+        let params = ConsensusParams::default();
+        Self {
+            block_time: defaults.block_time,
+            max_future_drift: defaults.max_future_drift,
+            hash_function: HashFunction::default(),
+            merkle_hash_function: None,
+            limits: defaults.limits,
+            proofs: defaults.proofs,
+            proof_type: ProofType::Groth16,
+            liveness: defaults.liveness,
+            verifying_keys: Vec::new(),
+            artifacts: Vec::new(),
+            min_validators: params.min_validators,
+            max_validators: params.max_validators,
+            quorum_numerator: params.quorum_numerator,
+            quorum_denominator: params.quorum_denominator,
+        }
+    }
+}
+
+impl GenesisParams {
+    // What the chain starts out with; validators may vote to change it later
+    pub fn params(&self) -> ConsensusParams {
+        ConsensusParams {
+            block_time: self.block_time,
+            min_validators: self.min_validators,
+            max_validators: self.max_validators,
+            quorum_numerator: self.quorum_numerator,
+            quorum_denominator: self.quorum_denominator,
+        }
+    }
+
+    fn set_params(&mut self, params: &ConsensusParams) {
+        self.block_time = params.block_time;
+        self.min_validators = params.min_validators;
+        self.max_validators = params.max_validators;
+        self.quorum_numerator = params.quorum_numerator;
+        self.quorum_denominator = params.quorum_denominator;
+    }
+}
+
+impl Genesis {
+    // Single-validator genesis for starting a new chain, funding the validator with the supply
+    pub fn new(chain_id: u64, validator: NodeId, stake: u64, balance: u64) -> Self {
+        Self {
+            chain_id,
+            timestamp: Utc::now(),
+            consensus: GenesisParams::default(),
+            validators: vec![GenesisValidator { node_id: validator, stake }],
+            accounts: vec![GenesisAccount { account: validator, balance }],
+        }
+    }
+
+    // Spec for a new chain carrying on from finalized block `height`: its active validators with
+    // their stakes and every funded account. Nonces start over, so the new chain needs a chain
+    // id of its own to keep the old chain's transactions from being replayed on it
+    pub async fn build_spec(storage: &StorageManager, height: u64, chain_id: u64, mut consensus: GenesisParams) -> Result<Self> {
+        let finalized = storage.get_finalized_block().await?.map(|block| block.header.block_number);
+        let snapshot = if finalized == Some(height) {
+            Snapshot::from_storage(storage).await?
+        } else {
+            Snapshot::at_height(storage, height).await?
+        };
+        let (block, state, accounts) = match snapshot {
+            Some(Snapshot { block, consensus_state: Some(state), accounts, .. }) => (block, state, accounts),
+            _ => bail!("No finalized state at block #{}, the node may have pruned it", height),
+        };
+        // Parameters the validators voted for carry over; states from before they were on-chain
+        // have no block time on record
+        if state.params.block_time > 0 {
+            consensus.set_params(&state.params);
+        }
+
+        let mut genesis = Self {
+            chain_id,
+            // Kept from the block rather than taken from the clock, so the same height always
+            // gives the same spec
+            timestamp: block.header.timestamp,
+            consensus,
+            validators: state.validators.iter()
+                .filter(|(_, info)| info.is_active)
+                .map(|(node_id, info)| GenesisValidator { node_id: *node_id, stake: info.stake })
+                .collect(),
+            accounts: accounts.into_iter()
+                .filter(|(_, account)| account.balance > 0)
+                .map(|(account, state)| GenesisAccount { account, balance: state.balance })
+                .collect(),
+        };
+        genesis.validators.sort_by_key(|validator| validator.node_id);
+        genesis.accounts.sort_by_key(|account| account.account);
+        genesis.validate()?;
+        Ok(genesis)
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let mut genesis: Self = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        genesis.validate()?;
+
+        // Entry order in the file does not change the chain
+        genesis.validators.sort_by_key(|validator| validator.node_id);
+        genesis.accounts.sort_by_key(|account| account.account);
+
+        info!("🌱 Loaded genesis for chain {} from {} ({})", genesis.chain_id, path.display(), hex::encode(genesis.hash()));
+        Ok(genesis)
+    }
+
+    pub fn write_to(&self, path: &Path) -> Result<()> {
+        self.validate()?;
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.validators.is_empty() {
+            bail!("Genesis has no validators");
+        }
+        let mut validators = HashSet::new();
+        for validator in &self.validators {
+            if !validators.insert(validator.node_id) {
+                bail!("Genesis lists validator {} twice", hex::encode(validator.node_id));
+            }
+            if validator.stake < MIN_VALIDATOR_STAKE {
+                bail!("Genesis stake of {} for validator {} is below the minimum validator stake of {}",
+                    validator.stake, hex::encode(validator.node_id), MIN_VALIDATOR_STAKE);
+            }
+        }
+
+        let mut accounts = HashSet::new();
+        let mut supply: u64 = 0;
+        for account in &self.accounts {
+            if !accounts.insert(account.account) {
+                bail!("Genesis lists account {} twice", hex::encode(account.account));
+            }
+            supply = match supply.checked_add(account.balance) {
+                Some(supply) => supply,
+                None => bail!("Genesis balances overflow the total supply"),
+            };
+        }
+
+        if let Err(e) = self.consensus.params().validate() {
+            bail!("Genesis {}", e);
+        }
+        if self.consensus.limits.max_txs_per_block == 0 || self.consensus.limits.max_tx_bytes == 0 {
+            bail!("Genesis max_txs_per_block and max_tx_bytes must be at least 1");
+        }
+        if self.consensus.proofs.proof_interval == 0 {
+            bail!("Genesis proof_interval must be at least 1");
+        }
+        if !matches!(self.consensus.proof_type, ProofType::Groth16 | ProofType::Plonk) {
+            bail!("Genesis proof_type must be a block proof system, not {:?}", self.consensus.proof_type);
+        }
+
+        // Version 0 is what blocks without a proof name
+        let mut circuits = HashSet::new();
+        for circuit in &self.consensus.verifying_keys {
+            if circuit.proof_type == ProofType::Checkpoint || circuit.circuit_version == 0 {
+                bail!("Genesis verifying key for {:?} circuit version {} can not be registered",
+                    circuit.proof_type, circuit.circuit_version);
+            }
+            if !circuits.insert((circuit.proof_type.clone(), circuit.circuit_version)) {
+                bail!("Genesis lists {:?} circuit version {} twice", circuit.proof_type, circuit.circuit_version);
+            }
+        }
+
+        // Names are joined onto the key directory and the mirror URLs
+        let mut files = HashSet::new();
+        for artifact in &self.consensus.artifacts {
+            if artifact.file.is_empty() || artifact.file.contains(['/', '\\']) || artifact.file.starts_with('.') {
+                bail!("Genesis artifact '{}' is not a plain file name", artifact.file);
+            }
+            if !files.insert(&artifact.file) {
+                bail!("Genesis lists artifact {} twice", artifact.file);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn hash(&self) -> BlockHash {
+        let mut hasher = Sha256::new();
+        hasher.update(GENESIS_DOMAIN);
+        hasher.update(bincode::serialize(self).unwrap());
+        hasher.finalize().into()
+    }
+
+    // Consensus parameters are set by the chain, not by the node
+    pub fn apply_to(&self, config: &mut ConsensusConfig) {
+        config.block_time = self.consensus.block_time;
+        config.max_future_drift = self.consensus.max_future_drift;
+        config.limits = self.consensus.limits;
+        config.proofs = self.consensus.proofs;
+        config.liveness = self.consensus.liveness;
+    }
+
+    // Value of zk_proof.backend that proves blocks in the chain's proof system
+    pub fn proof_backend(&self) -> &'static str {
+        match self.consensus.proof_type {
+            ProofType::Plonk => "plonk",
+            _ => "groth16",
+        }
+    }
+
+    // None when the genesis registers no keys
+    pub fn verifying_keys(&self) -> Option<VerifyingKeys> {
+        if self.consensus.verifying_keys.is_empty() {
+            return None;
+        }
+        Some(VerifyingKeys::new(self.consensus.verifying_keys.iter().map(|circuit| CircuitKey {
+            proof_type: circuit.proof_type.clone(),
+            circuit_version: circuit.circuit_version,
+            key: circuit.key,
+        })))
+    }
+
+    pub fn artifact_hashes(&self) -> BTreeMap<String, [u8; 32]> {
+        self.consensus.artifacts.iter()
+            .map(|artifact| (artifact.file.clone(), artifact.sha256))
+            .collect()
+    }
+
+    pub fn accounts(&self) -> BTreeMap<AccountId, Account> {
+        self.accounts.iter()
+            .map(|account| (account.account, Account { balance: account.balance, nonce: 0 }))
+            .collect()
+    }
+
+    pub fn consensus_state(&self) -> ConsensusState {
+        let validators: Vec<_> = self.validators.iter()
+            .map(|validator| (validator.node_id, validator.stake))
+            .collect();
+        staking::genesis_state(&validators, self.timestamp, self.consensus.params())
+    }
+
+    // Block 0 has no transactions, proof or proposer; its merkle root commits to the genesis instead
+    pub fn block(&self) -> Block {
+        Block {
+            header: BlockHeader {
+                block_number: 0,
+                parent_hash: [0; 32],
+                timestamp: self.timestamp,
+                merkle_root: self.hash(),
+                state_root: state::state_root(&self.accounts()),
+                votes_root: [0; 32],
+                validator: [0; 32],
+                difficulty: 0,
+                nonce: 0,
+            },
+            transactions: Vec::new(),
+            parent_votes: Vec::new(),
+            zk_proof: ZKProof {
+                proof_data: vec![],
+                public_inputs: vec![],
+                circuit_version: 0,
+                proof_type: ProofType::Groth16,
+            },
+            signature: vec![],
+        }
+    }
+
+    // Writes block 0 and the initial state into an empty database, or checks that the
+    // database was created from this genesis. Returns the genesis block hash
+    pub async fn initialize(&self, storage: &StorageManager) -> Result<BlockHash> {
+        let block = self.block();
+        let hash = block.hash();
+
+        if let Some(stored) = storage.get_block(0).await? {
+            if stored.hash() != hash {
+                bail!("Database was created from genesis {}, not {}", hex::encode(stored.hash()), hex::encode(hash));
+            }
+            return Ok(hash);
+        }
+        if let Some(first) = storage.get_block(1).await? {
+            if first.header.parent_hash != hash {
+                bail!("Database holds a chain that does not start from genesis {}", hex::encode(hash));
+            }
+        }
+        // Snapshots start above block 0, which there is no need to add
+        if storage.get_latest_block().await?.is_some() {
+            return Ok(hash);
+        }
+
+        let state = self.consensus_state();
+        let accounts: Vec<_> = self.accounts().into_iter().collect();
+        storage.store_block(&block).await?;
+        storage.set_finalized_block(&block, &accounts).await?;
+        storage.store_consensus_state(&state).await?;
+        storage.store_epoch_change(&staking::epoch_change(&state, &block)).await?;
+
+        info!("🌱 Initialized chain {} at genesis {} ({} validators, {} accounts)",
+            self.chain_id, hex::encode(hash), state.validators.len(), accounts.len());
+        Ok(hash)
+    }
+}
+
+// Ids are written as hex strings, optionally 0x-prefixed
+mod hex_id {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(id: &[u8; 32], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&hex::encode(id))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<[u8; 32], D::Error> {
+        parse(&String::deserialize(deserializer)?).map_err(D::Error::custom)
+    }
+
+    fn parse(hex_str: &str) -> Result<[u8; 32], String> {
+        let bytes = hex::decode(hex_str.trim_start_matches("0x")).map_err(|e| e.to_string())?;
+        bytes.try_into().map_err(|_| "expected a 32-byte hex string".to_string())
+    }
+}
@@ -0,0 +1,34 @@
+// The node as a library; the zk_consensus binary is a thin CLI over Node. Programs embedding
+// it build a node with Node::builder() or use the engine, storage and types directly
+pub mod admin;
+pub mod consensus;
+pub mod zk_proof;
+pub mod network;
+pub mod storage;
+pub mod types;
+pub mod snapshot;
+pub mod clock;
+pub mod bench;
+pub mod conformance;
+pub mod crypto;
+pub mod rpc;
+pub mod staking;
+pub mod light_client;
+pub mod logging;
+pub mod sync;
+pub mod state;
+pub mod config;
+pub mod shutdown;
+pub mod explorer;
+pub mod genesis;
+pub mod node;
+pub mod wallet;
+pub mod bridge;
+#[cfg(feature = "testing")]
+pub mod testing;
+
+pub use config::NodeConfig;
+pub use consensus::ConsensusEngine;
+pub use node::{Node, NodeBuilder};
+pub use storage::StorageManager;
+pub use types::{Block, BlockHash, ChainEvent, NodeId, Transaction, TransactionKind};
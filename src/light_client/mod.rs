@@ -0,0 +1,305 @@
+use crate::consensus::{self, ConsensusSender, Inbox};
+use crate::crypto;
+use crate::shutdown::ShutdownSignal;
+use crate::types::{
+    self, BlockHash, CertifiedHeader, ConsensusMessage, ConsensusParams, HeaderRequest, HeaderResponse, LightBlock, NodeId,
+    TrustedCheckpoint,
+};
+use crate::zk_proof::ZKProofGenerator;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::{mpsc, RwLock};
+use tracing::{info, debug, warn, error};
+
+// Full nodes only answer with finalized headers, so polling a few times per block is enough
+const SYNC_INTERVAL: std::time::Duration = std::time::Duration::from_secs(4);
+
+// Latest finalized header whose parent link, finality certificate, proposer signature and ZK proof were checked
+#[derive(Debug, Clone)]
+pub struct VerifiedHead {
+    pub block: LightBlock,
+    pub hash: BlockHash,
+    pub verified_headers: u64,
+    pub updated_at: DateTime<Utc>,
+}
+
+// Follows the finalized chain from headers and proofs alone; transactions are never downloaded
+pub struct LightClient {
+    zk_generator: ZKProofGenerator,
+    node_id: NodeId,
+    message_tx: ConsensusSender,
+    inbox: Inbox,
+    network_tx: Option<mpsc::Sender<ConsensusMessage>>,
+    head: Arc<RwLock<Option<VerifiedHead>>>,
+    pending_request: Option<[u8; 32]>,
+    // Parent of block 1; zero for chains started without a genesis file
+    genesis_hash: BlockHash,
+    // Header to start from instead of block 1, trusted without its ancestors
+    checkpoint: Option<TrustedCheckpoint>,
+    // Active validators and their stake after the verified head, whose precommits finalize the
+    // next header; unknown before the first header of a chain started without a genesis file
+    validators: Option<HashMap<NodeId, u64>>,
+    // Parameters of the state after the verified head, which set the quorum of those validators
+    params: ConsensusParams,
+}
+
+impl LightClient {
+    pub fn new(zk_generator: ZKProofGenerator, node_id: NodeId) -> Self {
+        info!("🪶 Initializing light client");
+
+        let (message_tx, inbox) = consensus::inbox_channel();
+
+        Self {
+            zk_generator,
+            node_id,
+            message_tx,
+            inbox,
+            network_tx: None,
+            head: Arc::new(RwLock::new(None)),
+            pending_request: None,
+            genesis_hash: [0; 32],
+            checkpoint: None,
+            validators: None,
+            params: ConsensusParams::default(),
+        }
+    }
+
+    pub fn get_message_sender(&self) -> ConsensusSender {
+        self.message_tx.clone()
+    }
+
+    pub fn set_network_sender(&mut self, network_tx: mpsc::Sender<ConsensusMessage>) {
+        self.network_tx = Some(network_tx);
+    }
+
+    pub fn set_genesis(&mut self, genesis_hash: BlockHash, validators: Vec<(NodeId, u64)>, params: ConsensusParams) {
+        self.genesis_hash = genesis_hash;
+        self.validators = Some(validators.into_iter().collect());
+        self.params = params;
+    }
+
+    // The checkpoint header has to come with the validator set its hash names, which is
+    // followed from there
+    pub fn set_checkpoint(&mut self, checkpoint: TrustedCheckpoint) {
+        self.checkpoint = Some(checkpoint);
+    }
+
+    fn first_block(&self) -> u64 {
+        self.checkpoint.map_or(1, |checkpoint| checkpoint.height)
+    }
+
+    // Shared with the RPC server
+    pub fn head_handle(&self) -> Arc<RwLock<Option<VerifiedHead>>> {
+        self.head.clone()
+    }
+
+    pub async fn start(&mut self, mut shutdown: ShutdownSignal) -> Result<()> {
+        info!("🚀 Starting light client sync");
+        let mut sync_interval = tokio::time::interval(SYNC_INTERVAL);
+
+        loop {
+            tokio::select! {
+                message = self.inbox.recv() => {
+                    // Blocks, votes and everything else gossiped on the topic are ignored
+                    if let Some(ConsensusMessage::HeaderResponse(response)) = message {
+                        self.handle_header_response(response).await?;
+                    }
+                }
+                _ = sync_interval.tick() => {
+                    self.request_headers().await;
+                }
+                _ = shutdown.recv() => {
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    async fn request_headers(&mut self) {
+        let from_block = self.head.read().await
+            .as_ref()
+            .map_or(self.first_block(), |head| head.block.header.block_number + 1);
+        let request_id = rand::random();
+        self.pending_request = Some(request_id);
+
+        debug!("Requesting headers from block {}", from_block);
+        let request = HeaderRequest { from_block, request_id, requester: self.node_id };
+        self.send_to_network(ConsensusMessage::HeaderRequest(request)).await;
+    }
+
+    async fn handle_header_response(&mut self, response: HeaderResponse) -> Result<()> {
+        // Responses to other light clients, or from slower peers to an older request
+        if self.pending_request != Some(response.request_id) {
+            return Ok(());
+        }
+
+        let mut head = self.head.write().await;
+        let mut verified = 0;
+
+        for certified in response.headers {
+            let expected = head.as_ref().map_or(self.first_block(), |head| head.block.header.block_number + 1);
+            if certified.block.header.block_number < expected {
+                continue;
+            }
+
+            if !self.verify_header(head.as_ref(), &certified).await? {
+                warn!("🚫 Rejected headers from {} at block #{}",
+                    hex::encode(response.responder), certified.block.header.block_number);
+                break;
+            }
+            if let Some(validators) = certified.validators {
+                self.validators = Some(validators.into_iter().collect());
+            }
+            if let Some(params) = certified.params {
+                self.params = params;
+            }
+
+            let block = certified.block;
+            let verified_headers = head.as_ref().map_or(0, |head| head.verified_headers) + 1;
+            *head = Some(VerifiedHead {
+                hash: block.header.hash(),
+                block,
+                verified_headers,
+                updated_at: Utc::now(),
+            });
+            verified += 1;
+        }
+
+        if let Some(head) = head.as_ref().filter(|_| verified > 0) {
+            info!("🪶 Verified head at #{} ({} new headers)", head.block.header.block_number, verified);
+        }
+        drop(head);
+
+        // Keep asking while peers have newer headers
+        if verified > 0 {
+            self.request_headers().await;
+        }
+
+        Ok(())
+    }
+
+    // Without transactions the proposer can not be checked against the election; the
+    // signature, parent link, finality certificate and proof still have to hold
+    async fn verify_header(&self, parent: Option<&VerifiedHead>, certified: &CertifiedHeader) -> Result<bool> {
+        let block = &certified.block;
+        let header = &block.header;
+
+        // The trusted checkpoint stands in for every header before it
+        if let (None, Some(checkpoint)) = (parent, &self.checkpoint) {
+            if header.block_number != checkpoint.height || header.hash() != checkpoint.block_hash {
+                warn!("🧭 Header #{} is not the trusted checkpoint", header.block_number);
+                return Ok(false);
+            }
+            match &certified.validators {
+                Some(validators) if types::hash_validator_set(validators.clone()) == checkpoint.validator_set_hash => {}
+                _ => {
+                    warn!("🧭 Checkpoint header #{} does not come with the trusted validator set", header.block_number);
+                    return Ok(false);
+                }
+            }
+            // The checkpoint does not name them, but no valid ones go below the two thirds we assume
+            return Ok(certified.params.is_none_or(|params| params.validate().is_ok()));
+        }
+        let (parent_number, parent_hash) = parent
+            .map_or((0, self.genesis_hash), |parent| (parent.block.header.block_number, parent.hash));
+
+        if header.block_number != parent_number + 1 || header.parent_hash != parent_hash {
+            warn!("🔗 Header #{} does not extend verified head #{}", header.block_number, parent_number);
+            return Ok(false);
+        }
+
+        if let Some(parent) = parent {
+            if header.timestamp <= parent.block.header.timestamp {
+                warn!("⏰ Header #{} is not later than its parent", header.block_number);
+                return Ok(false);
+            }
+        }
+
+        if !crypto::verify_signature(&header.validator, &block.signing_hash(), &block.signature) {
+            warn!("🚫 Header #{} has an invalid proposer signature", header.block_number);
+            return Ok(false);
+        }
+
+        if !self.verify_certificate(certified) {
+            return Ok(false);
+        }
+
+        // Headers the chain's proof policy does not prove name the last checkpoint instead, which
+        // proves nothing about their state, so they are not followed
+        if let Some((proven, _)) = block.zk_proof.checkpoint_reference() {
+            warn!("🔖 Header #{} carries no proof of its own, only a reference to checkpoint #{}", header.block_number, proven);
+            return Ok(false);
+        }
+
+        if !self.zk_generator.can_verify(&block.zk_proof.proof_type) {
+            warn!("🔐 Header #{} carries a {:?} proof this build can not verify", header.block_number, block.zk_proof.proof_type);
+            return Ok(false);
+        }
+
+        let parent_state_root = parent.map(|parent| &parent.block.header.state_root);
+        Ok(self.zk_generator.verify_header_proof(header, parent_state_root, &block.zk_proof).await?)
+    }
+
+    // Precommits must carry a quorum of the stake of the validators we follow, under the
+    // parameters we follow, and, when the header brings a new set and parameters, of that set
+    // under those too, which finalize the headers after it. A change replacing a third or more of
+    // the stake at once can only be followed from a newer checkpoint
+    fn verify_certificate(&self, certified: &CertifiedHeader) -> bool {
+        let header = &certified.block.header;
+        let certificate = &certified.certificate;
+        if certificate.block_hash != header.hash() || certificate.block_number != header.block_number {
+            warn!("📜 Certificate does not finalize header #{}", header.block_number);
+            return false;
+        }
+
+        let signers: HashMap<NodeId, u64> = match (&certified.validators, &self.validators) {
+            (Some(validators), _) => validators.iter().copied().collect(),
+            (None, Some(tracked)) => tracked.clone(),
+            (None, None) => {
+                warn!("👥 Header #{} does not come with the validators that finalized it", header.block_number);
+                return false;
+            }
+        };
+        let params = match certified.params {
+            Some(params) if params.validate().is_err() => {
+                warn!("👥 Header #{} comes with invalid consensus parameters", header.block_number);
+                return false;
+            }
+            Some(params) => params,
+            None => self.params,
+        };
+        let total = signers.values().sum();
+        if let Err(e) = consensus::verify_precommits(certificate, |node_id| signers.get(node_id).copied(), total, &params) {
+            warn!("📜 Invalid finality certificate for header #{}: {}", header.block_number, e);
+            return false;
+        }
+
+        let tracked = match &self.validators {
+            Some(tracked) => tracked,
+            // Chains started without a genesis file bond the proposer of block 1 as their only validator
+            None => {
+                let bootstraps = header.block_number == 1 && header.parent_hash == [0; 32];
+                return bootstraps && signers.len() == 1 && signers.contains_key(&header.validator);
+            }
+        };
+        let weight = certificate.precommits.iter()
+            .filter_map(|vote| tracked.get(&vote.validator))
+            .sum();
+        if !self.params.has_quorum(weight, tracked.values().sum()) {
+            warn!("👥 Precommits for header #{} carry {} of the {} stake of the validators we follow",
+                header.block_number, weight, tracked.values().sum::<u64>());
+            return false;
+        }
+        true
+    }
+
+    async fn send_to_network(&self, message: ConsensusMessage) {
+        if let Some(network_tx) = &self.network_tx {
+            if let Err(e) = network_tx.send(message).await {
+                error!("Failed to hand message to network manager: {}", e);
+            }
+        }
+    }
+}
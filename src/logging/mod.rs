@@ -0,0 +1,70 @@
+use crate::config::LogConfig;
+use anyhow::{anyhow, Result};
+use tracing::Subscriber;
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::{
+    fmt::{self, MakeWriter},
+    prelude::*,
+    registry::LookupSpan,
+    reload, EnvFilter, Layer, Registry,
+};
+
+pub const LOG_FORMATS: [&str; 2] = ["text", "json"];
+pub const LOG_ROTATIONS: [&str; 3] = ["hourly", "daily", "never"];
+// Rotated files are named zk_consensus.log.<date>
+const LOG_FILE_PREFIX: &str = "zk_consensus.log";
+
+// Replaces the filter of the running node
+pub type LogFilterHandle = reload::Handle<EnvFilter, Registry>;
+
+// Held by main for as long as the node runs; dropping it flushes the log file
+pub struct Logging {
+    pub filter: LogFilterHandle,
+    _file_writer: Option<WorkerGuard>,
+}
+
+// Logs to stdout, and to rotated files in log.dir when it is set. The filter applies to both
+// and can be swapped at runtime through the handle
+pub fn init(config: &LogConfig) -> Result<Logging> {
+    let (filter, filter_handle) = reload::Layer::new(parse_filter(&config.filter)?);
+
+    let (file_layer, file_writer) = match &config.dir {
+        Some(dir) => {
+            let rotation = match config.rotation.as_str() {
+                "hourly" => Rotation::HOURLY,
+                "never" => Rotation::NEVER,
+                _ => Rotation::DAILY,
+            };
+            // Writes happen on a background thread so a slow disk does not stall the node
+            let (writer, guard) = tracing_appender::non_blocking(RollingFileAppender::new(rotation, dir, LOG_FILE_PREFIX));
+            (Some(output_layer(&config.format, writer, false)), Some(guard))
+        }
+        None => (None, None),
+    };
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(output_layer(&config.format, std::io::stdout, true))
+        .with(file_layer)
+        .try_init()?;
+
+    Ok(Logging { filter: filter_handle, _file_writer: file_writer })
+}
+
+// Accepts a plain level too, which applies to every target
+pub fn parse_filter(directives: &str) -> Result<EnvFilter> {
+    EnvFilter::try_new(directives).map_err(|e| anyhow!("Invalid log filter '{}': {}", directives, e))
+}
+
+fn output_layer<S, W>(format: &str, writer: W, ansi: bool) -> Box<dyn Layer<S> + Send + Sync>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    let layer = fmt::layer().with_writer(writer).with_ansi(ansi);
+    match format {
+        "json" => layer.json().boxed(),
+        _ => layer.boxed(),
+    }
+}
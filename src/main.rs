@@ -1,124 +1,920 @@
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use tracing::{info, warn};
-use tracing_subscriber;
-use std::sync::Arc;
-use tokio::sync::Mutex;
-
-mod consensus;
-mod zk_proof;
-mod network;
-mod storage;
-mod types;
-
-use consensus::ConsensusEngine;
-use zk_proof::ZKProofGenerator;
-use network::NetworkManager;
-use storage::StorageManager;
-use types::Transaction;
+use zk_consensus::crypto::{Keystore, NodeKeypair};
+use zk_consensus::genesis::Genesis;
+use zk_consensus::{admin, bench, bridge, config, conformance, crypto, logging, node, rpc, snapshot, staking, state, storage, types, wallet};
+use zk_consensus::{Node, NodeConfig, StorageManager};
+#[cfg(feature = "testing")]
+use zk_consensus::testing;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
-    /// Node mode: validator, full_node, light_client
-    #[arg(short, long, default_value = "validator")]
-    mode: String,
+    #[command(subcommand)]
+    command: Option<Command>,
+    
+    /// TOML configuration file; the flags below override its values
+    #[arg(short, long)]
+    config: Option<std::path::PathBuf>,
+    
+    /// Run a development chain with this node as its only validator, sealing a block as soon
+    /// as transactions arrive; mock proofs, no peers, and zk_consensus-dev.db and dev_node_key
+    /// unless --db-path and --key-file are given
+    #[arg(long)]
+    dev: bool,
     
-    /// Network port
-    #[arg(short, long, default_value_t = 8080)]
-    port: u16,
+    /// With --dev, also seal an empty block after this many seconds without one, 0 never does
+    /// (replaces consensus.seal_interval) [default: 0]
+    #[arg(long)]
+    seal_interval: Option<u64>,
+    
+    /// Node mode: validator, full_node, light_client [default: validator]
+    #[arg(short, long)]
+    mode: Option<String>,
+    
+    /// Network port [default: 8080]
+    #[arg(short, long)]
+    port: Option<u16>,
     
-    /// Bootstrap nodes
+    /// Bootstrap nodes (replace network.bootstrap_nodes)
     #[arg(short, long)]
     bootstrap: Vec<String>,
     
-    /// Enable debug logging
+    /// Addresses peers reach this node at from outside, e.g. a forwarded port (replace
+    /// network.external_addresses)
+    #[arg(long)]
+    external_address: Vec<String>,
+    
+    /// Enable debug logging (replaces log.filter with debug)
     #[arg(short, long)]
     debug: bool,
-}
-
-async fn create_test_transactions(storage: &StorageManager) -> Result<(), Box<dyn std::error::Error>> {
-    info!("💰 Creating test transactions");
-    
-    for i in 0..5 {
-        let tx = Transaction {
-            id: [i; 32],
-            from: [(i + 1) as u8; 32],
-            to: [(i + 2) as u8; 32],
-            amount: (i + 1) as u64 * 100,
-            timestamp: chrono::Utc::now(),
-            signature: vec![0u8; 64],
-        };
-        
-        storage.store_transaction(&tx).await?;
-        info!("📝 Created transaction #{}: {} tokens", i, tx.amount);
-    }
     
-    Ok(())
-}
-
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let args = Args::parse();
+    /// Log filter directives, e.g. info,zk_consensus::network=debug (replaces log.filter and RUST_LOG)
+    #[arg(long)]
+    log_filter: Option<String>,
     
-    // Initialize logging
-    let log_level = if args.debug {
-        tracing::Level::DEBUG
-    } else {
-        tracing::Level::INFO
-    };
+    /// Log format: text or json [default: text]
+    #[arg(long)]
+    log_format: Option<String>,
+    
+    /// Also write the log to daily rotated files in this directory (replaces log.dir)
+    #[arg(long)]
+    log_dir: Option<std::path::PathBuf>,
+    
+    /// RocksDB directory [default: zk_consensus.db]
+    #[arg(long)]
+    db_path: Option<String>,
+    
+    /// Snapshot mirrors: http(s)://host/path or ipfs://api-host:port[/ipns-name] (replace snapshot.mirrors)
+    #[arg(long)]
+    snapshot_mirror: Vec<String>,
+    
+    /// Publish a snapshot to the mirrors every N blocks, 0 disables publishing [default: 0]
+    #[arg(long)]
+    snapshot_interval: Option<u64>,
+    
+    /// Start an empty database from a snapshot file written by `snapshot create`
+    #[arg(long)]
+    restore_snapshot: Option<std::path::PathBuf>,
+    
+    /// genesis.json of the chain to join (replaces genesis)
+    #[arg(long)]
+    genesis: Option<std::path::PathBuf>,
     
-    tracing_subscriber::fmt()
-        .with_max_level(log_level)
-        .init();
+    /// Trusted checkpoint to sync from, height:block_hash:validator_set_hash (replaces checkpoint)
+    #[arg(long)]
+    checkpoint: Option<String>,
     
-    info!("🚀 Starting ZK-PoV Consensus Node");
-    info!("📋 Mode: {}", args.mode);
-    info!("🌐 Port: {}", args.port);
-    info!("🔗 Bootstrap nodes: {:?}", args.bootstrap);
+    /// Path of the node's Ed25519 key file, generated on first start [default: node_key]
+    #[arg(long)]
+    key_file: Option<std::path::PathBuf>,
     
-    // Initialize components
-    let storage = StorageManager::new("zk_consensus.db")?;
-    let zk_generator = ZKProofGenerator::new()?;
+    /// Encrypted keystore holding the validator key, used instead of --key-file; the password
+    /// is read from ZK_KEYSTORE_PASSWORD or asked for on start
+    #[arg(long)]
+    keystore: Option<std::path::PathBuf>,
     
-    // Create test transactions
-    create_test_transactions(&storage).await?;
+    /// NTP servers used for clock skew monitoring (replace clock.ntp_servers) [default: pool.ntp.org]
+    #[arg(long)]
+    ntp_server: Vec<String>,
     
-    let consensus = ConsensusEngine::new(zk_generator, storage)?;
-    let consensus = Arc::new(Mutex::new(consensus));
+    /// Clock skew (in milliseconds) above which health checks report a warning [default: 500]
+    #[arg(long)]
+    max_clock_skew_ms: Option<i64>,
     
-    info!("✅ All components initialized successfully");
+    /// Stake to bond when running as a validator [default: 1000]
+    #[arg(long)]
+    stake: Option<u64>,
     
-    // Clone for network manager
-    let consensus_for_network = consensus.clone();
+    /// JSON-RPC port (listens on localhost only) [default: 9933]
+    #[arg(long)]
+    rpc_port: Option<u16>,
     
-    // Start consensus and network in parallel
-    tokio::select! {
-        result = async {
-            let mut c = consensus.lock().await;
-            c.start().await
-        } => {
-            if let Err(e) = result {
-                warn!("❌ Consensus engine stopped with error: {}", e);
+    /// Explorer REST API port (listens on localhost only), 0 disables it [default: 9934]
+    #[arg(long)]
+    explorer_port: Option<u16>,
+    
+    /// gRPC API port (listens on localhost only, needs the grpc feature), 0 disables it [default: 9935]
+    #[arg(long)]
+    grpc_port: Option<u16>,
+    
+    /// Misbehave as a validator, for testing consensus: equivocate, withhold-votes, invalid-proofs
+    /// or delay-messages
+    #[cfg(feature = "testing")]
+    #[arg(long)]
+    byzantine: Option<String>,
+}
+
+impl Args {
+    // Flags given on the command line win over the config file
+    fn apply_to(&self, config: &mut NodeConfig) {
+        if self.dev {
+            config.dev();
+        }
+        if let Some(interval) = self.seal_interval {
+            config.consensus.seal_interval = interval;
+        }
+        if let Some(mode) = &self.mode {
+            config.mode = mode.clone();
+        }
+        if let Some(port) = self.port {
+            config.network.port = port;
+        }
+        if !self.bootstrap.is_empty() {
+            config.network.bootstrap_nodes = self.bootstrap.clone();
+        }
+        if !self.external_address.is_empty() {
+            config.network.external_addresses = self.external_address.clone();
+        }
+        if let Some(rpc_port) = self.rpc_port {
+            config.network.rpc_port = rpc_port;
+        }
+        if let Some(explorer_port) = self.explorer_port {
+            config.network.explorer_port = explorer_port;
+        }
+        if let Some(grpc_port) = self.grpc_port {
+            config.network.grpc_port = grpc_port;
+        }
+        if let Some(db_path) = &self.db_path {
+            config.storage.db_path = db_path.clone();
+        }
+        if !self.snapshot_mirror.is_empty() {
+            config.snapshot.mirrors = self.snapshot_mirror.clone();
+        }
+        if let Some(interval) = self.snapshot_interval {
+            config.snapshot.interval = interval;
+        }
+        if let Some(genesis) = &self.genesis {
+            config.genesis = Some(genesis.clone());
+        }
+        if let Some(checkpoint) = &self.checkpoint {
+            config.checkpoint = Some(checkpoint.clone());
+        }
+        if let Some(key_file) = &self.key_file {
+            config.key_file = key_file.clone();
+        }
+        if let Some(keystore) = &self.keystore {
+            config.keystore = Some(keystore.clone());
+        }
+        if !self.ntp_server.is_empty() {
+            config.clock.ntp_servers = self.ntp_server.clone();
+        }
+        if let Some(max_skew_ms) = self.max_clock_skew_ms {
+            config.clock.max_skew_ms = max_skew_ms;
+        }
+        if let Some(stake) = self.stake {
+            config.consensus.stake = stake;
+        }
+        if self.debug {
+            config.log.filter = "debug".to_string();
+        }
+        if let Some(filter) = &self.log_filter {
+            config.log.filter = filter.clone();
+        }
+        if let Some(format) = &self.log_format {
+            config.log.format = format.clone();
+        }
+        if let Some(dir) = &self.log_dir {
+            config.log.dir = Some(dir.clone());
+        }
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Generate or check cross-implementation conformance test vectors
+    Conformance {
+        #[command(subcommand)]
+        action: ConformanceAction,
+    },
+    /// Create genesis files for new chains
+    Genesis {
+        #[command(subcommand)]
+        action: GenesisAction,
+    },
+    /// Manage the validator key in an encrypted keystore
+    Keys {
+        #[command(subcommand)]
+        action: KeysAction,
+    },
+    /// Follow addresses without their keys, and sign transfers offline for any node to submit
+    Wallet {
+        #[command(subcommand)]
+        action: WalletAction,
+    },
+    /// Export finalized blocks as proofs for verifiers on other chains, and import such proofs
+    /// of another chain
+    Bridge {
+        #[command(subcommand)]
+        action: BridgeAction,
+    },
+    /// Write a genesis file for a new chain that starts from the active validators and balances
+    /// at a finalized block of a stopped node, e.g. for forks, testnet resets or test fixtures
+    BuildSpec {
+        #[arg(short, long, default_value = "genesis.json")]
+        out: std::path::PathBuf,
+        /// Chain id of the new chain; nonces start over, so reusing the old one would let the
+        /// old chain's transactions be replayed
+        #[arg(long)]
+        chain_id: u64,
+        /// Finalized block to start from, as long as it is not pruned [default: the finalized block]
+        #[arg(long)]
+        height: Option<u64>,
+        #[arg(long, default_value = "zk_consensus.db")]
+        db_path: String,
+        /// Take the consensus parameters from this genesis file, such as the old chain's;
+        /// the defaults are used otherwise. Those validators vote on, such as the block time,
+        /// are taken from the chain at the height
+        #[arg(long)]
+        genesis: Option<std::path::PathBuf>,
+    },
+    /// Create snapshot files for fast-syncing new nodes
+    Snapshot {
+        #[command(subcommand)]
+        action: SnapshotAction,
+    },
+    /// Measure proof generation and verification on synthetic blocks for each proof backend
+    /// compiled into this build
+    Bench {
+        /// mock, groth16, plonk or nova (chain proofs); repeat for several [default: all compiled in]
+        #[arg(long)]
+        backend: Vec<String>,
+        /// Transactions per synthetic block, comma separated
+        #[arg(long, value_delimiter = ',', default_value = "1,10,100")]
+        txs: Vec<usize>,
+        /// Blocks proven and verified per backend and block size
+        #[arg(long, default_value_t = 5)]
+        iterations: usize,
+        /// Also write the results as JSON, e.g. for tracking them in CI
+        #[arg(long)]
+        json: Option<std::path::PathBuf>,
+    },
+    /// Back up the finalized chain of a stopped node into a directory; after the first backup
+    /// only the blocks and accounts changed since the last one are archived
+    Backup {
+        #[arg(long, default_value = "backup")]
+        dir: std::path::PathBuf,
+        #[arg(long, default_value = "zk_consensus.db")]
+        db_path: String,
+        /// Archive everything again instead of what changed since the last backup
+        #[arg(long)]
+        full: bool,
+    },
+    /// Restore a backup directory into a new database
+    Restore {
+        #[arg(long, default_value = "backup")]
+        dir: std::path::PathBuf,
+        #[arg(long, default_value = "zk_consensus.db")]
+        db_path: String,
+    },
+    /// Check the archived files of a backup against the hashes in its manifest, and that its
+    /// blocks and states follow one another
+    VerifyBackup {
+        #[arg(long, default_value = "backup")]
+        dir: std::path::PathBuf,
+    },
+    /// Rebuild the database of a stopped node by replaying its blocks from genesis through
+    /// validation and execution, e.g. after index format changes or suspected corruption. Reads
+    /// the config file and flags such as --db-path and --genesis like the node; the old
+    /// database is kept as <db_path>.old
+    Reindex,
+    /// Write the proving and verifying keys of this build's Groth16 setup into a directory, for
+    /// nodes started with zk_proof.key_dir; verifiers only need the .vk files. Reads the config
+    /// file and --genesis like the node, and refuses chains Groth16 can not prove
+    ExportKeys {
+        #[arg(long, default_value = "circuit_keys")]
+        dir: std::path::PathBuf,
+    },
+    /// Print the height, peers, mempool and proof backlog of a running node
+    Status {
+        /// JSON-RPC endpoint of the node
+        #[arg(long, default_value = "http://127.0.0.1:9933")]
+        rpc: String,
+    },
+    /// Interactive console for inspecting and steering a running node; most commands need
+    /// network.admin enabled on the node
+    Console {
+        /// JSON-RPC endpoint of the node
+        #[arg(long, default_value = "http://127.0.0.1:9933")]
+        rpc: String,
+    },
+    /// Run validators in one process on a seeded scheduler and check that no two of them
+    /// finalize different blocks at the same height; a failing seed replays the same run
+    #[cfg(feature = "testing")]
+    Simulate {
+        /// Seed of the first run; every further run takes the next one
+        #[arg(long, default_value_t = 0)]
+        seed: u64,
+        #[arg(long, default_value_t = 1)]
+        runs: u64,
+        #[arg(long, default_value_t = 4)]
+        nodes: usize,
+        /// Virtual seconds each run lasts
+        #[arg(long, default_value_t = 300)]
+        duration: u64,
+        /// Messages are delivered after a random delay up to this, which reorders them
+        #[arg(long, default_value_t = 2000)]
+        max_delay_ms: u64,
+        /// Chance of losing each copy of a message, below 1
+        #[arg(long, default_value_t = 0.0)]
+        drop_rate: f64,
+        /// Byzantine behavior of the first validator: equivocate, withhold-votes or invalid-proofs
+        #[arg(long)]
+        byzantine: Option<String>,
+        /// Directory for the databases of the simulated nodes
+        #[arg(long, default_value = "simulation")]
+        db_dir: std::path::PathBuf,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum GenesisAction {
+    /// Write a genesis file with this node as the only validator, holding the whole supply
+    Init {
+        #[arg(short, long, default_value = "genesis.json")]
+        out: std::path::PathBuf,
+        #[arg(long, default_value_t = types::DEFAULT_CHAIN_ID)]
+        chain_id: u64,
+        #[arg(long, default_value_t = staking::MIN_VALIDATOR_STAKE)]
+        stake: u64,
+        #[arg(long, default_value = "node_key")]
+        key_file: std::path::PathBuf,
+        /// Take the validator from an encrypted keystore instead of the key file
+        #[arg(long)]
+        keystore: Option<std::path::PathBuf>,
+    },
+}
+
+// Passwords come from ZK_KEYSTORE_PASSWORD when it is set, otherwise they are asked for
+#[derive(Subcommand, Debug)]
+enum KeysAction {
+    /// Generate a new validator key into a keystore
+    Generate {
+        #[arg(long, default_value = "keystore.json")]
+        keystore: std::path::PathBuf,
+        /// Replace an existing keystore
+        #[arg(long)]
+        force: bool,
+    },
+    /// Encrypt a plain key file, such as an existing node_key, into a keystore
+    Import {
+        #[arg(long, default_value = "node_key")]
+        key_file: std::path::PathBuf,
+        #[arg(long, default_value = "keystore.json")]
+        keystore: std::path::PathBuf,
+        /// Replace an existing keystore
+        #[arg(long)]
+        force: bool,
+    },
+    /// Decrypt the keystore into a plain key file
+    Export {
+        #[arg(long, default_value = "keystore.json")]
+        keystore: std::path::PathBuf,
+        #[arg(short, long)]
+        out: std::path::PathBuf,
+    },
+    /// Print the node id of the key in a keystore
+    Show {
+        #[arg(long, default_value = "keystore.json")]
+        keystore: std::path::PathBuf,
+    },
+}
+
+// Balances and history come from --rpc, or from --db-path of a stopped node when given
+#[derive(Subcommand, Debug)]
+enum WalletAction {
+    /// Print the address of a key file or keystore
+    Address {
+        #[arg(long, default_value = "node_key")]
+        key_file: std::path::PathBuf,
+        /// Take the key from an encrypted keystore instead of the key file
+        #[arg(long)]
+        keystore: Option<std::path::PathBuf>,
+    },
+    /// Add an address to the wallet file
+    Watch {
+        address: String,
+        #[arg(long)]
+        label: Option<String>,
+        #[arg(long, default_value = "wallet.json")]
+        wallet: std::path::PathBuf,
+    },
+    /// Remove an address from the wallet file
+    Unwatch {
+        address: String,
+        #[arg(long, default_value = "wallet.json")]
+        wallet: std::path::PathBuf,
+    },
+    /// Print the balance and next nonce of an address, or of every watched one
+    Balance {
+        address: Option<String>,
+        #[arg(long, default_value = "wallet.json")]
+        wallet: std::path::PathBuf,
+        /// Balance and nonce as of this finalized block instead
+        #[arg(long)]
+        height: Option<u64>,
+        #[arg(long, default_value = "http://127.0.0.1:9933")]
+        rpc: String,
+        #[arg(long)]
+        db_path: Option<String>,
+    },
+    /// Print the finalized transactions of an address, newest first
+    History {
+        address: String,
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+        /// Cursor printed below the previous page
+        #[arg(long)]
+        cursor: Option<u64>,
+        #[arg(long, default_value = "http://127.0.0.1:9933")]
+        rpc: String,
+        #[arg(long)]
+        db_path: Option<String>,
+    },
+    /// Build and sign a transfer without contacting a node; `wallet balance` prints the nonce
+    Sign {
+        #[arg(long)]
+        to: String,
+        #[arg(long)]
+        amount: u64,
+        #[arg(long, default_value_t = 1)]
+        fee: u64,
+        #[arg(long)]
+        nonce: u64,
+        #[arg(long, default_value_t = types::DEFAULT_CHAIN_ID)]
+        chain_id: u64,
+        /// Last block number that may include the transfer
+        #[arg(long)]
+        valid_until_height: Option<u64>,
+        #[arg(long, default_value = "node_key")]
+        key_file: std::path::PathBuf,
+        /// Sign with the key of an encrypted keystore instead of the key file
+        #[arg(long)]
+        keystore: Option<std::path::PathBuf>,
+        #[arg(short, long, default_value = "transaction.json")]
+        out: std::path::PathBuf,
+    },
+    /// Sign a validator's governance proposal: new consensus parameters, or with --remove-validator
+    /// a validator to take out of the set. Proposals replace every parameter, so give the ones to
+    /// keep as `consensus_getParams` prints them
+    SignProposal {
+        #[arg(long, conflicts_with_all = ["block_time", "min_validators", "max_validators", "quorum_numerator", "quorum_denominator"])]
+        remove_validator: Option<String>,
+        #[arg(long, default_value_t = types::ConsensusParams::default().block_time)]
+        block_time: u64,
+        #[arg(long, default_value_t = types::ConsensusParams::default().min_validators)]
+        min_validators: u64,
+        #[arg(long, default_value_t = types::ConsensusParams::default().max_validators)]
+        max_validators: u64,
+        #[arg(long, default_value_t = types::ConsensusParams::default().quorum_numerator)]
+        quorum_numerator: u64,
+        #[arg(long, default_value_t = types::ConsensusParams::default().quorum_denominator)]
+        quorum_denominator: u64,
+        #[arg(long)]
+        nonce: u64,
+        #[arg(long, default_value_t = types::DEFAULT_CHAIN_ID)]
+        chain_id: u64,
+        #[arg(long, default_value = "node_key")]
+        key_file: std::path::PathBuf,
+        /// Sign with the key of an encrypted keystore instead of the key file
+        #[arg(long)]
+        keystore: Option<std::path::PathBuf>,
+        #[arg(short, long, default_value = "transaction.json")]
+        out: std::path::PathBuf,
+    },
+    /// Sign a validator's vote on an open proposal, listed by `governance_getProposals`
+    SignVote {
+        #[arg(long)]
+        proposal: String,
+        /// Vote against the proposal instead
+        #[arg(long)]
+        reject: bool,
+        #[arg(long)]
+        nonce: u64,
+        #[arg(long, default_value_t = types::DEFAULT_CHAIN_ID)]
+        chain_id: u64,
+        #[arg(long, default_value = "node_key")]
+        key_file: std::path::PathBuf,
+        /// Sign with the key of an encrypted keystore instead of the key file
+        #[arg(long)]
+        keystore: Option<std::path::PathBuf>,
+        #[arg(short, long, default_value = "transaction.json")]
+        out: std::path::PathBuf,
+    },
+    /// Submit a transaction signed by `wallet sign`, `wallet sign-proposal` or `wallet sign-vote`
+    Submit {
+        #[arg(long, default_value = "transaction.json")]
+        tx: std::path::PathBuf,
+        #[arg(long, default_value = "http://127.0.0.1:9933")]
+        rpc: String,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum BridgeAction {
+    /// Fetch the bridge proof of a finalized block from a running node
+    Export {
+        /// A block with a ZK proof of its own [default: the finalized block]
+        #[arg(long)]
+        height: Option<u64>,
+        #[arg(short, long, default_value = "bridge-proof.bin")]
+        out: std::path::PathBuf,
+        #[arg(long, default_value = "http://127.0.0.1:9933")]
+        rpc: String,
+    },
+    /// Import bridge proofs of another chain in order, starting from a trusted checkpoint of it.
+    /// Reads the config file and --genesis like the node, for the chain's hash functions and
+    /// verifying keys
+    Verify {
+        /// height:block_hash:validator_set_hash, from chain_getCheckpoint on the other chain
+        #[arg(long)]
+        checkpoint: String,
+        #[arg(required = true)]
+        proofs: Vec<std::path::PathBuf>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum SnapshotAction {
+    /// Write the finalized state of a stopped node to a compressed snapshot file
+    Create {
+        #[arg(short, long)]
+        out: std::path::PathBuf,
+        /// Must be the finalized height, which is the default
+        #[arg(long)]
+        height: Option<u64>,
+        #[arg(long, default_value = "zk_consensus.db")]
+        db_path: String,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum ConformanceAction {
+    /// Write canonical test vectors to a directory
+    Generate {
+        #[arg(short, long, default_value = "conformance-vectors")]
+        out: std::path::PathBuf,
+    },
+    /// Validate a directory of test vectors against this implementation
+    Check {
+        #[arg(short, long, default_value = "conformance-vectors")]
+        dir: std::path::PathBuf,
+    },
+}
+
+async fn run_command(command: Command, config: NodeConfig) -> Result<(), Box<dyn std::error::Error>> {
+    match command {
+        Command::Conformance { action: ConformanceAction::Generate { out } } => {
+            conformance::generate(&out).await?;
+        }
+        Command::Conformance { action: ConformanceAction::Check { dir } } => {
+            let report = conformance::check(&dir).await?;
+            if !report.is_success() {
+                return Err(format!("{} conformance checks failed", report.failed.len()).into());
+            }
+        }
+        Command::Genesis { action: GenesisAction::Init { out, chain_id, stake, key_file, keystore } } => {
+            let validator = match keystore {
+                Some(keystore) => Keystore::load(&keystore)?.node_id()?,
+                None => NodeKeypair::load_or_generate(&key_file)?.node_id(),
+            };
+            let genesis = Genesis::new(chain_id, validator, stake, state::GENESIS_SUPPLY);
+            genesis.write_to(&out)?;
+            info!("🌱 Wrote genesis {} for chain {} to {}", hex::encode(genesis.hash()), chain_id, out.display());
+        }
+        Command::Keys { action: KeysAction::Generate { keystore, force } } => {
+            if keystore.exists() && !force {
+                return Err(format!("{} already exists, pass --force to replace it", keystore.display()).into());
+            }
+            let keypair = NodeKeypair::generate();
+            Keystore::encrypt(&keypair, &crypto::keystore_password(true)?)?.save(&keystore)?;
+            info!("🔑 Generated validator key {} in {}", hex::encode(keypair.node_id()), keystore.display());
+        }
+        Command::Keys { action: KeysAction::Import { key_file, keystore, force } } => {
+            if keystore.exists() && !force {
+                return Err(format!("{} already exists, pass --force to replace it", keystore.display()).into());
+            }
+            let keypair = NodeKeypair::load(&key_file)?;
+            Keystore::encrypt(&keypair, &crypto::keystore_password(true)?)?.save(&keystore)?;
+            info!("🔑 Imported validator key {} into {}", hex::encode(keypair.node_id()), keystore.display());
+        }
+        Command::Keys { action: KeysAction::Export { keystore, out } } => {
+            let keypair = Keystore::load(&keystore)?.decrypt(&crypto::keystore_password(false)?)?;
+            keypair.save(&out)?;
+            warn!("🔑 Wrote the unencrypted key {} to {}", hex::encode(keypair.node_id()), out.display());
+        }
+        Command::Keys { action: KeysAction::Show { keystore } } => {
+            println!("{}", hex::encode(Keystore::load(&keystore)?.node_id()?));
+        }
+        Command::Wallet { action: WalletAction::Address { key_file, keystore } } => {
+            let address = match keystore {
+                Some(keystore) => Keystore::load(&keystore)?.node_id()?,
+                None => wallet::address(&NodeKeypair::load(&key_file)?),
+            };
+            println!("{}", hex::encode(address));
+        }
+        Command::Wallet { action: WalletAction::Watch { address, label, wallet: path } } => {
+            let mut watched = wallet::Wallet::load(&path)?;
+            if watched.watch(&wallet::parse_address(&address)?, label) {
+                info!("👛 Watching {} in {}", address, path.display());
+            }
+            watched.save(&path)?;
+        }
+        Command::Wallet { action: WalletAction::Unwatch { address, wallet: path } } => {
+            let mut watched = wallet::Wallet::load(&path)?;
+            if !watched.unwatch(&wallet::parse_address(&address)?) {
+                return Err(format!("{} is not watched in {}", address, path.display()).into());
+            }
+            watched.save(&path)?;
+        }
+        Command::Wallet { action: WalletAction::Balance { address, wallet: path, height, rpc, db_path } } => {
+            let addresses = match address {
+                Some(address) => vec![wallet::WatchedAddress { address, label: None }],
+                None => wallet::Wallet::load(&path)?.addresses,
+            };
+            if addresses.is_empty() {
+                return Err(format!("no address given and none watched in {}", path.display()).into());
+            }
+            let source = chain_source(rpc, db_path)?;
+            for watched in addresses {
+                let address = wallet::parse_address(&watched.address)?;
+                let account = source.account(&address, height).await?;
+                match height {
+                    Some(height) => println!("{}  {:>20}  nonce {:<6} at #{} {}", watched.address, account.balance,
+                        account.nonce, height, watched.label.unwrap_or_default()),
+                    None => println!("{}  {:>20}  next nonce {:<6} {}", watched.address, account.balance,
+                        source.next_nonce(&address).await?, watched.label.unwrap_or_default()),
+                }
             }
         }
-        result = async {
-            // For now, just simulate network activity
-            loop {
-                tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
-                info!("📡 Network heartbeat");
+        Command::Wallet { action: WalletAction::History { address, limit, cursor, rpc, db_path } } => {
+            let account = wallet::parse_address(&address)?;
+            let page = storage::HistoryPage { cursor, limit };
+            let history = chain_source(rpc, db_path)?.history(&account, &page).await?;
+            for entry in &history.transactions {
+                let tx = &entry.transaction;
+                let (direction, other) = if tx.from == account { ("out", tx.to) } else { ("in ", tx.from) };
+                println!("#{}.{}  {}  {:>20}  fee {:<6} {}  {}", entry.block_number, entry.index, direction,
+                    tx.amount, tx.fee, hex::encode(other), hex::encode(tx.id));
             }
-            #[allow(unreachable_code)]
-            Ok::<(), Box<dyn std::error::Error>>(())
-        } => {
-            if let Err(e) = result {
-                warn!("❌ Network manager stopped with error: {}", e);
+            if let Some(cursor) = history.next_cursor {
+                println!("more with --cursor {}", cursor);
             }
         }
-        _ = tokio::signal::ctrl_c() => {
-            info!("🛑 Received shutdown signal");
+        Command::Wallet { action: WalletAction::Sign { to, amount, fee, nonce, chain_id, valid_until_height, key_file, keystore, out } } => {
+            let keypair = match keystore {
+                Some(keystore) => Keystore::load(&keystore)?.decrypt(&crypto::keystore_password(false)?)?,
+                None => NodeKeypair::load(&key_file)?,
+            };
+            let transfer = wallet::Transfer {
+                to: wallet::parse_address(&to)?,
+                amount,
+                fee,
+                nonce,
+                chain_id,
+                valid_until: valid_until_height.map(types::Expiry::Height),
+            };
+            let tx = wallet::sign_transfer(&keypair, &transfer);
+            wallet::write_transaction(&tx, &out)?;
+            info!("✍️ Signed transfer {} of {} to {} with nonce {} into {}",
+                hex::encode(tx.id), amount, to, nonce, out.display());
+        }
+        Command::Wallet { action: WalletAction::SignProposal {
+            remove_validator, block_time, min_validators, max_validators, quorum_numerator, quorum_denominator,
+            nonce, chain_id, key_file, keystore, out,
+        } } => {
+            let keypair = match keystore {
+                Some(keystore) => Keystore::load(&keystore)?.decrypt(&crypto::keystore_password(false)?)?,
+                None => NodeKeypair::load(&key_file)?,
+            };
+            let action = match remove_validator {
+                Some(validator) => staking::ProposalAction::RemoveValidator(wallet::parse_address(&validator)?),
+                None => staking::ProposalAction::ChangeParams(types::ConsensusParams {
+                    block_time, min_validators, max_validators, quorum_numerator, quorum_denominator,
+                }),
+            };
+            let tx = wallet::sign_proposal(&keypair, action, nonce, chain_id)?;
+            wallet::write_transaction(&tx, &out)?;
+            info!("✍️ Signed proposal {} with nonce {} into {}", hex::encode(tx.id), nonce, out.display());
+        }
+        Command::Wallet { action: WalletAction::SignVote { proposal, reject, nonce, chain_id, key_file, keystore, out } } => {
+            let keypair = match keystore {
+                Some(keystore) => Keystore::load(&keystore)?.decrypt(&crypto::keystore_password(false)?)?,
+                None => NodeKeypair::load(&key_file)?,
+            };
+            let tx = wallet::sign_proposal_vote(&keypair, wallet::parse_proposal_id(&proposal)?, !reject, nonce, chain_id);
+            wallet::write_transaction(&tx, &out)?;
+            info!("✍️ Signed a vote {} proposal {} with nonce {} into {}",
+                if reject { "against" } else { "for" }, proposal, nonce, out.display());
+        }
+        Command::Wallet { action: WalletAction::Submit { tx, rpc } } => {
+            let tx = wallet::read_transaction(&tx)?;
+            if wallet::submit(&rpc, &tx).await? {
+                info!("📤 Submitted {}", hex::encode(tx.id));
+            } else {
+                warn!("📤 Submitted {}, it waits for the transactions with the nonces before {}", hex::encode(tx.id), tx.nonce);
+            }
+        }
+        Command::Bridge { action: BridgeAction::Export { height, out, rpc } } => {
+            let params = serde_json::json!({ "number": height });
+            let exported = rpc::call(&rpc, "bridge_getProof", params).await?;
+            let bytes = hex::decode(exported["proof"].as_str().unwrap_or_default())?;
+            std::fs::write(&out, &bytes)?;
+            info!("🌉 Wrote the bridge proof of block #{} ({} bytes, validator set {}) to {}",
+                exported["height"], bytes.len(), exported["validator_set_hash"].as_str().unwrap_or_default(), out.display());
+        }
+        Command::Bridge { action: BridgeAction::Verify { checkpoint, proofs } } => {
+            let checkpoint = types::TrustedCheckpoint::parse(&checkpoint)?;
+            let mut verifier = node::bridge_verifier(config, checkpoint).await?;
+            for path in proofs {
+                let proof = bridge::BridgeProof::from_bytes(&std::fs::read(&path)?)?;
+                let header = verifier.import(&proof).await
+                    .map_err(|e| format!("{}: {}", path.display(), e))?;
+                println!("#{}  {}  state root {}", header.block_number, hex::encode(header.block_hash), hex::encode(header.state_root));
+            }
+        }
+        Command::BuildSpec { out, chain_id, height, db_path, genesis } => {
+            let storage = StorageManager::new(&config::StorageConfig { db_path, ..Default::default() })?;
+            let height = match height {
+                Some(height) => height,
+                None => match storage.get_finalized_block().await? {
+                    Some(block) => block.header.block_number,
+                    None => return Err("database has no finalized block".into()),
+                },
+            };
+            let consensus = match genesis {
+                Some(path) => Genesis::load(&path)?.consensus,
+                None => Default::default(),
+            };
+            
+            let spec = Genesis::build_spec(&storage, height, chain_id, consensus).await?;
+            spec.write_to(&out)?;
+            info!("🌱 Wrote genesis {} for chain {} from block #{} ({} validators, {} accounts) to {}",
+                hex::encode(spec.hash()), chain_id, height, spec.validators.len(), spec.accounts.len(), out.display());
+        }
+        Command::Snapshot { action: SnapshotAction::Create { out, height, db_path } } => {
+            let storage = StorageManager::new(&config::StorageConfig { db_path, ..Default::default() })?;
+            let height = match height {
+                Some(height) => height,
+                None => match storage.get_finalized_block().await? {
+                    Some(block) => block.header.block_number,
+                    None => return Err("database has no finalized block".into()),
+                },
+            };
+            
+            let snapshot = storage.create_snapshot(height).await?;
+            snapshot.write_to(&out)?;
+            info!("📸 Wrote snapshot #{} to {}", height, out.display());
+        }
+        Command::Backup { dir, db_path, full } => {
+            let storage = StorageManager::new(&config::StorageConfig { db_path, ..Default::default() })?;
+            if storage.backup(&dir, full).await?.is_none() {
+                info!("💾 Backup in {} is already at the finalized block", dir.display());
+            }
+        }
+        Command::Restore { dir, db_path } => {
+            let storage = StorageManager::new(&config::StorageConfig { db_path, ..Default::default() })?;
+            storage.restore(&dir).await?;
+        }
+        Command::VerifyBackup { dir } => {
+            snapshot::verify_backup(&dir)?;
+        }
+        Command::Bench { backend, txs, iterations, json } => {
+            let results = bench::run(&bench::BenchConfig { backends: backend, tx_counts: txs, iterations }).await?;
+            bench::print_table(&results);
+            if let Some(path) = json {
+                std::fs::write(&path, serde_json::to_string_pretty(&results)?)?;
+                info!("⏱️ Wrote {} benchmark results to {}", results.len(), path.display());
+            }
         }
+        Command::Reindex => {
+            let report = node::reindex(config).await?;
+            info!("🔁 Replayed {} blocks, {} with a finality certificate; remove {} once the node runs fine",
+                report.height, report.certificates, report.old_db_path.display());
+        }
+        Command::ExportKeys { dir } => {
+            for (path, sha256) in node::export_keys(config, &dir)? {
+                info!("🔑 Wrote {} (sha256 {})", path.display(), hex::encode(sha256));
+            }
+        }
+        Command::Status { rpc } => {
+            print_status(&rpc::call(&rpc, "system_status", serde_json::Value::Null).await?);
+        }
+        Command::Console { rpc } => {
+            admin::run_console(&rpc).await?;
+        }
+        #[cfg(feature = "testing")]
+        Command::Simulate { seed, runs, nodes, duration, max_delay_ms, drop_rate, byzantine, db_dir } => {
+            for seed in seed..seed.saturating_add(runs) {
+                let config = testing::SimulationConfig {
+                    seed,
+                    nodes,
+                    duration,
+                    max_delay_ms,
+                    drop_rate,
+                    byzantine: byzantine.clone(),
+                    db_dir: db_dir.clone(),
+                };
+                let report = testing::simulate(&config).await?;
+                println!("seed {}: finalized #{}, {} messages delivered, {} dropped, trace {}",
+                    report.seed, report.finalized, report.delivered, report.dropped, hex::encode(report.trace));
+            }
+        }
+    }
+    
+    Ok(())
+}
+
+// A database given on the command line is read instead of asking the node
+fn chain_source(rpc: String, db_path: Option<String>) -> Result<wallet::ChainSource, Box<dyn std::error::Error>> {
+    Ok(match db_path {
+        Some(db_path) => wallet::ChainSource::Storage(StorageManager::new(&config::StorageConfig { db_path, ..Default::default() })?),
+        None => wallet::ChainSource::Rpc(rpc),
+    })
+}
+
+fn print_status(status: &serde_json::Value) {
+    let height = |key: &str| status[key].as_u64().map_or("-".to_string(), |height| format!("#{}", height));
+    
+    println!("Mode:       {}", status["mode"].as_str().unwrap_or("unknown"));
+    println!("Height:     {}", height("height"));
+    if status["mode"] == "light_client" {
+        return;
+    }
+    println!("Justified:  {}", height("justified_height"));
+    println!("Finalized:  {}", height("finalized_height"));
+    println!("Peers:      {}", status["peers"]);
+    println!("Mempool:    {} transactions", status["mempool"]);
+    match status["validator"].as_object() {
+        Some(validator) => println!("Validator:  {} with stake {}",
+            if validator["is_active"] == true { "active" } else { "inactive" }, validator["stake"]),
+        None => println!("Validator:  no"),
+    }
+    println!("Proof jobs: {} queued, {} running", status["proof_jobs"]["queued"], status["proof_jobs"]["running"]);
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = Args::parse();
+    
+    // Subcommands only log, they do not read the config file; reindex replays the chain with
+    // the node's settings, bridge verify checks proofs with its genesis and proof settings, and
+    // export-keys writes keys for the genesis' chain
+    let mut config = match &args.command {
+        Some(Command::Reindex | Command::ExportKeys { .. } | Command::Bridge { action: BridgeAction::Verify { .. } }) | None => {
+            NodeConfig::load(args.config.as_deref())?
+        }
+        Some(_) => NodeConfig::default(),
+    };
+    if let Ok(filter) = std::env::var(tracing_subscriber::EnvFilter::DEFAULT_ENV) {
+        config.log.filter = filter;
+    }
+    args.apply_to(&mut config);
+    if matches!(args.command, Some(Command::Reindex) | None) {
+        config.validate()?;
+    }
+    
+    // The filter can be changed at runtime through the admin RPC
+    let logging = logging::init(&config.log)?;
+    
+    if let Some(command) = args.command {
+        return run_command(command, config).await;
+    }
+    if let Some(path) = &args.config {
+        info!("📄 Loaded configuration from {}", path.display());
+    }
+    
+    let mut node = Node::builder()
+        .with_config(config)
+        .with_log_filter(logging.filter.clone());
+    if let Some(path) = args.restore_snapshot {
+        node = node.restore_snapshot(path);
+    }
+    #[cfg(feature = "testing")]
+    if let Some(name) = &args.byzantine {
+        node = node.with_byzantine(testing::behavior(name)?);
     }
     
-    info!("👋 Shutting down ZK-PoV Consensus Node");
+    node.build().await?.run().await?;
     Ok(())
 }
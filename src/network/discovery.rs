@@ -0,0 +1,125 @@
+use libp2p::{Multiaddr, PeerId};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+// Wait before redialing a peer after its first failure, doubled with every further failure
+const BASE_BACKOFF: Duration = Duration::from_secs(5);
+const MAX_BACKOFF: Duration = Duration::from_secs(10 * 60);
+// Remembered peers that failed this many dials in a row are given up on; bootstrap nodes never are
+const MAX_DIAL_FAILURES: u32 = 10;
+// Peers beyond this push out the lowest-scored ones; bootstrap nodes are always kept
+pub const MAX_DIAL_TARGETS: usize = 1024;
+
+#[derive(Debug)]
+struct DialTarget {
+    addrs: Vec<Multiaddr>,
+    bootstrap: bool,
+    failures: u32,
+    next_attempt: Instant,
+}
+
+// Peers to keep connections to: bootstrap nodes, peers remembered from earlier runs and peers
+// found on the LAN. Each one is redialed with exponential backoff while it is unreachable
+#[derive(Debug, Default)]
+pub struct Dialer {
+    targets: HashMap<PeerId, DialTarget>,
+}
+
+impl Dialer {
+    // Returns the peers dropped to make room, which is the new one itself when every target
+    // ranks above it. Lower scores rank lower, then more failed dials
+    pub fn add(
+        &mut self,
+        peer: PeerId,
+        addrs: Vec<Multiaddr>,
+        bootstrap: bool,
+        now: Instant,
+        score: impl Fn(&PeerId) -> f64,
+    ) -> Vec<PeerId> {
+        let mut dropped = Vec::new();
+        if !bootstrap && !self.targets.contains_key(&peer) && self.targets.len() >= MAX_DIAL_TARGETS {
+            let incoming = score(&peer);
+            let lowest = self.targets.iter()
+                .filter(|(_, target)| !target.bootstrap)
+                .map(|(peer, target)| (*peer, score(peer), target.failures))
+                .min_by(|a, b| a.1.total_cmp(&b.1).then(b.2.cmp(&a.2)));
+            match lowest {
+                // The new peer has not failed a dial yet
+                Some((lowest, lowest_score, failures)) if lowest_score.total_cmp(&incoming).then(0.cmp(&failures)).is_lt() => {
+                    self.targets.remove(&lowest);
+                    dropped.push(lowest);
+                }
+                _ => return vec![peer],
+            }
+        }
+
+        let target = self.targets.entry(peer).or_insert_with(|| DialTarget {
+            addrs: Vec::new(),
+            bootstrap,
+            failures: 0,
+            next_attempt: now,
+        });
+        target.bootstrap |= bootstrap;
+        for addr in addrs {
+            if !target.addrs.contains(&addr) {
+                target.addrs.push(addr);
+            }
+        }
+        dropped
+    }
+
+    pub fn remove(&mut self, peer: &PeerId) {
+        self.targets.remove(peer);
+    }
+
+    pub fn connected(&mut self, peer: &PeerId) {
+        if let Some(target) = self.targets.get_mut(peer) {
+            target.failures = 0;
+        }
+    }
+
+    // Not dialed again before a full backoff, so a peer that keeps dropping us is not
+    // redialed in a loop
+    pub fn disconnected(&mut self, peer: &PeerId, now: Instant) {
+        if let Some(target) = self.targets.get_mut(peer) {
+            target.next_attempt = now + backoff(target.failures);
+        }
+    }
+
+    // True when the peer was given up on
+    pub fn failed(&mut self, peer: &PeerId, now: Instant) -> bool {
+        let target = match self.targets.get_mut(peer) {
+            Some(target) => target,
+            None => return false,
+        };
+        target.failures += 1;
+        target.next_attempt = now + backoff(target.failures);
+
+        if !target.bootstrap && target.failures >= MAX_DIAL_FAILURES {
+            self.targets.remove(peer);
+            return true;
+        }
+        false
+    }
+
+    // Targets whose backoff is over, at most `limit` of them. They are not handed out again
+    // before their next backoff, whether the dial succeeds or not
+    pub fn due(&mut self, now: Instant, limit: usize, skip: impl Fn(&PeerId) -> bool) -> Vec<(PeerId, Vec<Multiaddr>)> {
+        let mut due = Vec::new();
+        for (peer, target) in self.targets.iter_mut() {
+            if due.len() == limit {
+                break;
+            }
+            if target.next_attempt > now || skip(peer) {
+                continue;
+            }
+            target.next_attempt = now + backoff(target.failures);
+            due.push((*peer, target.addrs.clone()));
+        }
+        due
+    }
+}
+
+fn backoff(failures: u32) -> Duration {
+    BASE_BACKOFF.saturating_mul(1 << failures.min(16)).min(MAX_BACKOFF)
+}
@@ -0,0 +1,16 @@
+use libp2p::{swarm::DialError, PeerId};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum NetworkError {
+    #[error("invalid peer id '{0}'")]
+    InvalidPeerId(String),
+    #[error("invalid multiaddr '{0}'")]
+    InvalidAddress(String),
+    #[error("peer {0} is already banned")]
+    AlreadyBanned(PeerId),
+    #[error("dial failed: {0}")]
+    Dial(#[from] DialError),
+    #[error("transport security setup failed: {0}")]
+    Security(String),
+}
@@ -0,0 +1,145 @@
+use anyhow::{bail, Result};
+use flate2::{read::DeflateDecoder, write::DeflateEncoder};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::io::{self, Read, Write};
+
+// Gossip payloads are a codec byte and then the wire encoding of the message, compressed with
+// that codec. Compressed payloads put the length of the message (u32) before the compressed
+// bytes, so a peer can not make us inflate more than it declared
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Compression {
+    None,
+    Deflate,
+}
+
+// Messages smaller than this go out as they are; votes and requests barely shrink
+const COMPRESS_THRESHOLD: usize = 512;
+
+impl Compression {
+    fn tag(self) -> u8 {
+        match self {
+            Compression::None => 0,
+            Compression::Deflate => 1,
+        }
+    }
+}
+
+pub fn encode_frame(wire: &[u8], compression: Compression) -> Vec<u8> {
+    let compressed = match compression {
+        Compression::Deflate if wire.len() >= COMPRESS_THRESHOLD => deflate(wire).ok(),
+        _ => None,
+    };
+    match compressed {
+        // Incompressible messages, such as proofs, are not worth the length prefix
+        Some(compressed) if compressed.len() + 4 < wire.len() => {
+            let mut out = Vec::with_capacity(compressed.len() + 5);
+            out.push(Compression::Deflate.tag());
+            out.extend_from_slice(&(wire.len() as u32).to_le_bytes());
+            out.extend_from_slice(&compressed);
+            out
+        }
+        _ => {
+            let mut out = Vec::with_capacity(wire.len() + 1);
+            out.push(Compression::None.tag());
+            out.extend_from_slice(wire);
+            out
+        }
+    }
+}
+
+// The wire encoding of a gossiped message, which may not exceed `max_bytes` however small it
+// was compressed
+pub fn decode_frame(data: &[u8], max_bytes: usize) -> Result<Cow<'_, [u8]>> {
+    let (tag, payload) = match data.split_first() {
+        Some(frame) => frame,
+        None => bail!("Empty message"),
+    };
+    match *tag {
+        0 => {
+            if payload.len() > max_bytes {
+                bail!("Message of {} bytes is over the limit of {}", payload.len(), max_bytes);
+            }
+            Ok(Cow::Borrowed(payload))
+        }
+        1 => {
+            if payload.len() < 4 {
+                bail!("Compressed message has no length");
+            }
+            let (len, compressed) = payload.split_at(4);
+            let len = u32::from_le_bytes(len.try_into()?) as usize;
+            if len > max_bytes {
+                bail!("Message of {} bytes is over the limit of {}", len, max_bytes);
+            }
+            // Inflating stops one byte past the declared length, which is enough to tell a lie
+            let mut wire = Vec::new();
+            DeflateDecoder::new(compressed).take(len as u64 + 1).read_to_end(&mut wire)?;
+            if wire.len() != len {
+                bail!("Compressed message does not inflate to the {} bytes it declares", len);
+            }
+            Ok(Cow::Owned(wire))
+        }
+        other => bail!("Unknown compression {}", other),
+    }
+}
+
+fn deflate(bytes: &[u8]) -> io::Result<Vec<u8>> {
+    let mut encoder = DeflateEncoder::new(Vec::new(), flate2::Compression::fast());
+    encoder.write_all(bytes)?;
+    encoder.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Repetitive, so it shrinks when deflated
+    fn message(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i % 7) as u8).collect()
+    }
+
+    fn compressed_frame(wire: &[u8], declared: u32) -> Vec<u8> {
+        let mut frame = vec![Compression::Deflate.tag()];
+        frame.extend_from_slice(&declared.to_le_bytes());
+        frame.extend_from_slice(&deflate(wire).unwrap());
+        frame
+    }
+
+    #[test]
+    fn frames_round_trip() {
+        for len in [0, 1, COMPRESS_THRESHOLD - 1, COMPRESS_THRESHOLD, 64 * 1024] {
+            let wire = message(len);
+            for compression in [Compression::None, Compression::Deflate] {
+                let frame = encode_frame(&wire, compression);
+                assert_eq!(decode_frame(&frame, wire.len()).unwrap(), wire.as_slice());
+            }
+        }
+        // Below the threshold messages go out as they are
+        assert_eq!(encode_frame(&message(10), Compression::Deflate)[0], Compression::None.tag());
+
+        let wire = message(4096);
+        assert_eq!(decode_frame(&compressed_frame(&wire, 4096), 4096).unwrap(), wire.as_slice());
+    }
+
+    #[test]
+    fn truncated_frames_are_rejected() {
+        assert!(decode_frame(&[], 1024).is_err());
+        // A compressed frame cut off in its length, and in its payload
+        assert!(decode_frame(&[Compression::Deflate.tag(), 0, 4], 1024).is_err());
+        let frame = compressed_frame(&message(4096), 4096);
+        assert!(decode_frame(&frame[..frame.len() / 2], 4096).is_err());
+        // Nor can a codec we do not know be read
+        assert!(decode_frame(&[7, 1, 2, 3], 1024).is_err());
+    }
+
+    #[test]
+    fn oversized_frames_are_rejected() {
+        let wire = message(4096);
+        assert!(decode_frame(&encode_frame(&wire, Compression::None), 4095).is_err());
+        assert!(decode_frame(&encode_frame(&wire, Compression::Deflate), 4095).is_err());
+        // The declared length is checked before anything is inflated
+        assert!(decode_frame(&compressed_frame(&message(16), u32::MAX), 4096).is_err());
+        // and a payload inflating past it is caught, however large the limit
+        assert!(decode_frame(&compressed_frame(&wire, 1024), usize::MAX).is_err());
+    }
+}
@@ -0,0 +1,223 @@
+use super::framing::Compression;
+use crate::types::{BlockHash, NodeId};
+use anyhow::{bail, Result};
+use libp2p::{
+    core::{upgrade::{InboundUpgrade, OutboundUpgrade, UpgradeInfo}, Endpoint},
+    futures::{future::BoxFuture, AsyncReadExt, AsyncWriteExt},
+    swarm::{
+        handler::{OneShotHandler, OneShotHandlerConfig},
+        ConnectionDenied, ConnectionId, FromSwarm, NetworkBehaviour, NotifyHandler, StreamUpgradeError,
+        SubstreamProtocol, THandler, THandlerInEvent, THandlerOutEvent, ToSwarm,
+    },
+    Multiaddr, PeerId, Stream, StreamProtocol,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::io;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+// Bumped whenever the wire format of gossiped messages or the rules blocks are checked by change
+pub const PROTOCOL_VERSION: u32 = 15;
+const HANDSHAKE_PROTOCOL: StreamProtocol = StreamProtocol::new("/zk-consensus/handshake/1");
+// A handshake is a few dozen bytes; anything much larger is not one
+const MAX_HANDSHAKE_BYTES: usize = 1024;
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+// What two nodes tell each other on connecting, before anything else is trusted
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Handshake {
+    pub protocol_version: u32,
+    pub chain_id: u64,
+    // Hash of block 0; None until the node has one, such as a new node bootstrapping a chain
+    pub genesis_hash: Option<BlockHash>,
+    // Number and hash of the node's finalized head
+    pub head: Option<(u64, BlockHash)>,
+    // Codecs the node wants gossip compressed with; it decodes every codec it knows either way,
+    // as relayed messages keep the compression their author picked
+    pub compression: Vec<Compression>,
+}
+
+impl Handshake {
+    // Why we can not talk to the peer, if we can not
+    pub fn check(&self, peer: &Handshake) -> Result<()> {
+        if peer.protocol_version != self.protocol_version {
+            bail!("protocol version {} differs from ours {}", peer.protocol_version, self.protocol_version);
+        }
+        if peer.chain_id != self.chain_id {
+            bail!("chain id {} differs from ours {}", peer.chain_id, self.chain_id);
+        }
+        if let (Some(theirs), Some(ours)) = (peer.genesis_hash, self.genesis_hash) {
+            if theirs != ours {
+                bail!("genesis {} differs from ours {}", hex::encode(theirs), hex::encode(ours));
+            }
+        }
+        Ok(())
+    }
+}
+
+// Finalized height a connected peer announced, for picking sync targets
+#[derive(Debug, Clone)]
+pub struct PeerHead {
+    pub node_id: NodeId,
+    pub finalized_height: u64,
+}
+
+// Node ids are the Ed25519 keys peer ids are derived from
+pub fn node_id(peer: &PeerId) -> Option<NodeId> {
+    let multihash: &libp2p::multihash::Multihash<64> = peer.as_ref();
+    // Keys this short are inlined in the peer id with the identity hash
+    if multihash.code() != 0 {
+        return None;
+    }
+    let key = libp2p::identity::PublicKey::try_decode_protobuf(multihash.digest()).ok()?;
+    Some(key.try_into_ed25519().ok()?.to_bytes())
+}
+
+#[derive(Debug)]
+pub enum Event {
+    Received { peer_id: PeerId, handshake: Handshake },
+    Failed { peer_id: PeerId, error: String },
+}
+
+// Sends our handshake and reads the peer's on a substream of its own. The side that dialed
+// opens it as soon as the connection is up
+#[derive(Debug, Clone)]
+pub struct HandshakeProtocol {
+    local: Handshake,
+}
+
+impl UpgradeInfo for HandshakeProtocol {
+    type Info = StreamProtocol;
+    type InfoIter = std::iter::Once<StreamProtocol>;
+
+    fn protocol_info(&self) -> Self::InfoIter {
+        std::iter::once(HANDSHAKE_PROTOCOL)
+    }
+}
+
+impl InboundUpgrade<Stream> for HandshakeProtocol {
+    type Output = Handshake;
+    type Error = io::Error;
+    type Future = BoxFuture<'static, io::Result<Handshake>>;
+
+    fn upgrade_inbound(self, mut stream: Stream, _: StreamProtocol) -> Self::Future {
+        Box::pin(async move {
+            let theirs = read_handshake(&mut stream).await?;
+            write_handshake(&mut stream, &self.local).await?;
+            stream.close().await?;
+            Ok(theirs)
+        })
+    }
+}
+
+impl OutboundUpgrade<Stream> for HandshakeProtocol {
+    type Output = Handshake;
+    type Error = io::Error;
+    type Future = BoxFuture<'static, io::Result<Handshake>>;
+
+    fn upgrade_outbound(self, mut stream: Stream, _: StreamProtocol) -> Self::Future {
+        Box::pin(async move {
+            write_handshake(&mut stream, &self.local).await?;
+            read_handshake(&mut stream).await
+        })
+    }
+}
+
+async fn write_handshake(stream: &mut Stream, handshake: &Handshake) -> io::Result<()> {
+    let bytes = bincode::serialize(handshake).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    stream.write_all(&(bytes.len() as u32).to_le_bytes()).await?;
+    stream.write_all(&bytes).await?;
+    stream.flush().await
+}
+
+async fn read_handshake(stream: &mut Stream) -> io::Result<Handshake> {
+    let mut len = [0; 4];
+    stream.read_exact(&mut len).await?;
+    let len = u32::from_le_bytes(len) as usize;
+    if len > MAX_HANDSHAKE_BYTES {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("handshake of {} bytes", len)));
+    }
+    let mut bytes = vec![0; len];
+    stream.read_exact(&mut bytes).await?;
+    bincode::deserialize(&bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+// Runs the handshake on every connection; what to do with a peer that fails it is left to
+// the network manager
+pub struct Behaviour {
+    local: Handshake,
+    events: VecDeque<ToSwarm<Event, HandshakeProtocol>>,
+}
+
+impl Behaviour {
+    pub fn new(local: Handshake) -> Self {
+        Self { local, events: VecDeque::new() }
+    }
+
+    // Used for connections opened from now on
+    pub fn set_local(&mut self, local: Handshake) {
+        self.local = local;
+    }
+
+    fn handler(&self) -> OneShotHandler<HandshakeProtocol, HandshakeProtocol, Handshake> {
+        let protocol = SubstreamProtocol::new(HandshakeProtocol { local: self.local.clone() }, ())
+            .with_timeout(HANDSHAKE_TIMEOUT);
+        OneShotHandler::new(protocol, OneShotHandlerConfig::default())
+    }
+}
+
+impl NetworkBehaviour for Behaviour {
+    type ConnectionHandler = OneShotHandler<HandshakeProtocol, HandshakeProtocol, Handshake>;
+    type ToSwarm = Event;
+
+    fn handle_established_inbound_connection(
+        &mut self,
+        _: ConnectionId,
+        _: PeerId,
+        _: &Multiaddr,
+        _: &Multiaddr,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        Ok(self.handler())
+    }
+
+    fn handle_established_outbound_connection(
+        &mut self,
+        connection_id: ConnectionId,
+        peer_id: PeerId,
+        _: &Multiaddr,
+        _: Endpoint,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        self.events.push_back(ToSwarm::NotifyHandler {
+            peer_id,
+            handler: NotifyHandler::One(connection_id),
+            event: HandshakeProtocol { local: self.local.clone() },
+        });
+        Ok(self.handler())
+    }
+
+    fn on_swarm_event(&mut self, _: FromSwarm) {}
+
+    fn on_connection_handler_event(
+        &mut self,
+        peer_id: PeerId,
+        _: ConnectionId,
+        event: THandlerOutEvent<Self>,
+    ) {
+        let event = match event {
+            Ok(handshake) => Event::Received { peer_id, handshake },
+            Err(StreamUpgradeError::NegotiationFailed) => {
+                Event::Failed { peer_id, error: "does not speak the handshake protocol".to_string() }
+            }
+            Err(e) => Event::Failed { peer_id, error: e.to_string() },
+        };
+        self.events.push_back(ToSwarm::GenerateEvent(event));
+    }
+
+    fn poll(&mut self, _: &mut Context<'_>) -> Poll<ToSwarm<Self::ToSwarm, THandlerInEvent<Self>>> {
+        match self.events.pop_front() {
+            Some(event) => Poll::Ready(event),
+            None => Poll::Pending,
+        }
+    }
+}
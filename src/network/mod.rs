@@ -1,52 +1,886 @@
-use crate::types::{ConsensusMessage, Block, BlockVote, ConsensusState};
-use crate::consensus::ConsensusEngine;
-use anyhow::Result;
+use crate::admin::NetworkCommand;
+use crate::types::{self, ChainEvent, ConsensusMessage, Block, BlockVote, Encode, Transaction, VoteBundle};
+use crate::config::{NatConfig, NetworkConfig};
+use crate::consensus::ConsensusSender;
+use crate::shutdown::ShutdownSignal;
+use crate::storage::{KnownPeer, MempoolEvent, StorageManager};
+use anyhow::{anyhow, Result};
 use serde::{Serialize, Deserialize};
-use std::collections::{hash_map::DefaultHasher, HashMap};
-use std::hash::{Hash, Hasher};
+use sha2::{Sha256, Digest};
+use chrono::Utc;
+use libp2p::{
+    futures::StreamExt,
+    connection_limits, dcutr, gossipsub, identify, kad, mdns, relay, tcp, upnp, yamux,
+    core::transport::ListenerId,
+    multiaddr::Protocol,
+    swarm::{behaviour::toggle::Toggle, dial_opts::DialOpts, NetworkBehaviour, SwarmEvent},
+    Multiaddr, PeerId, StreamProtocol, Swarm,
+};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use std::sync::Arc;
 use tracing::{info, debug, warn, error};
-use tokio::sync::mpsc;
+use tokio::sync::{broadcast, mpsc, RwLock};
 
-pub struct NetworkManager<'a> {
-    consensus: &'a mut crate::consensus::ConsensusEngine,
-    consensus_tx: mpsc::Sender<ConsensusMessage>,
-    peer_id: String,
+mod discovery;
+mod error;
+mod framing;
+mod handshake;
+mod nat;
+mod rate_limit;
+mod reputation;
+mod security;
+mod seen;
+mod tx_gossip;
+
+pub use error::NetworkError;
+pub use framing::Compression;
+pub use handshake::{Handshake, PeerHead, PROTOCOL_VERSION};
+pub use nat::relay_peer;
+pub use reputation::{MessageHash, Misbehaviour, PeerInfo, PeerReport};
+pub use security::{TransportSecurity, SECURITY_PROTOCOLS};
+use discovery::{Dialer, MAX_DIAL_TARGETS};
+use nat::NatEvent;
+use rate_limit::{MessageClass, RateDecision, RateLimiter};
+use reputation::PeerReputation;
+use security::{NegotiatedSecurity, SecurityUpgrade};
+use seen::SeenCache;
+
+const CONSENSUS_TOPIC: &str = "zk-consensus/messages/3";
+// How long the swarm keeps running on shutdown to send the last messages
+const SHUTDOWN_FLUSH: Duration = Duration::from_millis(500);
+const KAD_PROTOCOL: &str = "/zk-consensus/kad/1.0.0";
+const IDENTIFY_PROTOCOL: &str = "/zk-consensus/1.0.0";
+// How often the peer list served over RPC is refreshed, expired bans are lifted and
+// unreachable peers are redialed
+const PEER_REFRESH_INTERVAL: Duration = Duration::from_secs(5);
+// Dials started per refresh, so a large peer store does not open hundreds of connections at once
+const MAX_DIALS_PER_REFRESH: usize = 8;
+// Message hashes remembered for dropping duplicates, several heights' worth of blocks and votes
+const SEEN_CACHE_SIZE: usize = 16384;
+// Our votes are held back this long, so the ones cast close together go out as one message
+const VOTE_FLUSH_INTERVAL: Duration = Duration::from_millis(200);
+// Votes per bundle, far below the gossipsub message size limit; larger bundles are rejected
+const MAX_BUNDLE_VOTES: usize = 64;
+// Peers that have not completed the handshake by then are disconnected
+const HANDSHAKE_DEADLINE: Duration = Duration::from_secs(15);
+// Peers on another chain or protocol version are refused this long, in case they upgrade
+const INCOMPATIBLE_BAN: Duration = Duration::from_secs(10 * 60);
+// Room for the gossipsub envelope around a message: its author, sequence number, topic and signature
+const GOSSIP_ENVELOPE_BYTES: usize = 1024;
+// Transactions entering our mempool are held back this long, so they go to each peer in batches
+const TX_FLUSH_INTERVAL: Duration = Duration::from_millis(100);
+// Transactions per pushed batch; batches are also cut before max_message_bytes
+const MAX_TX_BATCH: usize = 256;
+// Transaction ids remembered per peer as known to it, because it sent them or we did
+const KNOWN_TXS_PER_PEER: usize = 8192;
+// Transaction ids we already received or announced, for telling new transactions from echoes
+const SEEN_TXS_SIZE: usize = 65536;
+// Block requests sync sent lately, whose responses wait for room in the consensus inbox
+const SYNC_REQUESTS_SIZE: usize = 256;
+
+#[derive(NetworkBehaviour)]
+struct ZkBehaviour {
+    limits: connection_limits::Behaviour,
+    gossipsub: gossipsub::Behaviour,
+    mdns: Toggle<mdns::tokio::Behaviour>,
+    kademlia: kad::Behaviour<kad::store::MemoryStore>,
+    identify: identify::Behaviour,
+    handshake: handshake::Behaviour,
+    transactions: tx_gossip::Behaviour,
+    relay_client: relay::client::Behaviour,
+    relay_server: Toggle<relay::Behaviour>,
+    dcutr: Toggle<dcutr::Behaviour>,
+    upnp: Toggle<upnp::tokio::Behaviour>,
+}
+
+pub struct NetworkManager {
+    swarm: Swarm<ZkBehaviour>,
+    topic: gossipsub::IdentTopic,
+    consensus_tx: ConsensusSender,
+    outbound_tx: mpsc::Sender<ConsensusMessage>,
+    outbound_rx: mpsc::Receiver<ConsensusMessage>,
+    peer_id: PeerId,
     port: u16,
     bootstrap_nodes: Vec<String>,
+    max_peers: usize,
+    // Codec we compress gossip with, when every peer accepts it
+    compression: Compression,
+    max_message_bytes: usize,
+    dialer: Dialer,
+    // Good peers are remembered here and redialed on startup; light clients have no storage
+    peer_store: Option<StorageManager>,
+    stats: NetworkStats,
+    reputation: PeerReputation,
+    rate_limiter: RateLimiter,
+    seen: SeenCache,
+    // Votes from consensus waiting for the next flush
+    vote_batch: Vec<BlockVote>,
+    // Transactions that entered our mempool since the last flush
+    tx_queue: Vec<Transaction>,
+    // Ids of transactions each handshaken peer has, which are not pushed to it again
+    known_txs: HashMap<PeerId, SeenCache>,
+    seen_txs: SeenCache,
+    sync_requests: SeenCache,
+    // Pushed transactions go to the mempool through here; light clients keep none
+    tx_sink: Option<mpsc::Sender<Vec<Transaction>>>,
+    // Handshakes of connected peers, and when the ones still owing theirs connected
+    handshakes: HashMap<PeerId, Handshake>,
+    awaiting_handshake: HashMap<PeerId, Instant>,
+    // Peer heads go to the consensus engine's block sync; light clients do not sync blocks
+    peer_head_tx: Option<mpsc::Sender<PeerHead>>,
+    // Finalized blocks change the head our handshake announces
+    chain_events: Option<broadcast::Receiver<ChainEvent>>,
+    report_tx: mpsc::Sender<PeerReport>,
+    report_rx: mpsc::Receiver<PeerReport>,
+    admin_tx: mpsc::Sender<NetworkCommand>,
+    admin_rx: mpsc::Receiver<NetworkCommand>,
+    peers: Arc<RwLock<Vec<PeerInfo>>>,
+    security: NegotiatedSecurity,
+    external_addresses: Vec<Multiaddr>,
+    nat: NatConfig,
+    // Circuit listeners on the configured relays; relays missing here are listened on again
+    relay_listeners: HashMap<ListenerId, Multiaddr>,
+    // NAT-PMP runs when no UPnP gateway answered, and reports mappings through here
+    nat_tx: mpsc::Sender<NatEvent>,
+    nat_rx: mpsc::Receiver<NatEvent>,
+    nat_pmp_started: bool,
 }
 
-impl<'a> NetworkManager<'a> {
+impl NetworkManager {
     pub fn new(
-        port: u16,
-        bootstrap_nodes: Vec<String>,
-        consensus: &'a mut crate::consensus::ConsensusEngine,
+        config: &NetworkConfig,
+        identity: libp2p::identity::Keypair,
+        consensus_tx: ConsensusSender,
     ) -> Result<Self> {
-        info!("Initializing Network Manager (Mock Implementation)");
-        
-        let peer_id = format!("peer_{}", rand::random::<u64>());
-        let consensus_tx = consensus.get_message_sender();
-        
+        info!("Initializing Network Manager (libp2p)");
+        let compression = match config.compression {
+            true => Compression::Deflate,
+            false => Compression::None,
+        };
+
+        // Connections are encrypted and authenticated with the node key before anything is sent
+        let security = NegotiatedSecurity::default();
+        let swarm = libp2p::SwarmBuilder::with_existing_identity(identity)
+            .with_tokio()
+            .with_tcp(
+                tcp::Config::default(),
+                |key: &libp2p::identity::Keypair| SecurityUpgrade::new(key, &config.security, security.clone()),
+                yamux::Config::default,
+            )?
+            // Relayed connections are upgraded like direct ones, so relays see only ciphertext
+            .with_relay_client(
+                |key: &libp2p::identity::Keypair| SecurityUpgrade::new(key, &config.security, security.clone()),
+                yamux::Config::default,
+            )?
+            .with_behaviour(|key, relay_client| {
+                let peer_id = key.public().to_peer_id();
+
+                // Content-addressed message ids so the same block/vote gossiped by
+                // several peers is only delivered once
+                let gossipsub_config = gossipsub::ConfigBuilder::default()
+                    .heartbeat_interval(Duration::from_secs(1))
+                    .max_transmit_size(config.max_message_bytes + GOSSIP_ENVELOPE_BYTES)
+                    .validation_mode(gossipsub::ValidationMode::Strict)
+                    .message_id_fn(|message: &gossipsub::Message| {
+                        gossipsub::MessageId::from(Sha256::digest(&message.data).to_vec())
+                    })
+                    .build()?;
+                let gossipsub = gossipsub::Behaviour::new(
+                    gossipsub::MessageAuthenticity::Signed(key.clone()),
+                    gossipsub_config,
+                )?;
+
+                let mdns = match config.mdns {
+                    true => Some(mdns::tokio::Behaviour::new(mdns::Config::default(), peer_id)?),
+                    false => None,
+                };
+                // Hole punching opens a direct connection next to the relayed one it replaces
+                let per_peer = if config.nat.hole_punching { 2 } else { 1 };
+                let limits = connection_limits::Behaviour::new(
+                    connection_limits::ConnectionLimits::default()
+                        .with_max_established(Some(config.max_peers))
+                        .with_max_established_per_peer(Some(per_peer)),
+                );
+
+                let mut kad_config = kad::Config::default();
+                kad_config.set_protocol_names(vec![StreamProtocol::new(KAD_PROTOCOL)]);
+                let kademlia = kad::Behaviour::with_config(
+                    peer_id,
+                    kad::store::MemoryStore::new(peer_id),
+                    kad_config,
+                );
+
+                let identify = identify::Behaviour::new(
+                    identify::Config::new(IDENTIFY_PROTOCOL.to_string(), key.public()),
+                );
+                // Storage is only known once start runs, which fills in the genesis and head
+                let handshake = handshake::Behaviour::new(Handshake {
+                    protocol_version: PROTOCOL_VERSION,
+                    chain_id: types::chain_id(),
+                    genesis_hash: None,
+                    head: None,
+                    compression: accepted_compression(compression),
+                });
+
+                let transactions = tx_gossip::Behaviour::new(config.max_message_bytes);
+
+                let relay_server = config.nat.relay_server
+                    .then(|| relay::Behaviour::new(peer_id, relay::Config::default()));
+                let dcutr = config.nat.hole_punching.then(|| dcutr::Behaviour::new(peer_id));
+                let upnp = config.nat.upnp.then(upnp::tokio::Behaviour::default);
+
+                Ok(ZkBehaviour {
+                    limits,
+                    gossipsub,
+                    mdns: mdns.into(),
+                    kademlia,
+                    identify,
+                    handshake,
+                    transactions,
+                    relay_client,
+                    relay_server: relay_server.into(),
+                    dcutr: dcutr.into(),
+                    upnp: upnp.into(),
+                })
+            })?
+            .with_swarm_config(|c| c.with_idle_connection_timeout(Duration::from_secs(60)))
+            .build();
+
+        let peer_id = *swarm.local_peer_id();
+        let (outbound_tx, outbound_rx) = mpsc::channel(1000);
+        let (report_tx, report_rx) = mpsc::channel(1000);
+        let (admin_tx, admin_rx) = mpsc::channel(16);
+        let (nat_tx, nat_rx) = mpsc::channel(16);
+
+        info!("🆔 Peer ID: {}", peer_id);
+
         Ok(Self {
-            consensus,
+            swarm,
+            topic: gossipsub::IdentTopic::new(CONSENSUS_TOPIC),
             consensus_tx,
+            outbound_tx,
+            outbound_rx,
             peer_id,
-            port,
-            bootstrap_nodes,
+            port: config.port,
+            bootstrap_nodes: config.bootstrap_nodes.clone(),
+            max_peers: config.max_peers as usize,
+            compression,
+            max_message_bytes: config.max_message_bytes,
+            dialer: Dialer::default(),
+            peer_store: None,
+            stats: NetworkStats::new(),
+            reputation: PeerReputation::new(),
+            rate_limiter: RateLimiter::new(config.rate_limits.clone()),
+            seen: SeenCache::new(SEEN_CACHE_SIZE),
+            vote_batch: Vec::new(),
+            tx_queue: Vec::new(),
+            known_txs: HashMap::new(),
+            seen_txs: SeenCache::new(SEEN_TXS_SIZE),
+            sync_requests: SeenCache::new(SYNC_REQUESTS_SIZE),
+            tx_sink: None,
+            handshakes: HashMap::new(),
+            awaiting_handshake: HashMap::new(),
+            peer_head_tx: None,
+            chain_events: None,
+            report_tx,
+            report_rx,
+            admin_tx,
+            admin_rx,
+            peers: Arc::new(RwLock::new(Vec::new())),
+            security,
+            // Checked with the rest of the configuration
+            external_addresses: config.external_addresses.iter().filter_map(|addr| addr.parse().ok()).collect(),
+            nat: config.nat.clone(),
+            relay_listeners: HashMap::new(),
+            nat_tx,
+            nat_rx,
+            nat_pmp_started: false,
         })
     }
-    
-    pub async fn start(&mut self) -> Result<()> {
+
+    // Sender used by the consensus engine to publish messages to the network
+    pub fn get_broadcast_sender(&self) -> mpsc::Sender<ConsensusMessage> {
+        self.outbound_tx.clone()
+    }
+
+    // Sender used by the consensus engine to report peers whose messages it rejected
+    pub fn get_report_sender(&self) -> mpsc::Sender<PeerReport> {
+        self.report_tx.clone()
+    }
+
+    // Sender for operator commands from the admin RPC
+    pub fn get_admin_sender(&self) -> mpsc::Sender<NetworkCommand> {
+        self.admin_tx.clone()
+    }
+
+    // Connected peers and their scores, refreshed every PEER_REFRESH_INTERVAL
+    pub fn peers_handle(&self) -> Arc<RwLock<Vec<PeerInfo>>> {
+        self.peers.clone()
+    }
+
+    pub fn set_peer_store(&mut self, storage: StorageManager) {
+        self.peer_store = Some(storage);
+    }
+
+    pub fn set_peer_head_sender(&mut self, peer_head_tx: mpsc::Sender<PeerHead>) {
+        self.peer_head_tx = Some(peer_head_tx);
+    }
+
+    pub fn set_chain_events(&mut self, events: broadcast::Receiver<ChainEvent>) {
+        self.chain_events = Some(events);
+    }
+
+    // Where transactions peers push to us go to be checked and admitted to the mempool. Those
+    // admitted come back as mempool events and are passed on to the peers that lack them
+    pub fn set_transaction_sender(&mut self, tx_sink: mpsc::Sender<Vec<Transaction>>) {
+        self.tx_sink = Some(tx_sink);
+    }
+
+    pub async fn start(&mut self, mut shutdown: ShutdownSignal) -> Result<()> {
         info!("Starting Network Manager on port {}", self.port);
-        
-        // Mock network loop
+
+        self.swarm.behaviour_mut().gossipsub.subscribe(&self.topic)?;
+        self.update_handshake().await;
+        self.swarm.listen_on(format!("/ip4/0.0.0.0/tcp/{}", self.port).parse()?)?;
+        self.start_nat_traversal();
+
+        self.load_known_peers().await;
+        // Bootstrap nodes given with their peer id are redialed like remembered peers; bare
+        // addresses are dialed once
+        for addr in self.bootstrap_nodes.clone() {
+            let result = match addr.parse::<Multiaddr>() {
+                Ok(multiaddr) => match multiaddr.iter().last() {
+                    Some(Protocol::P2p(peer_id)) => {
+                        self.swarm.behaviour_mut().kademlia.add_address(&peer_id, multiaddr.clone());
+                        self.add_dial_target(peer_id, vec![multiaddr], true).await;
+                        Ok(())
+                    }
+                    _ => self.connect_to_peer(&addr).await,
+                },
+                Err(_) => Err(NetworkError::InvalidAddress(addr.clone())),
+            };
+            if let Err(e) = result {
+                warn!("Failed to dial bootstrap node {}: {}", addr, e);
+            }
+        }
+        self.redial();
+
+        if !self.bootstrap_nodes.is_empty() {
+            if let Err(e) = self.swarm.behaviour_mut().kademlia.bootstrap() {
+                warn!("Kademlia bootstrap failed: {:?}", e);
+            }
+        }
+
+        let mut peer_refresh = tokio::time::interval(PEER_REFRESH_INTERVAL);
+        let mut vote_flush = tokio::time::interval(VOTE_FLUSH_INTERVAL);
+        let mut tx_flush = tokio::time::interval(TX_FLUSH_INTERVAL);
+        let mut mempool_events = self.peer_store.as_ref().map(StorageManager::subscribe_mempool);
+        let mut chain_events = self.chain_events.take();
+
         loop {
-            tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
-            
-            // Simulate network activity
-            debug!("Network manager running on port {}", self.port);
+            tokio::select! {
+                event = self.swarm.select_next_some() => {
+                    self.handle_swarm_event(event).await;
+                }
+                message = self.outbound_rx.recv() => {
+                    if let Some(message) = message {
+                        self.publish(message).await?;
+                    }
+                }
+                _ = vote_flush.tick() => {
+                    self.flush_votes().await?;
+                }
+                event = next_mempool_event(&mut mempool_events) => {
+                    self.queue_transaction(event);
+                }
+                _ = tx_flush.tick() => {
+                    self.flush_transactions();
+                }
+                _ = next_finalized(&mut chain_events) => {
+                    self.update_handshake().await;
+                }
+                report = self.report_rx.recv() => {
+                    if let Some(report) = report {
+                        if let Some(peer) = self.reputation.report(&report) {
+                            self.ban_peer(peer, report.misbehaviour).await;
+                        }
+                    }
+                }
+                command = self.admin_rx.recv() => {
+                    if let Some(command) = command {
+                        self.handle_admin_command(command).await;
+                    }
+                }
+                event = self.nat_rx.recv() => {
+                    if let Some(event) = event {
+                        self.handle_nat_event(event);
+                    }
+                }
+                _ = peer_refresh.tick() => {
+                    self.refresh_peers().await;
+                }
+                _ = shutdown.recv() => {
+                    break;
+                }
+            }
+        }
+
+        // Publish what consensus already queued, such as our last votes
+        let mut drained = 0;
+        while let Ok(message) = self.outbound_rx.try_recv() {
+            self.publish(message).await?;
+            drained += 1;
+        }
+        drained += self.vote_batch.len();
+        self.flush_votes().await?;
+
+        // Publishing only queues the message; keep driving the swarm so it reaches peers
+        if drained > 0 {
+            let flush = tokio::time::sleep(SHUTDOWN_FLUSH);
+            tokio::pin!(flush);
+            loop {
+                tokio::select! {
+                    event = self.swarm.select_next_some() => {
+                        self.handle_swarm_event(event).await;
+                    }
+                    _ = &mut flush => break,
+                }
+            }
+        }
+
+        info!("Network manager stopped ({} queued messages published)", drained);
+        Ok(())
+    }
+
+    async fn handle_swarm_event(&mut self, event: SwarmEvent<ZkBehaviourEvent>) {
+        match event {
+            SwarmEvent::NewListenAddr { address, .. } => {
+                info!("📡 Listening on {}/p2p/{}", address, self.peer_id);
+            }
+            SwarmEvent::ConnectionEstablished { peer_id, endpoint, .. } => {
+                if self.reputation.is_banned(&peer_id) {
+                    debug!("Refusing banned peer {}", peer_id);
+                    let _ = self.swarm.disconnect_peer_id(peer_id);
+                    return;
+                }
+                debug!("Connected to peer {} at {}", peer_id, endpoint.get_remote_address());
+                self.awaiting_handshake.entry(peer_id).or_insert_with(Instant::now);
+                self.dialer.connected(&peer_id);
+                self.stats.connected_peers = self.swarm.connected_peers().count();
+            }
+            SwarmEvent::ConnectionClosed { peer_id, num_established, .. } => {
+                debug!("Disconnected from peer {}", peer_id);
+                if num_established == 0 {
+                    self.handshakes.remove(&peer_id);
+                    self.known_txs.remove(&peer_id);
+                    self.awaiting_handshake.remove(&peer_id);
+                    if let Ok(mut security) = self.security.lock() {
+                        security.remove(&peer_id);
+                    }
+                    self.reputation.forget(&peer_id);
+                    self.dialer.disconnected(&peer_id, Instant::now());
+                }
+                self.stats.connected_peers = self.swarm.connected_peers().count();
+            }
+            SwarmEvent::Behaviour(ZkBehaviourEvent::Gossipsub(gossipsub::Event::Message {
+                propagation_source,
+                message,
+                ..
+            })) => {
+                // Peers are only listened to once they passed the handshake, the author of what
+                // they relay included
+                if !self.handshakes.contains_key(&propagation_source) {
+                    debug!("Dropping message relayed by {} before its handshake", propagation_source);
+                    return;
+                }
+                // Messages are signed by their author, who is the one to blame for their content
+                let source = message.source.unwrap_or(propagation_source);
+                self.handle_gossip_message(source, &message.data).await;
+            }
+            SwarmEvent::Behaviour(ZkBehaviourEvent::Mdns(mdns::Event::Discovered(peers))) => {
+                for (peer_id, addr) in peers {
+                    debug!("mDNS discovered peer {} at {}", peer_id, addr);
+                    self.add_dial_target(peer_id, vec![addr.clone()], false).await;
+                    self.swarm.behaviour_mut().gossipsub.add_explicit_peer(&peer_id);
+                    self.swarm.behaviour_mut().kademlia.add_address(&peer_id, addr);
+                }
+            }
+            SwarmEvent::Behaviour(ZkBehaviourEvent::Mdns(mdns::Event::Expired(peers))) => {
+                for (peer_id, _) in peers {
+                    debug!("mDNS peer expired: {}", peer_id);
+                    self.swarm.behaviour_mut().gossipsub.remove_explicit_peer(&peer_id);
+                }
+            }
+            SwarmEvent::Behaviour(ZkBehaviourEvent::Identify(identify::Event::Received { peer_id, info })) => {
+                // Can arrive after the handshake got the peer refused
+                if self.reputation.is_banned(&peer_id) {
+                    return;
+                }
+                // Feed identified listen addresses into the DHT so peers are reachable by id
+                self.remember_peer(peer_id, &info.listen_addrs).await;
+                for addr in info.listen_addrs {
+                    self.swarm.behaviour_mut().kademlia.add_address(&peer_id, addr);
+                }
+            }
+            SwarmEvent::Behaviour(ZkBehaviourEvent::Handshake(event)) => {
+                self.handle_handshake(event).await;
+            }
+            SwarmEvent::Behaviour(ZkBehaviourEvent::Transactions(event)) => {
+                self.handle_transactions(event).await;
+            }
+            SwarmEvent::Behaviour(ZkBehaviourEvent::Upnp(event)) => {
+                self.handle_upnp(event);
+            }
+            SwarmEvent::Behaviour(ZkBehaviourEvent::RelayClient(relay::client::Event::ReservationReqAccepted {
+                relay_peer_id, renewal: false, ..
+            })) => {
+                info!("🔁 Reachable through relay {}", relay_peer_id);
+            }
+            SwarmEvent::Behaviour(ZkBehaviourEvent::RelayServer(relay::Event::CircuitReqAccepted { src_peer_id, dst_peer_id })) => {
+                debug!("Relaying {} to {}", src_peer_id, dst_peer_id);
+            }
+            SwarmEvent::Behaviour(ZkBehaviourEvent::Dcutr(dcutr::Event { remote_peer_id, result })) => match result {
+                Ok(_) => info!("🕳️ Direct connection to {} through hole punching", remote_peer_id),
+                Err(e) => debug!("Hole punching to {} failed: {}", remote_peer_id, e),
+            },
+            SwarmEvent::ListenerClosed { listener_id, reason, .. } => {
+                if let Some(relay) = self.relay_listeners.remove(&listener_id) {
+                    match reason {
+                        Ok(()) => warn!("🔁 Relay {} is unreachable or refused our reservation", relay),
+                        Err(e) => warn!("🔁 Lost the circuit listener on relay {}: {}", relay, e),
+                    }
+                }
+            }
+            // The connection may have been refused after its upgrade recorded a security, and
+            // nothing says for which peer
+            SwarmEvent::IncomingConnectionError { .. } => {
+                if let Ok(mut security) = self.security.lock() {
+                    security.retain(|peer, _| self.swarm.is_connected(peer));
+                }
+            }
+            SwarmEvent::OutgoingConnectionError { peer_id, error, .. } => {
+                warn!("Outgoing connection to {:?} failed: {}", peer_id, error);
+                if let Some(peer_id) = peer_id {
+                    // Refused after its upgrade, like an incoming one
+                    if !self.swarm.is_connected(&peer_id) {
+                        if let Ok(mut security) = self.security.lock() {
+                            security.remove(&peer_id);
+                        }
+                    }
+                    if self.dialer.failed(&peer_id, Instant::now()) {
+                        info!("Giving up on unreachable peer {}", peer_id);
+                        self.forget_peer(&peer_id).await;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Peers on another chain or protocol version are dropped before they can send us anything
+    // we would misread; compatible ones tell block sync how far ahead they are
+    async fn handle_handshake(&mut self, event: handshake::Event) {
+        let (peer_id, theirs) = match event {
+            handshake::Event::Received { peer_id, handshake } => (peer_id, handshake),
+            handshake::Event::Failed { peer_id, error } => {
+                warn!("🤝 Handshake with {} failed: {}", peer_id, error);
+                let _ = self.swarm.disconnect_peer_id(peer_id);
+                return;
+            }
+        };
+        self.awaiting_handshake.remove(&peer_id);
+
+        let ours = local_handshake(self.peer_store.clone(), self.compression).await;
+        if let Err(e) = ours.check(&theirs) {
+            warn!("🤝 Refusing peer {}: {}", peer_id, e);
+            self.reputation.ban(peer_id, INCOMPATIBLE_BAN);
+            let _ = self.swarm.disconnect_peer_id(peer_id);
+            self.forget_peer(&peer_id).await;
+            return;
+        }
+
+        debug!("🤝 Handshake with {} done, finalized head {:?}", peer_id, theirs.head.map(|(number, _)| number));
+        if let (Some((finalized_height, _)), Some(node_id), Some(peer_head_tx)) =
+            (theirs.head, handshake::node_id(&peer_id), &self.peer_head_tx)
+        {
+            let _ = peer_head_tx.send(PeerHead { node_id, finalized_height }).await;
+        }
+        self.handshakes.insert(peer_id, theirs);
+        self.known_txs.insert(peer_id, SeenCache::new(KNOWN_TXS_PER_PEER));
+        self.announce_pool(peer_id).await;
+    }
+
+    async fn update_handshake(&mut self) {
+        let handshake = local_handshake(self.peer_store.clone(), self.compression).await;
+        self.swarm.behaviour_mut().handshake.set_local(handshake);
+    }
+
+    async fn handle_gossip_message(&mut self, source: PeerId, data: &[u8]) {
+        self.stats.messages_received += 1;
+        self.stats.bytes_received += data.len() as u64;
+
+        let wire = match framing::decode_frame(data, self.max_message_bytes) {
+            Ok(wire) => wire,
+            Err(e) => {
+                warn!("Dropping malformed message from {}: {}", source, e);
+                if self.reputation.penalize(source, Misbehaviour::Undecodable) {
+                    self.ban_peer(source, Misbehaviour::Undecodable).await;
+                }
+                return;
+            }
+        };
+
+        // Hashed uncompressed, so a message has the same id however its author framed it
+        let hash: MessageHash = Sha256::digest(&wire).into();
+
+        // Late duplicates and replays of anything we already handled, including our own messages.
+        // Gossip relays them through every peer, so they do not count against the one relaying.
+        // Messages are only remembered once delivered, see deliver
+        if self.seen.check(hash) {
+            self.stats.duplicates_dropped += 1;
+            debug!("Dropping duplicate message from {}", source);
+            return;
+        }
+
+        if self.reputation.record_message(source, hash) {
+            self.ban_peer(source, Misbehaviour::Flooding).await;
+            return;
+        }
+
+        let message = match ConsensusMessage::from_wire(&wire) {
+            Ok(message) => message,
+            Err(e) => {
+                warn!("Dropping undecodable message from {}: {}", source, e);
+                if self.reputation.penalize(source, Misbehaviour::Undecodable) {
+                    self.ban_peer(source, Misbehaviour::Undecodable).await;
+                }
+                return;
+            }
+        };
+
+        match message {
+            ConsensusMessage::VoteBundle(bundle) => self.unbundle_votes(source, bundle).await,
+            message => self.deliver(source, message, hash).await,
+        }
+    }
+
+    // Each vote is handled as if it had been gossiped on its own, so duplicates, rate limits
+    // and consensus reports work per vote
+    async fn unbundle_votes(&mut self, source: PeerId, bundle: VoteBundle) {
+        if bundle.votes.len() > MAX_BUNDLE_VOTES {
+            warn!("Dropping bundle of {} votes from {}", bundle.votes.len(), source);
+            if self.reputation.penalize(source, Misbehaviour::Undecodable) {
+                self.ban_peer(source, Misbehaviour::Undecodable).await;
+            }
+            return;
+        }
+
+        for vote in bundle.votes {
+            let message = ConsensusMessage::BlockVote(vote);
+            let hash = message_id(&message);
+            if self.seen.check(hash) {
+                self.stats.duplicates_dropped += 1;
+                continue;
+            }
+            self.reputation.remember(source, hash);
+            self.deliver(source, message, hash).await;
+        }
+    }
+
+    // A message dropped over the rate limit is not marked seen, so the same message relayed
+    // by another peer still gets through
+    async fn deliver(&mut self, source: PeerId, message: ConsensusMessage, hash: MessageHash) {
+        if let Some(class) = MessageClass::of(&message) {
+            if !self.within_budget(source, class).await {
+                return;
+            }
+        }
+        self.seen.insert(hash);
+
+        // Votes are signed as they are cast, unlike blocks which wait for their proof
+        if let ConsensusMessage::BlockVote(vote) = &message {
+            if let Ok(latency) = (Utc::now() - vote.timestamp).to_std() {
+                self.reputation.record_latency(source, latency);
+            }
+        }
+
+        debug!("Received message from {}", source);
+        // Other blocks are dropped when the engine falls behind; they can be synced later
+        let requested = match &message {
+            ConsensusMessage::BlocksResponse(response) => self.sync_requests.check(response.request_id),
+            _ => false,
+        };
+        let sent = match requested {
+            true => self.consensus_tx.send_awaited(message).await,
+            false => self.consensus_tx.send(message).await,
+        };
+        if let Err(e) = sent {
+            error!("Failed to send message to consensus engine: {}", e);
+        }
+    }
+
+    // Whether a message of the class from the peer is to be handled at all
+    async fn within_budget(&mut self, source: PeerId, class: MessageClass) -> bool {
+        match self.rate_limiter.check(source, class) {
+            RateDecision::Allow => true,
+            RateDecision::Drop => {
+                self.stats.rate_limited += 1;
+                debug!("Dropping {:?} message from {} over the rate limit", class, source);
+                false
+            }
+            RateDecision::Ban(duration) => {
+                self.stats.rate_limited += 1;
+                if self.reputation.ban(source, duration) {
+                    self.ban_peer(source, Misbehaviour::Flooding).await;
+                }
+                false
+            }
+        }
+    }
+
+    // Transactions a peer pushed to us; the ones new to us go on to the mempool
+    async fn handle_transactions(&mut self, event: tx_gossip::Event) {
+        let (peer_id, transactions) = match event {
+            tx_gossip::Event::Received { peer_id, transactions } => (peer_id, transactions),
+            tx_gossip::Event::Failed { peer_id, error } => {
+                debug!("Could not push transactions to {}: {}", peer_id, error);
+                return;
+            }
+        };
+        let tx_sink = match &self.tx_sink {
+            Some(tx_sink) => tx_sink.clone(),
+            None => return,
+        };
+        // Peers are only listened to once they passed the handshake
+        if !self.known_txs.contains_key(&peer_id) {
+            return;
+        }
+        if transactions.len() > MAX_TX_BATCH {
+            warn!("Dropping batch of {} transactions from {}", transactions.len(), peer_id);
+            if self.reputation.penalize(peer_id, Misbehaviour::Undecodable) {
+                self.ban_peer(peer_id, Misbehaviour::Undecodable).await;
+            }
+            return;
+        }
+        if !self.within_budget(peer_id, MessageClass::Transactions).await {
+            return;
+        }
+
+        let now = Utc::now();
+        let mut fresh = Vec::new();
+        for transaction in transactions {
+            // The id is what peers and the seen cache go by, so it has to name the contents
+            if transaction.id != transaction.content_id() {
+                warn!("Dropping transaction from {} whose id does not match its contents", peer_id);
+                if self.reputation.penalize(peer_id, Misbehaviour::Undecodable) {
+                    self.ban_peer(peer_id, Misbehaviour::Undecodable).await;
+                    return;
+                }
+                continue;
+            }
+            if let Some(known) = self.known_txs.get_mut(&peer_id) {
+                known.insert(transaction.id);
+            }
+            // Only marked as seen once the mempool admits it (queue_transaction), so one dropped
+            // here for a full intake or rejected for now can still arrive again
+            let known = self.seen_txs.check(transaction.id);
+            self.reputation.record_transaction(peer_id, known, (now - transaction.timestamp).to_std().ok());
+            self.stats.txs_received += 1;
+            match known {
+                true => self.stats.txs_known += 1,
+                false => fresh.push(transaction),
+            }
+        }
+        if fresh.is_empty() {
+            return;
+        }
+
+        debug!("Received {} new transactions from {}", fresh.len(), peer_id);
+        // A busy mempool must not hold up consensus gossip; what is dropped here may still
+        // reach us in a block
+        if let Err(e) = tx_sink.try_send(fresh) {
+            warn!("Dropping transactions from {}, the mempool intake is full: {}", peer_id, e);
+        }
+    }
+
+    // Transactions that entered our mempool, whether over RPC, from a peer or back from a
+    // reorganized block
+    fn queue_transaction(&mut self, event: MempoolEvent) {
+        if let MempoolEvent::TxAdded(transaction) = event {
+            self.seen_txs.insert(transaction.id);
+            self.tx_queue.push(transaction);
+        }
+    }
+
+    // Every peer gets the queued transactions it is not known to have
+    fn flush_transactions(&mut self) {
+        if self.tx_queue.is_empty() {
+            return;
+        }
+        let queued = std::mem::take(&mut self.tx_queue);
+        let peers: Vec<PeerId> = self.known_txs.keys().copied().collect();
+        for peer in peers {
+            self.push_transactions(peer, &queued);
+        }
+    }
+
+    // A peer that just connected gets our whole pool, which it may have missed
+    async fn announce_pool(&mut self, peer: PeerId) {
+        let storage = match &self.peer_store {
+            Some(storage) => storage,
+            None => return,
+        };
+        match storage.get_pending_transactions().await {
+            Ok(pending) => self.push_transactions(peer, &pending),
+            Err(e) => warn!("Failed to read the mempool for {}: {}", peer, e),
+        }
+    }
+
+    // Sends the transactions the peer lacks in batches under max_message_bytes, and from then
+    // on counts them as known to it
+    fn push_transactions(&mut self, peer: PeerId, transactions: &[Transaction]) {
+        let known = match self.known_txs.get_mut(&peer) {
+            Some(known) => known,
+            None => return,
+        };
+        let missing: Vec<&Transaction> = transactions.iter().filter(|tx| !known.insert(tx.id)).collect();
+        if missing.is_empty() {
+            return;
+        }
+        let compression = match self.handshakes.get(&peer) {
+            Some(handshake) if handshake.compression.contains(&self.compression) => self.compression,
+            _ => Compression::None,
+        };
+
+        // The wire version and the length of the list come first
+        let mut batch = Vec::new();
+        let mut bytes = 5;
+        for transaction in missing {
+            let size = transaction.encode().len();
+            if size + 5 > self.max_message_bytes {
+                self.stats.oversized_dropped += 1;
+                continue;
+            }
+            if batch.len() == MAX_TX_BATCH || bytes + size > self.max_message_bytes {
+                self.send_batch(peer, std::mem::take(&mut batch), compression);
+                bytes = 5;
+            }
+            bytes += size;
+            batch.push(transaction.clone());
         }
+        if !batch.is_empty() {
+            self.send_batch(peer, batch, compression);
+        }
+    }
+
+    fn send_batch(&mut self, peer: PeerId, batch: Vec<Transaction>, compression: Compression) {
+        let count = batch.len();
+        self.swarm.behaviour_mut().transactions.send(peer, tx_gossip::encode_batch(batch, compression));
+        self.reputation.record_txs_sent(peer, count);
+        self.stats.txs_sent += count as u64;
     }
-    
+
     // Public methods for broadcasting messages
     pub async fn broadcast_block(&mut self, block: &Block) -> Result<()> {
         let message = ConsensusMessage::NewBlock(block.clone());
@@ -54,73 +888,408 @@ impl<'a> NetworkManager<'a> {
         info!("Broadcasted block {}", block.header.block_number);
         Ok(())
     }
-    
+
     pub async fn broadcast_vote(&mut self, vote: &BlockVote) -> Result<()> {
         let message = ConsensusMessage::BlockVote(vote.clone());
         self.broadcast_message(&message).await?;
         debug!("Broadcasted vote for block {:?}", vote.block_hash);
         Ok(())
     }
-    
-    pub async fn broadcast_consensus_state(&mut self, state: &ConsensusState) -> Result<()> {
-        let message = ConsensusMessage::ConsensusState(state.clone());
-        self.broadcast_message(&message).await?;
-        debug!("Broadcasted consensus state");
-        Ok(())
-    }
-    
+
     pub async fn broadcast_proof_request(&mut self, request: &crate::types::ProofRequest) -> Result<()> {
         let message = ConsensusMessage::ZKProofRequest(request.clone());
         self.broadcast_message(&message).await?;
         debug!("Broadcasted proof request for block {}", request.block_number);
         Ok(())
     }
-    
+
     pub async fn broadcast_proof_response(&mut self, response: &crate::types::ProofResponse) -> Result<()> {
         let message = ConsensusMessage::ZKProofResponse(response.clone());
         self.broadcast_message(&message).await?;
         debug!("Broadcasted proof response");
         Ok(())
     }
-    
+
+    // Votes wait for the next flush; everything else goes out right away
+    async fn publish(&mut self, message: ConsensusMessage) -> Result<()> {
+        if let ConsensusMessage::GetBlocks(request) = &message {
+            self.sync_requests.insert(request.request_id);
+        }
+        match message {
+            ConsensusMessage::BlockVote(vote) => {
+                self.vote_batch.push(vote);
+                if self.vote_batch.len() >= MAX_BUNDLE_VOTES {
+                    self.flush_votes().await?;
+                }
+                Ok(())
+            }
+            message => self.broadcast_message(&message).await,
+        }
+    }
+
+    async fn flush_votes(&mut self) -> Result<()> {
+        let message = match self.vote_batch.len() {
+            0 => return Ok(()),
+            // A lone vote goes out as is
+            1 => ConsensusMessage::BlockVote(self.vote_batch.remove(0)),
+            _ => ConsensusMessage::VoteBundle(VoteBundle { votes: std::mem::take(&mut self.vote_batch) }),
+        };
+        self.broadcast_message(&message).await
+    }
+
     async fn broadcast_message(&mut self, message: &ConsensusMessage) -> Result<()> {
-        // Mock broadcasting - in real implementation this would use libp2p
-        debug!("Mock broadcasting message: {:?}", message);
-        
-        // For now, just send to our own consensus engine
-        if let Err(e) = self.consensus_tx.send(message.clone()).await {
-            error!("Failed to send message to consensus engine: {}", e);
+        let wire = message.to_wire();
+        if wire.len() > self.max_message_bytes {
+            self.stats.oversized_dropped += 1;
+            warn!("Not gossiping a message of {} bytes, over the limit of {}", wire.len(), self.max_message_bytes);
+            return Ok(());
+        }
+        self.seen.insert(Sha256::digest(&wire).into());
+        let data = framing::encode_frame(&wire, self.outbound_compression());
+        let len = data.len() as u64;
+
+        match self.swarm.behaviour_mut().gossipsub.publish(self.topic.clone(), data) {
+            Ok(_) => {
+                self.stats.messages_sent += 1;
+                self.stats.bytes_sent += len;
+            }
+            // Nobody to gossip to yet is expected on a fresh or single-node network
+            Err(gossipsub::PublishError::InsufficientPeers) => {
+                debug!("No peers subscribed yet, message not gossiped");
+            }
+            Err(e) => warn!("Failed to publish message: {}", e),
         }
-        
+
         Ok(())
     }
-    
+
+    // Gossip sends the same bytes to every peer, so we only compress once each peer that has
+    // finished the handshake accepts our codec
+    fn outbound_compression(&self) -> Compression {
+        match self.compression {
+            Compression::None => Compression::None,
+            codec if self.handshakes.values().all(|handshake| handshake.compression.contains(&codec)) => codec,
+            _ => Compression::None,
+        }
+    }
+
     // Utility methods
-    pub fn get_peer_id(&self) -> &str {
-        &self.peer_id
+    pub fn get_peer_id(&self) -> String {
+        self.peer_id.to_string()
     }
-    
-    pub fn get_connected_peers(&self) -> Vec<String> {
-        // Mock connected peers
-        vec![self.peer_id.clone()]
+
+    pub fn get_connected_peers(&self) -> Vec<PeerInfo> {
+        let security = match self.security.lock() {
+            Ok(security) => security.clone(),
+            Err(_) => HashMap::new(),
+        };
+        self.swarm.connected_peers()
+            .map(|peer| {
+                let mut info = self.reputation.peer_info(peer);
+                info.finalized_height = self.handshakes.get(peer).and_then(|handshake| handshake.head).map(|(number, _)| number);
+                info.security = security.get(peer).copied();
+                info
+            })
+            .collect()
     }
-    
-    pub async fn connect_to_peer(&mut self, addr: &str) -> Result<()> {
-        info!("Mock connecting to peer: {}", addr);
+
+    async fn ban_peer(&mut self, peer: PeerId, misbehaviour: Misbehaviour) {
+        warn!("🚫 Banning peer {} after {:?} (score {:.1})",
+            peer, misbehaviour, self.reputation.peer_info(&peer).score);
+        self.swarm.behaviour_mut().gossipsub.blacklist_peer(&peer);
+        let _ = self.swarm.disconnect_peer_id(peer);
+        self.forget_peer(&peer).await;
+    }
+
+    async fn handle_admin_command(&mut self, command: NetworkCommand) {
+        match command {
+            NetworkCommand::Ban { peer, duration, reply } => {
+                let _ = reply.send(self.ban_by_operator(&peer, duration).await);
+            }
+        }
+    }
+
+    async fn ban_by_operator(&mut self, peer: &str, duration: Duration) -> Result<(), NetworkError> {
+        let peer: PeerId = peer.parse().map_err(|_| NetworkError::InvalidPeerId(peer.to_string()))?;
+        if !self.reputation.ban(peer, duration) {
+            return Err(NetworkError::AlreadyBanned(peer));
+        }
+
+        warn!("🚫 Banning peer {} for {}s on operator request", peer, duration.as_secs());
+        self.swarm.behaviour_mut().gossipsub.blacklist_peer(&peer);
+        let _ = self.swarm.disconnect_peer_id(peer);
+        self.forget_peer(&peer).await;
         Ok(())
     }
-    
+
+    async fn refresh_peers(&mut self) {
+        for peer in self.reputation.expire_bans() {
+            info!("Ban on peer {} expired", peer);
+            self.swarm.behaviour_mut().gossipsub.remove_blacklisted_peer(&peer);
+        }
+        self.rate_limiter.prune();
+        self.redial();
+        self.listen_on_relays();
+
+        let now = Instant::now();
+        let late: Vec<PeerId> = self.awaiting_handshake.iter()
+            .filter(|(_, connected)| now.duration_since(**connected) > HANDSHAKE_DEADLINE)
+            .map(|(peer, _)| *peer)
+            .collect();
+        for peer in late {
+            warn!("🤝 Disconnecting peer {}: no handshake within {}s", peer, HANDSHAKE_DEADLINE.as_secs());
+            self.awaiting_handshake.remove(&peer);
+            let _ = self.swarm.disconnect_peer_id(peer);
+        }
+        *self.peers.write().await = self.get_connected_peers();
+    }
+
+    // Configured external addresses are advertised as they are. Mapping a port is left to UPnP,
+    // with NAT-PMP as the fallback, and relays make us reachable when neither works
+    fn start_nat_traversal(&mut self) {
+        for address in self.external_addresses.clone() {
+            info!("📣 Advertising external address {}", address);
+            self.swarm.add_external_address(address);
+        }
+        if !self.nat.upnp && self.nat.nat_pmp {
+            self.start_nat_pmp();
+        }
+        self.listen_on_relays();
+    }
+
+    fn start_nat_pmp(&mut self) {
+        if self.nat_pmp_started {
+            return;
+        }
+        self.nat_pmp_started = true;
+        tokio::spawn(nat::run_nat_pmp(self.port, self.nat_tx.clone()));
+    }
+
+    // The relay client dials the relay and reserves a slot; peers then dial us through it
+    fn listen_on_relays(&mut self) {
+        for relay in &self.nat.relays {
+            let relay = match nat::relay_peer(relay) {
+                Some((_, relay)) => relay,
+                None => continue,
+            };
+            if self.relay_listeners.values().any(|listening| *listening == relay) {
+                continue;
+            }
+            match self.swarm.listen_on(nat::circuit_address(&relay)) {
+                Ok(listener_id) => {
+                    self.relay_listeners.insert(listener_id, relay);
+                }
+                Err(e) => warn!("🔁 Could not listen through relay {}: {}", relay, e),
+            }
+        }
+    }
+
+    fn handle_upnp(&mut self, event: upnp::Event) {
+        match event {
+            upnp::Event::NewExternalAddr(address) => info!("🌐 UPnP mapped {}", address),
+            upnp::Event::ExpiredExternalAddr(address) => warn!("🌐 UPnP mapping of {} expired", address),
+            upnp::Event::GatewayNotFound => {
+                match self.nat.nat_pmp {
+                    true => {
+                        debug!("No UPnP gateway found, trying NAT-PMP");
+                        self.start_nat_pmp();
+                    }
+                    false => info!("🌐 No UPnP gateway found"),
+                }
+            }
+            upnp::Event::NonRoutableGateway => {
+                warn!("🌐 The UPnP gateway is behind another NAT; configure network.nat.relays to be reachable");
+            }
+        }
+    }
+
+    fn handle_nat_event(&mut self, event: NatEvent) {
+        match event {
+            NatEvent::Mapped(address) => self.swarm.add_external_address(address),
+            NatEvent::Expired(address) => self.swarm.remove_external_address(&address),
+        }
+    }
+
+    async fn load_known_peers(&mut self) {
+        let storage = match &self.peer_store {
+            Some(storage) => storage,
+            None => return,
+        };
+        let known = match storage.get_known_peers().await {
+            Ok(known) => known,
+            Err(e) => {
+                warn!("Failed to load known peers: {}", e);
+                return;
+            }
+        };
+
+        for (peer_id, peer) in &known {
+            let peer_id: PeerId = match peer_id.parse() {
+                Ok(peer_id) => peer_id,
+                Err(_) => continue,
+            };
+            let addrs: Vec<Multiaddr> = peer.addrs.iter().filter_map(|addr| addr.parse().ok()).collect();
+            for addr in &addrs {
+                self.swarm.behaviour_mut().kademlia.add_address(&peer_id, addr.clone());
+            }
+            self.add_dial_target(peer_id, addrs, false).await;
+        }
+        if !known.is_empty() {
+            info!("📒 Redialing {} peers known from earlier runs", known.len());
+        }
+    }
+
+    // Dials known peers we are not connected to, while there is room for more connections
+    fn redial(&mut self) {
+        let connected = self.swarm.connected_peers().count();
+        if connected >= self.max_peers {
+            return;
+        }
+
+        let limit = MAX_DIALS_PER_REFRESH.min(self.max_peers - connected);
+        let swarm = &self.swarm;
+        let reputation = &self.reputation;
+        let due = self.dialer.due(Instant::now(), limit, |peer| {
+            swarm.is_connected(peer) || reputation.is_banned(peer)
+        });
+        for (peer_id, addrs) in due {
+            debug!("Dialing known peer {}", peer_id);
+            if let Err(e) = self.swarm.dial(DialOpts::peer_id(peer_id).addresses(addrs).build()) {
+                debug!("Could not dial {}: {}", peer_id, e);
+            }
+        }
+    }
+
+    // Peers the dialer drops to make room are forgotten. Returns false when the peer itself was
+    // dropped
+    async fn add_dial_target(&mut self, peer_id: PeerId, addrs: Vec<Multiaddr>, bootstrap: bool) -> bool {
+        let reputation = &self.reputation;
+        let dropped = self.dialer.add(peer_id, addrs, bootstrap, Instant::now(), |peer| reputation.score(peer));
+        for peer in &dropped {
+            debug!("Dropping peer {} to stay within {} known peers", peer, MAX_DIAL_TARGETS);
+            self.forget_peer(peer).await;
+        }
+        !dropped.contains(&peer_id)
+    }
+
+    // Peers that told us where they listen are kept for the next start
+    async fn remember_peer(&mut self, peer_id: PeerId, addrs: &[Multiaddr]) {
+        if !self.add_dial_target(peer_id, addrs.to_vec(), false).await {
+            return;
+        }
+
+        if let Some(storage) = &self.peer_store {
+            let peer = KnownPeer {
+                addrs: addrs.iter().map(|addr| addr.to_string()).collect(),
+                last_seen: Utc::now(),
+            };
+            if let Err(e) = storage.store_peer(&peer_id.to_string(), &peer).await {
+                warn!("Failed to store peer {}: {}", peer_id, e);
+            }
+        }
+    }
+
+    async fn forget_peer(&mut self, peer_id: &PeerId) {
+        self.dialer.remove(peer_id);
+
+        if let Some(storage) = &self.peer_store {
+            if let Err(e) = storage.remove_peer(&peer_id.to_string()).await {
+                warn!("Failed to remove peer {}: {}", peer_id, e);
+            }
+        }
+    }
+
+    pub fn get_stats(&self) -> &NetworkStats {
+        &self.stats
+    }
+
+    pub async fn connect_to_peer(&mut self, addr: &str) -> Result<(), NetworkError> {
+        let addr: Multiaddr = addr.parse().map_err(|_| NetworkError::InvalidAddress(addr.to_string()))?;
+
+        if let Some(Protocol::P2p(peer_id)) = addr.iter().last() {
+            self.swarm.behaviour_mut().kademlia.add_address(&peer_id, addr.clone());
+        }
+
+        self.swarm.dial(addr.clone())?;
+        info!("Dialing peer: {}", addr);
+        Ok(())
+    }
+
     pub async fn disconnect_from_peer(&mut self, peer_id: &str) -> Result<()> {
-        info!("Mock disconnecting from peer: {}", peer_id);
+        let peer_id: PeerId = peer_id.parse()?;
+        self.swarm.disconnect_peer_id(peer_id)
+            .map_err(|_| anyhow!("Not connected to peer {}", peer_id))?;
+        info!("Disconnected from peer: {}", peer_id);
         Ok(())
     }
 }
 
-// Helper function to generate a deterministic topic hash
-fn hash_topic(topic: &str) -> u64 {
-    let mut hasher = DefaultHasher::new();
-    topic.hash(&mut hasher);
-    hasher.finish()
+// Returns once a block was finalized; a receiver that lagged missed some, so one was
+async fn next_finalized(events: &mut Option<broadcast::Receiver<ChainEvent>>) {
+    let events = match events {
+        Some(events) => events,
+        None => return std::future::pending().await,
+    };
+    loop {
+        match events.recv().await {
+            Ok(ChainEvent::BlockFinalized(_)) | Err(broadcast::error::RecvError::Lagged(_)) => return,
+            Ok(_) => {}
+            Err(broadcast::error::RecvError::Closed) => return std::future::pending().await,
+        }
+    }
+}
+
+// Next change to the mempool; never resolves on light clients, which have none. A subscriber
+// that fell behind skips what it missed, as peers that lack those transactions get them when
+// they reconnect
+async fn next_mempool_event(events: &mut Option<broadcast::Receiver<MempoolEvent>>) -> MempoolEvent {
+    let events = match events {
+        Some(events) => events,
+        None => return std::future::pending().await,
+    };
+    loop {
+        match events.recv().await {
+            Ok(event) => return event,
+            Err(broadcast::error::RecvError::Lagged(missed)) => {
+                warn!("Missed {} mempool events, their transactions are not pushed to peers", missed);
+            }
+            Err(broadcast::error::RecvError::Closed) => return std::future::pending().await,
+        }
+    }
+}
+
+// Our genesis and finalized head as storage has them; light clients only know the chain id
+async fn local_handshake(storage: Option<StorageManager>, compression: Compression) -> Handshake {
+    let mut handshake = Handshake {
+        protocol_version: PROTOCOL_VERSION,
+        chain_id: types::chain_id(),
+        genesis_hash: None,
+        head: None,
+        compression: accepted_compression(compression),
+    };
+    if let Some(storage) = storage {
+        // Chains started without a genesis file begin at block 1, which has no parent
+        let genesis = match storage.get_block(0).await {
+            Ok(None) => storage.get_block(1).await.map(|block| block.filter(|block| block.header.parent_hash == [0; 32])),
+            result => result,
+        };
+        match genesis {
+            Ok(genesis) => handshake.genesis_hash = genesis.map(|block| block.hash()),
+            Err(e) => warn!("Failed to read the genesis block: {}", e),
+        }
+        match storage.get_finalized_block().await {
+            Ok(head) => handshake.head = head.map(|block| (block.header.block_number, block.hash())),
+            Err(e) => warn!("Failed to read the finalized block: {}", e),
+        }
+    }
+    handshake
+}
+
+fn accepted_compression(compression: Compression) -> Vec<Compression> {
+    match compression {
+        Compression::None => Vec::new(),
+        codec => vec![codec],
+    }
 }
 
 // Network statistics
@@ -131,23 +1300,37 @@ pub struct NetworkStats {
     pub messages_received: u64,
     pub bytes_sent: u64,
     pub bytes_received: u64,
+    pub duplicates_dropped: u64,
+    // Over a per-peer or global gossip budget
+    pub rate_limited: u64,
+    // Our messages over max_message_bytes, which were not gossiped
+    pub oversized_dropped: u64,
+    // Transactions peers pushed to us, those we already had, and those we pushed to peers
+    pub txs_received: u64,
+    pub txs_known: u64,
+    pub txs_sent: u64,
 }
 
 impl NetworkStats {
     pub fn new() -> Self {
         Self {
-            connected_peers: 1, // Mock: always connected to self
+            connected_peers: 0,
             messages_sent: 0,
             messages_received: 0,
             bytes_sent: 0,
             bytes_received: 0,
+            duplicates_dropped: 0,
+            rate_limited: 0,
+            oversized_dropped: 0,
+            txs_received: 0,
+            txs_known: 0,
+            txs_sent: 0,
         }
     }
 }
 
-// TODO: Implement real P2P network using libp2p when dependencies are properly configured
-// This will include:
-// - Real libp2p swarm with floodsub and mdns
-// - Proper peer discovery and connection management
-// - Message broadcasting and receiving
-// - Network event handling 
\ No newline at end of file
+// Id a message is remembered and reported under, so consensus can refer back to the peer it came
+// from. Hashed over the uncompressed wire payload, so it is the same however the message was framed
+pub fn message_id(message: &ConsensusMessage) -> MessageHash {
+    Sha256::digest(message.to_wire()).into()
+}
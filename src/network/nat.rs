@@ -0,0 +1,104 @@
+use anyhow::{bail, Result};
+use libp2p::{multiaddr::Protocol, Multiaddr, PeerId};
+use std::net::Ipv4Addr;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tracing::{info, debug, warn};
+
+// Lifetime asked for NAT-PMP mappings; they are renewed halfway through
+const NAT_PMP_LIFETIME: Duration = Duration::from_secs(2 * 60 * 60);
+// Gateways answer right away or not at all
+const NAT_PMP_TIMEOUT: Duration = Duration::from_secs(3);
+// Wait before trying again after a mapping failed
+const NAT_PMP_RETRY: Duration = Duration::from_secs(5 * 60);
+
+// What the NAT-PMP task found out about our reachability
+#[derive(Debug)]
+pub enum NatEvent {
+    Mapped(Multiaddr),
+    Expired(Multiaddr),
+}
+
+// Relays are given with the peer id we reserve a slot with, so the relay can be authenticated
+pub fn relay_peer(addr: &str) -> Option<(PeerId, Multiaddr)> {
+    let multiaddr: Multiaddr = addr.parse().ok()?;
+    match multiaddr.iter().last() {
+        Some(Protocol::P2p(peer_id)) => Some((peer_id, multiaddr)),
+        _ => None,
+    }
+}
+
+// Where peers reach us through a relay once it accepted our reservation
+pub fn circuit_address(relay: &Multiaddr) -> Multiaddr {
+    relay.clone().with(Protocol::P2pCircuit)
+}
+
+// Keeps a NAT-PMP mapping of our TCP port on the default gateway, for gateways that do not
+// speak UPnP. Runs until the network manager stops listening
+pub async fn run_nat_pmp(port: u16, events: mpsc::Sender<NatEvent>) {
+    let mut mapped: Option<Multiaddr> = None;
+    loop {
+        let wait = match map_port(port).await {
+            Ok((address, lifetime)) => {
+                if mapped.as_ref() != Some(&address) {
+                    info!("🌐 NAT-PMP mapped port {} to {}", port, address);
+                    if let Some(old) = mapped.replace(address.clone()) {
+                        let _ = events.send(NatEvent::Expired(old)).await;
+                    }
+                    if events.send(NatEvent::Mapped(address)).await.is_err() {
+                        return;
+                    }
+                }
+                lifetime / 2
+            }
+            Err(e) => {
+                match mapped.take() {
+                    Some(old) => {
+                        warn!("🌐 NAT-PMP mapping of port {} lapsed: {}", port, e);
+                        if events.send(NatEvent::Expired(old)).await.is_err() {
+                            return;
+                        }
+                    }
+                    None => debug!("NAT-PMP mapping of port {} failed: {}", port, e),
+                }
+                NAT_PMP_RETRY
+            }
+        };
+        tokio::time::sleep(wait).await;
+        if events.is_closed() {
+            return;
+        }
+    }
+}
+
+// The gateway's public address with the port it mapped, and for how long
+async fn map_port(port: u16) -> Result<(Multiaddr, Duration)> {
+    let mut client = natpmp::new_tokio_natpmp().await?;
+
+    client.send_public_address_request().await?;
+    let public_address = match read_response(&client).await? {
+        natpmp::Response::Gateway(gateway) => *gateway.public_address(),
+        other => bail!("unexpected NAT-PMP response {:?}", other),
+    };
+    if public_address.is_private() || public_address.is_unspecified() {
+        bail!("gateway {} is behind another NAT ({})", client.gateway(), public_address);
+    }
+
+    client.send_port_mapping_request(natpmp::Protocol::TCP, port, port, NAT_PMP_LIFETIME.as_secs() as u32).await?;
+    let mapping = match read_response(&client).await? {
+        natpmp::Response::TCP(mapping) => mapping,
+        other => bail!("unexpected NAT-PMP response {:?}", other),
+    };
+    Ok((tcp_address(public_address, mapping.public_port()), *mapping.lifetime()))
+}
+
+async fn read_response(client: &natpmp::NatpmpAsync<tokio::net::UdpSocket>) -> Result<natpmp::Response> {
+    match tokio::time::timeout(NAT_PMP_TIMEOUT, client.read_response_or_retry()).await {
+        Ok(response) => Ok(response?),
+        Err(_) => bail!("gateway {} did not answer", client.gateway()),
+    }
+}
+
+fn tcp_address(ip: Ipv4Addr, port: u16) -> Multiaddr {
+    Multiaddr::empty().with(Protocol::Ip4(ip)).with(Protocol::Tcp(port))
+}
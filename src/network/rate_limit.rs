@@ -0,0 +1,222 @@
+use crate::config::RateLimitConfig;
+use crate::types::ConsensusMessage;
+use libp2p::PeerId;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+// Buckets hold this many seconds' worth of budget, so short bursts pass
+const BURST_SECONDS: f64 = 2.0;
+// Messages over budget are counted per window; too many in one window gets the peer banned
+const VIOLATION_WINDOW: Duration = Duration::from_secs(10);
+
+// Gossip that costs us real work, budgeted separately so one kind can not starve another.
+// Responses to our own requests are only subject to the flooding window
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MessageClass {
+    Block,
+    Vote,
+    ProofRequest,
+    Transactions,
+}
+
+impl MessageClass {
+    pub fn of(message: &ConsensusMessage) -> Option<Self> {
+        match message {
+            ConsensusMessage::NewBlock(_) | ConsensusMessage::CompactBlock(_) => Some(Self::Block),
+            ConsensusMessage::BlockVote(_) | ConsensusMessage::Proposal(_) | ConsensusMessage::RoundChange(_) => {
+                Some(Self::Vote)
+            }
+            ConsensusMessage::ZKProofRequest(_) => Some(Self::ProofRequest),
+            ConsensusMessage::GetBlockTxs(_) | ConsensusMessage::BlockTxs(_) => Some(Self::Transactions),
+            _ => None,
+        }
+    }
+}
+
+pub enum RateDecision {
+    Allow,
+    // Over a budget; the message is dropped
+    Drop,
+    // The peer kept going over its budget and is to be banned
+    Ban(Duration),
+}
+
+#[derive(Debug)]
+struct TokenBucket {
+    tokens: f64,
+    // Tokens per second; zero disables the limit
+    rate: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    fn new(rate: u32, now: Instant) -> Self {
+        Self {
+            tokens: rate as f64 * BURST_SECONDS,
+            rate: rate as f64,
+            updated: now,
+        }
+    }
+
+    fn try_take(&mut self, now: Instant) -> bool {
+        if self.rate == 0.0 {
+            return true;
+        }
+
+        let elapsed = now.duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.rate * BURST_SECONDS);
+        self.updated = now;
+
+        if self.tokens < 1.0 {
+            return false;
+        }
+        self.tokens -= 1.0;
+        true
+    }
+}
+
+#[derive(Debug)]
+struct PeerBudget {
+    buckets: HashMap<MessageClass, TokenBucket>,
+    window_start: Instant,
+    violations: u32,
+    last_message: Instant,
+}
+
+// Token buckets per peer and message class, plus one per class shared by all peers
+#[derive(Debug)]
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    global: HashMap<MessageClass, TokenBucket>,
+    peers: HashMap<PeerId, PeerBudget>,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig) -> Self {
+        Self {
+            config,
+            global: HashMap::new(),
+            peers: HashMap::new(),
+        }
+    }
+
+    fn per_peer_rate(&self, class: MessageClass) -> u32 {
+        match class {
+            MessageClass::Block => self.config.blocks_per_peer,
+            MessageClass::Vote => self.config.votes_per_peer,
+            MessageClass::ProofRequest => self.config.proof_requests_per_peer,
+            MessageClass::Transactions => self.config.transactions_per_peer,
+        }
+    }
+
+    fn global_rate(&self, class: MessageClass) -> u32 {
+        match class {
+            MessageClass::Block => self.config.blocks_global,
+            MessageClass::Vote => self.config.votes_global,
+            MessageClass::ProofRequest => self.config.proof_requests_global,
+            MessageClass::Transactions => self.config.transactions_global,
+        }
+    }
+
+    pub fn check(&mut self, peer: PeerId, class: MessageClass) -> RateDecision {
+        let now = Instant::now();
+        let per_peer_rate = self.per_peer_rate(class);
+        let budget = self.peers.entry(peer).or_insert_with(|| PeerBudget {
+            buckets: HashMap::new(),
+            window_start: now,
+            violations: 0,
+            last_message: now,
+        });
+        budget.last_message = now;
+
+        if budget.buckets.entry(class).or_insert_with(|| TokenBucket::new(per_peer_rate, now)).try_take(now) {
+            // Checked after the peer's own budget, so a flooding peer does not use up the shared one
+            let global_rate = self.global_rate(class);
+            let global = self.global.entry(class).or_insert_with(|| TokenBucket::new(global_rate, now));
+            return if global.try_take(now) { RateDecision::Allow } else { RateDecision::Drop };
+        }
+
+        if now.duration_since(budget.window_start) >= VIOLATION_WINDOW {
+            budget.window_start = now;
+            budget.violations = 0;
+        }
+        budget.violations += 1;
+
+        if budget.violations == self.config.violations_before_ban + 1 {
+            return RateDecision::Ban(Duration::from_secs(self.config.ban_secs));
+        }
+        RateDecision::Drop
+    }
+
+    // Peers quiet for a whole violation window have full buckets and nothing counted against
+    // them, so dropping their budgets loses nothing
+    pub fn prune(&mut self) {
+        let now = Instant::now();
+        self.peers.retain(|_, budget| now.duration_since(budget.last_message) < VIOLATION_WINDOW);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(per_peer: u32, global: u32) -> RateLimitConfig {
+        RateLimitConfig {
+            blocks_per_peer: per_peer,
+            blocks_global: global,
+            violations_before_ban: 3,
+            ..RateLimitConfig::default()
+        }
+    }
+
+    // How many of `count` messages the limiter lets through
+    fn allowed(limiter: &mut RateLimiter, peer: PeerId, class: MessageClass, count: u32) -> u32 {
+        (0..count).filter(|_| matches!(limiter.check(peer, class), RateDecision::Allow)).count() as u32
+    }
+
+    #[test]
+    fn buckets_allow_a_burst_and_refill_at_their_rate() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(5, start);
+        assert_eq!((0..20).filter(|_| bucket.try_take(start)).count(), 10);
+
+        // Half a second earns 2.5 tokens, and a long pause no more than a burst
+        assert_eq!((0..20).filter(|_| bucket.try_take(start + Duration::from_millis(500))).count(), 2);
+        assert_eq!((0..20).filter(|_| bucket.try_take(start + Duration::from_secs(60))).count(), 10);
+
+        let mut unlimited = TokenBucket::new(0, start);
+        assert!((0..1000).all(|_| unlimited.try_take(start)));
+    }
+
+    #[test]
+    fn peers_and_classes_have_their_own_budgets() {
+        let mut limiter = RateLimiter::new(config(5, 1000));
+        let (flooding, other) = (PeerId::random(), PeerId::random());
+        assert_eq!(allowed(&mut limiter, flooding, MessageClass::Block, 20), 10);
+        assert_eq!(allowed(&mut limiter, flooding, MessageClass::Vote, 20), 20);
+        assert_eq!(allowed(&mut limiter, other, MessageClass::Block, 20), 10);
+    }
+
+    #[test]
+    fn the_global_budget_is_shared_by_all_peers() {
+        let mut limiter = RateLimiter::new(config(1000, 5));
+        let peers: Vec<_> = (0..4).map(|_| PeerId::random()).collect();
+        let total: u32 = peers.iter().map(|peer| allowed(&mut limiter, *peer, MessageClass::Block, 5)).sum();
+        assert_eq!(total, 10);
+        // Drops for lack of global budget are not the peer's doing
+        assert!(peers.iter().all(|peer| !matches!(limiter.check(*peer, MessageClass::Block), RateDecision::Ban(_))));
+    }
+
+    #[test]
+    fn peers_that_keep_flooding_are_banned_once() {
+        let mut limiter = RateLimiter::new(config(5, 1000));
+        let peer = PeerId::random();
+        assert_eq!(allowed(&mut limiter, peer, MessageClass::Block, 10), 10);
+
+        for _ in 0..3 {
+            assert!(matches!(limiter.check(peer, MessageClass::Block), RateDecision::Drop));
+        }
+        assert!(matches!(limiter.check(peer, MessageClass::Block), RateDecision::Ban(ban) if ban == Duration::from_secs(600)));
+        assert!(matches!(limiter.check(peer, MessageClass::Block), RateDecision::Drop));
+    }
+}
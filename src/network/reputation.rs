@@ -0,0 +1,298 @@
+use super::security::TransportSecurity;
+use libp2p::PeerId;
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+pub type MessageHash = [u8; 32];
+
+// Peers are disconnected and refused for BAN_DURATION once their score drops to this
+const BAN_THRESHOLD: f64 = -100.0;
+const BAN_DURATION: Duration = Duration::from_secs(60 * 60);
+// Penalties wear off at this rate, so a peer that behaves again recovers
+const RECOVERY_PER_SECOND: f64 = 0.1;
+// More gossip than this from one peer within a window counts as flooding
+const RATE_WINDOW: Duration = Duration::from_secs(10);
+const MAX_MESSAGES_PER_WINDOW: u32 = 500;
+// Recently received messages, remembered so consensus reports can be traced to their peer
+const MAX_TRACKED_MESSAGES: usize = 4096;
+// Weight of the newest sample in the latency average
+const LATENCY_SMOOTHING: f64 = 0.2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum Misbehaviour {
+    InvalidBlock,
+    InvalidProof,
+    InvalidVote,
+    Undecodable,
+    Flooding,
+}
+
+impl Misbehaviour {
+    fn penalty(self) -> f64 {
+        match self {
+            Misbehaviour::InvalidBlock => 40.0,
+            Misbehaviour::InvalidProof => 50.0,
+            Misbehaviour::InvalidVote => 20.0,
+            Misbehaviour::Undecodable => 10.0,
+            Misbehaviour::Flooding => 25.0,
+        }
+    }
+}
+
+// Sent by the consensus engine for a gossiped message it rejected
+#[derive(Debug, Clone)]
+pub struct PeerReport {
+    pub message_id: MessageHash,
+    pub misbehaviour: Misbehaviour,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PeerInfo {
+    pub peer_id: String,
+    pub score: f64,
+    pub invalid_blocks: u64,
+    pub invalid_proofs: u64,
+    pub invalid_votes: u64,
+    pub undecodable_messages: u64,
+    pub messages_received: u64,
+    // Over the last complete rate window
+    pub messages_per_second: f64,
+    // Average delay between a vote being cast and reaching us
+    pub latency_ms: Option<f64>,
+    // Transactions the peer pushed to us, and how many of those we already had
+    pub txs_received: u64,
+    pub txs_known: u64,
+    // Average delay between a transaction being signed and reaching us, over those the peer
+    // was first to bring
+    pub tx_latency_ms: Option<f64>,
+    // Transactions we pushed to the peer
+    pub txs_sent: u64,
+    // Finalized head the peer announced in its handshake
+    pub finalized_height: Option<u64>,
+    // Encryption the connection negotiated
+    pub security: Option<TransportSecurity>,
+}
+
+#[derive(Debug)]
+struct PeerRecord {
+    score: f64,
+    updated: Instant,
+    invalid_blocks: u64,
+    invalid_proofs: u64,
+    invalid_votes: u64,
+    undecodable_messages: u64,
+    messages_received: u64,
+    window_start: Instant,
+    window_messages: u32,
+    messages_per_second: f64,
+    latency_ms: Option<f64>,
+    txs_received: u64,
+    txs_known: u64,
+    tx_latency_ms: Option<f64>,
+    txs_sent: u64,
+}
+
+impl PeerRecord {
+    fn new(now: Instant) -> Self {
+        Self {
+            score: 0.0,
+            updated: now,
+            invalid_blocks: 0,
+            invalid_proofs: 0,
+            invalid_votes: 0,
+            undecodable_messages: 0,
+            messages_received: 0,
+            window_start: now,
+            window_messages: 0,
+            messages_per_second: 0.0,
+            latency_ms: None,
+            txs_received: 0,
+            txs_known: 0,
+            tx_latency_ms: None,
+            txs_sent: 0,
+        }
+    }
+
+    fn current_score(&self, now: Instant) -> f64 {
+        let elapsed = now.duration_since(self.updated).as_secs_f64();
+        (self.score + elapsed * RECOVERY_PER_SECOND).min(0.0)
+    }
+
+    fn recover(&mut self, now: Instant) {
+        self.score = self.current_score(now);
+        self.updated = now;
+    }
+}
+
+// Scores start at zero and only go down; misbehaviour costs points, time gives them back
+#[derive(Debug, Default)]
+pub struct PeerReputation {
+    peers: HashMap<PeerId, PeerRecord>,
+    banned: HashMap<PeerId, Instant>,
+    messages: HashMap<MessageHash, PeerId>,
+    message_order: VecDeque<MessageHash>,
+}
+
+impl PeerReputation {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Returns true when the peer crossed the ban threshold by flooding us
+    pub fn record_message(&mut self, peer: PeerId, message_id: MessageHash) -> bool {
+        self.remember(peer, message_id);
+
+        let now = Instant::now();
+        let record = self.peers.entry(peer).or_insert_with(|| PeerRecord::new(now));
+        record.messages_received += 1;
+
+        if now.duration_since(record.window_start) >= RATE_WINDOW {
+            record.messages_per_second = record.window_messages as f64 / RATE_WINDOW.as_secs_f64();
+            record.window_start = now;
+            record.window_messages = 0;
+        }
+        record.window_messages += 1;
+
+        // Penalized once per window rather than once per excess message
+        record.window_messages == MAX_MESSAGES_PER_WINDOW + 1 && self.penalize(peer, Misbehaviour::Flooding)
+    }
+
+    // Lets consensus reports about the message reach the peer, without counting it as received
+    pub fn remember(&mut self, peer: PeerId, message_id: MessageHash) {
+        if self.messages.insert(message_id, peer).is_none() {
+            self.message_order.push_back(message_id);
+            if self.message_order.len() > MAX_TRACKED_MESSAGES {
+                if let Some(oldest) = self.message_order.pop_front() {
+                    self.messages.remove(&oldest);
+                }
+            }
+        }
+    }
+
+    pub fn record_latency(&mut self, peer: PeerId, latency: Duration) {
+        let record = self.peers.entry(peer).or_insert_with(|| PeerRecord::new(Instant::now()));
+        let sample = latency.as_secs_f64() * 1000.0;
+        record.latency_ms = Some(match record.latency_ms {
+            Some(average) => average + LATENCY_SMOOTHING * (sample - average),
+            None => sample,
+        });
+    }
+
+    // A transaction the peer pushed; the latency only counts when it was new to us
+    pub fn record_transaction(&mut self, peer: PeerId, known: bool, latency: Option<Duration>) {
+        let record = self.peers.entry(peer).or_insert_with(|| PeerRecord::new(Instant::now()));
+        record.txs_received += 1;
+        if known {
+            record.txs_known += 1;
+            return;
+        }
+        if let Some(latency) = latency {
+            let sample = latency.as_secs_f64() * 1000.0;
+            record.tx_latency_ms = Some(match record.tx_latency_ms {
+                Some(average) => average + LATENCY_SMOOTHING * (sample - average),
+                None => sample,
+            });
+        }
+    }
+
+    pub fn record_txs_sent(&mut self, peer: PeerId, count: usize) {
+        let record = self.peers.entry(peer).or_insert_with(|| PeerRecord::new(Instant::now()));
+        record.txs_sent += count as u64;
+    }
+
+    // The peer a consensus report is about, if we still remember the message and it crossed the threshold
+    pub fn report(&mut self, report: &PeerReport) -> Option<PeerId> {
+        let peer = *self.messages.get(&report.message_id)?;
+        self.penalize(peer, report.misbehaviour).then_some(peer)
+    }
+
+    // Returns true when the peer is newly banned
+    pub fn penalize(&mut self, peer: PeerId, misbehaviour: Misbehaviour) -> bool {
+        let now = Instant::now();
+        let record = self.peers.entry(peer).or_insert_with(|| PeerRecord::new(now));
+        record.recover(now);
+        record.score -= misbehaviour.penalty();
+
+        match misbehaviour {
+            Misbehaviour::InvalidBlock => record.invalid_blocks += 1,
+            Misbehaviour::InvalidProof => record.invalid_proofs += 1,
+            Misbehaviour::InvalidVote => record.invalid_votes += 1,
+            Misbehaviour::Undecodable => record.undecodable_messages += 1,
+            Misbehaviour::Flooding => {}
+        }
+
+        if record.score > BAN_THRESHOLD || self.banned.contains_key(&peer) {
+            return false;
+        }
+        self.banned.insert(peer, now + BAN_DURATION);
+        true
+    }
+
+    // Bans the peer outright, regardless of its score. Returns true when it is newly banned
+    pub fn ban(&mut self, peer: PeerId, duration: Duration) -> bool {
+        if self.banned.contains_key(&peer) {
+            return false;
+        }
+        self.banned.insert(peer, Instant::now() + duration);
+        true
+    }
+
+    pub fn is_banned(&self, peer: &PeerId) -> bool {
+        self.banned.contains_key(peer)
+    }
+
+    // Lifts bans that ran out; the peers start over with a clean score
+    pub fn expire_bans(&mut self) -> Vec<PeerId> {
+        let now = Instant::now();
+        let expired: Vec<PeerId> = self.banned.iter()
+            .filter(|(_, until)| **until <= now)
+            .map(|(peer, _)| *peer)
+            .collect();
+
+        for peer in &expired {
+            self.banned.remove(peer);
+            self.peers.remove(peer);
+        }
+        expired
+    }
+
+    // Zero for peers we have nothing against
+    pub fn score(&self, peer: &PeerId) -> f64 {
+        self.peers.get(peer).map_or(0.0, |record| record.current_score(Instant::now()))
+    }
+
+    pub fn peer_info(&self, peer: &PeerId) -> PeerInfo {
+        let now = Instant::now();
+        let fresh = PeerRecord::new(now);
+        let record = self.peers.get(peer).unwrap_or(&fresh);
+
+        PeerInfo {
+            peer_id: peer.to_string(),
+            score: record.current_score(now),
+            invalid_blocks: record.invalid_blocks,
+            invalid_proofs: record.invalid_proofs,
+            invalid_votes: record.invalid_votes,
+            undecodable_messages: record.undecodable_messages,
+            messages_received: record.messages_received,
+            messages_per_second: record.messages_per_second,
+            latency_ms: record.latency_ms,
+            txs_received: record.txs_received,
+            txs_known: record.txs_known,
+            tx_latency_ms: record.tx_latency_ms,
+            txs_sent: record.txs_sent,
+            finalized_height: None,
+            security: None,
+        }
+    }
+
+    // Stops tracking a peer that left, unless it still has penalties to work off
+    pub fn forget(&mut self, peer: &PeerId) {
+        if let Some(record) = self.peers.get_mut(peer) {
+            record.recover(Instant::now());
+            if record.score >= 0.0 && !self.banned.contains_key(peer) {
+                self.peers.remove(peer);
+            }
+        }
+    }
+}
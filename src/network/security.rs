@@ -0,0 +1,156 @@
+use super::NetworkError;
+#[cfg(feature = "tls")]
+use libp2p::{futures::future::Either, tls};
+use libp2p::{
+    core::upgrade::{InboundConnectionUpgrade, OutboundConnectionUpgrade, UpgradeInfo},
+    futures::{future::BoxFuture, AsyncRead, AsyncWrite},
+    identity::Keypair,
+    noise, PeerId,
+};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io;
+use std::sync::{Arc, Mutex};
+
+// Values of network.security; tls needs a build with the tls feature
+pub const SECURITY_PROTOCOLS: [&str; 2] = ["noise", "tls"];
+
+// How a connection is encrypted. Both authenticate the peer with the key its peer id is derived
+// from, which is its node id, so a peer can not claim another node's id
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TransportSecurity {
+    // Noise XX handshake with X25519, ChaCha20-Poly1305 and SHA-256
+    Noise,
+    // TLS 1.3 with a self-signed certificate carrying the node key
+    Tls,
+}
+
+impl TransportSecurity {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "noise" => Some(Self::Noise),
+            "tls" => Some(Self::Tls),
+            _ => None,
+        }
+    }
+
+    fn protocol(&self) -> &'static str {
+        match self {
+            Self::Noise => "/noise",
+            Self::Tls => "/tls/1.0.0",
+        }
+    }
+
+    pub fn is_compiled_in(name: &str) -> bool {
+        match Self::parse(name) {
+            Some(Self::Noise) => true,
+            Some(Self::Tls) => cfg!(feature = "tls"),
+            None => false,
+        }
+    }
+}
+
+// Security each connected peer's connection negotiated, recorded as it is upgraded
+pub type NegotiatedSecurity = Arc<Mutex<HashMap<PeerId, TransportSecurity>>>;
+
+#[cfg(feature = "tls")]
+type SecureStream<C> = Either<noise::Output<C>, tls::TlsStream<C>>;
+#[cfg(not(feature = "tls"))]
+type SecureStream<C> = noise::Output<C>;
+
+// Offers the configured protocols in order and upgrades the connection with whichever the two
+// sides agree on
+#[derive(Clone)]
+pub struct SecurityUpgrade {
+    offered: Vec<TransportSecurity>,
+    noise: noise::Config,
+    #[cfg(feature = "tls")]
+    tls: tls::Config,
+    negotiated: NegotiatedSecurity,
+}
+
+impl SecurityUpgrade {
+    // `protocols` were checked with the rest of the configuration
+    pub fn new(keypair: &Keypair, protocols: &[String], negotiated: NegotiatedSecurity) -> Result<Self, NetworkError> {
+        Ok(Self {
+            offered: protocols.iter().filter_map(|name| TransportSecurity::parse(name)).collect(),
+            noise: noise::Config::new(keypair).map_err(|e| NetworkError::Security(e.to_string()))?,
+            #[cfg(feature = "tls")]
+            tls: tls::Config::new(keypair).map_err(|e| NetworkError::Security(e.to_string()))?,
+            negotiated,
+        })
+    }
+
+    async fn upgrade<C>(self, socket: C, protocol: &'static str, inbound: bool) -> io::Result<(PeerId, SecureStream<C>)>
+    where
+        C: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    {
+        let security = match protocol {
+            protocol if protocol == TransportSecurity::Tls.protocol() => TransportSecurity::Tls,
+            _ => TransportSecurity::Noise,
+        };
+        let (peer_id, stream) = match security {
+            TransportSecurity::Noise => {
+                let (peer_id, stream) = match inbound {
+                    true => self.noise.clone().upgrade_inbound(socket, protocol).await,
+                    false => self.noise.clone().upgrade_outbound(socket, protocol).await,
+                }.map_err(io::Error::other)?;
+                #[cfg(feature = "tls")]
+                let stream = Either::Left(stream);
+                (peer_id, stream)
+            }
+            #[cfg(feature = "tls")]
+            TransportSecurity::Tls => {
+                let (peer_id, stream) = match inbound {
+                    true => self.tls.clone().upgrade_inbound(socket, protocol).await,
+                    false => self.tls.clone().upgrade_outbound(socket, protocol).await,
+                }.map_err(io::Error::other)?;
+                (peer_id, Either::Right(stream))
+            }
+            // Never offered, as the configuration is refused
+            #[cfg(not(feature = "tls"))]
+            TransportSecurity::Tls => return Err(io::Error::other("this build has no TLS support")),
+        };
+
+        if let Ok(mut negotiated) = self.negotiated.lock() {
+            negotiated.insert(peer_id, security);
+        }
+        Ok((peer_id, stream))
+    }
+}
+
+impl UpgradeInfo for SecurityUpgrade {
+    type Info = &'static str;
+    type InfoIter = Vec<&'static str>;
+
+    fn protocol_info(&self) -> Self::InfoIter {
+        self.offered.iter().map(|security| security.protocol()).collect()
+    }
+}
+
+impl<C> InboundConnectionUpgrade<C> for SecurityUpgrade
+where
+    C: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+{
+    type Output = (PeerId, SecureStream<C>);
+    type Error = io::Error;
+    type Future = BoxFuture<'static, io::Result<Self::Output>>;
+
+    fn upgrade_inbound(self, socket: C, protocol: &'static str) -> Self::Future {
+        Box::pin(self.upgrade(socket, protocol, true))
+    }
+}
+
+impl<C> OutboundConnectionUpgrade<C> for SecurityUpgrade
+where
+    C: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+{
+    type Output = (PeerId, SecureStream<C>);
+    type Error = io::Error;
+    type Future = BoxFuture<'static, io::Result<Self::Output>>;
+
+    fn upgrade_outbound(self, socket: C, protocol: &'static str) -> Self::Future {
+        Box::pin(self.upgrade(socket, protocol, false))
+    }
+}
@@ -0,0 +1,49 @@
+use std::collections::{HashMap, VecDeque};
+
+use super::reputation::MessageHash;
+
+// Gossipsub only remembers message ids for about a minute; this keeps the most recently seen
+// hashes around long enough to drop late duplicates and replays before they reach consensus
+#[derive(Debug)]
+pub struct SeenCache {
+    capacity: usize,
+    // Hash to the stamp of its latest sighting
+    entries: HashMap<MessageHash, u64>,
+    // Sightings oldest first; entries refreshed since are skipped when evicting
+    order: VecDeque<(MessageHash, u64)>,
+    next_stamp: u64,
+}
+
+impl SeenCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: HashMap::new(),
+            order: VecDeque::new(),
+            next_stamp: 0,
+        }
+    }
+
+    // Returns true when the hash is in the cache, which makes it the most recent. Unlike
+    // insert, a hash not in the cache is not added
+    pub fn check(&mut self, hash: MessageHash) -> bool {
+        self.entries.contains_key(&hash) && self.insert(hash)
+    }
+
+    // Returns true when the hash was already in the cache. Either way it becomes the most recent
+    pub fn insert(&mut self, hash: MessageHash) -> bool {
+        let stamp = self.next_stamp;
+        self.next_stamp += 1;
+
+        let seen = self.entries.insert(hash, stamp).is_some();
+        self.order.push_back((hash, stamp));
+
+        while self.entries.len() > self.capacity || self.order.len() > self.capacity * 2 {
+            let Some((oldest, oldest_stamp)) = self.order.pop_front() else { break };
+            if self.entries.get(&oldest) == Some(&oldest_stamp) {
+                self.entries.remove(&oldest);
+            }
+        }
+        seen
+    }
+}
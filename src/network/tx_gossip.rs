@@ -0,0 +1,209 @@
+use super::framing::{self, Compression};
+use crate::types::{Decode, Encode, Transaction, WIRE_VERSION};
+use libp2p::{
+    core::{upgrade::{InboundUpgrade, OutboundUpgrade, UpgradeInfo}, Endpoint},
+    futures::{future::BoxFuture, AsyncReadExt, AsyncWriteExt},
+    swarm::{
+        handler::{OneShotHandler, OneShotHandlerConfig},
+        ConnectionDenied, ConnectionId, FromSwarm, NetworkBehaviour, NotifyHandler, StreamUpgradeError,
+        SubstreamProtocol, THandler, THandlerInEvent, THandlerOutEvent, ToSwarm,
+    },
+    Multiaddr, PeerId, Stream, StreamProtocol,
+};
+use std::collections::VecDeque;
+use std::io;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+const TX_PROTOCOL: StreamProtocol = StreamProtocol::new("/zk-consensus/transactions/1");
+const TX_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug)]
+pub enum Event {
+    Received { peer_id: PeerId, transactions: Vec<Transaction> },
+    Failed { peer_id: PeerId, error: String },
+}
+
+// What a substream ended with: a batch the peer pushed to us, or ours delivered
+#[derive(Debug)]
+pub enum Transfer {
+    Received(Vec<Transaction>),
+    Sent,
+}
+
+impl From<Vec<Transaction>> for Transfer {
+    fn from(transactions: Vec<Transaction>) -> Self {
+        Transfer::Received(transactions)
+    }
+}
+
+impl From<()> for Transfer {
+    fn from(_: ()) -> Self {
+        Transfer::Sent
+    }
+}
+
+// Reads one batch of transactions a peer pushes on a substream of its own
+#[derive(Debug, Clone)]
+pub struct ReceiveBatch {
+    max_bytes: usize,
+}
+
+// Writes one batch, framed for the codecs that peer accepts
+#[derive(Debug)]
+pub struct SendBatch {
+    frame: Vec<u8>,
+}
+
+impl UpgradeInfo for ReceiveBatch {
+    type Info = StreamProtocol;
+    type InfoIter = std::iter::Once<StreamProtocol>;
+
+    fn protocol_info(&self) -> Self::InfoIter {
+        std::iter::once(TX_PROTOCOL)
+    }
+}
+
+impl UpgradeInfo for SendBatch {
+    type Info = StreamProtocol;
+    type InfoIter = std::iter::Once<StreamProtocol>;
+
+    fn protocol_info(&self) -> Self::InfoIter {
+        std::iter::once(TX_PROTOCOL)
+    }
+}
+
+impl InboundUpgrade<Stream> for ReceiveBatch {
+    type Output = Vec<Transaction>;
+    type Error = io::Error;
+    type Future = BoxFuture<'static, io::Result<Vec<Transaction>>>;
+
+    fn upgrade_inbound(self, mut stream: Stream, _: StreamProtocol) -> Self::Future {
+        Box::pin(async move {
+            let mut len = [0; 4];
+            stream.read_exact(&mut len).await?;
+            let len = u32::from_le_bytes(len) as usize;
+            // The frame adds a codec byte and the length of compressed payloads
+            if len > self.max_bytes + 5 {
+                return Err(invalid(format!("transaction batch of {} bytes", len)));
+            }
+            let mut frame = vec![0; len];
+            stream.read_exact(&mut frame).await?;
+            stream.close().await?;
+            decode_batch(&frame, self.max_bytes).map_err(|e| invalid(e.to_string()))
+        })
+    }
+}
+
+impl OutboundUpgrade<Stream> for SendBatch {
+    type Output = ();
+    type Error = io::Error;
+    type Future = BoxFuture<'static, io::Result<()>>;
+
+    fn upgrade_outbound(self, mut stream: Stream, _: StreamProtocol) -> Self::Future {
+        Box::pin(async move {
+            stream.write_all(&(self.frame.len() as u32).to_le_bytes()).await?;
+            stream.write_all(&self.frame).await?;
+            stream.close().await
+        })
+    }
+}
+
+fn invalid(error: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+// Batches carry the gossip wire version, so a layout change is caught like on gossip
+pub fn encode_batch(transactions: Vec<Transaction>, compression: Compression) -> Vec<u8> {
+    let mut wire = vec![WIRE_VERSION];
+    transactions.encode_to(&mut wire);
+    framing::encode_frame(&wire, compression)
+}
+
+fn decode_batch(frame: &[u8], max_bytes: usize) -> anyhow::Result<Vec<Transaction>> {
+    let wire = framing::decode_frame(frame, max_bytes)?;
+    match wire.split_first() {
+        Some((&WIRE_VERSION, batch)) => Vec::<Transaction>::decode(batch),
+        Some((version, _)) => anyhow::bail!("Unsupported wire version {}", version),
+        None => anyhow::bail!("Empty transaction batch"),
+    }
+}
+
+// Pushes transactions straight to the peers that have not seen them, rather than gossiping
+// every one to everybody. Which peer gets what is left to the network manager
+pub struct Behaviour {
+    max_bytes: usize,
+    events: VecDeque<ToSwarm<Event, SendBatch>>,
+}
+
+impl Behaviour {
+    pub fn new(max_bytes: usize) -> Self {
+        Self { max_bytes, events: VecDeque::new() }
+    }
+
+    // `frame` is a batch from encode_batch
+    pub fn send(&mut self, peer_id: PeerId, frame: Vec<u8>) {
+        self.events.push_back(ToSwarm::NotifyHandler {
+            peer_id,
+            handler: NotifyHandler::Any,
+            event: SendBatch { frame },
+        });
+    }
+
+    fn handler(&self) -> OneShotHandler<ReceiveBatch, SendBatch, Transfer> {
+        let protocol = SubstreamProtocol::new(ReceiveBatch { max_bytes: self.max_bytes }, ())
+            .with_timeout(TX_TIMEOUT);
+        OneShotHandler::new(protocol, OneShotHandlerConfig::default())
+    }
+}
+
+impl NetworkBehaviour for Behaviour {
+    type ConnectionHandler = OneShotHandler<ReceiveBatch, SendBatch, Transfer>;
+    type ToSwarm = Event;
+
+    fn handle_established_inbound_connection(
+        &mut self,
+        _: ConnectionId,
+        _: PeerId,
+        _: &Multiaddr,
+        _: &Multiaddr,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        Ok(self.handler())
+    }
+
+    fn handle_established_outbound_connection(
+        &mut self,
+        _: ConnectionId,
+        _: PeerId,
+        _: &Multiaddr,
+        _: Endpoint,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        Ok(self.handler())
+    }
+
+    fn on_swarm_event(&mut self, _: FromSwarm) {}
+
+    fn on_connection_handler_event(
+        &mut self,
+        peer_id: PeerId,
+        _: ConnectionId,
+        event: THandlerOutEvent<Self>,
+    ) {
+        let event = match event {
+            Ok(Transfer::Received(transactions)) => Event::Received { peer_id, transactions },
+            Ok(Transfer::Sent) => return,
+            Err(StreamUpgradeError::NegotiationFailed) => {
+                Event::Failed { peer_id, error: "does not speak the transaction protocol".to_string() }
+            }
+            Err(e) => Event::Failed { peer_id, error: e.to_string() },
+        };
+        self.events.push_back(ToSwarm::GenerateEvent(event));
+    }
+
+    fn poll(&mut self, _: &mut Context<'_>) -> Poll<ToSwarm<Self::ToSwarm, THandlerInEvent<Self>>> {
+        match self.events.pop_front() {
+            Some(event) => Poll::Ready(event),
+            None => Poll::Pending,
+        }
+    }
+}
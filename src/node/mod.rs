@@ -0,0 +1,537 @@
+use crate::admin::AdminHandle;
+use crate::bridge::BridgeVerifier;
+use crate::clock::{Clock, ClockSkewMonitor};
+use crate::config::{NetworkConfig, NodeConfig};
+use crate::consensus::ConsensusEngine;
+use crate::crypto::{self, Keystore, NodeKeypair};
+use crate::explorer::ExplorerServer;
+use crate::genesis::Genesis;
+use crate::light_client::LightClient;
+use crate::logging::LogFilterHandle;
+use crate::network::NetworkManager;
+use crate::rpc::{self, FullNodeHandles, RpcServer};
+use crate::shutdown::{Shutdown, ShutdownTrigger};
+use crate::snapshot::{SnapshotDistributor, SnapshotTrust};
+use crate::storage::StorageManager;
+use crate::staking;
+use crate::types::{self, ChainEvent, ConsensusState, NodeId, Transaction, TransactionKind, TrustedCheckpoint};
+use crate::zk_proof::{self, KeyRole, ProofService, ZKProofGenerator};
+use anyhow::{bail, Result};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::{broadcast, mpsc, RwLock};
+use tracing::{info, warn};
+
+#[cfg(feature = "testing")]
+use crate::testing::ByzantineBehavior;
+
+mod reindex;
+
+// Batches of transactions pushed by peers waiting to be admitted to the mempool
+const TX_INTAKE_CAPACITY: usize = 256;
+
+pub use reindex::{reindex, ReindexReport};
+
+// Assembles a node from a NodeConfig. Programs embedding the node can hand in the storage,
+// network settings, key and genesis instead of having them read from the config
+#[derive(Default)]
+pub struct NodeBuilder {
+    config: NodeConfig,
+    storage: Option<StorageManager>,
+    keypair: Option<NodeKeypair>,
+    genesis: Option<Genesis>,
+    restore_snapshot: Option<PathBuf>,
+    log_filter: Option<LogFilterHandle>,
+    #[cfg(feature = "testing")]
+    byzantine: Option<Box<dyn ByzantineBehavior>>,
+}
+
+// A built node; the RPC, explorer and storage services already run, consensus and networking
+// start with run
+pub struct Node {
+    node_id: NodeId,
+    storage: Option<StorageManager>,
+    state: Option<Arc<RwLock<ConsensusState>>>,
+    events: Option<broadcast::Receiver<ChainEvent>>,
+    role: Role,
+    shutdown: Shutdown,
+}
+
+enum Role {
+    Full { consensus: Box<ConsensusEngine>, network: NetworkManager },
+    // Light clients keep no storage and take no part in consensus; they only follow verified headers
+    Light { light_client: Box<LightClient>, network: NetworkManager },
+}
+
+impl NodeBuilder {
+    // Replaces the whole config, including anything set by with_network before
+    pub fn with_config(mut self, config: NodeConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    // An already opened database instead of the one at storage.db_path
+    pub fn with_storage(mut self, storage: StorageManager) -> Self {
+        self.storage = Some(storage);
+        self
+    }
+
+    pub fn with_network(mut self, network: NetworkConfig) -> Self {
+        self.config.network = network;
+        self
+    }
+
+    // Signing key instead of the keystore or key file of the config
+    pub fn with_keypair(mut self, keypair: NodeKeypair) -> Self {
+        self.keypair = Some(keypair);
+        self
+    }
+
+    // Genesis instead of the file at config.genesis
+    pub fn with_genesis(mut self, genesis: Genesis) -> Self {
+        self.genesis = Some(genesis);
+        self
+    }
+
+    // Lets admin_setLogFilter change the filter of the logging the program set up
+    pub fn with_log_filter(mut self, log_filter: LogFilterHandle) -> Self {
+        self.log_filter = Some(log_filter);
+        self
+    }
+
+    // Starts an empty database from a snapshot file written by `snapshot create`
+    pub fn restore_snapshot(mut self, path: PathBuf) -> Self {
+        self.restore_snapshot = Some(path);
+        self
+    }
+
+    #[cfg(feature = "testing")]
+    pub fn with_byzantine(mut self, behavior: Box<dyn ByzantineBehavior>) -> Self {
+        self.byzantine = Some(behavior);
+        self
+    }
+
+    pub async fn build(self) -> Result<Node> {
+        let mut config = self.config;
+        config.validate()?;
+
+        let genesis = load_genesis(&mut config, self.genesis)?;
+        let checkpoint = config.trusted_checkpoint()?;
+        
+        info!("🚀 Starting ZK-PoV Consensus Node");
+        info!("📋 Mode: {}", config.mode);
+        info!("🌐 Port: {}", config.network.port);
+        info!("🔗 Bootstrap nodes: {:?}", config.network.bootstrap_nodes);
+
+        let clock = Arc::new(ClockSkewMonitor::new(
+            config.clock.ntp_servers.clone(),
+            chrono::Duration::milliseconds(config.clock.max_skew_ms),
+        ));
+        let mut shutdown = Shutdown::new();
+        let clock_for_monitor = clock.clone();
+        let signal = shutdown.signal();
+        shutdown.spawn("Clock skew monitor", async move { clock_for_monitor.run(signal).await });
+
+        let keypair = match self.keypair {
+            Some(keypair) => keypair,
+            None => load_node_key(&config)?,
+        };
+
+        if config.mode == "light_client" {
+            let node_id = keypair.node_id();
+            let mut zk_generator = ZKProofGenerator::load(&config.zk_proof, config.zk_role()).await?;
+            if let Some(keys) = genesis.as_ref().and_then(Genesis::verifying_keys) {
+                zk_generator.use_verifying_keys(keys)?;
+            }
+            let mut light_client = LightClient::new(zk_generator, node_id);
+            if let Some(genesis) = &genesis {
+                let state = genesis.consensus_state();
+                light_client.set_genesis(genesis.block().hash(), staking::active_validators(&state), state.params);
+            }
+            if let Some(checkpoint) = checkpoint {
+                light_client.set_checkpoint(checkpoint);
+            }
+
+            let rpc = RpcServer::light_client(config.network.rpc_port, light_client.head_handle(), clock);
+            let signal = shutdown.signal();
+            shutdown.spawn("RPC server", async move { rpc.start(signal).await });
+
+            let network = NetworkManager::new(&config.network, keypair.network_identity()?, light_client.get_message_sender())?;
+            light_client.set_network_sender(network.get_broadcast_sender());
+
+            info!("✅ Light client initialized successfully");
+            return Ok(Node {
+                node_id,
+                storage: None,
+                state: None,
+                events: None,
+                role: Role::Light { light_client: Box::new(light_client), network },
+                shutdown,
+            });
+        }
+
+        // Initialize components
+        let storage = match self.storage {
+            Some(storage) => storage,
+            None => StorageManager::new(&config.storage)?,
+        };
+        let mut zk_generator = ZKProofGenerator::load(&config.zk_proof, config.zk_role()).await?;
+        if let Some(keys) = genesis.as_ref().and_then(Genesis::verifying_keys) {
+            zk_generator.use_verifying_keys(keys)?;
+        }
+        zk_generator.persist_proofs(storage.clone());
+        let zk_generator = Arc::new(zk_generator);
+        let proof_service = ProofService::start(zk_generator.clone(), &config.zk_proof);
+        let snapshots = Arc::new(SnapshotDistributor::new(&config.snapshot.mirrors)?);
+
+        // Snapshots from a file or a mirror must be finalized by the checkpoint's or the genesis validators
+        let genesis_state = genesis.as_ref().map(Genesis::consensus_state);
+        let trust = match (&checkpoint, &genesis_state) {
+            (Some(checkpoint), _) => Some(SnapshotTrust::Checkpoint(checkpoint)),
+            (None, Some(state)) => Some(SnapshotTrust::Validators(state)),
+            (None, None) => None,
+        };
+
+        if let Some(path) = &self.restore_snapshot {
+            let trust = match trust {
+                Some(trust) => trust,
+                None => bail!("restoring a snapshot needs --genesis or a trusted checkpoint to check its finality against"),
+            };
+            let snapshot = storage.restore_from_snapshot(path, &zk_generator, trust).await?;
+            info!("📥 Restored snapshot #{} from {}", snapshot.height, path.display());
+        }
+
+        // Bootstrap an empty node from the snapshot mirrors instead of validator peers
+        if snapshots.has_mirrors() && storage.get_latest_block().await?.is_none() {
+            match trust {
+                Some(trust) => match snapshots.fetch_latest(&zk_generator, trust).await? {
+                    Some(snapshot) => snapshot.import_into(&storage).await?,
+                    None => warn!("⚠️ No verified snapshot available from mirrors, syncing from genesis"),
+                },
+                None => warn!("⚠️ Not starting from the mirror snapshots, without a genesis or trusted checkpoint their finality can not be checked"),
+            }
+        }
+
+        // Chains with a genesis file start from its block 0; others are bootstrapped by their first validator
+        let genesis_hash = match &genesis {
+            Some(genesis) => Some(genesis.initialize(&storage).await?),
+            None if storage.get_block(0).await?.is_some() => {
+                bail!("database was created from a genesis file, start the node with --genesis");
+            }
+            None => None,
+        };
+
+        // The pending pool was kept across the restart; drop what the chain or our settings no
+        // longer allow
+        let next_block = storage.get_finalized_block().await?.map_or(0, |block| block.header.block_number) + 1;
+        let now = clock.clock().now();
+        let pending = storage.revalidate_pending(config.mempool.max_transactions, |tx| {
+            rpc::check_transaction(&config.consensus.limits, &config.mempool, next_block, &now, tx)
+        }).await?;
+        if pending > 0 {
+            info!("📋 Restored {} pending transactions", pending);
+        }
+
+        // Create test transactions
+        if config.mode == "validator" && storage.get_latest_block().await?.is_none() {
+            create_test_transactions(&storage, &keypair).await?;
+        }
+
+        let node_id = keypair.node_id();
+        let network_identity = keypair.network_identity()?;
+        let mut consensus = ConsensusEngine::new(
+            zk_generator,
+            proof_service.clone(),
+            storage.clone(),
+            clock.clock(),
+            keypair,
+            &config.consensus,
+        )?;
+
+        if config.mode == "validator" {
+            consensus.set_validator_stake(config.consensus.stake);
+        }
+        if let (Some(genesis), Some(genesis_hash)) = (&genesis, genesis_hash) {
+            consensus.set_genesis(genesis_hash, genesis.timestamp);
+        }
+        consensus.set_proof_request_limits(&config.zk_proof);
+        if let Some(checkpoint) = checkpoint {
+            info!("🧭 Trusted checkpoint #{} {}", checkpoint.height, hex::encode(checkpoint.block_hash));
+            consensus.set_checkpoint(checkpoint);
+        }
+        #[cfg(feature = "testing")]
+        if let Some(behavior) = self.byzantine {
+            consensus.set_byzantine(behavior);
+        }
+
+        if snapshots.has_mirrors() && config.snapshot.interval > 0 {
+            let (snapshot_storage, interval) = (storage.clone(), config.snapshot.interval);
+            let events = consensus.subscribe();
+            let signal = shutdown.signal();
+            shutdown.spawn("Snapshot publisher", async move {
+                snapshots.run_publisher(snapshot_storage, interval, events, signal).await
+            });
+        }
+        if config.storage.pruning == "pruned" {
+            let (pruner_storage, keep_blocks) = (storage.clone(), config.storage.keep_blocks);
+            let events = consensus.subscribe();
+            let signal = shutdown.signal();
+            shutdown.spawn("Storage pruner", async move {
+                pruner_storage.run_pruner(keep_blocks, events, signal).await
+            });
+        }
+        let (mempool_storage, events, signal) = (storage.clone(), consensus.subscribe(), shutdown.signal());
+        let mempool_clock = clock.clock();
+        shutdown.spawn("Mempool pruner", async move {
+            mempool_storage.run_mempool_pruner(mempool_clock, events, signal).await
+        });
+        if config.storage.compaction_interval_secs > 0 || config.storage.disk_alarm_bytes > 0 {
+            let maintenance_storage = storage.clone();
+            let (interval, alarm) = (config.storage.compaction_interval_secs, config.storage.disk_alarm_bytes);
+            let signal = shutdown.signal();
+            shutdown.spawn("Storage maintenance", async move {
+                maintenance_storage.run_maintenance(interval, alarm, signal).await
+            });
+        }
+
+        let mut network = NetworkManager::new(&config.network, network_identity, consensus.get_message_sender())?;
+        network.set_peer_store(storage.clone());
+        network.set_peer_head_sender(consensus.get_peer_head_sender());
+        network.set_chain_events(consensus.subscribe());
+        let (pushed_tx, pushed_rx) = mpsc::channel(TX_INTAKE_CAPACITY);
+        network.set_transaction_sender(pushed_tx);
+        let (intake_storage, limits, mempool) = (storage.clone(), config.consensus.limits, config.mempool.clone());
+        let (intake_clock, signal) = (clock.clock(), shutdown.signal());
+        shutdown.spawn("Transaction intake", async move {
+            rpc::admit_pushed_transactions(intake_storage, limits, mempool, intake_clock, pushed_rx, signal).await
+        });
+        consensus.set_network_sender(network.get_broadcast_sender());
+        consensus.set_peer_report_sender(network.get_report_sender());
+
+        let handles = FullNodeHandles {
+            storage: storage.clone(),
+            state: consensus.state_handle(),
+            sync: consensus.sync_status_handle(),
+            import: consensus.import_stats_handle(),
+            inbox: consensus.inbox_monitor(),
+            peers: network.peers_handle(),
+            proofs: proof_service,
+            node_id,
+            chain_events: consensus.subscribe(),
+            limits: config.consensus.limits,
+            mempool: config.mempool.clone(),
+        };
+        let mut rpc = RpcServer::new(config.network.rpc_port, handles, clock);
+        if config.network.admin {
+            warn!("🛠️ Admin RPC methods enabled on port {}", config.network.rpc_port);
+            rpc.enable_admin(AdminHandle {
+                consensus: consensus.get_admin_sender(),
+                network: network.get_admin_sender(),
+                log_filter: self.log_filter,
+            });
+        }
+        let signal = shutdown.signal();
+        shutdown.spawn("RPC server", async move { rpc.start(signal).await });
+
+        if config.network.explorer_port != 0 {
+            let explorer = ExplorerServer::new(
+                config.network.explorer_port,
+                storage.clone(),
+                consensus.state_handle(),
+                consensus.sync_status_handle(),
+            );
+            let signal = shutdown.signal();
+            shutdown.spawn("Explorer API", async move { explorer.start(signal).await });
+        }
+
+        #[cfg(feature = "grpc")]
+        if config.network.grpc_port != 0 {
+            let grpc = crate::rpc::GrpcServer::new(
+                config.network.grpc_port,
+                storage.clone(),
+                consensus.state_handle(),
+                consensus.subscribe(),
+                config.consensus.limits,
+                config.mempool.clone(),
+                clock.clock(),
+            );
+            let signal = shutdown.signal();
+            shutdown.spawn("gRPC server", async move { grpc.start(signal).await });
+        }
+
+        info!("✅ All components initialized successfully");
+        Ok(Node {
+            node_id,
+            storage: Some(storage),
+            state: Some(consensus.state_handle()),
+            events: Some(consensus.subscribe()),
+            role: Role::Full { consensus: Box::new(consensus), network },
+            shutdown,
+        })
+    }
+}
+
+impl Node {
+    pub fn builder() -> NodeBuilder {
+        NodeBuilder::default()
+    }
+
+    pub fn node_id(&self) -> NodeId {
+        self.node_id
+    }
+
+    // None for light clients, which keep no storage
+    pub fn storage(&self) -> Option<&StorageManager> {
+        self.storage.as_ref()
+    }
+
+    pub fn consensus_state(&self) -> Option<Arc<RwLock<ConsensusState>>> {
+        self.state.clone()
+    }
+
+    // Imported and finalized blocks, votes and epochs; None for light clients
+    pub fn subscribe(&self) -> Option<broadcast::Receiver<ChainEvent>> {
+        self.events.as_ref().map(|events| events.resubscribe())
+    }
+
+    // Stops a running node from outside its tasks, like Ctrl-C does
+    pub fn stop_handle(&self) -> ShutdownTrigger {
+        self.shutdown.trigger_handle()
+    }
+
+    // Runs consensus and networking until Ctrl-C, a stop through the handle or the end of
+    // either, then stops every task
+    pub async fn run(self) -> Result<()> {
+        let mut shutdown = self.shutdown;
+        match self.role {
+            Role::Full { mut consensus, mut network } => {
+                let signal = shutdown.signal();
+                shutdown.spawn_critical("Consensus engine", async move { consensus.start(signal).await });
+                let signal = shutdown.signal();
+                shutdown.spawn_critical("Network manager", async move { network.start(signal).await });
+                shutdown.wait().await;
+                info!("👋 Shutting down ZK-PoV Consensus Node");
+            }
+            Role::Light { mut light_client, mut network } => {
+                let signal = shutdown.signal();
+                shutdown.spawn_critical("Light client", async move { light_client.start(signal).await });
+                let signal = shutdown.signal();
+                shutdown.spawn_critical("Network manager", async move { network.start(signal).await });
+                shutdown.wait().await;
+                info!("👋 Shutting down light client");
+            }
+        }
+        Ok(())
+    }
+}
+
+// Verifier for bridge proofs of the chain the config's genesis file describes, with its hash
+// functions and verifying keys; nothing is proven, so no proving keys are loaded
+pub async fn bridge_verifier(mut config: NodeConfig, checkpoint: TrustedCheckpoint) -> Result<BridgeVerifier> {
+    let genesis = load_genesis(&mut config, None)?;
+    let mut zk_generator = ZKProofGenerator::load(&config.zk_proof, KeyRole::Verifier).await?;
+    if let Some(keys) = genesis.as_ref().and_then(Genesis::verifying_keys) {
+        zk_generator.use_verifying_keys(keys)?;
+    }
+    Ok(BridgeVerifier::new(zk_generator, checkpoint))
+}
+
+// Groth16 keys for the chain the config's genesis file describes, written like
+// zk_proof::export_keys does; they only prove blocks of chains with Poseidon merkle trees
+pub fn export_keys(mut config: NodeConfig, dir: &Path) -> Result<Vec<(PathBuf, [u8; 32])>> {
+    load_genesis(&mut config, None)?;
+    if config.zk_proof.backend != "groth16" {
+        bail!("The chain proves blocks with {}, which reads no key files", config.zk_proof.backend);
+    }
+    zk_proof::export_keys(dir)
+}
+
+// The chain id and consensus parameters have to be in place before anything is validated
+fn load_genesis(config: &mut NodeConfig, genesis: Option<Genesis>) -> Result<Option<Genesis>> {
+    let genesis = match (genesis, &config.genesis) {
+        (Some(genesis), _) => Some(genesis),
+        (None, Some(path)) => Some(Genesis::load(path)?),
+        (None, None) => None,
+    };
+    if let Some(genesis) = &genesis {
+        types::set_chain_id(genesis.chain_id)?;
+        types::set_hash_function(genesis.consensus.hash_function)?;
+        if let Some(merkle_hash_function) = genesis.consensus.merkle_hash_function {
+            types::set_merkle_hash_function(merkle_hash_function)?;
+        }
+        genesis.apply_to(&mut config.consensus);
+        config.zk_proof.artifact_hashes = genesis.artifact_hashes();
+        // Peers only accept proofs in the chain's proof system
+        let backend = genesis.proof_backend();
+        if config.zk_proof.backend != backend {
+            info!("🔧 Using the {} backend the genesis requires instead of {}", backend, config.zk_proof.backend);
+            config.zk_proof.backend = backend.to_string();
+        }
+    } else if cfg!(feature = "groth16") && !config.zk_proof.mock && config.zk_proof.backend == "groth16" {
+        // A chain without a genesis file hashes its trees the way its proofs need them
+        types::set_merkle_hash_function(types::HashFunction::Poseidon)?;
+    }
+    // The Groth16 circuit recomputes the merkle and state trees with Poseidon gadgets, and Nova
+    // folding steps the merkle root and block hash with SHA-256 ones
+    let merkle_hash_function = types::merkle_hash_function();
+    let proves_groth16 = cfg!(feature = "groth16") && !config.zk_proof.mock && config.zk_proof.backend == "groth16";
+    if merkle_hash_function != types::HashFunction::Poseidon && proves_groth16 {
+        bail!("zk_proof.backend groth16 requires the poseidon merkle hash function, the chain uses {:?}",
+            merkle_hash_function);
+    }
+    // The PLONK circuit only has SHA-256 gadgets
+    if merkle_hash_function != types::HashFunction::Sha256 && config.zk_proof.backend == "plonk" {
+        bail!("zk_proof.backend plonk requires the sha256 merkle hash function, the chain uses {:?}",
+            merkle_hash_function);
+    }
+    if cfg!(feature = "nova") && !config.zk_proof.mock && merkle_hash_function != types::HashFunction::Sha256 {
+        bail!("Nova chain proofs require the sha256 merkle hash function, the chain uses {:?}; \
+            run with zk_proof.mock or a build without the nova feature", merkle_hash_function);
+    }
+    if cfg!(feature = "nova") && !config.zk_proof.mock && types::hash_function() != types::HashFunction::Sha256 {
+        bail!("Nova chain proofs require the sha256 hash function, the chain uses {:?}; \
+            run with zk_proof.mock or a build without the nova feature", types::hash_function());
+    }
+    Ok(genesis)
+}
+
+// Signing key from the keystore when one is configured, otherwise from the plain key file
+fn load_node_key(config: &NodeConfig) -> Result<NodeKeypair> {
+    let path = match &config.keystore {
+        Some(path) => path,
+        None => return NodeKeypair::load_or_generate(&config.key_file),
+    };
+
+    let keystore = Keystore::load(path)?;
+    info!("🔐 Unlocking validator key {} from {}", hex::encode(&keystore.node_id), path.display());
+    keystore.decrypt(&crypto::keystore_password(false)?)
+}
+
+// Spends from the genesis supply, so only a validator bootstrapping a new chain can fund them;
+// nonce 0 is taken by the genesis stake transaction
+async fn create_test_transactions(storage: &StorageManager, keypair: &NodeKeypair) -> Result<()> {
+    info!("💰 Creating test transactions");
+
+    for i in 0..5u8 {
+        let mut tx = Transaction {
+            id: [0; 32],
+            from: keypair.node_id(),
+            to: [i + 2; 32],
+            amount: (i as u64 + 1) * 100,
+            fee: 1,
+            nonce: i as u64 + 1,
+            chain_id: types::chain_id(),
+            timestamp: chrono::Utc::now(),
+            kind: TransactionKind::Transfer,
+            valid_until: None,
+            signature: vec![],
+        };
+        tx.id = tx.content_id();
+        tx.signature = keypair.sign(&tx.signing_hash());
+
+        storage.store_transaction(&tx).await?;
+        info!("📝 Created transaction #{}: {} tokens", i, tx.amount);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,146 @@
+use super::load_genesis;
+use crate::clock::ClockSkewMonitor;
+use crate::config::{NodeConfig, StorageConfig};
+use crate::consensus::ConsensusEngine;
+use crate::crypto::NodeKeypair;
+use crate::storage::StorageManager;
+use crate::zk_proof::{KeyRole, ProofService, ZKProofGenerator};
+use anyhow::{bail, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tracing::{info, warn};
+
+// Blocks read from the old database and checked at once
+const REINDEX_BATCH: u64 = 64;
+
+#[derive(Debug, Clone)]
+pub struct ReindexReport {
+    // Finalized block the new database ends at
+    pub height: u64,
+    pub certificates: u64,
+    // Where the old database was moved
+    pub old_db_path: PathBuf,
+}
+
+// Rebuilds the database of a stopped node by replaying its finalized chain from genesis
+// through the checks and execution synced blocks get. Only blocks, finality certificates,
+// proofs and pending transactions are taken over; the state, receipts, transaction indexes,
+// epochs and their stats are derived again. The new database is built next to the old one,
+// which is moved aside to `<db_path>.old` once every block has been replayed
+pub async fn reindex(mut config: NodeConfig) -> Result<ReindexReport> {
+    let genesis = load_genesis(&mut config, None)?;
+    let db_path = config.storage.db_path.clone();
+    let new_path = PathBuf::from(format!("{}.reindex", db_path));
+    let old_path = PathBuf::from(format!("{}.old", db_path));
+    if !Path::new(&db_path).exists() {
+        bail!("no database at {}", db_path);
+    }
+    if old_path.exists() {
+        bail!("{} is left from an earlier reindex, remove it first", old_path.display());
+    }
+    if new_path.exists() {
+        warn!("🗑️ Removing {} of an interrupted reindex", new_path.display());
+        fs::remove_dir_all(&new_path)?;
+    }
+
+    // Every handle on the databases has to be dropped before they can be moved
+    let (height, certificates) = {
+        let source = StorageManager::new(&config.storage)?;
+        let finalized = match source.get_finalized_block().await? {
+            Some(block) => block,
+            None => bail!("database at {} has no finalized block to reindex", db_path),
+        };
+        let pruned_below = source.pruned_below().await?;
+        if pruned_below > 0 {
+            bail!("transactions below block #{} are pruned, only archive databases can be reindexed", pruned_below);
+        }
+        if source.get_block(1).await?.is_none() {
+            bail!("database has no block #1, databases started from a snapshot cannot be reindexed");
+        }
+        match &genesis {
+            // The old database has blocks, so this only checks that they are of this genesis
+            Some(genesis) => {
+                genesis.initialize(&source).await?;
+            }
+            None if source.get_block(0).await?.is_some() => {
+                bail!("database was created from a genesis file, reindex with --genesis");
+            }
+            None => {}
+        }
+
+        let target = StorageManager::new(&StorageConfig {
+            db_path: new_path.to_string_lossy().into_owned(),
+            ..config.storage.clone()
+        })?;
+        let genesis_hash = match &genesis {
+            Some(genesis) => Some(genesis.initialize(&target).await?),
+            None => None,
+        };
+
+        // Blocks are only verified, so no proving keys are loaded
+        let mut zk_generator = ZKProofGenerator::load(&config.zk_proof, KeyRole::Verifier).await?;
+        if let Some(keys) = genesis.as_ref().and_then(|genesis| genesis.verifying_keys()) {
+            zk_generator.use_verifying_keys(keys)?;
+        }
+        let zk_generator = Arc::new(zk_generator);
+        let proof_service = ProofService::start(zk_generator.clone(), &config.zk_proof);
+        let clock = Arc::new(ClockSkewMonitor::new(
+            config.clock.ntp_servers.clone(),
+            chrono::Duration::milliseconds(config.clock.max_skew_ms),
+        ));
+        let mut consensus = ConsensusEngine::new(
+            zk_generator,
+            proof_service,
+            target.clone(),
+            clock.clock(),
+            NodeKeypair::generate(),
+            &config.consensus,
+        )?;
+        if let (Some(genesis), Some(genesis_hash)) = (&genesis, genesis_hash) {
+            consensus.set_genesis(genesis_hash, genesis.timestamp);
+        }
+        consensus.resume().await?;
+
+        let height = finalized.header.block_number;
+        info!("🔁 Reindexing {} blocks of {} into {}", height, db_path, new_path.display());
+        let mut certificates = 0;
+        let mut from = 1;
+        while from <= height {
+            let to = height.min(from + REINDEX_BATCH - 1);
+            let blocks = source.get_block_range(from, to).await?;
+            if blocks.len() as u64 != to - from + 1 {
+                bail!("database is missing blocks between #{} and #{}", from, to);
+            }
+
+            let hashes: Vec<_> = blocks.iter().map(|block| block.hash()).collect();
+            if let Some(rejected) = consensus.reindex_blocks(blocks).await? {
+                bail!("block #{} does not pass validation, {} is left as it was", rejected, db_path);
+            }
+            for hash in &hashes {
+                if let Some(certificate) = source.get_certificate(hash).await? {
+                    target.store_certificate(&certificate).await?;
+                    certificates += 1;
+                }
+                if let Some(proof) = source.get_proof(hash).await? {
+                    target.store_proof(hash, &proof).await?;
+                }
+            }
+            info!("🔁 Reindexed block #{} of #{}", to, height);
+            from = to + 1;
+        }
+
+        if let Some(chain_proof) = source.get_chain_proof().await? {
+            target.store_chain_proof(&chain_proof).await?;
+        }
+        target.restore_pending_transactions(&source.get_pending_transactions().await?).await?;
+        target.store_consensus_state(&*consensus.state_handle().read().await).await?;
+        target.flush().await?;
+        (height, certificates)
+    };
+
+    fs::rename(&db_path, &old_path)?;
+    fs::rename(&new_path, &db_path)?;
+    info!("✅ Reindexed {} up to block #{}, the old database was moved to {}", db_path, height, old_path.display());
+    Ok(ReindexReport { height, certificates, old_db_path: old_path })
+}
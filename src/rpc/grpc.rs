@@ -0,0 +1,251 @@
+use super::{accept_transaction, RpcError, INVALID_PARAMS, NOT_FOUND};
+use crate::clock::Clock;
+use crate::config::MempoolConfig;
+use crate::shutdown::ShutdownSignal;
+use crate::storage::{StorageError, StorageManager};
+use crate::types::{Block, BlockHash, BlockLimits, ChainEvent, ConsensusState, Decode, Encode, Transaction};
+use anyhow::Result;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::sync::{broadcast, mpsc, RwLock};
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{transport::Server, Request, Response, Status};
+use tracing::{info, debug};
+
+mod proto {
+    tonic::include_proto!("zkconsensus.v1");
+}
+
+use proto::blocks_server::{Blocks, BlocksServer};
+use proto::transactions_server::{Transactions, TransactionsServer};
+use proto::validators_server::{Validators, ValidatorsServer};
+
+// Blocks a stream may have queued for a slow client before it waits
+const STREAM_BUFFER: usize = 32;
+
+// gRPC counterpart of the JSON-RPC API for indexers and custody systems, on localhost only
+pub struct GrpcServer {
+    addr: SocketAddr,
+    service: Arc<NodeService>,
+}
+
+struct NodeService {
+    storage: StorageManager,
+    state: Arc<RwLock<ConsensusState>>,
+    // Never read; each block stream subscribes from it
+    events: broadcast::Receiver<ChainEvent>,
+    limits: BlockLimits,
+    mempool: MempoolConfig,
+    // Transactions are admitted by the node's clock
+    clock: Arc<dyn Clock>,
+}
+
+impl GrpcServer {
+    pub fn new(
+        port: u16,
+        storage: StorageManager,
+        state: Arc<RwLock<ConsensusState>>,
+        chain_events: broadcast::Receiver<ChainEvent>,
+        limits: BlockLimits,
+        mempool: MempoolConfig,
+        clock: Arc<dyn Clock>,
+    ) -> Self {
+        Self {
+            addr: SocketAddr::from(([127, 0, 0, 1], port)),
+            service: Arc::new(NodeService { storage, state, events: chain_events, limits, mempool, clock }),
+        }
+    }
+
+    pub async fn start(self, mut shutdown: ShutdownSignal) -> Result<()> {
+        info!("🔌 gRPC server listening on {}", self.addr);
+
+        Server::builder()
+            .add_service(BlocksServer::from_arc(self.service.clone()))
+            .add_service(TransactionsServer::from_arc(self.service.clone()))
+            .add_service(ValidatorsServer::from_arc(self.service))
+            .serve_with_shutdown(self.addr, async move { shutdown.recv().await })
+            .await?;
+
+        Ok(())
+    }
+}
+
+impl From<&Block> for proto::Block {
+    fn from(block: &Block) -> Self {
+        Self {
+            hash: block.hash().to_vec(),
+            number: block.header.block_number,
+            parent_hash: block.header.parent_hash.to_vec(),
+            timestamp: block.header.timestamp.timestamp(),
+            merkle_root: block.header.merkle_root.to_vec(),
+            state_root: block.header.state_root.to_vec(),
+            proposer: block.header.validator.to_vec(),
+            transaction_count: block.transactions.len() as u32,
+            encoded: block.encode(),
+        }
+    }
+}
+
+impl From<RpcError> for Status {
+    fn from(e: RpcError) -> Self {
+        match e.code {
+            INVALID_PARAMS => Status::invalid_argument(e.message),
+            NOT_FOUND => Status::not_found(e.message),
+            _ => Status::internal(e.message),
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl Blocks for NodeService {
+    type StreamBlocksStream = ReceiverStream<Result<proto::Block, Status>>;
+
+    async fn get_block(&self, request: Request<proto::GetBlockRequest>) -> Result<Response<proto::Block>, Status> {
+        use proto::get_block_request::BlockId;
+
+        let block = match request.into_inner().block_id {
+            Some(BlockId::Number(number)) => self.storage.get_block(number).await.map_err(storage_error)?,
+            Some(BlockId::Hash(hash)) => self.storage.get_block_by_hash(&parse_hash(hash)?).await.map_err(storage_error)?,
+            None => return Err(Status::invalid_argument("expected a block number or hash")),
+        };
+
+        match block {
+            Some(block) if self.storage.is_header_only(block.header.block_number).await.map_err(storage_error)? => {
+                Err(Status::not_found(format!("block {} is pruned to its header", block.header.block_number)))
+            }
+            Some(block) => Ok(Response::new((&block).into())),
+            None => Err(Status::not_found("block not found")),
+        }
+    }
+
+    async fn get_latest_block(&self, _request: Request<proto::GetLatestBlockRequest>) -> Result<Response<proto::Block>, Status> {
+        match self.storage.get_latest_block().await.map_err(storage_error)? {
+            Some(block) => Ok(Response::new((&block).into())),
+            None => Err(Status::not_found("no blocks yet")),
+        }
+    }
+
+    async fn stream_blocks(&self, request: Request<proto::StreamBlocksRequest>) -> Result<Response<Self::StreamBlocksStream>, Status> {
+        let from_block = request.into_inner().from_block;
+        let (tx, rx) = mpsc::channel(STREAM_BUFFER);
+
+        // Subscribed before catching up, so no block finalized in between is missed
+        let events = self.events.resubscribe();
+        tokio::spawn(stream_finalized(self.storage.clone(), events, from_block, tx));
+        debug!("Streaming finalized blocks from {} over gRPC", from_block);
+
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+}
+
+#[tonic::async_trait]
+impl Transactions for NodeService {
+    async fn submit_transaction(&self, request: Request<proto::SubmitTransactionRequest>) -> Result<Response<proto::SubmitTransactionResponse>, Status> {
+        let transaction = Transaction::decode(&request.into_inner().transaction)
+            .map_err(|e| Status::invalid_argument(format!("invalid transaction encoding: {}", e)))?;
+        accept_transaction(&self.storage, &self.limits, &self.mempool, &*self.clock, &transaction).await?;
+
+        Ok(Response::new(proto::SubmitTransactionResponse { id: transaction.id.to_vec() }))
+    }
+
+    async fn get_transaction_status(&self, request: Request<proto::GetTransactionStatusRequest>) -> Result<Response<proto::GetTransactionStatusResponse>, Status> {
+        let id = request.into_inner().id;
+        let tx_id = parse_hash(id.clone())?;
+
+        let status = if self.storage.is_transaction_pending(&tx_id).await.map_err(storage_error)? {
+            proto::TransactionStatus::Pending
+        } else if self.storage.get_transaction(&tx_id).await.map_err(storage_error)?.is_some() {
+            proto::TransactionStatus::Included
+        } else {
+            proto::TransactionStatus::Unknown
+        };
+
+        Ok(Response::new(proto::GetTransactionStatusResponse { id, status: status as i32 }))
+    }
+}
+
+#[tonic::async_trait]
+impl Validators for NodeService {
+    async fn list_validators(&self, _request: Request<proto::ListValidatorsRequest>) -> Result<Response<proto::ListValidatorsResponse>, Status> {
+        let state = self.state.read().await;
+        let validators = state.validators.iter()
+            .map(|(node_id, info)| proto::Validator {
+                node_id: node_id.to_vec(),
+                stake: info.stake,
+                is_active: info.is_active,
+                last_block_time: info.last_block_time.timestamp(),
+                performance_score: info.performance_score,
+            })
+            .collect();
+
+        Ok(Response::new(proto::ListValidatorsResponse { validators }))
+    }
+
+    async fn get_validator(&self, request: Request<proto::GetValidatorRequest>) -> Result<Response<proto::Validator>, Status> {
+        let node_id = parse_hash(request.into_inner().node_id)?;
+        let state = self.state.read().await;
+
+        match state.validators.get(&node_id) {
+            Some(info) => Ok(Response::new(proto::Validator {
+                node_id: node_id.to_vec(),
+                stake: info.stake,
+                is_active: info.is_active,
+                last_block_time: info.last_block_time.timestamp(),
+                performance_score: info.performance_score,
+            })),
+            None => Err(Status::not_found("not a validator")),
+        }
+    }
+}
+
+// Sends finalized blocks from `next` on until the client goes away. Blocks are read from
+// storage rather than taken from the events, so a lagging subscription only delays the stream
+async fn stream_finalized(
+    storage: StorageManager,
+    mut events: broadcast::Receiver<ChainEvent>,
+    mut next: u64,
+    tx: mpsc::Sender<Result<proto::Block, Status>>,
+) {
+    loop {
+        let finalized = match storage.get_finalized_block().await {
+            Ok(block) => block.map(|block| block.header.block_number),
+            Err(e) => {
+                let _ = tx.send(Err(storage_error(e))).await;
+                return;
+            }
+        };
+
+        while finalized.is_some_and(|finalized| next <= finalized) {
+            match storage.get_block(next).await {
+                // Blocks from before a snapshot the node was synced from are skipped
+                Ok(None) => {}
+                Ok(Some(block)) => {
+                    if tx.send(Ok((&block).into())).await.is_err() {
+                        return;
+                    }
+                }
+                Err(e) => {
+                    let _ = tx.send(Err(storage_error(e))).await;
+                    return;
+                }
+            }
+            next += 1;
+        }
+
+        loop {
+            match events.recv().await {
+                Ok(ChainEvent::BlockFinalized(_)) | Err(broadcast::error::RecvError::Lagged(_)) => break,
+                Ok(_) => continue,
+                Err(broadcast::error::RecvError::Closed) => return,
+            }
+        }
+    }
+}
+
+fn parse_hash(bytes: Vec<u8>) -> Result<BlockHash, Status> {
+    bytes.try_into().map_err(|_| Status::invalid_argument("expected 32 bytes"))
+}
+
+fn storage_error(e: StorageError) -> Status {
+    RpcError::from(e).into()
+}
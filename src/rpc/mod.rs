@@ -0,0 +1,1416 @@
+use crate::admin::{AdminHandle, ConsensusCommand, NetworkCommand};
+use crate::bridge::{BridgeError, BridgeProof};
+use crate::clock::{Clock, ClockSkewMonitor};
+use crate::config::MempoolConfig;
+use crate::consensus::{ConsensusError, ImportStats, InboxMonitor};
+use crate::light_client::VerifiedHead;
+use crate::logging;
+use crate::network::{NetworkError, PeerInfo};
+use crate::shutdown::ShutdownSignal;
+use crate::staking;
+use crate::state::AccountId;
+use crate::storage::{HistoryPage, MempoolEvent, NonceStatus, StorageError, StorageManager, MAX_HISTORY_PAGE};
+use crate::sync::SyncStatus;
+use crate::types::{self, BlockHash, BlockLimits, ChainEvent, ConsensusState, NodeId, Transaction, TrustedCheckpoint};
+use crate::zk_proof::{ProofError, ProofService};
+use anyhow::{anyhow, Result};
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::sse::{Event, KeepAlive, Sse},
+    routing::{get, post},
+    Json, Router,
+};
+use chrono::{DateTime, Utc};
+use libp2p::futures::{stream, Stream};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{hash_map::Entry, HashMap, HashSet, VecDeque};
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::sync::{broadcast, mpsc, oneshot, watch, RwLock};
+use tracing::{info, debug, warn};
+
+#[cfg(feature = "grpc")]
+mod grpc;
+#[cfg(feature = "grpc")]
+pub use grpc::GrpcServer;
+
+// Standard JSON-RPC 2.0 error codes
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const INTERNAL_ERROR: i64 = -32603;
+// Server errors, one per module a method can fail in
+const NOT_FOUND: i64 = -32001;
+const STORAGE_ERROR: i64 = -32002;
+const PROOF_ERROR: i64 = -32003;
+const CONSENSUS_ERROR: i64 = -32004;
+const NETWORK_ERROR: i64 = -32005;
+
+// Chain events kept for system_events
+const RECENT_EVENTS: usize = 128;
+// Pending transactions listed by system_mempool, which reports the full count
+const MAX_MEMPOOL_LISTED: usize = 100;
+// Finalized blocks whose fees fee_estimate looks at
+const FEE_ESTIMATE_BLOCKS: u64 = 20;
+// Transactions one tx_submitBatch call may carry
+const MAX_SUBMIT_BATCH: usize = 10_000;
+const DEFAULT_BAN_SECS: u64 = 60 * 60;
+
+#[derive(Debug, Deserialize)]
+struct RpcRequest {
+    jsonrpc: String,
+    #[serde(default)]
+    id: Value,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Debug, Serialize)]
+struct RpcResponse {
+    jsonrpc: &'static str,
+    id: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<RpcError>,
+}
+
+#[derive(Debug, Serialize)]
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        Self { code, message: message.into() }
+    }
+
+    fn invalid_params(message: impl Into<String>) -> Self {
+        Self::new(INVALID_PARAMS, message)
+    }
+}
+
+impl From<StorageError> for RpcError {
+    fn from(e: StorageError) -> Self {
+        let code = match e {
+            StorageError::NotFound(_) => NOT_FOUND,
+            StorageError::Rejected(_) => INVALID_PARAMS,
+            _ => STORAGE_ERROR,
+        };
+        Self::new(code, e.to_string())
+    }
+}
+
+impl From<ProofError> for RpcError {
+    fn from(e: ProofError) -> Self {
+        match e {
+            ProofError::Storage(e) => e.into(),
+            ProofError::Unsupported(_) | ProofError::InvalidInput(_) => Self::invalid_params(e.to_string()),
+            _ => Self::new(PROOF_ERROR, e.to_string()),
+        }
+    }
+}
+
+impl From<ConsensusError> for RpcError {
+    fn from(e: ConsensusError) -> Self {
+        Self::new(CONSENSUS_ERROR, e.to_string())
+    }
+}
+
+impl From<NetworkError> for RpcError {
+    fn from(e: NetworkError) -> Self {
+        let code = match e {
+            NetworkError::InvalidPeerId(_) | NetworkError::InvalidAddress(_) => INVALID_PARAMS,
+            _ => NETWORK_ERROR,
+        };
+        Self::new(code, e.to_string())
+    }
+}
+
+// Errors from code still on anyhow keep their code when they wrap one of the typed errors
+impl From<BridgeError> for RpcError {
+    fn from(e: BridgeError) -> Self {
+        match e {
+            BridgeError::Storage(e) => e.into(),
+            BridgeError::Proof(e) => e.into(),
+            BridgeError::UnknownBlock(_) | BridgeError::NoCertificate(_) | BridgeError::NoValidatorSet(_) => {
+                Self::new(NOT_FOUND, e.to_string())
+            }
+            BridgeError::NotFinalized(_) | BridgeError::Unproven(..) => Self::invalid_params(e.to_string()),
+            _ => Self::new(INTERNAL_ERROR, e.to_string()),
+        }
+    }
+}
+
+impl From<anyhow::Error> for RpcError {
+    fn from(e: anyhow::Error) -> Self {
+        let e = match e.downcast::<StorageError>() {
+            Ok(e) => return e.into(),
+            Err(e) => e,
+        };
+        match e.downcast::<ProofError>() {
+            Ok(e) => e.into(),
+            Err(e) => Self::new(INTERNAL_ERROR, format!("{:#}", e)),
+        }
+    }
+}
+
+impl RpcResponse {
+    fn result(id: Value, result: Value) -> Self {
+        Self { jsonrpc: "2.0", id, result: Some(result), error: None }
+    }
+
+    fn error(id: Value, error: RpcError) -> Self {
+        Self { jsonrpc: "2.0", id, result: None, error: Some(error) }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct BlockQuery {
+    number: Option<u64>,
+    hash: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TransactionQuery {
+    id: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct AccountQuery {
+    account: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct CheckpointQuery {
+    // The finalized block when absent
+    number: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AccountAtQuery {
+    account: String,
+    // The finalized block when absent
+    height: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ValidatorsQuery {
+    // The current validators when absent; otherwise those a finalized block left
+    height: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct HistoryQuery {
+    account: String,
+    // next_cursor of the previous page
+    cursor: Option<u64>,
+    limit: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EpochQuery {
+    epoch: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct BanQuery {
+    peer: String,
+    secs: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LogFilterQuery {
+    filter: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct DumpStateQuery {
+    height: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SubmitTransaction {
+    transaction: Transaction,
+}
+
+#[derive(Debug, Deserialize)]
+struct SubmitBatch {
+    transactions: Vec<Transaction>,
+}
+
+// What became of one transaction of a tx_submitBatch call, in the order they were sent
+#[derive(Debug, Serialize)]
+struct BatchResult {
+    id: String,
+    accepted: bool,
+    // Accepted, but waiting behind a nonce gap
+    queued: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum TransactionStatus {
+    Pending,
+    Included,
+    Unknown,
+}
+
+// Full nodes answer from storage and consensus state, light clients only from their
+// verified header chain
+enum NodeView {
+    Full {
+        storage: StorageManager,
+        state: Arc<RwLock<ConsensusState>>,
+        sync: Arc<RwLock<SyncStatus>>,
+        import: Arc<RwLock<ImportStats>>,
+        inbox: InboxMonitor,
+        peers: Arc<RwLock<Vec<PeerInfo>>>,
+        proofs: ProofService,
+        node_id: NodeId,
+        events: Arc<RwLock<VecDeque<ChainEvent>>>,
+        limits: BlockLimits,
+        mempool: MempoolConfig,
+    },
+    Light(Arc<RwLock<Option<VerifiedHead>>>),
+}
+
+// What a full node's RPC server answers from, handed over by the node as it starts
+pub struct FullNodeHandles {
+    pub storage: StorageManager,
+    pub state: Arc<RwLock<ConsensusState>>,
+    pub sync: Arc<RwLock<SyncStatus>>,
+    pub import: Arc<RwLock<ImportStats>>,
+    pub inbox: InboxMonitor,
+    pub peers: Arc<RwLock<Vec<PeerInfo>>>,
+    pub proofs: ProofService,
+    pub node_id: NodeId,
+    pub chain_events: broadcast::Receiver<ChainEvent>,
+    pub limits: BlockLimits,
+    pub mempool: MempoolConfig,
+}
+
+pub struct RpcServer {
+    addr: SocketAddr,
+    node: NodeView,
+    clock: Arc<ClockSkewMonitor>,
+    admin: Option<AdminHandle>,
+}
+
+impl RpcServer {
+    pub fn new(port: u16, node: FullNodeHandles, clock: Arc<ClockSkewMonitor>) -> Self {
+        let FullNodeHandles { storage, state, sync, import, inbox, peers, proofs, node_id, chain_events, limits, mempool } = node;
+        let events = Arc::new(RwLock::new(VecDeque::new()));
+        tokio::spawn(collect_events(chain_events, events.clone()));
+
+        Self {
+            addr: SocketAddr::from(([127, 0, 0, 1], port)),
+            node: NodeView::Full { storage, state, sync, import, inbox, peers, proofs, node_id, events, limits, mempool },
+            clock,
+            admin: None,
+        }
+    }
+
+    pub fn light_client(
+        port: u16,
+        head: Arc<RwLock<Option<VerifiedHead>>>,
+        clock: Arc<ClockSkewMonitor>,
+    ) -> Self {
+        Self {
+            addr: SocketAddr::from(([127, 0, 0, 1], port)),
+            node: NodeView::Light(head),
+            clock,
+            admin: None,
+        }
+    }
+
+    // Serves the admin_* methods, which change how the node runs
+    pub fn enable_admin(&mut self, admin: AdminHandle) {
+        self.admin = Some(admin);
+    }
+
+    pub async fn start(self, mut shutdown: ShutdownSignal) -> Result<()> {
+        let listener = TcpListener::bind(self.addr).await?;
+        info!("🛰️ JSON-RPC server listening on http://{}", self.addr);
+
+        // Event streams stay open until the client leaves, so they are ended on shutdown
+        let (stop, stopping) = watch::channel(false);
+        let app = Router::new()
+            .route("/", post(handle_http))
+            .route("/mempool/events", get(move |state| handle_mempool_events(state, stopping.clone())))
+            .with_state(Arc::new(self));
+        axum::serve(listener, app)
+            .with_graceful_shutdown(async move {
+                shutdown.recv().await;
+                let _ = stop.send(true);
+            })
+            .await?;
+
+        Ok(())
+    }
+
+    async fn dispatch(&self, method: &str, params: Value) -> Result<Value, RpcError> {
+        match method {
+            "chain_getBlock" => self.chain_get_block(params).await,
+            "chain_getLatestBlock" => self.chain_get_latest_block().await,
+            "chain_getFinality" => self.chain_get_finality(params).await,
+            "chain_getFinalityCertificate" => self.chain_get_finality_certificate(params).await,
+            "chain_getCheckpoint" => self.chain_get_checkpoint(params).await,
+            "bridge_getProof" => self.bridge_get_proof(params).await,
+            "tx_submit" => self.tx_submit(params).await,
+            "tx_submitBatch" => self.tx_submit_batch(params).await,
+            "tx_getStatus" => self.tx_get_status(params).await,
+            "tx_getReceipt" => self.tx_get_receipt(params).await,
+            "fee_estimate" => self.fee_estimate().await,
+            "state_getAccount" => self.state_get_account(params).await,
+            "state_getAccountProof" => self.state_get_account_proof(params).await,
+            "state_getTransactions" => self.state_get_transactions(params).await,
+            "state_getNonces" => self.state_get_nonces(params).await,
+            "consensus_getValidators" => self.consensus_get_validators(params).await,
+            "consensus_getState" => self.consensus_get_state().await,
+            "consensus_getParams" => self.consensus_get_params().await,
+            "governance_getProposals" => self.governance_get_proposals().await,
+            "consensus_getEpoch" => self.consensus_get_epoch(params).await,
+            "consensus_getEpochStats" => self.consensus_get_epoch_stats(params).await,
+            "system_health" => self.system_health().await,
+            "system_status" => self.system_status().await,
+            "system_syncState" => self.system_sync_state().await,
+            "system_importStats" => self.system_import_stats().await,
+            "system_consensusQueues" => self.system_consensus_queues().await,
+            "system_peers" => self.system_peers().await,
+            "system_events" => self.system_events().await,
+            "system_pruning" => self.system_pruning().await,
+            "system_storage" => self.system_storage().await,
+            "system_mempool" => self.system_mempool().await,
+            "system_safety" => self.system_safety().await,
+            "admin_banPeer" => self.admin_ban_peer(params).await,
+            "admin_proposeNow" => self.admin_propose_now().await,
+            "admin_resumeProduction" => self.admin_resume_production().await,
+            "admin_setLogFilter" => self.admin_set_log_filter(params).await,
+            "admin_dumpState" => self.admin_dump_state(params).await,
+            "zk_proofJobs" => self.zk_proof_jobs().await,
+            "light_getHead" => self.light_get_head().await,
+            _ => Err(RpcError::new(METHOD_NOT_FOUND, format!("Method not found: {}", method))),
+        }
+    }
+
+    async fn chain_get_block(&self, params: Value) -> Result<Value, RpcError> {
+        let query: BlockQuery = parse_params(params)?;
+        let storage = self.storage()?;
+
+        let block = match (query.number, query.hash) {
+            (Some(number), None) => storage.get_block(number).await?,
+            (None, Some(hash)) => storage.get_block_by_hash(&parse_hash(&hash)?).await?,
+            _ => return Err(RpcError::invalid_params("expected exactly one of `number` or `hash`")),
+        };
+
+        match block {
+            Some(block) if storage.is_header_only(block.header.block_number).await? => {
+                Err(RpcError::new(NOT_FOUND, format!("block {} is pruned to its header", block.header.block_number)))
+            }
+            block => to_value(block),
+        }
+    }
+
+    // A finalized block as a trusted checkpoint for the `checkpoint` setting of new nodes
+    async fn chain_get_checkpoint(&self, params: Value) -> Result<Value, RpcError> {
+        let query: CheckpointQuery = parse_params(params)?;
+        let storage = self.storage()?;
+        let finalized = match storage.get_finalized_block().await? {
+            Some(block) => block.header.block_number,
+            None => return Err(RpcError::new(NOT_FOUND, "no finalized block yet")),
+        };
+        let height = query.number.unwrap_or(finalized);
+        if height > finalized {
+            return Err(RpcError::invalid_params(format!("block {} is not finalized yet", height)));
+        }
+        let (block, state) = match (storage.get_block(height).await?, storage.get_consensus_state_at(height).await?) {
+            (Some(block), Some(state)) => (block, state),
+            _ => return Err(RpcError::new(NOT_FOUND, format!("no consensus state kept for block {}", height))),
+        };
+
+        let checkpoint = TrustedCheckpoint {
+            height,
+            block_hash: block.hash(),
+            validator_set_hash: types::validator_set_hash(&state),
+        };
+        Ok(json!({
+            "height": height,
+            "block_hash": hex::encode(checkpoint.block_hash),
+            "validator_set_hash": hex::encode(checkpoint.validator_set_hash),
+            "checkpoint": checkpoint.to_string(),
+        }))
+    }
+
+    // A finalized block with its proof, certificate and validator set, encoded for verifiers on
+    // other chains that trust one of our checkpoints
+    async fn bridge_get_proof(&self, params: Value) -> Result<Value, RpcError> {
+        let query: CheckpointQuery = parse_params(params)?;
+        let storage = self.storage()?;
+        let height = match query.number {
+            Some(height) => height,
+            None => match storage.get_finalized_block().await? {
+                Some(block) => block.header.block_number,
+                None => return Err(RpcError::new(NOT_FOUND, "no finalized block yet")),
+            },
+        };
+
+        let proof = BridgeProof::export(storage, height).await?;
+        Ok(json!({
+            "height": height,
+            "block_hash": hex::encode(proof.block.header.hash()),
+            "validator_set_hash": hex::encode(proof.validator_set_hash()),
+            "proof_type": proof.block.zk_proof.proof_type,
+            "proof": hex::encode(proof.to_bytes()),
+        }))
+    }
+
+    async fn chain_get_latest_block(&self) -> Result<Value, RpcError> {
+        to_value(self.storage()?.get_latest_block().await?)
+    }
+
+    // Precommit weight and validators a committed block was finalized with
+    async fn chain_get_finality(&self, params: Value) -> Result<Value, RpcError> {
+        let query: BlockQuery = parse_params(params)?;
+        let storage = self.storage()?;
+
+        let block_hash = match (query.number, query.hash) {
+            (Some(number), None) => match storage.get_block(number).await? {
+                Some(block) => block.hash(),
+                None => return Ok(Value::Null),
+            },
+            (None, Some(hash)) => parse_hash(&hash)?,
+            _ => return Err(RpcError::invalid_params("expected exactly one of `number` or `hash`")),
+        };
+        let finality = match storage.get_finality(&block_hash).await? {
+            Some(finality) => finality,
+            None => return Ok(Value::Null),
+        };
+
+        let validators: Vec<Value> = finality.validators.iter()
+            .map(|(node_id, stake)| json!({ "node_id": hex::encode(node_id), "stake": stake }))
+            .collect();
+
+        Ok(json!({
+            "block_hash": hex::encode(finality.block_hash),
+            "block_number": finality.block_number,
+            "round": finality.round,
+            "validators": validators,
+            "weight": finality.weight,
+            "total_stake": finality.total_stake,
+        }))
+    }
+
+    // The signed precommits themselves, which anyone can check against the validator set
+    async fn chain_get_finality_certificate(&self, params: Value) -> Result<Value, RpcError> {
+        let query: BlockQuery = parse_params(params)?;
+        let storage = self.storage()?;
+
+        let block_hash = match (query.number, query.hash) {
+            (Some(number), None) => match storage.get_block(number).await? {
+                Some(block) => block.hash(),
+                None => return Ok(Value::Null),
+            },
+            (None, Some(hash)) => parse_hash(&hash)?,
+            _ => return Err(RpcError::invalid_params("expected exactly one of `number` or `hash`")),
+        };
+        let certificate = match storage.get_certificate(&block_hash).await? {
+            Some(certificate) => certificate,
+            None => return Ok(Value::Null),
+        };
+
+        let precommits: Vec<Value> = certificate.precommits.iter()
+            .map(|vote| json!({
+                "validator": hex::encode(vote.validator),
+                "timestamp": vote.timestamp,
+                "signature": hex::encode(&vote.signature),
+            }))
+            .collect();
+
+        Ok(json!({
+            "block_hash": hex::encode(certificate.block_hash),
+            "block_number": certificate.block_number,
+            "round": certificate.round,
+            "precommits": precommits,
+        }))
+    }
+
+    async fn tx_submit(&self, params: Value) -> Result<Value, RpcError> {
+        let SubmitTransaction { transaction } = parse_params(params)?;
+        let (storage, limits, mempool) = match &self.node {
+            NodeView::Full { storage, limits, mempool, .. } => (storage, limits, mempool),
+            NodeView::Light(_) => return Err(unsupported("light clients do not keep a mempool")),
+        };
+        let ready = accept_transaction(storage, limits, mempool, &*self.clock.clock(), &transaction).await?;
+
+        Ok(json!({ "id": hex::encode(transaction.id), "queued": !ready }))
+    }
+
+    // Rejected transactions do not fail the call; each one gets its own result
+    async fn tx_submit_batch(&self, params: Value) -> Result<Value, RpcError> {
+        let SubmitBatch { transactions } = parse_params(params)?;
+        if transactions.len() > MAX_SUBMIT_BATCH {
+            return Err(RpcError::invalid_params(format!(
+                "{} transactions, at most {} can be submitted at once", transactions.len(), MAX_SUBMIT_BATCH)));
+        }
+        let (storage, limits, mempool) = match &self.node {
+            NodeView::Full { storage, limits, mempool, .. } => (storage, limits, mempool),
+            NodeView::Light(_) => return Err(unsupported("light clients do not keep a mempool")),
+        };
+        let results = accept_transactions(storage, limits, mempool, &*self.clock.clock(), transactions).await?;
+
+        let accepted = results.iter().filter(|result| result.accepted).count();
+        debug!("Accepted {} of {} transactions submitted in a batch over RPC", accepted, results.len());
+        Ok(json!({ "accepted": accepted, "rejected": results.len() - accepted, "results": results }))
+    }
+
+    async fn tx_get_status(&self, params: Value) -> Result<Value, RpcError> {
+        let query: TransactionQuery = parse_params(params)?;
+        let tx_id = parse_hash(&query.id)?;
+        let storage = self.storage()?;
+
+        let status = if storage.is_transaction_pending(&tx_id).await? {
+            TransactionStatus::Pending
+        } else if storage.get_transaction(&tx_id).await?.is_some() {
+            TransactionStatus::Included
+        } else {
+            TransactionStatus::Unknown
+        };
+
+        Ok(json!({ "id": query.id, "status": status }))
+    }
+
+    // Only transactions in finalized blocks have a receipt
+    async fn tx_get_receipt(&self, params: Value) -> Result<Value, RpcError> {
+        let query: TransactionQuery = parse_params(params)?;
+        let receipt = match self.storage()?.get_receipt(&parse_hash(&query.id)?).await? {
+            Some(receipt) => receipt,
+            None => return Ok(Value::Null),
+        };
+
+        Ok(json!({
+            "id": query.id,
+            "block_hash": hex::encode(receipt.block_hash),
+            "block_number": receipt.block_number,
+            "index": receipt.index,
+            "status": receipt.status,
+            "fee": receipt.fee,
+        }))
+    }
+
+    // Fees paid by the transactions of the last finalized blocks, never below what this node
+    // accepts; low gets in when blocks have room, high when they are contested
+    async fn fee_estimate(&self) -> Result<Value, RpcError> {
+        let (storage, mempool) = match &self.node {
+            NodeView::Full { storage, mempool, .. } => (storage, mempool),
+            NodeView::Light(_) => return Err(unsupported("light clients do not keep a mempool")),
+        };
+        let finalized = match storage.get_finalized_block().await? {
+            Some(block) => block.header.block_number,
+            None => 0,
+        };
+        let start = finalized.saturating_sub(FEE_ESTIMATE_BLOCKS - 1);
+        let blocks = storage.get_block_range(start, finalized).await?;
+
+        let mut fees: Vec<u64> = blocks.iter()
+            .flat_map(|block| block.transactions.iter().map(|tx| tx.fee))
+            .collect();
+        fees.sort_unstable();
+        let percentile = |p: usize| match fees.len() {
+            0 => mempool.min_fee,
+            len => fees[(len - 1) * p / 100].max(mempool.min_fee),
+        };
+
+        Ok(json!({
+            "min_fee": mempool.min_fee,
+            "blocks": blocks.len(),
+            "transactions": fees.len(),
+            "low": percentile(25),
+            "median": percentile(50),
+            "high": percentile(90),
+        }))
+    }
+
+    // Balance and nonce as of the last finalized block, or of an earlier one whose state is kept
+    async fn state_get_account(&self, params: Value) -> Result<Value, RpcError> {
+        let query: AccountAtQuery = parse_params(params)?;
+        let id = parse_hash(&query.account)?;
+        let storage = self.storage()?;
+        let account = match query.height {
+            Some(height) => storage.get_state_at(height).await?.account(&id).await?,
+            None => storage.get_account(&id).await?,
+        }.unwrap_or_default();
+
+        Ok(json!({
+            "account": query.account,
+            "block_number": query.height,
+            "balance": account.balance,
+            "nonce": account.nonce,
+        }))
+    }
+
+    // Merkle proof of the account against the state root of a finalized block, which a client
+    // holding only the header can check
+    async fn state_get_account_proof(&self, params: Value) -> Result<Value, RpcError> {
+        let query: AccountAtQuery = parse_params(params)?;
+        let storage = self.storage()?;
+        let height = match query.height {
+            Some(height) => height,
+            None => match storage.get_finalized_block().await? {
+                Some(block) => block.header.block_number,
+                None => return Err(RpcError::new(NOT_FOUND, "no finalized block yet")),
+            },
+        };
+        let (block, proof) = storage.get_account_proof(&parse_hash(&query.account)?, height).await?;
+
+        Ok(json!({
+            "account": query.account,
+            "block_number": height,
+            "block_hash": hex::encode(block.hash()),
+            "state_root": hex::encode(block.header.state_root),
+            "state": proof.state.map(|account| json!({ "balance": account.balance, "nonce": account.nonce })),
+            "siblings": proof.siblings.iter().map(hex::encode).collect::<Vec<_>>(),
+            "neighbour": proof.neighbour.map(|(id, account)| json!({
+                "account": hex::encode(id),
+                "balance": account.balance,
+                "nonce": account.nonce,
+            })),
+        }))
+    }
+
+    // Finalized transactions sent or received by the account, newest first
+    async fn state_get_transactions(&self, params: Value) -> Result<Value, RpcError> {
+        let query: HistoryQuery = parse_params(params)?;
+        let page = HistoryPage { cursor: query.cursor, limit: query.limit.unwrap_or(MAX_HISTORY_PAGE) };
+        to_value(self.storage()?.get_transactions_by_address(&parse_hash(&query.account)?, &page).await?)
+    }
+
+    // Next nonce to use, the nonces pending transactions wait on, and the balance they spend from
+    async fn state_get_nonces(&self, params: Value) -> Result<Value, RpcError> {
+        let query: AccountQuery = parse_params(params)?;
+        let status = self.storage()?.get_nonce_status(&parse_hash(&query.account)?).await?;
+
+        Ok(json!({
+            "account": query.account,
+            "confirmed_nonce": status.confirmed_nonce,
+            "next_nonce": status.next_nonce,
+            "queued": status.queued,
+            "gaps": status.gaps,
+            "confirmed_balance": status.confirmed_balance,
+        }))
+    }
+
+    async fn consensus_get_validators(&self, params: Value) -> Result<Value, RpcError> {
+        let query: ValidatorsQuery = match params {
+            Value::Null => ValidatorsQuery::default(),
+            params => parse_params(params)?,
+        };
+        match query.height {
+            Some(height) => Ok(validators_json(&self.storage()?.get_state_at(height).await?.consensus_state)),
+            None => Ok(validators_json(&*self.state()?.read().await)),
+        }
+    }
+
+    async fn consensus_get_state(&self) -> Result<Value, RpcError> {
+        let state = self.state()?.read().await;
+
+        Ok(json!({
+            "head": state.head,
+            "justified": state.justified,
+            "finalized": state.finalized,
+            "epoch": state.epoch,
+            "total_stake": state.total_stake,
+            "validator_count": state.validators.len(),
+            "pending_stake_changes": state.pending_stake_changes.len(),
+        }))
+    }
+
+    // Parameters in force; governance proposals change them
+    async fn consensus_get_params(&self) -> Result<Value, RpcError> {
+        let state = self.state()?.read().await;
+        Ok(json!({ "params": state.params }))
+    }
+
+    // Open proposals with the active stake for and against them so far, and those decided as
+    // the current epoch began
+    async fn governance_get_proposals(&self) -> Result<Value, RpcError> {
+        let state = self.state()?.read().await;
+
+        let proposals: Vec<Value> = state.proposals.iter()
+            .map(|proposal| {
+                let (approving, rejecting) = proposal.tally(&state);
+                let action = match &proposal.action {
+                    staking::ProposalAction::ChangeParams(params) => json!({ "change_params": params }),
+                    staking::ProposalAction::RemoveValidator(node_id) => json!({ "remove_validator": hex::encode(node_id) }),
+                };
+                json!({
+                    "id": hex::encode(proposal.id),
+                    "proposer": hex::encode(proposal.proposer),
+                    "action": action,
+                    "block_number": proposal.block_number,
+                    "voting_ends_epoch": proposal.voting_ends,
+                    "status": proposal.status,
+                    "votes": proposal.votes.len(),
+                    "approving_stake": approving,
+                    "rejecting_stake": rejecting,
+                })
+            })
+            .collect();
+
+        Ok(json!({
+            "epoch": state.epoch,
+            "total_stake": state.total_stake,
+            "proposals": proposals,
+        }))
+    }
+
+    // Validator set of a past or the current epoch, null until its first block is finalized
+    async fn consensus_get_epoch(&self, params: Value) -> Result<Value, RpcError> {
+        let EpochQuery { epoch } = parse_params(params)?;
+        let change = match self.storage()?.get_epoch_change(epoch).await? {
+            Some(change) => change,
+            None => return Ok(Value::Null),
+        };
+
+        let validators: Vec<Value> = change.validators.iter()
+            .map(|(node_id, stake)| json!({ "node_id": hex::encode(node_id), "stake": stake }))
+            .collect();
+
+        Ok(json!({
+            "epoch": change.epoch,
+            "start_block": change.start_block,
+            "block_hash": hex::encode(change.block_hash),
+            "validators": validators,
+            "total_stake": change.total_stake,
+        }))
+    }
+
+    // Proposals, precommits and proof responses of each active validator during the epoch
+    async fn consensus_get_epoch_stats(&self, params: Value) -> Result<Value, RpcError> {
+        let EpochQuery { epoch } = parse_params(params)?;
+        let stats = match self.storage()?.get_epoch_stats(epoch).await? {
+            Some(stats) => stats,
+            None => return Ok(Value::Null),
+        };
+
+        let mut validators: Vec<_> = stats.validators.iter().collect();
+        validators.sort_by_key(|(node_id, _)| **node_id);
+        let validators: Vec<Value> = validators.into_iter()
+            .map(|(node_id, validator)| json!({
+                "node_id": hex::encode(node_id),
+                "proposed": validator.proposed,
+                "missed": validator.missed,
+                "missed_votes": validator.missed_votes,
+                "performance_score": validator.performance_score,
+                "votes": validator.votes,
+                "proof_responses": validator.proof_responses,
+                "avg_proof_latency_ms": validator.proof_latency_ms.checked_div(validator.proof_responses),
+            }))
+            .collect();
+
+        Ok(json!({
+            "epoch": stats.epoch,
+            "blocks": stats.blocks,
+            "validators": validators,
+        }))
+    }
+
+    async fn system_health(&self) -> Result<Value, RpcError> {
+        let clock = self.clock.health_check().await;
+        let storage = match &self.node {
+            NodeView::Full { storage, .. } => storage,
+            NodeView::Light(_) => return Ok(json!({ "clock": clock })),
+        };
+        let safety = storage.get_safety_alarm().await?.unwrap_or_default();
+        Ok(json!({
+            "clock": clock,
+            "safety": { "halted": safety.halted, "violations": safety.violations.len() },
+        }))
+    }
+
+    // Everything `status` prints, in one call
+    async fn system_status(&self) -> Result<Value, RpcError> {
+        let (storage, state, peers, proofs, node_id) = match &self.node {
+            NodeView::Full { storage, state, peers, proofs, node_id, .. } => (storage, state, peers, proofs, node_id),
+            NodeView::Light(head) => {
+                let height = head.read().await.as_ref().map(|head| head.block.header.block_number);
+                return Ok(json!({ "mode": "light_client", "height": height }));
+            }
+        };
+
+        let height = storage.get_latest_block().await?.map(|block| block.header.block_number);
+        let finalized_height = storage.get_finalized_block().await?.map(|block| block.header.block_number);
+        let (justified_height, validator) = {
+            let state = state.read().await;
+            (state.justified, state.validators.get(node_id).map(|info| json!({ "stake": info.stake, "is_active": info.is_active })))
+        };
+        let (queued, running) = proofs.backlog().await;
+
+        Ok(json!({
+            "mode": if validator.is_some() { "validator" } else { "full_node" },
+            "node_id": hex::encode(node_id),
+            "height": height,
+            "justified_height": justified_height,
+            "finalized_height": finalized_height,
+            "peers": peers.read().await.len(),
+            "mempool": storage.get_pending_transactions().await?.len(),
+            "validator": validator,
+            "proof_jobs": { "queued": queued, "running": running },
+        }))
+    }
+
+    // Safety violations this node detected, and whether it halted on them
+    async fn system_safety(&self) -> Result<Value, RpcError> {
+        to_value(self.storage()?.get_safety_alarm().await?.unwrap_or_default())
+    }
+
+    async fn system_pruning(&self) -> Result<Value, RpcError> {
+        to_value(self.storage()?.get_pruning_stats().await?)
+    }
+
+    // Estimated keys and bytes per column family and the database's size on disk
+    async fn system_storage(&self) -> Result<Value, RpcError> {
+        to_value(self.storage()?.get_storage_stats().await?)
+    }
+
+    // Pending transactions with the total count, listed in the order the next block would take them
+    async fn system_mempool(&self) -> Result<Value, RpcError> {
+        let pending = self.storage()?.get_pending_by_fee().await?;
+        let transactions: Vec<Value> = pending.iter()
+            .take(MAX_MEMPOOL_LISTED)
+            .map(|tx| json!({
+                "id": hex::encode(tx.id),
+                "from": hex::encode(tx.from),
+                "to": hex::encode(tx.to),
+                "amount": tx.amount,
+                "fee": tx.fee,
+                "nonce": tx.nonce,
+            }))
+            .collect();
+
+        Ok(json!({ "count": pending.len(), "transactions": transactions }))
+    }
+
+    // Disconnects the peer and refuses it for `secs`, an hour by default
+    async fn admin_ban_peer(&self, params: Value) -> Result<Value, RpcError> {
+        let admin = self.admin()?;
+        let BanQuery { peer, secs } = parse_params(params)?;
+        let duration = Duration::from_secs(secs.unwrap_or(DEFAULT_BAN_SECS));
+
+        ask(&admin.network, |reply| NetworkCommand::Ban { peer: peer.clone(), duration, reply }).await??;
+        Ok(json!({ "peer": peer, "secs": duration.as_secs() }))
+    }
+
+    // Proposes without waiting for the next consensus tick; fails unless it is our turn
+    async fn admin_propose_now(&self) -> Result<Value, RpcError> {
+        let height = ask(&self.admin()?.consensus, ConsensusCommand::ProposeNow).await??;
+        Ok(json!({ "height": height }))
+    }
+
+    async fn admin_resume_production(&self) -> Result<Value, RpcError> {
+        let violations = ask(&self.admin()?.consensus, ConsensusCommand::ResumeProduction).await??;
+        Ok(json!({ "resumed": true, "violations": violations }))
+    }
+
+    // Replaces the whole filter, until the next call or a restart
+    async fn admin_set_log_filter(&self, params: Value) -> Result<Value, RpcError> {
+        let log_filter = match &self.admin()?.log_filter {
+            Some(log_filter) => log_filter,
+            None => return Err(unsupported("the log filter is not managed by this node")),
+        };
+        let LogFilterQuery { filter } = parse_params(params)?;
+        let filter = logging::parse_filter(&filter).map_err(|e| RpcError::invalid_params(e.to_string()))?;
+        let directives = filter.to_string();
+
+        log_filter.reload(filter).map_err(|e| anyhow!("{}", e))?;
+        info!("🛠️ Log filter set to {} on operator request", directives);
+        Ok(json!({ "filter": directives }))
+    }
+
+    // Without a height the live consensus and round state; with one what storage has on that
+    // height: the block header, the precommits that finalized it and the validator set of its epoch
+    async fn admin_dump_state(&self, params: Value) -> Result<Value, RpcError> {
+        let admin = self.admin()?;
+        let DumpStateQuery { height } = parse_params(params)?;
+
+        let height = match height {
+            Some(height) => height,
+            None => {
+                let round = ask(&admin.consensus, ConsensusCommand::RoundState).await?;
+                let polka = |value: Option<(BlockHash, u64)>| value
+                    .map(|(block_hash, round)| json!({ "block_hash": hex::encode(block_hash), "round": round }));
+                return Ok(json!({
+                    "round": {
+                        "height": round.height,
+                        "round": round.round,
+                        "step": round.step,
+                        "proposed": round.proposed,
+                        "locked": polka(round.locked),
+                        "valid": polka(round.valid),
+                    },
+                    "state": self.consensus_get_state().await?,
+                    "validators": validators_json(&*self.state()?.read().await),
+                }));
+            }
+        };
+
+        let block = match self.storage()?.get_block(height).await? {
+            Some(block) => block,
+            None => return Ok(Value::Null),
+        };
+        Ok(json!({
+            "hash": hex::encode(block.hash()),
+            "header": block.header,
+            "transactions": block.transactions.len(),
+            "parent_votes": block.parent_votes.len(),
+            "finality": self.chain_get_finality(json!({ "number": height })).await?,
+            "epoch": self.consensus_get_epoch(json!({ "epoch": staking::epoch_of(height) })).await?,
+        }))
+    }
+
+    async fn system_sync_state(&self) -> Result<Value, RpcError> {
+        match &self.node {
+            NodeView::Full { sync, .. } => to_value(&*sync.read().await),
+            NodeView::Light(_) => Err(unsupported("light clients sync headers only, see light_getHead")),
+        }
+    }
+
+    // How many blocks each import stage passed and rejected, and how long it took
+    async fn system_import_stats(&self) -> Result<Value, RpcError> {
+        match &self.node {
+            NodeView::Full { import, .. } => to_value(&*import.read().await),
+            NodeView::Light(_) => Err(unsupported("light clients do not import blocks")),
+        }
+    }
+
+    // Messages waiting in each lane of the consensus inbox, and how many were dropped or held up
+    // because their lane was full
+    async fn system_consensus_queues(&self) -> Result<Value, RpcError> {
+        match &self.node {
+            NodeView::Full { inbox, .. } => to_value(inbox.stats()),
+            NodeView::Light(_) => Err(unsupported("light clients run no consensus engine")),
+        }
+    }
+
+    // Connected peers with their scores; misbehaving peers have negative scores until they are banned
+    async fn system_peers(&self) -> Result<Value, RpcError> {
+        match &self.node {
+            NodeView::Full { peers, .. } => to_value(&*peers.read().await),
+            NodeView::Light(_) => Err(unsupported("light clients do not score peers")),
+        }
+    }
+
+    // Latest blocks imported and finalized, votes counted and epochs started, oldest first
+    async fn system_events(&self) -> Result<Value, RpcError> {
+        match &self.node {
+            NodeView::Full { events, .. } => to_value(&*events.read().await),
+            NodeView::Light(_) => Err(unsupported("light clients do not take part in consensus")),
+        }
+    }
+
+    async fn zk_proof_jobs(&self) -> Result<Value, RpcError> {
+        match &self.node {
+            NodeView::Full { proofs, .. } => to_value(&proofs.jobs().await),
+            NodeView::Light(_) => Err(unsupported("light clients do not generate proofs")),
+        }
+    }
+
+    async fn light_get_head(&self) -> Result<Value, RpcError> {
+        let head = match &self.node {
+            NodeView::Light(head) => head.read().await,
+            NodeView::Full { .. } => return Err(unsupported("light_getHead is only served by light clients")),
+        };
+
+        Ok(match head.as_ref() {
+            Some(head) => json!({
+                "block_number": head.block.header.block_number,
+                "hash": hex::encode(head.hash),
+                "header": head.block.header,
+                "proof_type": head.block.zk_proof.proof_type,
+                "verified_headers": head.verified_headers,
+                "updated_at": head.updated_at,
+            }),
+            None => Value::Null,
+        })
+    }
+
+    fn storage(&self) -> Result<&StorageManager, RpcError> {
+        match &self.node {
+            NodeView::Full { storage, .. } => Ok(storage),
+            NodeView::Light(_) => Err(unsupported("light clients do not store blocks or transactions")),
+        }
+    }
+
+    fn admin(&self) -> Result<&AdminHandle, RpcError> {
+        self.admin.as_ref().ok_or_else(|| unsupported("admin methods are disabled, set network.admin = true"))
+    }
+
+    fn state(&self) -> Result<&Arc<RwLock<ConsensusState>>, RpcError> {
+        match &self.node {
+            NodeView::Full { state, .. } => Ok(state),
+            NodeView::Light(_) => Err(unsupported("light clients do not track consensus state")),
+        }
+    }
+}
+
+// Checks that only need the transaction itself, the number of the next block and the time on
+// the node's clock, which batches run in parallel
+pub fn check_transaction(
+    limits: &BlockLimits,
+    mempool: &MempoolConfig,
+    next_block: u64,
+    now: &DateTime<Utc>,
+    transaction: &Transaction,
+) -> Result<(), String> {
+    if let Err(e) = transaction.validate() {
+        return Err(e.to_string());
+    }
+
+    if transaction.is_expired(next_block, now) {
+        return Err("transaction has expired".to_string());
+    }
+
+    if transaction.fee < mempool.min_fee {
+        return Err(format!("fee {} is below this node's minimum of {}", transaction.fee, mempool.min_fee));
+    }
+
+    if let Err(e) = limits.check_transaction(transaction) {
+        return Err(e.to_string());
+    }
+
+    if !staking::validate_transaction(transaction) {
+        return Err("staking transaction is not signed by its validator".to_string());
+    }
+    Ok(())
+}
+
+// Adds a submitted transaction to the mempool; invalid ones are rejected with INVALID_PARAMS.
+// Returns false when it is queued behind a nonce gap
+async fn accept_transaction(
+    storage: &StorageManager,
+    limits: &BlockLimits,
+    mempool: &MempoolConfig,
+    clock: &dyn Clock,
+    transaction: &Transaction,
+) -> Result<bool, RpcError> {
+    let next_block = next_block_number(storage).await?;
+    if let Err(e) = check_transaction(limits, mempool, next_block, &clock.now(), transaction) {
+        return Err(RpcError::invalid_params(e));
+    }
+
+    if storage.get_transaction(&transaction.id).await?.is_some() {
+        return Err(RpcError::invalid_params("transaction already known"));
+    }
+
+    let ready = match storage.get_nonce_status(&transaction.from).await?.check(transaction) {
+        Ok(ready) => ready,
+        Err(e) => return Err(RpcError::invalid_params(e.to_string())),
+    };
+
+    storage.make_room(transaction, mempool.max_transactions).await?;
+    storage.store_transaction(transaction).await?;
+    debug!("Accepted transaction {} over RPC{}", hex::encode(transaction.id),
+        if ready { "" } else { ", queued behind a nonce gap" });
+    Ok(ready)
+}
+
+// The first block a submitted transaction could go into
+async fn next_block_number(storage: &StorageManager) -> Result<u64, RpcError> {
+    Ok(storage.get_finalized_block().await?.map_or(0, |block| block.header.block_number) + 1)
+}
+
+// accept_transaction for many transactions at once. Signatures and limits are checked on all
+// cores, nonces and balances in order with the sender's earlier transactions of the batch
+// counted as pending, and the accepted transactions are written in one batch. Transactions peers push to
+// us are admitted the same way
+async fn accept_transactions(
+    storage: &StorageManager,
+    limits: &BlockLimits,
+    mempool: &MempoolConfig,
+    clock: &dyn Clock,
+    transactions: Vec<Transaction>,
+) -> Result<Vec<BatchResult>, RpcError> {
+    let next_block = next_block_number(storage).await?;
+    let now = clock.now();
+    let transactions = Arc::new(transactions);
+    let workers = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let chunk = transactions.len().div_ceil(workers).max(1);
+    let mut checks = Vec::new();
+    for start in (0..transactions.len()).step_by(chunk) {
+        let (transactions, limits, mempool) = (transactions.clone(), *limits, mempool.clone());
+        checks.push(tokio::task::spawn_blocking(move || {
+            transactions[start..(start + chunk).min(transactions.len())].iter()
+                .map(|transaction| check_transaction(&limits, &mempool, next_block, &now, transaction))
+                .collect::<Vec<_>>()
+        }));
+    }
+    let mut checked = Vec::with_capacity(transactions.len());
+    for check in checks {
+        checked.extend(check.await.map_err(anyhow::Error::from)?);
+    }
+
+    let mut room = mempool.max_transactions.saturating_sub(storage.get_pending_transactions().await?.len());
+    let mut nonces: HashMap<AccountId, NonceStatus> = HashMap::new();
+    let mut seen = HashSet::new();
+    let mut admitted = Vec::new();
+    let mut results = Vec::with_capacity(transactions.len());
+    for (transaction, checked) in transactions.iter().zip(checked) {
+        let verdict = match checked {
+            Err(e) => Err(e),
+            Ok(()) if !seen.insert(transaction.id) || storage.get_transaction(&transaction.id).await?.is_some() => {
+                Err("transaction already known".to_string())
+            }
+            Ok(()) => {
+                let status = match nonces.entry(transaction.from) {
+                    Entry::Occupied(entry) => entry.into_mut(),
+                    Entry::Vacant(entry) => {
+                        entry.insert(storage.get_nonce_status(&transaction.from).await?)
+                    }
+                };
+                match status.check(transaction) {
+                    Err(e) => Err(e.to_string()),
+                    Ok(ready) if room > 0 => {
+                        room -= 1;
+                        status.record(transaction);
+                        admitted.push(transaction.clone());
+                        Ok(ready)
+                    }
+                    // Once the mempool is full each transaction has to outbid the cheapest
+                    // pending one, which may be one admitted just before it
+                    Ok(ready) => {
+                        storage.store_transactions_batch(&admitted).await?;
+                        admitted.clear();
+                        match storage.make_room(transaction, mempool.max_transactions).await {
+                            Err(StorageError::Rejected(e)) => Err(e),
+                            Err(e) => return Err(e.into()),
+                            Ok(()) => {
+                                storage.store_transaction(transaction).await?;
+                                status.record(transaction);
+                                Ok(ready)
+                            }
+                        }
+                    }
+                }
+            }
+        };
+
+        let id = hex::encode(transaction.id);
+        results.push(match verdict {
+            Ok(ready) => BatchResult { id, accepted: true, queued: !ready, error: None },
+            Err(error) => BatchResult { id, accepted: false, queued: false, error: Some(error) },
+        });
+    }
+    storage.store_transactions_batch(&admitted).await?;
+    Ok(results)
+}
+
+// Admits the transactions peers push to us, batch by batch as if submitted with
+// tx_submitBatch, until shutdown
+pub async fn admit_pushed_transactions(
+    storage: StorageManager,
+    limits: BlockLimits,
+    mempool: MempoolConfig,
+    clock: Arc<dyn Clock>,
+    mut pushed: mpsc::Receiver<Vec<Transaction>>,
+    mut shutdown: ShutdownSignal,
+) -> Result<()> {
+    loop {
+        let transactions = tokio::select! {
+            transactions = pushed.recv() => match transactions {
+                Some(transactions) => transactions,
+                None => return Ok(()),
+            },
+            _ = shutdown.recv() => return Ok(()),
+        };
+        let count = transactions.len();
+        match accept_transactions(&storage, &limits, &mempool, &*clock, transactions).await {
+            Ok(results) => debug!("Accepted {} of {} transactions pushed by peers",
+                results.iter().filter(|result| result.accepted).count(), count),
+            Err(e) => warn!("Failed to admit {} transactions pushed by peers: {}", count, e.message),
+        }
+    }
+}
+
+// Sends a command to the consensus engine or network manager and waits for its answer
+async fn ask<C, T>(commands: &mpsc::Sender<C>, command: impl FnOnce(oneshot::Sender<T>) -> C) -> Result<T> {
+    let (reply, answer) = oneshot::channel();
+    commands.send(command(reply)).await.map_err(|_| anyhow!("node is shutting down"))?;
+    Ok(answer.await?)
+}
+
+async fn collect_events(mut chain_events: broadcast::Receiver<ChainEvent>, events: Arc<RwLock<VecDeque<ChainEvent>>>) {
+    loop {
+        let event = match chain_events.recv().await {
+            Ok(event) => event,
+            Err(broadcast::error::RecvError::Lagged(_)) => continue,
+            Err(broadcast::error::RecvError::Closed) => return,
+        };
+
+        let mut events = events.write().await;
+        if events.len() == RECENT_EVENTS {
+            events.pop_front();
+        }
+        events.push_back(event);
+    }
+}
+
+async fn handle_http(State(server): State<Arc<RpcServer>>, body: String) -> Json<Value> {
+    let request: Value = match serde_json::from_str(&body) {
+        Ok(request) => request,
+        Err(e) => return Json(json!(RpcResponse::error(Value::Null, RpcError::new(PARSE_ERROR, e.to_string())))),
+    };
+
+    // Batches are answered with an array of responses in the same order
+    let response = match request {
+        Value::Array(requests) if !requests.is_empty() => {
+            let mut responses = Vec::with_capacity(requests.len());
+            for request in requests {
+                responses.push(handle_request(&server, request).await);
+            }
+            json!(responses)
+        }
+        request => json!(handle_request(&server, request).await),
+    };
+
+    Json(response)
+}
+
+// Server-sent events for every change to the pending pool, for block builders and wallets.
+// A client that falls behind gets a `lagged` event and should reload the pool with system_mempool
+async fn handle_mempool_events(
+    State(server): State<Arc<RpcServer>>,
+    stopping: watch::Receiver<bool>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, (StatusCode, &'static str)> {
+    let events = match &server.node {
+        NodeView::Full { storage, .. } => storage.subscribe_mempool(),
+        NodeView::Light(_) => return Err((StatusCode::NOT_FOUND, "light clients have no mempool")),
+    };
+
+    let events = stream::unfold((events, stopping), |(mut events, mut stopping)| async move {
+        let event = tokio::select! {
+            received = events.recv() => match received {
+                Ok(event) => mempool_event(&event),
+                Err(broadcast::error::RecvError::Lagged(missed)) => {
+                    Event::default().event("lagged").data(json!({ "missed": missed }).to_string())
+                }
+                Err(broadcast::error::RecvError::Closed) => return None,
+            },
+            _ = stopping.changed() => return None,
+        };
+        Some((Ok(event), (events, stopping)))
+    });
+
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+fn mempool_event(event: &MempoolEvent) -> Event {
+    let (name, data) = match event {
+        MempoolEvent::TxAdded(tx) => ("tx_added", json!({
+            "id": hex::encode(tx.id),
+            "from": hex::encode(tx.from),
+            "to": hex::encode(tx.to),
+            "amount": tx.amount,
+            "fee": tx.fee,
+            "nonce": tx.nonce,
+        })),
+        MempoolEvent::TxDropped { tx_id, reason } => ("tx_dropped", json!({
+            "id": hex::encode(tx_id),
+            "reason": reason,
+        })),
+        MempoolEvent::TxIncluded { tx_id, block_hash, block_number } => ("tx_included", json!({
+            "id": hex::encode(tx_id),
+            "block_hash": hex::encode(block_hash),
+            "block_number": block_number,
+        })),
+    };
+    Event::default().event(name).data(data.to_string())
+}
+
+async fn handle_request(server: &RpcServer, request: Value) -> RpcResponse {
+    let request: RpcRequest = match serde_json::from_value(request) {
+        Ok(request) => request,
+        Err(e) => return RpcResponse::error(Value::Null, RpcError::new(INVALID_REQUEST, e.to_string())),
+    };
+
+    if request.jsonrpc != "2.0" {
+        return RpcResponse::error(request.id, RpcError::new(INVALID_REQUEST, "jsonrpc must be \"2.0\""));
+    }
+
+    debug!("RPC call {}", request.method);
+    match server.dispatch(&request.method, request.params).await {
+        Ok(result) => RpcResponse::result(request.id, result),
+        Err(error) => RpcResponse::error(request.id, error),
+    }
+}
+
+// Client side of the API, for subcommands talking to a running node
+pub async fn call(url: &str, method: &str, params: Value) -> Result<Value> {
+    let request = json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params });
+    let mut response: Value = reqwest::Client::new()
+        .post(url)
+        .json(&request)
+        .send().await
+        .map_err(|e| anyhow!("Could not reach the node at {}: {}", url, e))?
+        .json().await?;
+
+    if let Some(error) = response.get("error") {
+        return Err(anyhow!("{} failed: {}", method, error["message"].as_str().unwrap_or("unknown error")));
+    }
+    Ok(response["result"].take())
+}
+
+// Node ids are not valid JSON object keys, so validators are returned as a list
+fn validators_json(state: &ConsensusState) -> Value {
+    let validators: Vec<Value> = state.validators.iter()
+        .map(|(node_id, info)| json!({
+            "node_id": hex::encode(node_id),
+            "stake": info.stake,
+            "is_active": info.is_active,
+            "last_block_time": info.last_block_time,
+            "performance_score": info.performance_score,
+            "proposed": info.proposed,
+            "missed": info.missed,
+            "missed_votes": info.missed_votes,
+            "jailed_at": info.jailed_at,
+            "rewards": info.rewards,
+        }))
+        .collect();
+
+    Value::Array(validators)
+}
+
+// Accepts params as an object, optionally wrapped in a single-element array
+fn parse_params<T: serde::de::DeserializeOwned>(params: Value) -> Result<T, RpcError> {
+    let params = match params {
+        Value::Array(mut values) if values.len() == 1 => values.remove(0),
+        params => params,
+    };
+
+    serde_json::from_value(params).map_err(|e| RpcError::invalid_params(e.to_string()))
+}
+
+fn parse_hash(hex_str: &str) -> Result<BlockHash, RpcError> {
+    let bytes = hex::decode(hex_str.trim_start_matches("0x"))
+        .map_err(|e| RpcError::invalid_params(format!("invalid hex: {}", e)))?;
+
+    bytes.try_into()
+        .map_err(|_| RpcError::invalid_params("expected a 32-byte hex string"))
+}
+
+fn unsupported(message: &str) -> RpcError {
+    RpcError::new(METHOD_NOT_FOUND, message)
+}
+
+fn to_value<T: Serialize>(value: T) -> Result<Value, RpcError> {
+    serde_json::to_value(value).map_err(|e| RpcError::new(INTERNAL_ERROR, e.to_string()))
+}
@@ -0,0 +1,123 @@
+use anyhow::Result;
+use std::future::Future;
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+use tokio::time::{timeout_at, Duration, Instant};
+use tracing::{info, warn};
+
+// Tasks still running this long after the signal are aborted
+const JOIN_TIMEOUT: Duration = Duration::from_secs(30);
+
+// Tells every long-running task to stop and waits for them to finish their cleanup
+pub struct Shutdown {
+    notify: broadcast::Sender<()>,
+    // Subscribed up front so a critical task failing early is not missed
+    stopped: ShutdownSignal,
+    tasks: Vec<(&'static str, JoinHandle<()>)>,
+}
+
+// Triggers the shutdown from outside the tasks, such as a program embedding the node
+#[derive(Clone)]
+pub struct ShutdownTrigger {
+    notify: broadcast::Sender<()>,
+}
+
+// Resolves once shutdown was triggered; stays resolved after that
+pub struct ShutdownSignal {
+    rx: broadcast::Receiver<()>,
+    received: bool,
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        let (notify, rx) = broadcast::channel(1);
+        Self {
+            notify,
+            stopped: ShutdownSignal { rx, received: false },
+            tasks: Vec::new(),
+        }
+    }
+
+    pub fn signal(&self) -> ShutdownSignal {
+        ShutdownSignal { rx: self.notify.subscribe(), received: false }
+    }
+
+    pub fn trigger(&self) {
+        let _ = self.notify.send(());
+    }
+
+    pub fn trigger_handle(&self) -> ShutdownTrigger {
+        ShutdownTrigger { notify: self.notify.clone() }
+    }
+
+    // The task is expected to return soon after its signal fires
+    pub fn spawn<F>(&mut self, name: &'static str, task: F)
+    where
+        F: Future<Output = Result<()>> + Send + 'static,
+    {
+        let handle = tokio::spawn(async move {
+            if let Err(e) = task.await {
+                warn!("❌ {} stopped with error: {}", name, e);
+            }
+        });
+        self.tasks.push((name, handle));
+    }
+
+    // Like spawn, but the whole node shuts down when the task ends
+    pub fn spawn_critical<F>(&mut self, name: &'static str, task: F)
+    where
+        F: Future<Output = Result<()>> + Send + 'static,
+    {
+        let notify = self.notify.clone();
+        self.spawn(name, async move {
+            let result = task.await;
+            let _ = notify.send(());
+            result
+        });
+    }
+
+    // Runs until Ctrl-C, a trigger or the end of a critical task, then stops every task
+    pub async fn wait(mut self) {
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => info!("🛑 Received shutdown signal"),
+            _ = self.stopped.recv() => info!("🛑 A critical task stopped or shutdown was triggered, shutting down"),
+        }
+        self.join().await
+    }
+
+    async fn join(self) {
+        self.trigger();
+        let deadline = Instant::now() + JOIN_TIMEOUT;
+
+        for (name, mut handle) in self.tasks {
+            if timeout_at(deadline, &mut handle).await.is_err() {
+                warn!("⚠️ {} did not stop within {}s, aborting it", name, JOIN_TIMEOUT.as_secs());
+                handle.abort();
+            }
+        }
+
+        info!("🧹 All tasks stopped");
+    }
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ShutdownTrigger {
+    pub fn trigger(&self) {
+        let _ = self.notify.send(());
+    }
+}
+
+impl ShutdownSignal {
+    pub async fn recv(&mut self) {
+        if !self.received {
+            // A closed channel means the coordinator is gone, which is a shutdown as well
+            let _ = self.rx.recv().await;
+            self.received = true;
+        }
+    }
+}
@@ -0,0 +1,283 @@
+use super::{decode_archive, encode_archive, Snapshot, SnapshotDiff};
+use crate::state;
+use crate::storage::StorageManager;
+use crate::types::{Block, BlockHash};
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::Path;
+use tracing::info;
+
+const BACKUP_MANIFEST: &str = "manifest.json";
+const BACKUP_VERSION: u32 = 1;
+// Blocks are archived in files of this many, so a full backup is not held in memory at once
+const BLOCKS_PER_FILE: u64 = 1000;
+
+// Index of a backup directory; the manifest is written last, so it only names complete files
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupManifest {
+    pub version: u32,
+    pub entries: Vec<BackupEntry>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BackupKind {
+    // Every stored block and the whole state
+    Full,
+    // The blocks since the previous entry and the accounts they changed
+    Incremental,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupEntry {
+    pub kind: BackupKind,
+    // First and last block archived; the state is the one after `height`
+    pub from_height: u64,
+    pub height: u64,
+    pub block_hash: String,
+    pub state_root: String,
+    // The state file first, then the block files in order
+    pub files: Vec<BackupFile>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupFile {
+    pub name: String,
+    pub sha256: String,
+    pub size: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BackupReport {
+    pub entries: usize,
+    pub files: usize,
+    pub blocks: u64,
+    pub height: u64,
+}
+
+impl BackupManifest {
+    fn read(dir: &Path) -> Result<Option<Self>> {
+        let path = dir.join(BACKUP_MANIFEST);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let manifest: BackupManifest = serde_json::from_slice(&fs::read(path)?)?;
+        if manifest.version != BACKUP_VERSION {
+            bail!("backup version {} is not supported, expected {}", manifest.version, BACKUP_VERSION);
+        }
+        Ok(Some(manifest))
+    }
+
+    fn write(&self, dir: &Path) -> Result<()> {
+        let tmp = dir.join(format!("{}.tmp", BACKUP_MANIFEST));
+        fs::write(&tmp, serde_json::to_vec_pretty(self)?)?;
+        fs::rename(tmp, dir.join(BACKUP_MANIFEST))?;
+        Ok(())
+    }
+
+    // Restores start from the newest full backup and apply every incremental one after it
+    fn restore_chain(&self) -> Result<&[BackupEntry]> {
+        match self.entries.iter().rposition(|entry| entry.kind == BackupKind::Full) {
+            Some(start) => Ok(&self.entries[start..]),
+            None => bail!("backup has no full entry to start from"),
+        }
+    }
+}
+
+// Archives the finalized block of `storage` into `dir`: everything on the first backup or
+// with `full`, otherwise only what changed since the last entry. None when the backup is
+// already at the finalized block
+pub async fn backup(storage: &StorageManager, dir: &Path, full: bool) -> Result<Option<BackupEntry>> {
+    fs::create_dir_all(dir)?;
+    let mut manifest = BackupManifest::read(dir)?.unwrap_or(BackupManifest { version: BACKUP_VERSION, entries: Vec::new() });
+    let current = match Snapshot::from_storage(storage).await? {
+        Some(snapshot) => snapshot,
+        None => bail!("database has no finalized block to back up"),
+    };
+
+    let last = manifest.entries.last().filter(|_| !full);
+    let entry = match last {
+        None => {
+            // Nodes started from a snapshot have no blocks before it
+            let from_height = storage.get_first_block().await?.map_or(current.height, |block| block.header.block_number);
+            let state = write_file(dir, &format!("full-{}.state", current.height), &encode_archive(&current)?)?;
+            archive_entry(storage, dir, BackupKind::Full, from_height, &current, state).await?
+        }
+        Some(last) if last.height == current.height && last.block_hash == hex::encode(current.block.hash()) => {
+            return Ok(None);
+        }
+        Some(last) => {
+            let archived = storage.get_block(last.height).await?.map(|block| hex::encode(block.hash()));
+            if last.height >= current.height || archived.as_deref() != Some(last.block_hash.as_str()) {
+                bail!("the backup ends at block #{} {}, which is not below the finalized block of this database",
+                    last.height, last.block_hash);
+            }
+            let base = rebuild_state(dir, manifest.restore_chain()?)?;
+            let diff = current.diff(&base);
+            let name = format!("diff-{}-{}.state", base.height, current.height);
+            let state = write_file(dir, &name, &encode_archive(&diff)?)?;
+            archive_entry(storage, dir, BackupKind::Incremental, base.height + 1, &current, state).await?
+        }
+    };
+
+    manifest.entries.push(entry.clone());
+    manifest.write(dir)?;
+    info!("💾 Backed up blocks #{}..#{} ({:?}, {} files) to {}",
+        entry.from_height, entry.height, entry.kind, entry.files.len(), dir.display());
+    Ok(Some(entry))
+}
+
+async fn archive_entry(
+    storage: &StorageManager,
+    dir: &Path,
+    kind: BackupKind,
+    from_height: u64,
+    snapshot: &Snapshot,
+    state: BackupFile,
+) -> Result<BackupEntry> {
+    let mut files = vec![state];
+    let mut start = from_height;
+    while start <= snapshot.height {
+        let end = (start + BLOCKS_PER_FILE - 1).min(snapshot.height);
+        let blocks = storage.get_block_range(start, end).await?;
+        files.push(write_file(dir, &format!("blocks-{}-{}.bin", start, end), &encode_archive(&blocks)?)?);
+        start = end + 1;
+    }
+
+    Ok(BackupEntry {
+        kind,
+        from_height,
+        height: snapshot.height,
+        block_hash: hex::encode(snapshot.block.hash()),
+        state_root: hex::encode(snapshot.block.header.state_root),
+        files,
+        created_at: Utc::now(),
+    })
+}
+
+// Writes the blocks and the state of a backup into `storage`, which must not hold a chain yet
+pub async fn restore(storage: &StorageManager, dir: &Path) -> Result<Snapshot> {
+    if storage.get_latest_block().await?.is_some() {
+        bail!("refusing to restore a backup over an existing chain");
+    }
+    let manifest = match BackupManifest::read(dir)? {
+        Some(manifest) => manifest,
+        None => bail!("{} has no backup manifest", dir.display()),
+    };
+    let entries = manifest.restore_chain()?;
+    let snapshot = rebuild_state(dir, entries)?;
+
+    for entry in entries {
+        for file in &entry.files[1..] {
+            let blocks: Vec<Block> = decode_archive(&read_file(dir, file)?)?;
+            storage.store_blocks_batch(&blocks).await?;
+        }
+    }
+    snapshot.import_into(storage).await?;
+
+    info!("📥 Restored backup of block #{} from {}", snapshot.height, dir.display());
+    Ok(snapshot)
+}
+
+// Checks every archived file against its hash, that the blocks of each restore chain follow
+// one another up to the block each entry names, and that the state rebuilt at every entry
+// matches that block's state root
+pub fn verify_backup(dir: &Path) -> Result<BackupReport> {
+    let manifest = match BackupManifest::read(dir)? {
+        Some(manifest) => manifest,
+        None => bail!("{} has no backup manifest", dir.display()),
+    };
+    let mut report = BackupReport { entries: manifest.entries.len(), files: 0, blocks: 0, height: 0 };
+
+    let mut state: Option<Snapshot> = None;
+    let mut previous: Option<(u64, BlockHash)> = None;
+    for entry in &manifest.entries {
+        let (state_file, block_files) = match entry.files.split_first() {
+            Some(files) => files,
+            None => bail!("entry for block #{} archives no files", entry.height),
+        };
+        let bytes = read_file(dir, state_file)?;
+        let snapshot = match (entry.kind, &state) {
+            (BackupKind::Full, _) => {
+                previous = None;
+                decode_archive::<Snapshot>(&bytes)?
+            }
+            (BackupKind::Incremental, Some(base)) => base.apply(&decode_archive::<SnapshotDiff>(&bytes)?)?,
+            (BackupKind::Incremental, None) => bail!("incremental entry for block #{} has no full entry before it", entry.height),
+        };
+        if snapshot.height != entry.height || hex::encode(snapshot.block.hash()) != entry.block_hash {
+            bail!("state of the entry for block #{} is of another block", entry.height);
+        }
+        let accounts = snapshot.accounts.iter().copied().collect();
+        if state::state_root(&accounts) != snapshot.block.header.state_root {
+            bail!("archived accounts do not match the state root of block #{}", entry.height);
+        }
+
+        for file in block_files {
+            let blocks: Vec<Block> = decode_archive(&read_file(dir, file)?)?;
+            for block in &blocks {
+                let number = block.header.block_number;
+                if let Some((height, hash)) = previous {
+                    if number != height + 1 || block.header.parent_hash != hash {
+                        bail!("block #{} in {} does not follow block #{}", number, file.name, height);
+                    }
+                }
+                previous = Some((number, block.hash()));
+                report.blocks += 1;
+            }
+        }
+        match previous {
+            Some((height, hash)) if height == entry.height && hex::encode(hash) == entry.block_hash => {}
+            _ => bail!("the blocks of the entry for block #{} do not end at it", entry.height),
+        }
+
+        report.files += entry.files.len();
+        report.height = entry.height;
+        state = Some(snapshot);
+    }
+
+    info!("✅ Verified backup in {}: {} entries, {} files, {} blocks up to #{}",
+        dir.display(), report.entries, report.files, report.blocks, report.height);
+    Ok(report)
+}
+
+// State after the last of `entries`, which start with a full one
+fn rebuild_state(dir: &Path, entries: &[BackupEntry]) -> Result<Snapshot> {
+    let mut snapshot: Option<Snapshot> = None;
+    for entry in entries {
+        let state_file = match entry.files.first() {
+            Some(file) => file,
+            None => bail!("entry for block #{} archives no files", entry.height),
+        };
+        let bytes = read_file(dir, state_file)?;
+        snapshot = Some(match snapshot {
+            None => decode_archive(&bytes)?,
+            Some(base) => base.apply(&decode_archive(&bytes)?)?,
+        });
+    }
+    match snapshot {
+        Some(snapshot) => Ok(snapshot),
+        None => bail!("backup has no entries"),
+    }
+}
+
+fn write_file(dir: &Path, name: &str, bytes: &[u8]) -> Result<BackupFile> {
+    fs::write(dir.join(name), bytes)?;
+    Ok(BackupFile {
+        name: name.to_string(),
+        sha256: hex::encode(Sha256::digest(bytes)),
+        size: bytes.len() as u64,
+    })
+}
+
+fn read_file(dir: &Path, file: &BackupFile) -> Result<Vec<u8>> {
+    let bytes = fs::read(dir.join(&file.name))?;
+    if bytes.len() as u64 != file.size || hex::encode(Sha256::digest(&bytes)) != file.sha256 {
+        bail!("{} does not match the hash in the manifest", file.name);
+    }
+    Ok(bytes)
+}
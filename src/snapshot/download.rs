@@ -0,0 +1,84 @@
+use crate::types::{NodeId, SnapshotChunk};
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
+use sha2::{Sha256, Digest};
+use std::collections::BTreeMap;
+
+// Keeps every chunk below the gossipsub message limit, like block responses
+pub const MAX_CHUNK_BYTES: usize = 48 * 1024;
+// Bounds what a peer can make us buffer, about 200 MB
+const MAX_CHUNKS: u32 = 4096;
+// Largest archive a peer can send, which mirrors are held to as well
+pub const MAX_ARCHIVE_BYTES: usize = MAX_CHUNKS as usize * MAX_CHUNK_BYTES;
+
+// Collects the chunks of a snapshot requested from one peer
+pub struct SnapshotDownload {
+    pub request_id: [u8; 32],
+    pub peer: NodeId,
+    pub deadline: DateTime<Utc>,
+    // Height, archive hash and chunk count, as announced by the first chunk
+    announced: Option<(u64, [u8; 32], u32)>,
+    chunks: BTreeMap<u32, Vec<u8>>,
+}
+
+impl SnapshotDownload {
+    pub fn new(request_id: [u8; 32], peer: NodeId, deadline: DateTime<Utc>) -> Self {
+        Self {
+            request_id,
+            peer,
+            deadline,
+            announced: None,
+            chunks: BTreeMap::new(),
+        }
+    }
+
+    // Returns the archive once every chunk arrived and it hashes to what the peer announced
+    pub fn add_chunk(&mut self, chunk: SnapshotChunk) -> Result<Option<Vec<u8>>> {
+        if chunk.responder != self.peer {
+            bail!("chunk from {} while downloading from {}", hex::encode(chunk.responder), hex::encode(self.peer));
+        }
+        if chunk.total == 0 || chunk.total > MAX_CHUNKS || chunk.index >= chunk.total {
+            bail!("chunk {} of {} is out of range", chunk.index, chunk.total);
+        }
+        if chunk.data.len() > MAX_CHUNK_BYTES {
+            bail!("chunk {} carries {} bytes", chunk.index, chunk.data.len());
+        }
+
+        let announced = (chunk.height, chunk.archive_hash, chunk.total);
+        if *self.announced.get_or_insert(announced) != announced {
+            bail!("chunk {} describes a different snapshot than the ones before", chunk.index);
+        }
+
+        self.chunks.insert(chunk.index, chunk.data);
+        if self.chunks.len() < chunk.total as usize {
+            return Ok(None);
+        }
+
+        let archive: Vec<u8> = self.chunks.values().flatten().copied().collect();
+        if <[u8; 32]>::from(Sha256::digest(&archive)) != chunk.archive_hash {
+            bail!("archive does not match its announced hash");
+        }
+        Ok(Some(archive))
+    }
+}
+
+pub fn split_archive(archive: &[u8], request_id: [u8; 32], responder: NodeId, height: u64) -> Vec<SnapshotChunk> {
+    let archive_hash = Sha256::digest(archive).into();
+    let total = archive.len().div_ceil(MAX_CHUNK_BYTES).max(1) as u32;
+
+    (0..total)
+        .map(|index| {
+            let start = index as usize * MAX_CHUNK_BYTES;
+            let end = (start + MAX_CHUNK_BYTES).min(archive.len());
+            SnapshotChunk {
+                request_id,
+                responder,
+                height,
+                archive_hash,
+                index,
+                total,
+                data: archive[start..end].to_vec(),
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,599 @@
+use crate::types::{self, Block, ChainEvent, ConsensusState, FinalityCertificate, TrustedCheckpoint};
+use crate::state::{self, Account, AccountId};
+use crate::storage::{StorageError, StorageManager};
+use crate::shutdown::ShutdownSignal;
+use crate::consensus;
+use crate::crypto;
+use crate::staking;
+use crate::zk_proof::ZKProofGenerator;
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, Utc};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use serde::{Deserialize, Serialize};
+use sha2::{Sha256, Digest};
+use std::collections::{BTreeMap, HashMap};
+use std::io::{Read, Write};
+use std::path::Path;
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tracing::{info, debug, warn};
+
+pub(crate) mod backup;
+mod download;
+
+pub use backup::{BackupEntry, BackupFile, BackupKind, BackupManifest, BackupReport, verify_backup};
+pub use download::{SnapshotDownload, split_archive};
+use download::MAX_ARCHIVE_BYTES;
+
+const MANIFEST_NAME: &str = "latest.json";
+// Decoding stops here, so a small archive can not inflate into unbounded memory
+const MAX_DECODED_BYTES: u64 = 1 << 30;
+// A mirror that stalls is given up on, and the next one tried
+const MIRROR_CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+const MIRROR_READ_TIMEOUT: Duration = Duration::from_secs(60);
+// Manifests and IPFS API answers are small JSON documents
+const MAX_MANIFEST_BYTES: usize = 64 * 1024;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub height: u64,
+    pub block: Block,
+    pub consensus_state: Option<ConsensusState>,
+    // Account state after `block`, checked against its state root on import
+    pub accounts: Vec<(AccountId, Account)>,
+    // Precommits `block` was finalized with
+    pub certificate: Option<FinalityCertificate>,
+    pub created_at: DateTime<Utc>,
+}
+
+// Validators a snapshot's block must have been finalized by before it is trusted: those a
+// trusted checkpoint names, or those the node already follows, such as the genesis set
+#[derive(Debug, Clone, Copy)]
+pub enum SnapshotTrust<'a> {
+    Checkpoint(&'a TrustedCheckpoint),
+    Validators(&'a ConsensusState),
+}
+
+// What changed between two snapshots of the same chain: the block and validator set of the
+// newer one and only the accounts that differ
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotDiff {
+    pub base_height: u64,
+    pub height: u64,
+    pub block: Block,
+    pub consensus_state: Option<ConsensusState>,
+    pub changed: Vec<(AccountId, Account)>,
+    pub removed: Vec<AccountId>,
+    pub certificate: Option<FinalityCertificate>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotManifest {
+    pub height: u64,
+    pub block_hash: String,
+    pub archive_hash: String,
+    pub archive_size: u64,
+    pub archive_name: String,
+    pub ipfs_cid: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl Snapshot {
+    pub async fn from_storage(storage: &StorageManager) -> Result<Option<Self>> {
+        // The stored accounts belong to the finalized block, not to a later unfinalized tip
+        let block = match storage.get_finalized_block().await? {
+            Some(block) => block,
+            None => return Ok(None),
+        };
+
+        // The current consensus state may have applied blocks past the finalized one, so the one
+        // stored as the block was finalized is taken instead
+        let height = block.header.block_number;
+        let consensus_state = match storage.get_consensus_state_at(height).await? {
+            Some(state) => Some(state),
+            None => storage.get_consensus_state().await?.filter(|state| state.head == height),
+        };
+
+        Ok(Some(Self {
+            height,
+            consensus_state,
+            accounts: storage.get_accounts().await?.into_iter().collect(),
+            certificate: storage.get_certificate(&block.hash()).await?,
+            block,
+            created_at: Utc::now(),
+        }))
+    }
+
+    // Snapshot of an earlier finalized block, as long as its state was not pruned yet
+    pub async fn at_height(storage: &StorageManager, height: u64) -> Result<Option<Self>> {
+        let finalized = storage.get_finalized_block().await?.map_or(0, |block| block.header.block_number);
+        if height == 0 || height > finalized || height < storage.pruned_below().await? {
+            return Ok(None);
+        }
+        let (block, consensus_state) = match (storage.get_block(height).await?, storage.get_consensus_state_at(height).await?) {
+            (Some(block), Some(state)) => (block, state),
+            _ => return Ok(None),
+        };
+
+        Ok(Some(Self {
+            height,
+            consensus_state: Some(consensus_state),
+            accounts: storage.get_accounts_at(height).await?.into_iter().collect(),
+            certificate: storage.get_certificate(&block.hash()).await?,
+            block,
+            created_at: Utc::now(),
+        }))
+    }
+
+    pub fn encode(&self) -> Result<Vec<u8>> {
+        encode_archive(self)
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self> {
+        decode_archive(bytes)
+    }
+
+    // The accounts that differ from `base`, an earlier snapshot of the same chain
+    pub fn diff(&self, base: &Snapshot) -> SnapshotDiff {
+        let before: BTreeMap<_, _> = base.accounts.iter().copied().collect();
+        let after: BTreeMap<_, _> = self.accounts.iter().copied().collect();
+
+        SnapshotDiff {
+            base_height: base.height,
+            height: self.height,
+            block: self.block.clone(),
+            consensus_state: self.consensus_state.clone(),
+            changed: after.iter()
+                .filter(|(id, account)| before.get(*id) != Some(*account))
+                .map(|(id, account)| (*id, *account))
+                .collect(),
+            removed: before.keys().filter(|id| !after.contains_key(*id)).copied().collect(),
+            certificate: self.certificate.clone(),
+            created_at: self.created_at,
+        }
+    }
+
+    // The snapshot `diff` was taken against this one turns into; its accounts have to match
+    // the new block's state root
+    pub fn apply(&self, diff: &SnapshotDiff) -> Result<Snapshot> {
+        if diff.base_height != self.height {
+            bail!("diff to #{} is against #{}, not #{}", diff.height, diff.base_height, self.height);
+        }
+        let mut accounts: BTreeMap<_, _> = self.accounts.iter().copied().collect();
+        for id in &diff.removed {
+            accounts.remove(id);
+        }
+        accounts.extend(diff.changed.iter().copied());
+        if state::state_root(&accounts) != diff.block.header.state_root {
+            bail!("accounts after the diff do not match the state root of block #{}", diff.height);
+        }
+
+        Ok(Snapshot {
+            height: diff.height,
+            block: diff.block.clone(),
+            consensus_state: diff.consensus_state.clone(),
+            accounts: accounts.into_iter().collect(),
+            certificate: diff.certificate.clone(),
+            created_at: diff.created_at,
+        })
+    }
+
+    pub fn write_to(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, self.encode()?)?;
+        Ok(())
+    }
+
+    pub fn read_from(path: &Path) -> Result<Self> {
+        Self::decode(&std::fs::read(path)?)
+    }
+
+    // The block must be signed by its proposer, carry a valid proof and have been finalized by
+    // validators we already trust; the accounts are checked against its state root on import
+    pub async fn verify(&self, zk_generator: &ZKProofGenerator, trust: SnapshotTrust<'_>) -> Result<()> {
+        if self.block.header.block_number != self.height {
+            bail!("snapshot height {} does not match its block #{}", self.height, self.block.header.block_number);
+        }
+        if !crypto::verify_signature(&self.block.header.validator, &self.block.signing_hash(), &self.block.signature) {
+            bail!("invalid proposer signature on block {}", self.height);
+        }
+        // Blocks between checkpoints carry a reference instead of a proof, which proves nothing
+        // about the state they end in
+        if let Some((proven, _)) = self.block.zk_proof.checkpoint_reference() {
+            bail!("block {} carries no proof of its own, only a reference to checkpoint #{}", self.height, proven);
+        }
+        if !zk_generator.verify_block_proof(&self.block).await? {
+            bail!("ZK proof verification failed for block {}", self.height);
+        }
+        self.verify_finality(trust)
+    }
+
+    // A quorum of the snapshot's own validator set must have precommitted its block. That set
+    // is the one a trusted checkpoint names, or a quorum of the validators we follow signed too
+    fn verify_finality(&self, trust: SnapshotTrust<'_>) -> Result<()> {
+        let certificate = match &self.certificate {
+            Some(certificate) => certificate,
+            None => bail!("snapshot block {} comes without a finality certificate", self.height),
+        };
+        if certificate.block_hash != self.block.hash() || certificate.block_number != self.height {
+            bail!("finality certificate is not for snapshot block {}", self.height);
+        }
+        let state = match &self.consensus_state {
+            Some(state) => state,
+            None => bail!("snapshot has no validator set to check its finality certificate against"),
+        };
+        let signers: HashMap<_, _> = staking::active_validators(state).into_iter().collect();
+        consensus::verify_precommits(certificate, |node_id| signers.get(node_id).copied(), signers.values().sum(), &state.params)?;
+
+        let (trusted, params) = match trust {
+            SnapshotTrust::Checkpoint(checkpoint) => return self.check_checkpoint(checkpoint),
+            SnapshotTrust::Validators(trusted) => (staking::active_validators(trusted), &trusted.params),
+        };
+        let total: u64 = trusted.iter().map(|(_, stake)| stake).sum();
+        let weight = trusted.iter()
+            .filter(|(node_id, _)| certificate.precommits.iter().any(|vote| vote.validator == *node_id))
+            .map(|(_, stake)| stake)
+            .sum();
+        if !params.has_quorum(weight, total) {
+            bail!("precommits for snapshot block {} carry {} of the {} stake of the validators we follow",
+                self.height, weight, total);
+        }
+        Ok(())
+    }
+
+    // A node starting from a trusted checkpoint only takes a snapshot of exactly that block,
+    // with the validator set the checkpoint names
+    pub fn check_checkpoint(&self, checkpoint: &TrustedCheckpoint) -> Result<()> {
+        if self.height != checkpoint.height {
+            bail!("snapshot #{} is not the trusted checkpoint #{}", self.height, checkpoint.height);
+        }
+        if self.block.hash() != checkpoint.block_hash {
+            bail!("snapshot block {} is not the trusted checkpoint {}",
+                hex::encode(self.block.hash()), hex::encode(checkpoint.block_hash));
+        }
+        let validator_set_hash = match &self.consensus_state {
+            Some(state) => types::validator_set_hash(state),
+            None => bail!("snapshot has no validator set to check against the trusted checkpoint"),
+        };
+        if validator_set_hash != checkpoint.validator_set_hash {
+            bail!("snapshot validator set {} does not match the trusted checkpoint's {}",
+                hex::encode(validator_set_hash), hex::encode(checkpoint.validator_set_hash));
+        }
+        Ok(())
+    }
+
+    pub async fn import_into(&self, storage: &StorageManager) -> Result<()> {
+        let accounts = self.accounts.iter().copied().collect();
+        if state::state_root(&accounts) != self.block.header.state_root {
+            bail!("Snapshot accounts do not match the state root of block #{}", self.height);
+        }
+
+        storage.store_block(&self.block).await?;
+        if let Some(certificate) = &self.certificate {
+            storage.store_certificate(certificate).await?;
+        }
+        // Snapshots are only taken of committed blocks, so consensus resumes above it
+        storage.set_finalized_block(&self.block, &self.accounts).await?;
+        if let Some(state) = &self.consensus_state {
+            storage.store_consensus_state(state).await?;
+            storage.store_consensus_state_at(self.height, state).await?;
+        }
+
+        info!("📥 Imported snapshot at height {}", self.height);
+        Ok(())
+    }
+
+    fn manifest(&self, archive: &[u8]) -> SnapshotManifest {
+        SnapshotManifest {
+            height: self.height,
+            block_hash: hex::encode(self.block.hash()),
+            archive_hash: hex::encode(Sha256::digest(archive)),
+            archive_size: archive.len() as u64,
+            archive_name: format!("snapshot-{}.bin", self.height),
+            ipfs_cid: None,
+            created_at: self.created_at,
+        }
+    }
+}
+
+// Snapshot and backup archives are gzip-compressed bincode
+fn encode_archive<T: Serialize>(value: &T) -> Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(&bincode::serialize(value)?)?;
+    Ok(encoder.finish()?)
+}
+
+fn decode_archive<T: serde::de::DeserializeOwned>(bytes: &[u8]) -> Result<T> {
+    let mut decoded = Vec::new();
+    GzDecoder::new(bytes).take(MAX_DECODED_BYTES + 1).read_to_end(&mut decoded)?;
+    if decoded.len() as u64 > MAX_DECODED_BYTES {
+        bail!("Snapshot archive inflates beyond {} bytes", MAX_DECODED_BYTES);
+    }
+    Ok(bincode::deserialize(&decoded)?)
+}
+
+// Reads a mirror's response chunk by chunk, giving up once it is larger than `max_bytes`
+async fn read_body(mut response: reqwest::Response, max_bytes: usize) -> Result<Vec<u8>> {
+    if let Some(length) = response.content_length().filter(|length| *length > max_bytes as u64) {
+        bail!("{} bytes, more than the {} a mirror may send", length, max_bytes);
+    }
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        if body.len() + chunk.len() > max_bytes {
+            bail!("more than the {} bytes a mirror may send", max_bytes);
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(body)
+}
+
+#[derive(Debug, Clone)]
+pub enum SnapshotMirror {
+    // Plain HTTP(S) server accepting PUT uploads and serving files by name
+    Http { base_url: String },
+    // Kubo RPC API; snapshots are pinned and the manifest is published under IPNS
+    Ipfs { api_url: String, ipns_name: Option<String> },
+}
+
+impl SnapshotMirror {
+    pub fn parse(spec: &str) -> Result<Self> {
+        if spec.starts_with("http://") || spec.starts_with("https://") {
+            return Ok(SnapshotMirror::Http {
+                base_url: spec.trim_end_matches('/').to_string(),
+            });
+        }
+
+        if let Some(rest) = spec.strip_prefix("ipfs://") {
+            let (host, ipns_name) = match rest.split_once('/') {
+                Some((host, name)) if !name.is_empty() => (host, Some(name.to_string())),
+                Some((host, _)) => (host, None),
+                None => (rest, None),
+            };
+            return Ok(SnapshotMirror::Ipfs {
+                api_url: format!("http://{}/api/v0", host),
+                ipns_name,
+            });
+        }
+
+        bail!("Unsupported snapshot mirror: {}", spec)
+    }
+}
+
+impl std::fmt::Display for SnapshotMirror {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SnapshotMirror::Http { base_url } => write!(f, "{}", base_url),
+            SnapshotMirror::Ipfs { api_url, ipns_name } => {
+                write!(f, "{} ({})", api_url, ipns_name.as_deref().unwrap_or("self"))
+            }
+        }
+    }
+}
+
+pub struct SnapshotDistributor {
+    mirrors: Vec<SnapshotMirror>,
+    client: reqwest::Client,
+}
+
+impl SnapshotDistributor {
+    pub fn new(mirror_specs: &[String]) -> Result<Self> {
+        let mirrors = mirror_specs.iter()
+            .map(|spec| SnapshotMirror::parse(spec))
+            .collect::<Result<Vec<_>>>()?;
+
+        info!("🪞 Configured {} snapshot mirrors", mirrors.len());
+
+        let client = reqwest::Client::builder()
+            .connect_timeout(MIRROR_CONNECT_TIMEOUT)
+            .read_timeout(MIRROR_READ_TIMEOUT)
+            .build()?;
+
+        Ok(Self { mirrors, client })
+    }
+
+    pub fn has_mirrors(&self) -> bool {
+        !self.mirrors.is_empty()
+    }
+
+    // Publish to every mirror; a failing mirror does not prevent the others from receiving the snapshot
+    pub async fn publish(&self, snapshot: &Snapshot) -> Result<usize> {
+        let archive = snapshot.encode()?;
+        let manifest = snapshot.manifest(&archive);
+        let mut published = 0;
+
+        for mirror in &self.mirrors {
+            let result = match mirror {
+                SnapshotMirror::Http { base_url } => {
+                    self.publish_http(base_url, &manifest, &archive).await
+                }
+                SnapshotMirror::Ipfs { api_url, .. } => {
+                    self.publish_ipfs(api_url, manifest.clone(), &archive).await
+                }
+            };
+
+            match result {
+                Ok(()) => {
+                    published += 1;
+                    info!("📤 Published snapshot #{} to {}", snapshot.height, mirror);
+                }
+                Err(e) => warn!("❌ Failed to publish snapshot to {}: {}", mirror, e),
+            }
+        }
+
+        Ok(published)
+    }
+
+    // Try mirrors in order and return the first snapshot that passes hash and proof verification
+    pub async fn fetch_latest(&self, zk_generator: &ZKProofGenerator, trust: SnapshotTrust<'_>) -> Result<Option<Snapshot>> {
+        for mirror in &self.mirrors {
+            match self.fetch_from(mirror, zk_generator, trust).await {
+                Ok(snapshot) => {
+                    info!("✅ Fetched verified snapshot #{} from {}", snapshot.height, mirror);
+                    return Ok(Some(snapshot));
+                }
+                Err(e) => warn!("❌ Snapshot from {} rejected: {}", mirror, e),
+            }
+        }
+
+        Ok(None)
+    }
+
+    async fn fetch_from(&self, mirror: &SnapshotMirror, zk_generator: &ZKProofGenerator, trust: SnapshotTrust<'_>) -> Result<Snapshot> {
+        let (manifest, archive) = match mirror {
+            SnapshotMirror::Http { base_url } => self.fetch_http(base_url).await?,
+            SnapshotMirror::Ipfs { api_url, ipns_name } => {
+                self.fetch_ipfs(api_url, ipns_name.as_deref()).await?
+            }
+        };
+
+        debug!("Fetched snapshot manifest for height {} ({} bytes)", manifest.height, archive.len());
+        Self::verify(&manifest, &archive, zk_generator, trust).await
+    }
+
+    async fn verify(manifest: &SnapshotManifest, archive: &[u8], zk_generator: &ZKProofGenerator, trust: SnapshotTrust<'_>) -> Result<Snapshot> {
+        let archive_hash = hex::encode(Sha256::digest(archive));
+        if archive_hash != manifest.archive_hash {
+            bail!("archive hash mismatch: expected {}, got {}", manifest.archive_hash, archive_hash);
+        }
+
+        let snapshot = Snapshot::decode(archive)?;
+        if snapshot.height != manifest.height {
+            bail!("snapshot height does not match manifest height {}", manifest.height);
+        }
+
+        if hex::encode(snapshot.block.hash()) != manifest.block_hash {
+            bail!("block hash does not match manifest");
+        }
+
+        snapshot.verify(zk_generator, trust).await?;
+        Ok(snapshot)
+    }
+
+    async fn publish_http(&self, base_url: &str, manifest: &SnapshotManifest, archive: &[u8]) -> Result<()> {
+        self.client.put(format!("{}/{}", base_url, manifest.archive_name))
+            .body(archive.to_vec())
+            .send().await?
+            .error_for_status()?;
+
+        // Manifest goes last so fetchers never see a manifest without its archive
+        self.client.put(format!("{}/{}", base_url, MANIFEST_NAME))
+            .json(manifest)
+            .send().await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+
+    async fn fetch_http(&self, base_url: &str) -> Result<(SnapshotManifest, Vec<u8>)> {
+        let response = self.client.get(format!("{}/{}", base_url, MANIFEST_NAME))
+            .send().await?
+            .error_for_status()?;
+        let manifest: SnapshotManifest = serde_json::from_slice(&read_body(response, MAX_MANIFEST_BYTES).await?)?;
+
+        let response = self.client.get(format!("{}/{}", base_url, manifest.archive_name))
+            .send().await?
+            .error_for_status()?;
+        let archive = read_body(response, MAX_ARCHIVE_BYTES).await?;
+
+        Ok((manifest, archive))
+    }
+
+    async fn publish_ipfs(&self, api_url: &str, mut manifest: SnapshotManifest, archive: &[u8]) -> Result<()> {
+        let archive_cid = self.ipfs_add(api_url, &manifest.archive_name, archive.to_vec()).await?;
+        manifest.ipfs_cid = Some(archive_cid);
+
+        let manifest_cid = self.ipfs_add(api_url, MANIFEST_NAME, serde_json::to_vec(&manifest)?).await?;
+
+        self.client.post(format!("{}/name/publish", api_url))
+            .query(&[("arg", format!("/ipfs/{}", manifest_cid))])
+            .send().await?
+            .error_for_status()?;
+
+        debug!("Published snapshot manifest {} under IPNS", manifest_cid);
+        Ok(())
+    }
+
+    async fn fetch_ipfs(&self, api_url: &str, ipns_name: Option<&str>) -> Result<(SnapshotManifest, Vec<u8>)> {
+        let mut request = self.client.post(format!("{}/name/resolve", api_url));
+        if let Some(name) = ipns_name {
+            request = request.query(&[("arg", name)]);
+        }
+
+        let response = request.send().await?.error_for_status()?;
+        let resolved: serde_json::Value = serde_json::from_slice(&read_body(response, MAX_MANIFEST_BYTES).await?)?;
+        let manifest_path = resolved["Path"].as_str()
+            .ok_or_else(|| anyhow!("IPNS resolve returned no path"))?
+            .to_string();
+
+        let manifest: SnapshotManifest = serde_json::from_slice(&self.ipfs_cat(api_url, &manifest_path, MAX_MANIFEST_BYTES).await?)?;
+        let archive_cid = manifest.ipfs_cid.clone()
+            .ok_or_else(|| anyhow!("manifest has no IPFS CID"))?;
+        let archive = self.ipfs_cat(api_url, &archive_cid, MAX_ARCHIVE_BYTES).await?;
+
+        Ok((manifest, archive))
+    }
+
+    async fn ipfs_add(&self, api_url: &str, name: &str, data: Vec<u8>) -> Result<String> {
+        let part = reqwest::multipart::Part::bytes(data).file_name(name.to_string());
+        let form = reqwest::multipart::Form::new().part("file", part);
+
+        let response: serde_json::Value = self.client.post(format!("{}/add", api_url))
+            .query(&[("pin", "true")])
+            .multipart(form)
+            .send().await?
+            .error_for_status()?
+            .json().await?;
+
+        response["Hash"].as_str()
+            .map(|cid| cid.to_string())
+            .ok_or_else(|| anyhow!("IPFS add returned no hash"))
+    }
+
+    async fn ipfs_cat(&self, api_url: &str, path: &str, max_bytes: usize) -> Result<Vec<u8>> {
+        let response = self.client.post(format!("{}/cat", api_url))
+            .query(&[("arg", path)])
+            .send().await?
+            .error_for_status()?;
+        read_body(response, max_bytes).await
+    }
+
+    pub async fn run_publisher(
+        &self,
+        storage: StorageManager,
+        interval: u64,
+        mut events: broadcast::Receiver<ChainEvent>,
+        mut shutdown: ShutdownSignal,
+    ) -> Result<()> {
+        let mut last_published = 0u64;
+
+        loop {
+            let height = tokio::select! {
+                event = events.recv() => match event {
+                    Ok(ChainEvent::BlockFinalized(finalized)) => finalized.block_number,
+                    Ok(_) => continue,
+                    // Only the latest height matters, so missed events are harmless
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => return Ok(()),
+                },
+                _ = shutdown.recv() => return Ok(()),
+            };
+            if height < last_published + interval {
+                continue;
+            }
+
+            let snapshot = match storage.create_snapshot(height).await {
+                Ok(snapshot) => snapshot,
+                // Blocks without a proof of their own wait for the next one that has it
+                Err(StorageError::Rejected(reason)) => {
+                    debug!("Not publishing a snapshot at #{}: {}", height, reason);
+                    continue;
+                }
+                Err(e) => return Err(e.into()),
+            };
+
+            if self.publish(&snapshot).await? > 0 {
+                last_published = snapshot.height;
+            }
+        }
+    }
+}
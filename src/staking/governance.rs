@@ -0,0 +1,381 @@
+use super::{epoch_of, unbondable_stake, PendingStakeChange, StakeChange, ACTIVATION_DELAY_EPOCHS, EPOCH_LENGTH};
+use crate::types::{BlockHash, ConsensusParams, ConsensusState, NodeId};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tracing::{info, debug, warn};
+
+// Full epochs after the one a proposal was made in that validators may still vote on it
+pub const VOTING_PERIOD_EPOCHS: u64 = 1;
+// Proposals open at a time; further ones are included but have no effect
+pub const MAX_OPEN_PROPOSALS: usize = 16;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum ProposalAction {
+    // Replaces every consensus parameter, so the ones to keep are given as they are
+    ChangeParams(ConsensusParams),
+    // Jails the validator and unbonds all of its stake; it may bond again
+    RemoveValidator(NodeId),
+}
+
+impl ProposalAction {
+    pub fn is_well_formed(&self) -> bool {
+        match self {
+            ProposalAction::ChangeParams(params) => params.validate().is_ok(),
+            ProposalAction::RemoveValidator(_) => true,
+        }
+    }
+}
+
+// Sent by an active validator; the transaction id becomes the proposal id
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ProposalTransaction {
+    pub proposer: NodeId,
+    pub action: ProposalAction,
+}
+
+// Sent by an active validator for an open proposal; a later one replaces it
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ProposalVoteTransaction {
+    pub voter: NodeId,
+    pub proposal: BlockHash,
+    pub approve: bool,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ProposalStatus {
+    Voting,
+    // Decided as an epoch began, and kept through that epoch
+    Enacted,
+    Rejected,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ProposalVote {
+    pub voter: NodeId,
+    pub approve: bool,
+    // Block that included the vote, so a reorg can take it back
+    pub block_number: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct GovernanceProposal {
+    pub id: BlockHash,
+    pub proposer: NodeId,
+    pub action: ProposalAction,
+    pub block_number: u64,
+    // Last epoch votes count in; the proposal is decided as the epoch after it begins
+    pub voting_ends: u64,
+    pub votes: Vec<ProposalVote>,
+    pub status: ProposalStatus,
+}
+
+impl GovernanceProposal {
+    // Active stake behind the latest vote of each voter, approving and rejecting
+    pub fn tally(&self, state: &ConsensusState) -> (u64, u64) {
+        let mut latest: HashMap<NodeId, bool> = HashMap::new();
+        for vote in &self.votes {
+            latest.insert(vote.voter, vote.approve);
+        }
+        let (mut approving, mut rejecting) = (0u64, 0u64);
+        for (voter, approve) in latest {
+            if let Some(info) = state.validators.get(&voter).filter(|info| info.is_active) {
+                if approve {
+                    approving += info.stake;
+                } else {
+                    rejecting += info.stake;
+                }
+            }
+        }
+        (approving, rejecting)
+    }
+}
+
+fn is_active(state: &ConsensusState, node_id: &NodeId) -> bool {
+    state.validators.get(node_id).is_some_and(|info| info.is_active)
+}
+
+pub(super) fn record_proposal(state: &mut ConsensusState, id: BlockHash, proposal: &ProposalTransaction, block_number: u64) {
+    if !is_active(state, &proposal.proposer) {
+        debug!("Ignoring proposal of {}, it is not an active validator", hex::encode(proposal.proposer));
+        return;
+    }
+    if state.proposals.iter().any(|recorded| recorded.id == id) {
+        debug!("Ignoring proposal {}, it was recorded already", hex::encode(id));
+        return;
+    }
+    let open = state.proposals.iter().filter(|proposal| proposal.status == ProposalStatus::Voting).count();
+    if open >= MAX_OPEN_PROPOSALS {
+        debug!("Ignoring proposal {}, {} are open already", hex::encode(id), open);
+        return;
+    }
+    info!("🗳️ Proposal {} by {}: {:?}", hex::encode(id), hex::encode(proposal.proposer), proposal.action);
+    state.proposals.push(GovernanceProposal {
+        id,
+        proposer: proposal.proposer,
+        action: proposal.action.clone(),
+        block_number,
+        voting_ends: epoch_of(block_number) + VOTING_PERIOD_EPOCHS,
+        votes: Vec::new(),
+        status: ProposalStatus::Voting,
+    });
+}
+
+pub(super) fn revert_proposal(state: &mut ConsensusState, id: &BlockHash, block_number: u64) {
+    state.proposals.retain(|proposal| proposal.id != *id || proposal.block_number != block_number);
+}
+
+// Votes of validators outside the active set, or on proposals that are not open, are included
+// but have no effect
+pub(super) fn record_vote(state: &mut ConsensusState, vote: &ProposalVoteTransaction, block_number: u64) {
+    if !is_active(state, &vote.voter) {
+        debug!("Ignoring proposal vote of {}, it is not an active validator", hex::encode(vote.voter));
+        return;
+    }
+    let epoch = epoch_of(block_number);
+    match state.proposals.iter_mut().find(|proposal| proposal.id == vote.proposal) {
+        Some(proposal) if proposal.status == ProposalStatus::Voting && epoch <= proposal.voting_ends => {
+            proposal.votes.push(ProposalVote { voter: vote.voter, approve: vote.approve, block_number });
+        }
+        _ => debug!("Ignoring vote on {}, it is not open", hex::encode(vote.proposal)),
+    }
+}
+
+pub(super) fn revert_vote(state: &mut ConsensusState, vote: &ProposalVoteTransaction, block_number: u64) {
+    if let Some(proposal) = state.proposals.iter_mut().find(|proposal| proposal.id == vote.proposal) {
+        if let Some(index) = proposal.votes.iter().position(|recorded| {
+            recorded.voter == vote.voter && recorded.approve == vote.approve && recorded.block_number == block_number
+        }) {
+            proposal.votes.remove(index);
+        }
+    }
+}
+
+// Runs as a new epoch begins, before the set rotates. Proposals whose voting period ended pass
+// when validators holding a quorum of the active stake approved them, and take effect right away;
+// all are decided against the parameters and stake of the epoch that ended. Proposals decided at
+// the previous boundary are dropped
+pub(super) fn decide_proposals(state: &mut ConsensusState) {
+    let epoch = state.epoch;
+    state.proposals.retain(|proposal| proposal.status == ProposalStatus::Voting);
+
+    let total = state.total_stake;
+    let mut enacted = Vec::new();
+    for index in 0..state.proposals.len() {
+        let proposal = &state.proposals[index];
+        if proposal.voting_ends >= epoch {
+            continue;
+        }
+        let (approving, rejecting) = proposal.tally(state);
+        let passed = state.params.has_quorum(approving, total);
+        info!("🗳️ Proposal {} {} in epoch {}: {} for, {} against of {} stake",
+            hex::encode(proposal.id), if passed { "passed" } else { "was rejected" }, epoch, approving, rejecting, total);
+        if passed {
+            enacted.push((index, proposal.action.clone()));
+        }
+        state.proposals[index].status = if passed { ProposalStatus::Enacted } else { ProposalStatus::Rejected };
+    }
+
+    // A passed proposal that can not take effect any more counts as rejected
+    for (index, action) in enacted {
+        if !enact(state, action) {
+            state.proposals[index].status = ProposalStatus::Rejected;
+        }
+    }
+}
+
+// Returns false when the action would leave the chain unable to go on, or has nothing to act on
+fn enact(state: &mut ConsensusState, action: ProposalAction) -> bool {
+    match action {
+        ProposalAction::ChangeParams(params) => {
+            info!("🏛️ Consensus parameters changed in epoch {}: block time {}s, {}-{} validators, quorum {}/{}",
+                state.epoch, params.block_time, params.min_validators, params.max_validators,
+                params.quorum_numerator, params.quorum_denominator);
+            state.params = params;
+            true
+        }
+        ProposalAction::RemoveValidator(node_id) => remove_validator(state, node_id),
+    }
+}
+
+// Like jail_offline, never takes the active set below min_validators. The validator is jailed
+// as of the block opening the epoch and its stake unbonds like an unstake sent in it, so it is
+// paid back rather than lost
+fn remove_validator(state: &mut ConsensusState, node_id: NodeId) -> bool {
+    let active = state.validators.values().filter(|info| info.is_active).count() as u64;
+    if is_active(state, &node_id) && active <= state.params.min_validators.max(1) {
+        warn!("🏛️ Not removing {}, one of the last {} active validators", hex::encode(node_id), active);
+        return false;
+    }
+    let epoch = state.epoch;
+    let stake = unbondable_stake(state, &node_id);
+    let validator = match state.validators.get_mut(&node_id) {
+        Some(validator) => validator,
+        None => {
+            debug!("Not removing {}, it is not a validator", hex::encode(node_id));
+            return false;
+        }
+    };
+    validator.is_active = false;
+    validator.jailed_at = Some(epoch * EPOCH_LENGTH);
+
+    state.pending_stake_changes.retain(|pending| pending.validator != node_id || pending.change != StakeChange::Unjail);
+    if stake > 0 {
+        state.pending_stake_changes.push(PendingStakeChange {
+            validator: node_id,
+            change: StakeChange::Unbond(stake),
+            activation_epoch: epoch + 1 + ACTIVATION_DELAY_EPOCHS,
+        });
+    }
+    info!("🏛️ Validator {} removed in epoch {}, unbonding {} stake", hex::encode(node_id), epoch, stake);
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::staking::{genesis_state, MIN_VALIDATOR_STAKE};
+    use chrono::DateTime;
+
+    const PROPOSAL: BlockHash = [9; 32];
+
+    fn validator(i: u8) -> NodeId {
+        [i; 32]
+    }
+
+    // Four active validators of equal stake
+    fn state() -> ConsensusState {
+        let validators: Vec<_> = (1..=4).map(|i| (validator(i), MIN_VALIDATOR_STAKE)).collect();
+        genesis_state(&validators, DateTime::from_timestamp(1_700_000_000, 0).unwrap(), ConsensusParams::default())
+    }
+
+    fn propose(state: &mut ConsensusState, action: ProposalAction) {
+        record_proposal(state, PROPOSAL, &ProposalTransaction { proposer: validator(1), action }, 1);
+    }
+
+    fn vote(state: &mut ConsensusState, voter: u8, approve: bool, block_number: u64) {
+        record_vote(state, &ProposalVoteTransaction { voter: validator(voter), proposal: PROPOSAL, approve }, block_number);
+    }
+
+    fn status(state: &ConsensusState) -> ProposalStatus {
+        state.proposals[0].status
+    }
+
+    fn params() -> ConsensusParams {
+        ConsensusParams { block_time: 6, ..ConsensusParams::default() }
+    }
+
+    // Votes in the epoch after the proposal, decided as the next one begins
+    fn decide(state: &mut ConsensusState, approving: &[u8]) {
+        for voter in approving {
+            vote(state, *voter, true, EPOCH_LENGTH);
+        }
+        state.epoch = 2;
+        decide_proposals(state);
+    }
+
+    #[test]
+    fn tally_counts_the_latest_vote_of_active_validators() {
+        let mut state = state();
+        propose(&mut state, ProposalAction::ChangeParams(params()));
+        vote(&mut state, 1, true, 2);
+        vote(&mut state, 2, true, 2);
+        vote(&mut state, 2, false, 3);
+        vote(&mut state, 3, false, 3);
+        assert_eq!(state.proposals[0].tally(&state), (MIN_VALIDATOR_STAKE, 2 * MIN_VALIDATOR_STAKE));
+
+        // Validators that left the active set no longer count, and outsiders can not vote
+        state.validators.get_mut(&validator(3)).unwrap().is_active = false;
+        vote(&mut state, 7, true, 3);
+        assert_eq!(state.proposals[0].tally(&state), (MIN_VALIDATOR_STAKE, MIN_VALIDATOR_STAKE));
+    }
+
+    #[test]
+    fn proposals_are_decided_once_their_voting_period_ends() {
+        let mut state = state();
+        propose(&mut state, ProposalAction::ChangeParams(params()));
+        vote(&mut state, 1, true, 1);
+        state.epoch = 1;
+        decide_proposals(&mut state);
+        assert_eq!(status(&state), ProposalStatus::Voting);
+
+        // Three of four is over two thirds of the stake
+        decide(&mut state, &[2, 3]);
+        assert_eq!(status(&state), ProposalStatus::Enacted);
+        assert_eq!(state.params, params());
+
+        // and dropped at the boundary after
+        state.epoch = 3;
+        decide_proposals(&mut state);
+        assert!(state.proposals.is_empty());
+    }
+
+    #[test]
+    fn proposals_without_a_quorum_are_rejected() {
+        let mut state = state();
+        propose(&mut state, ProposalAction::ChangeParams(params()));
+        decide(&mut state, &[1, 2]);
+        assert_eq!(status(&state), ProposalStatus::Rejected);
+        assert_eq!(state.params, ConsensusParams::default());
+
+        // Votes after the voting period do not count
+        let mut state = self::state();
+        propose(&mut state, ProposalAction::ChangeParams(params()));
+        vote(&mut state, 1, true, 2 * EPOCH_LENGTH);
+        decide(&mut state, &[2, 3]);
+        assert_eq!(status(&state), ProposalStatus::Rejected);
+    }
+
+    #[test]
+    fn removed_validators_are_jailed_and_unbond_their_stake() {
+        let mut state = state();
+        propose(&mut state, ProposalAction::RemoveValidator(validator(4)));
+        decide(&mut state, &[1, 2, 3]);
+        assert_eq!(status(&state), ProposalStatus::Enacted);
+
+        let removed = &state.validators[&validator(4)];
+        assert!(!removed.is_active);
+        assert_eq!(removed.jailed_at, Some(2 * EPOCH_LENGTH));
+        let pending: Vec<_> = state.pending_stake_changes.iter()
+            .map(|pending| (pending.validator, pending.change.clone(), pending.activation_epoch))
+            .collect();
+        assert_eq!(pending, vec![(validator(4), StakeChange::Unbond(MIN_VALIDATOR_STAKE), 3 + ACTIVATION_DELAY_EPOCHS)]);
+    }
+
+    #[test]
+    fn removals_that_can_not_take_effect_are_rejected() {
+        // A node that is not a validator
+        let mut state = state();
+        propose(&mut state, ProposalAction::RemoveValidator(validator(7)));
+        decide(&mut state, &[1, 2, 3]);
+        assert_eq!(status(&state), ProposalStatus::Rejected);
+        assert!(!state.validators.contains_key(&validator(7)));
+
+        // and one of the last min_validators active ones
+        let mut state = self::state();
+        state.params.min_validators = 4;
+        propose(&mut state, ProposalAction::RemoveValidator(validator(4)));
+        decide(&mut state, &[1, 2, 3]);
+        assert_eq!(status(&state), ProposalStatus::Rejected);
+        assert!(state.validators[&validator(4)].is_active);
+        assert!(state.pending_stake_changes.is_empty());
+    }
+
+    #[test]
+    fn reverted_blocks_take_back_their_proposals_and_votes() {
+        let mut state = state();
+        propose(&mut state, ProposalAction::ChangeParams(params()));
+        vote(&mut state, 2, true, 2);
+        vote(&mut state, 2, true, 3);
+
+        // Only the vote the reverted block included goes
+        revert_vote(&mut state, &ProposalVoteTransaction { voter: validator(2), proposal: PROPOSAL, approve: true }, 3);
+        assert_eq!(state.proposals[0].votes, vec![ProposalVote { voter: validator(2), approve: true, block_number: 2 }]);
+
+        // A proposal of the same id from another block stays
+        revert_proposal(&mut state, &PROPOSAL, 2);
+        assert_eq!(state.proposals.len(), 1);
+        revert_proposal(&mut state, &PROPOSAL, 1);
+        assert!(state.proposals.is_empty());
+    }
+}
@@ -0,0 +1,555 @@
+use crate::crypto::{self, NodeKeypair};
+use crate::types::{Block, ConsensusParams, ConsensusState, EpochChange, LivenessPolicy, NodeId, Transaction, TransactionKind, ValidatorInfo, chain_id};
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use tracing::{info, debug, warn};
+
+mod governance;
+mod rewards;
+mod slashing;
+
+pub use governance::{
+    GovernanceProposal, ProposalAction, ProposalStatus, ProposalTransaction, ProposalVote, ProposalVoteTransaction,
+    MAX_OPEN_PROPOSALS, VOTING_PERIOD_EPOCHS,
+};
+pub use rewards::block_rewards;
+pub use slashing::{Evidence, EvidenceTransaction, SlashRecord, is_slashed};
+
+// Validators need at least this much bonded stake to be active
+pub const MIN_VALIDATOR_STAKE: u64 = 1000;
+// Blocks per epoch; stake changes only take effect on epoch boundaries
+pub const EPOCH_LENGTH: u64 = 32;
+// Number of full epochs a stake change waits in the queue before it applies
+pub const ACTIVATION_DELAY_EPOCHS: u64 = 1;
+// Lowest performance score, so a validator that missed its slots is still elected now and then
+// and can earn its score back
+pub const MIN_PERFORMANCE_SCORE: f64 = 0.1;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct StakeTransaction {
+    pub validator: NodeId,
+    pub amount: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct UnstakeTransaction {
+    pub validator: NodeId,
+    pub amount: u64,
+}
+
+// Sent by a jailed validator to be considered for the active set again
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct UnjailTransaction {
+    pub validator: NodeId,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum StakeChange {
+    Bond(u64),
+    Unbond(u64),
+    Unjail,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingStakeChange {
+    pub validator: NodeId,
+    pub change: StakeChange,
+    pub activation_epoch: u64,
+}
+
+pub fn epoch_of(block_number: u64) -> u64 {
+    block_number / EPOCH_LENGTH
+}
+
+// Chains started without a genesis file have no validator set until their first block, which
+// has no parent and bonds its own proposer
+pub fn bootstraps_chain(block: &Block) -> bool {
+    block.header.block_number == 1 && block.header.parent_hash == [0; 32]
+}
+
+// The bootstrapping block and every block starting a new epoch rotate the validator set
+pub fn opens_epoch(block: &Block) -> bool {
+    bootstraps_chain(block) || block.header.block_number.is_multiple_of(EPOCH_LENGTH)
+}
+
+// Stake bonded in the bootstrapping block founds the validator set right away; unbonds always
+// wait for a later epoch
+fn activation_epoch(block: &Block) -> u64 {
+    if bootstraps_chain(block) {
+        0
+    } else {
+        epoch_of(block.header.block_number) + 1 + ACTIVATION_DELAY_EPOCHS
+    }
+}
+
+// Validator set of a chain started from a genesis file, active from block 0
+pub fn genesis_state(validators: &[(NodeId, u64)], timestamp: DateTime<Utc>, params: ConsensusParams) -> ConsensusState {
+    let mut state = ConsensusState {
+        head: 0,
+        justified: 0,
+        finalized: 0,
+        validators: validators.iter()
+            .map(|(node_id, stake)| (*node_id, ValidatorInfo {
+                stake: *stake,
+                is_active: false,
+                last_block_time: timestamp,
+                performance_score: 1.0,
+                proposed: 0,
+                missed: 0,
+                rewards: 0,
+                missed_votes: 0,
+                jailed_at: None,
+            }))
+            .collect(),
+        total_stake: 0,
+        epoch: 0,
+        pending_stake_changes: Vec::new(),
+        slashed: Vec::new(),
+        params,
+        proposals: Vec::new(),
+    };
+    rotate_validator_set(&mut state);
+    state
+}
+
+// Builds a stake transaction signed by the validator's own key, which proves ownership of the node id
+pub fn stake_transaction(keypair: &NodeKeypair, amount: u64, nonce: u64) -> Result<Transaction> {
+    if amount < MIN_VALIDATOR_STAKE {
+        bail!("Stake of {} is below the minimum validator stake of {}", amount, MIN_VALIDATOR_STAKE);
+    }
+
+    let kind = TransactionKind::Stake(StakeTransaction {
+        validator: keypair.node_id(),
+        amount,
+    });
+    Ok(signed_transaction(keypair, amount, nonce, kind))
+}
+
+// Reports are signed by the reporting node, not by the offender
+pub fn evidence_transaction(keypair: &NodeKeypair, evidence: Evidence, nonce: u64) -> Transaction {
+    signed_transaction(keypair, 0, nonce, TransactionKind::Evidence(Box::new(EvidenceTransaction { evidence })))
+}
+
+pub fn unstake_transaction(keypair: &NodeKeypair, amount: u64, nonce: u64) -> Transaction {
+    let kind = TransactionKind::Unstake(UnstakeTransaction {
+        validator: keypair.node_id(),
+        amount,
+    });
+    signed_transaction(keypair, amount, nonce, kind)
+}
+
+pub fn unjail_transaction(keypair: &NodeKeypair, nonce: u64) -> Transaction {
+    let kind = TransactionKind::Unjail(UnjailTransaction { validator: keypair.node_id() });
+    signed_transaction(keypair, 0, nonce, kind)
+}
+
+fn signed_transaction(keypair: &NodeKeypair, amount: u64, nonce: u64, kind: TransactionKind) -> Transaction {
+    let mut tx = Transaction {
+        id: [0; 32],
+        from: keypair.node_id(),
+        to: [0; 32],
+        amount,
+        fee: 0,
+        nonce,
+        chain_id: chain_id(),
+        timestamp: Utc::now(),
+        kind,
+        valid_until: None,
+        signature: vec![],
+    };
+
+    tx.id = tx.content_id();
+    tx.signature = keypair.sign(&tx.signing_hash());
+    tx
+}
+
+// Jailed validators stay registered; they unjail rather than bond again
+pub fn is_registered(state: &ConsensusState, validator: &NodeId) -> bool {
+    state.validators.get(validator).is_some_and(|info| info.is_active || info.jailed_at.is_some())
+        || state.pending_stake_changes.iter()
+            .any(|pending| pending.validator == *validator && matches!(pending.change, StakeChange::Bond(_)))
+}
+
+// Whether the validator is jailed and has sat out its cooldown, so an unjail transaction
+// sent now lets it back in at the next epoch
+pub fn can_unjail(state: &ConsensusState, validator: &NodeId, policy: &LivenessPolicy) -> bool {
+    state.validators.get(validator)
+        .and_then(|info| info.jailed_at)
+        .is_some_and(|jailed_at| state.epoch + 1 >= epoch_of(jailed_at) + policy.jail_epochs)
+}
+
+// Checks that a staking transaction is well-formed and authorised by the validator it names
+pub fn validate_transaction(tx: &Transaction) -> bool {
+    let (validator, well_formed) = match &tx.kind {
+        TransactionKind::Transfer => return true,
+        // Anyone may report evidence; the report moves no funds
+        TransactionKind::Evidence(report) => return tx.amount == 0 && report.evidence.is_well_formed(),
+        TransactionKind::Stake(stake) => (stake.validator, stake.amount > 0),
+        TransactionKind::Unstake(unstake) => (unstake.validator, unstake.amount > 0),
+        TransactionKind::Unjail(unjail) => (unjail.validator, tx.amount == 0),
+        TransactionKind::Proposal(proposal) => (proposal.proposer, tx.amount == 0 && proposal.action.is_well_formed()),
+        TransactionKind::ProposalVote(vote) => (vote.voter, tx.amount == 0),
+    };
+
+    well_formed
+        && tx.from == validator
+        && crypto::verify_signature(&validator, &tx.signing_hash(), &tx.signature)
+}
+
+// There is no validator set to elect the genesis proposer from, so it has to bond itself in the block
+pub fn genesis_proposer(block: &Block) -> Option<NodeId> {
+    let proposer = block.header.validator;
+    block.transactions.iter()
+        .any(|tx| validate_transaction(tx) && matches!(&tx.kind,
+            TransactionKind::Stake(stake) if stake.validator == proposer && stake.amount >= MIN_VALIDATOR_STAKE))
+        .then_some(proposer)
+}
+
+// Queues the block's staking transactions and applies due changes when the block opens a new epoch.
+// `missed` are the validators elected for the rounds before the one the block was proposed in.
+// Returns the stake the block unbonded, which goes back to the validators' balances
+pub fn apply_block(state: &mut ConsensusState, block: &Block, missed: &[NodeId], policy: &LivenessPolicy) -> Vec<(NodeId, u64)> {
+    let block_epoch = epoch_of(block.header.block_number);
+    let genesis = bootstraps_chain(block);
+    // Taken before the epoch rolls over, as the parent was voted on by the set it was proposed in
+    let absent = absent_voters(state, block);
+    let rewards = block_rewards(state, &block.header.validator, &block.parent_votes);
+
+    if let Some(proposer) = state.validators.get_mut(&block.header.validator) {
+        proposer.last_block_time = block.header.timestamp;
+    }
+
+    for tx in &block.transactions {
+        let change = match &tx.kind {
+            TransactionKind::Transfer => continue,
+            _ if !validate_transaction(tx) => {
+                warn!("❌ Skipping invalid staking transaction {}", hex::encode(tx.id));
+                continue;
+            }
+            TransactionKind::Evidence(report) => {
+                slashing::slash(state, &report.evidence, block.header.block_number);
+                continue;
+            }
+            TransactionKind::Proposal(proposal) => {
+                governance::record_proposal(state, tx.id, proposal, block.header.block_number);
+                continue;
+            }
+            TransactionKind::ProposalVote(vote) => {
+                governance::record_vote(state, vote, block.header.block_number);
+                continue;
+            }
+            TransactionKind::Unstake(unstake) if unstake.amount > unbondable_stake(state, &unstake.validator) => {
+                debug!("Ignoring unstake of {} from {}, more than the {} it has bonded",
+                    unstake.amount, hex::encode(unstake.validator), unbondable_stake(state, &unstake.validator));
+                continue;
+            }
+            TransactionKind::Stake(stake) => (stake.validator, StakeChange::Bond(stake.amount)),
+            TransactionKind::Unstake(unstake) => (unstake.validator, StakeChange::Unbond(unstake.amount)),
+            TransactionKind::Unjail(unjail) => (unjail.validator, StakeChange::Unjail),
+        };
+
+        debug!("Queued {:?} for validator {}", change.1, hex::encode(change.0));
+        let activation_epoch = match change.1 {
+            StakeChange::Unjail => block_epoch + 1,
+            StakeChange::Bond(_) => activation_epoch(block),
+            StakeChange::Unbond(_) => block_epoch + 1 + ACTIVATION_DELAY_EPOCHS,
+        };
+        state.pending_stake_changes.push(PendingStakeChange {
+            validator: change.0,
+            change: change.1,
+            activation_epoch,
+        });
+    }
+
+    // The genesis stakes replace the set its proposer bootstrapped the chain with
+    if genesis {
+        state.validators.clear();
+    }
+
+    let mut unbonded = Vec::new();
+    if genesis || block_epoch > state.epoch {
+        state.epoch = block_epoch;
+        unbonded = process_epoch(state, block.header.timestamp, policy);
+    }
+
+    // Counted after the epoch rolls over, so a block is scored in the epoch of its height
+    count_proposals(state, block, missed, &absent, true);
+    rewards::record_rewards(state, &rewards, true);
+    jail_offline(state, block.header.block_number, policy);
+    unbonded
+}
+
+// Stake `block` unbonds when applied on `parent`, the consensus state of the block it builds on
+pub fn unbonded_stake(parent: &ConsensusState, block: &Block, policy: &LivenessPolicy) -> Vec<(NodeId, u64)> {
+    if !opens_epoch(block) {
+        return Vec::new();
+    }
+    // Missed slots are only counted after the epoch's changes are applied
+    apply_block(&mut parent.clone(), block, &[], policy)
+}
+
+// Stake a validator may still unbond: what it has bonded, counting bonds still queued, less
+// the unbonds already queued. validate_transaction makes sure only the validator unbonds
+fn unbondable_stake(state: &ConsensusState, validator: &NodeId) -> u64 {
+    let bonded = state.validators.get(validator).map_or(0, |info| info.stake);
+    state.pending_stake_changes.iter()
+        .filter(|pending| pending.validator == *validator)
+        .fold(bonded, |stake, pending| match pending.change {
+            StakeChange::Bond(amount) => stake.saturating_add(amount),
+            StakeChange::Unbond(amount) => stake.saturating_sub(amount),
+            StakeChange::Unjail => stake,
+        })
+}
+
+// Active validators whose precommit for the parent the block does not carry. The first block
+// has no parent to vote on
+fn absent_voters(state: &ConsensusState, block: &Block) -> Vec<NodeId> {
+    if block.header.block_number <= 1 {
+        return Vec::new();
+    }
+    state.validators.iter()
+        .filter(|(node_id, info)| info.is_active
+            && block.parent_votes.binary_search_by_key(*node_id, |vote| vote.validator).is_err())
+        .map(|(node_id, _)| *node_id)
+        .collect()
+}
+
+// Takes validators that reached a limit of the policy out of the active set until they send an
+// unjail transaction. The set is never jailed below min_validators, or the chain would stop
+fn jail_offline(state: &mut ConsensusState, block_number: u64, policy: &LivenessPolicy) {
+    let reached = |limit: u64, count: u64| limit > 0 && count >= limit;
+    let mut offline: Vec<NodeId> = state.validators.iter()
+        .filter(|(_, info)| info.is_active
+            && (reached(policy.max_missed_slots, info.missed) || reached(policy.max_missed_votes, info.missed_votes)))
+        .map(|(node_id, _)| *node_id)
+        .collect();
+    if offline.is_empty() {
+        return;
+    }
+    offline.sort_unstable();
+
+    for node_id in offline {
+        let active = state.validators.values().filter(|info| info.is_active).count() as u64;
+        if active <= state.params.min_validators.max(1) {
+            warn!("⛓️ Not jailing {}, one of the last {} active validators", hex::encode(node_id), active);
+            break;
+        }
+        if let Some(validator) = state.validators.get_mut(&node_id) {
+            validator.is_active = false;
+            validator.jailed_at = Some(block_number);
+            info!("⛓️ Jailed validator {} in block #{} ({} missed slots, {} missed votes)",
+                hex::encode(node_id), block_number, validator.missed, validator.missed_votes);
+        }
+    }
+    slashing::update_total_stake(state);
+}
+
+fn count_proposals(state: &mut ConsensusState, block: &Block, missed: &[NodeId], absent: &[NodeId], apply: bool) {
+    let count = |counter: &mut u64| *counter = if apply { *counter + 1 } else { counter.saturating_sub(1) };
+
+    if let Some(proposer) = state.validators.get_mut(&block.header.validator) {
+        count(&mut proposer.proposed);
+    }
+    for node_id in missed {
+        if let Some(validator) = state.validators.get_mut(node_id) {
+            count(&mut validator.missed);
+        }
+    }
+    for node_id in absent {
+        if let Some(validator) = state.validators.get_mut(node_id) {
+            count(&mut validator.missed_votes);
+        }
+    }
+}
+
+// Validators' weight in the proposer election: their stake scaled by their performance score
+pub fn proposer_weight(info: &ValidatorInfo) -> u64 {
+    (info.stake as f64 * info.performance_score.max(MIN_PERFORMANCE_SCORE)) as u64
+}
+
+// Everything here must only depend on the chain, since every node rotates the set on its own.
+// Returns the stake of the unbonds that took effect
+fn process_epoch(state: &mut ConsensusState, timestamp: DateTime<Utc>, policy: &LivenessPolicy) -> Vec<(NodeId, u64)> {
+    let epoch = state.epoch;
+    update_performance(state);
+    governance::decide_proposals(state);
+
+    let (due, queued): (Vec<_>, Vec<_>) = state.pending_stake_changes.drain(..)
+        .partition(|pending| pending.activation_epoch <= epoch);
+    state.pending_stake_changes = queued;
+
+    let mut unbonded = Vec::new();
+    for pending in due {
+        if pending.change == StakeChange::Unjail {
+            unjail(state, &pending.validator, policy);
+            continue;
+        }
+        let validator = state.validators.entry(pending.validator).or_insert_with(|| ValidatorInfo {
+            stake: 0,
+            is_active: false,
+            last_block_time: timestamp,
+            performance_score: 1.0,
+            proposed: 0,
+            missed: 0,
+            rewards: 0,
+            missed_votes: 0,
+            jailed_at: None,
+        });
+
+        match pending.change {
+            StakeChange::Bond(amount) => validator.stake = validator.stake.saturating_add(amount),
+            // Slashing may have taken part of it since it was queued
+            StakeChange::Unbond(amount) => {
+                let amount = amount.min(validator.stake);
+                validator.stake -= amount;
+                if amount > 0 {
+                    unbonded.push((pending.validator, amount));
+                }
+            }
+            StakeChange::Unjail => {}
+        }
+    }
+
+    state.validators.retain(|_, info| info.stake > 0);
+    rotate_validator_set(state);
+    unbonded
+}
+
+// Unjail transactions sent before the cooldown is over, or by validators that are not jailed,
+// are included but have no effect
+fn unjail(state: &mut ConsensusState, node_id: &NodeId, policy: &LivenessPolicy) {
+    let epoch = state.epoch;
+    // Validators removed by governance stay jailed while their stake unbonds
+    if unbondable_stake(state, node_id) == 0 {
+        debug!("Ignoring unjail of {}, all of its stake is unbonding", hex::encode(node_id));
+        return;
+    }
+    match state.validators.get_mut(node_id) {
+        Some(validator) if validator.jailed_at.is_some_and(|jailed_at| epoch >= epoch_of(jailed_at) + policy.jail_epochs) => {
+            validator.jailed_at = None;
+            info!("🔓 Validator {} unjailed in epoch {}", hex::encode(node_id), epoch);
+        }
+        _ => debug!("Ignoring unjail of {}, it is not jailed or still cooling down", hex::encode(node_id)),
+    }
+}
+
+// The share of its slots a validator filled in the ending epoch counts half towards its new
+// score, so one bad epoch is recovered from in a few good ones. Validators that had no slot
+// keep their score
+fn update_performance(state: &mut ConsensusState) {
+    for validator in state.validators.values_mut() {
+        let slots = validator.proposed + validator.missed;
+        if validator.is_active && slots > 0 {
+            let filled = validator.proposed as f64 / slots as f64;
+            validator.performance_score = ((validator.performance_score + filled) / 2.0).max(MIN_PERFORMANCE_SCORE);
+        }
+        validator.proposed = 0;
+        validator.missed = 0;
+        validator.missed_votes = 0;
+    }
+}
+
+// Activates up to max_validators validators holding the minimum stake, ranked by
+// stake weighted by performance score, with ties going to the lower node id
+fn rotate_validator_set(state: &mut ConsensusState) {
+    let epoch = state.epoch;
+    let mut candidates: Vec<(NodeId, f64)> = state.validators.iter()
+        .filter(|(_, info)| info.stake >= MIN_VALIDATOR_STAKE && info.jailed_at.is_none())
+        .map(|(node_id, info)| (*node_id, info.stake as f64 * info.performance_score))
+        .collect();
+    candidates.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+    let active: HashSet<NodeId> = candidates.into_iter()
+        .take(state.params.max_validators as usize)
+        .map(|(node_id, _)| node_id)
+        .collect();
+
+    for (node_id, validator) in state.validators.iter_mut() {
+        let was_active = validator.is_active;
+        validator.is_active = active.contains(node_id);
+
+        if validator.is_active && !was_active {
+            info!("🏛️ Validator {} activated with {} stake in epoch {}",
+                hex::encode(node_id), validator.stake, epoch);
+        } else if !validator.is_active && was_active {
+            info!("🏛️ Validator {} deactivated in epoch {} ({} stake, score {:.2})",
+                hex::encode(node_id), epoch, validator.stake, validator.performance_score);
+        }
+    }
+
+    state.total_stake = state.validators.values()
+        .filter(|info| info.is_active)
+        .map(|info| info.stake)
+        .sum();
+}
+
+// Active validators and their stake, sorted by node id
+pub fn active_validators(state: &ConsensusState) -> Vec<(NodeId, u64)> {
+    let mut validators: Vec<_> = state.validators.iter()
+        .filter(|(_, info)| info.is_active)
+        .map(|(node_id, info)| (*node_id, info.stake))
+        .collect();
+    validators.sort_unstable();
+    validators
+}
+
+// Active set as rotated in by `block`, which must open an epoch
+pub fn epoch_change(state: &ConsensusState, block: &Block) -> EpochChange {
+    EpochChange {
+        epoch: state.epoch,
+        start_block: block.header.block_number,
+        block_hash: block.hash(),
+        validators: active_validators(state),
+        total_stake: state.total_stake,
+    }
+}
+
+// Drops the queued changes, proposal counts, rewards and jailings a block contributed; used when the
+// block is reorganized away
+pub fn revert_block(state: &mut ConsensusState, block: &Block, missed: &[NodeId]) {
+    let block_number = block.header.block_number;
+    let mut jailed = false;
+    for validator in state.validators.values_mut().filter(|info| info.jailed_at == Some(block_number)) {
+        validator.jailed_at = None;
+        validator.is_active = true;
+        jailed = true;
+    }
+    if jailed {
+        slashing::update_total_stake(state);
+    }
+
+    count_proposals(state, block, missed, &absent_voters(state, block), false);
+    rewards::record_rewards(state, &block_rewards(state, &block.header.validator, &block.parent_votes), false);
+
+    for tx in block.transactions.iter().filter(|tx| validate_transaction(tx)) {
+        let (validator, change) = match &tx.kind {
+            TransactionKind::Transfer => continue,
+            TransactionKind::Evidence(report) => {
+                slashing::revert_slash(state, &report.evidence, block);
+                continue;
+            }
+            TransactionKind::Proposal(_) => {
+                governance::revert_proposal(state, &tx.id, block_number);
+                continue;
+            }
+            TransactionKind::ProposalVote(vote) => {
+                governance::revert_vote(state, vote, block_number);
+                continue;
+            }
+            TransactionKind::Stake(stake) => (stake.validator, StakeChange::Bond(stake.amount)),
+            TransactionKind::Unstake(unstake) => (unstake.validator, StakeChange::Unbond(unstake.amount)),
+            TransactionKind::Unjail(unjail) => (unjail.validator, StakeChange::Unjail),
+        };
+
+        let activation_epoch = match change {
+            StakeChange::Unjail => epoch_of(block_number) + 1,
+            _ => activation_epoch(block),
+        };
+        if let Some(index) = state.pending_stake_changes.iter().position(|pending| {
+            pending.validator == validator && pending.change == change && pending.activation_epoch == activation_epoch
+        }) {
+            state.pending_stake_changes.remove(index);
+        }
+    }
+}
@@ -0,0 +1,44 @@
+use crate::types::{BlockVote, ConsensusState, NodeId};
+
+// Minted by every block, on top of the fees its transactions pay the proposer
+pub const BLOCK_REWARD: u64 = 100;
+// Share of the block reward that goes to the proposer; the voters split the rest
+pub const PROPOSER_REWARD_PERCENT: u64 = 20;
+
+// Amounts a block mints, proposer first. `state` is the consensus state the block builds on and
+// `parent_votes` are sorted by validator, as blocks carry them:
+// the voter pool is split by stake across its whole active set, and only paid when the
+// precommits the block carries hold a quorum of that stake. The share of a validator whose
+// precommit is missing is not minted, so leaving votes out earns the proposer nothing
+pub fn block_rewards(state: &ConsensusState, proposer: &NodeId, parent_votes: &[BlockVote]) -> Vec<(NodeId, u64)> {
+    let proposer_reward = BLOCK_REWARD * PROPOSER_REWARD_PERCENT / 100;
+    let mut rewards = vec![(*proposer, proposer_reward)];
+
+    let active = super::active_validators(state);
+    let total: u64 = active.iter().map(|(_, stake)| stake).sum();
+    let voters: Vec<_> = active.into_iter()
+        .filter(|(node_id, _)| parent_votes.binary_search_by_key(node_id, |vote| vote.validator).is_ok())
+        .collect();
+    let voted: u64 = voters.iter().map(|(_, stake)| stake).sum();
+    if !state.params.has_quorum(voted, total) {
+        return rewards;
+    }
+
+    let pool = (BLOCK_REWARD - proposer_reward) as u128;
+    rewards.extend(voters.into_iter().map(|(node_id, stake)| (node_id, (pool * stake as u128 / total as u128) as u64)));
+    rewards
+}
+
+// Keeps the validators' reward totals in step with their balances; `apply` is false when the
+// block is reverted
+pub(super) fn record_rewards(state: &mut ConsensusState, rewards: &[(NodeId, u64)], apply: bool) {
+    for (node_id, amount) in rewards {
+        if let Some(validator) = state.validators.get_mut(node_id) {
+            validator.rewards = if apply {
+                validator.rewards.saturating_add(*amount)
+            } else {
+                validator.rewards.saturating_sub(*amount)
+            };
+        }
+    }
+}
@@ -0,0 +1,161 @@
+use crate::crypto;
+use crate::types::{Block, BlockHash, BlockVote, ConsensusState, Encode, LightBlock, NodeId, ValidatorInfo};
+use serde::{Deserialize, Serialize};
+use sha2::{Sha256, Digest};
+use tracing::{info, debug};
+
+use super::MIN_VALIDATOR_STAKE;
+
+// Share of the offender's bonded stake that is burned per offense
+pub const DOUBLE_VOTE_SLASH_PERCENT: u64 = 5;
+pub const INVALID_PROOF_SLASH_PERCENT: u64 = 10;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum Evidence {
+    // Two votes signed by one validator for different values in the same round and step
+    DoubleVote(BlockVote, BlockVote),
+    // A block signed by its proposer whose proof does not verify against the header
+    InvalidProof(LightBlock),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct EvidenceTransaction {
+    pub evidence: Evidence,
+}
+
+// Kept in the consensus state so an offense is only punished once and reorgs can undo it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SlashRecord {
+    pub offense: BlockHash,
+    pub offender: NodeId,
+    pub amount: u64,
+    pub block_number: u64,
+}
+
+impl Evidence {
+    pub fn offender(&self) -> NodeId {
+        match self {
+            Evidence::DoubleVote(first, _) => first.validator,
+            Evidence::InvalidProof(block) => block.header.validator,
+        }
+    }
+
+    // Identifies the offense rather than the evidence, so reports from several validators collapse
+    pub fn offense_id(&self) -> BlockHash {
+        let mut hasher = Sha256::new();
+        hasher.update(self.offender());
+        match self {
+            Evidence::DoubleVote(first, _) => {
+                hasher.update(b"double_vote");
+                hasher.update(first.height.to_le_bytes());
+                hasher.update(first.round.to_le_bytes());
+                hasher.update(first.step.encode());
+            }
+            Evidence::InvalidProof(block) => {
+                hasher.update(b"invalid_proof");
+                hasher.update(block.header.block_number.to_le_bytes());
+            }
+        }
+        hasher.finalize().into()
+    }
+
+    // Everything but the proof itself, which needs a verifier; see ConsensusEngine::verify_evidence
+    pub fn is_well_formed(&self) -> bool {
+        match self {
+            Evidence::DoubleVote(first, second) => {
+                first.validator == second.validator
+                    && first.height == second.height
+                    && first.round == second.round
+                    && first.step == second.step
+                    && first.value() != second.value()
+                    && crypto::verify_signature(&first.validator, &first.signing_hash(), &first.signature)
+                    && crypto::verify_signature(&second.validator, &second.signing_hash(), &second.signature)
+            }
+            Evidence::InvalidProof(block) => {
+                crypto::verify_signature(&block.header.validator, &block.signing_hash(), &block.signature)
+            }
+        }
+    }
+
+    fn slash_percent(&self) -> u64 {
+        match self {
+            Evidence::DoubleVote(..) => DOUBLE_VOTE_SLASH_PERCENT,
+            Evidence::InvalidProof(_) => INVALID_PROOF_SLASH_PERCENT,
+        }
+    }
+}
+
+pub fn is_slashed(state: &ConsensusState, evidence: &Evidence) -> bool {
+    let offense = evidence.offense_id();
+    state.slashed.iter().any(|record| record.offense == offense)
+}
+
+// Burns part of the offender's stake right away instead of waiting for the epoch
+pub fn slash(state: &mut ConsensusState, evidence: &Evidence, block_number: u64) {
+    let offender = evidence.offender();
+    if is_slashed(state, evidence) {
+        debug!("Offense by {} was already slashed", hex::encode(offender));
+        return;
+    }
+
+    let validator = match state.validators.get_mut(&offender) {
+        Some(validator) => validator,
+        None => {
+            debug!("Evidence against {} who has no bonded stake", hex::encode(offender));
+            return;
+        }
+    };
+
+    let amount = (validator.stake * evidence.slash_percent() / 100).max(1).min(validator.stake);
+    validator.stake -= amount;
+    // Slashing can only shrink the set; anyone benched by rotation stays out until the next epoch
+    validator.is_active &= validator.stake >= MIN_VALIDATOR_STAKE;
+    info!("⚔️ Slashed validator {} by {} stake in block #{} ({} left{})",
+        hex::encode(offender), amount, block_number, validator.stake,
+        if validator.is_active { "" } else { ", deactivated" });
+
+    state.slashed.push(SlashRecord {
+        offense: evidence.offense_id(),
+        offender,
+        amount,
+        block_number,
+    });
+    update_total_stake(state);
+}
+
+// Gives the stake back when the block that slashed it is reorganized away. A validator no
+// longer in the set comes back dated by that block rather than by our clock, so every node
+// reverts to the same state
+pub fn revert_slash(state: &mut ConsensusState, evidence: &Evidence, block: &Block) {
+    let block_number = block.header.block_number;
+    let offense = evidence.offense_id();
+    let index = match state.slashed.iter()
+        .position(|record| record.offense == offense && record.block_number == block_number)
+    {
+        Some(index) => index,
+        None => return,
+    };
+    let record = state.slashed.remove(index);
+
+    let validator = state.validators.entry(record.offender).or_insert_with(|| ValidatorInfo {
+        stake: 0,
+        is_active: false,
+        last_block_time: block.header.timestamp,
+        performance_score: 1.0,
+        proposed: 0,
+        missed: 0,
+        rewards: 0,
+        missed_votes: 0,
+        jailed_at: None,
+    });
+    validator.stake += record.amount;
+    validator.is_active = validator.stake >= MIN_VALIDATOR_STAKE && validator.jailed_at.is_none();
+    update_total_stake(state);
+}
+
+pub(super) fn update_total_stake(state: &mut ConsensusState) {
+    state.total_stake = state.validators.values()
+        .filter(|info| info.is_active)
+        .map(|info| info.stake)
+        .sum();
+}
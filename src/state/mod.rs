@@ -0,0 +1,232 @@
+use crate::staking;
+use crate::types::{Block, BlockHash, ReceiptStatus, Transaction, TransactionKind, TransactionReceipt};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use thiserror::Error;
+use tracing::debug;
+
+mod smt;
+
+pub use smt::{AccountProof, StateTransition};
+
+pub type AccountId = [u8; 32];
+
+// Minted to the proposer of a chain started without a genesis file, whose only source of funds it is
+pub const GENESIS_SUPPLY: u64 = 1_000_000_000;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Account {
+    pub balance: u64,
+    pub nonce: u64,
+}
+
+// Why a transaction does not execute on a state
+#[derive(Debug, Error)]
+pub enum TransactionError {
+    #[error("expected nonce {expected} but transaction has {found}")]
+    Nonce { expected: u64, found: u64 },
+    #[error(transparent)]
+    Invalid(anyhow::Error),
+    // Spends more than the sender has left after the transactions before it, such as the same
+    // funds a second time
+    #[error("balance {balance} is below the {cost} the transaction costs")]
+    Overdraw { balance: u64, cost: u64 },
+}
+
+// A block with a transaction that does not execute is invalid as a whole
+#[derive(Debug, Error)]
+#[error("transaction {index} ({}) of {} failed: {error}", hex::encode(tx_id), hex::encode(sender))]
+pub struct BlockExecutionError {
+    pub index: usize,
+    pub tx_id: [u8; 32],
+    pub sender: AccountId,
+    pub error: TransactionError,
+}
+
+// Accounts as of the last finalized block. Committed blocks always build on the finalized
+// block, so proposals are executed on top of this and undecided blocks never touch it.
+#[derive(Debug, Clone, Default)]
+pub struct AccountState {
+    accounts: BTreeMap<AccountId, Account>,
+}
+
+impl AccountState {
+    pub fn new(accounts: BTreeMap<AccountId, Account>) -> Self {
+        Self { accounts }
+    }
+
+    pub fn account(&self, id: &AccountId) -> Account {
+        self.accounts.get(id).copied().unwrap_or_default()
+    }
+
+    pub fn root(&self) -> BlockHash {
+        state_root(&self.accounts)
+    }
+
+    pub fn proof(&self, id: &AccountId) -> AccountProof {
+        smt::prove(&self.accounts, id)
+    }
+
+    // Witness for going from this state to `after`
+    pub fn transition(&self, after: &AccountState) -> StateTransition {
+        StateTransition::between(&self.accounts, &after.accounts)
+    }
+
+    // Checks nonce, signature and balance, and only changes the state if all of them hold.
+    // The fee goes to `proposer`, the block's validator
+    pub fn apply_transaction(&mut self, tx: &Transaction, proposer: &AccountId) -> Result<(), TransactionError> {
+        self.execute_transaction(tx, proposer, false)
+    }
+
+    fn execute_transaction(
+        &mut self,
+        tx: &Transaction,
+        proposer: &AccountId,
+        signature_checked: bool,
+    ) -> Result<(), TransactionError> {
+        let mut sender = self.account(&tx.from);
+
+        if tx.nonce != sender.nonce {
+            return Err(TransactionError::Nonce { expected: sender.nonce, found: tx.nonce });
+        }
+        let valid = match signature_checked {
+            true => tx.validate_unsigned(),
+            false => tx.validate(),
+        };
+        valid.map_err(TransactionError::Invalid)?;
+
+        // validate() already rejected costs that overflow
+        let cost = tx.total_cost().unwrap_or(u64::MAX);
+        if sender.balance < cost {
+            return Err(TransactionError::Overdraw { balance: sender.balance, cost });
+        }
+        sender.nonce += 1;
+        sender.balance -= cost;
+        self.accounts.insert(tx.from, sender);
+
+        // Staked amounts are bonded by the staking module, and come back through end_block
+        if matches!(tx.kind, TransactionKind::Transfer) {
+            self.credit(&tx.to, tx.amount);
+        }
+        self.credit(proposer, tx.fee);
+        Ok(())
+    }
+
+    // Beyond the genesis supply only block rewards are minted, far too slowly for a balance to overflow
+    fn credit(&mut self, id: &AccountId, amount: u64) {
+        let account = self.accounts.entry(*id).or_default();
+        account.balance = account.balance.saturating_add(amount);
+    }
+
+    // Credits applied before a block's transactions run: the block rewards, see
+    // staking::block_rewards, and the supply minted by a block bootstrapping a chain, the only
+    // one without a parent. Chains with a genesis file start from its allocation instead
+    pub fn begin_block(&mut self, parent_hash: &BlockHash, proposer: &AccountId, rewards: &[(AccountId, u64)]) {
+        if *parent_hash == [0; 32] {
+            self.credit(proposer, GENESIS_SUPPLY);
+        }
+        for (node_id, reward) in rewards {
+            self.credit(node_id, *reward);
+        }
+    }
+
+    // Credits applied after a block's transactions: the stake that staking::apply_block unbonded
+    // in it, back to the validators' balances
+    pub fn end_block(&mut self, unbonded: &[(AccountId, u64)]) {
+        for (validator, amount) in unbonded {
+            self.credit(validator, *amount);
+        }
+    }
+
+    // Executes every transaction of a block; a single failing transaction invalidates the block.
+    // `rewards` is what the block mints, see staking::block_rewards, and `unbonded` the stake
+    // it releases, see staking::unbonded_stake
+    pub fn apply_block(&mut self, block: &Block, rewards: &[(AccountId, u64)], unbonded: &[(AccountId, u64)]) -> Result<Vec<TransactionReceipt>, BlockExecutionError> {
+        self.execute_block(block, rewards, unbonded, false)
+    }
+
+    // apply_block for a block whose transaction signatures the signature verifier checked
+    pub fn apply_verified_block(&mut self, block: &Block, rewards: &[(AccountId, u64)], unbonded: &[(AccountId, u64)]) -> Result<Vec<TransactionReceipt>, BlockExecutionError> {
+        self.execute_block(block, rewards, unbonded, true)
+    }
+
+    fn execute_block(
+        &mut self,
+        block: &Block,
+        rewards: &[(AccountId, u64)],
+        unbonded: &[(AccountId, u64)],
+        signatures_checked: bool,
+    ) -> Result<Vec<TransactionReceipt>, BlockExecutionError> {
+        self.begin_block(&block.header.parent_hash, &block.header.validator, rewards);
+
+        let block_hash = block.hash();
+        let mut receipts = Vec::with_capacity(block.transactions.len());
+        for (index, tx) in block.transactions.iter().enumerate() {
+            if let Err(error) = self.execute_transaction(tx, &block.header.validator, signatures_checked) {
+                return Err(BlockExecutionError { index, tx_id: tx.id, sender: tx.from, error });
+            }
+
+            // Mirrors the check staking::apply_block skips transactions on
+            let ignored = !matches!(tx.kind, TransactionKind::Transfer) && !staking::validate_transaction(tx);
+            receipts.push(TransactionReceipt {
+                tx_id: tx.id,
+                block_hash,
+                block_number: block.header.block_number,
+                index: index as u32,
+                status: if ignored { ReceiptStatus::Ignored } else { ReceiptStatus::Success },
+                fee: tx.fee,
+            });
+        }
+        self.end_block(unbonded);
+
+        debug!("Executed {} transactions of block #{}", block.transactions.len(), block.header.block_number);
+        Ok(receipts)
+    }
+
+    // Length of the account's path in the state tree, see smt::depth
+    pub fn path_depth(&self, id: &AccountId) -> usize {
+        smt::depth(&self.accounts, id)
+    }
+
+    // Whether the account is any different from what it is in `before`
+    pub fn differs(&self, before: &AccountState, id: &AccountId) -> bool {
+        self.accounts.get(id) != before.accounts.get(id)
+    }
+
+    // Accounts executing `tx` can change: its sender, a transfer's recipient and the proposer
+    pub fn touched_by(tx: &Transaction, proposer: &AccountId) -> Vec<AccountId> {
+        let mut ids = vec![tx.from, *proposer];
+        if matches!(tx.kind, TransactionKind::Transfer) {
+            ids.push(tx.to);
+        }
+        ids
+    }
+
+    // The accounts as they are now, for restore to put back
+    pub fn save(&self, ids: &[AccountId]) -> Vec<(AccountId, Option<Account>)> {
+        ids.iter().map(|id| (*id, self.accounts.get(id).copied())).collect()
+    }
+
+    pub fn restore(&mut self, saved: Vec<(AccountId, Option<Account>)>) {
+        // An account saved twice keeps the state it was first saved in
+        for (id, account) in saved.into_iter().rev() {
+            match account {
+                Some(account) => self.accounts.insert(id, account),
+                None => self.accounts.remove(&id),
+            };
+        }
+    }
+
+    // Accounts that differ from `before`, to be written back to storage
+    pub fn changes_since(&self, before: &AccountState) -> Vec<(AccountId, Account)> {
+        self.accounts.iter()
+            .filter(|(id, account)| before.accounts.get(*id) != Some(*account))
+            .map(|(id, account)| (*id, *account))
+            .collect()
+    }
+}
+
+// Root of the sparse merkle tree over the accounts, see smt
+pub fn state_root(accounts: &BTreeMap<AccountId, Account>) -> BlockHash {
+    smt::root(accounts)
+}
@@ -0,0 +1,334 @@
+use super::{Account, AccountId};
+use crate::types::{self, BlockHash};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::ops::Bound;
+
+// Sparse merkle tree over the 256 bits of the account id, most significant bit first, so the
+// leaves are in the same order as the accounts map. A subtree holding a single account is
+// that account's leaf and an empty subtree is EMPTY; only subtrees with two or more accounts
+// get an inner node, which keeps paths about log2(accounts) long instead of 256.
+//
+// The tree hashes with the merkle hash function, like transaction merkle trees, so block
+// circuits recompute both with the same gadgets. A leaf hashes the 48 bytes of the id and the
+// big endian balance and nonce, which line up with SHA-256 words; an inner node is the merkle
+// parent of its children. Neither can hash like the other: SHA-256 pads in the length, and
+// Poseidon hashes bytes with a sponge but parents with a compression
+const EMPTY: BlockHash = [0; 32];
+const KEY_BITS: usize = 256;
+
+// Proof that an account holds `state` under a state root, or that it is not in the tree
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AccountProof {
+    pub account: AccountId,
+    // None when the account has never been credited
+    pub state: Option<Account>,
+    // Hashes of the subtrees next to the path, from the root down
+    pub siblings: Vec<BlockHash>,
+    // For a missing account: the one other account found where its path ends, if any
+    pub neighbour: Option<(AccountId, Account)>,
+}
+
+// A block's state transition as single account updates, in id order, each proven against the
+// root the updates before it left. The chain of roots from the parent's state root to the
+// block's is the witness a circuit needs to prove the state change
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StateTransition {
+    pub pre_root: BlockHash,
+    pub post_root: BlockHash,
+    pub updates: Vec<AccountUpdate>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AccountUpdate {
+    // Proof of the state before the update
+    pub proof: AccountProof,
+    pub state: Account,
+}
+
+pub fn root(accounts: &BTreeMap<AccountId, Account>) -> BlockHash {
+    subtree(&leaves(accounts), 0)
+}
+
+// Length of the path of an account in the tree: one past the longest prefix its id shares with
+// another account's. The ids sharing the most with it are next to it in id order
+pub fn depth(accounts: &BTreeMap<AccountId, Account>, account: &AccountId) -> usize {
+    let before = accounts.range(..*account).next_back();
+    let after = accounts.range((Bound::Excluded(*account), Bound::Unbounded)).next();
+    before.into_iter().chain(after)
+        .map(|(id, _)| common_prefix(id, account) + 1)
+        .max()
+        .unwrap_or(0)
+}
+
+pub fn prove(accounts: &BTreeMap<AccountId, Account>, account: &AccountId) -> AccountProof {
+    let leaves = leaves(accounts);
+    let mut path = &leaves[..];
+    let mut siblings = Vec::new();
+
+    // Walks down until at most one account is left on the path
+    while path.len() > 1 {
+        let depth = siblings.len();
+        let (left, right) = path.split_at(path.partition_point(|(id, _)| !bit(id, depth)));
+        if bit(account, depth) {
+            siblings.push(subtree(left, depth + 1));
+            path = right;
+        } else {
+            siblings.push(subtree(right, depth + 1));
+            path = left;
+        }
+    }
+
+    let found = path.first().map(|(id, _)| (*id, accounts[id]));
+    match found {
+        Some((id, state)) if id == *account => AccountProof { account: id, state: Some(state), siblings, neighbour: None },
+        neighbour => AccountProof { account: *account, state: None, siblings, neighbour },
+    }
+}
+
+impl AccountProof {
+    // Root of the tree the proof was taken from; None when the proof is malformed
+    pub fn root(&self) -> Option<BlockHash> {
+        if self.siblings.len() > KEY_BITS {
+            return None;
+        }
+        let bottom = match (&self.state, &self.neighbour) {
+            (Some(state), None) => leaf(&self.account, state),
+            (None, None) => EMPTY,
+            // The neighbour has to be on the path the account would be on
+            (None, Some((id, state))) if *id != self.account && common_prefix(id, &self.account) >= self.siblings.len() => {
+                leaf(id, state)
+            }
+            _ => return None,
+        };
+        Some(fold(&self.account, bottom, &self.siblings))
+    }
+
+    pub fn verify(&self, root: &BlockHash) -> bool {
+        self.root() == Some(*root)
+    }
+
+    // Length of the account's path once it is set: a missing account takes its neighbour's
+    // place, which splits where their ids first differ
+    pub fn updated_depth(&self) -> usize {
+        match &self.neighbour {
+            Some((id, _)) => common_prefix(id, &self.account) + 1,
+            None => self.siblings.len(),
+        }
+    }
+
+    // Root of the same tree with the account set to `state`
+    pub fn updated_root(&self, state: &Account) -> Option<BlockHash> {
+        self.root()?;
+        let new_leaf = leaf(&self.account, state);
+        let bottom = match &self.neighbour {
+            None => new_leaf,
+            // The neighbour's leaf splits into an inner node above both accounts where their ids
+            // first differ, with empty subtrees beside the shared part of the path
+            Some((id, neighbour)) => {
+                let depth = common_prefix(id, &self.account);
+                let other = leaf(id, neighbour);
+                let mut hash = if bit(&self.account, depth) { node(&other, &new_leaf) } else { node(&new_leaf, &other) };
+                for depth in (self.siblings.len()..depth).rev() {
+                    hash = if bit(&self.account, depth) { node(&EMPTY, &hash) } else { node(&hash, &EMPTY) };
+                }
+                hash
+            }
+        };
+        Some(fold(&self.account, bottom, &self.siblings))
+    }
+}
+
+impl StateTransition {
+    // A block that changes no account
+    pub fn unchanged(root: BlockHash) -> Self {
+        Self { pre_root: root, post_root: root, updates: Vec::new() }
+    }
+
+    // Proves every account that differs between the two states, applying the changes one by one
+    pub fn between(before: &BTreeMap<AccountId, Account>, after: &BTreeMap<AccountId, Account>) -> Self {
+        let mut accounts = before.clone();
+        let mut updates = Vec::new();
+        for (id, state) in after {
+            if before.get(id) == Some(state) {
+                continue;
+            }
+            updates.push(AccountUpdate { proof: prove(&accounts, id), state: *state });
+            accounts.insert(*id, *state);
+        }
+
+        Self { pre_root: root(before), post_root: root(&accounts), updates }
+    }
+
+    // Every update is proven against the root left by the ones before it, and the last one
+    // ends at post_root
+    pub fn verify(&self) -> bool {
+        let mut root = self.pre_root;
+        for update in &self.updates {
+            if !update.proof.verify(&root) {
+                return false;
+            }
+            root = match update.proof.updated_root(&update.state) {
+                Some(root) => root,
+                None => return false,
+            };
+        }
+        root == self.post_root
+    }
+}
+
+fn leaves(accounts: &BTreeMap<AccountId, Account>) -> Vec<(AccountId, BlockHash)> {
+    accounts.iter().map(|(id, account)| (*id, leaf(id, account))).collect()
+}
+
+// `leaves` are sorted by id and share their first `depth` bits
+fn subtree(leaves: &[(AccountId, BlockHash)], depth: usize) -> BlockHash {
+    match leaves {
+        [] => EMPTY,
+        [(_, leaf)] => *leaf,
+        _ => {
+            let (left, right) = leaves.split_at(leaves.partition_point(|(id, _)| !bit(id, depth)));
+            node(&subtree(left, depth + 1), &subtree(right, depth + 1))
+        }
+    }
+}
+
+// Hashes up from the bottom of the account's path to the root
+fn fold(account: &AccountId, bottom: BlockHash, siblings: &[BlockHash]) -> BlockHash {
+    siblings.iter().enumerate().rev().fold(bottom, |hash, (depth, sibling)| {
+        if bit(account, depth) { node(sibling, &hash) } else { node(&hash, sibling) }
+    })
+}
+
+fn leaf(id: &AccountId, account: &Account) -> BlockHash {
+    types::merkle_hasher().hash(&[id, &account.balance.to_be_bytes(), &account.nonce.to_be_bytes()])
+}
+
+fn node(left: &BlockHash, right: &BlockHash) -> BlockHash {
+    types::merkle_hasher().hash_pair(left, right)
+}
+
+fn bit(id: &AccountId, depth: usize) -> bool {
+    id[depth / 8] & (0x80 >> (depth % 8)) != 0
+}
+
+fn common_prefix(a: &AccountId, b: &AccountId) -> usize {
+    (0..KEY_BITS).find(|depth| bit(a, *depth) != bit(b, *depth)).unwrap_or(KEY_BITS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // An id whose first byte is `first`, so the tests control where paths split
+    fn id(first: u8, last: u8) -> AccountId {
+        let mut id = [0; 32];
+        id[0] = first;
+        id[31] = last;
+        id
+    }
+
+    fn account(balance: u64) -> Account {
+        Account { balance, nonce: balance % 7 }
+    }
+
+    fn accounts(ids: &[AccountId]) -> BTreeMap<AccountId, Account> {
+        ids.iter().enumerate().map(|(i, id)| (*id, account(100 + i as u64))).collect()
+    }
+
+    #[test]
+    fn accounts_prove_their_state() {
+        let accounts = accounts(&[id(0x00, 1), id(0x40, 2), id(0x80, 3), id(0x81, 4), id(0xff, 5)]);
+        let root = root(&accounts);
+        for (id, state) in &accounts {
+            let proof = prove(&accounts, id);
+            assert_eq!(proof.state, Some(*state));
+            assert!(proof.verify(&root));
+
+            let mut forged = proof.clone();
+            forged.state = Some(account(state.balance + 1));
+            assert!(!forged.verify(&root));
+        }
+    }
+
+    #[test]
+    fn missing_accounts_prove_their_absence() {
+        let accounts = accounts(&[id(0x00, 1), id(0x80, 2), id(0xc0, 3)]);
+        let root = root(&accounts);
+
+        // Ends at an empty subtree, and at another account's leaf
+        for missing in [id(0x40, 9), id(0x81, 9)] {
+            let proof = prove(&accounts, &missing);
+            assert_eq!(proof.state, None);
+            assert!(proof.verify(&root));
+        }
+        let proof = prove(&accounts, &id(0x81, 9));
+        assert_eq!(proof.neighbour.map(|(id, _)| id), Some(id(0x80, 2)));
+
+        // The neighbour has to be on the missing account's path
+        let mut forged = prove(&accounts, &id(0x40, 9));
+        forged.neighbour = Some((id(0xc0, 3), accounts[&id(0xc0, 3)]));
+        assert!(!forged.verify(&root));
+
+        // and an account in the tree can not be proven missing
+        let mut hidden = prove(&accounts, &id(0x80, 2));
+        hidden.state = None;
+        assert!(!hidden.verify(&root));
+    }
+
+    #[test]
+    fn empty_tree() {
+        let accounts = BTreeMap::new();
+        assert_eq!(root(&accounts), EMPTY);
+        assert!(prove(&accounts, &id(1, 1)).verify(&EMPTY));
+        assert_eq!(depth(&accounts, &id(1, 1)), 0);
+    }
+
+    #[test]
+    fn paths_end_below_the_longest_shared_prefix() {
+        let accounts = accounts(&[id(0x00, 1), id(0x80, 2), id(0x80, 3)]);
+        assert_eq!(depth(&accounts, &id(0x00, 1)), 1);
+        // These two share all bits but the last
+        assert_eq!(depth(&accounts, &id(0x80, 2)), KEY_BITS);
+        assert_eq!(depth(&accounts, &id(0x80, 3)), KEY_BITS);
+        for id in accounts.keys() {
+            assert_eq!(prove(&accounts, id).siblings.len(), depth(&accounts, id));
+        }
+        assert_eq!(depth(&BTreeMap::from([(id(0x00, 1), account(1))]), &id(0x00, 1)), 0);
+    }
+
+    #[test]
+    fn updates_move_the_root_as_rebuilding_does() {
+        let mut accounts = accounts(&[id(0x00, 1), id(0x80, 2), id(0xc0, 3)]);
+        // A change, an account splitting an existing leaf, and one in an empty subtree
+        for (id, state) in [(id(0x80, 2), account(1)), (id(0xc1, 4), account(2)), (id(0x40, 5), account(3))] {
+            let proof = prove(&accounts, &id);
+            let expected_depth = {
+                let mut updated = accounts.clone();
+                updated.insert(id, state);
+                depth(&updated, &id)
+            };
+            assert_eq!(proof.updated_depth(), expected_depth);
+            accounts.insert(id, state);
+            assert_eq!(proof.updated_root(&state), Some(root(&accounts)));
+        }
+    }
+
+    #[test]
+    fn transitions_chain_from_the_pre_root_to_the_post_root() {
+        let before = accounts(&[id(0x00, 1), id(0x80, 2)]);
+        let mut after = before.clone();
+        after.insert(id(0x80, 2), account(5));
+        after.insert(id(0x81, 3), account(6));
+
+        let transition = StateTransition::between(&before, &after);
+        assert_eq!((transition.pre_root, transition.post_root), (root(&before), root(&after)));
+        assert_eq!(transition.updates.len(), 2);
+        assert!(transition.verify());
+
+        let mut skipped = transition.clone();
+        skipped.updates.remove(0);
+        assert!(!skipped.verify());
+        assert!(StateTransition::between(&before, &before).verify());
+    }
+}
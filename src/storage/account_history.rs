@@ -0,0 +1,188 @@
+use super::{StorageManager, CF_ACCOUNTS, CF_ACCOUNT_HISTORY, CF_ACCOUNT_VERSIONS, CF_BLOCKS};
+use crate::state::{self, Account, AccountId, AccountProof};
+use crate::types::{Block, ConsensusState, NodeId};
+use super::{Result, StorageError};
+use rocksdb::{Direction, IteratorMode, WriteBatch};
+use std::collections::BTreeMap;
+
+// What a finalized block left behind. The consensus state is loaded up front, accounts are
+// read one at a time, so looking up a balance does not roll back every account
+pub struct StateAt<'a> {
+    storage: &'a StorageManager,
+    pub block: Block,
+    pub consensus_state: ConsensusState,
+}
+
+impl StateAt<'_> {
+    pub fn block_number(&self) -> u64 {
+        self.block.header.block_number
+    }
+
+    pub async fn account(&self, id: &AccountId) -> Result<Option<Account>> {
+        self.storage.get_account_at(id, self.block_number()).await
+    }
+
+    pub async fn balance(&self, id: &AccountId) -> Result<u64> {
+        Ok(self.account(id).await?.map_or(0, |account| account.balance))
+    }
+
+    // Active validators and their stake, as types::validator_set_hash commits to them
+    pub fn validator_set(&self) -> Vec<(NodeId, u64)> {
+        let mut validators: Vec<_> = self.consensus_state.validators.iter()
+            .filter(|(_, info)| info.is_active)
+            .map(|(node_id, info)| (*node_id, info.stake))
+            .collect();
+        validators.sort();
+        validators
+    }
+}
+
+impl StorageManager {
+    // Height of the block that changed the account, then the account id, so the changes of
+    // a block are next to each other and older blocks come first
+    fn account_history_key(block_number: u64, id: &AccountId) -> Vec<u8> {
+        let mut key = Vec::with_capacity(40);
+        key.extend_from_slice(&Self::block_key(block_number));
+        key.extend_from_slice(id);
+        key
+    }
+
+    // The same entries keyed the other way round, so one account's changes are next to each
+    // other, oldest first
+    fn account_version_key(id: &AccountId, block_number: u64) -> Vec<u8> {
+        let mut key = Vec::with_capacity(40);
+        key.extend_from_slice(id);
+        key.extend_from_slice(&Self::block_key(block_number));
+        key
+    }
+
+    // Keeps the values `accounts` had before block `block_number` changed them, which is
+    // what rolls the accounts back to an earlier block
+    pub(super) fn add_account_history_to_batch(
+        &self,
+        batch: &mut WriteBatch,
+        block_number: u64,
+        accounts: &[(AccountId, Account)],
+    ) -> Result<()> {
+        for (id, _) in accounts {
+            let before = bincode::serialize(&self.get::<Account>(CF_ACCOUNTS, id)?)?;
+            batch.put_cf(self.cf(CF_ACCOUNT_HISTORY)?, Self::account_history_key(block_number, id), &before);
+            batch.put_cf(self.cf(CF_ACCOUNT_VERSIONS)?, Self::account_version_key(id, block_number), &before);
+        }
+        Ok(())
+    }
+
+    // Builds the account-ordered copy of the history for databases from before it (schema 7)
+    pub(super) fn index_account_history(&self) -> Result<u64> {
+        let mut batch = WriteBatch::default();
+        let mut indexed = 0;
+        for item in self.db.iterator_cf(self.cf(CF_ACCOUNT_HISTORY)?, IteratorMode::Start) {
+            let (key, value) = item?;
+            if key.len() != 40 {
+                return Err(StorageError::Corrupted(format!("malformed account history key of {} bytes", key.len())));
+            }
+            let block_number = u64::from_be_bytes(key[..8].try_into().unwrap());
+            batch.put_cf(self.cf(CF_ACCOUNT_VERSIONS)?, Self::account_version_key(&Self::account_id(&key[8..])?, block_number), value);
+            indexed += 1;
+        }
+        self.db.write(batch)?;
+        Ok(indexed)
+    }
+
+    // Drops the history of block `block_number`, once its state is no longer served
+    pub(super) fn prune_account_history(&self, batch: &mut WriteBatch, block_number: u64) -> Result<u64> {
+        let prefix = Self::block_key(block_number);
+        let mut reclaimed = 0;
+        for item in self.db.prefix_iterator_cf(self.cf(CF_ACCOUNT_HISTORY)?, prefix) {
+            let (key, value) = item?;
+            if !key.starts_with(&prefix) {
+                break;
+            }
+            // Counted twice, for the entry and its account-ordered copy
+            reclaimed += 2 * (key.len() + value.len()) as u64;
+            let id = Self::account_id(&key[8..])?;
+            batch.delete_cf(self.cf(CF_ACCOUNT_HISTORY)?, &key);
+            batch.delete_cf(self.cf(CF_ACCOUNT_VERSIONS)?, Self::account_version_key(&id, block_number));
+        }
+        Ok(reclaimed)
+    }
+
+    // Accounts as of finalized block `block_number`: the current ones with every later block's
+    // changes undone. Read from one snapshot, so a block finalizing meanwhile can not mix in
+    pub async fn get_accounts_at(&self, block_number: u64) -> Result<BTreeMap<AccountId, Account>> {
+        let snapshot = self.db.snapshot();
+        let mut accounts = BTreeMap::new();
+        for item in snapshot.iterator_cf(self.cf(CF_ACCOUNTS)?, IteratorMode::Start) {
+            let (key, value) = item?;
+            accounts.insert(Self::account_id(&key)?, bincode::deserialize(&value)?);
+        }
+
+        // Newest first, down to the block after the requested one
+        let end = Self::account_history_key(u64::MAX, &[u8::MAX; 32]);
+        for item in snapshot.iterator_cf(self.cf(CF_ACCOUNT_HISTORY)?, IteratorMode::From(&end, Direction::Reverse)) {
+            let (key, value) = item?;
+            if key.len() != 40 {
+                return Err(StorageError::Corrupted(format!("malformed account history key of {} bytes", key.len())));
+            }
+            if u64::from_be_bytes(key[..8].try_into().unwrap()) <= block_number {
+                break;
+            }
+            let id = Self::account_id(&key[8..])?;
+            match bincode::deserialize::<Option<Account>>(&value)? {
+                Some(account) => accounts.insert(id, account),
+                None => accounts.remove(&id),
+            };
+        }
+
+        Ok(accounts)
+    }
+
+    // Account as of finalized block `block_number`: what it was before the first later block
+    // changed it, or the current account when none did. Read from one snapshot, like
+    // get_accounts_at; see get_state_at for which blocks this holds for
+    pub async fn get_account_at(&self, id: &AccountId, block_number: u64) -> Result<Option<Account>> {
+        let snapshot = self.db.snapshot();
+        let start = Self::account_version_key(id, block_number.saturating_add(1));
+        if let Some(item) = snapshot.iterator_cf(self.cf(CF_ACCOUNT_VERSIONS)?, IteratorMode::From(&start, Direction::Forward)).next() {
+            let (key, value) = item?;
+            if key.starts_with(id) {
+                return Ok(bincode::deserialize(&value)?);
+            }
+        }
+        match snapshot.get_cf(self.cf(CF_ACCOUNTS)?, id)? {
+            Some(value) => Ok(Some(bincode::deserialize(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    // State as of finalized block `height`. Kept from the first block this node finalized, or
+    // the snapshot it started from, until pruning drops the block's account history and
+    // consensus state, which go together
+    pub async fn get_state_at(&self, height: u64) -> Result<StateAt<'_>> {
+        let block = match self.get_block(height).await? {
+            Some(block) => block,
+            None => return Err(StorageError::NotFound(format!("block #{}", height))),
+        };
+        let consensus_state = match self.get_consensus_state_at(height).await? {
+            Some(state) => state,
+            None => return Err(StorageError::NotFound(format!("state of block #{}", height))),
+        };
+        Ok(StateAt { storage: self, block, consensus_state })
+    }
+
+    // Proof of the account against the state root of canonical block `block_number`. Only
+    // finalized blocks whose history was not pruned have their state kept
+    pub async fn get_account_proof(&self, id: &AccountId, block_number: u64) -> Result<(Block, AccountProof)> {
+        let block: Block = match self.get(CF_BLOCKS, &Self::block_key(block_number))? {
+            Some(block) => block,
+            None => return Err(StorageError::NotFound(format!("block #{}", block_number))),
+        };
+
+        let accounts = self.get_accounts_at(block_number).await?;
+        if state::state_root(&accounts) != block.header.state_root {
+            return Err(StorageError::NotFound(format!("state of block #{}", block_number)));
+        }
+        let proof = state::AccountState::new(accounts).proof(id);
+        Ok((block, proof))
+    }
+}
@@ -0,0 +1,24 @@
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum StorageError {
+    #[error("database error: {0}")]
+    Database(#[from] rocksdb::Error),
+    #[error("could not encode or decode a record: {0}")]
+    Codec(#[from] bincode::Error),
+    #[error("i/o error: {0}")]
+    Io(#[from] std::io::Error),
+    // Written by a node that does not match this one, or damaged on disk
+    #[error("corrupted database: {0}")]
+    Corrupted(String),
+    // Written by a newer node, or by an older one this node can not migrate
+    #[error("incompatible database: {0}")]
+    Schema(String),
+    #[error("{0} not found")]
+    NotFound(String),
+    // The operation would break an invariant of the stored chain
+    #[error("{0}")]
+    Rejected(String),
+    #[error("snapshot: {0:#}")]
+    Snapshot(anyhow::Error),
+}
@@ -0,0 +1,113 @@
+use super::{StorageManager, CF_ADDRESS_TXS};
+use crate::state::AccountId;
+use crate::types::{Block, Transaction};
+use super::{Result, StorageError};
+use rocksdb::{Direction, IteratorMode, WriteBatch};
+use serde::{Deserialize, Serialize};
+
+// Transactions one page of address history may hold
+pub const MAX_HISTORY_PAGE: usize = 100;
+
+// A page of an address's transactions, newest first
+#[derive(Debug, Clone)]
+pub struct HistoryPage {
+    // next_cursor of the previous page; the newest transactions when absent
+    pub cursor: Option<u64>,
+    pub limit: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AddressTransaction {
+    pub block_number: u64,
+    // Position of the transaction in the block
+    pub index: u32,
+    pub transaction: Transaction,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AddressHistory {
+    pub transactions: Vec<AddressTransaction>,
+    // Absent on the last page
+    pub next_cursor: Option<u64>,
+}
+
+impl StorageManager {
+    // Address, then block number and index in the block, so an address's transactions are
+    // stored in chain order. Both the sender and the recipient get an entry
+    fn address_key(address: &AccountId, position: u64) -> Vec<u8> {
+        let mut key = Vec::with_capacity(40);
+        key.extend_from_slice(address);
+        key.extend_from_slice(&position.to_be_bytes());
+        key
+    }
+
+    fn position(block_number: u64, index: u32) -> u64 {
+        (block_number << 32) | index as u64
+    }
+
+    // Indexes the transactions of a finalized block under their sender and recipient
+    pub async fn index_transactions(&self, block: &Block) -> Result<()> {
+        let mut batch = WriteBatch::default();
+        for (index, tx) in block.transactions.iter().enumerate() {
+            let position = Self::position(block.header.block_number, index as u32);
+            for address in [&tx.from, &tx.to] {
+                batch.put_cf(self.cf(CF_ADDRESS_TXS)?, Self::address_key(address, position), tx.id);
+            }
+        }
+        self.db.write(batch)?;
+        Ok(())
+    }
+
+    // Drops the index entries of a block whose transactions are pruned
+    pub(super) fn unindex_transactions(&self, batch: &mut WriteBatch, block: &Block) -> Result<u64> {
+        let mut reclaimed = 0;
+        for (index, tx) in block.transactions.iter().enumerate() {
+            let position = Self::position(block.header.block_number, index as u32);
+            for address in [&tx.from, &tx.to] {
+                batch.delete_cf(self.cf(CF_ADDRESS_TXS)?, Self::address_key(address, position));
+                reclaimed += 40 + tx.id.len() as u64;
+            }
+        }
+        Ok(reclaimed)
+    }
+
+    // Finalized transactions sent or received by the address, newest first
+    pub async fn get_transactions_by_address(&self, address: &AccountId, page: &HistoryPage) -> Result<AddressHistory> {
+        let limit = page.limit.clamp(1, MAX_HISTORY_PAGE);
+        let start = Self::address_key(address, page.cursor.unwrap_or(u64::MAX));
+
+        // Entries are collected before any lookup, as the iterator can not be held across an await
+        let mut entries = Vec::new();
+        let mut next_cursor = None;
+        for item in self.db.iterator_cf(self.cf(CF_ADDRESS_TXS)?, IteratorMode::From(&start, Direction::Reverse)) {
+            let (key, tx_id) = item?;
+            if !key.starts_with(address) {
+                break;
+            }
+            let position = match key.get(32..40) {
+                Some(position) => u64::from_be_bytes(position.try_into().unwrap()),
+                None => return Err(StorageError::Corrupted(format!("malformed address history key of {} bytes", key.len()))),
+            };
+            if entries.len() == limit {
+                next_cursor = Some(position);
+                break;
+            }
+            if let Ok(tx_id) = <[u8; 32]>::try_from(tx_id.as_ref()) {
+                entries.push((position, tx_id));
+            }
+        }
+
+        let mut transactions = Vec::with_capacity(entries.len());
+        for (position, tx_id) in entries {
+            if let Some(transaction) = self.get_transaction(&tx_id).await? {
+                transactions.push(AddressTransaction {
+                    block_number: position >> 32,
+                    index: position as u32,
+                    transaction,
+                });
+            }
+        }
+
+        Ok(AddressHistory { transactions, next_cursor })
+    }
+}
@@ -0,0 +1,123 @@
+use super::{StorageManager, COLUMN_FAMILIES, CF_CONSENSUS_STATE};
+use crate::shutdown::ShutdownSignal;
+use super::Result;
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use tracing::{info, warn};
+
+const COMPACTION_KEY: &[u8] = b"last_compaction";
+// How often the maintenance task wakes up to check the disk usage and whether a compaction is due
+const CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ColumnFamilyStats {
+    pub name: &'static str,
+    // RocksDB's estimates; table files hold more than the live data until compaction drops
+    // deleted and overwritten entries
+    pub keys: u64,
+    pub live_bytes: u64,
+    pub sst_bytes: u64,
+    pub memtable_bytes: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StorageStats {
+    pub column_families: Vec<ColumnFamilyStats>,
+    // Everything in the database directory, write-ahead log and RocksDB's own files included
+    pub disk_bytes: u64,
+    pub last_compaction: Option<DateTime<Utc>>,
+}
+
+impl StorageManager {
+    pub async fn get_storage_stats(&self) -> Result<StorageStats> {
+        let mut column_families = Vec::with_capacity(COLUMN_FAMILIES.len());
+        for name in COLUMN_FAMILIES {
+            let property = |property: &str| -> Result<u64> {
+                Ok(self.db.property_int_value_cf(self.cf(name)?, property)?.unwrap_or(0))
+            };
+            column_families.push(ColumnFamilyStats {
+                name,
+                keys: property("rocksdb.estimate-num-keys")?,
+                live_bytes: property("rocksdb.estimate-live-data-size")?,
+                sst_bytes: property("rocksdb.total-sst-files-size")?,
+                memtable_bytes: property("rocksdb.cur-size-all-mem-tables")?,
+            });
+        }
+
+        Ok(StorageStats {
+            column_families,
+            disk_bytes: self.disk_usage()?,
+            last_compaction: self.get(CF_CONSENSUS_STATE, COMPACTION_KEY)?,
+        })
+    }
+
+    // RocksDB keeps all its files directly in the database directory
+    fn disk_usage(&self) -> Result<u64> {
+        let mut total = 0;
+        for entry in std::fs::read_dir(self.db.path())? {
+            let metadata = entry?.metadata()?;
+            if metadata.is_file() {
+                total += metadata.len();
+            }
+        }
+        Ok(total)
+    }
+
+    // Rewrites every column family, which is what returns space freed by pruning to the
+    // filesystem. Runs on a blocking thread, since a large database takes minutes
+    pub async fn compact(&self) -> Result<()> {
+        let storage = self.clone();
+        tokio::task::spawn_blocking(move || -> Result<()> {
+            for name in COLUMN_FAMILIES {
+                storage.db.compact_range_cf(storage.cf(name)?, None::<&[u8]>, None::<&[u8]>);
+            }
+            Ok(())
+        }).await.map_err(std::io::Error::from)??;
+
+        self.put(CF_CONSENSUS_STATE, COMPACTION_KEY, &Utc::now())?;
+        info!("🗜️ Database compaction completed");
+        Ok(())
+    }
+
+    // Compacts every `compaction_interval` seconds, counted across restarts, and warns once the
+    // database takes more than `disk_alarm_bytes`; 0 disables either
+    pub async fn run_maintenance(
+        &self,
+        compaction_interval: u64,
+        disk_alarm_bytes: u64,
+        mut shutdown: ShutdownSignal,
+    ) -> anyhow::Result<()> {
+        // A database we never compacted is counted from now, rather than compacted during startup
+        if self.get::<DateTime<Utc>>(CF_CONSENSUS_STATE, COMPACTION_KEY)?.is_none() {
+            self.put(CF_CONSENSUS_STATE, COMPACTION_KEY, &Utc::now())?;
+        }
+
+        let mut check = tokio::time::interval(CHECK_INTERVAL);
+        let mut alarmed = false;
+        loop {
+            tokio::select! {
+                _ = check.tick() => {}
+                _ = shutdown.recv() => return Ok(()),
+            }
+
+            if compaction_interval > 0 {
+                let last: Option<DateTime<Utc>> = self.get(CF_CONSENSUS_STATE, COMPACTION_KEY)?;
+                if last.is_none_or(|last| Utc::now() - last >= Duration::seconds(compaction_interval as i64)) {
+                    self.compact().await?;
+                }
+            }
+
+            if disk_alarm_bytes > 0 {
+                let disk_bytes = self.disk_usage()?;
+                if disk_bytes > disk_alarm_bytes && !alarmed {
+                    warn!("💾 Database takes {} MiB, over the alarm threshold of {} MiB",
+                        disk_bytes / (1024 * 1024), disk_alarm_bytes / (1024 * 1024));
+                    alarmed = true;
+                } else if disk_bytes <= disk_alarm_bytes && alarmed {
+                    info!("💾 Database is back under the alarm threshold ({} MiB)", disk_bytes / (1024 * 1024));
+                    alarmed = false;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,549 @@
+use super::{StorageManager, CF_PENDING, CF_TRANSACTIONS};
+use crate::clock::Clock;
+use crate::state::{Account, AccountId};
+use crate::shutdown::ShutdownSignal;
+use crate::types::{Block, BlockHash, ChainEvent, Transaction, TransactionKind};
+use super::{Result, StorageError};
+use anyhow::bail;
+use chrono::{DateTime, Utc};
+use rocksdb::WriteBatch;
+use serde::Serialize;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap, HashMap, VecDeque};
+use std::sync::{Arc, Mutex, MutexGuard};
+use tokio::sync::broadcast;
+use tracing::debug;
+
+// How far past an account's next nonce a transaction may be queued
+const MAX_NONCE_AHEAD: u64 = 64;
+// Mempool events buffered for subscribers; one that falls further behind is told it lagged
+pub const MEMPOOL_EVENT_CAPACITY: usize = 4096;
+
+// Changes to the pending pool, announced to subscribers as they are written
+#[derive(Debug, Clone)]
+pub enum MempoolEvent {
+    // Submitted, or back in the pool after its block was reorganized away
+    TxAdded(Transaction),
+    TxDropped { tx_id: [u8; 32], reason: DropReason },
+    // Left the pool for a block on our canonical chain
+    TxIncluded { tx_id: [u8; 32], block_hash: BlockHash, block_number: u64 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DropReason {
+    // Outbid in a full pool
+    Evicted,
+    // Can never be included, such as a nonce the account used up
+    Invalid,
+    // Past its valid_until
+    Expired,
+    // Spends more than its sender has once the transactions before it ran, such as a second
+    // spend of the same funds
+    Overdraw,
+}
+
+// Where an account's pending transactions stand against its nonce and balance
+#[derive(Debug, Clone)]
+pub struct NonceStatus {
+    // Nonce after the finalized block and the blocks built on it on our branch
+    pub confirmed_nonce: u64,
+    // Nonce the next transaction has to use to be included without waiting; pending transactions
+    // fill the nonces from confirmed_nonce up to it
+    pub next_nonce: u64,
+    // Pending transactions past a gap, waiting for the missing nonces
+    pub queued: Vec<u64>,
+    // Nonces missing between next_nonce and the last queued transaction
+    pub gaps: Vec<u64>,
+    // Finalized balance less what the blocks built on it on our branch spend. Funds they or
+    // pending transactions send to the account are not counted until they are finalized, as
+    // blocks only execute on the finalized state
+    pub confirmed_balance: u64,
+    // What each pending transaction costs, by nonce
+    pending_costs: BTreeMap<u64, u64>,
+}
+
+// Sender, nonce and cost of every pending transaction, kept in step with the pool by emit so
+// checking a submission does not read the whole pool
+#[derive(Default)]
+pub(super) struct PendingIndex {
+    senders: HashMap<[u8; 32], (AccountId, u64)>,
+    // Costs by sender, keyed by nonce and id
+    costs: HashMap<AccountId, BTreeMap<(u64, [u8; 32]), u64>>,
+}
+
+impl PendingIndex {
+    pub(super) fn new(pending: &[Transaction]) -> Mutex<Self> {
+        let mut index = Self::default();
+        for tx in pending {
+            index.insert(tx);
+        }
+        Mutex::new(index)
+    }
+
+    fn insert(&mut self, tx: &Transaction) {
+        self.senders.insert(tx.id, (tx.from, tx.nonce));
+        self.costs.entry(tx.from).or_default()
+            .insert((tx.nonce, tx.id), tx.total_cost().unwrap_or(u64::MAX));
+    }
+
+    fn remove(&mut self, tx_id: &[u8; 32]) {
+        let (sender, nonce) = match self.senders.remove(tx_id) {
+            Some(entry) => entry,
+            None => return,
+        };
+        if let Some(costs) = self.costs.get_mut(&sender) {
+            costs.remove(&(nonce, *tx_id));
+            if costs.is_empty() {
+                self.costs.remove(&sender);
+            }
+        }
+    }
+
+    fn apply(&mut self, event: &MempoolEvent) {
+        match event {
+            MempoolEvent::TxAdded(tx) => self.insert(tx),
+            MempoolEvent::TxDropped { tx_id, .. } | MempoolEvent::TxIncluded { tx_id, .. } => self.remove(tx_id),
+        }
+    }
+
+    // What the sender's pending transactions from `from_nonce` on cost, by nonce
+    fn costs(&self, sender: &AccountId, from_nonce: u64) -> BTreeMap<u64, u64> {
+        match self.costs.get(sender) {
+            Some(costs) => costs.range((from_nonce, [0; 32])..)
+                .map(|((nonce, _), cost)| (*nonce, *cost))
+                .collect(),
+            None => BTreeMap::new(),
+        }
+    }
+}
+
+impl NonceStatus {
+    fn new(confirmed_nonce: u64, confirmed_balance: u64, pending_costs: BTreeMap<u64, u64>) -> Self {
+        let mut next_nonce = confirmed_nonce;
+        while pending_costs.contains_key(&next_nonce) {
+            next_nonce += 1;
+        }
+        let queued: Vec<u64> = pending_costs.keys().copied().filter(|nonce| *nonce > next_nonce).collect();
+        let gaps = match queued.last() {
+            Some(last) => (next_nonce..*last).filter(|nonce| !queued.contains(nonce)).collect(),
+            None => Vec::new(),
+        };
+
+        Self { confirmed_nonce, next_nonce, queued, gaps, confirmed_balance, pending_costs }
+    }
+
+    // Ok(true) when the transaction can be included right away, Ok(false) when it waits on a gap.
+    // Nonces taken by executed or pending transactions are replays, and a transaction the
+    // balance left after the sender's pending transactions before it can not pay for would be
+    // a second spend of the same funds
+    pub fn check(&self, tx: &Transaction) -> anyhow::Result<bool> {
+        let nonce = tx.nonce;
+        if nonce < self.next_nonce || self.queued.contains(&nonce) {
+            bail!("nonce {} is already used, the account's next nonce is {}", nonce, self.next_nonce);
+        }
+        if nonce > self.next_nonce + MAX_NONCE_AHEAD {
+            bail!("nonce {} is more than {} past the account's next nonce {}", nonce, MAX_NONCE_AHEAD, self.next_nonce);
+        }
+
+        let cost = tx.total_cost().unwrap_or(u64::MAX);
+        let spent = self.pending_costs.range(..nonce).fold(0u64, |spent, (_, cost)| spent.saturating_add(*cost));
+        let available = self.confirmed_balance.saturating_sub(spent);
+        if available < cost {
+            bail!("balance {} leaves {} after the account's pending transactions, below the {} the transaction costs",
+                self.confirmed_balance, available, cost);
+        }
+        Ok(nonce == self.next_nonce)
+    }
+
+    // Counts a transaction just admitted as pending, for checking the sender's next ones
+    pub fn record(&mut self, tx: &Transaction) {
+        let mut pending_costs = std::mem::take(&mut self.pending_costs);
+        pending_costs.insert(tx.nonce, tx.total_cost().unwrap_or(u64::MAX));
+        *self = Self::new(self.confirmed_nonce, self.confirmed_balance, pending_costs);
+    }
+}
+
+impl StorageManager {
+    pub async fn get_nonce_status(&self, account: &AccountId) -> Result<NonceStatus> {
+        let Account { mut balance, nonce: mut confirmed_nonce } = self.get_account(account).await?.unwrap_or_default();
+
+        // Blocks above the finalized one executed their transactions already, and took them out
+        // of the pool
+        let start = self.get_finalized_block().await?.map_or(0, |block| block.header.block_number + 1);
+        if let Some(latest) = self.get_latest_block().await? {
+            for block in self.get_block_range(start, latest.header.block_number).await? {
+                for tx in block.transactions.iter().filter(|tx| tx.from == *account) {
+                    confirmed_nonce = confirmed_nonce.max(tx.nonce + 1);
+                    balance = balance.saturating_sub(tx.total_cost().unwrap_or(u64::MAX));
+                }
+            }
+        }
+
+        let pending_costs = self.pending_index().costs(account, confirmed_nonce);
+        Ok(NonceStatus::new(confirmed_nonce, balance, pending_costs))
+    }
+
+    // Drops pending transactions whose nonces the finalized accounts used up, such as replays of
+    // transactions a block just executed
+    pub async fn drop_stale_transactions(&self) -> Result<()> {
+        let mut nonces = HashMap::new();
+        let mut stale = Vec::new();
+        for tx in self.get_pending_transactions().await? {
+            let nonce = match nonces.get(&tx.from) {
+                Some(nonce) => *nonce,
+                None => {
+                    let nonce = self.get_account(&tx.from).await?.map_or(0, |account| account.nonce);
+                    *nonces.entry(tx.from).or_insert(nonce)
+                }
+            };
+            if tx.nonce < nonce {
+                stale.push(tx.id);
+            }
+        }
+
+        if !stale.is_empty() {
+            debug!("Dropping {} pending transactions with used nonces", stale.len());
+            self.remove_pending_transactions(&stale, DropReason::Invalid).await?;
+        }
+        Ok(())
+    }
+
+    // Drops pending transactions that can not go into a block with this number and timestamp
+    pub async fn drop_expired_transactions(&self, block_number: u64, timestamp: &DateTime<Utc>) -> Result<()> {
+        let expired: Vec<_> = self.get_pending_transactions().await?.into_iter()
+            .filter(|tx| tx.is_expired(block_number, timestamp))
+            .map(|tx| tx.id)
+            .collect();
+
+        if !expired.is_empty() {
+            debug!("Dropping {} expired pending transactions", expired.len());
+            self.remove_pending_transactions(&expired, DropReason::Expired).await?;
+        }
+        Ok(())
+    }
+
+    // Clears the pool of what each finalized block made stale or expired, as blocks finalize.
+    // Expiry is judged by the node's clock, like the blocks that would include the transactions
+    pub async fn run_mempool_pruner(
+        &self,
+        clock: Arc<dyn Clock>,
+        mut events: broadcast::Receiver<ChainEvent>,
+        mut shutdown: ShutdownSignal,
+    ) -> anyhow::Result<()> {
+        loop {
+            let block_number = tokio::select! {
+                event = events.recv() => match event {
+                    Ok(ChainEvent::BlockFinalized(finalized)) => finalized.block_number,
+                    Ok(_) => continue,
+                    // The pool is checked against the latest finalized state either way
+                    Err(broadcast::error::RecvError::Lagged(_)) => match self.get_finalized_block().await? {
+                        Some(block) => block.header.block_number,
+                        None => continue,
+                    },
+                    Err(broadcast::error::RecvError::Closed) => return Ok(()),
+                },
+                _ = shutdown.recv() => return Ok(()),
+            };
+
+            self.drop_stale_transactions().await?;
+            self.drop_expired_transactions(block_number + 1, &clock.now()).await?;
+        }
+    }
+
+    // Takes a block's transactions out of the pool as it joins our canonical chain
+    pub async fn remove_included_transactions(&self, block: &Block) -> Result<()> {
+        let cf = self.cf(CF_PENDING)?;
+        let block_hash = block.hash();
+        let mut batch = WriteBatch::default();
+        let mut included = Vec::new();
+        for tx in &block.transactions {
+            if self.db.get_cf(cf, tx.id)?.is_some() {
+                batch.delete_cf(cf, tx.id);
+                included.push(tx.id);
+            }
+        }
+        self.db.write(batch)?;
+
+        for tx_id in included {
+            self.emit(MempoolEvent::TxIncluded { tx_id, block_hash, block_number: block.header.block_number });
+        }
+        Ok(())
+    }
+
+    // The pool outlives restarts in its own column; this checks it against the chain when the
+    // node starts. Transactions a block executed, whose nonces were used up or that fail `check`
+    // now, such as after they expired or the minimum fee was raised, leave the pool. A pool over
+    // `capacity` sheds the transactions a block would take last. Returns how many are kept
+    pub async fn revalidate_pending(
+        &self,
+        capacity: usize,
+        check: impl Fn(&Transaction) -> std::result::Result<(), String>,
+    ) -> Result<usize> {
+        let mut included = Vec::new();
+        let mut invalid = Vec::new();
+        let mut kept = Vec::new();
+        for tx in self.get_pending_transactions().await? {
+            if let Some(receipt) = self.get_receipt(&tx.id).await? {
+                included.push(receipt);
+                continue;
+            }
+            let nonce = self.get_account(&tx.from).await?.unwrap_or_default().nonce;
+            if tx.nonce < nonce {
+                debug!("Dropping pending transaction {}: nonce {} is used up", hex::encode(tx.id), tx.nonce);
+                invalid.push(tx.id);
+                continue;
+            }
+            match check(&tx) {
+                Ok(()) => kept.push(tx),
+                Err(e) => {
+                    debug!("Dropping pending transaction {}: {}", hex::encode(tx.id), e);
+                    invalid.push(tx.id);
+                }
+            }
+        }
+
+        let cf = self.cf(CF_PENDING)?;
+        let mut batch = WriteBatch::default();
+        for receipt in &included {
+            batch.delete_cf(cf, receipt.tx_id);
+        }
+        self.db.write(batch)?;
+        for receipt in included {
+            self.emit(MempoolEvent::TxIncluded {
+                tx_id: receipt.tx_id,
+                block_hash: receipt.block_hash,
+                block_number: receipt.block_number,
+            });
+        }
+        self.remove_pending_transactions(&invalid, DropReason::Invalid).await?;
+
+        // Forgotten entirely like those make_room evicts
+        let mut kept = order_by_fee(kept);
+        let evicted = kept.split_off(kept.len().min(capacity));
+        let mut batch = WriteBatch::default();
+        for tx in &evicted {
+            batch.delete_cf(cf, tx.id);
+            batch.delete_cf(self.cf(CF_TRANSACTIONS)?, tx.id);
+        }
+        self.db.write(batch)?;
+        for tx in evicted {
+            self.emit(MempoolEvent::TxDropped { tx_id: tx.id, reason: DropReason::Evicted });
+        }
+        Ok(kept.len())
+    }
+
+    // Pending transactions in the order a block takes them, see order_by_fee
+    pub async fn get_pending_by_fee(&self) -> Result<Vec<Transaction>> {
+        Ok(order_by_fee(self.get_pending_transactions().await?))
+    }
+
+    // Events for every change to the pending pool from now on
+    pub fn subscribe_mempool(&self) -> broadcast::Receiver<MempoolEvent> {
+        self.mempool_events.subscribe()
+    }
+
+    // Nobody listening is not an error
+    pub(super) fn emit(&self, event: MempoolEvent) {
+        self.pending_index().apply(&event);
+        let _ = self.mempool_events.send(event);
+    }
+
+    fn pending_index(&self) -> MutexGuard<'_, PendingIndex> {
+        match self.pending_index.lock() {
+            Ok(index) => index,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    // Called before adding `incoming` to a pool that may hold `capacity` transactions. A full
+    // pool drops its cheapest transaction that is last in its account's nonce order, so no
+    // other pending transaction is left behind a gap, but only for a higher fee. The sender's
+    // own transactions are never dropped for it
+    pub async fn make_room(&self, incoming: &Transaction, capacity: usize) -> Result<()> {
+        let pending = self.get_pending_transactions().await?;
+        if pending.len() < capacity {
+            return Ok(());
+        }
+
+        let mut last: BTreeMap<AccountId, &Transaction> = BTreeMap::new();
+        for tx in pending.iter().filter(|tx| tx.from != incoming.from) {
+            match last.get(&tx.from) {
+                Some(other) if other.nonce > tx.nonce => {}
+                _ => {
+                    last.insert(tx.from, tx);
+                }
+            }
+        }
+        let cheapest = last.into_values().min_by_key(|tx| (tx.fee, Reverse(tx.id)));
+        let evicted = match cheapest {
+            Some(tx) if tx.fee < incoming.fee => tx,
+            Some(tx) => {
+                return Err(StorageError::Rejected(format!(
+                    "mempool is full, a fee above {} is needed", tx.fee)));
+            }
+            None => return Err(StorageError::Rejected("mempool is full".to_string())),
+        };
+
+        // Forgotten entirely, so it can be submitted again later
+        let mut batch = WriteBatch::default();
+        batch.delete_cf(self.cf(CF_PENDING)?, evicted.id);
+        batch.delete_cf(self.cf(CF_TRANSACTIONS)?, evicted.id);
+        self.db.write(batch)?;
+        self.emit(MempoolEvent::TxDropped { tx_id: evicted.id, reason: DropReason::Evicted });
+
+        debug!("Evicted transaction {} with fee {} from the full mempool", hex::encode(evicted.id), evicted.fee);
+        Ok(())
+    }
+}
+
+// Highest fee first, except that an account's transactions stay in nonce order and staking
+// transactions go before transfers; the order a block takes pending transactions in
+pub fn order_by_fee(transactions: impl IntoIterator<Item = Transaction>) -> Vec<Transaction> {
+    let mut by_sender: BTreeMap<AccountId, Vec<Transaction>> = BTreeMap::new();
+    for tx in transactions {
+        by_sender.entry(tx.from).or_default().push(tx);
+    }
+    let mut queues: Vec<VecDeque<Transaction>> = by_sender.into_values()
+        .map(|mut txs| {
+            txs.sort_by_key(|tx| tx.nonce);
+            txs.into()
+        })
+        .collect();
+
+    // Only the next transaction of every account competes on fee
+    let priority = |tx: &Transaction| (!matches!(tx.kind, TransactionKind::Transfer), tx.fee, Reverse(tx.id));
+    let mut heads: BinaryHeap<_> = queues.iter().enumerate()
+        .filter_map(|(index, queue)| queue.front().map(|tx| (priority(tx), index)))
+        .collect();
+    let mut ordered = Vec::new();
+    while let Some((_, index)) = heads.pop() {
+        if let Some(tx) = queues[index].pop_front() {
+            ordered.push(tx);
+        }
+        if let Some(next) = queues[index].front() {
+            heads.push((priority(next), index));
+        }
+    }
+    ordered
+}
+
+// The pending pool kept in memory and brought up to date from mempool events, so a reader
+// such as the proposer does not scan the pool in storage each time. A subscriber that lagged
+// behind reloads the pool once and carries on with the events after it
+pub struct MempoolView {
+    storage: StorageManager,
+    events: broadcast::Receiver<MempoolEvent>,
+    pending: HashMap<[u8; 32], Transaction>,
+    loaded: bool,
+}
+
+impl MempoolView {
+    // Subscribes right away; the pool itself is read on the first refresh
+    pub fn new(storage: StorageManager) -> Self {
+        Self {
+            events: storage.subscribe_mempool(),
+            storage,
+            pending: HashMap::new(),
+            loaded: false,
+        }
+    }
+
+    pub async fn refresh(&mut self) -> Result<()> {
+        loop {
+            if !self.loaded {
+                let pending = self.storage.get_pending_transactions().await?;
+                self.pending = pending.into_iter().map(|tx| (tx.id, tx)).collect();
+                self.loaded = true;
+            }
+            match self.events.try_recv() {
+                Ok(MempoolEvent::TxAdded(tx)) => {
+                    self.pending.insert(tx.id, tx);
+                }
+                Ok(MempoolEvent::TxDropped { tx_id, .. } | MempoolEvent::TxIncluded { tx_id, .. }) => {
+                    self.pending.remove(&tx_id);
+                }
+                Err(broadcast::error::TryRecvError::Lagged(missed)) => {
+                    debug!("Mempool view missed {} events, reloading the pool", missed);
+                    self.loaded = false;
+                }
+                Err(broadcast::error::TryRecvError::Empty | broadcast::error::TryRecvError::Closed) => return Ok(()),
+            }
+        }
+    }
+
+    pub fn transactions(&self) -> impl Iterator<Item = &Transaction> {
+        self.pending.values()
+    }
+
+    pub fn by_fee(&self) -> Vec<Transaction> {
+        order_by_fee(self.pending.values().cloned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::DEFAULT_CHAIN_ID;
+
+    fn transfer(nonce: u64, amount: u64) -> Transaction {
+        Transaction {
+            id: [nonce as u8; 32],
+            from: [1; 32],
+            to: [2; 32],
+            amount,
+            fee: 1,
+            nonce,
+            chain_id: DEFAULT_CHAIN_ID,
+            timestamp: DateTime::from_timestamp(1_700_000_000, 0).unwrap(),
+            kind: TransactionKind::Transfer,
+            valid_until: None,
+            signature: vec![0; 64],
+        }
+    }
+
+    // Nonce 5 is confirmed; 5 and 6 are pending at a cost of 10 each, 8 waits on the gap at 7
+    fn status(balance: u64) -> NonceStatus {
+        NonceStatus::new(5, balance, BTreeMap::from([(5, 10), (6, 10), (8, 10)]))
+    }
+
+    #[test]
+    fn pending_transactions_fill_the_nonces_up_to_a_gap() {
+        let status = status(100);
+        assert_eq!((status.next_nonce, status.queued.clone(), status.gaps.clone()), (7, vec![8], vec![7]));
+
+        assert!(status.check(&transfer(7, 9)).unwrap());
+        assert!(!status.check(&transfer(9, 9)).unwrap());
+        // Replays of confirmed, pending and queued nonces
+        for nonce in [4, 5, 6, 8] {
+            assert!(status.check(&transfer(nonce, 9)).is_err());
+        }
+        assert!(status.check(&transfer(7 + MAX_NONCE_AHEAD, 9)).is_ok());
+        assert!(status.check(&transfer(8 + MAX_NONCE_AHEAD, 9)).is_err());
+    }
+
+    #[test]
+    fn transactions_are_paid_from_what_earlier_pending_ones_leave() {
+        // 20 of the 35 go to nonces 5 and 6, which leaves 15 for nonce 7
+        let status = status(35);
+        assert!(status.check(&transfer(7, 14)).is_ok());
+        assert!(status.check(&transfer(7, 15)).is_err());
+        // Nonce 8 is pending at nonces below 9 too
+        assert!(status.check(&transfer(9, 4)).is_ok());
+        assert!(status.check(&transfer(9, 5)).is_err());
+        // Costs that overflow never fit
+        assert!(status.check(&transfer(7, u64::MAX)).is_err());
+        assert!(NonceStatus::new(7, 0, BTreeMap::new()).check(&transfer(7, 0)).is_err());
+    }
+
+    #[test]
+    fn recorded_transactions_count_against_the_next_ones() {
+        let mut recorded = status(45);
+        recorded.record(&transfer(7, 9));
+        assert_eq!((recorded.next_nonce, recorded.gaps.clone()), (9, Vec::new()));
+        assert!(recorded.check(&transfer(7, 9)).is_err());
+        // Nonces 5 to 8 take 40 of the 45
+        assert!(recorded.check(&transfer(9, 4)).is_ok());
+        assert!(recorded.check(&transfer(9, 5)).is_err());
+    }
+}
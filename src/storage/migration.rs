@@ -0,0 +1,231 @@
+use super::{StorageManager, CF_BLOCKS, CF_BLOCK_TREE, CF_CONSENSUS_STATE, CF_PENDING, CF_TRANSACTIONS};
+use super::{CONSENSUS_STATE_KEY, FINALIZED_BLOCK_KEY};
+use crate::staking::{PendingStakeChange, SlashRecord};
+use crate::types::{Block, ConsensusParams, ConsensusState, NodeId, Transaction, TransactionKind, ValidatorInfo, DEFAULT_CHAIN_ID};
+use super::{Result, StorageError};
+use chrono::{DateTime, Utc};
+use rocksdb::{IteratorMode, WriteBatch};
+use serde::Deserialize;
+use std::collections::HashMap;
+use tracing::info;
+
+// Bumped whenever a stored encoding changes; databases without a version are schema 0
+pub const SCHEMA_VERSION: u32 = 9;
+const SCHEMA_VERSION_KEY: &[u8] = b"schema_version";
+
+// Transaction layout before fees and chain ids (schema 0)
+#[derive(Deserialize)]
+struct LegacyTransaction {
+    id: [u8; 32],
+    from: [u8; 32],
+    to: [u8; 32],
+    amount: u64,
+    nonce: u64,
+    timestamp: DateTime<Utc>,
+    kind: TransactionKind,
+    signature: Vec<u8>,
+}
+
+impl From<LegacyTransaction> for Transaction {
+    fn from(legacy: LegacyTransaction) -> Self {
+        Self {
+            id: legacy.id,
+            from: legacy.from,
+            to: legacy.to,
+            amount: legacy.amount,
+            fee: 0,
+            nonce: legacy.nonce,
+            chain_id: DEFAULT_CHAIN_ID,
+            timestamp: legacy.timestamp,
+            kind: legacy.kind,
+            valid_until: None,
+            signature: legacy.signature,
+        }
+    }
+}
+
+// Consensus state with only the head block (schema 6)
+#[derive(Deserialize)]
+struct LegacyConsensusState {
+    current_block: u64,
+    validators: HashMap<NodeId, ValidatorInfo>,
+    total_stake: u64,
+    epoch: u64,
+    pending_stake_changes: Vec<PendingStakeChange>,
+    slashed: Vec<SlashRecord>,
+}
+
+impl LegacyConsensusState {
+    fn upgrade(self, finalized: u64) -> UnparameterizedConsensusState {
+        UnparameterizedConsensusState {
+            head: self.current_block.max(finalized),
+            justified: finalized,
+            finalized,
+            validators: self.validators,
+            total_stake: self.total_stake,
+            epoch: self.epoch,
+            pending_stake_changes: self.pending_stake_changes,
+            slashed: self.slashed,
+        }
+    }
+}
+
+// Consensus state before on-chain parameters (schemas 7 and 8)
+#[derive(Deserialize)]
+struct UnparameterizedConsensusState {
+    head: u64,
+    justified: u64,
+    finalized: u64,
+    validators: HashMap<NodeId, ValidatorInfo>,
+    total_stake: u64,
+    epoch: u64,
+    pending_stake_changes: Vec<PendingStakeChange>,
+    slashed: Vec<SlashRecord>,
+}
+
+// Those chains ran on the block time nodes were configured with, which the consensus engine
+// fills in; everything else was the defaults
+impl From<UnparameterizedConsensusState> for ConsensusState {
+    fn from(legacy: UnparameterizedConsensusState) -> Self {
+        Self {
+            head: legacy.head,
+            justified: legacy.justified,
+            finalized: legacy.finalized,
+            validators: legacy.validators,
+            total_stake: legacy.total_stake,
+            epoch: legacy.epoch,
+            pending_stake_changes: legacy.pending_stake_changes,
+            slashed: legacy.slashed,
+            params: ConsensusParams { block_time: 0, ..ConsensusParams::default() },
+            proposals: Vec::new(),
+        }
+    }
+}
+
+impl StorageManager {
+    pub(super) fn migrate(&self) -> Result<()> {
+        let version = match self.get::<u32>(CF_CONSENSUS_STATE, SCHEMA_VERSION_KEY)? {
+            Some(version) => version,
+            None if self.is_empty()? => SCHEMA_VERSION,
+            None => 0,
+        };
+
+        if version > SCHEMA_VERSION {
+            return Err(StorageError::Schema(format!(
+                "schema {} is newer than this node supports ({})", version, SCHEMA_VERSION)));
+        }
+        if version == 0 {
+            self.migrate_transactions()?;
+        }
+        // Blocks now carry the parent votes their rewards go to, which headers commit to, so
+        // stored blocks can not be rewritten either (schema 1)
+        if version == 1 && self.db.iterator_cf(self.cf(CF_BLOCKS)?, IteratorMode::Start).next().is_some() {
+            return Err(StorageError::Schema(
+                "blocks from before block rewards; remove it and sync the chain again".to_string()));
+        }
+        // State roots are now sparse merkle tree roots, which headers commit to (schema 2)
+        if version == 2 && self.db.iterator_cf(self.cf(CF_BLOCKS)?, IteratorMode::Start).next().is_some() {
+            return Err(StorageError::Schema(
+                "blocks from before the state trie; remove it and sync the chain again".to_string()));
+        }
+        // Proofs name a circuit version instead of carrying a key, and proposer signatures
+        // cover them (schema 3)
+        if version == 3 && self.db.iterator_cf(self.cf(CF_BLOCKS)?, IteratorMode::Start).next().is_some() {
+            return Err(StorageError::Schema(
+                "blocks from before circuit versions; remove it and sync the chain again".to_string()));
+        }
+        // Transactions may carry an expiry, which their encoding and signatures cover (schema 4)
+        if version == 4 && self.db.iterator_cf(self.cf(CF_BLOCKS)?, IteratorMode::Start).next().is_some() {
+            return Err(StorageError::Schema(
+                "blocks from before transaction expiry; remove it and sync the chain again".to_string()));
+        }
+        // Validators carry liveness counters and transactions may unjail one (schema 5)
+        if version == 5 && self.db.iterator_cf(self.cf(CF_BLOCKS)?, IteratorMode::Start).next().is_some() {
+            return Err(StorageError::Schema(
+                "blocks from before validator jailing; remove it and sync the chain again".to_string()));
+        }
+        // Consensus states tell the justified and finalized blocks apart from the head (schema 6),
+        // and carry the consensus parameters and governance proposals (schema 8)
+        if (6..=8).contains(&version) {
+            self.migrate_consensus_states(version)?;
+        }
+        // Account history is also kept by account, for balances at past blocks (schema 7)
+        if version == 6 || version == 7 {
+            let indexed = self.index_account_history()?;
+            info!("🗄️ Indexed {} account history entries by account", indexed);
+        }
+
+        self.put(CF_CONSENSUS_STATE, SCHEMA_VERSION_KEY, &SCHEMA_VERSION)
+    }
+
+    fn is_empty(&self) -> Result<bool> {
+        for cf in [CF_BLOCKS, CF_TRANSACTIONS, CF_CONSENSUS_STATE] {
+            if self.db.iterator_cf(self.cf(cf)?, IteratorMode::Start).next().is_some() {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    // Looked-up transactions are rewritten with no fee. Blocks can not be: their merkle roots
+    // commit to the old encoding, so a chain from before fees has to be synced again. Pending
+    // transactions are dropped since their signatures do not cover the chain id.
+    fn migrate_transactions(&self) -> Result<()> {
+        if self.db.iterator_cf(self.cf(CF_BLOCKS)?, IteratorMode::Start).next().is_some() {
+            return Err(StorageError::Schema(
+                "blocks from before transaction fees; remove it and sync the chain again".to_string()));
+        }
+
+        let mut batch = WriteBatch::default();
+        let mut migrated = 0;
+        for item in self.db.iterator_cf(self.cf(CF_TRANSACTIONS)?, IteratorMode::Start) {
+            let (key, value) = item?;
+            let transaction: Transaction = bincode::deserialize::<LegacyTransaction>(&value)?.into();
+            batch.put_cf(self.cf(CF_TRANSACTIONS)?, key, bincode::serialize(&transaction)?);
+            migrated += 1;
+        }
+
+        let mut dropped = 0;
+        for item in self.db.iterator_cf(self.cf(CF_PENDING)?, IteratorMode::Start) {
+            let (key, _) = item?;
+            batch.delete_cf(self.cf(CF_PENDING)?, key);
+            dropped += 1;
+        }
+
+        self.db.write(batch)?;
+        info!("🗄️ Migrated {} stored transactions to schema {}, dropped {} pending", migrated, SCHEMA_VERSION, dropped);
+        Ok(())
+    }
+
+    // For schema 6, the current state is final up to the finalized block; states kept per
+    // finalized block are final up to theirs. Nothing records which heights had a prevote
+    // quorum, so the justified block starts out as the finalized one
+    fn migrate_consensus_states(&self, version: u32) -> Result<()> {
+        let finalized = match self.db.get_cf(self.cf(CF_CONSENSUS_STATE)?, FINALIZED_BLOCK_KEY)? {
+            Some(hash) => self.get::<Block>(CF_BLOCK_TREE, &hash)?.map_or(0, |block| block.header.block_number),
+            None => 0,
+        };
+
+        let mut batch = WriteBatch::default();
+        let mut migrated = 0;
+        for item in self.db.iterator_cf(self.cf(CF_CONSENSUS_STATE)?, IteratorMode::Start) {
+            let (key, value) = item?;
+            let finalized = match key.as_ref() {
+                CONSENSUS_STATE_KEY => finalized,
+                // Per-block states are keyed by the big-endian block number
+                block_key if block_key.len() == 8 => u64::from_be_bytes(block_key.try_into().unwrap()),
+                _ => continue,
+            };
+            let state: ConsensusState = match version {
+                6 => bincode::deserialize::<LegacyConsensusState>(&value)?.upgrade(finalized).into(),
+                _ => bincode::deserialize::<UnparameterizedConsensusState>(&value)?.into(),
+            };
+            batch.put_cf(self.cf(CF_CONSENSUS_STATE)?, key, bincode::serialize(&state)?);
+            migrated += 1;
+        }
+
+        self.db.write(batch)?;
+        info!("🗄️ Migrated {} stored consensus states to schema {}", migrated, SCHEMA_VERSION);
+        Ok(())
+    }
+}
@@ -1,195 +1,674 @@
-use crate::types::{Block, BlockVote, Transaction, ConsensusState};
-use anyhow::Result;
-use serde::{Serialize, Deserialize};
-use tracing::{info, debug, error};
-use std::sync::Arc;
-use tokio::sync::RwLock;
-use std::collections::HashMap;
+use crate::types::{Block, BlockFinalized, BlockHash, FinalityCertificate, BlockVote, Transaction, TransactionReceipt, ConsensusState, EpochChange, EpochStats, RoundCheckpoint, ZKProof};
+use crate::config::StorageConfig;
+use crate::state::{Account, AccountId};
+use crate::snapshot::{self, BackupEntry, Snapshot, SnapshotTrust};
+use crate::zk_proof::ZKProofGenerator;
+use crate::consensus::SafetyAlarm;
+use serde::{Serialize, de::DeserializeOwned};
+use tracing::{info, debug};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast;
+use rocksdb::{ColumnFamily, ColumnFamilyDescriptor, Direction, IteratorMode, Options, WriteBatch, WriteOptions, DB};
+
+mod account_history;
+mod error;
+mod migration;
+mod history;
+mod maintenance;
+mod mempool;
+mod peers;
+mod pruning;
+
+pub use account_history::StateAt;
+pub use error::StorageError;
+pub use history::{AddressHistory, HistoryPage, MAX_HISTORY_PAGE};
+pub use mempool::{order_by_fee, DropReason, MempoolEvent, MempoolView, NonceStatus};
+use mempool::PendingIndex;
+pub use peers::KnownPeer;
+pub use pruning::{MIN_KEEP_BLOCKS, PRUNING_MODES};
+
+// Column families
+const CF_BLOCKS: &str = "blocks";
+const CF_BLOCK_HASHES: &str = "block_hashes";
+const CF_BLOCK_TREE: &str = "block_tree";
+const CF_BLOCK_CHILDREN: &str = "block_children";
+const CF_VOTES: &str = "votes";
+const CF_TRANSACTIONS: &str = "transactions";
+const CF_PENDING: &str = "pending_transactions";
+const CF_CONSENSUS_STATE: &str = "consensus_state";
+const CF_ACCOUNTS: &str = "accounts";
+const CF_EPOCHS: &str = "epochs";
+const CF_EPOCH_STATS: &str = "epoch_stats";
+const CF_FINALITY: &str = "finality";
+const CF_RECEIPTS: &str = "receipts";
+const CF_PROOFS: &str = "proofs";
+const CF_ADDRESS_TXS: &str = "address_transactions";
+const CF_PEERS: &str = "peers";
+const CF_ACCOUNT_HISTORY: &str = "account_history";
+const CF_CERTIFICATES: &str = "finality_certificates";
+const CF_ACCOUNT_VERSIONS: &str = "account_versions";
+
+const COLUMN_FAMILIES: [&str; 19] = [
+    CF_BLOCKS,
+    CF_BLOCK_HASHES,
+    CF_BLOCK_TREE,
+    CF_BLOCK_CHILDREN,
+    CF_VOTES,
+    CF_TRANSACTIONS,
+    CF_PENDING,
+    CF_CONSENSUS_STATE,
+    CF_ACCOUNTS,
+    CF_EPOCHS,
+    CF_EPOCH_STATS,
+    CF_FINALITY,
+    CF_RECEIPTS,
+    CF_PROOFS,
+    CF_ADDRESS_TXS,
+    CF_PEERS,
+    CF_ACCOUNT_HISTORY,
+    CF_CERTIFICATES,
+    CF_ACCOUNT_VERSIONS,
+];
+
+const CONSENSUS_STATE_KEY: &[u8] = b"current";
+const FINALIZED_BLOCK_KEY: &[u8] = b"finalized";
+const CHAIN_PROOF_KEY: &[u8] = b"chain_proof";
+const ROUND_CHECKPOINT_KEY: &[u8] = b"round";
+const SAFETY_ALARM_KEY: &[u8] = b"safety_alarm";
+// Followed by a block hash; longer than the block number keys, which migrations go by
+const STATE_BEFORE_PREFIX: &[u8] = b"before";
+
+type Result<T, E = StorageError> = std::result::Result<T, E>;
+
+// Result of switching the canonical chain to another branch
+#[derive(Debug, Clone)]
+pub struct Reorg {
+    pub common_ancestor: BlockHash,
+    pub removed: Vec<Block>,
+    pub added: Vec<Block>,
+}
 
 pub struct StorageManager {
-    blocks: Arc<RwLock<HashMap<u64, Block>>>,
-    votes: Arc<RwLock<HashMap<String, BlockVote>>>,
-    transactions: Arc<RwLock<HashMap<String, Transaction>>>,
-    pending_transactions: Arc<RwLock<Vec<Transaction>>>,
-    consensus_state: Arc<RwLock<Option<ConsensusState>>>,
+    db: Arc<DB>,
+    // Shared by clones, so every writer to the pending pool announces on the same channel
+    mempool_events: broadcast::Sender<MempoolEvent>,
+    pending_index: Arc<Mutex<PendingIndex>>,
 }
 
 impl StorageManager {
-    pub fn new(_db_path: &str) -> Result<Self> {
-        info!("Initializing Storage Manager (Mock Implementation)");
-        
-        Ok(Self {
-            blocks: Arc::new(RwLock::new(HashMap::new())),
-            votes: Arc::new(RwLock::new(HashMap::new())),
-            transactions: Arc::new(RwLock::new(HashMap::new())),
-            pending_transactions: Arc::new(RwLock::new(Vec::new())),
-            consensus_state: Arc::new(RwLock::new(None)),
-        })
-    }
-    
+    pub fn new(config: &StorageConfig) -> Result<Self> {
+        let db_path = &config.db_path;
+        info!("Initializing Storage Manager (RocksDB at {})", db_path);
+
+        let mut options = Options::default();
+        options.create_if_missing(true);
+        options.create_missing_column_families(true);
+        options.set_max_open_files(config.max_open_files);
+
+        let descriptors = COLUMN_FAMILIES.iter()
+            .map(|name| ColumnFamilyDescriptor::new(*name, Options::default()));
+        let db = DB::open_cf_descriptors(&options, db_path, descriptors)?;
+
+        let mut storage = Self {
+            db: Arc::new(db),
+            mempool_events: broadcast::channel(mempool::MEMPOOL_EVENT_CAPACITY).0,
+            pending_index: Arc::default(),
+        };
+        storage.migrate()?;
+        // After migrating, which may clear the pool
+        storage.pending_index = Arc::new(PendingIndex::new(&storage.read_pending()?));
+        Ok(storage)
+    }
+
+    fn cf(&self, name: &str) -> Result<&ColumnFamily> {
+        self.db.cf_handle(name)
+            .ok_or_else(|| StorageError::Corrupted(format!("missing column family {}", name)))
+    }
+
+    fn get<T: DeserializeOwned>(&self, cf: &str, key: &[u8]) -> Result<Option<T>> {
+        match self.db.get_cf(self.cf(cf)?, key)? {
+            Some(bytes) => Ok(Some(bincode::deserialize(&bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn put<T: Serialize>(&self, cf: &str, key: &[u8], value: &T) -> Result<()> {
+        self.db.put_cf(self.cf(cf)?, key, bincode::serialize(value)?)?;
+        Ok(())
+    }
+
+    fn count_keys(&self, cf: &str) -> Result<u64> {
+        let mut count = 0;
+        for item in self.db.iterator_cf(self.cf(cf)?, IteratorMode::Start) {
+            item?;
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    // Heights are stored big-endian so key order matches block order
+    fn block_key(block_number: u64) -> [u8; 8] {
+        block_number.to_be_bytes()
+    }
+
+    fn state_before_key(block_hash: &BlockHash) -> Vec<u8> {
+        [STATE_BEFORE_PREFIX, block_hash].concat()
+    }
+
+    fn account_id(key: &[u8]) -> Result<AccountId> {
+        key.try_into()
+            .map_err(|_| StorageError::Corrupted(format!("malformed account key of {} bytes", key.len())))
+    }
+
+    // Prefixed by block hash; a validator has one vote per round and step
+    fn vote_key(vote: &BlockVote) -> Vec<u8> {
+        let mut key = Vec::with_capacity(73);
+        key.extend_from_slice(&vote.block_hash);
+        key.extend_from_slice(&vote.validator);
+        key.extend_from_slice(&vote.round.to_be_bytes());
+        key.push(vote.step as u8);
+        key
+    }
+
+    // Children are keyed by parent hash ++ child hash so a prefix scan lists a block's children
+    fn child_key(parent_hash: &BlockHash, child_hash: &BlockHash) -> Vec<u8> {
+        let mut key = Vec::with_capacity(64);
+        key.extend_from_slice(parent_hash);
+        key.extend_from_slice(child_hash);
+        key
+    }
+
+    fn add_block_to_batch(&self, batch: &mut WriteBatch, block: &Block) -> Result<()> {
+        let key = Self::block_key(block.header.block_number);
+        batch.put_cf(self.cf(CF_BLOCKS)?, key, bincode::serialize(block)?);
+        batch.put_cf(self.cf(CF_BLOCK_HASHES)?, block.hash(), key);
+        self.add_block_to_tree_batch(batch, block)
+    }
+
+    fn add_block_to_tree_batch(&self, batch: &mut WriteBatch, block: &Block) -> Result<()> {
+        let hash = block.hash();
+        batch.put_cf(self.cf(CF_BLOCK_TREE)?, hash, bincode::serialize(block)?);
+        batch.put_cf(self.cf(CF_BLOCK_CHILDREN)?, Self::child_key(&block.header.parent_hash, &hash), []);
+        Ok(())
+    }
+
+    fn add_transaction_to_batch(&self, batch: &mut WriteBatch, transaction: &Transaction) -> Result<()> {
+        let value = bincode::serialize(transaction)?;
+        batch.put_cf(self.cf(CF_TRANSACTIONS)?, transaction.id, &value);
+        batch.put_cf(self.cf(CF_PENDING)?, transaction.id, &value);
+        Ok(())
+    }
+
     // Block storage operations
     pub async fn store_block(&self, block: &Block) -> Result<()> {
-        let mut blocks = self.blocks.write().await;
-        blocks.insert(block.header.block_number, block.clone());
-        
+        let mut batch = WriteBatch::default();
+        self.add_block_to_batch(&mut batch, block)?;
+        self.db.write(batch)?;
+
         debug!("Stored block {:?} at height {}", block.hash(), block.header.block_number);
         Ok(())
     }
-    
+
     pub async fn get_block(&self, block_number: u64) -> Result<Option<Block>> {
-        let blocks = self.blocks.read().await;
-        Ok(blocks.get(&block_number).cloned())
+        self.get(CF_BLOCKS, &Self::block_key(block_number))
     }
-    
+
+    // Looks the block up in the block tree, so blocks on side branches are found too
     pub async fn get_block_by_hash(&self, block_hash: &[u8; 32]) -> Result<Option<Block>> {
-        let blocks = self.blocks.read().await;
-        for block in blocks.values() {
-            if block.hash() == *block_hash {
-                return Ok(Some(block.clone()));
+        self.get(CF_BLOCK_TREE, block_hash)
+    }
+
+    pub async fn is_canonical(&self, block_hash: &[u8; 32]) -> Result<bool> {
+        Ok(self.db.get_cf(self.cf(CF_BLOCK_HASHES)?, block_hash)?.is_some())
+    }
+
+    pub async fn get_latest_block(&self) -> Result<Option<Block>> {
+        match self.db.iterator_cf(self.cf(CF_BLOCKS)?, IteratorMode::End).next() {
+            Some(item) => {
+                let (_, value) = item?;
+                Ok(Some(bincode::deserialize(&value)?))
             }
+            None => Ok(None),
         }
-        Ok(None)
     }
-    
-    pub async fn get_latest_block(&self) -> Result<Option<Block>> {
-        let blocks = self.blocks.read().await;
-        let latest_block_number = blocks.keys().max().copied();
-        
-        if let Some(block_number) = latest_block_number {
-            Ok(blocks.get(&block_number).cloned())
-        } else {
-            Ok(None)
+
+    pub async fn get_first_block(&self) -> Result<Option<Block>> {
+        match self.db.iterator_cf(self.cf(CF_BLOCKS)?, IteratorMode::Start).next() {
+            Some(item) => {
+                let (_, value) = item?;
+                Ok(Some(bincode::deserialize(&value)?))
+            }
+            None => Ok(None),
         }
     }
-    
+
     pub async fn get_block_range(&self, start: u64, end: u64) -> Result<Vec<Block>> {
-        let blocks = self.blocks.read().await;
+        let start_key = Self::block_key(start);
+        let end_key = Self::block_key(end);
         let mut result = Vec::new();
-        
-        for block_number in start..=end {
-            if let Some(block) = blocks.get(&block_number) {
-                result.push(block.clone());
+
+        let iter = self.db.iterator_cf(
+            self.cf(CF_BLOCKS)?,
+            IteratorMode::From(&start_key, Direction::Forward),
+        );
+        for item in iter {
+            let (key, value) = item?;
+            if key.as_ref() > end_key.as_slice() {
+                break;
             }
+            result.push(bincode::deserialize(&value)?);
         }
-        
+
         Ok(result)
     }
-    
+
+    // Block tree operations
+    pub async fn store_block_in_tree(&self, block: &Block) -> Result<()> {
+        let mut batch = WriteBatch::default();
+        self.add_block_to_tree_batch(&mut batch, block)?;
+        self.db.write(batch)?;
+
+        debug!("Stored side-branch block {:?} at height {}", block.hash(), block.header.block_number);
+        Ok(())
+    }
+
+    pub async fn get_children(&self, block_hash: &BlockHash) -> Result<Vec<BlockHash>> {
+        let mut result = Vec::new();
+
+        for item in self.db.prefix_iterator_cf(self.cf(CF_BLOCK_CHILDREN)?, block_hash) {
+            let (key, _) = item?;
+            if !key.starts_with(block_hash) {
+                break;
+            }
+            let mut child = [0u8; 32];
+            child.copy_from_slice(&key[32..]);
+            result.push(child);
+        }
+
+        Ok(result)
+    }
+
+    // Accounts always match the finalized block, so both are written together, along with
+    // what the block changed. The first finalized block has nothing before it to roll back to
+    pub async fn set_finalized_block(&self, block: &Block, accounts: &[(AccountId, Account)]) -> Result<()> {
+        let block_hash = block.hash();
+        let mut batch = WriteBatch::default();
+        if self.db.get_cf(self.cf(CF_CONSENSUS_STATE)?, FINALIZED_BLOCK_KEY)?.is_some() {
+            self.add_account_history_to_batch(&mut batch, block.header.block_number, accounts)?;
+        }
+        batch.put_cf(self.cf(CF_CONSENSUS_STATE)?, FINALIZED_BLOCK_KEY, block_hash);
+        for (id, account) in accounts {
+            batch.put_cf(self.cf(CF_ACCOUNTS)?, id, bincode::serialize(account)?);
+        }
+        self.db.write(batch)?;
+
+        debug!("Finalized block {:?} ({} accounts updated)", block_hash, accounts.len());
+        Ok(())
+    }
+
+    pub async fn get_finalized_block(&self) -> Result<Option<Block>> {
+        match self.db.get_cf(self.cf(CF_CONSENSUS_STATE)?, FINALIZED_BLOCK_KEY)? {
+            Some(hash) => self.get(CF_BLOCK_TREE, &hash),
+            None => Ok(None),
+        }
+    }
+
+    pub async fn get_account(&self, id: &AccountId) -> Result<Option<Account>> {
+        self.get(CF_ACCOUNTS, id)
+    }
+
+    pub async fn get_accounts(&self) -> Result<BTreeMap<AccountId, Account>> {
+        let mut accounts = BTreeMap::new();
+
+        for item in self.db.iterator_cf(self.cf(CF_ACCOUNTS)?, IteratorMode::Start) {
+            let (key, value) = item?;
+            accounts.insert(Self::account_id(&key)?, bincode::deserialize(&value)?);
+        }
+
+        Ok(accounts)
+    }
+
+    // Recursive proof covering the chain up to the finalized block
+    pub async fn store_chain_proof(&self, proof: &ZKProof) -> Result<()> {
+        self.put(CF_CONSENSUS_STATE, CHAIN_PROOF_KEY, proof)
+    }
+
+    pub async fn get_chain_proof(&self) -> Result<Option<ZKProof>> {
+        self.get(CF_CONSENSUS_STATE, CHAIN_PROOF_KEY)
+    }
+
+    // Accounts, validator set and block as of `height`. Only the accounts of the finalized
+    // block are kept, so that is the one height a snapshot can be taken at
+    pub async fn create_snapshot(&self, height: u64) -> Result<Snapshot> {
+        let snapshot = match Snapshot::from_storage(self).await.map_err(StorageError::Snapshot)? {
+            Some(snapshot) => snapshot,
+            None => return Err(StorageError::NotFound("finalized block to snapshot".to_string())),
+        };
+        if snapshot.height != height {
+            return Err(StorageError::Rejected(format!(
+                "only the finalized height {} can be snapshotted, not {}", snapshot.height, height)));
+        }
+        if let Some((proven, _)) = snapshot.block.zk_proof.checkpoint_reference() {
+            return Err(StorageError::Rejected(format!(
+                "block {} carries no proof of its own, only a reference to checkpoint #{}", height, proven)));
+        }
+        Ok(snapshot)
+    }
+
+    // Imports an archive written by `snapshot create`, checked like a peer's would be; the
+    // database must not hold a chain yet
+    pub async fn restore_from_snapshot(&self, path: &Path, zk_generator: &ZKProofGenerator, trust: SnapshotTrust<'_>) -> Result<Snapshot> {
+        if self.get_latest_block().await?.is_some() {
+            return Err(StorageError::Rejected("refusing to restore a snapshot over an existing chain".to_string()));
+        }
+
+        let snapshot = Snapshot::read_from(path).map_err(StorageError::Snapshot)?;
+        snapshot.verify(zk_generator, trust).await.map_err(StorageError::Snapshot)?;
+        snapshot.import_into(self).await.map_err(StorageError::Snapshot)?;
+        Ok(snapshot)
+    }
+
+    // Validator set of every finalized epoch, so blocks of past epochs can be checked against it
+    pub async fn store_epoch_change(&self, change: &EpochChange) -> Result<()> {
+        self.put(CF_EPOCHS, &change.epoch.to_be_bytes(), change)
+    }
+
+    pub async fn get_epoch_change(&self, epoch: u64) -> Result<Option<EpochChange>> {
+        self.get(CF_EPOCHS, &epoch.to_be_bytes())
+    }
+
+    // Proposals, votes and proof responses of the active validators, per epoch
+    pub async fn store_epoch_stats(&self, stats: &EpochStats) -> Result<()> {
+        self.put(CF_EPOCH_STATS, &stats.epoch.to_be_bytes(), stats)
+    }
+
+    pub async fn get_epoch_stats(&self, epoch: u64) -> Result<Option<EpochStats>> {
+        self.get(CF_EPOCH_STATS, &epoch.to_be_bytes())
+    }
+
+    // Validators and stake each committed block was finalized with, keyed by block hash
+    pub async fn store_finality(&self, finality: &BlockFinalized) -> Result<()> {
+        self.put(CF_FINALITY, &finality.block_hash, finality)
+    }
+
+    pub async fn get_finality(&self, block_hash: &BlockHash) -> Result<Option<BlockFinalized>> {
+        self.get(CF_FINALITY, block_hash)
+    }
+
+    // Signed precommits of finalized blocks, keyed by block hash. Unlike votes they are kept
+    // when blocks are pruned, so any node can prove the finality of its whole chain
+    pub async fn store_certificate(&self, certificate: &FinalityCertificate) -> Result<()> {
+        self.put(CF_CERTIFICATES, &certificate.block_hash, certificate)
+    }
+
+    pub async fn get_certificate(&self, block_hash: &BlockHash) -> Result<Option<FinalityCertificate>> {
+        self.get(CF_CERTIFICATES, block_hash)
+    }
+
+    // Makes the branch ending in `tip` canonical. Fails rather than reverting the finalized block
+    pub async fn reorg_to(&self, tip: &BlockHash) -> Result<Reorg> {
+        // Walk back from the new tip until we meet the canonical chain
+        let mut added = Vec::new();
+        let mut cursor = *tip;
+        let common_ancestor = loop {
+            if cursor == [0; 32] || self.is_canonical(&cursor).await? {
+                break cursor;
+            }
+            let block: Block = self.get(CF_BLOCK_TREE, &cursor)?
+                .ok_or_else(|| StorageError::NotFound(format!("block {} in branch", hex::encode(cursor))))?;
+            cursor = block.header.parent_hash;
+            added.push(block);
+        };
+        added.reverse();
+
+        let fork_height = match self.get_block_by_hash(&common_ancestor).await? {
+            Some(block) => block.header.block_number,
+            None => 0,
+        };
+        if let Some(finalized) = self.get_finalized_block().await? {
+            if fork_height < finalized.header.block_number {
+                return Err(StorageError::Rejected(format!("reorg to {} would revert finalized block #{}",
+                    hex::encode(tip), finalized.header.block_number)));
+            }
+        }
+
+        let removed = match self.get_latest_block().await? {
+            Some(head) => self.get_block_range(fork_height + 1, head.header.block_number).await?,
+            None => Vec::new(),
+        };
+
+        let mut batch = WriteBatch::default();
+        for block in &removed {
+            batch.delete_cf(self.cf(CF_BLOCKS)?, Self::block_key(block.header.block_number));
+            batch.delete_cf(self.cf(CF_BLOCK_HASHES)?, block.hash());
+        }
+        for block in &added {
+            self.add_block_to_batch(&mut batch, block)?;
+        }
+        self.db.write(batch)?;
+
+        info!("Reorganized chain at height {}: {} blocks removed, {} added",
+            fork_height, removed.len(), added.len());
+        Ok(Reorg { common_ancestor, removed, added })
+    }
+
     // Vote storage operations
     pub async fn store_vote(&self, vote: &BlockVote) -> Result<()> {
-        let key = format!("{}:{}", hex::encode(vote.block_hash), hex::encode(vote.validator));
-        let mut votes = self.votes.write().await;
-        votes.insert(key, vote.clone());
-        
+        self.put(CF_VOTES, &Self::vote_key(vote), vote)?;
+
         debug!("Stored vote for block {:?} by validator {:?}", vote.block_hash, vote.validator);
         Ok(())
     }
-    
+
     pub async fn get_votes_for_block(&self, block_hash: [u8; 32]) -> Result<Vec<BlockVote>> {
-        let votes = self.votes.read().await;
-        let prefix = hex::encode(block_hash);
-        
         let mut result = Vec::new();
-        for (key, vote) in votes.iter() {
-            if key.starts_with(&prefix) {
-                result.push(vote.clone());
+
+        for item in self.db.prefix_iterator_cf(self.cf(CF_VOTES)?, block_hash) {
+            let (key, value) = item?;
+            if !key.starts_with(&block_hash) {
+                break;
             }
+            result.push(bincode::deserialize(&value)?);
         }
-        
+
         Ok(result)
     }
-    
+
     // Transaction storage operations
     pub async fn store_transaction(&self, transaction: &Transaction) -> Result<()> {
-        let key = hex::encode(transaction.id);
-        let mut transactions = self.transactions.write().await;
-        transactions.insert(key, transaction.clone());
-        
-        // Add to pending transactions
-        let mut pending = self.pending_transactions.write().await;
-        pending.push(transaction.clone());
-        
+        let mut batch = WriteBatch::default();
+        self.add_transaction_to_batch(&mut batch, transaction)?;
+        self.db.write(batch)?;
+        self.emit(MempoolEvent::TxAdded(transaction.clone()));
+
         debug!("Stored transaction {}", hex::encode(transaction.id));
         Ok(())
     }
-    
+
     pub async fn get_transaction(&self, tx_id: &[u8; 32]) -> Result<Option<Transaction>> {
-        let key = hex::encode(tx_id);
-        let transactions = self.transactions.read().await;
-        Ok(transactions.get(&key).cloned())
+        self.get(CF_TRANSACTIONS, tx_id)
+    }
+
+    // Receipts of a finalized block's transactions, keyed by transaction id
+    pub async fn store_receipts(&self, receipts: &[TransactionReceipt]) -> Result<()> {
+        let mut batch = WriteBatch::default();
+        for receipt in receipts {
+            batch.put_cf(self.cf(CF_RECEIPTS)?, receipt.tx_id, bincode::serialize(receipt)?);
+        }
+        self.db.write(batch)?;
+        Ok(())
+    }
+
+    pub async fn get_receipt(&self, tx_id: &[u8; 32]) -> Result<Option<TransactionReceipt>> {
+        self.get(CF_RECEIPTS, tx_id)
     }
-    
+
+    // Proofs this node generated, by block hash
+    pub async fn store_proof(&self, block_hash: &BlockHash, proof: &ZKProof) -> Result<()> {
+        self.put(CF_PROOFS, block_hash, proof)
+    }
+
+    pub async fn get_proof(&self, block_hash: &BlockHash) -> Result<Option<ZKProof>> {
+        self.get(CF_PROOFS, block_hash)
+    }
+
     pub async fn get_pending_transactions(&self) -> Result<Vec<Transaction>> {
-        let pending = self.pending_transactions.read().await;
-        Ok(pending.clone())
+        self.read_pending()
+    }
+
+    fn read_pending(&self) -> Result<Vec<Transaction>> {
+        let mut result = Vec::new();
+        for item in self.db.iterator_cf(self.cf(CF_PENDING)?, IteratorMode::Start) {
+            let (_, value) = item?;
+            result.push(bincode::deserialize(&value)?);
+        }
+        Ok(result)
+    }
+
+    pub async fn is_transaction_pending(&self, tx_id: &[u8; 32]) -> Result<bool> {
+        Ok(self.db.get_cf(self.cf(CF_PENDING)?, tx_id)?.is_some())
     }
-    
-    pub async fn remove_pending_transactions(&self, tx_ids: &[[u8; 32]]) -> Result<()> {
-        let mut pending = self.pending_transactions.write().await;
-        pending.retain(|tx| !tx_ids.contains(&tx.id));
+
+    // Puts transactions back into the pending pool, e.g. after their block was reorganized away
+    pub async fn restore_pending_transactions(&self, transactions: &[Transaction]) -> Result<()> {
+        let cf = self.cf(CF_PENDING)?;
+        let mut batch = WriteBatch::default();
+        for transaction in transactions {
+            batch.put_cf(cf, transaction.id, bincode::serialize(transaction)?);
+        }
+        self.db.write(batch)?;
+        for transaction in transactions {
+            self.emit(MempoolEvent::TxAdded(transaction.clone()));
+        }
         Ok(())
     }
-    
+
+    // Only transactions that were pending are announced as dropped
+    pub async fn remove_pending_transactions(&self, tx_ids: &[[u8; 32]], reason: DropReason) -> Result<()> {
+        let cf = self.cf(CF_PENDING)?;
+        let mut batch = WriteBatch::default();
+        let mut dropped = Vec::new();
+        for tx_id in tx_ids {
+            if self.db.get_cf(cf, tx_id)?.is_some() {
+                batch.delete_cf(cf, tx_id);
+                dropped.push(*tx_id);
+            }
+        }
+        self.db.write(batch)?;
+        for tx_id in dropped {
+            self.emit(MempoolEvent::TxDropped { tx_id, reason });
+        }
+        Ok(())
+    }
+
     // Consensus state storage
     pub async fn store_consensus_state(&self, state: &ConsensusState) -> Result<()> {
-        let mut consensus_state = self.consensus_state.write().await;
-        *consensus_state = Some(state.clone());
-        Ok(())
+        self.put(CF_CONSENSUS_STATE, CONSENSUS_STATE_KEY, state)
     }
-    
+
     pub async fn get_consensus_state(&self) -> Result<Option<ConsensusState>> {
-        let consensus_state = self.consensus_state.read().await;
-        Ok(consensus_state.clone())
+        self.get(CF_CONSENSUS_STATE, CONSENSUS_STATE_KEY)
+    }
+
+    // Consensus state as of finalized block `block_number`, which snapshots at a trusted
+    // checkpoint are served from; pruned along with the block's account history
+    pub async fn store_consensus_state_at(&self, block_number: u64, state: &ConsensusState) -> Result<()> {
+        self.put(CF_CONSENSUS_STATE, &Self::block_key(block_number), state)
+    }
+
+    pub async fn get_consensus_state_at(&self, block_number: u64) -> Result<Option<ConsensusState>> {
+        self.get(CF_CONSENSUS_STATE, &Self::block_key(block_number))
+    }
+
+    // Consensus state a block not yet final was applied on, which a reorg dropping the block
+    // goes back to. Kept until the block is final or reorganized away
+    pub async fn store_state_before(&self, block_hash: &BlockHash, state: &ConsensusState) -> Result<()> {
+        self.put(CF_CONSENSUS_STATE, &Self::state_before_key(block_hash), state)
+    }
+
+    pub async fn get_state_before(&self, block_hash: &BlockHash) -> Result<Option<ConsensusState>> {
+        self.get(CF_CONSENSUS_STATE, &Self::state_before_key(block_hash))
+    }
+
+    pub async fn delete_state_before(&self, block_hash: &BlockHash) -> Result<()> {
+        self.db.delete_cf(self.cf(CF_CONSENSUS_STATE)?, Self::state_before_key(block_hash))?;
+        Ok(())
     }
-    
+
+    // Synced to disk before returning: the vote it records goes out right after, and must
+    // survive even a power loss
+    pub async fn store_round_checkpoint(&self, checkpoint: &RoundCheckpoint) -> Result<()> {
+        let mut options = WriteOptions::default();
+        options.set_sync(true);
+        self.db.put_cf_opt(self.cf(CF_CONSENSUS_STATE)?, ROUND_CHECKPOINT_KEY, bincode::serialize(checkpoint)?, &options)?;
+        Ok(())
+    }
+
+    pub async fn get_round_checkpoint(&self) -> Result<Option<RoundCheckpoint>> {
+        self.get(CF_CONSENSUS_STATE, ROUND_CHECKPOINT_KEY)
+    }
+
+    pub async fn store_safety_alarm(&self, alarm: &SafetyAlarm) -> Result<()> {
+        self.put(CF_CONSENSUS_STATE, SAFETY_ALARM_KEY, alarm)
+    }
+
+    pub async fn get_safety_alarm(&self) -> Result<Option<SafetyAlarm>> {
+        self.get(CF_CONSENSUS_STATE, SAFETY_ALARM_KEY)
+    }
+
     // Utility operations
     pub async fn get_block_count(&self) -> Result<u64> {
-        let blocks = self.blocks.read().await;
-        Ok(blocks.len() as u64)
+        self.count_keys(CF_BLOCKS)
     }
-    
+
     pub async fn get_transaction_count(&self) -> Result<u64> {
-        let transactions = self.transactions.read().await;
-        Ok(transactions.len() as u64)
+        self.count_keys(CF_TRANSACTIONS)
     }
-    
-    pub async fn compact(&self) -> Result<()> {
-        info!("Mock database compaction completed");
+
+    // Writes memtables out so nothing depends on replaying the WAL after shutdown
+    pub async fn flush(&self) -> Result<()> {
+        for name in COLUMN_FAMILIES {
+            self.db.flush_cf(self.cf(name)?)?;
+        }
+        info!("Database flushed");
         Ok(())
     }
-    
-    pub async fn backup(&self, backup_path: &str) -> Result<()> {
-        info!("Mock database backup completed to: {}", backup_path);
-        Ok(())
+
+    // Archives the finalized chain into a backup directory; after the first backup only what
+    // changed since the last one, unless `full`. None when the backup is already up to date
+    pub async fn backup(&self, dir: &Path, full: bool) -> Result<Option<BackupEntry>> {
+        snapshot::backup::backup(self, dir, full).await.map_err(StorageError::Snapshot)
     }
-    
-    pub async fn restore(&self, backup_path: &str) -> Result<()> {
-        info!("Mock database restore completed from: {}", backup_path);
-        Ok(())
+
+    // Restores a backup directory into this database, which must not hold a chain yet
+    pub async fn restore(&self, dir: &Path) -> Result<Snapshot> {
+        snapshot::backup::restore(self, dir).await.map_err(StorageError::Snapshot)
     }
-    
+
     // Batch operations for better performance
     pub async fn store_blocks_batch(&self, blocks: &[Block]) -> Result<()> {
-        let mut blocks_map = self.blocks.write().await;
-        
+        let mut batch = WriteBatch::default();
         for block in blocks {
-            blocks_map.insert(block.header.block_number, block.clone());
+            self.add_block_to_batch(&mut batch, block)?;
         }
-        
+        self.db.write(batch)?;
+
         debug!("Stored {} blocks in batch", blocks.len());
         Ok(())
     }
-    
+
     pub async fn store_transactions_batch(&self, transactions: &[Transaction]) -> Result<()> {
-        let mut transactions_map = self.transactions.write().await;
-        let mut pending = self.pending_transactions.write().await;
-        
+        let mut batch = WriteBatch::default();
+        for transaction in transactions {
+            self.add_transaction_to_batch(&mut batch, transaction)?;
+        }
+        self.db.write(batch)?;
         for transaction in transactions {
-            let key = hex::encode(transaction.id);
-            transactions_map.insert(key, transaction.clone());
-            pending.push(transaction.clone());
+            self.emit(MempoolEvent::TxAdded(transaction.clone()));
         }
-        
+
         debug!("Stored {} transactions in batch", transactions.len());
         Ok(())
     }
@@ -198,18 +677,9 @@ impl StorageManager {
 impl Clone for StorageManager {
     fn clone(&self) -> Self {
         Self {
-            blocks: self.blocks.clone(),
-            votes: self.votes.clone(),
-            transactions: self.transactions.clone(),
-            pending_transactions: self.pending_transactions.clone(),
-            consensus_state: self.consensus_state.clone(),
+            db: self.db.clone(),
+            mempool_events: self.mempool_events.clone(),
+            pending_index: self.pending_index.clone(),
         }
     }
 }
-
-// TODO: Implement real RocksDB storage when dependencies are properly configured
-// This will include:
-// - Real RocksDB database operations
-// - Proper serialization/deserialization
-// - Backup and restore functionality
-// - Batch operations for better performance 
\ No newline at end of file
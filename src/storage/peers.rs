@@ -0,0 +1,32 @@
+use super::{Result, StorageManager, CF_PEERS};
+use chrono::{DateTime, Utc};
+use rocksdb::IteratorMode;
+use serde::{Deserialize, Serialize};
+
+// A peer we were connected to, with the addresses it listens on; redialed on startup
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KnownPeer {
+    pub addrs: Vec<String>,
+    pub last_seen: DateTime<Utc>,
+}
+
+// Keyed by the peer id in its string form, so storage does not depend on libp2p
+impl StorageManager {
+    pub async fn store_peer(&self, peer_id: &str, peer: &KnownPeer) -> Result<()> {
+        self.put(CF_PEERS, peer_id.as_bytes(), peer)
+    }
+
+    pub async fn remove_peer(&self, peer_id: &str) -> Result<()> {
+        self.db.delete_cf(self.cf(CF_PEERS)?, peer_id.as_bytes())?;
+        Ok(())
+    }
+
+    pub async fn get_known_peers(&self) -> Result<Vec<(String, KnownPeer)>> {
+        let mut peers = Vec::new();
+        for item in self.db.iterator_cf(self.cf(CF_PEERS)?, IteratorMode::Start) {
+            let (key, value) = item?;
+            peers.push((String::from_utf8_lossy(&key).into_owned(), bincode::deserialize(&value)?));
+        }
+        Ok(peers)
+    }
+}
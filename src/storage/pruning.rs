@@ -0,0 +1,148 @@
+use super::{StorageManager, CF_BLOCKS, CF_BLOCK_TREE, CF_CONSENSUS_STATE, CF_PROOFS, CF_RECEIPTS, CF_TRANSACTIONS, CF_VOTES};
+use crate::shutdown::ShutdownSignal;
+use crate::types::ChainEvent;
+use super::Result;
+use chrono::{DateTime, Utc};
+use rocksdb::WriteBatch;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use tracing::{info, debug};
+
+pub const PRUNING_MODES: [&str; 2] = ["archive", "pruned"];
+// Pruned nodes keep at least this many finalized blocks, enough to serve sync and reorg checks
+pub const MIN_KEEP_BLOCKS: u64 = 128;
+
+const PRUNING_KEY: &[u8] = b"pruning";
+// Blocks pruned per write batch, so a large backlog does not hold up other writers
+const PRUNE_BATCH: u64 = 64;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PruningStats {
+    // Lowest block that still has its transactions; 0 on archive nodes
+    pub pruned_below: u64,
+    pub blocks_pruned: u64,
+    // Encoded size of everything deleted, before compaction returns it to the filesystem
+    pub bytes_reclaimed: u64,
+    pub last_run: Option<DateTime<Utc>>,
+}
+
+impl StorageManager {
+    pub async fn get_pruning_stats(&self) -> Result<PruningStats> {
+        Ok(self.get(CF_CONSENSUS_STATE, PRUNING_KEY)?.unwrap_or_default())
+    }
+
+    // Lowest block whose transactions are still stored
+    pub async fn pruned_below(&self) -> Result<u64> {
+        Ok(self.get_pruning_stats().await?.pruned_below)
+    }
+
+    // Pruned blocks are kept as header, proof and signature only. Their emptied transaction list
+    // no longer matches the merkle root, so they are never to be served as whole blocks
+    pub async fn is_header_only(&self, block_number: u64) -> Result<bool> {
+        Ok(block_number > 0 && block_number < self.pruned_below().await?)
+    }
+
+    // Drops the transactions, receipts, address history, votes, account history, consensus state and
+    // proofs generated here of canonical blocks below `below`, which can not be proven again.
+    // Headers, their proofs and signatures stay, which is all light clients and header sync need.
+    // Never reaches the finalized block: blocks above it may still be reverted, and snapshots are
+    // taken of it
+    pub async fn prune_blocks(&self, below: u64) -> Result<PruningStats> {
+        let finalized = self.get_finalized_block().await?.map_or(0, |block| block.header.block_number);
+        let below = below.min(finalized);
+        let mut stats = self.get_pruning_stats().await?;
+        let start = stats.pruned_below.max(1);
+        if below <= start {
+            return Ok(stats);
+        }
+
+        let mut batch = WriteBatch::default();
+        let mut reclaimed = 0u64;
+        let mut pruned = 0u64;
+        for mut block in self.get_block_range(start, below - 1).await? {
+            let hash = block.hash();
+            let full_size = bincode::serialized_size(&block)?;
+
+            for tx in &block.transactions {
+                for cf in [CF_TRANSACTIONS, CF_RECEIPTS] {
+                    if let Some(value) = self.db.get_cf(self.cf(cf)?, tx.id)? {
+                        reclaimed += value.len() as u64;
+                        batch.delete_cf(self.cf(cf)?, tx.id);
+                    }
+                }
+            }
+            reclaimed += self.unindex_transactions(&mut batch, &block)?;
+            reclaimed += self.prune_account_history(&mut batch, block.header.block_number)?;
+            let state_key = Self::block_key(block.header.block_number);
+            if let Some(value) = self.db.get_cf(self.cf(CF_CONSENSUS_STATE)?, state_key)? {
+                reclaimed += value.len() as u64;
+                batch.delete_cf(self.cf(CF_CONSENSUS_STATE)?, state_key);
+            }
+            if let Some(value) = self.db.get_cf(self.cf(CF_PROOFS)?, hash)? {
+                reclaimed += value.len() as u64;
+                batch.delete_cf(self.cf(CF_PROOFS)?, hash);
+            }
+            for item in self.db.prefix_iterator_cf(self.cf(CF_VOTES)?, hash) {
+                let (key, value) = item?;
+                if !key.starts_with(&hash) {
+                    break;
+                }
+                reclaimed += (key.len() + value.len()) as u64;
+                batch.delete_cf(self.cf(CF_VOTES)?, key);
+            }
+
+            block.transactions.clear();
+            let body = bincode::serialize(&block)?;
+            // The block is stored by height and in the block tree
+            reclaimed += 2 * (full_size - body.len() as u64);
+            batch.put_cf(self.cf(CF_BLOCKS)?, Self::block_key(block.header.block_number), &body);
+            batch.put_cf(self.cf(CF_BLOCK_TREE)?, hash, &body);
+            pruned += 1;
+        }
+
+        stats.pruned_below = below;
+        stats.blocks_pruned += pruned;
+        stats.bytes_reclaimed += reclaimed;
+        stats.last_run = Some(Utc::now());
+        batch.put_cf(self.cf(CF_CONSENSUS_STATE)?, PRUNING_KEY, bincode::serialize(&stats)?);
+        self.db.write(batch)?;
+
+        debug!("Pruned blocks #{}..#{} ({} bytes)", start, below - 1, reclaimed);
+        Ok(stats)
+    }
+
+    // Keeps the bodies of the last `keep_blocks` finalized blocks, pruning older ones as blocks finalize
+    pub async fn run_pruner(
+        &self,
+        keep_blocks: u64,
+        mut events: broadcast::Receiver<ChainEvent>,
+        mut shutdown: ShutdownSignal,
+    ) -> anyhow::Result<()> {
+        info!("✂️ Pruning block bodies older than {} finalized blocks", keep_blocks);
+
+        loop {
+            let height = tokio::select! {
+                event = events.recv() => match event {
+                    Ok(ChainEvent::BlockFinalized(finalized)) => finalized.block_number,
+                    Ok(_) => continue,
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => return Ok(()),
+                },
+                _ = shutdown.recv() => return Ok(()),
+            };
+
+            let target = height.saturating_sub(keep_blocks) + 1;
+            let before = self.get_pruning_stats().await?;
+            let mut stats = before.clone();
+            while stats.pruned_below.max(1) < target {
+                stats = self.prune_blocks(target.min(stats.pruned_below.max(1) + PRUNE_BATCH)).await?;
+                tokio::task::yield_now().await;
+            }
+
+            if stats.blocks_pruned > before.blocks_pruned {
+                debug!("✂️ Pruned {} blocks up to #{}, {} KiB reclaimed in total",
+                    stats.blocks_pruned - before.blocks_pruned, stats.pruned_below - 1, stats.bytes_reclaimed / 1024);
+            }
+        }
+    }
+}
@@ -0,0 +1,324 @@
+use crate::types::{Block, BlocksResponse, GetBlocks, NodeId};
+use chrono::{DateTime, Duration, Utc};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tracing::{info, debug, warn};
+
+// Largest range a single request asks for
+pub const BATCH_SIZE: u64 = 16;
+// Downloaded blocks are buffered at most this far ahead of the imported height
+const MAX_LOOKAHEAD: u64 = 256;
+const MAX_IN_FLIGHT_PER_PEER: usize = 2;
+const MAX_PEER_FAILURES: u32 = 3;
+const REQUEST_TIMEOUT_SECS: i64 = 10;
+// How often all peers are asked for blocks past our height, which also tells us how far ahead they are
+const PROBE_INTERVAL_SECS: i64 = 15;
+const PROGRESS_INTERVAL_SECS: i64 = 5;
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SyncStatus {
+    pub syncing: bool,
+    pub local_height: u64,
+    pub target_height: u64,
+    pub peers: usize,
+    pub requests_in_flight: usize,
+    pub blocks_downloaded: u64,
+    pub blocks_imported: u64,
+    pub blocks_rejected: u64,
+    pub blocks_per_second: f64,
+}
+
+struct PeerState {
+    finalized_height: u64,
+    in_flight: usize,
+    failures: u32,
+}
+
+struct Request {
+    // None for probes, which every peer may answer
+    peer: Option<NodeId>,
+    from_block: u64,
+    to_block: u64,
+    sent_at: DateTime<Utc>,
+}
+
+// Tracks which peers are ahead of us and spreads batched block downloads across them;
+// importing the downloaded blocks is left to the consensus engine
+pub struct BlockSync {
+    peers: HashMap<NodeId, PeerState>,
+    requests: HashMap<[u8; 32], Request>,
+    // Blocks waiting for their parent to be imported, with the peer that served them
+    downloaded: BTreeMap<u64, (Block, NodeId)>,
+    last_probe: Option<DateTime<Utc>>,
+    last_report: Option<(DateTime<Utc>, u64)>,
+    status: Arc<RwLock<SyncStatus>>,
+    // Request ids
+    rng: StdRng,
+}
+
+impl BlockSync {
+    pub fn new() -> Self {
+        Self {
+            peers: HashMap::new(),
+            requests: HashMap::new(),
+            downloaded: BTreeMap::new(),
+            last_probe: None,
+            last_report: None,
+            status: Arc::new(RwLock::new(SyncStatus::default())),
+            rng: StdRng::from_entropy(),
+        }
+    }
+
+    #[cfg(feature = "testing")]
+    pub fn set_rng(&mut self, rng: StdRng) {
+        self.rng = rng;
+    }
+
+    // Shared with the RPC server
+    pub fn status_handle(&self) -> Arc<RwLock<SyncStatus>> {
+        self.status.clone()
+    }
+
+    // Highest finalized block any peer has reported
+    pub fn target_height(&self) -> u64 {
+        self.peers.values().map(|peer| peer.finalized_height).max().unwrap_or(0)
+    }
+
+    pub fn is_behind(&self, local_height: u64) -> bool {
+        self.target_height() > local_height
+    }
+
+    // Peer with the highest finalized block, preferring the ones that failed us least
+    pub fn best_peer(&self) -> Option<NodeId> {
+        self.peers.iter()
+            .max_by_key(|(_, state)| (state.finalized_height, std::cmp::Reverse(state.failures)))
+            .map(|(peer, _)| *peer)
+    }
+
+    // Expires stale requests and returns the requests to send for missing blocks
+    pub async fn poll(&mut self, local_height: u64, now: DateTime<Utc>) -> Vec<GetBlocks> {
+        self.expire_requests(now);
+        self.downloaded.retain(|height, _| *height > local_height);
+
+        let mut outgoing = Vec::new();
+
+        let probe_due = self.last_probe
+            .is_none_or(|last| now - last >= Duration::seconds(PROBE_INTERVAL_SECS));
+        if probe_due {
+            self.last_probe = Some(now);
+            outgoing.push(self.request(None, local_height + 1, local_height + BATCH_SIZE, now));
+        }
+
+        let window_end = self.target_height().min(local_height + MAX_LOOKAHEAD);
+        let mut height = local_height + 1;
+        while height <= window_end {
+            if self.is_covered(height) {
+                height += 1;
+                continue;
+            }
+
+            // Extend the batch up to the next block we already have or asked for
+            let mut to_block = height;
+            while to_block < window_end
+                && to_block - height + 1 < BATCH_SIZE
+                && !self.is_covered(to_block + 1)
+            {
+                to_block += 1;
+            }
+
+            let peer = match self.pick_peer(to_block) {
+                Some(peer) => peer,
+                None => break,
+            };
+            outgoing.push(self.request(Some(peer), height, to_block, now));
+            height = to_block + 1;
+        }
+
+        self.report_progress(local_height, now).await;
+        outgoing
+    }
+
+    // A peer's finalized height as its handshake announced it, which makes it a sync target
+    // before it answered any request
+    pub fn peer_head(&mut self, peer: NodeId, finalized_height: u64) {
+        let state = self.peers.entry(peer).or_insert(PeerState {
+            finalized_height: 0,
+            in_flight: 0,
+            failures: 0,
+        });
+        state.finalized_height = state.finalized_height.max(finalized_height);
+    }
+
+    // Buffers the blocks of a response to one of our requests
+    pub async fn on_response(&mut self, response: BlocksResponse, local_height: u64) {
+        let (peer, from_block, to_block) = match self.requests.get(&response.request_id) {
+            Some(request) => (request.peer, request.from_block, request.to_block),
+            None => return,
+        };
+
+        // Probes stay open for other peers' answers until they time out
+        if let Some(peer) = peer {
+            if peer != response.responder {
+                return;
+            }
+            self.requests.remove(&response.request_id);
+            if let Some(state) = self.peers.get_mut(&peer) {
+                state.in_flight = state.in_flight.saturating_sub(1);
+            }
+        }
+
+        let in_order = response.blocks.iter()
+            .enumerate()
+            .all(|(i, block)| block.header.block_number == from_block + i as u64)
+            && response.blocks.len() as u64 <= to_block - from_block + 1;
+        if !in_order || response.blocks.is_empty() {
+            warn!("⚠️ Peer {} answered blocks #{}-#{} with an unusable range",
+                hex::encode(response.responder), from_block, to_block);
+            self.penalize(&response.responder);
+            return;
+        }
+
+        let state = self.peers.entry(response.responder).or_insert(PeerState {
+            finalized_height: 0,
+            in_flight: 0,
+            failures: 0,
+        });
+        state.finalized_height = state.finalized_height.max(response.finalized_height);
+
+        let mut added = 0;
+        for block in response.blocks {
+            let height = block.header.block_number;
+            if height > local_height && !self.downloaded.contains_key(&height) {
+                self.downloaded.insert(height, (block, response.responder));
+                added += 1;
+            }
+        }
+
+        debug!("Downloaded {} blocks from {}", added, hex::encode(response.responder));
+        self.status.write().await.blocks_downloaded += added;
+    }
+
+    // Up to `max` downloaded blocks that extend the imported chain one after the other
+    pub fn next_blocks(&mut self, local_height: u64, max: usize) -> Vec<(Block, NodeId)> {
+        let mut blocks = Vec::new();
+        while blocks.len() < max {
+            match self.downloaded.remove(&(local_height + 1 + blocks.len() as u64)) {
+                Some(next) => blocks.push(next),
+                None => break,
+            }
+        }
+        blocks
+    }
+
+    // Hands back blocks taken with next_blocks that were not imported
+    pub fn requeue(&mut self, blocks: Vec<(Block, NodeId)>) {
+        for (block, peer) in blocks {
+            self.downloaded.insert(block.header.block_number, (block, peer));
+        }
+    }
+
+    pub async fn block_imported(&mut self) {
+        self.status.write().await.blocks_imported += 1;
+    }
+
+    // The peer served a block that failed validation; nothing else it sent is trusted
+    pub async fn block_rejected(&mut self, peer: &NodeId) {
+        self.downloaded.retain(|_, (_, served_by)| served_by != peer);
+        self.penalize(peer);
+        self.status.write().await.blocks_rejected += 1;
+    }
+
+    fn request(&mut self, peer: Option<NodeId>, from_block: u64, to_block: u64, now: DateTime<Utc>) -> GetBlocks {
+        let request_id = self.rng.gen();
+        if let Some(state) = peer.and_then(|peer| self.peers.get_mut(&peer)) {
+            state.in_flight += 1;
+        }
+        self.requests.insert(request_id, Request { peer, from_block, to_block, sent_at: now });
+
+        GetBlocks { from_block, to_block, request_id, target: peer }
+    }
+
+    fn expire_requests(&mut self, now: DateTime<Utc>) {
+        let expired: Vec<_> = self.requests.iter()
+            .filter(|(_, request)| now - request.sent_at >= Duration::seconds(REQUEST_TIMEOUT_SECS))
+            .map(|(request_id, _)| *request_id)
+            .collect();
+
+        for request_id in expired {
+            if let Some(Request { peer: Some(peer), from_block, to_block, .. }) = self.requests.remove(&request_id) {
+                debug!("Request for blocks #{}-#{} to {} timed out", from_block, to_block, hex::encode(peer));
+                if let Some(state) = self.peers.get_mut(&peer) {
+                    state.in_flight = state.in_flight.saturating_sub(1);
+                }
+                self.penalize(&peer);
+            }
+        }
+    }
+
+    fn is_covered(&self, height: u64) -> bool {
+        self.downloaded.contains_key(&height)
+            || self.requests.values().any(|request| {
+                request.peer.is_some() && (request.from_block..=request.to_block).contains(&height)
+            })
+    }
+
+    // Least busy peer that has finalized the whole range
+    fn pick_peer(&self, to_block: u64) -> Option<NodeId> {
+        self.peers.iter()
+            .filter(|(_, state)| state.finalized_height >= to_block && state.in_flight < MAX_IN_FLIGHT_PER_PEER)
+            .min_by_key(|(_, state)| (state.in_flight, state.failures))
+            .map(|(peer, _)| *peer)
+    }
+
+    fn penalize(&mut self, peer: &NodeId) {
+        let failures = match self.peers.get_mut(peer) {
+            Some(state) => {
+                state.failures += 1;
+                state.failures
+            }
+            None => return,
+        };
+
+        if failures >= MAX_PEER_FAILURES {
+            warn!("🚫 Dropping sync peer {} after {} failed requests", hex::encode(peer), failures);
+            self.peers.remove(peer);
+        }
+    }
+
+    async fn report_progress(&mut self, local_height: u64, now: DateTime<Utc>) {
+        let target_height = self.target_height();
+        let syncing = target_height > local_height;
+        let mut status = self.status.write().await;
+
+        let (last_time, last_imported) = *self.last_report.get_or_insert((now, status.blocks_imported));
+        let elapsed = (now - last_time).num_milliseconds();
+        if elapsed >= PROGRESS_INTERVAL_SECS * 1000 {
+            status.blocks_per_second = (status.blocks_imported - last_imported) as f64 * 1000.0 / elapsed as f64;
+            self.last_report = Some((now, status.blocks_imported));
+
+            if syncing {
+                info!("⏬ Syncing block #{} of #{} ({:.1} blocks/s from {} peers)",
+                    local_height, target_height, status.blocks_per_second, self.peers.len());
+            }
+        }
+
+        if status.syncing && !syncing {
+            info!("✅ Synced to block #{}", local_height);
+        }
+
+        status.syncing = syncing;
+        status.local_height = local_height;
+        status.target_height = target_height;
+        status.peers = self.peers.len();
+        status.requests_in_flight = self.requests.len();
+    }
+}
+
+impl Default for BlockSync {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,87 @@
+use crate::types::{Block, BlockVote, ConsensusMessage, VoteType};
+use anyhow::{bail, Result};
+use std::time::Duration;
+
+mod simulation;
+
+pub use simulation::{simulate, SimulationConfig};
+
+// Values of --byzantine
+pub const BEHAVIORS: [&str; 4] = ["equivocate", "withhold-votes", "invalid-proofs", "delay-messages"];
+// Longer than a round at the default block time
+const MESSAGE_DELAY: Duration = Duration::from_secs(15);
+
+// Faults a validator can be made to commit, to exercise the safety and liveness of voting and
+// finality against them. Attached with ConsensusEngine::set_byzantine; honest by default
+pub trait ByzantineBehavior: Send + Sync {
+    // Votes sent in place of `vote`, each signed by the engine. Returning none withholds the
+    // vote, returning conflicting ones equivocates
+    fn votes(&self, vote: BlockVote) -> Vec<BlockVote> {
+        vec![vote]
+    }
+
+    // Called on our own proposal once its proof is attached, before it is signed
+    fn tamper_proposal(&self, _block: &mut Block) {}
+
+    // How long an outgoing message is held back
+    fn delay(&self, _message: &ConsensusMessage) -> Duration {
+        Duration::ZERO
+    }
+}
+
+// Votes for the block and, in the same step, for a different one
+pub struct Equivocate;
+
+// Never votes, so the rest of the validators have to reach quorum without us
+pub struct WithholdVotes;
+
+// Proposes blocks whose proofs do not verify against them, whichever backend is in use
+pub struct InvalidProofs;
+
+// Holds every outgoing message back, as if we were behind a slow link
+pub struct DelayMessages(pub Duration);
+
+impl ByzantineBehavior for Equivocate {
+    fn votes(&self, vote: BlockVote) -> Vec<BlockVote> {
+        let mut conflicting = vote.clone();
+        match vote.vote {
+            VoteType::Approve => {
+                conflicting.block_hash = [0; 32];
+                conflicting.vote = VoteType::Reject;
+            }
+            VoteType::Reject | VoteType::Abstain => {
+                conflicting.block_hash = [0xff; 32];
+                conflicting.vote = VoteType::Approve;
+            }
+        }
+        vec![vote, conflicting]
+    }
+}
+
+impl ByzantineBehavior for WithholdVotes {
+    fn votes(&self, _vote: BlockVote) -> Vec<BlockVote> {
+        Vec::new()
+    }
+}
+
+impl ByzantineBehavior for InvalidProofs {
+    fn tamper_proposal(&self, block: &mut Block) {
+        block.zk_proof.public_inputs.push(0);
+    }
+}
+
+impl ByzantineBehavior for DelayMessages {
+    fn delay(&self, _message: &ConsensusMessage) -> Duration {
+        self.0
+    }
+}
+
+pub fn behavior(name: &str) -> Result<Box<dyn ByzantineBehavior>> {
+    Ok(match name {
+        "equivocate" => Box::new(Equivocate),
+        "withhold-votes" => Box::new(WithholdVotes),
+        "invalid-proofs" => Box::new(InvalidProofs),
+        "delay-messages" => Box::new(DelayMessages(MESSAGE_DELAY)),
+        other => bail!("Unknown byzantine behavior '{}', expected one of {:?}", other, BEHAVIORS),
+    })
+}
@@ -0,0 +1,251 @@
+use crate::clock::SimulatedClock;
+use crate::config::{ConsensusConfig, StorageConfig, ZkProofConfig};
+use crate::consensus::ConsensusEngine;
+use crate::crypto::NodeKeypair;
+use crate::genesis::{Genesis, GenesisParams, GenesisValidator};
+use crate::network;
+use crate::staking::MIN_VALIDATOR_STAKE;
+use crate::storage::StorageManager;
+use crate::types::{self, BlockHash, ChainEvent, ConsensusMessage};
+use crate::zk_proof::{ProofService, ZKProofGenerator};
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use sha2::{Digest, Sha256};
+use std::collections::btree_map::Entry;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::broadcast::error::TryRecvError;
+use tokio::sync::{broadcast, mpsc};
+use tracing::info;
+
+// Virtual time every run starts at, so nothing in a run depends on when it was started
+const START_TIME: i64 = 1_700_000_000;
+// Same as the tick of consensus_loop
+const TICK_MS: i64 = 1000;
+// Outgoing messages are collected after every step, which sends a handful at most
+const OUTBOX_SIZE: usize = 1000;
+
+#[derive(Debug, Clone)]
+pub struct SimulationConfig {
+    pub seed: u64,
+    pub nodes: usize,
+    // Virtual seconds to run for
+    pub duration: u64,
+    // Each copy of a message is delivered after a random delay up to this, which reorders them
+    pub max_delay_ms: u64,
+    // Chance of each copy of a message being lost
+    pub drop_rate: f64,
+    // Behavior of the first validator, the others are honest
+    pub byzantine: Option<String>,
+    // Every node gets a database under it, wiped at the start of each run
+    pub db_dir: PathBuf,
+}
+
+#[derive(Debug)]
+pub struct SimulationReport {
+    pub seed: u64,
+    // Highest block every node finalized
+    pub finalized: u64,
+    pub delivered: u64,
+    pub dropped: u64,
+    // Hash over every delivery and finalized block; runs from the same seed have the same one
+    pub trace: [u8; 32],
+}
+
+enum Event {
+    Tick(usize),
+    Deliver(usize, Box<ConsensusMessage>),
+}
+
+struct SimNode {
+    engine: ConsensusEngine,
+    outbox: mpsc::Receiver<ConsensusMessage>,
+    events: broadcast::Receiver<ChainEvent>,
+    finalized: u64,
+}
+
+// Runs a chain of in-process validators on a seeded scheduler instead of timers and gossip.
+// Time only moves when the scheduler hands out the next event, and every tick, delay, loss
+// and key comes from the seed, so a run can be repeated exactly. Fails when two nodes
+// finalize different blocks at the same height
+pub async fn simulate(config: &SimulationConfig) -> Result<SimulationReport> {
+    if config.nodes == 0 {
+        bail!("A simulation needs at least one node");
+    }
+    if !(0.0..1.0).contains(&config.drop_rate) {
+        bail!("drop_rate must be at least 0 and below 1, got {}", config.drop_rate);
+    }
+    // It holds messages back on real timers, which the scheduler can not see
+    if config.byzantine.as_deref() == Some("delay-messages") {
+        bail!("delay-messages can not be simulated, raise max_delay_ms instead");
+    }
+
+    let mut rng = StdRng::seed_from_u64(config.seed);
+    let start = match Utc.timestamp_opt(START_TIME, 0).single() {
+        Some(start) => start,
+        None => bail!("invalid simulation start time"),
+    };
+    let clock = Arc::new(SimulatedClock::new(start));
+    let keypairs: Vec<_> = (0..config.nodes)
+        .map(|_| NodeKeypair::from_secret_bytes(&rng.gen()))
+        .collect();
+    let genesis = genesis(&keypairs, start);
+    let mut consensus_config = ConsensusConfig::default();
+    genesis.apply_to(&mut consensus_config);
+
+    std::fs::create_dir_all(&config.db_dir)?;
+    let mut nodes = Vec::new();
+    for (index, keypair) in keypairs.into_iter().enumerate() {
+        nodes.push(start_node(config, index, keypair, &genesis, &consensus_config, clock.clone(), rng.gen()).await?);
+    }
+
+    // Ordered by time, then by when the event was scheduled
+    let mut queue = BTreeMap::new();
+    let mut scheduled = 0u64;
+    let mut schedule = |queue: &mut BTreeMap<(i64, u64), Event>, at: i64, event: Event| {
+        queue.insert((at, scheduled), event);
+        scheduled += 1;
+    };
+    // Nodes tick out of step with each other, like real ones
+    for index in 0..nodes.len() {
+        schedule(&mut queue, rng.gen_range(0..TICK_MS), Event::Tick(index));
+    }
+
+    let end = config.duration as i64 * 1000;
+    let mut finalized: BTreeMap<u64, (BlockHash, usize)> = BTreeMap::new();
+    let mut trace = Sha256::new();
+    let (mut delivered, mut dropped) = (0, 0);
+
+    while let Some(((at, _), event)) = queue.pop_first() {
+        if at > end {
+            break;
+        }
+        clock.set_time(start + Duration::milliseconds(at));
+
+        let index = match event {
+            Event::Tick(index) => {
+                nodes[index].engine.step().await
+                    .map_err(|e| anyhow!("Node {} failed a tick at {}ms: {:#}", index, at, e))?;
+                schedule(&mut queue, at + TICK_MS, Event::Tick(index));
+                index
+            }
+            Event::Deliver(index, message) => {
+                trace.update(at.to_le_bytes());
+                trace.update((index as u64).to_le_bytes());
+                trace.update(network::message_id(&message));
+                delivered += 1;
+                nodes[index].engine.deliver(*message).await
+                    .map_err(|e| anyhow!("Node {} failed to handle a message at {}ms: {:#}", index, at, e))?;
+                index
+            }
+        };
+
+        // Gossip reaches every other node once; copies are lost or delayed independently
+        let mut outgoing = Vec::new();
+        while let Ok(message) = nodes[index].outbox.try_recv() {
+            outgoing.push(message);
+        }
+        // The engine may send in hash map order, which differs between processes
+        outgoing.sort_by_key(network::message_id);
+        for message in outgoing {
+            for peer in (0..nodes.len()).filter(|peer| *peer != index) {
+                if rng.gen_bool(config.drop_rate) {
+                    dropped += 1;
+                    continue;
+                }
+                let delay = rng.gen_range(0..=config.max_delay_ms) as i64;
+                schedule(&mut queue, at + delay, Event::Deliver(peer, Box::new(message.clone())));
+            }
+        }
+
+        loop {
+            let block = match nodes[index].events.try_recv() {
+                Ok(ChainEvent::BlockFinalized(block)) => block,
+                Ok(_) => continue,
+                Err(TryRecvError::Empty) => break,
+                Err(e) => bail!("Node {} missed chain events: {}", index, e),
+            };
+            match finalized.entry(block.block_number) {
+                Entry::Vacant(entry) => {
+                    trace.update(block.block_hash);
+                    entry.insert((block.block_hash, index));
+                }
+                Entry::Occupied(entry) if entry.get().0 != block.block_hash => {
+                    let (other_hash, other) = *entry.get();
+                    bail!("Safety violated with seed {}: block #{} finalized as {} by node {} and as {} by node {} at {}ms",
+                        config.seed, block.block_number, hex::encode(other_hash), other,
+                        hex::encode(block.block_hash), index, at);
+                }
+                Entry::Occupied(_) => {}
+            }
+            nodes[index].finalized = nodes[index].finalized.max(block.block_number);
+        }
+    }
+
+    let report = SimulationReport {
+        seed: config.seed,
+        finalized: nodes.iter().map(|node| node.finalized).min().unwrap_or(0),
+        delivered,
+        dropped,
+        trace: trace.finalize().into(),
+    };
+    info!("🎲 Simulation with seed {} finalized #{} on all {} nodes ({} messages delivered, {} dropped)",
+        report.seed, report.finalized, nodes.len(), report.delivered, report.dropped);
+    Ok(report)
+}
+
+// Every node starts as a validator with the minimum stake
+fn genesis(keypairs: &[NodeKeypair], timestamp: DateTime<Utc>) -> Genesis {
+    let mut validators: Vec<_> = keypairs.iter()
+        .map(|keypair| GenesisValidator { node_id: keypair.node_id(), stake: MIN_VALIDATOR_STAKE })
+        .collect();
+    validators.sort_by_key(|validator| validator.node_id);
+
+    Genesis {
+        chain_id: types::chain_id(),
+        timestamp,
+        consensus: GenesisParams::default(),
+        validators,
+        accounts: Vec::new(),
+    }
+}
+
+async fn start_node(
+    config: &SimulationConfig,
+    index: usize,
+    keypair: NodeKeypair,
+    genesis: &Genesis,
+    consensus_config: &ConsensusConfig,
+    clock: Arc<SimulatedClock>,
+    seed: u64,
+) -> Result<SimNode> {
+    let db_path = config.db_dir.join(format!("node-{}", index));
+    if db_path.exists() {
+        std::fs::remove_dir_all(&db_path)?;
+    }
+    let storage = StorageManager::new(&StorageConfig {
+        db_path: db_path.to_string_lossy().into_owned(),
+        ..Default::default()
+    })?;
+    let genesis_hash = genesis.initialize(&storage).await?;
+
+    // Mock proofs are deterministic and fast; one worker per node is plenty
+    let zk_config = ZkProofConfig { mock: true, workers: 1, ..Default::default() };
+    let zk_generator = Arc::new(ZKProofGenerator::mock()?);
+    let proof_service = ProofService::start(zk_generator.clone(), &zk_config);
+
+    let mut engine = ConsensusEngine::new(zk_generator, proof_service, storage, clock, keypair, consensus_config)?;
+    engine.set_genesis(genesis_hash, genesis.timestamp);
+    engine.set_seed(seed);
+    if let (0, Some(name)) = (index, &config.byzantine) {
+        engine.set_byzantine(super::behavior(name)?);
+    }
+    let (network_tx, outbox) = mpsc::channel(OUTBOX_SIZE);
+    engine.set_network_sender(network_tx);
+    let events = engine.subscribe();
+    engine.resume().await?;
+
+    Ok(SimNode { engine, outbox, events, finalized: 0 })
+}
@@ -0,0 +1,790 @@
+use super::*;
+use crate::bridge::{BridgeProof, BridgeValidator};
+use crate::staking::{
+    Evidence, GovernanceProposal, ProposalAction, ProposalStatus, ProposalTransaction, ProposalVote, ProposalVoteTransaction,
+    StakeChange,
+};
+use std::hash::Hash;
+
+// Canonical encoding of everything that is hashed, signed or gossiped. Unlike bincode the layout
+// is spelled out here field by field, so it does not move with serde derives or library options,
+// and every value has exactly one encoding:
+// - integers are fixed-width little endian, floats their IEEE 754 bits, bools one byte of 0 or 1
+// - fixed-size hashes and ids are written as is
+// - byte strings and lists carry a u32 length, maps are lists of pairs sorted by key
+// - enum variants and options carry a one-byte tag
+// - timestamps are seconds (i64) and then nanoseconds (u32)
+// Gossip messages are prefixed with WIRE_VERSION, which changes whenever this layout does
+pub const WIRE_VERSION: u8 = 10;
+
+pub trait Encode {
+    fn encode_to(&self, out: &mut Vec<u8>);
+
+    fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.encode_to(&mut out);
+        out
+    }
+}
+
+pub trait Decode: Sized {
+    // Reads a value off the front of `input`
+    fn decode_from(input: &mut &[u8]) -> Result<Self>;
+
+    // Trailing bytes are rejected along with everything else that is not a canonical encoding
+    fn decode(mut bytes: &[u8]) -> Result<Self> {
+        let value = Self::decode_from(&mut bytes)?;
+        if !bytes.is_empty() {
+            bail!("{} trailing bytes after value", bytes.len());
+        }
+        Ok(value)
+    }
+}
+
+fn take<'a>(input: &mut &'a [u8], len: usize) -> Result<&'a [u8]> {
+    if input.len() < len {
+        bail!("Unexpected end of input: {} bytes needed, {} left", len, input.len());
+    }
+    let (bytes, rest) = input.split_at(len);
+    *input = rest;
+    Ok(bytes)
+}
+
+fn decode_tag(input: &mut &[u8]) -> Result<u8> {
+    Ok(take(input, 1)?[0])
+}
+
+// Every element takes at least one byte, so a length beyond the remaining input is corrupt and
+// is rejected before anything is allocated for it
+fn decode_len(input: &mut &[u8]) -> Result<usize> {
+    let len = u32::decode_from(input)? as usize;
+    if len > input.len() {
+        bail!("Length {} exceeds the {} bytes left", len, input.len());
+    }
+    Ok(len)
+}
+
+fn encode_len(len: usize, out: &mut Vec<u8>) {
+    (len as u32).encode_to(out);
+}
+
+macro_rules! int_codec {
+    ($($int:ty),*) => {$(
+        impl Encode for $int {
+            fn encode_to(&self, out: &mut Vec<u8>) {
+                out.extend_from_slice(&self.to_le_bytes());
+            }
+        }
+
+        impl Decode for $int {
+            fn decode_from(input: &mut &[u8]) -> Result<Self> {
+                Ok(Self::from_le_bytes(take(input, std::mem::size_of::<Self>())?.try_into()?))
+            }
+        }
+    )*};
+}
+
+int_codec!(u8, u32, u64, i64);
+
+// Fields are encoded in the order listed, which must be the order they are declared in
+macro_rules! struct_codec {
+    ($name:ty { $($field:ident),* $(,)? }) => {
+        impl Encode for $name {
+            fn encode_to(&self, out: &mut Vec<u8>) {
+                $(self.$field.encode_to(out);)*
+            }
+        }
+
+        impl Decode for $name {
+            fn decode_from(input: &mut &[u8]) -> Result<Self> {
+                Ok(Self {
+                    $($field: Decode::decode_from(input)?,)*
+                })
+            }
+        }
+    };
+}
+
+impl Encode for bool {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        out.push(*self as u8);
+    }
+}
+
+impl Decode for bool {
+    fn decode_from(input: &mut &[u8]) -> Result<Self> {
+        match decode_tag(input)? {
+            0 => Ok(false),
+            1 => Ok(true),
+            other => bail!("Invalid bool {}", other),
+        }
+    }
+}
+
+impl Encode for f64 {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        self.to_bits().encode_to(out);
+    }
+}
+
+impl Decode for f64 {
+    fn decode_from(input: &mut &[u8]) -> Result<Self> {
+        Ok(f64::from_bits(u64::decode_from(input)?))
+    }
+}
+
+impl Encode for [u8; 32] {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(self);
+    }
+}
+
+impl Decode for [u8; 32] {
+    fn decode_from(input: &mut &[u8]) -> Result<Self> {
+        Ok(take(input, 32)?.try_into()?)
+    }
+}
+
+impl<T: Encode> Encode for Vec<T> {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        encode_len(self.len(), out);
+        for item in self {
+            item.encode_to(out);
+        }
+    }
+}
+
+impl<T: Decode> Decode for Vec<T> {
+    fn decode_from(input: &mut &[u8]) -> Result<Self> {
+        let len = decode_len(input)?;
+        (0..len).map(|_| T::decode_from(input)).collect()
+    }
+}
+
+impl<T: Encode> Encode for Option<T> {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        match self {
+            None => out.push(0),
+            Some(value) => {
+                out.push(1);
+                value.encode_to(out);
+            }
+        }
+    }
+}
+
+impl<T: Decode> Decode for Option<T> {
+    fn decode_from(input: &mut &[u8]) -> Result<Self> {
+        match decode_tag(input)? {
+            0 => Ok(None),
+            1 => Ok(Some(T::decode_from(input)?)),
+            other => bail!("Invalid option tag {}", other),
+        }
+    }
+}
+
+impl<T: Encode> Encode for Box<T> {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        (**self).encode_to(out);
+    }
+}
+
+impl<T: Decode> Decode for Box<T> {
+    fn decode_from(input: &mut &[u8]) -> Result<Self> {
+        Ok(Box::new(T::decode_from(input)?))
+    }
+}
+
+impl<A: Encode, B: Encode> Encode for (A, B) {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        self.0.encode_to(out);
+        self.1.encode_to(out);
+    }
+}
+
+impl<A: Decode, B: Decode> Decode for (A, B) {
+    fn decode_from(input: &mut &[u8]) -> Result<Self> {
+        Ok((A::decode_from(input)?, B::decode_from(input)?))
+    }
+}
+
+impl<K: Encode + Ord, V: Encode> Encode for HashMap<K, V> {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        let mut entries: Vec<_> = self.iter().collect();
+        entries.sort_by(|a, b| a.0.cmp(b.0));
+        encode_len(entries.len(), out);
+        for (key, value) in entries {
+            key.encode_to(out);
+            value.encode_to(out);
+        }
+    }
+}
+
+impl<K: Decode + Ord + Hash, V: Decode> Decode for HashMap<K, V> {
+    fn decode_from(input: &mut &[u8]) -> Result<Self> {
+        let entries: Vec<(K, V)> = Vec::decode_from(input)?;
+        if entries.windows(2).any(|pair| pair[0].0 >= pair[1].0) {
+            bail!("Map keys are not sorted and unique");
+        }
+        Ok(entries.into_iter().collect())
+    }
+}
+
+impl Encode for DateTime<Utc> {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        self.timestamp().encode_to(out);
+        self.timestamp_subsec_nanos().encode_to(out);
+    }
+}
+
+impl Decode for DateTime<Utc> {
+    fn decode_from(input: &mut &[u8]) -> Result<Self> {
+        let seconds = i64::decode_from(input)?;
+        let nanos = u32::decode_from(input)?;
+        match DateTime::from_timestamp(seconds, nanos) {
+            Some(timestamp) => Ok(timestamp),
+            None => bail!("Invalid timestamp {}.{:09}", seconds, nanos),
+        }
+    }
+}
+
+// Fieldless enums, tagged by position
+macro_rules! unit_enum_codec {
+    ($name:ident { $($variant:ident = $tag:literal),* $(,)? }) => {
+        impl Encode for $name {
+            fn encode_to(&self, out: &mut Vec<u8>) {
+                out.push(match self {
+                    $($name::$variant => $tag,)*
+                });
+            }
+        }
+
+        impl Decode for $name {
+            fn decode_from(input: &mut &[u8]) -> Result<Self> {
+                match decode_tag(input)? {
+                    $($tag => Ok($name::$variant),)*
+                    other => bail!("Invalid {} tag {}", stringify!($name), other),
+                }
+            }
+        }
+    };
+}
+
+unit_enum_codec!(ProofType { Groth16 = 0, Plonk = 1, Nova = 2, Checkpoint = 3 });
+unit_enum_codec!(VoteType { Approve = 0, Reject = 1, Abstain = 2 });
+unit_enum_codec!(VoteStep { Prevote = 0, Precommit = 1 });
+unit_enum_codec!(ProposalStatus { Voting = 0, Enacted = 1, Rejected = 2 });
+
+struct_codec!(BlockHeader { block_number, parent_hash, timestamp, merkle_root, state_root, votes_root, validator, difficulty, nonce });
+struct_codec!(ZKProof { proof_data, public_inputs, circuit_version, proof_type });
+struct_codec!(Block { header, transactions, parent_votes, zk_proof, signature });
+struct_codec!(Transaction { id, from, to, amount, fee, nonce, chain_id, timestamp, kind, valid_until, signature });
+struct_codec!(LightBlock { header, zk_proof, signature });
+struct_codec!(CompactBlock { header, zk_proof, signature, tx_ids, parent_votes });
+struct_codec!(BlockVote { block_hash, validator, vote, height, round, step, timestamp, signature });
+struct_codec!(Proposal { height, round, block_hash, proposer, signature });
+struct_codec!(RoundChange { height, round, validator, signature });
+struct_codec!(StakeTransaction { validator, amount });
+struct_codec!(UnstakeTransaction { validator, amount });
+struct_codec!(UnjailTransaction { validator });
+struct_codec!(EvidenceTransaction { evidence });
+struct_codec!(ProposalTransaction { proposer, action });
+struct_codec!(ProposalVoteTransaction { voter, proposal, approve });
+struct_codec!(ConsensusParams { block_time, min_validators, max_validators, quorum_numerator, quorum_denominator });
+struct_codec!(ConsensusState { head, justified, finalized, validators, total_stake, epoch, pending_stake_changes, slashed, params, proposals });
+struct_codec!(GovernanceProposal { id, proposer, action, block_number, voting_ends, votes, status });
+struct_codec!(ProposalVote { voter, approve, block_number });
+struct_codec!(ValidatorInfo { stake, is_active, last_block_time, performance_score, proposed, missed, rewards, missed_votes, jailed_at });
+struct_codec!(PendingStakeChange { validator, change, activation_epoch });
+struct_codec!(SlashRecord { offense, offender, amount, block_number });
+struct_codec!(ProofRequest { block_number, block_hash, request_id, requester, target, signature });
+struct_codec!(ProofResponse { request_id, proof, responder, target });
+struct_codec!(HeaderRequest { from_block, request_id, requester });
+struct_codec!(HeaderResponse { request_id, headers, responder });
+struct_codec!(CertifiedHeader { block, certificate, validators, params });
+struct_codec!(GetBlocks { from_block, to_block, request_id, target });
+struct_codec!(BlocksResponse { request_id, blocks, finalized_height, responder });
+struct_codec!(EpochChange { epoch, start_block, block_hash, validators, total_stake });
+struct_codec!(SnapshotRequest { request_id, requester, target, height });
+struct_codec!(SnapshotChunk { request_id, responder, height, archive_hash, index, total, data });
+struct_codec!(GetBlockTxs { block_hash, tx_ids, requester });
+struct_codec!(BlockTxs { block_hash, transactions, target });
+struct_codec!(VoteBundle { votes });
+struct_codec!(FinalityCertificate { block_hash, block_number, round, precommits });
+struct_codec!(BridgeValidator { node_id, stake });
+struct_codec!(BridgeProof { block, certificate, validators });
+struct_codec!(GetFinalityCertificate { from_block, to_block, requester, target });
+struct_codec!(CertificateResponse { certificates, target });
+
+impl Encode for TransactionKind {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        match self {
+            TransactionKind::Transfer => out.push(0),
+            TransactionKind::Stake(stake) => {
+                out.push(1);
+                stake.encode_to(out);
+            }
+            TransactionKind::Unstake(unstake) => {
+                out.push(2);
+                unstake.encode_to(out);
+            }
+            TransactionKind::Evidence(evidence) => {
+                out.push(3);
+                evidence.encode_to(out);
+            }
+            TransactionKind::Unjail(unjail) => {
+                out.push(4);
+                unjail.encode_to(out);
+            }
+            TransactionKind::Proposal(proposal) => {
+                out.push(5);
+                proposal.encode_to(out);
+            }
+            TransactionKind::ProposalVote(vote) => {
+                out.push(6);
+                vote.encode_to(out);
+            }
+        }
+    }
+}
+
+impl Decode for TransactionKind {
+    fn decode_from(input: &mut &[u8]) -> Result<Self> {
+        Ok(match decode_tag(input)? {
+            0 => TransactionKind::Transfer,
+            1 => TransactionKind::Stake(Decode::decode_from(input)?),
+            2 => TransactionKind::Unstake(Decode::decode_from(input)?),
+            3 => TransactionKind::Evidence(Decode::decode_from(input)?),
+            4 => TransactionKind::Unjail(Decode::decode_from(input)?),
+            5 => TransactionKind::Proposal(Decode::decode_from(input)?),
+            6 => TransactionKind::ProposalVote(Decode::decode_from(input)?),
+            other => bail!("Invalid transaction kind {}", other),
+        })
+    }
+}
+
+impl Encode for Expiry {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        match self {
+            Expiry::Height(height) => {
+                out.push(0);
+                height.encode_to(out);
+            }
+            Expiry::Time(time) => {
+                out.push(1);
+                time.encode_to(out);
+            }
+        }
+    }
+}
+
+impl Decode for Expiry {
+    fn decode_from(input: &mut &[u8]) -> Result<Self> {
+        Ok(match decode_tag(input)? {
+            0 => Expiry::Height(u64::decode_from(input)?),
+            1 => Expiry::Time(DateTime::decode_from(input)?),
+            other => bail!("Invalid expiry {}", other),
+        })
+    }
+}
+
+impl Encode for StakeChange {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        let (tag, amount) = match self {
+            StakeChange::Bond(amount) => (0, amount),
+            StakeChange::Unbond(amount) => (1, amount),
+            StakeChange::Unjail => return out.push(2),
+        };
+        out.push(tag);
+        amount.encode_to(out);
+    }
+}
+
+impl Decode for StakeChange {
+    fn decode_from(input: &mut &[u8]) -> Result<Self> {
+        Ok(match decode_tag(input)? {
+            0 => StakeChange::Bond(u64::decode_from(input)?),
+            1 => StakeChange::Unbond(u64::decode_from(input)?),
+            2 => StakeChange::Unjail,
+            other => bail!("Invalid stake change {}", other),
+        })
+    }
+}
+
+impl Encode for ProposalAction {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        match self {
+            ProposalAction::ChangeParams(params) => {
+                out.push(0);
+                params.encode_to(out);
+            }
+            ProposalAction::RemoveValidator(node_id) => {
+                out.push(1);
+                node_id.encode_to(out);
+            }
+        }
+    }
+}
+
+impl Decode for ProposalAction {
+    fn decode_from(input: &mut &[u8]) -> Result<Self> {
+        Ok(match decode_tag(input)? {
+            0 => ProposalAction::ChangeParams(Decode::decode_from(input)?),
+            1 => ProposalAction::RemoveValidator(Decode::decode_from(input)?),
+            other => bail!("Invalid proposal action {}", other),
+        })
+    }
+}
+
+impl Encode for Evidence {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        match self {
+            Evidence::DoubleVote(first, second) => {
+                out.push(0);
+                first.encode_to(out);
+                second.encode_to(out);
+            }
+            Evidence::InvalidProof(block) => {
+                out.push(1);
+                block.encode_to(out);
+            }
+        }
+    }
+}
+
+impl Decode for Evidence {
+    fn decode_from(input: &mut &[u8]) -> Result<Self> {
+        Ok(match decode_tag(input)? {
+            0 => Evidence::DoubleVote(BlockVote::decode_from(input)?, BlockVote::decode_from(input)?),
+            1 => Evidence::InvalidProof(LightBlock::decode_from(input)?),
+            other => bail!("Invalid evidence {}", other),
+        })
+    }
+}
+
+// Tags follow the order of the variants; new messages are added at the end
+macro_rules! message_codec {
+    ($($variant:ident = $tag:literal),* $(,)?) => {
+        impl Encode for ConsensusMessage {
+            fn encode_to(&self, out: &mut Vec<u8>) {
+                match self {
+                    $(ConsensusMessage::$variant(message) => {
+                        out.push($tag);
+                        message.encode_to(out);
+                    })*
+                }
+            }
+        }
+
+        impl Decode for ConsensusMessage {
+            fn decode_from(input: &mut &[u8]) -> Result<Self> {
+                Ok(match decode_tag(input)? {
+                    $($tag => ConsensusMessage::$variant(Decode::decode_from(input)?),)*
+                    other => bail!("Invalid message tag {}", other),
+                })
+            }
+        }
+    };
+}
+
+message_codec!(
+    NewBlock = 0,
+    BlockVote = 1,
+    // 2 carried a whole consensus state, which nodes only take from finalized blocks and snapshots
+    ZKProofRequest = 3,
+    ZKProofResponse = 4,
+    Proposal = 5,
+    HeaderRequest = 6,
+    HeaderResponse = 7,
+    GetBlocks = 8,
+    BlocksResponse = 9,
+    EpochChange = 10,
+    GetSnapshot = 11,
+    SnapshotChunk = 12,
+    CompactBlock = 13,
+    GetBlockTxs = 14,
+    BlockTxs = 15,
+    RoundChange = 16,
+    VoteBundle = 17,
+    GetFinalityCertificate = 18,
+    FinalityCertificate = 19,
+);
+
+impl ConsensusMessage {
+    // What goes out over gossip: the wire version, then the message
+    pub fn to_wire(&self) -> Vec<u8> {
+        let mut out = vec![WIRE_VERSION];
+        self.encode_to(&mut out);
+        out
+    }
+
+    pub fn from_wire(data: &[u8]) -> Result<Self> {
+        match data.split_first() {
+            Some((&WIRE_VERSION, message)) => Self::decode(message),
+            Some((version, _)) => bail!("Unsupported wire version {}", version),
+            None => bail!("Empty message"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::staking::{PendingStakeChange, SlashRecord};
+
+    fn timestamp(seconds: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(1_700_000_000 + seconds, 123_456_789).unwrap()
+    }
+
+    fn header(block_number: u64) -> BlockHeader {
+        BlockHeader {
+            block_number,
+            parent_hash: [1; 32],
+            timestamp: timestamp(block_number as i64),
+            merkle_root: [2; 32],
+            state_root: [3; 32],
+            votes_root: [4; 32],
+            validator: [5; 32],
+            difficulty: 1000,
+            nonce: u64::MAX,
+        }
+    }
+
+    fn proof() -> ZKProof {
+        ZKProof {
+            proof_data: vec![6; 192],
+            public_inputs: vec![7; 40],
+            circuit_version: 1,
+            proof_type: ProofType::Plonk,
+        }
+    }
+
+    fn vote(step: VoteStep) -> BlockVote {
+        BlockVote {
+            block_hash: [8; 32],
+            validator: [9; 32],
+            vote: VoteType::Approve,
+            height: 7,
+            round: 2,
+            step,
+            timestamp: timestamp(3),
+            signature: vec![10; 64],
+        }
+    }
+
+    fn light_block() -> LightBlock {
+        LightBlock { header: header(7), zk_proof: proof(), signature: vec![11; 64] }
+    }
+
+    fn certificate() -> FinalityCertificate {
+        FinalityCertificate { block_hash: [8; 32], block_number: 7, round: 2, precommits: vec![vote(VoteStep::Precommit)] }
+    }
+
+    fn params() -> ConsensusParams {
+        ConsensusParams::default()
+    }
+
+    // One transaction of every kind, covering the tagged enums they carry
+    fn transactions() -> Vec<Transaction> {
+        let kinds = vec![
+            TransactionKind::Transfer,
+            TransactionKind::Stake(StakeTransaction { validator: [12; 32], amount: 500 }),
+            TransactionKind::Unstake(UnstakeTransaction { validator: [12; 32], amount: 200 }),
+            TransactionKind::Evidence(Box::new(EvidenceTransaction {
+                evidence: Evidence::DoubleVote(vote(VoteStep::Prevote), vote(VoteStep::Precommit)),
+            })),
+            TransactionKind::Evidence(Box::new(EvidenceTransaction { evidence: Evidence::InvalidProof(light_block()) })),
+            TransactionKind::Unjail(UnjailTransaction { validator: [12; 32] }),
+            TransactionKind::Proposal(ProposalTransaction { proposer: [12; 32], action: ProposalAction::ChangeParams(params()) }),
+            TransactionKind::Proposal(ProposalTransaction { proposer: [12; 32], action: ProposalAction::RemoveValidator([13; 32]) }),
+            TransactionKind::ProposalVote(ProposalVoteTransaction { voter: [12; 32], proposal: [14; 32], approve: true }),
+        ];
+        kinds.into_iter().enumerate()
+            .map(|(i, kind)| Transaction {
+                id: [i as u8; 32],
+                from: [15; 32],
+                to: [16; 32],
+                amount: 100,
+                fee: 1,
+                nonce: i as u64,
+                chain_id: 1,
+                timestamp: timestamp(i as i64),
+                kind,
+                valid_until: match i % 3 {
+                    0 => None,
+                    1 => Some(Expiry::Height(90)),
+                    _ => Some(Expiry::Time(timestamp(60))),
+                },
+                signature: vec![17; 64],
+            })
+            .collect()
+    }
+
+    fn block() -> Block {
+        Block {
+            header: header(7),
+            transactions: transactions(),
+            parent_votes: vec![vote(VoteStep::Precommit)],
+            zk_proof: proof(),
+            signature: vec![11; 64],
+        }
+    }
+
+    // A single validator, so the map's Debug output does not depend on iteration order
+    fn consensus_state() -> ConsensusState {
+        let validator = ValidatorInfo {
+            stake: 1000,
+            is_active: true,
+            last_block_time: timestamp(0),
+            performance_score: 0.75,
+            proposed: 3,
+            missed: 1,
+            rewards: 40,
+            missed_votes: 2,
+            jailed_at: Some(5),
+        };
+        ConsensusState {
+            head: 9,
+            justified: 8,
+            finalized: 7,
+            validators: HashMap::from([([12; 32], validator)]),
+            total_stake: 1000,
+            epoch: 1,
+            pending_stake_changes: [StakeChange::Bond(10), StakeChange::Unbond(20), StakeChange::Unjail].into_iter()
+                .map(|change| PendingStakeChange { validator: [12; 32], change, activation_epoch: 2 })
+                .collect(),
+            slashed: vec![SlashRecord { offense: [18; 32], offender: [12; 32], amount: 50, block_number: 6 }],
+            params: params(),
+            proposals: vec![GovernanceProposal {
+                id: [14; 32],
+                proposer: [12; 32],
+                action: ProposalAction::RemoveValidator([13; 32]),
+                block_number: 4,
+                voting_ends: 20,
+                votes: vec![ProposalVote { voter: [12; 32], approve: false, block_number: 5 }],
+                status: ProposalStatus::Voting,
+            }],
+        }
+    }
+
+    fn messages() -> Vec<ConsensusMessage> {
+        vec![
+            ConsensusMessage::NewBlock(block()),
+            ConsensusMessage::BlockVote(vote(VoteStep::Prevote)),
+            ConsensusMessage::ZKProofRequest(ProofRequest {
+                block_number: 7, block_hash: [8; 32], request_id: [19; 32], requester: [9; 32], target: [12; 32], signature: vec![20; 64],
+            }),
+            ConsensusMessage::ZKProofResponse(ProofResponse { request_id: [19; 32], proof: proof(), responder: [12; 32], target: [9; 32] }),
+            ConsensusMessage::Proposal(Proposal { height: 7, round: 2, block_hash: [8; 32], proposer: [5; 32], signature: vec![21; 64] }),
+            ConsensusMessage::HeaderRequest(HeaderRequest { from_block: 3, request_id: [19; 32], requester: [9; 32] }),
+            ConsensusMessage::HeaderResponse(HeaderResponse {
+                request_id: [19; 32],
+                headers: vec![
+                    CertifiedHeader { block: light_block(), certificate: certificate(), validators: None, params: None },
+                    CertifiedHeader { block: light_block(), certificate: certificate(), validators: Some(vec![([12; 32], 1000)]), params: Some(params()) },
+                ],
+                responder: [12; 32],
+            }),
+            ConsensusMessage::GetBlocks(GetBlocks { from_block: 1, to_block: 9, request_id: [19; 32], target: Some([12; 32]) }),
+            ConsensusMessage::BlocksResponse(BlocksResponse { request_id: [19; 32], blocks: vec![block()], finalized_height: 7, responder: [12; 32] }),
+            ConsensusMessage::EpochChange(EpochChange { epoch: 1, start_block: 100, block_hash: [8; 32], validators: vec![([12; 32], 1000)], total_stake: 1000 }),
+            ConsensusMessage::GetSnapshot(SnapshotRequest { request_id: [19; 32], requester: [9; 32], target: [12; 32], height: Some(100) }),
+            ConsensusMessage::SnapshotChunk(SnapshotChunk {
+                request_id: [19; 32], responder: [12; 32], height: 100, archive_hash: [22; 32], index: 1, total: 3, data: vec![23; 1024],
+            }),
+            ConsensusMessage::CompactBlock(CompactBlock {
+                header: header(7), zk_proof: proof(), signature: vec![11; 64], tx_ids: vec![[0; 32], [1; 32]], parent_votes: vec![vote(VoteStep::Precommit)],
+            }),
+            ConsensusMessage::GetBlockTxs(GetBlockTxs { block_hash: [8; 32], tx_ids: vec![[1; 32]], requester: [9; 32] }),
+            ConsensusMessage::BlockTxs(BlockTxs { block_hash: [8; 32], transactions: transactions(), target: [9; 32] }),
+            ConsensusMessage::RoundChange(RoundChange { height: 7, round: 3, validator: [9; 32], signature: vec![24; 64] }),
+            ConsensusMessage::VoteBundle(VoteBundle { votes: vec![vote(VoteStep::Prevote), vote(VoteStep::Precommit)] }),
+            ConsensusMessage::GetFinalityCertificate(GetFinalityCertificate { from_block: 1, to_block: 7, requester: [9; 32], target: None }),
+            ConsensusMessage::FinalityCertificate(CertificateResponse { certificates: vec![certificate()], target: [9; 32] }),
+        ]
+    }
+
+    #[test]
+    fn every_message_round_trips() {
+        let messages = messages();
+        // A message added to the enum needs a case here
+        assert_eq!(messages.len(), 19);
+
+        for message in messages {
+            let wire = message.to_wire();
+            let decoded = ConsensusMessage::from_wire(&wire).unwrap();
+            assert_eq!(format!("{:?}", decoded), format!("{:?}", message));
+            assert_eq!(decoded.to_wire(), wire);
+        }
+    }
+
+    #[test]
+    fn consensus_state_round_trips() {
+        let state = consensus_state();
+        let encoded = state.encode();
+        let decoded = ConsensusState::decode(&encoded).unwrap();
+        assert_eq!(format!("{:?}", decoded), format!("{:?}", state));
+    }
+
+    #[test]
+    fn every_truncation_is_rejected() {
+        for message in messages() {
+            let wire = message.to_wire();
+            for len in 0..wire.len() {
+                assert!(ConsensusMessage::from_wire(&wire[..len]).is_err(), "{:?} decoded from {} of {} bytes", message, len, wire.len());
+            }
+        }
+    }
+
+    #[test]
+    fn trailing_bytes_are_rejected() {
+        let mut wire = ConsensusMessage::BlockVote(vote(VoteStep::Prevote)).to_wire();
+        wire.push(0);
+        assert!(ConsensusMessage::from_wire(&wire).is_err());
+    }
+
+    #[test]
+    fn unknown_tags_and_versions_are_rejected() {
+        let wire = ConsensusMessage::BlockVote(vote(VoteStep::Prevote)).to_wire();
+
+        let mut other_version = wire.clone();
+        other_version[0] = WIRE_VERSION + 1;
+        assert!(ConsensusMessage::from_wire(&other_version).is_err());
+
+        let mut unknown_message = wire.clone();
+        unknown_message[1] = 20;
+        assert!(ConsensusMessage::from_wire(&unknown_message).is_err());
+
+        // Consensus states are no longer gossiped
+        let mut retired_message = wire.clone();
+        retired_message[1] = 2;
+        assert!(ConsensusMessage::from_wire(&retired_message).is_err());
+
+        // The vote type follows the block hash and validator
+        let mut unknown_vote = wire;
+        unknown_vote[2 + 64] = 3;
+        assert!(ConsensusMessage::from_wire(&unknown_vote).is_err());
+    }
+
+    #[test]
+    fn non_canonical_values_are_rejected() {
+        assert!(bool::decode(&[2]).is_err());
+        assert!(Option::<u64>::decode(&[2]).is_err());
+        // Length beyond the input
+        assert!(Vec::<u8>::decode(&[5, 0, 0, 0, 1]).is_err());
+
+        // Map keys out of order
+        let mut unsorted = Vec::new();
+        encode_len(2, &mut unsorted);
+        for key in [2u64, 1] {
+            key.encode_to(&mut unsorted);
+            0u64.encode_to(&mut unsorted);
+        }
+        assert!(HashMap::<u64, u64>::decode(&unsorted).is_err());
+
+        let mut out_of_range = Vec::new();
+        0i64.encode_to(&mut out_of_range);
+        2_000_000_000u32.encode_to(&mut out_of_range);
+        assert!(DateTime::<Utc>::decode(&out_of_range).is_err());
+    }
+}
@@ -0,0 +1,121 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Sha256, Digest};
+use std::sync::OnceLock;
+
+use super::{poseidon, BlockHash};
+
+// Hash behind block hashes, merkle roots and proof inputs. Signatures and network message ids
+// stay on SHA-256, since they never end up inside a circuit
+pub trait Hasher: Send + Sync {
+    // Hash of the concatenated parts
+    fn hash(&self, parts: &[&[u8]]) -> BlockHash;
+
+    // Parent of two merkle tree nodes
+    fn hash_pair(&self, left: &BlockHash, right: &BlockHash) -> BlockHash {
+        self.hash(&[left, right])
+    }
+
+    // Merkle tree leaf of a transaction hash
+    fn merkle_leaf(&self, hash: &BlockHash) -> BlockHash {
+        *hash
+    }
+}
+
+pub struct Sha256Hasher;
+
+pub struct Blake3Hasher;
+
+// Merkle nodes are field elements, so leaves are reduced into the field and parents are a single
+// permutation over both children
+pub struct PoseidonHasher;
+
+impl Hasher for Sha256Hasher {
+    fn hash(&self, parts: &[&[u8]]) -> BlockHash {
+        let mut hasher = Sha256::new();
+        for part in parts {
+            hasher.update(part);
+        }
+        hasher.finalize().into()
+    }
+}
+
+impl Hasher for Blake3Hasher {
+    fn hash(&self, parts: &[&[u8]]) -> BlockHash {
+        let mut hasher = blake3::Hasher::new();
+        for part in parts {
+            hasher.update(part);
+        }
+        hasher.finalize().into()
+    }
+}
+
+impl Hasher for PoseidonHasher {
+    fn hash(&self, parts: &[&[u8]]) -> BlockHash {
+        poseidon::hash_bytes(parts)
+    }
+
+    fn hash_pair(&self, left: &BlockHash, right: &BlockHash) -> BlockHash {
+        poseidon::compress(left, right)
+    }
+
+    fn merkle_leaf(&self, hash: &BlockHash) -> BlockHash {
+        poseidon::to_field(hash)
+    }
+}
+
+// Chosen per chain in its genesis file
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HashFunction {
+    #[default]
+    Sha256,
+    Blake3,
+    Poseidon,
+}
+
+impl HashFunction {
+    pub fn hasher(self) -> &'static dyn Hasher {
+        match self {
+            HashFunction::Sha256 => &Sha256Hasher,
+            HashFunction::Blake3 => &Blake3Hasher,
+            HashFunction::Poseidon => &PoseidonHasher,
+        }
+    }
+
+    pub fn is_default(&self) -> bool {
+        *self == HashFunction::default()
+    }
+}
+
+static HASH_FUNCTION: OnceLock<HashFunction> = OnceLock::new();
+
+// Set once at startup, from the genesis file, before any block is hashed
+pub fn set_hash_function(hash_function: HashFunction) -> Result<()> {
+    HASH_FUNCTION.set(hash_function).map_err(|_| anyhow!("Hash function is already set"))
+}
+
+pub fn hash_function() -> HashFunction {
+    HASH_FUNCTION.get().copied().unwrap_or_default()
+}
+
+pub fn hasher() -> &'static dyn Hasher {
+    hash_function().hasher()
+}
+
+static MERKLE_HASH_FUNCTION: OnceLock<HashFunction> = OnceLock::new();
+
+// Set once at startup like the hash function, for chains whose transaction merkle trees use
+// another one, such as Poseidon to keep them cheap to recompute in a circuit
+pub fn set_merkle_hash_function(hash_function: HashFunction) -> Result<()> {
+    MERKLE_HASH_FUNCTION.set(hash_function).map_err(|_| anyhow!("Merkle hash function is already set"))
+}
+
+// The chain's hash function unless it sets one for merkle trees
+pub fn merkle_hash_function() -> HashFunction {
+    MERKLE_HASH_FUNCTION.get().copied().unwrap_or_else(hash_function)
+}
+
+pub fn merkle_hasher() -> &'static dyn Hasher {
+    merkle_hash_function().hasher()
+}
@@ -1,25 +1,62 @@
 use serde::{Deserialize, Serialize};
 use chrono::{DateTime, Utc};
 use sha2::{Sha256, Digest};
+use anyhow::{anyhow, bail, Result};
 use std::collections::HashMap;
+use std::sync::OnceLock;
+use crate::consensus::DetectedViolation;
+use crate::staking::{
+    EvidenceTransaction, GovernanceProposal, PendingStakeChange, ProposalTransaction, ProposalVoteTransaction, SlashRecord,
+    StakeTransaction, UnjailTransaction, UnstakeTransaction,
+};
+
+mod codec;
+mod hasher;
+pub mod poseidon;
+
+pub use codec::{Decode, Encode, WIRE_VERSION};
+pub use hasher::{
+    hasher, hash_function, merkle_hash_function, merkle_hasher, set_hash_function, set_merkle_hash_function,
+    HashFunction,
+};
 
 pub type BlockHash = [u8; 32];
 pub type NodeId = [u8; 32];
 pub type ProofHash = [u8; 32];
 
+// Transactions commit to the network they were signed for, so they can not be replayed on another.
+// Chains started without a genesis file use the default id
+pub const DEFAULT_CHAIN_ID: u64 = 1;
+static CHAIN_ID: OnceLock<u64> = OnceLock::new();
+
+// Set once at startup, from the genesis file
+pub fn set_chain_id(chain_id: u64) -> Result<()> {
+    CHAIN_ID.set(chain_id).map_err(|_| anyhow!("Chain id is already set"))
+}
+
+pub fn chain_id() -> u64 {
+    CHAIN_ID.get().copied().unwrap_or(DEFAULT_CHAIN_ID)
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Block {
     pub header: BlockHeader,
     pub transactions: Vec<Transaction>,
+    // Precommits for the parent block, sorted by validator; their signers share the block reward
+    pub parent_votes: Vec<BlockVote>,
     pub zk_proof: ZKProof,
+    pub signature: Vec<u8>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct BlockHeader {
     pub block_number: u64,
     pub parent_hash: BlockHash,
     pub timestamp: DateTime<Utc>,
     pub merkle_root: BlockHash,
+    // Account state after executing the block
+    pub state_root: BlockHash,
+    pub votes_root: BlockHash,
     pub validator: NodeId,
     pub difficulty: u64,
     pub nonce: u64,
@@ -31,31 +68,97 @@ pub struct Transaction {
     pub from: [u8; 32],
     pub to: [u8; 32],
     pub amount: u64,
+    // Paid by the sender to the proposer of the including block
+    pub fee: u64,
+    // Number of earlier transactions from the same sender
+    pub nonce: u64,
+    pub chain_id: u64,
     pub timestamp: DateTime<Utc>,
+    #[serde(default)]
+    pub kind: TransactionKind,
+    // Past this the transaction can no longer be included, and pools drop it
+    #[serde(default)]
+    pub valid_until: Option<Expiry>,
     pub signature: Vec<u8>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Expiry {
+    // Last block number that may include the transaction
+    Height(u64),
+    // Latest block timestamp that may include it
+    Time(DateTime<Utc>),
+}
+
+// Outcome of a transaction in a finalized block. Transactions that fail to execute invalidate
+// their block, so the only other outcome is a staking transaction the staking module ignored
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReceiptStatus {
+    Success,
+    Ignored,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TransactionReceipt {
+    pub tx_id: [u8; 32],
+    pub block_hash: BlockHash,
+    pub block_number: u64,
+    // Position of the transaction in the block
+    pub index: u32,
+    pub status: ReceiptStatus,
+    // Paid to the proposer whatever the status
+    pub fee: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+pub enum TransactionKind {
+    #[default]
+    Transfer,
+    Stake(StakeTransaction),
+    Unstake(UnstakeTransaction),
+    Evidence(Box<EvidenceTransaction>),
+    Unjail(UnjailTransaction),
+    Proposal(ProposalTransaction),
+    ProposalVote(ProposalVoteTransaction),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct ZKProof {
     pub proof_data: Vec<u8>,
     pub public_inputs: Vec<u8>,
-    pub verification_key: Vec<u8>,
+    // Circuit the proof was made with; it is verified against the keys the chain registers for
+    // this version. 0 for blocks carrying no proof
+    pub circuit_version: u32,
     pub proof_type: ProofType,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub enum ProofType {
     Groth16,
     Plonk,
     Nova,
+    // Not a proof: a block the proof policy does not prove names the last checkpoint instead
+    Checkpoint,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ConsensusState {
-    pub current_block: u64,
+    // Tip of the canonical chain. Blocks up to it are applied to the state, final or not
+    pub head: u64,
+    // Highest block a prevote quorum was seen for; at least `finalized`
+    pub justified: u64,
+    // Highest block a precommit quorum committed; blocks up to it are never reverted
+    pub finalized: u64,
     pub validators: HashMap<NodeId, ValidatorInfo>,
     pub total_stake: u64,
     pub epoch: u64,
+    pub pending_stake_changes: Vec<PendingStakeChange>,
+    pub slashed: Vec<SlashRecord>,
+    pub params: ConsensusParams,
+    // Governance proposals still open, and those decided as the epoch began
+    pub proposals: Vec<GovernanceProposal>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -63,39 +166,103 @@ pub struct ValidatorInfo {
     pub stake: u64,
     pub is_active: bool,
     pub last_block_time: DateTime<Utc>,
+    // Share of its proposal slots the validator filled, weighted towards recent epochs
     pub performance_score: f64,
+    // Proposal slots filled and missed so far this epoch; folded into the score when it ends
+    #[serde(default)]
+    pub proposed: u64,
+    #[serde(default)]
+    pub missed: u64,
+    // Block rewards earned so far, proposing and voting
+    #[serde(default)]
+    pub rewards: u64,
+    // Blocks so far this epoch that carry no precommit of the validator for their parent
+    #[serde(default)]
+    pub missed_votes: u64,
+    // Block the validator was jailed in for missing too many slots or votes. Jailed validators
+    // stay out of the active set until an unjail transaction lets them back in
+    #[serde(default)]
+    pub jailed_at: Option<u64>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ConsensusMessage {
     NewBlock(Block),
     BlockVote(BlockVote),
-    ConsensusState(ConsensusState),
     ZKProofRequest(ProofRequest),
     ZKProofResponse(ProofResponse),
+    Proposal(Proposal),
+    HeaderRequest(HeaderRequest),
+    HeaderResponse(HeaderResponse),
+    GetBlocks(GetBlocks),
+    BlocksResponse(BlocksResponse),
+    EpochChange(EpochChange),
+    GetSnapshot(SnapshotRequest),
+    SnapshotChunk(SnapshotChunk),
+    CompactBlock(CompactBlock),
+    GetBlockTxs(GetBlockTxs),
+    BlockTxs(BlockTxs),
+    RoundChange(RoundChange),
+    VoteBundle(VoteBundle),
+    GetFinalityCertificate(GetFinalityCertificate),
+    FinalityCertificate(CertificateResponse),
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct BlockVote {
     pub block_hash: BlockHash,
     pub validator: NodeId,
     pub vote: VoteType,
+    pub height: u64,
+    pub round: u64,
+    pub step: VoteStep,
     pub timestamp: DateTime<Utc>,
     pub signature: Vec<u8>,
 }
 
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum VoteStep {
+    Prevote,
+    Precommit,
+}
+
+// Re-proposal of an already known block by the proposer of a later round
 #[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Proposal {
+    pub height: u64,
+    pub round: u64,
+    pub block_hash: BlockHash,
+    pub proposer: NodeId,
+    pub signature: Vec<u8>,
+}
+
+// Sent by a validator that saw no valid proposal in time. Once more than 2/3 of the stake asks
+// for a round, everyone moves there without waiting out the current one
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RoundChange {
+    pub height: u64,
+    pub round: u64,
+    pub validator: NodeId,
+    pub signature: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum VoteType {
     Approve,
     Reject,
     Abstain,
 }
 
+// Asks one validator for a proof of a block; only the target answers
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProofRequest {
     pub block_number: u64,
+    pub block_hash: BlockHash,
     pub request_id: [u8; 32],
     pub requester: NodeId,
+    pub target: NodeId,
+    // By the requester, so it is served under its own quota and priority
+    pub signature: Vec<u8>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -103,14 +270,256 @@ pub struct ProofResponse {
     pub request_id: [u8; 32],
     pub proof: ZKProof,
     pub responder: NodeId,
+    // The requester; everyone else drops the response
+    pub target: NodeId,
+}
+
+// Finalized block without its transactions, which is all a light client downloads
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct LightBlock {
+    pub header: BlockHeader,
+    pub zk_proof: ZKProof,
+    pub signature: Vec<u8>,
+}
+
+// Proposed block as gossiped: receivers fill in the transactions from their pending pool and
+// ask for the ones they are missing
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompactBlock {
+    pub header: BlockHeader,
+    pub zk_proof: ZKProof,
+    pub signature: Vec<u8>,
+    pub tx_ids: Vec<[u8; 32]>,
+    pub parent_votes: Vec<BlockVote>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GetBlockTxs {
+    pub block_hash: BlockHash,
+    pub tx_ids: Vec<[u8; 32]>,
+    pub requester: NodeId,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlockTxs {
+    pub block_hash: BlockHash,
+    pub transactions: Vec<Transaction>,
+    pub target: NodeId,
+}
+
+// Asks for the certificates of the finalized blocks in a range, from one peer or, without a
+// target, from anyone holding them
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GetFinalityCertificate {
+    pub from_block: u64,
+    pub to_block: u64,
+    pub requester: NodeId,
+    pub target: Option<NodeId>,
+}
+
+// Carries no responder, so the same certificates from several peers are delivered once
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CertificateResponse {
+    pub certificates: Vec<FinalityCertificate>,
+    pub target: NodeId,
+}
+
+// Votes gossiped together to save per-message overhead; each is handled as if it came on its own
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VoteBundle {
+    pub votes: Vec<BlockVote>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HeaderRequest {
+    pub from_block: u64,
+    pub request_id: [u8; 32],
+    pub requester: NodeId,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HeaderResponse {
+    pub request_id: [u8; 32],
+    pub headers: Vec<CertifiedHeader>,
+    pub responder: NodeId,
+}
+
+// Finalized header with the precommits it was finalized with. Carries the active validators
+// and their stake after the block when they differ from those before it, and always on the
+// first header of a response, so light clients can follow the set the next precommits come from
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CertifiedHeader {
+    pub block: LightBlock,
+    pub certificate: FinalityCertificate,
+    pub validators: Option<Vec<(NodeId, u64)>>,
+    // Sent along with the validators, whose quorum they set
+    pub params: Option<ConsensusParams>,
+}
+
+// Range of finalized blocks requested by a syncing node, from one peer or any that has them
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GetBlocks {
+    pub from_block: u64,
+    pub to_block: u64,
+    pub request_id: [u8; 32],
+    pub target: Option<NodeId>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlocksResponse {
+    pub request_id: [u8; 32],
+    pub blocks: Vec<Block>,
+    pub finalized_height: u64,
+    pub responder: NodeId,
+}
+
+// Asks one peer for a snapshot of its finalized state, so a new node can skip replaying the chain
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotRequest {
+    pub request_id: [u8; 32],
+    pub requester: NodeId,
+    pub target: NodeId,
+    // A finalized block to take the snapshot at, such as a trusted checkpoint; None for the latest
+    pub height: Option<u64>,
+}
+
+// Piece of a compressed snapshot archive; whole archives do not fit in a gossip message
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotChunk {
+    pub request_id: [u8; 32],
+    pub responder: NodeId,
+    pub height: u64,
+    pub archive_hash: [u8; 32],
+    pub index: u32,
+    pub total: u32,
+    pub data: Vec<u8>,
+}
+
+// Active validator set rotated in by the first block of an epoch; also stored per epoch
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct EpochChange {
+    pub epoch: u64,
+    pub start_block: u64,
+    pub block_hash: BlockHash,
+    // Sorted by node id
+    pub validators: Vec<(NodeId, u64)>,
+    pub total_stake: u64,
+}
+
+// What the active validators did during an epoch as this node saw it. Proposals come from
+// the chain; votes and proof responses are what reached us
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EpochStats {
+    pub epoch: u64,
+    // Finalized blocks of the epoch
+    pub blocks: u64,
+    pub validators: HashMap<NodeId, ValidatorStats>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ValidatorStats {
+    pub proposed: u64,
+    pub missed: u64,
+    // Finalized blocks that carry no precommit of it for their parent
+    #[serde(default)]
+    pub missed_votes: u64,
+    // Score the validator served the epoch with
+    pub performance_score: f64,
+    // Finalized blocks its precommit was counted for
+    pub votes: u64,
+    // Valid proofs it sent for our proof requests, and how long they took in total
+    pub proof_responses: u64,
+    pub proof_latency_ms: u64,
+}
+
+// Where we stand at the current height, written before each of our votes goes out so a
+// restarted validator resumes the height without signing a conflicting vote
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RoundCheckpoint {
+    pub height: u64,
+    pub round: u64,
+    pub proposed: bool,
+    pub locked: Option<(BlockHash, u64)>,
+    pub valid: Option<(BlockHash, u64)>,
+    // Every vote we cast at this height
+    pub votes: Vec<BlockVote>,
+}
+
+// Precommits a block was committed with, stored per block and announced to subscribers
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct BlockFinalized {
+    pub block_hash: BlockHash,
+    pub block_number: u64,
+    pub round: u64,
+    // Active validators that precommitted the block with their stake, sorted by node id
+    pub validators: Vec<(NodeId, u64)>,
+    pub weight: u64,
+    pub total_stake: u64,
+}
+
+// The signed precommits a block was committed with. Anyone holding the validator set the block
+// was committed under can check them, so a node can prove the finality of its blocks to others
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct FinalityCertificate {
+    pub block_hash: BlockHash,
+    pub block_number: u64,
+    pub round: u64,
+    // Sorted by validator, one each
+    pub precommits: Vec<BlockVote>,
+}
+
+// What consensus announces to the rest of the node; subscribers react without the engine knowing them
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ChainEvent {
+    // Stored on the canonical chain or on a side branch
+    BlockImported { block_hash: BlockHash, block_number: u64 },
+    BlockFinalized(BlockFinalized),
+    // Valid votes counted towards a round, our own included
+    VoteReceived(BlockVote),
+    EpochChanged(EpochChange),
+    // Raised once per violation, whether or not it halted us
+    SafetyViolation(DetectedViolation),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct MerkleProof {
+    pub leaf_index: usize,
+    // The transaction hash as the merkle hash function takes it in, which for Poseidon is
+    // reduced into its field
+    pub leaf: BlockHash,
+    pub siblings: Vec<BlockHash>,
+}
+
+// Size caps every validator enforces on proposed blocks, in canonical encoding bytes. Block
+// bytes count the transactions only; the header and proof do not grow with what the proposer
+// puts in
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct BlockLimits {
+    pub max_block_bytes: u64,
+    pub max_txs_per_block: u64,
+    pub max_tx_bytes: u64,
 }
 
 impl Block {
+    // Blocks are identified by their header, which commits to the transactions via the
+    // merkle root, so light clients can link headers without downloading transactions
     pub fn hash(&self) -> BlockHash {
-        let mut hasher = Sha256::new();
-        hasher.update(&bincode::serialize(&self.header).unwrap());
-        hasher.update(&bincode::serialize(&self.transactions).unwrap());
-        hasher.finalize().into()
+        self.header.hash()
+    }
+    
+    // The proposer also signs the proof, so a bad proof is attributable to it
+    pub fn signing_hash(&self) -> BlockHash {
+        block_signing_hash(&self.header, &self.zk_proof)
+    }
+    
+    pub fn light(&self) -> LightBlock {
+        LightBlock {
+            header: self.header.clone(),
+            zk_proof: self.zk_proof.clone(),
+            signature: self.signature.clone(),
+        }
     }
     
     pub fn verify_zk_proof(&self) -> bool {
@@ -119,10 +528,533 @@ impl Block {
     }
 }
 
+impl ZKProof {
+    // Reference to checkpoint block `block_number`, carried in place of a proof
+    pub fn checkpoint(block_number: u64, block_hash: BlockHash) -> Self {
+        let mut public_inputs = Vec::with_capacity(40);
+        public_inputs.extend_from_slice(&block_number.to_le_bytes());
+        public_inputs.extend_from_slice(&block_hash);
+        Self {
+            proof_data: vec![],
+            public_inputs,
+            circuit_version: 0,
+            proof_type: ProofType::Checkpoint,
+        }
+    }
+    
+    pub fn checkpoint_reference(&self) -> Option<(u64, BlockHash)> {
+        if self.proof_type != ProofType::Checkpoint || self.public_inputs.len() != 40 || !self.proof_data.is_empty() {
+            return None;
+        }
+        let block_number = u64::from_le_bytes(self.public_inputs[..8].try_into().unwrap());
+        Some((block_number, self.public_inputs[8..].try_into().unwrap()))
+    }
+}
+
+impl CompactBlock {
+    pub fn new(block: &Block) -> Self {
+        Self {
+            header: block.header.clone(),
+            zk_proof: block.zk_proof.clone(),
+            signature: block.signature.clone(),
+            tx_ids: block.transactions.iter().map(|tx| tx.id).collect(),
+            parent_votes: block.parent_votes.clone(),
+        }
+    }
+    
+    pub fn hash(&self) -> BlockHash {
+        self.header.hash()
+    }
+    
+    pub fn signing_hash(&self) -> BlockHash {
+        block_signing_hash(&self.header, &self.zk_proof)
+    }
+    
+    // The transactions must be in tx_ids order
+    pub fn into_block(self, transactions: Vec<Transaction>) -> Block {
+        Block {
+            header: self.header,
+            transactions,
+            parent_votes: self.parent_votes,
+            zk_proof: self.zk_proof,
+            signature: self.signature,
+        }
+    }
+}
+
 impl BlockHeader {
     pub fn hash(&self) -> BlockHash {
+        hasher().hash(&[&self.encode()])
+    }
+}
+
+impl LightBlock {
+    pub fn signing_hash(&self) -> BlockHash {
+        block_signing_hash(&self.header, &self.zk_proof)
+    }
+}
+
+impl BlockVote {
+    // Approvals vote for their block, anything else counts as a nil vote
+    pub fn value(&self) -> Option<BlockHash> {
+        match self.vote {
+            VoteType::Approve => Some(self.block_hash),
+            VoteType::Reject | VoteType::Abstain => None,
+        }
+    }
+    
+    pub fn signing_hash(&self) -> [u8; 32] {
         let mut hasher = Sha256::new();
-        hasher.update(&bincode::serialize(self).unwrap());
+        hasher.update(self.block_hash);
+        hasher.update(self.validator);
+        hasher.update(self.vote.encode());
+        hasher.update(self.height.to_le_bytes());
+        hasher.update(self.round.to_le_bytes());
+        hasher.update(self.step.encode());
+        hasher.update(self.timestamp.encode());
         hasher.finalize().into()
     }
-} 
\ No newline at end of file
+}
+
+impl Proposal {
+    pub fn signing_hash(&self) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        hasher.update(self.height.to_le_bytes());
+        hasher.update(self.round.to_le_bytes());
+        hasher.update(self.block_hash);
+        hasher.update(self.proposer);
+        hasher.finalize().into()
+    }
+}
+
+impl RoundChange {
+    pub fn signing_hash(&self) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        hasher.update(b"round_change");
+        hasher.update(self.height.to_le_bytes());
+        hasher.update(self.round.to_le_bytes());
+        hasher.update(self.validator);
+        hasher.finalize().into()
+    }
+}
+
+impl ProofRequest {
+    pub fn signing_hash(&self) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        hasher.update(b"proof_request");
+        hasher.update(self.block_number.to_le_bytes());
+        hasher.update(self.block_hash);
+        hasher.update(self.request_id);
+        hasher.update(self.requester);
+        hasher.update(self.target);
+        hasher.finalize().into()
+    }
+}
+
+impl Transaction {
+    pub fn hash(&self) -> [u8; 32] {
+        hasher().hash(&[&self.encode()])
+    }
+    
+    // What the id must be: the sha256 of the transaction with an empty id and signature, so a
+    // peer cannot claim the id of a transaction it did not send
+    pub fn content_id(&self) -> [u8; 32] {
+        let mut unsigned = self.clone();
+        unsigned.id = [0; 32];
+        unsigned.signature = vec![];
+        Sha256::digest(unsigned.encode()).into()
+    }
+    
+    // Everything except the signature itself, behind a domain tag so a transaction signature
+    // can never be mistaken for a block or vote signature
+    pub fn signing_hash(&self) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        hasher.update(TRANSACTION_DOMAIN);
+        hasher.update(self.chain_id.to_le_bytes());
+        hasher.update(self.id);
+        hasher.update(self.from);
+        hasher.update(self.to);
+        hasher.update(self.amount.to_le_bytes());
+        hasher.update(self.fee.to_le_bytes());
+        hasher.update(self.nonce.to_le_bytes());
+        hasher.update(self.timestamp.encode());
+        hasher.update(self.kind.encode());
+        hasher.update(self.valid_until.encode());
+        hasher.finalize().into()
+    }
+    
+    // Whether a block with this number and timestamp is too late for the transaction
+    pub fn is_expired(&self, block_number: u64, timestamp: &DateTime<Utc>) -> bool {
+        match self.valid_until {
+            None => false,
+            Some(Expiry::Height(height)) => block_number > height,
+            Some(Expiry::Time(time)) => *timestamp > time,
+        }
+    }
+    
+    // Senders are ed25519 public keys, like node ids
+    pub fn verify_signature(&self) -> bool {
+        crate::crypto::verify_signature(&self.from, &self.signing_hash(), &self.signature)
+    }
+    
+    // What the sender's balance must cover; staked amounts leave it until they are unbonded
+    pub fn total_cost(&self) -> Option<u64> {
+        match &self.kind {
+            TransactionKind::Transfer => self.amount.checked_add(self.fee),
+            TransactionKind::Stake(stake) => stake.amount.checked_add(self.fee),
+            _ => Some(self.fee),
+        }
+    }
+    
+    // Checks that do not depend on account state; a transaction failing them never becomes valid
+    pub fn validate(&self) -> Result<()> {
+        self.validate_unsigned()?;
+        if !self.verify_signature() {
+            bail!("transaction is not signed by its sender");
+        }
+        Ok(())
+    }
+
+    // validate for a transaction whose signature was checked already
+    pub fn validate_unsigned(&self) -> Result<()> {
+        if self.chain_id != chain_id() {
+            bail!("signed for chain {} but this is chain {}", self.chain_id, chain_id());
+        }
+        if self.total_cost().is_none() {
+            bail!("amount plus fee {} overflows", self.fee);
+        }
+        // The stake is paid from the sender's balance, so only its own
+        if let TransactionKind::Stake(stake) = &self.kind {
+            if stake.validator != self.from {
+                bail!("stakes for {} but is sent by {}", hex::encode(stake.validator), hex::encode(self.from));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl MerkleProof {
+    pub fn verify(&self, root: &BlockHash) -> bool {
+        let mut hash = self.leaf;
+        let mut index = self.leaf_index;
+        
+        for sibling in &self.siblings {
+            hash = if index.is_multiple_of(2) {
+                hash_pair(&hash, sibling)
+            } else {
+                hash_pair(sibling, &hash)
+            };
+            index /= 2;
+        }
+        
+        hash == *root
+    }
+}
+
+impl Default for BlockLimits {
+    fn default() -> Self {
+        Self {
+            max_block_bytes: 1024 * 1024,
+            max_txs_per_block: 2_000,
+            // Evidence of an invalid proof carries the proof, so this leaves room for the larger backends
+            max_tx_bytes: 64 * 1024,
+        }
+    }
+}
+
+// Which blocks carry a proof of their own. Blocks at multiples of proof_interval are
+// checkpoints, proven before they are proposed; with deferred_proofs they are proven once they
+// finalize, and proposed without waiting on a prover. Every block without a proof carries a
+// reference to the last checkpoint below it instead
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct ProofPolicy {
+    pub proof_interval: u64,
+    pub deferred_proofs: bool,
+}
+
+impl Default for ProofPolicy {
+    fn default() -> Self {
+        Self {
+            proof_interval: 1,
+            deferred_proofs: false,
+        }
+    }
+}
+
+impl ProofPolicy {
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub fn is_checkpoint(&self, block_number: u64) -> bool {
+        block_number.is_multiple_of(self.proof_interval.max(1))
+    }
+
+    // Whether the block is proposed with a proof rather than a checkpoint reference
+    pub fn proves(&self, block_number: u64) -> bool {
+        !self.deferred_proofs && self.is_checkpoint(block_number)
+    }
+
+    // The reference a block has to carry: its parent when that is the last checkpoint, or else
+    // the one its parent carries. None for blocks proposed with a proof, and for a parent
+    // carrying the wrong reference
+    pub fn checkpoint_reference(&self, block_number: u64, parent_hash: &BlockHash, parent_proof: Option<&ZKProof>) -> Option<(u64, BlockHash)> {
+        if block_number == 0 || self.proves(block_number) {
+            return None;
+        }
+        let interval = self.proof_interval.max(1);
+        let checkpoint = (block_number - 1) / interval * interval;
+        if checkpoint + 1 == block_number {
+            return Some((checkpoint, *parent_hash));
+        }
+        parent_proof
+            .and_then(ZKProof::checkpoint_reference)
+            .filter(|(height, _)| *height == checkpoint)
+    }
+}
+
+impl BlockLimits {
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub fn check_transaction(&self, transaction: &Transaction) -> Result<()> {
+        let size = transaction.encode().len() as u64;
+        if size > self.max_tx_bytes {
+            bail!("transaction is {} bytes, over the limit of {}", size, self.max_tx_bytes);
+        }
+        Ok(())
+    }
+
+    pub fn check_block(&self, block: &Block) -> Result<()> {
+        if block.transactions.len() as u64 > self.max_txs_per_block {
+            bail!("{} transactions, over the limit of {}", block.transactions.len(), self.max_txs_per_block);
+        }
+        let mut size = 0;
+        for tx in &block.transactions {
+            let tx_size = tx.encode().len() as u64;
+            if tx_size > self.max_tx_bytes {
+                bail!("transaction {} is {} bytes, over the limit of {}", hex::encode(tx.id), tx_size, self.max_tx_bytes);
+            }
+            size += tx_size;
+        }
+        if size > self.max_block_bytes {
+            bail!("transactions take {} bytes, over the limit of {}", size, self.max_block_bytes);
+        }
+        Ok(())
+    }
+}
+
+// When active validators are jailed for being offline. Misses are counted per epoch: proposal
+// slots that passed without their block, and blocks carrying no precommit of theirs for the
+// parent. Reaching either limit jails them at once; after jail_epochs full epochs they can send
+// an unjail transaction. A limit of 0 disables it
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct LivenessPolicy {
+    pub max_missed_slots: u64,
+    pub max_missed_votes: u64,
+    pub jail_epochs: u64,
+}
+
+impl Default for LivenessPolicy {
+    fn default() -> Self {
+        Self {
+            max_missed_slots: 8,
+            // Half of an epoch, so precommits a proposer left out now and then do not add up to it
+            max_missed_votes: 16,
+            jail_epochs: 2,
+        }
+    }
+}
+
+impl LivenessPolicy {
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+// Parameters consensus reads from the chain rather than from the node. The genesis file sets
+// them, and a quorum of the active validators can change them at an epoch boundary
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(default)]
+pub struct ConsensusParams {
+    // Seconds per slot, which is also the length of a BFT round. 0 in states migrated from
+    // before it was on-chain, which ran on the configured block time
+    pub block_time: u64,
+    // Validators are not jailed below this many active ones, so the chain keeps going
+    pub min_validators: u64,
+    // Size of the active set chosen at each epoch boundary
+    pub max_validators: u64,
+    // Votes decide once they carry more than quorum_numerator / quorum_denominator of the
+    // active stake, which can not be set below two thirds
+    pub quorum_numerator: u64,
+    pub quorum_denominator: u64,
+}
+
+impl Default for ConsensusParams {
+    fn default() -> Self {
+        Self {
+            block_time: 12,
+            min_validators: 1,
+            max_validators: 100,
+            quorum_numerator: 2,
+            quorum_denominator: 3,
+        }
+    }
+}
+
+impl ConsensusParams {
+    pub fn validate(&self) -> Result<()> {
+        if self.block_time == 0 {
+            bail!("block_time must be at least one second");
+        }
+        if self.min_validators == 0 || self.max_validators < self.min_validators {
+            bail!("min_validators must be at least 1 and at most max_validators ({})", self.max_validators);
+        }
+        // Below two thirds two quorums could be formed without anyone equivocating
+        if self.quorum_numerator >= self.quorum_denominator
+            || (self.quorum_numerator as u128) * 3 < (self.quorum_denominator as u128) * 2
+        {
+            bail!("quorum {}/{} must be at least 2/3 and below 1", self.quorum_numerator, self.quorum_denominator);
+        }
+        Ok(())
+    }
+
+    pub fn has_quorum(&self, weight: u64, total: u64) -> bool {
+        total > 0 && (weight as u128) * (self.quorum_denominator as u128) > (total as u128) * (self.quorum_numerator as u128)
+    }
+}
+
+const TRANSACTION_DOMAIN: &[u8] = b"zk-consensus/transaction/v1";
+
+fn block_signing_hash(header: &BlockHeader, zk_proof: &ZKProof) -> BlockHash {
+    let mut hasher = Sha256::new();
+    hasher.update(header.hash());
+    hasher.update(zk_proof.encode());
+    hasher.finalize().into()
+}
+
+fn hash_pair(left: &BlockHash, right: &BlockHash) -> BlockHash {
+    merkle_hasher().hash_pair(left, right)
+}
+
+fn merkle_leaves(transactions: &[Transaction]) -> Vec<BlockHash> {
+    let hasher = merkle_hasher();
+    transactions.iter().map(|tx| hasher.merkle_leaf(&tx.hash())).collect()
+}
+
+// A finalized block the operator trusts from outside the network, such as another operator or
+// a block explorer. Nodes start syncing from it instead of genesis and never follow a chain
+// with another block at its height
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TrustedCheckpoint {
+    pub height: u64,
+    pub block_hash: BlockHash,
+    // validator_set_hash of the consensus state after the block
+    pub validator_set_hash: BlockHash,
+}
+
+impl TrustedCheckpoint {
+    // "height:block_hash:validator_set_hash" with hex hashes, as chain_getCheckpoint prints it
+    pub fn parse(spec: &str) -> Result<Self> {
+        let parts: Vec<&str> = spec.trim().split(':').collect();
+        let (height, block_hash, validator_set_hash) = match parts[..] {
+            [height, block_hash, validator_set_hash] => (height, block_hash, validator_set_hash),
+            _ => bail!("checkpoint '{}' is not height:block_hash:validator_set_hash", spec),
+        };
+        let hash = |hex_str: &str| -> Result<BlockHash> {
+            hex::decode(hex_str.trim_start_matches("0x"))?
+                .try_into()
+                .map_err(|_| anyhow!("checkpoint hash '{}' is not 32 bytes", hex_str))
+        };
+        Ok(Self {
+            height: height.parse()?,
+            block_hash: hash(block_hash)?,
+            validator_set_hash: hash(validator_set_hash)?,
+        })
+    }
+
+    // A block at the checkpoint's height that is not the checkpoint
+    pub fn conflicts_with(&self, block: &Block) -> bool {
+        block.header.block_number == self.height && block.hash() != self.block_hash
+    }
+}
+
+impl std::fmt::Display for TrustedCheckpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}:{}", self.height, hex::encode(self.block_hash), hex::encode(self.validator_set_hash))
+    }
+}
+
+// Commits to the active validators and their stake, sorted by node id
+pub fn validator_set_hash(state: &ConsensusState) -> BlockHash {
+    hash_validator_set(state.validators.iter()
+        .filter(|(_, info)| info.is_active)
+        .map(|(node_id, info)| (*node_id, info.stake))
+        .collect())
+}
+
+// Validators and their stake in any order, as validator_set_hash commits to them
+pub fn hash_validator_set(validators: Vec<(NodeId, u64)>) -> BlockHash {
+    let mut validators: Vec<_> = validators.into_iter()
+        .map(|(node_id, stake)| (node_id, stake.to_le_bytes()))
+        .collect();
+    validators.sort();
+    let parts: Vec<&[u8]> = validators.iter()
+        .flat_map(|(node_id, stake)| [node_id.as_slice(), stake.as_slice()])
+        .collect();
+    hasher().hash(&parts)
+}
+
+pub fn calculate_merkle_root(transactions: &[Transaction]) -> BlockHash {
+    if transactions.is_empty() {
+        return [0; 32];
+    }
+    
+    let mut hashes = merkle_leaves(transactions);
+    
+    // Build merkle tree
+    while hashes.len() > 1 {
+        hashes = hashes.chunks(2)
+            .map(|chunk| hash_pair(&chunk[0], chunk.get(1).unwrap_or(&chunk[0]))) // Duplicate for odd number
+            .collect();
+    }
+    
+    hashes[0]
+}
+
+// Commits the header to the parent precommits a block carries
+pub fn votes_root(votes: &[BlockVote]) -> BlockHash {
+    if votes.is_empty() {
+        return [0; 32];
+    }
+    
+    let encoded: Vec<Vec<u8>> = votes.iter().map(|vote| vote.encode()).collect();
+    let parts: Vec<&[u8]> = encoded.iter().map(Vec::as_slice).collect();
+    hasher().hash(&parts)
+}
+
+pub fn merkle_proof(transactions: &[Transaction], leaf_index: usize) -> Option<MerkleProof> {
+    if leaf_index >= transactions.len() {
+        return None;
+    }
+    
+    let mut hashes = merkle_leaves(transactions);
+    let leaf = hashes[leaf_index];
+    let mut index = leaf_index;
+    let mut siblings = Vec::new();
+    
+    while hashes.len() > 1 {
+        let sibling_index = if index.is_multiple_of(2) { index + 1 } else { index - 1 };
+        siblings.push(*hashes.get(sibling_index).unwrap_or(&hashes[index]));
+        
+        hashes = hashes.chunks(2)
+            .map(|chunk| hash_pair(&chunk[0], chunk.get(1).unwrap_or(&chunk[0])))
+            .collect();
+        index /= 2;
+    }
+    
+    Some(MerkleProof { leaf_index, leaf, siblings })
+}
@@ -0,0 +1,381 @@
+use std::sync::OnceLock;
+
+use super::BlockHash;
+
+// Poseidon over the BLS12-381 scalar field, the field the Groth16 circuits are written in, so a
+// merkle node costs a few hundred constraints in a circuit rather than the tens of thousands of
+// SHA-256. Width 3 (one capacity and two rate elements) with the x^5 S-box and 8 full and 57
+// partial rounds, the instance the Poseidon paper gives for 128-bit security over this field.
+// Round constants and the MDS matrix come from the paper's Grain LFSR, so other implementations
+// can derive the same ones
+pub const WIDTH: usize = 3;
+pub const FULL_ROUNDS: usize = 8;
+pub const PARTIAL_ROUNDS: usize = 57;
+pub const ALPHA: u64 = 5;
+
+// Capacity element of a two-to-one compression, 2^arity - 1 as in the paper's merkle tree mode
+pub const COMPRESS_DOMAIN: u64 = 3;
+// The byte sponge starts its capacity element at this plus the input length
+pub const BYTES_DOMAIN: u128 = 1 << 64;
+// Bytes packed into each element, so every chunk is below the modulus
+pub const BYTES_PER_ELEMENT: usize = 31;
+const FIELD_BITS: usize = 255;
+
+// Little endian limbs of the field modulus, of 2^256 and 2^512 modulo it, and -1/modulus mod 2^64
+const MODULUS: [u64; 4] = [0xffffffff00000001, 0x53bda402fffe5bfe, 0x3339d80809a1d805, 0x73eda753299d7d48];
+const R: [u64; 4] = [0x00000001fffffffe, 0x5884b7fa00034802, 0x998c4fefecbc4ff5, 0x1824b159acc5056f];
+const R2: [u64; 4] = [0xc999e990f3f29c6d, 0x2b6cedcb87925c23, 0x05d314967254398f, 0x0748d9d99f59ff11];
+const INV: u64 = 0xfffffffeffffffff;
+
+// Field element in Montgomery form
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Fr([u64; 4]);
+
+impl Fr {
+    const ZERO: Fr = Fr([0; 4]);
+
+    // Limbs below the modulus
+    fn from_canonical(limbs: [u64; 4]) -> Self {
+        Fr(limbs).mul(&Fr(R2))
+    }
+
+    fn from_u128(value: u128) -> Self {
+        Self::from_canonical([value as u64, (value >> 64) as u64, 0, 0])
+    }
+
+    // Any 32 little endian bytes, reduced modulo the field size
+    fn from_le_bytes_mod_order(bytes: &[u8; 32]) -> Self {
+        let mut limbs = [0u64; 4];
+        for (limb, chunk) in limbs.iter_mut().zip(bytes.chunks(8)) {
+            *limb = u64::from_le_bytes(chunk.try_into().unwrap());
+        }
+        while !below_modulus(&limbs) {
+            limbs = subtract_modulus(&limbs);
+        }
+        Self::from_canonical(limbs)
+    }
+
+    fn to_le_bytes(self) -> [u8; 32] {
+        let limbs = self.mul(&Fr([1, 0, 0, 0])).0;
+        let mut bytes = [0u8; 32];
+        for (chunk, limb) in bytes.chunks_mut(8).zip(limbs) {
+            chunk.copy_from_slice(&limb.to_le_bytes());
+        }
+        bytes
+    }
+
+    fn add(&self, other: &Fr) -> Fr {
+        let mut limbs = [0u64; 4];
+        let mut carry = 0;
+        for (i, limb) in limbs.iter_mut().enumerate() {
+            let sum = self.0[i] as u128 + other.0[i] as u128 + carry;
+            *limb = sum as u64;
+            carry = sum >> 64;
+        }
+        // Both are below a modulus of 255 bits, so the sum never carries out
+        match below_modulus(&limbs) {
+            true => Fr(limbs),
+            false => Fr(subtract_modulus(&limbs)),
+        }
+    }
+
+    // Montgomery multiplication, coarsely integrated operand scanning
+    fn mul(&self, other: &Fr) -> Fr {
+        let mut t = [0u64; 6];
+        for i in 0..4 {
+            let mut carry = 0;
+            for (j, limb) in t.iter_mut().take(4).enumerate() {
+                (*limb, carry) = mac(*limb, self.0[j], other.0[i], carry);
+            }
+            let sum = t[4] as u128 + carry as u128;
+            t[4] = sum as u64;
+            t[5] = (sum >> 64) as u64;
+
+            let m = t[0].wrapping_mul(INV);
+            let (_, mut carry) = mac(t[0], m, MODULUS[0], 0);
+            for j in 1..4 {
+                (t[j - 1], carry) = mac(t[j], m, MODULUS[j], carry);
+            }
+            let sum = t[4] as u128 + carry as u128;
+            t[3] = sum as u64;
+            t[4] = t[5] + (sum >> 64) as u64;
+        }
+
+        let limbs = [t[0], t[1], t[2], t[3]];
+        match t[4] == 0 && below_modulus(&limbs) {
+            true => Fr(limbs),
+            false => Fr(subtract_modulus(&limbs)),
+        }
+    }
+
+    fn pow(&self, exponent: &[u64; 4]) -> Fr {
+        let mut result = Fr(R);
+        for limb in exponent.iter().rev() {
+            for bit in (0..64).rev() {
+                result = result.mul(&result);
+                if (limb >> bit) & 1 == 1 {
+                    result = result.mul(self);
+                }
+            }
+        }
+        result
+    }
+
+    fn sbox(&self) -> Fr {
+        let square = self.mul(self);
+        square.mul(&square).mul(self)
+    }
+
+    // Zero has no inverse and maps to zero
+    fn inverse(&self) -> Fr {
+        let mut exponent = MODULUS;
+        exponent[0] -= 2;
+        self.pow(&exponent)
+    }
+}
+
+// a + b * c + carry, as the low word and the carry
+fn mac(a: u64, b: u64, c: u64, carry: u64) -> (u64, u64) {
+    let wide = a as u128 + (b as u128) * (c as u128) + carry as u128;
+    (wide as u64, (wide >> 64) as u64)
+}
+
+fn below_modulus(limbs: &[u64; 4]) -> bool {
+    for i in (0..4).rev() {
+        if limbs[i] != MODULUS[i] {
+            return limbs[i] < MODULUS[i];
+        }
+    }
+    false
+}
+
+fn subtract_modulus(limbs: &[u64; 4]) -> [u64; 4] {
+    let mut result = [0u64; 4];
+    let mut borrow = false;
+    for i in 0..4 {
+        let (diff, b1) = limbs[i].overflowing_sub(MODULUS[i]);
+        let (diff, b2) = diff.overflowing_sub(borrow as u64);
+        result[i] = diff;
+        borrow = b1 || b2;
+    }
+    result
+}
+
+// The 80-bit Grain LFSR of the Poseidon paper's parameter script
+struct Grain {
+    bits: [bool; 80],
+    head: usize,
+}
+
+impl Grain {
+    fn new() -> Self {
+        // Prime field, x^alpha S-box, field bits, width, full and partial rounds, then ones
+        let fields: [(u64, usize); 6] = [
+            (1, 2),
+            (0, 4),
+            (FIELD_BITS as u64, 12),
+            (WIDTH as u64, 12),
+            (FULL_ROUNDS as u64, 10),
+            (PARTIAL_ROUNDS as u64, 10),
+        ];
+        let mut bits = [true; 80];
+        let mut position = 0;
+        for (value, width) in fields {
+            for bit in (0..width).rev() {
+                bits[position] = (value >> bit) & 1 == 1;
+                position += 1;
+            }
+        }
+
+        let mut grain = Self { bits, head: 0 };
+        for _ in 0..160 {
+            grain.step();
+        }
+        grain
+    }
+
+    fn step(&mut self) -> bool {
+        let bit = |offset: usize| self.bits[(self.head + offset) % 80];
+        let new_bit = bit(62) ^ bit(51) ^ bit(38) ^ bit(23) ^ bit(13) ^ bit(0);
+        self.bits[self.head] = new_bit;
+        self.head = (self.head + 1) % 80;
+        new_bit
+    }
+
+    // Bits come in pairs, and the second is kept when the first is set
+    fn next_bit(&mut self) -> bool {
+        loop {
+            let keep = self.step();
+            let bit = self.step();
+            if keep {
+                return bit;
+            }
+        }
+    }
+
+    // FIELD_BITS bits, most significant first, as limbs
+    fn next_limbs(&mut self) -> [u64; 4] {
+        let mut limbs = [0u64; 4];
+        for index in (0..FIELD_BITS).rev() {
+            if self.next_bit() {
+                limbs[index / 64] |= 1 << (index % 64);
+            }
+        }
+        limbs
+    }
+
+    // Resampled until below the modulus, for round constants
+    fn next_element(&mut self) -> Fr {
+        loop {
+            let limbs = self.next_limbs();
+            if below_modulus(&limbs) {
+                return Fr::from_canonical(limbs);
+            }
+        }
+    }
+
+    // Reduced modulo the field size, for the MDS matrix
+    fn next_reduced(&mut self) -> Fr {
+        let mut limbs = self.next_limbs();
+        while !below_modulus(&limbs) {
+            limbs = subtract_modulus(&limbs);
+        }
+        Fr::from_canonical(limbs)
+    }
+}
+
+struct Parameters {
+    // WIDTH per round, in round order
+    round_constants: Vec<Fr>,
+    mds: [[Fr; WIDTH]; WIDTH],
+}
+
+fn parameters() -> &'static Parameters {
+    static PARAMETERS: OnceLock<Parameters> = OnceLock::new();
+    PARAMETERS.get_or_init(|| {
+        let mut grain = Grain::new();
+        let round_constants = (0..(FULL_ROUNDS + PARTIAL_ROUNDS) * WIDTH)
+            .map(|_| grain.next_element())
+            .collect();
+
+        // Cauchy matrix 1 / (x_i + y_j) over distinct xs and ys
+        let mds = loop {
+            let values: Vec<Fr> = (0..2 * WIDTH).map(|_| grain.next_reduced()).collect();
+            let distinct = values.iter().enumerate().all(|(i, a)| values[..i].iter().all(|b| a != b));
+            let (xs, ys) = values.split_at(WIDTH);
+            if !distinct || xs.iter().any(|x| ys.iter().any(|y| x.add(y) == Fr::ZERO)) {
+                continue;
+            }
+            let mut mds = [[Fr::ZERO; WIDTH]; WIDTH];
+            for (i, x) in xs.iter().enumerate() {
+                for (j, y) in ys.iter().enumerate() {
+                    mds[i][j] = x.add(y).inverse();
+                }
+            }
+            break mds;
+        };
+
+        Parameters { round_constants, mds }
+    })
+}
+
+fn permute(state: &mut [Fr; WIDTH]) {
+    let parameters = parameters();
+    let half = FULL_ROUNDS / 2;
+    for (round, constants) in parameters.round_constants.chunks(WIDTH).enumerate() {
+        for (element, constant) in state.iter_mut().zip(constants) {
+            *element = element.add(constant);
+        }
+        if round < half || round >= half + PARTIAL_ROUNDS {
+            for element in state.iter_mut() {
+                *element = element.sbox();
+            }
+        } else {
+            state[0] = state[0].sbox();
+        }
+
+        let mut mixed = [Fr::ZERO; WIDTH];
+        for (row, output) in parameters.mds.iter().zip(mixed.iter_mut()) {
+            for (coefficient, element) in row.iter().zip(state.iter()) {
+                *output = output.add(&coefficient.mul(element));
+            }
+        }
+        *state = mixed;
+    }
+}
+
+// Round constants, WIDTH per round, and the MDS matrix by rows, as canonical little endian
+// field elements for circuits to load
+pub fn round_constants() -> Vec<[u8; 32]> {
+    parameters().round_constants.iter().map(|constant| constant.to_le_bytes()).collect()
+}
+
+pub fn mds_matrix() -> [[[u8; 32]; WIDTH]; WIDTH] {
+    parameters().mds.map(|row| row.map(Fr::to_le_bytes))
+}
+
+// Hash as a field element: reduced modulo the field size, little endian
+pub fn to_field(hash: &BlockHash) -> BlockHash {
+    Fr::from_le_bytes_mod_order(hash).to_le_bytes()
+}
+
+// Parent of two merkle nodes: the first rate element of the permuted [3, left, right]
+pub fn compress(left: &BlockHash, right: &BlockHash) -> BlockHash {
+    let mut state = [
+        Fr::from_u128(COMPRESS_DOMAIN as u128),
+        Fr::from_le_bytes_mod_order(left),
+        Fr::from_le_bytes_mod_order(right),
+    ];
+    permute(&mut state);
+    state[1].to_le_bytes()
+}
+
+// Sponge over arbitrary bytes, absorbed BYTES_PER_ELEMENT at a time. The length goes into the
+// capacity element, so inputs differing only in trailing zeros hash differently
+pub fn hash_bytes(parts: &[&[u8]]) -> BlockHash {
+    let bytes = parts.concat();
+    let elements: Vec<Fr> = bytes.chunks(BYTES_PER_ELEMENT)
+        .map(|chunk| {
+            let mut padded = [0u8; 32];
+            padded[..chunk.len()].copy_from_slice(chunk);
+            Fr::from_le_bytes_mod_order(&padded)
+        })
+        .collect();
+
+    let mut state = [Fr::from_u128(BYTES_DOMAIN + bytes.len() as u128), Fr::ZERO, Fr::ZERO];
+    if elements.is_empty() {
+        permute(&mut state);
+    }
+    for block in elements.chunks(WIDTH - 1) {
+        for (element, input) in state[1..].iter_mut().zip(block) {
+            *element = element.add(input);
+        }
+        permute(&mut state);
+    }
+    state[1].to_le_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Big endian hex, as the reference prints field elements
+    fn element(hex: &str) -> Fr {
+        let mut bytes: [u8; 32] = hex::decode(hex).unwrap().try_into().unwrap();
+        bytes.reverse();
+        Fr::from_le_bytes_mod_order(&bytes)
+    }
+
+    // Test vector of the reference implementation by the Poseidon authors for this instance,
+    // poseidonperm_x5_255_3, which fixes the round constants and MDS matrix as well
+    #[test]
+    fn permutation_matches_the_reference() {
+        let mut state = [Fr::from_u128(0), Fr::from_u128(1), Fr::from_u128(2)];
+        permute(&mut state);
+        assert_eq!(state, [
+            element("28ce19420fc246a05553ad1e8c98f5c9d67166be2c18e9e4cb4b4e317dd2a78a"),
+            element("51f3e312c95343a896cfd8945ea82ba956c1118ce9b9859b6ea56637b4b1ddc4"),
+            element("3b2b69139b235626a0bfb56c9527ae66a7bf486ad8c11c14d1da0c69bbe0f79a"),
+        ]);
+    }
+}
@@ -0,0 +1,229 @@
+use crate::crypto::NodeKeypair;
+use crate::rpc;
+use crate::state::{Account, AccountId};
+use crate::storage::{AddressHistory, HistoryPage, StorageManager};
+use crate::staking::{ProposalAction, ProposalTransaction, ProposalVoteTransaction};
+use crate::types::{BlockHash, Expiry, Transaction, TransactionKind};
+use anyhow::{anyhow, bail, Result};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::fs;
+use std::path::Path;
+
+// A watch-only wallet is a list of addresses to follow, without their keys. Spending from one
+// of them is done offline: the transaction is built and signed where the key is, and the
+// signed file is handed to any node
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Wallet {
+    pub addresses: Vec<WatchedAddress>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WatchedAddress {
+    // Hex encoded account id
+    pub address: String,
+    #[serde(default)]
+    pub label: Option<String>,
+}
+
+impl Wallet {
+    // An empty wallet when the file does not exist yet
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        match serde_json::from_slice(&fs::read(path)?) {
+            Ok(wallet) => Ok(wallet),
+            Err(e) => bail!("{} is not a wallet file: {}", path.display(), e),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        fs::write(path, serde_json::to_vec_pretty(self)?)?;
+        Ok(())
+    }
+
+    // Returns false when the address was already watched; its label is replaced then
+    pub fn watch(&mut self, address: &AccountId, label: Option<String>) -> bool {
+        let address = hex::encode(address);
+        match self.addresses.iter_mut().find(|watched| watched.address == address) {
+            Some(watched) => {
+                watched.label = label.or(watched.label.take());
+                false
+            }
+            None => {
+                self.addresses.push(WatchedAddress { address, label });
+                true
+            }
+        }
+    }
+
+    pub fn unwatch(&mut self, address: &AccountId) -> bool {
+        let address = hex::encode(address);
+        let before = self.addresses.len();
+        self.addresses.retain(|watched| watched.address != address);
+        self.addresses.len() < before
+    }
+}
+
+// Accounts are ed25519 public keys, the same as node ids
+pub fn address(keypair: &NodeKeypair) -> AccountId {
+    keypair.node_id()
+}
+
+pub fn parse_address(address: &str) -> Result<AccountId> {
+    let bytes = hex::decode(address.trim_start_matches("0x"))
+        .map_err(|e| anyhow!("address {} is not hex: {}", address, e))?;
+    match bytes.try_into() {
+        Ok(address) => Ok(address),
+        Err(_) => bail!("address {} is not 32 bytes", address),
+    }
+}
+
+pub fn parse_proposal_id(id: &str) -> Result<BlockHash> {
+    let bytes = hex::decode(id.trim_start_matches("0x"))
+        .map_err(|e| anyhow!("proposal id {} is not hex: {}", id, e))?;
+    match bytes.try_into() {
+        Ok(id) => Ok(id),
+        Err(_) => bail!("proposal id {} is not 32 bytes", id),
+    }
+}
+
+// Where balances and history are read from: a node's JSON-RPC, or the database of a stopped
+// node on this machine
+pub enum ChainSource {
+    Rpc(String),
+    Storage(StorageManager),
+}
+
+impl ChainSource {
+    // As of the last finalized block, or of finalized block `height` when the node still keeps its state
+    pub async fn account(&self, address: &AccountId, height: Option<u64>) -> Result<Account> {
+        match self {
+            Self::Rpc(url) => {
+                let params = json!({ "account": hex::encode(address), "height": height });
+                let account = rpc::call(url, "state_getAccount", params).await?;
+                Ok(Account {
+                    balance: account["balance"].as_u64().unwrap_or_default(),
+                    nonce: account["nonce"].as_u64().unwrap_or_default(),
+                })
+            }
+            Self::Storage(storage) => {
+                let account = match height {
+                    Some(height) => storage.get_state_at(height).await?.account(address).await?,
+                    None => storage.get_account(address).await?,
+                };
+                Ok(account.unwrap_or_default())
+            }
+        }
+    }
+
+    // Nonce the next transaction from the address should use, counting its pending ones
+    pub async fn next_nonce(&self, address: &AccountId) -> Result<u64> {
+        match self {
+            Self::Rpc(url) => {
+                let nonces = rpc::call(url, "state_getNonces", json!({ "account": hex::encode(address) })).await?;
+                match nonces["next_nonce"].as_u64() {
+                    Some(nonce) => Ok(nonce),
+                    None => bail!("node returned no next nonce for {}", hex::encode(address)),
+                }
+            }
+            Self::Storage(storage) => Ok(storage.get_nonce_status(address).await?.next_nonce),
+        }
+    }
+
+    // Finalized transactions sent or received by the address, newest first
+    pub async fn history(&self, address: &AccountId, page: &HistoryPage) -> Result<AddressHistory> {
+        match self {
+            Self::Rpc(url) => {
+                let params = json!({ "account": hex::encode(address), "cursor": page.cursor, "limit": page.limit });
+                Ok(serde_json::from_value(rpc::call(url, "state_getTransactions", params).await?)?)
+            }
+            Self::Storage(storage) => Ok(storage.get_transactions_by_address(address, page).await?),
+        }
+    }
+}
+
+// A transfer to sign; the nonce has to be looked up beforehand, as signing needs no node
+#[derive(Debug, Clone)]
+pub struct Transfer {
+    pub to: AccountId,
+    pub amount: u64,
+    pub fee: u64,
+    pub nonce: u64,
+    pub chain_id: u64,
+    pub valid_until: Option<Expiry>,
+}
+
+pub fn sign_transfer(keypair: &NodeKeypair, transfer: &Transfer) -> Transaction {
+    sign(keypair, Transaction {
+        id: [0; 32],
+        from: address(keypair),
+        to: transfer.to,
+        amount: transfer.amount,
+        fee: transfer.fee,
+        nonce: transfer.nonce,
+        chain_id: transfer.chain_id,
+        timestamp: Utc::now(),
+        kind: TransactionKind::Transfer,
+        valid_until: transfer.valid_until,
+        signature: vec![],
+    })
+}
+
+// Governance proposals and votes count for active validators only, so the key has to be the
+// validator's own. The proposal id is the id of the signed transaction
+pub fn sign_proposal(keypair: &NodeKeypair, action: ProposalAction, nonce: u64, chain_id: u64) -> Result<Transaction> {
+    if let ProposalAction::ChangeParams(params) = &action {
+        params.validate()?;
+    }
+    let kind = TransactionKind::Proposal(ProposalTransaction { proposer: keypair.node_id(), action });
+    Ok(sign_governance(keypair, kind, nonce, chain_id))
+}
+
+pub fn sign_proposal_vote(keypair: &NodeKeypair, proposal: BlockHash, approve: bool, nonce: u64, chain_id: u64) -> Transaction {
+    let kind = TransactionKind::ProposalVote(ProposalVoteTransaction { voter: keypair.node_id(), proposal, approve });
+    sign_governance(keypair, kind, nonce, chain_id)
+}
+
+fn sign_governance(keypair: &NodeKeypair, kind: TransactionKind, nonce: u64, chain_id: u64) -> Transaction {
+    sign(keypair, Transaction {
+        id: [0; 32],
+        from: keypair.node_id(),
+        to: [0; 32],
+        amount: 0,
+        fee: 0,
+        nonce,
+        chain_id,
+        timestamp: Utc::now(),
+        kind,
+        valid_until: None,
+        signature: vec![],
+    })
+}
+
+fn sign(keypair: &NodeKeypair, mut tx: Transaction) -> Transaction {
+    tx.id = tx.content_id();
+    tx.signature = keypair.sign(&tx.signing_hash());
+    tx
+}
+
+pub fn write_transaction(tx: &Transaction, path: &Path) -> Result<()> {
+    fs::write(path, serde_json::to_vec_pretty(tx)?)?;
+    Ok(())
+}
+
+pub fn read_transaction(path: &Path) -> Result<Transaction> {
+    match serde_json::from_slice(&fs::read(path)?) {
+        Ok(tx) => Ok(tx),
+        Err(e) => bail!("{} is not a signed transaction: {}", path.display(), e),
+    }
+}
+
+// Hands a signed transaction to a node. Returns whether it is ready for a block, rather than
+// queued behind a nonce gap
+pub async fn submit(url: &str, tx: &Transaction) -> Result<bool> {
+    let result = rpc::call(url, "tx_submit", json!({ "transaction": tx })).await?;
+    Ok(result["queued"] == false)
+}
@@ -0,0 +1,278 @@
+use super::{ProofError, Result};
+use crate::config::ZkProofConfig;
+use anyhow::{anyhow, bail};
+use memmap2::Mmap;
+use sha2::{Sha256, Digest};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tracing::{info, warn};
+
+// A mirror that stalls must not hold up startup, nor fill the disk
+const DOWNLOAD_CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+const DOWNLOAD_READ_TIMEOUT: Duration = Duration::from_secs(60);
+const MAX_KEY_FILE_BYTES: u64 = 4 << 30;
+
+// Values of zk_proof.role; "auto" proves on validators and only verifies on other nodes
+pub const KEY_ROLES: [&str; 3] = ["auto", "prover", "verifier"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyRole {
+    // Proves blocks and the chain, and verifies
+    Prover,
+    // Holds verifying keys only; proving keys make up most of the memory and size of the keys
+    Verifier,
+}
+
+// Where backends take their circuit keys from. Without a directory they come from this build's
+// development setup; with one, the Groth16 keys are read from the files `export-keys` writes,
+// such as those of a trusted setup. PLONK and Nova need no trusted setup, so their keys are
+// always derived, though only provers derive proving keys
+#[derive(Debug, Clone)]
+pub struct KeySource {
+    pub role: KeyRole,
+    dir: Option<PathBuf>,
+}
+
+impl KeySource {
+    pub fn new(role: KeyRole, dir: Option<PathBuf>) -> Self {
+        Self { role, dir }
+    }
+
+    pub fn proving(&self) -> bool {
+        self.role == KeyRole::Prover
+    }
+
+    pub fn dir(&self) -> Option<&Path> {
+        self.dir.as_deref()
+    }
+
+    // Checks that every key file the compiled-in backends read for our role is there, so a
+    // node without them fails at startup rather than at its first proof
+    pub fn check(&self) -> Result<()> {
+        let dir = match &self.dir {
+            Some(dir) => dir,
+            None => return Ok(()),
+        };
+        let missing: Vec<PathBuf> = required_files(self.role).into_iter()
+            .map(|name| dir.join(name))
+            .filter(|path| !path.is_file())
+            .collect();
+        if !missing.is_empty() {
+            return Err(ProofError::MissingKeys(missing));
+        }
+        Ok(())
+    }
+
+    #[cfg_attr(not(feature = "groth16"), allow(dead_code))]
+    pub fn read(&self, name: &str) -> anyhow::Result<Vec<u8>> {
+        let path = match &self.dir {
+            Some(dir) => dir.join(name),
+            None => return Err(anyhow!("No key directory to read {} from", name)),
+        };
+        fs::read(&path).map_err(|e| anyhow!("Failed to read {}: {}", path.display(), e))
+    }
+
+    // Proving keys are mapped rather than read, so they are paged in as deserialization reaches
+    // them instead of being copied into memory first
+    #[cfg_attr(not(feature = "groth16"), allow(dead_code))]
+    pub fn map(&self, name: &str) -> anyhow::Result<Mmap> {
+        match &self.dir {
+            Some(dir) => map_file(&dir.join(name)),
+            None => Err(anyhow!("No key directory to map {} from", name)),
+        }
+    }
+}
+
+// Key files must not change while mapped; downloads replace them by renaming, which leaves
+// existing mappings intact
+fn map_file(path: &Path) -> anyhow::Result<Mmap> {
+    let file = File::open(path).map_err(|e| anyhow!("Failed to open {}: {}", path.display(), e))?;
+    unsafe { Mmap::map(&file) }.map_err(|e| anyhow!("Failed to map {}: {}", path.display(), e))
+}
+
+// A key file as found at startup
+#[derive(Debug, Clone)]
+pub struct ArtifactStatus {
+    pub name: &'static str,
+    pub bytes: u64,
+    pub sha256: [u8; 32],
+    // Whether the genesis pins its hash; unpinned files are used as they are
+    pub pinned: bool,
+}
+
+// Real circuit keys are hundreds of megabytes and come from a setup ceremony, so nodes take
+// them from zk_proof.key_dir rather than generating them. Files the directory lacks are
+// downloaded from zk_proof.key_mirrors, and every file is checked against the hash the
+// genesis pins before it is deserialized
+pub struct ArtifactManager {
+    keys: KeySource,
+    mirrors: Vec<String>,
+    hashes: BTreeMap<String, [u8; 32]>,
+}
+
+impl ArtifactManager {
+    pub fn new(config: &ZkProofConfig, role: KeyRole) -> Self {
+        Self {
+            keys: KeySource::new(role, config.key_dir.clone()),
+            mirrors: config.key_mirrors.clone(),
+            hashes: config.artifact_hashes.clone(),
+        }
+    }
+
+    // Only files the genesis pins are downloaded, since nothing else says a mirror's copy is
+    // the right one
+    pub async fn fetch(&self) -> Result<()> {
+        let dir = match self.keys.dir() {
+            Some(dir) if !self.mirrors.is_empty() => dir,
+            _ => return Ok(()),
+        };
+        for name in required_files(self.keys.role) {
+            let path = dir.join(name);
+            if path.is_file() {
+                continue;
+            }
+            let expected = match self.hashes.get(name) {
+                Some(expected) => *expected,
+                None => return Err(ProofError::Download(name.to_string(), anyhow!("the genesis pins no hash for it"))),
+            };
+            fs::create_dir_all(dir).map_err(|e| ProofError::Setup(e.into()))?;
+            self.download(name, &path, &expected).await?;
+        }
+        Ok(())
+    }
+
+    async fn download(&self, name: &str, path: &Path, expected: &[u8; 32]) -> Result<()> {
+        let client = reqwest::Client::builder()
+            .connect_timeout(DOWNLOAD_CONNECT_TIMEOUT)
+            .read_timeout(DOWNLOAD_READ_TIMEOUT)
+            .build()
+            .map_err(|e| ProofError::Download(name.to_string(), e.into()))?;
+        for mirror in &self.mirrors {
+            let url = format!("{}/{}", mirror.trim_end_matches('/'), name);
+            info!("⬇️ Downloading {} from {}", name, url);
+            match download_file(&client, &url, path, expected).await {
+                Ok(bytes) => {
+                    info!("✅ Downloaded {} ({} bytes)", name, bytes);
+                    return Ok(());
+                }
+                Err(e) => warn!("❌ {} from {} rejected: {:#}", name, url, e),
+            }
+        }
+        Err(ProofError::Download(name.to_string(), anyhow!("no mirror served it with the pinned hash")))
+    }
+
+    // Hashes every key file the role reads. Fails on one the genesis pins another hash for
+    pub fn verify(&self) -> Result<Vec<ArtifactStatus>> {
+        let dir = match self.keys.dir() {
+            Some(dir) => dir,
+            None => return Ok(Vec::new()),
+        };
+        let mut statuses = Vec::new();
+        for name in required_files(self.keys.role) {
+            let path = dir.join(name);
+            let map = map_file(&path).map_err(ProofError::Setup)?;
+            let sha256: [u8; 32] = Sha256::digest(&map[..]).into();
+            let pinned = match self.hashes.get(name) {
+                Some(expected) if *expected != sha256 => return Err(ProofError::ArtifactMismatch(path, *expected, sha256)),
+                Some(_) => true,
+                None => false,
+            };
+            statuses.push(ArtifactStatus { name, bytes: map.len() as u64, sha256, pinned });
+        }
+        Ok(statuses)
+    }
+
+    // Whether the genesis pins key files this node would not read, as without key_dir
+    pub fn unused_pins(&self) -> bool {
+        self.keys.dir().is_none() && !self.hashes.is_empty()
+    }
+}
+
+// Written next to the target and renamed once the hash checks out, so a node never reads a
+// partial or tampered file. Returns the size
+async fn download_file(client: &reqwest::Client, url: &str, path: &Path, expected: &[u8; 32]) -> anyhow::Result<u64> {
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".part");
+    let partial = PathBuf::from(partial);
+
+    let result = write_download(client, url, &partial, expected).await;
+    match result {
+        Ok(bytes) => {
+            tokio::fs::rename(&partial, path).await?;
+            Ok(bytes)
+        }
+        Err(e) => {
+            let _ = tokio::fs::remove_file(&partial).await;
+            Err(e)
+        }
+    }
+}
+
+async fn write_download(client: &reqwest::Client, url: &str, partial: &Path, expected: &[u8; 32]) -> anyhow::Result<u64> {
+    let mut response = client.get(url).send().await?.error_for_status()?;
+    if let Some(length) = response.content_length().filter(|length| *length > MAX_KEY_FILE_BYTES) {
+        bail!("{} bytes, more than the {} a key file may have", length, MAX_KEY_FILE_BYTES);
+    }
+    let mut file = tokio::fs::File::create(partial).await?;
+    let mut hasher = Sha256::new();
+    let mut bytes = 0u64;
+    while let Some(chunk) = response.chunk().await? {
+        bytes += chunk.len() as u64;
+        if bytes > MAX_KEY_FILE_BYTES {
+            bail!("more than the {} bytes a key file may have", MAX_KEY_FILE_BYTES);
+        }
+        hasher.update(&chunk);
+        file.write_all(&chunk).await?;
+    }
+    file.sync_all().await?;
+
+    let sha256: [u8; 32] = hasher.finalize().into();
+    if sha256 != *expected {
+        bail!("sha256 {} where the genesis pins {}", hex::encode(sha256), hex::encode(expected));
+    }
+    Ok(bytes)
+}
+
+// Key files a node of the role reads from zk_proof.key_dir. Proving keys contain the
+// verifying keys, so provers need no others
+#[cfg_attr(not(feature = "groth16"), allow(unused_variables))]
+pub fn required_files(role: KeyRole) -> Vec<&'static str> {
+    #[allow(unused_mut)]
+    let mut files = Vec::new();
+    #[cfg(feature = "groth16")]
+    files.extend(match role {
+        KeyRole::Prover => super::groth16::PROVING_KEY_FILES,
+        KeyRole::Verifier => super::groth16::VERIFYING_KEY_FILES,
+    });
+    files
+}
+
+// Writes the proving and verifying keys of this build's setup into `dir`, for nodes started
+// with zk_proof.key_dir. Returns the files written with their SHA-256, for the genesis to pin
+pub fn export_keys(dir: &Path) -> anyhow::Result<Vec<(PathBuf, [u8; 32])>> {
+    // The block circuit recomputes the merkle and state trees with Poseidon gadgets
+    #[cfg(feature = "groth16")]
+    if crate::types::merkle_hash_function() != crate::types::HashFunction::Poseidon {
+        bail!("Groth16 keys only prove chains with the poseidon merkle hash function, the chain uses {:?}",
+            crate::types::merkle_hash_function());
+    }
+    #[allow(unused_mut)]
+    let mut files: Vec<(&str, Vec<u8>)> = Vec::new();
+    #[cfg(feature = "groth16")]
+    files.extend(super::groth16::Groth16Backend::setup(&KeySource::new(KeyRole::Prover, None))?.key_files()?);
+    if files.is_empty() {
+        bail!("No backend in this build reads key files; build with the groth16 feature");
+    }
+
+    fs::create_dir_all(dir)?;
+    let mut written = Vec::with_capacity(files.len());
+    for (name, bytes) in files {
+        let path = dir.join(name);
+        fs::write(&path, &bytes)?;
+        written.push((path, Sha256::digest(&bytes).into()));
+    }
+    Ok(written)
+}
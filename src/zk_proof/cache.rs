@@ -0,0 +1,148 @@
+use crate::storage::{StorageError, StorageManager};
+use crate::types::{BlockHash, ZKProof};
+use sha2::{Sha256, Digest};
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
+use tokio::sync::Mutex;
+use tracing::debug;
+
+type Result<T, E = StorageError> = std::result::Result<T, E>;
+
+// Proofs and verification outcomes kept in memory; older proofs are read back from storage
+pub const PROOF_CACHE_SIZE: usize = 1024;
+
+// Least recently used entries are evicted once the map is over capacity
+struct Lru<K, V> {
+    capacity: usize,
+    // Value and the stamp of its latest use
+    entries: HashMap<K, (V, u64)>,
+    // Uses oldest first; entries used since are skipped when evicting
+    order: VecDeque<(K, u64)>,
+    next_stamp: u64,
+}
+
+impl<K: Hash + Eq + Copy, V: Clone> Lru<K, V> {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: HashMap::new(),
+            order: VecDeque::new(),
+            next_stamp: 0,
+        }
+    }
+
+    fn touch(&mut self, key: K) -> u64 {
+        let stamp = self.next_stamp;
+        self.next_stamp += 1;
+        self.order.push_back((key, stamp));
+        stamp
+    }
+
+    fn get(&mut self, key: &K) -> Option<V> {
+        if !self.entries.contains_key(key) {
+            return None;
+        }
+        let stamp = self.touch(*key);
+        let entry = self.entries.get_mut(key)?;
+        entry.1 = stamp;
+        let value = entry.0.clone();
+        self.evict();
+        Some(value)
+    }
+
+    fn insert(&mut self, key: K, value: V) {
+        let stamp = self.touch(key);
+        self.entries.insert(key, (value, stamp));
+        self.evict();
+    }
+
+    fn evict(&mut self) {
+        while self.entries.len() > self.capacity || self.order.len() > self.capacity * 2 {
+            let (oldest, oldest_stamp) = match self.order.pop_front() {
+                Some(oldest) => oldest,
+                None => break,
+            };
+            if self.entries.get(&oldest).map(|entry| entry.1) == Some(oldest_stamp) {
+                self.entries.remove(&oldest);
+            }
+        }
+    }
+}
+
+// A block is proven when it is proposed, again for every proof request and verified by each
+// path it arrives on. Proofs are kept by block hash, in memory and optionally in storage so they
+// survive restarts, and verification outcomes are remembered per block and proof
+pub struct ProofCache {
+    proofs: Mutex<Lru<BlockHash, ZKProof>>,
+    verified: Mutex<Lru<[u8; 32], bool>>,
+    // Verified proofs other validators made for blocks whose own proof we can not check
+    substitutes: Mutex<Lru<BlockHash, ZKProof>>,
+    storage: Option<StorageManager>,
+}
+
+impl ProofCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            proofs: Mutex::new(Lru::new(capacity)),
+            verified: Mutex::new(Lru::new(capacity)),
+            substitutes: Mutex::new(Lru::new(capacity)),
+            storage: None,
+        }
+    }
+
+    pub fn persist_to(&mut self, storage: StorageManager) {
+        self.storage = Some(storage);
+    }
+
+    pub async fn get(&self, block_hash: &BlockHash) -> Result<Option<ZKProof>> {
+        if let Some(proof) = self.proofs.lock().await.get(block_hash) {
+            return Ok(Some(proof));
+        }
+
+        let storage = match &self.storage {
+            Some(storage) => storage,
+            None => return Ok(None),
+        };
+        let proof = storage.get_proof(block_hash).await?;
+        if let Some(proof) = &proof {
+            debug!("Loaded proof for block {} from storage", hex::encode(&block_hash[..8]));
+            self.proofs.lock().await.insert(*block_hash, proof.clone());
+        }
+        Ok(proof)
+    }
+
+    // Proofs we generated are valid by construction, so they are recorded as verified too
+    pub async fn insert(&self, block_hash: BlockHash, proof: &ZKProof) -> Result<()> {
+        if let Some(storage) = &self.storage {
+            storage.store_proof(&block_hash, proof).await?;
+        }
+        self.verified.lock().await.insert(verification_key(&block_hash, proof)?, true);
+        self.proofs.lock().await.insert(block_hash, proof.clone());
+        Ok(())
+    }
+
+    pub async fn verified(&self, block_hash: &BlockHash, proof: &ZKProof) -> Result<Option<bool>> {
+        Ok(self.verified.lock().await.get(&verification_key(block_hash, proof)?))
+    }
+
+    pub async fn record_verification(&self, block_hash: &BlockHash, proof: &ZKProof, is_valid: bool) -> Result<()> {
+        self.verified.lock().await.insert(verification_key(block_hash, proof)?, is_valid);
+        Ok(())
+    }
+
+    // Only for proofs that verified; the block's own proof stays unverified
+    pub async fn record_substitute(&self, block_hash: BlockHash, proof: &ZKProof) {
+        self.substitutes.lock().await.insert(block_hash, proof.clone());
+    }
+
+    pub async fn substitute(&self, block_hash: &BlockHash) -> Option<ZKProof> {
+        self.substitutes.lock().await.get(block_hash)
+    }
+}
+
+fn verification_key(block_hash: &BlockHash, proof: &ZKProof) -> Result<[u8; 32]> {
+    let mut hasher = Sha256::new();
+    hasher.update(block_hash);
+    hasher.update(bincode::serialize(proof)?);
+    Ok(hasher.finalize().into())
+}
@@ -0,0 +1,52 @@
+use crate::storage::StorageError;
+use crate::types::ProofType;
+use std::path::PathBuf;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ProofError {
+    // No backend for the proof system is compiled into this build
+    #[error("{0:?} proofs are not supported by this node")]
+    Unsupported(ProofType),
+    #[error("unknown proof backend '{0}', expected one of {1:?}")]
+    UnknownBackend(String, [&'static str; 2]),
+    // The chain registers no circuit version for the key we prove with
+    #[error("the chain registers no {0:?} circuit version for this node's verifying key")]
+    UnregisteredKey(ProofType),
+    // Circuit parameters or keys could not be generated, read or written
+    #[error("proof system setup failed: {0:#}")]
+    Setup(anyhow::Error),
+    #[error("zk_proof.key_dir lacks the key files {}", .0.iter().map(|path| path.display().to_string()).collect::<Vec<_>>().join(", "))]
+    MissingKeys(Vec<PathBuf>),
+    // A key file whose SHA-256 is not the one the genesis pins: expected, then found
+    #[error("{} has sha256 {}, the genesis pins {}", .0.display(), hex::encode(.2), hex::encode(.1))]
+    ArtifactMismatch(PathBuf, [u8; 32], [u8; 32]),
+    // Pins only cover key files, and without key_dir the keys come from the development setup
+    #[error("the genesis pins key files, but zk_proof.key_dir is not set")]
+    UnusedPins,
+    #[error("could not download {0}: {1:#}")]
+    Download(String, anyhow::Error),
+    // zk_proof.role is verifier, so no proving keys were loaded
+    #[error("this node only verifies proofs and holds no proving keys")]
+    VerifierOnly,
+    #[error("proving failed: {0:#}")]
+    Prover(anyhow::Error),
+    // The verifier could not run at all, as opposed to rejecting the proof
+    #[error("verification failed to run: {0:#}")]
+    Verifier(anyhow::Error),
+    // The inputs can not be proven, such as blocks out of order for a chain proof
+    #[error("{0}")]
+    InvalidInput(String),
+    #[error("unsupported public inputs version {0}, expected {}", super::PUBLIC_INPUTS_VERSION)]
+    UnsupportedInputs(u8),
+    #[error("public inputs of {0} bytes, expected {}", super::PUBLIC_INPUTS_LEN)]
+    MalformedInputs(usize),
+    #[error("proof queue is full")]
+    QueueFull,
+    #[error("proof workers have stopped")]
+    Stopped,
+    #[error("proof job {0} was dropped")]
+    JobDropped(u64),
+    #[error(transparent)]
+    Storage(#[from] StorageError),
+}
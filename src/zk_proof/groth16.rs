@@ -0,0 +1,493 @@
+use crate::state::{Account, StateTransition};
+use crate::types::{self, poseidon, Block, HashFunction, ProofType};
+use anyhow::{bail, Result};
+use ark_bls12_381::{Bls12_381, Fr};
+use ark_crypto_primitives::snark::SNARK;
+use ark_ff::PrimeField;
+use ark_groth16::{Groth16, PreparedVerifyingKey, Proof, ProvingKey, VerifyingKey};
+use ark_r1cs_std::fields::fp::FpVar;
+use ark_r1cs_std::prelude::*;
+use ark_relations::r1cs::{ConstraintSynthesizer, ConstraintSystemRef, SynthesisError};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use ark_std::rand::{rngs::StdRng, SeedableRng};
+use sha2::{Sha256, Digest};
+use tracing::info;
+
+use super::witness::{update_witnesses, UpdateWitness};
+use super::{KeySource, ProofBackend, PublicInputs, StateCapacity};
+
+// Circuits have a fixed shape, so blocks are padded up to this many transactions (power of two)
+pub const MAX_CIRCUIT_TXS: usize = 4;
+// and state transitions up to this many account updates, with paths this deep
+pub const MAX_STATE_UPDATES: usize = 8;
+pub const MAX_STATE_DEPTH: usize = 16;
+
+// Development parameters: every node derives the same keys from this seed.
+// This is NOT a trusted setup and must be replaced by ceremony artifacts in production.
+const DEV_SETUP_SEED: u64 = 0x5A4B_504F_565F_5345;
+
+// Key files in zk_proof.key_dir
+pub const PROVING_KEY_FILES: [&str; 1] = ["groth16_block.pk"];
+pub const VERIFYING_KEY_FILES: [&str; 1] = ["groth16_block.vk"];
+
+// Proves that the merkle root of the public inputs is the merkle root (as computed by
+// types::calculate_merkle_root) of their `tx_count` transaction hashes, and that their state
+// root is what the account updates of the block's state transition make of the parent state
+// root, each update checked against the state tree as state::smt builds it. Both trees are
+// hashed with Poseidon, so the circuit only proves blocks of chains that set it as their merkle
+// hash function. The other inputs are bound so the proof commits to the parent and validator
+// set; that the updates are the ones the transactions make is checked by nodes.
+#[derive(Clone)]
+pub struct BlockValidationCircuit {
+    pub inputs: PublicInputs,
+    pub tx_hashes: Vec<[u8; 32]>,
+    pub updates: Vec<UpdateWitness>,
+}
+
+impl BlockValidationCircuit {
+    pub fn blank() -> Self {
+        Self {
+            inputs: PublicInputs::default(),
+            tx_hashes: vec![[0; 32]; MAX_CIRCUIT_TXS],
+            updates: vec![UpdateWitness::padding(MAX_STATE_DEPTH); MAX_STATE_UPDATES],
+        }
+    }
+
+    pub fn from_block(block: &Block, inputs: &PublicInputs, state: &StateTransition) -> Result<Self> {
+        if block.transactions.len() > MAX_CIRCUIT_TXS {
+            bail!("Block has {} transactions, circuit supports at most {}",
+                block.transactions.len(), MAX_CIRCUIT_TXS);
+        }
+
+        let mut tx_hashes: Vec<[u8; 32]> = block.transactions.iter().map(|tx| tx.hash()).collect();
+        tx_hashes.resize(MAX_CIRCUIT_TXS, [0; 32]);
+
+        Ok(Self {
+            inputs: *inputs,
+            tx_hashes,
+            updates: update_witnesses(state, MAX_STATE_UPDATES, MAX_STATE_DEPTH)?,
+        })
+    }
+}
+
+impl ConstraintSynthesizer<Fr> for BlockValidationCircuit {
+    fn generate_constraints(self, cs: ConstraintSystemRef<Fr>) -> Result<(), SynthesisError> {
+        // Public inputs, in the order of PublicInputs::field_elements
+        let inputs = self.inputs.field_elements().into_iter()
+            .map(|element| FpVar::new_input(cs.clone(), || Ok(Fr::from(element))))
+            .collect::<Result<Vec<_>, _>>()?;
+        let tx_count = &inputs[2];
+        let merkle_root = hash_bytes(&inputs[5], &inputs[6])?;
+
+        // Touch every input so all of them are bound by the proof
+        for input in &inputs {
+            let _ = input.square()?;
+        }
+
+        // One-hot encoding of the transaction count; also enforces tx_count <= MAX_CIRCUIT_TXS
+        let count_is = (0..=MAX_CIRCUIT_TXS)
+            .map(|n| tx_count.is_eq(&FpVar::constant(Fr::from(n as u64))))
+            .collect::<Result<Vec<_>, _>>()?;
+        Boolean::kary_or(&count_is)?.enforce_equal(&Boolean::TRUE)?;
+
+        // Leaves are the transaction hashes reduced into the field, as types::poseidon takes them
+        let leaves = self.tx_hashes.iter()
+            .map(|hash| FpVar::new_witness(cs.clone(), || Ok(Fr::from_le_bytes_mod_order(hash))))
+            .collect::<Result<Vec<_>, _>>()?;
+        let constants = PoseidonConstants::load();
+        let root = merkle_root_for_count(leaves, FpVar::zero(), &count_is, |left, right| {
+            poseidon_compress(left, right, &constants)
+        })?;
+        // Canonical little endian bytes, as the native hash outputs them
+        root.to_bytes_le()?.enforce_equal(&merkle_root)?;
+
+        // From the parent state root (inputs 11 and 12) through every update to the state root
+        // (inputs 7 and 8)
+        let mut state_root = FpVar::new_witness(cs.clone(), || Ok(Fr::from_le_bytes_mod_order(&self.inputs.parent_state_root)))?;
+        state_root.to_bytes_le()?.enforce_equal(&hash_bytes(&inputs[11], &inputs[12])?)?;
+        for update in &self.updates {
+            state_root = updated_root(cs.clone(), &state_root, update, &constants)?;
+        }
+        state_root.to_bytes_le()?.enforce_equal(&hash_bytes(&inputs[7], &inputs[8])?)
+    }
+}
+
+// Root of the state tree after the update, as AccountProof::updated_root computes it, with the
+// update's proof checked against `root`. Padding updates leave the root as it is
+fn updated_root(
+    cs: ConstraintSystemRef<Fr>,
+    root: &FpVar<Fr>,
+    update: &UpdateWitness,
+    constants: &PoseidonConstants,
+) -> Result<FpVar<Fr>, SynthesisError> {
+    let used = Boolean::new_witness(cs.clone(), || Ok(update.used))?;
+    let exists = Boolean::new_witness(cs.clone(), || Ok(update.before.is_some()))?;
+    let has_neighbour = Boolean::new_witness(cs.clone(), || Ok(update.neighbour.is_some()))?;
+    (&exists & &has_neighbour).enforce_equal(&Boolean::FALSE)?;
+
+    let account = UInt8::new_witness_vec(cs.clone(), &update.account)?;
+    let (neighbour_id, neighbour) = update.neighbour.unwrap_or_default();
+    let neighbour_id = UInt8::new_witness_vec(cs.clone(), &neighbour_id)?;
+    let old_leaf = leaf_hash(cs.clone(), &account, &update.before.unwrap_or_default(), constants)?;
+    let new_leaf = leaf_hash(cs.clone(), &account, &update.after, constants)?;
+    let neighbour_leaf = leaf_hash(cs.clone(), &neighbour_id, &neighbour, constants)?;
+    let directions = path_bits(&account)?;
+    let neighbour_directions = path_bits(&neighbour_id)?;
+
+    // The path stays as it is unless the neighbour's leaf splits, when it goes on past its old
+    // end down to a last level where the two ids differ, and share every bit above it
+    let old_levels = depth_flags(cs.clone(), update.old_depth)?;
+    let split_levels = depth_flags(cs.clone(), update.new_depth)?;
+    let new_levels = old_levels.iter().zip(&split_levels)
+        .map(|(old, split)| Boolean::conditionally_select(&has_neighbour, split, old))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut extended = Vec::with_capacity(MAX_STATE_DEPTH);
+    let mut last = Vec::with_capacity(MAX_STATE_DEPTH);
+    for depth in 0..MAX_STATE_DEPTH {
+        (&has_neighbour & &old_levels[depth] & !&new_levels[depth]).enforce_equal(&Boolean::FALSE)?;
+        extended.push(&new_levels[depth] & !&old_levels[depth]);
+        let is_last = match new_levels.get(depth + 1) {
+            Some(below) => &new_levels[depth] & !below,
+            None => new_levels[depth].clone(),
+        };
+        let on_path = &has_neighbour & &new_levels[depth];
+        neighbour_directions[depth].conditional_enforce_equal(&(&directions[depth] ^ &is_last), &on_path)?;
+        last.push(is_last);
+    }
+    (&has_neighbour & !Boolean::kary_or(&extended)?).enforce_equal(&Boolean::FALSE)?;
+
+    let siblings = update.siblings.iter()
+        .map(|sibling| FpVar::new_witness(cs.clone(), || Ok(Fr::from_le_bytes_mod_order(sibling))))
+        .collect::<Result<Vec<_>, _>>()?;
+    let empty = FpVar::zero();
+    let old_bottom = FpVar::conditionally_select(&has_neighbour, &neighbour_leaf, &empty)?;
+    let old_bottom = FpVar::conditionally_select(&exists, &old_leaf, &old_bottom)?;
+    fold(old_bottom, &directions, &siblings, &old_levels, constants)?.conditional_enforce_equal(root, &used)?;
+
+    // Past the old path's end the subtrees beside the new one are empty, but for the
+    // neighbour's leaf at the last level
+    let new_siblings = (0..MAX_STATE_DEPTH)
+        .map(|depth| {
+            let beside = FpVar::conditionally_select(&last[depth], &neighbour_leaf, &empty)?;
+            FpVar::conditionally_select(&old_levels[depth], &siblings[depth], &beside)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let new_root = fold(new_leaf, &directions, &new_siblings, &new_levels, constants)?;
+    FpVar::conditionally_select(&used, &new_root, root)
+}
+
+// state::smt's leaf: the byte sponge over the id and the big endian balance and nonce. Its 48
+// bytes make the two rate elements of a single permutation
+fn leaf_hash(cs: ConstraintSystemRef<Fr>, id: &[UInt8<Fr>], account: &Account, constants: &PoseidonConstants) -> Result<FpVar<Fr>, SynthesisError> {
+    let mut bytes = id.to_vec();
+    bytes.extend(UInt8::new_witness_vec(cs.clone(), &account.balance.to_be_bytes())?);
+    bytes.extend(UInt8::new_witness_vec(cs, &account.nonce.to_be_bytes())?);
+
+    let mut state = vec![FpVar::constant(Fr::from(poseidon::BYTES_DOMAIN + bytes.len() as u128)), FpVar::zero(), FpVar::zero()];
+    for (element, chunk) in state[1..].iter_mut().zip(bytes.chunks(poseidon::BYTES_PER_ELEMENT)) {
+        let bits = chunk.iter().map(|byte| byte.to_bits_le()).collect::<Result<Vec<_>, _>>()?.concat();
+        *element += Boolean::le_bits_to_fp(&bits)?;
+    }
+    Ok(poseidon_permute(state, constants)?.swap_remove(1))
+}
+
+// The first MAX_STATE_DEPTH bits of an id, most significant first like state::smt
+fn path_bits(id: &[UInt8<Fr>]) -> Result<Vec<Boolean<Fr>>, SynthesisError> {
+    let mut bits = Vec::with_capacity(MAX_STATE_DEPTH);
+    for byte in id.iter().take(MAX_STATE_DEPTH.div_ceil(8)) {
+        bits.extend(byte.to_bits_be()?);
+    }
+    bits.truncate(MAX_STATE_DEPTH);
+    Ok(bits)
+}
+
+// Which of the MAX_STATE_DEPTH levels a path of `depth` levels has: a run of set flags from the top
+fn depth_flags(cs: ConstraintSystemRef<Fr>, depth: usize) -> Result<Vec<Boolean<Fr>>, SynthesisError> {
+    let levels = (0..MAX_STATE_DEPTH)
+        .map(|level| Boolean::new_witness(cs.clone(), || Ok(level < depth)))
+        .collect::<Result<Vec<_>, _>>()?;
+    for pair in levels.windows(2) {
+        (&pair[1] & !&pair[0]).enforce_equal(&Boolean::FALSE)?;
+    }
+    Ok(levels)
+}
+
+// Hashes up the path's levels from the bottom, as state::smt folds a proof
+fn fold(
+    bottom: FpVar<Fr>,
+    directions: &[Boolean<Fr>],
+    siblings: &[FpVar<Fr>],
+    levels: &[Boolean<Fr>],
+    constants: &PoseidonConstants,
+) -> Result<FpVar<Fr>, SynthesisError> {
+    let mut hash = bottom;
+    for depth in (0..MAX_STATE_DEPTH).rev() {
+        let left = FpVar::conditionally_select(&directions[depth], &siblings[depth], &hash)?;
+        let right = FpVar::conditionally_select(&directions[depth], &hash, &siblings[depth])?;
+        let parent = poseidon_compress(&left, &right, constants)?;
+        hash = FpVar::conditionally_select(&levels[depth], &parent, &hash)?;
+    }
+    Ok(hash)
+}
+
+// A hash given as its two big endian 128-bit halves, as the bytes the hash function outputs.
+// Each half is taken from the low bytes of its canonical little endian encoding
+fn hash_bytes(hi: &FpVar<Fr>, lo: &FpVar<Fr>) -> Result<Vec<UInt8<Fr>>, SynthesisError> {
+    let mut bytes = Vec::with_capacity(32);
+    for half in [hi, lo] {
+        let le = half.to_bytes_le()?;
+        bytes.extend(le[..16].iter().rev().cloned());
+    }
+    Ok(bytes)
+}
+
+// Root of the tree over the first n leaves, for the n `count_is` selects. Tracks the tree width
+// for every possible count so duplication of the odd node and the root position can be
+// selected in-circuit
+fn merkle_root_for_count<T>(
+    mut level: Vec<T>,
+    empty: T,
+    count_is: &[Boolean<Fr>],
+    mut hash_pair: impl FnMut(&T, &T) -> Result<T, SynthesisError>,
+) -> Result<T, SynthesisError>
+where
+    T: CondSelectGadget<Fr> + Clone,
+{
+    let mut widths: Vec<usize> = (0..=MAX_CIRCUIT_TXS).collect();
+    let mut roots: Vec<Option<T>> = vec![None; MAX_CIRCUIT_TXS + 1];
+    roots[0] = Some(empty.clone());
+    roots[1] = Some(level[0].clone());
+
+    while level.len() > 1 {
+        let mut next = Vec::with_capacity(level.len() / 2);
+
+        for i in 0..level.len() / 2 {
+            let left = &level[2 * i];
+            let right = &level[2 * i + 1];
+
+            let has_right_for: Vec<_> = (0..=MAX_CIRCUIT_TXS)
+                .filter(|&n| 2 * i + 1 < widths[n])
+                .map(|n| count_is[n].clone())
+                .collect();
+            let has_right = if has_right_for.is_empty() {
+                Boolean::FALSE
+            } else {
+                Boolean::kary_or(&has_right_for)?
+            };
+
+            let right = T::conditionally_select(&has_right, right, left)?;
+            next.push(hash_pair(left, &right)?);
+        }
+
+        for n in 0..=MAX_CIRCUIT_TXS {
+            if widths[n] > 1 {
+                widths[n] = widths[n].div_ceil(2);
+                if widths[n] == 1 {
+                    roots[n] = Some(next[0].clone());
+                }
+            }
+        }
+
+        level = next;
+    }
+
+    let mut computed_root = empty;
+    for (n, root) in roots.into_iter().enumerate() {
+        if let Some(root) = root {
+            computed_root = T::conditionally_select(&count_is[n], &root, &computed_root)?;
+        }
+    }
+    Ok(computed_root)
+}
+
+// types::poseidon's constants as field elements
+struct PoseidonConstants {
+    round_constants: Vec<Fr>,
+    mds: Vec<Vec<Fr>>,
+}
+
+impl PoseidonConstants {
+    fn load() -> Self {
+        Self {
+            round_constants: poseidon::round_constants().iter().map(|bytes| Fr::from_le_bytes_mod_order(bytes)).collect(),
+            mds: poseidon::mds_matrix().iter()
+                .map(|row| row.iter().map(|bytes| Fr::from_le_bytes_mod_order(bytes)).collect())
+                .collect(),
+        }
+    }
+}
+
+// Same rounds as types::poseidon::compress: permutes [3, left, right] and keeps the first rate
+// element. About 240 constraints, where a SHA-256 compression takes tens of thousands
+fn poseidon_compress(left: &FpVar<Fr>, right: &FpVar<Fr>, constants: &PoseidonConstants) -> Result<FpVar<Fr>, SynthesisError> {
+    let state = vec![FpVar::constant(Fr::from(poseidon::COMPRESS_DOMAIN)), left.clone(), right.clone()];
+    Ok(poseidon_permute(state, constants)?.swap_remove(1))
+}
+
+fn poseidon_permute(mut state: Vec<FpVar<Fr>>, constants: &PoseidonConstants) -> Result<Vec<FpVar<Fr>>, SynthesisError> {
+    let half = poseidon::FULL_ROUNDS / 2;
+    for (round, round_constants) in constants.round_constants.chunks(poseidon::WIDTH).enumerate() {
+        for (element, constant) in state.iter_mut().zip(round_constants) {
+            *element += FpVar::constant(*constant);
+        }
+        if round < half || round >= half + poseidon::PARTIAL_ROUNDS {
+            for element in state.iter_mut() {
+                *element = element.pow_by_constant([poseidon::ALPHA])?;
+            }
+        } else {
+            state[0] = state[0].pow_by_constant([poseidon::ALPHA])?;
+        }
+
+        let mut mixed = Vec::with_capacity(poseidon::WIDTH);
+        for row in &constants.mds {
+            let mut sum = FpVar::zero();
+            for (coefficient, element) in row.iter().zip(&state) {
+                sum += element * FpVar::constant(*coefficient);
+            }
+            mixed.push(sum);
+        }
+        state = mixed;
+    }
+    Ok(state)
+}
+
+// Verifier-only nodes hold no proving keys
+pub struct Groth16Backend {
+    proving_key: Option<ProvingKey<Bls12_381>>,
+    verifying_key: PreparedVerifyingKey<Bls12_381>,
+    verifying_key_hash: [u8; 32],
+}
+
+impl Groth16Backend {
+    pub fn setup(keys: &KeySource) -> Result<Self> {
+        let (proving_key, verifying_key) = match keys.dir() {
+            Some(dir) => {
+                info!("🔐 Loading Groth16 {} keys from {}", if keys.proving() { "proving" } else { "verifying" }, dir.display());
+                read_keys(keys)?
+            }
+            None => {
+                info!("🔐 Running Groth16 setup for BlockValidationCircuit (max {} txs, {} account updates)",
+                    MAX_CIRCUIT_TXS, MAX_STATE_UPDATES);
+                info!("⚠️  Note: Using deterministic development parameters, not a trusted setup");
+
+                let mut rng = StdRng::seed_from_u64(DEV_SETUP_SEED);
+                let (proving_key, verifying_key) = without_tracing(|| {
+                    Groth16::<Bls12_381>::circuit_specific_setup(BlockValidationCircuit::blank(), &mut rng)
+                })?;
+
+                (keys.proving().then_some(proving_key), verifying_key)
+            }
+        };
+
+        let verifying_key_hash = key_hash(&verifying_key)?;
+
+        info!("✅ Groth16 keys ready (vk {})", hex::encode(verifying_key_hash));
+
+        Ok(Self {
+            proving_key,
+            verifying_key: Groth16::<Bls12_381>::process_vk(&verifying_key)?,
+            verifying_key_hash,
+        })
+    }
+
+    // Contents of the key files zk_proof.key_dir is read from
+    pub fn key_files(&self) -> Result<Vec<(&'static str, Vec<u8>)>> {
+        let proving_key = match &self.proving_key {
+            Some(proving_key) => proving_key,
+            None => bail!("Groth16 proving keys are not loaded"),
+        };
+
+        Ok(vec![
+            (PROVING_KEY_FILES[0], serialize(proving_key)?),
+            (VERIFYING_KEY_FILES[0], serialize(&self.verifying_key.vk)?),
+        ])
+    }
+}
+
+// Proving keys contain their verifying key
+fn read_keys(keys: &KeySource) -> Result<(Option<ProvingKey<Bls12_381>>, VerifyingKey<Bls12_381>)> {
+    if keys.proving() {
+        let proving_key = ProvingKey::<Bls12_381>::deserialize_compressed(&keys.map(PROVING_KEY_FILES[0])?[..])?;
+        let verifying_key = proving_key.vk.clone();
+        return Ok((Some(proving_key), verifying_key));
+    }
+    let verifying_key = VerifyingKey::<Bls12_381>::deserialize_compressed(keys.read(VERIFYING_KEY_FILES[0])?.as_slice())?;
+    Ok((None, verifying_key))
+}
+
+fn serialize(key: &impl CanonicalSerialize) -> Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    key.serialize_compressed(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn key_hash(verifying_key: &VerifyingKey<Bls12_381>) -> Result<[u8; 32]> {
+    Ok(Sha256::digest(serialize(verifying_key)?).into())
+}
+
+fn prove<C: ConstraintSynthesizer<Fr>>(proving_key: &ProvingKey<Bls12_381>, circuit: C) -> Result<Vec<u8>> {
+    let proof = without_tracing(|| {
+        Groth16::<Bls12_381>::prove(proving_key, circuit, &mut rand::rngs::OsRng)
+    })?;
+
+    let mut proof_bytes = Vec::new();
+    proof.serialize_compressed(&mut proof_bytes)?;
+    Ok(proof_bytes)
+}
+
+fn verify(verifying_key: &PreparedVerifyingKey<Bls12_381>, inputs: Option<Vec<Fr>>, proof_data: &[u8]) -> Result<bool> {
+    let inputs = match inputs {
+        Some(inputs) => inputs,
+        None => return Ok(false),
+    };
+
+    let proof = match Proof::<Bls12_381>::deserialize_compressed(proof_data) {
+        Ok(proof) => proof,
+        Err(_) => return Ok(false),
+    };
+
+    Ok(Groth16::<Bls12_381>::verify_with_processed_vk(verifying_key, &inputs, &proof)?)
+}
+
+impl ProofBackend for Groth16Backend {
+    fn proof_type(&self) -> ProofType {
+        ProofType::Groth16
+    }
+
+    fn verifying_key_hash(&self) -> [u8; 32] {
+        self.verifying_key_hash
+    }
+
+    fn max_transactions(&self) -> usize {
+        MAX_CIRCUIT_TXS
+    }
+
+    fn state_capacity(&self) -> StateCapacity {
+        StateCapacity { updates: MAX_STATE_UPDATES, depth: MAX_STATE_DEPTH }
+    }
+
+    fn prove(&self, block: &Block, inputs: &PublicInputs, state: &StateTransition) -> Result<Vec<u8>> {
+        if types::merkle_hash_function() != HashFunction::Poseidon {
+            bail!("Groth16 block proofs require the poseidon merkle hash function, the chain uses {:?}",
+                types::merkle_hash_function());
+        }
+        match &self.proving_key {
+            Some(proving_key) => prove(proving_key, BlockValidationCircuit::from_block(block, inputs, state)?),
+            None => bail!("This node holds no Groth16 proving key"),
+        }
+    }
+
+    fn verify(&self, inputs: &PublicInputs, proof_data: &[u8]) -> Result<bool> {
+        verify(&self.verifying_key, Some(public_input_elements(inputs)), proof_data)
+    }
+}
+
+// Gadgets open a tracing span per constraint; under the node's subscriber that
+// costs gigabytes of memory during synthesis, so disable tracing while proving
+fn without_tracing<T>(f: impl FnOnce() -> T) -> T {
+    tracing::subscriber::with_default(tracing::subscriber::NoSubscriber::default(), f)
+}
+
+fn public_input_elements(inputs: &PublicInputs) -> Vec<Fr> {
+    inputs.field_elements().into_iter().map(Fr::from).collect()
+}
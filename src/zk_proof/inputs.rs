@@ -0,0 +1,119 @@
+use super::{ProofError, Result};
+use crate::types::{Block, BlockHash, BlockHeader};
+use serde::{Deserialize, Serialize};
+
+// Leads the encoded inputs, and changes whenever their layout or field element encoding does
+pub const PUBLIC_INPUTS_VERSION: u8 = 2;
+// Version, block number and transaction count, then the five hashes
+pub const PUBLIC_INPUTS_LEN: usize = 1 + 8 + 8 + 5 * 32;
+// Version, block number, transaction count and two halves of each hash
+pub const PUBLIC_INPUT_ELEMENTS: usize = 3 + 5 * 2;
+
+// What a block proof proves, whatever the proof system: the block's place in the chain, its
+// transactions, the account state it leads to from its parent's and the validator set it was
+// proposed under.
+//
+// Encoded as PUBLIC_INPUTS_VERSION, the block number and transaction count as little endian
+// u64, then the parent hash, merkle root, state root, validator set hash and the parent's state
+// root. Circuits take the same values as field elements, in that order: the version and both
+// integers as one element each, and every hash as two big endian 128-bit halves, so they fit
+// any field above 2^128
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PublicInputs {
+    pub block_number: u64,
+    pub tx_count: u64,
+    pub parent_hash: BlockHash,
+    pub merkle_root: BlockHash,
+    pub state_root: BlockHash,
+    // types::validator_set_hash of the consensus state the block was proposed on
+    pub validator_set_hash: BlockHash,
+    // The state root the block's state transition starts from
+    pub parent_state_root: BlockHash,
+}
+
+impl PublicInputs {
+    pub fn new(block: &Block, parent_state_root: BlockHash, validator_set_hash: BlockHash) -> Self {
+        Self {
+            block_number: block.header.block_number,
+            tx_count: block.transactions.len() as u64,
+            parent_hash: block.header.parent_hash,
+            merkle_root: block.header.merkle_root,
+            state_root: block.header.state_root,
+            validator_set_hash,
+            parent_state_root,
+        }
+    }
+
+    // Every field but the validator set and the parent's state root, which only the consensus
+    // state and the parent tell
+    pub fn commits_to(&self, block: &Block) -> bool {
+        self.commits_to_header(&block.header) && self.tx_count == block.transactions.len() as u64
+    }
+
+    // Light clients have no transactions to count
+    pub fn commits_to_header(&self, header: &BlockHeader) -> bool {
+        self.block_number == header.block_number
+            && self.parent_hash == header.parent_hash
+            && self.merkle_root == header.merkle_root
+            && self.state_root == header.state_root
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(PUBLIC_INPUTS_LEN);
+        bytes.push(PUBLIC_INPUTS_VERSION);
+        bytes.extend_from_slice(&self.block_number.to_le_bytes());
+        bytes.extend_from_slice(&self.tx_count.to_le_bytes());
+        for hash in self.hashes() {
+            bytes.extend_from_slice(hash);
+        }
+        bytes
+    }
+
+    // Chain and checkpoint proofs have inputs of their own, which do not decode
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        match bytes.first() {
+            Some(&PUBLIC_INPUTS_VERSION) => {}
+            Some(version) => return Err(ProofError::UnsupportedInputs(*version)),
+            None => return Err(ProofError::MalformedInputs(0)),
+        }
+        if bytes.len() != PUBLIC_INPUTS_LEN {
+            return Err(ProofError::MalformedInputs(bytes.len()));
+        }
+
+        let hash = |index: usize| -> BlockHash { bytes[17 + index * 32..49 + index * 32].try_into().unwrap() };
+        Ok(Self {
+            block_number: u64::from_le_bytes(bytes[1..9].try_into().unwrap()),
+            tx_count: u64::from_le_bytes(bytes[9..17].try_into().unwrap()),
+            parent_hash: hash(0),
+            merkle_root: hash(1),
+            state_root: hash(2),
+            validator_set_hash: hash(3),
+            parent_state_root: hash(4),
+        })
+    }
+
+    // Backends map each value into their own field
+    pub fn field_elements(&self) -> [u128; PUBLIC_INPUT_ELEMENTS] {
+        let mut elements = [0u128; PUBLIC_INPUT_ELEMENTS];
+        elements[0] = PUBLIC_INPUTS_VERSION as u128;
+        elements[1] = self.block_number as u128;
+        elements[2] = self.tx_count as u128;
+        for (i, hash) in self.hashes().into_iter().enumerate() {
+            let [hi, lo] = hash_halves(hash);
+            elements[3 + i * 2] = hi;
+            elements[4 + i * 2] = lo;
+        }
+        elements
+    }
+
+    fn hashes(&self) -> [&BlockHash; 5] {
+        [&self.parent_hash, &self.merkle_root, &self.state_root, &self.validator_set_hash, &self.parent_state_root]
+    }
+}
+
+fn hash_halves(hash: &BlockHash) -> [u128; 2] {
+    [
+        u128::from_be_bytes(hash[..16].try_into().unwrap()),
+        u128::from_be_bytes(hash[16..].try_into().unwrap()),
+    ]
+}
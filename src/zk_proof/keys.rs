@@ -0,0 +1,49 @@
+use crate::types::ProofType;
+use std::collections::HashMap;
+
+// Circuit version our own keys are registered under on chains whose genesis registers none
+pub const DEFAULT_CIRCUIT_VERSION: u32 = 1;
+// Mock proofs are not made against a real key; they are registered under this one
+pub const MOCK_KEY: [u8; 32] = [0; 32];
+
+// Hashes of the verifying keys of one version of a proof system's circuits
+#[derive(Debug, Clone, PartialEq)]
+pub struct CircuitKey {
+    pub proof_type: ProofType,
+    pub circuit_version: u32,
+    // Block proof circuit, or the step circuit of chain proofs
+    pub key: [u8; 32],
+}
+
+// The keys proofs are checked against. Proofs only name the circuit version they were made
+// with, so a proposer can not hand in a key that happens to fit a bogus proof
+#[derive(Debug, Clone, Default)]
+pub struct VerifyingKeys {
+    keys: HashMap<(ProofType, u32), CircuitKey>,
+}
+
+impl VerifyingKeys {
+    // Later entries replace earlier ones for the same proof type and version
+    pub fn new(keys: impl IntoIterator<Item = CircuitKey>) -> Self {
+        let keys = keys.into_iter()
+            .map(|key| ((key.proof_type.clone(), key.circuit_version), key))
+            .collect();
+        Self { keys }
+    }
+
+    pub fn get(&self, proof_type: &ProofType, circuit_version: u32) -> Option<&CircuitKey> {
+        self.keys.get(&(proof_type.clone(), circuit_version))
+    }
+
+    pub fn registers(&self, proof_type: &ProofType) -> bool {
+        self.keys.keys().any(|(registered, _)| registered == proof_type)
+    }
+
+    // Newest version registered for one of our keys, which the proofs we make name
+    pub fn version_of(&self, proof_type: &ProofType, key: &[u8; 32]) -> Option<u32> {
+        self.keys.values()
+            .filter(|circuit| circuit.proof_type == *proof_type && circuit.key == *key)
+            .map(|circuit| circuit.circuit_version)
+            .max()
+    }
+}
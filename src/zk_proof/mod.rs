@@ -1,41 +1,335 @@
-use crate::types::{Block, ZKProof, ProofType, BlockHash};
-use anyhow::Result;
-use tracing::{info, debug, error, warn};
-use std::sync::Arc;
-use tokio::sync::RwLock;
+use crate::config::ZkProofConfig;
+use crate::state::{AccountId, AccountState, StateTransition};
+use crate::storage::StorageManager;
+use crate::types::{self, Block, BlockHash, BlockHeader, ZKProof, ProofType};
+use tracing::{info, debug, warn};
 use sha2::{Sha256, Digest};
-use rand::Rng;
+use std::collections::BTreeSet;
+use std::sync::Arc;
+
+#[cfg(feature = "groth16")]
+pub mod groth16;
+#[cfg(feature = "plonk")]
+pub mod plonk;
+#[cfg(feature = "nova")]
+pub mod nova;
+mod artifacts;
+mod cache;
+mod error;
+mod inputs;
+mod keys;
+mod service;
+mod verifier;
+#[cfg(any(feature = "groth16", feature = "plonk"))]
+mod witness;
+
+pub use artifacts::{export_keys, ArtifactManager, ArtifactStatus, KeyRole, KeySource, KEY_ROLES};
+use cache::ProofCache;
+pub use error::ProofError;
+pub use inputs::{PublicInputs, PUBLIC_INPUTS_LEN, PUBLIC_INPUTS_VERSION, PUBLIC_INPUT_ELEMENTS};
+pub use keys::{CircuitKey, VerifyingKeys, DEFAULT_CIRCUIT_VERSION, MOCK_KEY};
+pub use service::{ProofJob, ProofService};
+pub use verifier::{ProofVerifier, VerifierRegistry};
+
+// Values of zk_proof.backend, the proof system this node proves its blocks with
+pub const BLOCK_PROOF_BACKENDS: [&str; 2] = ["groth16", "plonk"];
+
+type Result<T, E = ProofError> = std::result::Result<T, E>;
+
+// A proof system for block validity proofs. Nodes set up every backend compiled in, so
+// blocks are verified whichever backend their proposer chose
+pub trait ProofBackend: Send + Sync {
+    fn proof_type(&self) -> ProofType;
+    
+    // Proofs only verify against the key this hash identifies
+    fn verifying_key_hash(&self) -> [u8; 32];
+    
+    // Circuits have a fixed shape, so a block can not exceed this many transactions
+    fn max_transactions(&self) -> usize;
+    
+    // nor make state changes beyond this
+    fn state_capacity(&self) -> StateCapacity;
+    
+    // `inputs` commit to the block; `state` proves every account the block changed against the
+    // parent's state root
+    fn prove(&self, block: &Block, inputs: &PublicInputs, state: &StateTransition) -> anyhow::Result<Vec<u8>>;
+    
+    fn verify(&self, inputs: &PublicInputs, proof_data: &[u8]) -> anyhow::Result<bool>;
+}
+
+// The account updates a block circuit proves: how many, and how deep their paths in the state
+// tree go
+#[derive(Debug, Clone, Copy)]
+pub struct StateCapacity {
+    pub updates: usize,
+    pub depth: usize,
+}
+
+impl StateCapacity {
+    // Whether the changes from `before` to `after` fit, given every account that changed is in
+    // `touched`; blocks being built keep that set as they go rather than comparing whole
+    // states. Trees only grow, so no path is deeper while the block is applied than in the
+    // state it leaves
+    pub fn fits(&self, before: &AccountState, after: &AccountState, touched: &BTreeSet<AccountId>) -> bool {
+        let mut changes = 0;
+        for id in touched.iter().filter(|id| after.differs(before, id)) {
+            changes += 1;
+            if changes > self.updates || after.path_depth(id) > self.depth {
+                return false;
+            }
+        }
+        true
+    }
+}
 
 pub struct ZKProofGenerator {
-    rng: Arc<RwLock<rand::rngs::ThreadRng>>,
+    // Proof system for the blocks we propose
+    proof_type: ProofType,
+    // Verifiers refuse to prove, having no proving keys
+    role: KeyRole,
+    // Empty when running on mock proofs
+    backends: Vec<Arc<dyn ProofBackend>>,
+    // Proofs are checked by the verifier of their own type, so proposers may use any backend
+    verifiers: VerifierRegistry,
+    #[cfg(feature = "nova")]
+    nova: Option<Arc<nova::NovaBackend>>,
+    cache: ProofCache,
 }
 
 impl ZKProofGenerator {
-    pub fn new() -> Result<Self> {
+    // Block proofs use the backends enabled by features, falling back to mock proofs when
+    // none is; chain proofs use Nova when the feature is enabled
+    pub fn new(proof_type: ProofType) -> Result<Self> {
+        Self::setup(proof_type, &KeySource::new(KeyRole::Prover, None))
+    }
+    
+    fn setup(proof_type: ProofType, keys: &KeySource) -> Result<Self> {
+        let backends = setup_backends(keys)?;
+        #[cfg(not(feature = "nova"))]
+        if backends.is_empty() {
+            return Ok(Self { role: keys.role, ..Self::mock()? });
+        }
+        if keys.proving() && !backends.is_empty() && !backends.iter().any(|backend| backend.proof_type() == proof_type) {
+            return Err(ProofError::Unsupported(proof_type));
+        }
+        
+        match keys.role {
+            KeyRole::Prover => info!("🔐 Proving blocks with {:?}", proof_type),
+            KeyRole::Verifier => info!("🔍 Verifying proofs only, without proving keys"),
+        }
+        for backend in &backends {
+            info!("🔑 {:?} verifying key: {}", backend.proof_type(), hex::encode(backend.verifying_key_hash()));
+        }
+        let mut verifiers = VerifierRegistry::new(&backends);
+        #[cfg(feature = "nova")]
+        info!("🔐 Initializing recursive chain proofs (Nova over Pallas/Vesta)");
+        #[cfg(feature = "nova")]
+        let nova = Arc::new(nova::NovaBackend::setup(keys).map_err(ProofError::Setup)?);
+        #[cfg(feature = "nova")]
+        verifiers.register(ProofType::Nova, Box::new(verifier::NovaVerifier(nova.clone())));
+        #[cfg(feature = "nova")]
+        let chain_key = nova.params_digest();
+        #[cfg(not(feature = "nova"))]
+        let chain_key = MOCK_KEY;
+        verifiers.set_keys(default_keys(&backends, chain_key));
+        
+        Ok(Self {
+            proof_type,
+            role: keys.role,
+            backends,
+            verifiers,
+            #[cfg(feature = "nova")]
+            nova: Some(nova),
+            cache: ProofCache::new(cache::PROOF_CACHE_SIZE),
+        })
+    }
+    
+    // with_config, after downloading the key files zk_proof.key_dir lacks from zk_proof.key_mirrors
+    pub async fn load(config: &ZkProofConfig, role: KeyRole) -> Result<Self> {
+        if !config.mock {
+            ArtifactManager::new(config, role).fetch().await?;
+        }
+        Self::with_config(config, role)
+    }
+    
+    // Provers load proving keys as well, verifiers only verifying keys; key files in
+    // zk_proof.key_dir are checked for, and against the genesis, before any setup runs
+    pub fn with_config(config: &ZkProofConfig, role: KeyRole) -> Result<Self> {
+        let proof_type = match config.backend.as_str() {
+            "groth16" => ProofType::Groth16,
+            "plonk" => ProofType::Plonk,
+            other => return Err(ProofError::UnknownBackend(other.to_string(), BLOCK_PROOF_BACKENDS)),
+        };
+        if config.mock {
+            return Ok(Self { role, proof_type, ..Self::mock()? });
+        }
+        
+        let keys = KeySource::new(role, config.key_dir.clone());
+        keys.check()?;
+        if keys.dir().is_some() && artifacts::required_files(role).is_empty() {
+            warn!("⚠️ zk_proof.key_dir is set, but no backend in this build reads key files");
+        }
+        let artifacts = ArtifactManager::new(config, role);
+        if artifacts.unused_pins() {
+            return Err(ProofError::UnusedPins);
+        }
+        for status in artifacts.verify()? {
+            match status.pinned {
+                true => info!("📦 {}: {} bytes, sha256 {} as pinned by the genesis",
+                    status.name, status.bytes, hex::encode(status.sha256)),
+                false => warn!("⚠️ {}: {} bytes, sha256 {}; the genesis pins no hash for it",
+                    status.name, status.bytes, hex::encode(status.sha256)),
+            }
+        }
+        Self::setup(proof_type, &keys)
+    }
+    
+    // Mock backend regardless of enabled features; used for tests and conformance vectors
+    pub fn mock() -> Result<Self> {
         info!("🔐 Initializing ZK Proof Generator (Mock Implementation)");
         info!("⚠️  Note: Using mock ZK proofs for development");
         
+        let mut verifiers = VerifierRegistry::new(&[]);
+        verifiers.set_keys(default_keys(&[], MOCK_KEY));
         Ok(Self {
-            rng: Arc::new(RwLock::new(rand::thread_rng())),
+            proof_type: ProofType::Groth16,
+            role: KeyRole::Prover,
+            backends: Vec::new(),
+            verifiers,
+            #[cfg(feature = "nova")]
+            nova: None,
+            cache: ProofCache::new(cache::PROOF_CACHE_SIZE),
         })
     }
     
-    pub async fn generate_proof(&self, block: &Block) -> Result<ZKProof> {
+    // Keeps generated proofs in storage as well, so a restarted node does not prove them again
+    pub fn persist_proofs(&mut self, storage: StorageManager) {
+        self.cache.persist_to(storage);
+    }
+    
+    // Checks proofs against the keys the chain registers instead of the ones we set up. Fails
+    // when the chain does not register the key we prove our own blocks with, or on verifiers,
+    // a key of one of our backends whose proof system the chain registers other keys for
+    pub fn use_verifying_keys(&mut self, keys: VerifyingKeys) -> Result<()> {
+        if self.role == KeyRole::Verifier {
+            for backend in &self.backends {
+                let proof_type = backend.proof_type();
+                if keys.registers(&proof_type) && keys.version_of(&proof_type, &backend.verifying_key_hash()).is_none() {
+                    return Err(ProofError::UnregisteredKey(proof_type));
+                }
+            }
+        } else {
+            let own_key = match self.backend(&self.proof_type) {
+                Some(backend) => backend.verifying_key_hash(),
+                None => MOCK_KEY,
+            };
+            if keys.version_of(&self.proof_type, &own_key).is_none() {
+                return Err(ProofError::UnregisteredKey(self.proof_type.clone()));
+            }
+        }
+        self.verifiers.set_keys(keys);
+        info!("🔑 Verifying proofs against the circuit keys registered at genesis");
+        Ok(())
+    }
+    
+    pub fn can_prove(&self) -> bool {
+        self.role == KeyRole::Prover
+    }
+    
+    fn check_can_prove(&self) -> Result<()> {
+        match self.role {
+            KeyRole::Prover => Ok(()),
+            KeyRole::Verifier => Err(ProofError::VerifierOnly),
+        }
+    }
+    
+    // Circuit version the proofs we make with `key` name
+    fn circuit_version(&self, proof_type: &ProofType, key: &[u8; 32]) -> Result<u32> {
+        match self.verifiers.keys().version_of(proof_type, key) {
+            Some(version) => Ok(version),
+            None => Err(ProofError::UnregisteredKey(proof_type.clone())),
+        }
+    }
+    
+    // A proof made earlier for this block, if it still commits to the block's contents
+    pub async fn cached_proof(&self, block: &Block) -> Result<Option<ZKProof>> {
+        Ok(self.cache.get(&block.hash()).await?
+            .filter(|proof| PublicInputs::from_bytes(&proof.public_inputs).is_ok_and(|inputs| inputs.commits_to(block))))
+    }
+    
+    fn backend(&self, proof_type: &ProofType) -> Option<&dyn ProofBackend> {
+        self.backends.iter()
+            .find(|backend| backend.proof_type() == *proof_type)
+            .map(|backend| backend.as_ref())
+    }
+    
+    // Upper bound on transactions per block imposed by the proving backend
+    pub fn max_transactions(&self) -> Option<usize> {
+        #[allow(unused_mut)]
+        let mut max = self.backend(&self.proof_type).map(|backend| backend.max_transactions());
+        
+        // Committed blocks must also fit the chain proof step
+        #[cfg(feature = "nova")]
+        if self.nova.is_some() {
+            max = Some(max.map_or(nova::MAX_STEP_TXS, |max: usize| max.min(nova::MAX_STEP_TXS)));
+        }
+        
+        max
+    }
+    
+    // Bound on the state changes of a block, imposed by the proving backend like max_transactions
+    pub fn state_capacity(&self) -> Option<StateCapacity> {
+        self.backend(&self.proof_type).map(|backend| backend.state_capacity())
+    }
+    
+    pub async fn generate_proof(&self, block: &Block, inputs: &PublicInputs, state: &StateTransition) -> Result<ZKProof> {
+        if let Some(proof) = self.cached_proof(block).await? {
+            debug!("♻️ Reusing cached proof for block #{}", block.header.block_number);
+            return Ok(proof);
+        }
+        
+        // A witness that does not end at the block's state root would prove nothing
+        if state.post_root != block.header.state_root || !state.verify() {
+            return Err(ProofError::InvalidInput(format!("state transition does not lead to the state root of block #{}", block.header.block_number)));
+        }
+        if state.pre_root != inputs.parent_state_root {
+            return Err(ProofError::InvalidInput(format!("state transition of block #{} does not start from the parent's state root", block.header.block_number)));
+        }
+        if !inputs.commits_to(block) {
+            return Err(ProofError::InvalidInput(format!("public inputs are not those of block #{}", block.header.block_number)));
+        }
+        
+        let proof = self.prove_block(block, inputs, state).await?;
+        self.cache.insert(block.hash(), &proof).await?;
+        Ok(proof)
+    }
+    
+    async fn prove_block(&self, block: &Block, inputs: &PublicInputs, state: &StateTransition) -> Result<ZKProof> {
+        self.check_can_prove()?;
         info!("🔨 Generating ZK proof for block #{}", block.header.block_number);
         
-        // Extract public inputs first
-        let public_inputs = self.extract_public_inputs(block);
+        let public_inputs = inputs.to_bytes();
         info!("📊 Public inputs: {} bytes", public_inputs.len());
         
+        if let Some(backend) = self.backend(&self.proof_type) {
+            let zk_proof = ZKProof {
+                proof_data: backend.prove(block, inputs, state).map_err(ProofError::Prover)?,
+                public_inputs,
+                circuit_version: self.circuit_version(&backend.proof_type(), &backend.verifying_key_hash())?,
+                proof_type: backend.proof_type(),
+            };
+            info!("✅ Generated {:?} proof: {} bytes", zk_proof.proof_type, zk_proof.proof_data.len());
+            return Ok(zk_proof);
+        }
+        
         // Generate deterministic proof based on block content
         let block_hash = self.hash_block_content(block);
-        let proof_data = self.generate_deterministic_proof(&block_hash).await?;
         
         let zk_proof = ZKProof {
-            proof_data,
+            proof_data: mock_proof(&block_hash),
             public_inputs,
-            verification_key: self.generate_verification_key(&block_hash),
-            proof_type: ProofType::Groth16,
+            circuit_version: self.circuit_version(&self.proof_type, &MOCK_KEY)?,
+            proof_type: self.proof_type.clone(),
         };
         
         info!("✅ Generated ZK proof: {} bytes proof, {} bytes public inputs", 
@@ -43,113 +337,284 @@ impl ZKProofGenerator {
         Ok(zk_proof)
     }
     
+    // Fails with ProofError::Unsupported for proof types without a verifier; check can_verify
+    // first where that is not an error
     pub async fn verify_proof(&self, zk_proof: &ZKProof) -> Result<bool> {
-        debug!("🔍 Verifying ZK proof ({} bytes)", zk_proof.proof_data.len());
-        
-        // Mock verification - check if proof data is valid format
-        let is_valid = !zk_proof.proof_data.is_empty() 
-            && !zk_proof.public_inputs.is_empty()
-            && zk_proof.proof_data.len() >= 64; // Minimum proof size
+        debug!("🔍 Verifying {:?} proof ({} bytes)", zk_proof.proof_type, zk_proof.proof_data.len());
+        if !self.accepts(zk_proof) {
+            return Ok(false);
+        }
         
+        let is_valid = self.verifiers.verify(zk_proof)?;
         if is_valid {
-            info!("✅ ZK proof verification successful");
+            info!("✅ {:?} proof verification successful", zk_proof.proof_type);
         } else {
-            warn!("❌ ZK proof verification failed");
+            warn!("❌ {:?} proof verification failed", zk_proof.proof_type);
         }
-        
         Ok(is_valid)
     }
+
+    pub async fn verify_block_proof(&self, block: &Block) -> Result<bool> {
+        // The proof must commit to this block's public inputs, not just be well-formed
+        if !PublicInputs::from_bytes(&block.zk_proof.public_inputs).is_ok_and(|inputs| inputs.commits_to(block)) {
+            warn!("❌ ZK proof public inputs do not match block #{}", block.header.block_number);
+            return Ok(false);
+        }
+
+        // Without a verifier for its own proof, only a substitute proof can vouch for the block
+        if !self.can_verify(&block.zk_proof.proof_type) {
+            return self.is_proven(block).await;
+        }
+
+        // Blocks arrive by gossip, sync and proof requests; each block and proof pair is verified once
+        let block_hash = block.hash();
+        if let Some(is_valid) = self.cache.verified(&block_hash, &block.zk_proof).await? {
+            return Ok(is_valid);
+        }
+        let is_valid = self.verify_proof(&block.zk_proof).await?;
+        self.cache.record_verification(&block_hash, &block.zk_proof, is_valid).await?;
+        Ok(is_valid)
+    }
+
+    // Mock nodes verify every proof type
+    pub fn can_verify(&self, proof_type: &ProofType) -> bool {
+        self.verifiers.supports(proof_type)
+    }
     
-    fn hash_block_content(&self, block: &Block) -> [u8; 32] {
-        let mut hasher = Sha256::new();
-        hasher.update(&block.header.block_number.to_le_bytes());
-        hasher.update(&block.header.parent_hash);
-        hasher.update(&block.header.merkle_root);
-        hasher.update(&block.header.timestamp.timestamp().to_le_bytes());
-        hasher.update(&block.header.validator);
-        hasher.finalize().into()
+    // Whether the block's proof was already found valid, or a proof of the same public inputs
+    // checked by verify_block_proof_with stands in for it
+    pub async fn is_proven(&self, block: &Block) -> Result<bool> {
+        let block_hash = block.hash();
+        if self.cache.verified(&block_hash, &block.zk_proof).await? == Some(true) {
+            return Ok(true);
+        }
+        Ok(self.cache.substitute(&block_hash).await
+            .is_some_and(|proof| proof.public_inputs == block.zk_proof.public_inputs))
     }
     
-    async fn generate_deterministic_proof(&self, block_hash: &[u8; 32]) -> Result<Vec<u8>> {
-        // Generate deterministic proof based on block hash
-        let mut proof_data = Vec::with_capacity(256);
-        
-        // Add block hash as proof base
-        proof_data.extend_from_slice(block_hash);
-        
-        // Add some deterministic padding based on hash
-        for i in 0..28 {
-            let val = block_hash[i % 32].wrapping_add(i as u8);
-            proof_data.extend_from_slice(&val.to_le_bytes());
+    // Checks a block whose proof we have no backend for against another proof of it. Both prove
+    // the same public inputs, so when the other one holds it is recorded as the block's
+    // substitute; the block's own proof is never marked verified
+    pub async fn verify_block_proof_with(&self, block: &Block, zk_proof: &ZKProof) -> Result<bool> {
+        let commits = PublicInputs::from_bytes(&block.zk_proof.public_inputs).is_ok_and(|inputs| inputs.commits_to(block));
+        if !commits || zk_proof.public_inputs != block.zk_proof.public_inputs {
+            warn!("❌ ZK proof public inputs do not match block #{}", block.header.block_number);
+            return Ok(false);
         }
         
-        // Add timestamp-based randomness (but deterministic for same block)
-        let time_factor = (block_hash[0] as u64) * 1000;
-        proof_data.extend_from_slice(&time_factor.to_le_bytes());
+        let block_hash = block.hash();
+        let is_valid = self.verify_proof(zk_proof).await?;
+        self.cache.record_verification(&block_hash, zk_proof, is_valid).await?;
+        if is_valid {
+            self.cache.record_substitute(block_hash, zk_proof).await;
+        }
+        Ok(is_valid)
+    }
+    
+    // Light clients only have the header, so the transaction count is taken from the proof.
+    // The block proof binds the header's parent, merkle root and state root, and the parent's
+    // state root when the caller knows it
+    pub async fn verify_header_proof(&self, header: &BlockHeader, parent_state_root: Option<&BlockHash>, zk_proof: &ZKProof) -> Result<bool> {
+        let commits = PublicInputs::from_bytes(&zk_proof.public_inputs).is_ok_and(|inputs| {
+            inputs.commits_to_header(header) && parent_state_root.is_none_or(|root| inputs.parent_state_root == *root)
+        });
+        if !commits {
+            warn!("❌ ZK proof public inputs do not match header #{}", header.block_number);
+            return Ok(false);
+        }
         
-        Ok(proof_data)
+        self.verify_proof(zk_proof).await
     }
     
-    fn generate_verification_key(&self, block_hash: &[u8; 32]) -> Vec<u8> {
-        // Generate deterministic verification key
-        let mut vk = Vec::with_capacity(64);
-        vk.extend_from_slice(block_hash);
-        vk.extend_from_slice(&block_hash[..32]); // Double the hash
-        vk
+    // Block proofs must be in the chain's proof system; the sender does not get to
+    // pick one we happen to have a verifier for
+    fn accepts(&self, zk_proof: &ZKProof) -> bool {
+        let accepted = zk_proof.proof_type == self.proof_type
+            || !matches!(zk_proof.proof_type, ProofType::Groth16 | ProofType::Plonk);
+        if !accepted {
+            warn!("❌ {:?} proof rejected, the chain requires {:?} proofs", zk_proof.proof_type, self.proof_type);
+        }
+        accepted
     }
     
-    fn extract_public_inputs(&self, block: &Block) -> Vec<u8> {
-        // Extract public inputs from block for ZK proof
-        let mut inputs = Vec::new();
-        
-        // Block number
-        inputs.extend_from_slice(&block.header.block_number.to_le_bytes());
-        
-        // Merkle root hash
-        inputs.extend_from_slice(&block.header.merkle_root);
-        
-        // Timestamp
-        inputs.extend_from_slice(&block.header.timestamp.timestamp().to_le_bytes());
-        
-        inputs
+    fn hash_block_content(&self, block: &Block) -> [u8; 32] {
+        types::hasher().hash(&[
+            &block.header.block_number.to_le_bytes(),
+            &block.header.parent_hash,
+            &block.header.merkle_root,
+            &block.header.state_root,
+            &block.header.timestamp.timestamp().to_le_bytes(),
+            &block.header.validator,
+        ])
     }
     
-    pub async fn generate_recursive_proof(&self, previous_proof: &ZKProof, new_block: &Block) -> Result<ZKProof> {
-        debug!("Generating recursive ZK proof");
+    // Extends the chain proof at the parent height (None before the first block) with consecutive blocks
+    pub async fn generate_recursive_proof(&self, previous_proof: Option<&ZKProof>, new_blocks: &[Block]) -> Result<ZKProof> {
+        debug!("Generating recursive ZK proof over {} blocks", new_blocks.len());
+        self.check_can_prove()?;
         
-        // Mock recursive proof generation
-        let mut rng = self.rng.write().await;
-        let proof_data: Vec<u8> = (0..256).map(|_| rng.gen()).collect();
+        let (height, mut acc) = match previous_proof {
+            Some(proof) => match parse_chain_inputs(proof) {
+                Some(position) => position,
+                None => return Err(ProofError::InvalidInput("previous proof is not a chain proof".to_string())),
+            },
+            None => (0, self.genesis_accumulator()),
+        };
+        if new_blocks.is_empty() {
+            return Err(ProofError::InvalidInput("no blocks to add to the chain proof".to_string()));
+        }
+        for (offset, block) in new_blocks.iter().enumerate() {
+            if block.header.block_number != height + 1 + offset as u64 {
+                return Err(ProofError::InvalidInput(format!("chain proof at height {} cannot be extended with block #{}",
+                    height + offset as u64, block.header.block_number)));
+            }
+        }
+        let new_height = height + new_blocks.len() as u64;
         
-        let public_inputs = self.extract_recursive_public_inputs(previous_proof, new_block);
+        #[cfg(feature = "nova")]
+        if let Some(backend) = &self.nova {
+            let proof_data = backend.prove(height, &acc, new_blocks).map_err(ProofError::Prover)?;
+            for block in new_blocks {
+                acc = nova::NovaBackend::accumulate(&acc, block).map_err(ProofError::Prover)?;
+            }
+            
+            let zk_proof = ZKProof {
+                proof_data,
+                public_inputs: chain_inputs(new_height, &acc),
+                circuit_version: self.circuit_version(&ProofType::Nova, &backend.params_digest())?,
+                proof_type: ProofType::Nova,
+            };
+            info!("✅ Generated Nova chain proof for {} blocks: {} bytes", new_height, zk_proof.proof_data.len());
+            return Ok(zk_proof);
+        }
         
+        // Mock accumulator: a hash chain over the same per-block values the Nova step absorbs
+        for block in new_blocks {
+            let mut hasher = Sha256::new();
+            hasher.update(acc);
+            hasher.update(block.header.block_number.to_le_bytes());
+            hasher.update(block.header.parent_hash);
+            hasher.update(block.header.merkle_root);
+            hasher.update((block.transactions.len() as u64).to_le_bytes());
+            acc = hasher.finalize().into();
+        }
+        
+        let public_inputs = chain_inputs(new_height, &acc);
         let zk_proof = ZKProof {
-            proof_data,
+            proof_data: mock_proof(&Sha256::digest(&public_inputs).into()),
             public_inputs,
-            verification_key: vec![],
-            proof_type: ProofType::Groth16,
+            circuit_version: self.circuit_version(&ProofType::Nova, &MOCK_KEY)?,
+            proof_type: ProofType::Nova,
         };
         
-        info!("Generated recursive ZK proof");
+        info!("Generated recursive ZK proof for {} blocks", new_height);
         Ok(zk_proof)
     }
     
-    fn extract_recursive_public_inputs(&self, previous_proof: &ZKProof, new_block: &Block) -> Vec<u8> {
-        let mut inputs = Vec::new();
-        
-        // Previous proof hash
-        let prev_proof_hash = Sha256::digest(&previous_proof.proof_data);
-        inputs.extend_from_slice(&prev_proof_hash);
+    // Checks that a chain proof attests to every block from genesis up to its height
+    pub async fn verify_chain_proof(&self, zk_proof: &ZKProof) -> Result<bool> {
+        let height = match parse_chain_inputs(zk_proof) {
+            Some((height, _)) => height,
+            None => {
+                warn!("❌ Not a chain proof");
+                return Ok(false);
+            }
+        };
         
-        // New block inputs
-        inputs.extend(self.extract_public_inputs(new_block));
+        let is_valid = self.verifiers.verify(zk_proof)?;
+        if is_valid {
+            info!("✅ Chain proof verified for {} blocks", height);
+        } else {
+            warn!("❌ Chain proof verification failed");
+        }
+        Ok(is_valid)
+    }
+    
+    fn genesis_accumulator(&self) -> [u8; 32] {
+        #[cfg(feature = "nova")]
+        if self.nova.is_some() {
+            return nova::NovaBackend::genesis_accumulator();
+        }
         
-        inputs
+        [0; 32]
+    }
+}
+
+// Keys of this build's own setup at the default version, for chains whose genesis registers
+// none. Without block backends, both block proof systems get mock keys
+fn default_keys(backends: &[Arc<dyn ProofBackend>], chain_key: [u8; 32]) -> VerifyingKeys {
+    let mut keys: Vec<_> = backends.iter()
+        .map(|backend| CircuitKey {
+            proof_type: backend.proof_type(),
+            circuit_version: DEFAULT_CIRCUIT_VERSION,
+            key: backend.verifying_key_hash(),
+        })
+        .collect();
+    if backends.is_empty() {
+        keys.extend([ProofType::Groth16, ProofType::Plonk].map(|proof_type| CircuitKey {
+            proof_type,
+            circuit_version: DEFAULT_CIRCUIT_VERSION,
+            key: MOCK_KEY,
+        }));
     }
+    keys.push(CircuitKey {
+        proof_type: ProofType::Nova,
+        circuit_version: DEFAULT_CIRCUIT_VERSION,
+        key: chain_key,
+    });
+    VerifyingKeys::new(keys)
 }
 
-// TODO: Implement real ZK-proof circuits when arkworks libraries are properly configured
-// This will include:
-// - BlockValidationCircuit implementing ConstraintSynthesizer
-// - RecursiveBlockCircuit for recursive proofs
-// - Proper Groth16 key generation and proof verification 
\ No newline at end of file
+// Sets up every block proof backend compiled into this build
+#[cfg_attr(not(any(feature = "groth16", feature = "plonk")), allow(unused_variables))]
+fn setup_backends(keys: &KeySource) -> Result<Vec<Arc<dyn ProofBackend>>> {
+    #[allow(unused_mut)]
+    let mut backends: Vec<Arc<dyn ProofBackend>> = Vec::new();
+    
+    #[cfg(feature = "groth16")]
+    backends.push(Arc::new(groth16::Groth16Backend::setup(keys).map_err(ProofError::Setup)?));
+    #[cfg(feature = "plonk")]
+    backends.push(Arc::new(plonk::PlonkBackend::setup(keys).map_err(ProofError::Setup)?));
+    
+    Ok(backends)
+}
+
+// Stand-in proof bytes for mock proofs, derived from the block hash or the public inputs
+fn mock_proof(block_hash: &[u8; 32]) -> Vec<u8> {
+    let mut proof_data = Vec::with_capacity(256);
+    
+    // Add block hash as proof base
+    proof_data.extend_from_slice(block_hash);
+    
+    // Add some deterministic padding based on hash
+    for i in 0..28 {
+        let val = block_hash[i % 32].wrapping_add(i as u8);
+        proof_data.extend_from_slice(&val.to_le_bytes());
+    }
+    
+    // Add timestamp-based randomness (but deterministic for same block)
+    let time_factor = (block_hash[0] as u64) * 1000;
+    proof_data.extend_from_slice(&time_factor.to_le_bytes());
+    
+    proof_data
+}
+
+// Chain proof public inputs: height (u64 LE) followed by the 32-byte accumulator
+fn chain_inputs(height: u64, acc: &[u8; 32]) -> Vec<u8> {
+    let mut inputs = height.to_le_bytes().to_vec();
+    inputs.extend_from_slice(acc);
+    inputs
+}
+
+pub fn chain_proof_height(zk_proof: &ZKProof) -> Option<u64> {
+    parse_chain_inputs(zk_proof).map(|(height, _)| height)
+}
+
+fn parse_chain_inputs(zk_proof: &ZKProof) -> Option<(u64, [u8; 32])> {
+    if !matches!(zk_proof.proof_type, ProofType::Nova) || zk_proof.public_inputs.len() != 40 {
+        return None;
+    }
+    
+    let height = u64::from_le_bytes(zk_proof.public_inputs[..8].try_into().ok()?);
+    Some((height, zk_proof.public_inputs[8..].try_into().ok()?))
+}
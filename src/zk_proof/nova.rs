@@ -0,0 +1,419 @@
+use crate::types::{self, calculate_merkle_root, Block, Encode, HashFunction};
+use anyhow::{anyhow, bail, Result};
+use ff::{Field, PrimeField};
+use generic_array::typenum::U24;
+use nova_snark::frontend::gadgets::poseidon::{
+    Elt, IOPattern, Simplex, Sponge, SpongeAPI, SpongeCircuit, SpongeOp, SpongeTrait, Strength,
+};
+use nova_snark::frontend::num::{AllocatedNum, Num};
+use nova_snark::frontend::{sha256, AllocatedBit, Assignment, Boolean, ConstraintSystem, SynthesisError};
+use nova_snark::nova::{CompressedSNARK, ProverKey, PublicParams, RecursiveSNARK, VerifierKey};
+use nova_snark::provider::{ipa_pc, PallasEngine, VestaEngine};
+use nova_snark::spartan::snark::RelaxedR1CSSNARK;
+use nova_snark::traits::{circuit::StepCircuit, snark::RelaxedR1CSSNARKTrait, Engine};
+use std::sync::Mutex;
+use tracing::info;
+
+use super::KeySource;
+
+// Each folding step re-checks one block's merkle root over at most this many transactions
+pub const MAX_STEP_TXS: usize = 4;
+
+// Pasta cycle with IPA commitments: transparent, so every node derives the same parameters
+type E1 = PallasEngine;
+type E2 = VestaEngine;
+type S1 = RelaxedR1CSSNARK<E1, ipa_pc::EvaluationEngine<E1>>;
+type S2 = RelaxedR1CSSNARK<E2, ipa_pc::EvaluationEngine<E2>>;
+type F = <E1 as Engine>::Scalar;
+
+// Length of an encoded header, and where its fields sit in it
+const HEADER_LEN: usize = 196;
+const PARENT_HASH_AT: usize = 8;
+const MERKLE_ROOT_AT: usize = 52;
+
+// Folded over the chain; the state after n steps is (n, accumulator over blocks 1..=n, hash of
+// block n as two 128-bit halves). Each step recomputes its block's hash from the header, whose
+// parent hash has to be the hash the step before it output, so the steps form one chain
+#[derive(Clone)]
+pub struct ChainStepCircuit {
+    header: Vec<u8>,
+    tx_hashes: Vec<[u8; 32]>,
+    tx_count: usize,
+}
+
+impl ChainStepCircuit {
+    pub fn blank() -> Self {
+        Self {
+            header: vec![0; HEADER_LEN],
+            tx_hashes: vec![[0; 32]; MAX_STEP_TXS],
+            tx_count: 0,
+        }
+    }
+
+    pub fn from_block(block: &Block) -> Result<Self> {
+        if block.transactions.len() > MAX_STEP_TXS {
+            bail!("Block has {} transactions, chain step supports at most {}",
+                block.transactions.len(), MAX_STEP_TXS);
+        }
+
+        let mut tx_hashes: Vec<[u8; 32]> = block.transactions.iter().map(|tx| tx.hash()).collect();
+        tx_hashes.resize(MAX_STEP_TXS, [0; 32]);
+
+        Ok(Self {
+            header: block.header.encode(),
+            tx_hashes,
+            tx_count: block.transactions.len(),
+        })
+    }
+}
+
+impl StepCircuit<F> for ChainStepCircuit {
+    fn arity(&self) -> usize {
+        4
+    }
+
+    fn synthesize<CS: ConstraintSystem<F>>(
+        &self,
+        cs: &mut CS,
+        z: &[AllocatedNum<F>],
+    ) -> Result<Vec<AllocatedNum<F>>, SynthesisError> {
+        let (height, acc, previous_hash) = (&z[0], &z[1], &z[2..4]);
+
+        // Steps are consecutive blocks: block number = previous height + 1
+        let block_number = AllocatedNum::alloc(cs.namespace(|| "block number"), || {
+            Ok(*height.get_value().get()? + F::ONE)
+        })?;
+        cs.enforce(
+            || "block number follows height",
+            |lc| lc + height.get_variable() + CS::one(),
+            |lc| lc + CS::one(),
+            |lc| lc + block_number.get_variable(),
+        );
+
+        // One-hot encoding of the transaction count
+        let count_is = (0..=MAX_STEP_TXS)
+            .map(|n| {
+                AllocatedBit::alloc(cs.namespace(|| format!("count is {}", n)), Some(self.tx_count == n))
+                    .map(Boolean::from)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        cs.enforce(
+            || "exactly one count",
+            |lc| count_is.iter().fold(lc, |lc, bit| lc + &bit.lc(CS::one(), F::ONE)),
+            |lc| lc + CS::one(),
+            |lc| lc + CS::one(),
+        );
+        let tx_count = count_is.iter().enumerate().fold(Num::zero(), |num, (n, bit)| {
+            num.add_bool_with_coeff(CS::one(), bit, F::from(n as u64))
+        });
+
+        let mut level = self.tx_hashes.iter().enumerate()
+            .map(|(i, hash)| alloc_bytes(cs.namespace(|| format!("tx {}", i)), hash))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // Same tree shape as types::calculate_merkle_root, tracked for every possible count
+        let mut widths: Vec<usize> = (0..=MAX_STEP_TXS).collect();
+        let mut roots: Vec<Option<Vec<Boolean>>> = vec![None; MAX_STEP_TXS + 1];
+        roots[0] = Some(vec![Boolean::constant(false); 256]);
+        roots[1] = Some(level[0].clone());
+
+        let mut depth = 0;
+        while level.len() > 1 {
+            let mut next = Vec::with_capacity(level.len() / 2);
+
+            for i in 0..level.len() / 2 {
+                let mut cs = cs.namespace(|| format!("node {} {}", depth, i));
+                let left = &level[2 * i];
+                let right = &level[2 * i + 1];
+
+                // Without a right sibling the left node is hashed with itself
+                let has_right = (0..=MAX_STEP_TXS)
+                    .filter(|&n| 2 * i + 1 < widths[n])
+                    .enumerate()
+                    .try_fold(Boolean::constant(false), |acc, (k, n)| {
+                        Boolean::or(cs.namespace(|| format!("has right {}", k)), &acc, &count_is[n])
+                    })?;
+
+                let right = select(cs.namespace(|| "right"), &has_right, right, left)?;
+                let mut preimage = left.clone();
+                preimage.extend(right);
+                next.push(sha256(cs.namespace(|| "sha256"), &preimage)?);
+            }
+
+            for n in 0..=MAX_STEP_TXS {
+                if widths[n] > 1 {
+                    widths[n] = widths[n].div_ceil(2);
+                    if widths[n] == 1 {
+                        roots[n] = Some(next[0].clone());
+                    }
+                }
+            }
+
+            level = next;
+            depth += 1;
+        }
+
+        let mut merkle_root = vec![Boolean::constant(false); 256];
+        for (n, root) in roots.into_iter().enumerate() {
+            if let Some(root) = root {
+                merkle_root = select(cs.namespace(|| format!("root {}", n)), &count_is[n], &root, &merkle_root)?;
+            }
+        }
+
+        let header = alloc_bytes(cs.namespace(|| "header"), &self.header)?;
+        let number_bytes = pack_le::<CS>(&header[..64]);
+        cs.enforce(
+            || "header number is block number",
+            |lc| lc + &number_bytes.lc(F::ONE),
+            |lc| lc + CS::one(),
+            |lc| lc + block_number.get_variable(),
+        );
+        for (i, (header_bit, root_bit)) in header[MERKLE_ROOT_AT * 8..(MERKLE_ROOT_AT + 32) * 8].iter().zip(&merkle_root).enumerate() {
+            Boolean::enforce_equal(cs.namespace(|| format!("header merkle root {}", i)), header_bit, root_bit)?;
+        }
+
+        // The first block's parent is anchored by the accumulator instead, there being no step
+        // before it; every later one must name the block the previous step hashed
+        let parent_hash = &header[PARENT_HASH_AT * 8..(PARENT_HASH_AT + 32) * 8];
+        for (i, (parent, previous)) in pack_halves::<CS>(parent_hash).iter().zip(previous_hash).enumerate() {
+            cs.enforce(
+                || format!("parent hash half {} follows the previous block", i),
+                |lc| lc + &parent.lc(F::ONE) - previous.get_variable(),
+                |lc| lc + height.get_variable(),
+                |lc| lc,
+            );
+        }
+        let block_hash = sha256(cs.namespace(|| "block hash"), &header)?;
+        let block_hash = pack_halves::<CS>(&block_hash).into_iter().enumerate()
+            .map(|(i, half)| alloc_num(cs.namespace(|| format!("block hash half {}", i)), &half))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut elements = vec![Elt::Allocated(acc.clone()), Elt::Allocated(block_number.clone())];
+        elements.extend(pack_halves::<CS>(parent_hash).map(Elt::Num));
+        elements.extend(pack_halves::<CS>(&merkle_root).map(Elt::Num));
+        elements.push(Elt::Num(tx_count));
+
+        let pattern = IOPattern(vec![SpongeOp::Absorb(ACC_INPUTS), SpongeOp::Squeeze(1)]);
+        let constants = Sponge::<F, U24>::api_constants(Strength::Standard);
+        let mut ns = cs.namespace(|| "accumulate");
+        let next_acc = {
+            let mut sponge = SpongeCircuit::new_with_constants(&constants, Simplex);
+            let acc = &mut ns;
+
+            sponge.start(pattern, None, acc);
+            SpongeAPI::absorb(&mut sponge, ACC_INPUTS, &elements, acc);
+            let output = SpongeAPI::squeeze(&mut sponge, 1, acc);
+            sponge.finish(acc).map_err(|_| SynthesisError::Unsatisfiable)?;
+            Elt::ensure_allocated(&output[0], &mut ns.namespace(|| "next acc"), true)?
+        };
+
+        let mut next = vec![block_number, next_acc];
+        next.extend(block_hash);
+        Ok(next)
+    }
+}
+
+// Field elements absorbed per block: accumulator, block number, parent hash and
+// merkle root as 128-bit halves, transaction count
+const ACC_INPUTS: u32 = 7;
+
+// Native counterpart of the accumulator update in ChainStepCircuit
+fn accumulate(acc: F, block: &Block) -> F {
+    let mut elements = vec![acc, F::from(block.header.block_number)];
+    elements.extend(hash_halves(&block.header.parent_hash));
+    elements.extend(hash_halves(&block.header.merkle_root));
+    elements.push(F::from(block.transactions.len() as u64));
+
+    let constants = Sponge::<F, U24>::api_constants(Strength::Standard);
+    let mut sponge = Sponge::new_with_constants(&constants, Simplex);
+    let acc = &mut ();
+    sponge.start(IOPattern(vec![SpongeOp::Absorb(ACC_INPUTS), SpongeOp::Squeeze(1)]), None, acc);
+    SpongeAPI::absorb(&mut sponge, ACC_INPUTS, &elements, acc);
+    let output = SpongeAPI::squeeze(&mut sponge, 1, acc);
+    sponge.finish(acc).expect("sponge pattern matches its use");
+    output[0]
+}
+
+fn hash_halves(hash: &[u8; 32]) -> [F; 2] {
+    let hi = u128::from_be_bytes(hash[..16].try_into().unwrap());
+    let lo = u128::from_be_bytes(hash[16..].try_into().unwrap());
+    [F::from_u128(hi), F::from_u128(lo)]
+}
+
+// Bytes as big-endian bits, the order the sha256 gadget expects
+fn alloc_bytes<CS: ConstraintSystem<F>>(mut cs: CS, bytes: &[u8]) -> Result<Vec<Boolean>, SynthesisError> {
+    bytes.iter()
+        .flat_map(|byte| (0..8).rev().map(move |bit| (byte >> bit) & 1 == 1))
+        .enumerate()
+        .map(|(i, bit)| AllocatedBit::alloc(cs.namespace(|| format!("bit {}", i)), Some(bit)).map(Boolean::from))
+        .collect()
+}
+
+// Packs bytes given as alloc_bytes lays them out into the little endian integer they encode
+fn pack_le<CS: ConstraintSystem<F>>(bits: &[Boolean]) -> Num<F> {
+    let mut num = Num::zero();
+    for (byte, bits) in bits.chunks(8).enumerate() {
+        for (bit, boolean) in bits.iter().rev().enumerate() {
+            num = num.add_bool_with_coeff(CS::one(), boolean, F::from(2u64).pow_vartime([(byte * 8 + bit) as u64]));
+        }
+    }
+    num
+}
+
+fn alloc_num<CS: ConstraintSystem<F>>(mut cs: CS, num: &Num<F>) -> Result<AllocatedNum<F>, SynthesisError> {
+    let allocated = AllocatedNum::alloc(cs.namespace(|| "value"), || num.get_value().ok_or(SynthesisError::AssignmentMissing))?;
+    cs.enforce(
+        || "allocated",
+        |lc| lc + &num.lc(F::ONE),
+        |lc| lc + CS::one(),
+        |lc| lc + allocated.get_variable(),
+    );
+    Ok(allocated)
+}
+
+// Packs a 256-bit digest into the same two field elements as hash_halves
+fn pack_halves<CS: ConstraintSystem<F>>(bits: &[Boolean]) -> [Num<F>; 2] {
+    let pack = |bits: &[Boolean]| {
+        let mut coeff = F::ONE;
+        let mut num = Num::zero();
+        for bit in bits.iter().rev() {
+            num = num.add_bool_with_coeff(CS::one(), bit, coeff);
+            coeff = coeff.double();
+        }
+        num
+    };
+    [pack(&bits[..128]), pack(&bits[128..])]
+}
+
+fn select<CS: ConstraintSystem<F>>(
+    mut cs: CS,
+    condition: &Boolean,
+    if_true: &[Boolean],
+    if_false: &[Boolean],
+) -> Result<Vec<Boolean>, SynthesisError> {
+    if_true.iter().zip(if_false).enumerate()
+        .map(|(i, (a, b))| {
+            let a = Boolean::and(cs.namespace(|| format!("a {}", i)), condition, a)?;
+            let b = Boolean::and(cs.namespace(|| format!("b {}", i)), &condition.not(), b)?;
+            Boolean::or(cs.namespace(|| format!("or {}", i)), &a, &b)
+        })
+        .collect()
+}
+
+type ChainSNARK = CompressedSNARK<E1, E2, ChainStepCircuit, S1, S2>;
+
+// Verifier-only nodes drop the public parameters and prover key once the verifier key is derived
+pub struct NovaBackend {
+    params: Option<PublicParams<E1, E2, ChainStepCircuit>>,
+    prover_key: Option<ProverKey<E1, E2, ChainStepCircuit, S1, S2>>,
+    verifier_key: VerifierKey<E1, E2, ChainStepCircuit, S1, S2>,
+    params_digest: [u8; 32],
+    // Folding continues from the uncompressed proof, which is too large to ship with blocks
+    running: Mutex<Option<RecursiveSNARK<E1, E2, ChainStepCircuit>>>,
+}
+
+impl NovaBackend {
+    pub fn setup(keys: &KeySource) -> Result<Self> {
+        if types::hash_function() != HashFunction::Sha256 || types::merkle_hash_function() != HashFunction::Sha256 {
+            bail!("ChainStepCircuit hashes blocks with SHA-256, the chain uses {:?} and {:?} for merkle roots",
+                types::hash_function(), types::merkle_hash_function());
+        }
+        info!("🔐 Running Nova setup for ChainStepCircuit (max {} txs per step)", MAX_STEP_TXS);
+
+        let params = PublicParams::setup(&ChainStepCircuit::blank(), &*S1::ck_floor(), &*S2::ck_floor())
+            .map_err(|e| anyhow!("Nova setup failed: {:?}", e))?;
+        let (prover_key, verifier_key) = ChainSNARK::setup(&params)
+            .map_err(|e| anyhow!("Nova compression setup failed: {:?}", e))?;
+        let params_digest = field_to_bytes(params.digest());
+
+        info!("✅ Nova parameters ready ({} constraints per step, digest {})",
+            params.num_constraints().0, hex::encode(params_digest));
+
+        let proving = keys.proving();
+        Ok(Self {
+            params: proving.then_some(params),
+            prover_key: proving.then_some(prover_key),
+            verifier_key,
+            params_digest,
+            running: Mutex::new(None),
+        })
+    }
+
+    pub fn params_digest(&self) -> [u8; 32] {
+        self.params_digest
+    }
+
+    pub fn genesis_accumulator() -> [u8; 32] {
+        field_to_bytes(F::ZERO)
+    }
+
+    pub fn accumulate(acc: &[u8; 32], block: &Block) -> Result<[u8; 32]> {
+        Ok(field_to_bytes(accumulate(field_from_bytes(acc)?, block)))
+    }
+
+    // Folds the blocks into the running accumulator at `height`, then compresses once
+    pub fn prove(&self, height: u64, acc: &[u8; 32], blocks: &[Block]) -> Result<Vec<u8>> {
+        let (params, prover_key) = match (&self.params, &self.prover_key) {
+            (Some(params), Some(prover_key)) => (params, prover_key),
+            _ => bail!("This node holds no Nova proving parameters"),
+        };
+        let circuits = blocks.iter()
+            .map(|block| {
+                // An unsatisfied step would leave an accumulator no later block can fix
+                if calculate_merkle_root(&block.transactions) != block.header.merkle_root {
+                    bail!("Block #{} does not match its merkle root", block.header.block_number);
+                }
+                ChainStepCircuit::from_block(block)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let first = circuits.first().ok_or_else(|| anyhow!("No blocks to fold"))?;
+
+        let mut running = self.running.lock().unwrap();
+        let position = [F::from(height), field_from_bytes(acc)?];
+        let mut snark = match running.take() {
+            Some(snark) if snark.outputs()[..2] == position => snark,
+            _ if height == 0 => {
+                RecursiveSNARK::new(params, first, &[F::ZERO; 4])
+                    .map_err(|e| anyhow!("Nova base case failed: {:?}", e))?
+            }
+            other => {
+                *running = other;
+                bail!("No running Nova accumulator at height {}", height);
+            }
+        };
+
+        for circuit in &circuits {
+            snark.prove_step(params, circuit)
+                .map_err(|e| anyhow!("Nova folding step failed: {:?}", e))?;
+        }
+        let compressed = ChainSNARK::prove(params, prover_key, &snark)
+            .map_err(|e| anyhow!("Nova compression failed: {:?}", e))?;
+
+        *running = Some(snark);
+        Ok(bincode::serialize(&compressed)?)
+    }
+
+    pub fn verify(&self, height: u64, acc: &[u8; 32], proof_data: &[u8]) -> Result<bool> {
+        let acc = match field_from_bytes(acc) {
+            Ok(acc) => acc,
+            Err(_) => return Ok(false),
+        };
+        let compressed: ChainSNARK = match bincode::deserialize(proof_data) {
+            Ok(compressed) => compressed,
+            Err(_) => return Ok(false),
+        };
+
+        Ok(height > 0 && match compressed.verify(&self.verifier_key, height as usize, &[F::ZERO; 4]) {
+            Ok(outputs) => outputs[..2] == [F::from(height), acc],
+            Err(_) => false,
+        })
+    }
+}
+
+fn field_to_bytes(value: F) -> [u8; 32] {
+    value.to_repr().into()
+}
+
+fn field_from_bytes(bytes: &[u8; 32]) -> Result<F> {
+    Option::from(F::from_repr((*bytes).into())).ok_or_else(|| anyhow!("Accumulator is not a field element"))
+}
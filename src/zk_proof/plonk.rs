@@ -0,0 +1,1075 @@
+use crate::state::{Account, StateTransition};
+use crate::types::{self, Block, HashFunction, ProofType};
+use anyhow::{anyhow, bail, Result};
+use ff::PrimeField;
+use halo2_proofs::circuit::{AssignedCell, Layouter, Region, SimpleFloorPlanner, Value};
+use halo2_proofs::pasta::{EqAffine, Fp};
+use halo2_proofs::plonk::{
+    create_proof, keygen_pk, keygen_vk, verify_proof, Advice, Circuit, Column, ConstraintSystem, Error,
+    Expression, Fixed, Instance, ProvingKey, Selector, SingleVerifier, VerifyingKey, VirtualCells,
+};
+use halo2_proofs::poly::{commitment::Params, Rotation};
+use halo2_proofs::transcript::{Blake2bRead, Blake2bWrite, Challenge255};
+use sha2::{Sha256, Digest};
+use std::slice;
+use tracing::info;
+
+use super::witness::{update_witnesses, UpdateWitness};
+use super::{KeySource, ProofBackend, PublicInputs, StateCapacity, PUBLIC_INPUT_ELEMENTS};
+
+// Same bounds as the Groth16 circuit, so a block proposed for one backend fits the other
+pub const MAX_CIRCUIT_TXS: usize = 4;
+pub const MAX_STATE_UPDATES: usize = 8;
+pub const MAX_STATE_DEPTH: usize = 16;
+
+// Path bits all come from the first word of an id
+const _: () = assert!(MAX_STATE_DEPTH <= 32);
+
+// 2^K rows; a SHA-256 compression takes 72, a merkle node two and a state update two paths of
+// MAX_STATE_DEPTH nodes
+const K: u32 = 16;
+
+// Instance column layout: PublicInputs::field_elements
+const BLOCK_INPUTS: usize = PUBLIC_INPUT_ELEMENTS;
+const BLOCK_TX_COUNT_ROW: usize = 2;
+const BLOCK_MERKLE_ROOT_ROWS: [usize; 2] = [5, 6];
+const BLOCK_STATE_ROOT_ROWS: [usize; 2] = [7, 8];
+const BLOCK_PARENT_STATE_ROOT_ROWS: [usize; 2] = [11, 12];
+
+// Proves that the merkle root of the public inputs is the SHA-256 merkle root (as computed by
+// types::calculate_merkle_root) of their `tx_count` transaction hashes, and that their state
+// root is what the account updates of the block's state transition make of the parent state
+// root, like the Groth16 block circuit does over Poseidon. The other inputs are bound so the
+// proof commits to the parent and validator set; that the updates are the ones the
+// transactions make is checked by nodes.
+#[derive(Clone)]
+pub struct BlockValidationCircuit {
+    tx_hashes: Vec<[u8; 32]>,
+    tx_count: usize,
+    parent_state_root: [u8; 32],
+    updates: Vec<UpdateWitness>,
+}
+
+impl BlockValidationCircuit {
+    pub fn blank() -> Self {
+        Self {
+            tx_hashes: vec![[0; 32]; MAX_CIRCUIT_TXS],
+            tx_count: 0,
+            parent_state_root: [0; 32],
+            updates: vec![UpdateWitness::padding(MAX_STATE_DEPTH); MAX_STATE_UPDATES],
+        }
+    }
+
+    pub fn from_block(block: &Block, state: &StateTransition) -> Result<Self> {
+        if block.transactions.len() > MAX_CIRCUIT_TXS {
+            bail!("Block has {} transactions, circuit supports at most {}",
+                block.transactions.len(), MAX_CIRCUIT_TXS);
+        }
+
+        let mut tx_hashes: Vec<[u8; 32]> = block.transactions.iter().map(|tx| tx.hash()).collect();
+        tx_hashes.resize(MAX_CIRCUIT_TXS, [0; 32]);
+
+        Ok(Self {
+            tx_hashes,
+            tx_count: block.transactions.len(),
+            parent_state_root: state.pre_root,
+            updates: update_witnesses(state, MAX_STATE_UPDATES, MAX_STATE_DEPTH)?,
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct BlockValidationConfig {
+    instance: Column<Instance>,
+    arithmetic: ArithmeticConfig,
+    sha256: Sha256Config,
+}
+
+impl Circuit<Fp> for BlockValidationCircuit {
+    type Config = BlockValidationConfig;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self::blank()
+    }
+
+    fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
+        let instance = meta.instance_column();
+        meta.enable_equality(instance);
+        let constants = meta.fixed_column();
+        meta.enable_constant(constants);
+
+        BlockValidationConfig {
+            instance,
+            arithmetic: ArithmeticConfig::configure(meta),
+            sha256: Sha256Config::configure(meta),
+        }
+    }
+
+    fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<Fp>) -> Result<(), Error> {
+        let arithmetic = &config.arithmetic;
+        let inputs = arithmetic.public_inputs(&mut layouter, config.instance, BLOCK_INPUTS)?;
+        let count_is = arithmetic.count(&mut layouter, &inputs[BLOCK_TX_COUNT_ROW], self.tx_count)?;
+
+        let leaves = self.tx_hashes.iter()
+            .map(|hash| arithmetic.witness_words(&mut layouter, &digest_words(hash)))
+            .collect::<Result<Vec<_>, _>>()?;
+        let root = merkle_root_for_count(&config, &mut layouter, leaves, &count_is)?;
+
+        // The root as the two big endian halves of its bytes
+        for (half, row) in root.chunks(4).zip(BLOCK_MERKLE_ROOT_ROWS) {
+            arithmetic.pack(&mut layouter, half, &inputs[row])?;
+        }
+
+        // From the parent state root through every update to the state root
+        let constants = StateConstants::assign(arithmetic, &mut layouter)?;
+        let mut state_root = arithmetic.witness_words(&mut layouter, &digest_words(&self.parent_state_root))?;
+        for (half, row) in state_root.chunks(4).zip(BLOCK_PARENT_STATE_ROOT_ROWS) {
+            arithmetic.pack(&mut layouter, half, &inputs[row])?;
+        }
+        for update in &self.updates {
+            state_root = updated_root(&config, &mut layouter, &state_root, update, &constants)?;
+        }
+        for (half, row) in state_root.chunks(4).zip(BLOCK_STATE_ROOT_ROWS) {
+            arithmetic.pack(&mut layouter, half, &inputs[row])?;
+        }
+        Ok(())
+    }
+}
+
+// Words and flags the state tree gadgets use as they are
+struct StateConstants {
+    initial_state: Vec<Word>,
+    // The second block of a merkle node, which hashes 64 bytes
+    node_padding: Vec<Word>,
+    // The end of a leaf's block after its 48 bytes
+    leaf_padding: Vec<Word>,
+    empty: Vec<Word>,
+    zero: Word,
+    one: Word,
+}
+
+impl StateConstants {
+    fn assign(arithmetic: &ArithmeticConfig, layouter: &mut impl Layouter<Fp>) -> Result<Self, Error> {
+        let mut node_padding = vec![0; 16];
+        node_padding[0] = 0x8000_0000;
+        node_padding[15] = 512;
+        let [zero, one] = [0, 1].map(|value| arithmetic.constant_words(layouter, &[value]).map(|mut words| words.remove(0)));
+        Ok(Self {
+            initial_state: arithmetic.constant_words(layouter, &INITIAL_STATE)?,
+            node_padding: arithmetic.constant_words(layouter, &node_padding)?,
+            leaf_padding: arithmetic.constant_words(layouter, &[0x8000_0000, 0, 0, 384])?,
+            empty: arithmetic.constant_words(layouter, &[0; 8])?,
+            zero: zero?,
+            one: one?,
+        })
+    }
+}
+
+// Root of the state tree after the update, as AccountProof::updated_root computes it, with the
+// update's proof checked against `root`. Padding updates leave the root as it is; see
+// updated_root in groth16.rs for the same checks in R1CS
+fn updated_root(
+    config: &BlockValidationConfig,
+    layouter: &mut impl Layouter<Fp>,
+    root: &[Word],
+    update: &UpdateWitness,
+    constants: &StateConstants,
+) -> Result<Vec<Word>, Error> {
+    let arithmetic = &config.arithmetic;
+    let flags = arithmetic.flags(layouter, &[update.used, update.before.is_some(), update.neighbour.is_some()])?;
+    let [used, exists, has_neighbour] = [&flags[0], &flags[1], &flags[2]];
+    let both = arithmetic.and(layouter, exists, has_neighbour, constants)?;
+    arithmetic.assert_zero(layouter, &both)?;
+
+    let account = arithmetic.witness_words(layouter, &digest_words(&update.account))?;
+    let (neighbour_id, neighbour) = update.neighbour.unwrap_or_default();
+    let neighbour_id = arithmetic.witness_words(layouter, &digest_words(&neighbour_id))?;
+    let old_leaf = leaf_hash(config, layouter, &account, &update.before.unwrap_or_default(), constants)?;
+    let new_leaf = leaf_hash(config, layouter, &account, &update.after, constants)?;
+    let neighbour_leaf = leaf_hash(config, layouter, &neighbour_id, &neighbour, constants)?;
+    let directions = arithmetic.bits(layouter, &account[0])?;
+    let neighbour_directions = arithmetic.bits(layouter, &neighbour_id[0])?;
+
+    // The path stays as it is unless the neighbour's leaf splits, when it goes on past its old
+    // end down to a last level where the two ids differ, and share every bit above it
+    let old_levels = depth_flags(arithmetic, layouter, update.old_depth, constants)?;
+    let split_levels = depth_flags(arithmetic, layouter, update.new_depth, constants)?;
+    let new_levels = arithmetic.select(layouter, has_neighbour, &split_levels, &old_levels)?;
+    // The new path has a last level, below every level of the old one
+    arithmetic.assert_equal_if(layouter, has_neighbour, &new_levels[..1], slice::from_ref(&constants.one))?;
+    let mut last = Vec::with_capacity(MAX_STATE_DEPTH);
+    for depth in 0..MAX_STATE_DEPTH {
+        let not_new = arithmetic.not(layouter, &new_levels[depth], constants)?;
+        let shortened = arithmetic.and(layouter, &old_levels[depth], &not_new, constants)?;
+        let shortened = arithmetic.and(layouter, has_neighbour, &shortened, constants)?;
+        arithmetic.assert_zero(layouter, &shortened)?;
+
+        let is_last = match new_levels.get(depth + 1) {
+            Some(below) => {
+                let not_below = arithmetic.not(layouter, below, constants)?;
+                arithmetic.and(layouter, &new_levels[depth], &not_below, constants)?
+            }
+            None => new_levels[depth].clone(),
+        };
+        let kept = arithmetic.and(layouter, &is_last, &old_levels[depth], constants)?;
+        let kept = arithmetic.and(layouter, has_neighbour, &kept, constants)?;
+        arithmetic.assert_zero(layouter, &kept)?;
+
+        let on_path = arithmetic.and(layouter, has_neighbour, &new_levels[depth], constants)?;
+        let expected = arithmetic.xor(layouter, &directions[depth], &is_last, constants)?;
+        arithmetic.assert_equal_if(layouter, &on_path, &[neighbour_directions[depth].clone()], &[expected])?;
+        last.push(is_last);
+    }
+
+    let siblings = update.siblings.iter()
+        .map(|sibling| arithmetic.witness_words(layouter, &digest_words(sibling)))
+        .collect::<Result<Vec<_>, _>>()?;
+    let old_bottom = arithmetic.select(layouter, has_neighbour, &neighbour_leaf, &constants.empty)?;
+    let old_bottom = arithmetic.select(layouter, exists, &old_leaf, &old_bottom)?;
+    let old_root = fold(config, layouter, old_bottom, &directions, &siblings, &old_levels, constants)?;
+    arithmetic.assert_equal_if(layouter, used, &old_root, root)?;
+
+    // Past the old path's end the subtrees beside the new one are empty, but for the
+    // neighbour's leaf at the last level
+    let new_siblings = (0..MAX_STATE_DEPTH)
+        .map(|depth| {
+            let beside = arithmetic.select(layouter, &last[depth], &neighbour_leaf, &constants.empty)?;
+            arithmetic.select(layouter, &old_levels[depth], &siblings[depth], &beside)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let new_root = fold(config, layouter, new_leaf, &directions, &new_siblings, &new_levels, constants)?;
+    arithmetic.select(layouter, used, &new_root, root)
+}
+
+// state::smt's leaf: SHA-256 of the id and the big endian balance and nonce, 48 bytes that
+// make a single block with their padding
+fn leaf_hash(
+    config: &BlockValidationConfig,
+    layouter: &mut impl Layouter<Fp>,
+    id: &[Word],
+    account: &Account,
+    constants: &StateConstants,
+) -> Result<Vec<Word>, Error> {
+    let values = [account.balance, account.nonce].map(|value| [(value >> 32) as u32, value as u32]).concat();
+    let mut block = id.to_vec();
+    block.extend(config.arithmetic.witness_words(layouter, &values)?);
+    block.extend(constants.leaf_padding.iter().cloned());
+    config.sha256.compress(layouter, &constants.initial_state, &block)
+}
+
+// Which of the MAX_STATE_DEPTH levels a path of `depth` levels has: a run of set flags from the top
+fn depth_flags(
+    arithmetic: &ArithmeticConfig,
+    layouter: &mut impl Layouter<Fp>,
+    depth: usize,
+    constants: &StateConstants,
+) -> Result<Vec<Word>, Error> {
+    let values: Vec<bool> = (0..MAX_STATE_DEPTH).map(|level| level < depth).collect();
+    let levels = arithmetic.flags(layouter, &values)?;
+    for pair in levels.windows(2) {
+        let not_above = arithmetic.not(layouter, &pair[0], constants)?;
+        let gap = arithmetic.and(layouter, &pair[1], &not_above, constants)?;
+        arithmetic.assert_zero(layouter, &gap)?;
+    }
+    Ok(levels)
+}
+
+// Hashes up the path's levels from the bottom, as state::smt folds a proof
+fn fold(
+    config: &BlockValidationConfig,
+    layouter: &mut impl Layouter<Fp>,
+    bottom: Vec<Word>,
+    directions: &[Word],
+    siblings: &[Vec<Word>],
+    levels: &[Word],
+    constants: &StateConstants,
+) -> Result<Vec<Word>, Error> {
+    let arithmetic = &config.arithmetic;
+    let mut hash = bottom;
+    for depth in (0..MAX_STATE_DEPTH).rev() {
+        let mut message = arithmetic.select(layouter, &directions[depth], &siblings[depth], &hash)?;
+        message.extend(arithmetic.select(layouter, &directions[depth], &hash, &siblings[depth])?);
+        let state = config.sha256.compress(layouter, &constants.initial_state, &message)?;
+        let parent = config.sha256.compress(layouter, &state, &constants.node_padding)?;
+        hash = arithmetic.select(layouter, &levels[depth], &parent, &hash)?;
+    }
+    Ok(hash)
+}
+
+// Root of the tree over the first n leaves, for the n `count_is` selects, as
+// BlockValidationCircuit in groth16.rs computes it: the tree width is tracked for every possible
+// count, so duplication of the odd node and the root position can be selected in-circuit
+fn merkle_root_for_count(
+    config: &BlockValidationConfig,
+    layouter: &mut impl Layouter<Fp>,
+    mut level: Vec<Vec<Word>>,
+    count_is: &[Word],
+) -> Result<Vec<Word>, Error> {
+    let arithmetic = &config.arithmetic;
+    let initial_state = arithmetic.constant_words(layouter, &INITIAL_STATE)?;
+    let mut padding = vec![0; 16];
+    padding[0] = 0x8000_0000;
+    padding[15] = 512;
+    let padding = arithmetic.constant_words(layouter, &padding)?;
+
+    let mut widths: Vec<usize> = (0..=MAX_CIRCUIT_TXS).collect();
+    let mut roots: Vec<Option<Vec<Word>>> = vec![None; MAX_CIRCUIT_TXS + 1];
+    roots[1] = Some(level[0].clone());
+
+    while level.len() > 1 {
+        let mut next = Vec::with_capacity(level.len() / 2);
+
+        for i in 0..level.len() / 2 {
+            let left = &level[2 * i];
+            let has_right_for: Vec<_> = (0..=MAX_CIRCUIT_TXS)
+                .filter(|&n| 2 * i + 1 < widths[n])
+                .map(|n| count_is[n].clone())
+                .collect();
+            // Counts are one-hot, so the sum of the flags is their disjunction
+            let right = match has_right_for.split_first() {
+                None => left.clone(),
+                Some((first, rest)) => {
+                    let has_right = rest.iter().try_fold(first.clone(), |sum, flag| arithmetic.add(layouter, &sum, flag))?;
+                    arithmetic.select(layouter, &has_right, &level[2 * i + 1], left)?
+                }
+            };
+
+            // A pair of digests is one block, then the padding block
+            let mut message = left.clone();
+            message.extend(right);
+            let state = config.sha256.compress(layouter, &initial_state, &message)?;
+            next.push(config.sha256.compress(layouter, &state, &padding)?);
+        }
+
+        for n in 0..=MAX_CIRCUIT_TXS {
+            if widths[n] > 1 {
+                widths[n] = widths[n].div_ceil(2);
+                if widths[n] == 1 {
+                    roots[n] = Some(next[0].clone());
+                }
+            }
+        }
+
+        level = next;
+    }
+
+    // Sum of the flagged root, the empty tree being all zero
+    let zero = arithmetic.constant_words(layouter, &[0; 8])?;
+    roots.iter().enumerate().skip(1).try_fold(zero, |sum, (n, root)| match root {
+        Some(root) => arithmetic.mul_add(layouter, &sum, &count_is[n], root),
+        None => Ok(sum),
+    })
+}
+
+// A 32-bit word in a cell that is range-checked wherever it was computed or is hashed
+#[derive(Clone, Debug)]
+struct Word {
+    cell: AssignedCell<Fp, Fp>,
+    value: u32,
+}
+
+// Field arithmetic on single cells: sums, selection and packing of words, the one-hot encoding
+// of the transaction count, and flags as words of value 0 or 1
+#[derive(Clone, Debug)]
+struct ArithmeticConfig {
+    x: Column<Advice>,
+    y: Column<Advice>,
+    s: Column<Advice>,
+    z: Column<Advice>,
+    add: Selector,
+    select: Selector,
+    mul_add: Selector,
+    pack: Selector,
+    count: Selector,
+    bits: Selector,
+}
+
+impl ArithmeticConfig {
+    fn configure(meta: &mut ConstraintSystem<Fp>) -> Self {
+        let [x, y, s, z] = [(); 4].map(|_| meta.advice_column());
+        for column in [x, y, s, z] {
+            meta.enable_equality(column);
+        }
+        let [add, select, mul_add, pack, count, bits] = [(); 6].map(|_| meta.selector());
+
+        meta.create_gate("add", |meta| {
+            let selector = meta.query_selector(add);
+            let [x, y, z] = [x, y, z].map(|column| meta.query_advice(column, Rotation::cur()));
+            vec![selector * (x + y - z)]
+        });
+
+        // z = s ? x : y
+        meta.create_gate("select", |meta| {
+            let selector = meta.query_selector(select);
+            let [x, y, s, z] = [x, y, s, z].map(|column| meta.query_advice(column, Rotation::cur()));
+            vec![selector * (s * (x - y.clone()) + y - z)]
+        });
+
+        meta.create_gate("mul add", |meta| {
+            let selector = meta.query_selector(mul_add);
+            let [x, y, s, z] = [x, y, s, z].map(|column| meta.query_advice(column, Rotation::cur()));
+            vec![selector * (x + s * y - z)]
+        });
+
+        // Four big endian words in x make the 128-bit value in z
+        meta.create_gate("pack", |meta| {
+            let selector = meta.query_selector(pack);
+            let words = [0, 1, 2, 3].map(|rotation| meta.query_advice(x, Rotation(rotation)));
+            let z = meta.query_advice(z, Rotation::cur());
+            let packed = words.into_iter().fold(Expression::Constant(Fp::from(0)), |packed, word| {
+                packed * Fp::from(1u64 << 32) + word
+            });
+            vec![selector * (packed - z)]
+        });
+
+        // One boolean flag per count in x, exactly one of them set, at the index of the count in z
+        meta.create_gate("transaction count", |meta| {
+            let selector = meta.query_selector(count);
+            let flags: Vec<_> = (0..=MAX_CIRCUIT_TXS)
+                .map(|n| meta.query_advice(x, Rotation(n as i32)))
+                .collect();
+            let count = meta.query_advice(z, Rotation::cur());
+            let mut constraints: Vec<_> = flags.iter().map(|flag| selector.clone() * boolean(flag)).collect();
+            let ones = flags.iter().fold(Expression::Constant(Fp::from(0)), |sum, flag| sum + flag.clone());
+            let index = flags.iter().enumerate().fold(Expression::Constant(Fp::from(0)), |sum, (n, flag)| {
+                sum + flag.clone() * Fp::from(n as u64)
+            });
+            constraints.push(selector.clone() * (ones - Expression::Constant(Fp::from(1))));
+            constraints.push(selector * (index - count));
+            constraints
+        });
+
+        // 32 boolean flags in x, most significant first, make the word in z
+        meta.create_gate("word bits", |meta| {
+            let selector = meta.query_selector(bits);
+            let flags: Vec<_> = (0..32).map(|i| meta.query_advice(x, Rotation(i))).collect();
+            let word = meta.query_advice(z, Rotation::cur());
+            let mut constraints: Vec<_> = flags.iter().map(|flag| selector.clone() * boolean(flag)).collect();
+            let packed = flags.iter().fold(Expression::Constant(Fp::from(0)), |packed, flag| {
+                packed * Fp::from(2) + flag.clone()
+            });
+            constraints.push(selector * (packed - word));
+            constraints
+        });
+
+        Self { x, y, s, z, add, select, mul_add, pack, count, bits }
+    }
+
+    // Copying from the instance column constrains every advice cell to its public input
+    fn public_inputs(&self, layouter: &mut impl Layouter<Fp>, instance: Column<Instance>, inputs: usize) -> Result<Vec<AssignedCell<Fp, Fp>>, Error> {
+        layouter.assign_region(|| "public inputs", |mut region| {
+            (0..inputs)
+                .map(|row| region.assign_advice_from_instance(|| "public input", instance, row, self.y, row))
+                .collect()
+        })
+    }
+
+    // Flags for the transaction counts 0..=MAX_CIRCUIT_TXS; fails to prove for any larger count
+    fn count(&self, layouter: &mut impl Layouter<Fp>, tx_count: &AssignedCell<Fp, Fp>, count: usize) -> Result<Vec<Word>, Error> {
+        layouter.assign_region(|| "transaction count", |mut region| {
+            self.count.enable(&mut region, 0)?;
+            tx_count.copy_advice(|| "count", &mut region, self.z, 0)?;
+            (0..=MAX_CIRCUIT_TXS)
+                .map(|n| {
+                    let value = (n == count) as u32;
+                    let cell = region.assign_advice(|| "flag", self.x, n, || Value::known(Fp::from(value as u64)))?;
+                    Ok(Word { cell, value })
+                })
+                .collect()
+        })
+    }
+
+    // Free words, range-checked where they are hashed
+    fn witness_words(&self, layouter: &mut impl Layouter<Fp>, values: &[u32]) -> Result<Vec<Word>, Error> {
+        layouter.assign_region(|| "witness words", |mut region| {
+            values.iter().enumerate()
+                .map(|(offset, value)| Ok(Word {
+                    cell: region.assign_advice(|| "word", self.x, offset, || Value::known(Fp::from(*value as u64)))?,
+                    value: *value,
+                }))
+                .collect()
+        })
+    }
+
+    fn constant_words(&self, layouter: &mut impl Layouter<Fp>, values: &[u32]) -> Result<Vec<Word>, Error> {
+        layouter.assign_region(|| "constant words", |mut region| {
+            values.iter().enumerate()
+                .map(|(offset, value)| Ok(Word {
+                    cell: region.assign_advice_from_constant(|| "word", self.x, offset, Fp::from(*value as u64))?,
+                    value: *value,
+                }))
+                .collect()
+        })
+    }
+
+    fn add(&self, layouter: &mut impl Layouter<Fp>, x: &Word, y: &Word) -> Result<Word, Error> {
+        layouter.assign_region(|| "add", |mut region| {
+            self.add.enable(&mut region, 0)?;
+            x.cell.copy_advice(|| "x", &mut region, self.x, 0)?;
+            y.cell.copy_advice(|| "y", &mut region, self.y, 0)?;
+            let value = x.value + y.value;
+            let cell = region.assign_advice(|| "z", self.z, 0, || Value::known(Fp::from(value as u64)))?;
+            Ok(Word { cell, value })
+        })
+    }
+
+    // Word by word, `x` where the flag is set and `y` where it is not
+    fn select(&self, layouter: &mut impl Layouter<Fp>, flag: &Word, x: &[Word], y: &[Word]) -> Result<Vec<Word>, Error> {
+        layouter.assign_region(|| "select", |mut region| {
+            x.iter().zip(y).enumerate()
+                .map(|(offset, (x, y))| {
+                    self.select.enable(&mut region, offset)?;
+                    flag.cell.copy_advice(|| "s", &mut region, self.s, offset)?;
+                    x.cell.copy_advice(|| "x", &mut region, self.x, offset)?;
+                    y.cell.copy_advice(|| "y", &mut region, self.y, offset)?;
+                    let value = if flag.value == 1 { x.value } else { y.value };
+                    let cell = region.assign_advice(|| "z", self.z, offset, || Value::known(Fp::from(value as u64)))?;
+                    Ok(Word { cell, value })
+                })
+                .collect()
+        })
+    }
+
+    // Word by word `sum + flag * words`; the sum of one-hot flagged words stays a word
+    fn mul_add(&self, layouter: &mut impl Layouter<Fp>, sum: &[Word], flag: &Word, words: &[Word]) -> Result<Vec<Word>, Error> {
+        layouter.assign_region(|| "mul add", |mut region| {
+            sum.iter().zip(words).enumerate()
+                .map(|(offset, (sum, word))| {
+                    self.mul_add.enable(&mut region, offset)?;
+                    sum.cell.copy_advice(|| "x", &mut region, self.x, offset)?;
+                    word.cell.copy_advice(|| "y", &mut region, self.y, offset)?;
+                    flag.cell.copy_advice(|| "s", &mut region, self.s, offset)?;
+                    let value = sum.value.wrapping_add(flag.value * word.value);
+                    let cell = region.assign_advice(|| "z", self.z, offset, || Value::known(Fp::from(value as u64)))?;
+                    Ok(Word { cell, value })
+                })
+                .collect()
+        })
+    }
+
+    // The word's first MAX_STATE_DEPTH bits, most significant first, as flags; range-checks it
+    fn bits(&self, layouter: &mut impl Layouter<Fp>, word: &Word) -> Result<Vec<Word>, Error> {
+        layouter.assign_region(|| "word bits", |mut region| {
+            self.bits.enable(&mut region, 0)?;
+            word.cell.copy_advice(|| "word", &mut region, self.z, 0)?;
+            let mut flags = Vec::with_capacity(32);
+            for offset in 0..32 {
+                let value = (word.value >> (31 - offset)) & 1;
+                let cell = region.assign_advice(|| "bit", self.x, offset, || Value::known(Fp::from(value as u64)))?;
+                flags.push(Word { cell, value });
+            }
+            flags.truncate(MAX_STATE_DEPTH);
+            Ok(flags)
+        })
+    }
+
+    // Free flags, constrained to 0 or 1 as the words with flag * flag = flag
+    fn flags(&self, layouter: &mut impl Layouter<Fp>, values: &[bool]) -> Result<Vec<Word>, Error> {
+        layouter.assign_region(|| "flags", |mut region| {
+            values.iter().enumerate()
+                .map(|(offset, value)| {
+                    self.mul_add.enable(&mut region, offset)?;
+                    region.assign_advice_from_constant(|| "x", self.x, offset, Fp::from(0))?;
+                    let value = *value as u32;
+                    let flag = Value::known(Fp::from(value as u64));
+                    let y = region.assign_advice(|| "y", self.y, offset, || flag)?;
+                    let s = region.assign_advice(|| "s", self.s, offset, || flag)?;
+                    let cell = region.assign_advice(|| "z", self.z, offset, || flag)?;
+                    region.constrain_equal(y.cell(), cell.cell())?;
+                    region.constrain_equal(s.cell(), cell.cell())?;
+                    Ok(Word { cell, value })
+                })
+                .collect()
+        })
+    }
+
+    fn and(&self, layouter: &mut impl Layouter<Fp>, x: &Word, y: &Word, constants: &StateConstants) -> Result<Word, Error> {
+        Ok(self.mul_add(layouter, slice::from_ref(&constants.zero), x, slice::from_ref(y))?.remove(0))
+    }
+
+    fn not(&self, layouter: &mut impl Layouter<Fp>, flag: &Word, constants: &StateConstants) -> Result<Word, Error> {
+        Ok(self.select(layouter, flag, slice::from_ref(&constants.zero), slice::from_ref(&constants.one))?.remove(0))
+    }
+
+    fn xor(&self, layouter: &mut impl Layouter<Fp>, x: &Word, y: &Word, constants: &StateConstants) -> Result<Word, Error> {
+        let not_y = self.not(layouter, y, constants)?;
+        Ok(self.select(layouter, x, &[not_y], slice::from_ref(y))?.remove(0))
+    }
+
+    fn assert_zero(&self, layouter: &mut impl Layouter<Fp>, word: &Word) -> Result<(), Error> {
+        layouter.assign_region(|| "zero", |mut region| {
+            let cell = word.cell.copy_advice(|| "x", &mut region, self.x, 0)?;
+            region.constrain_constant(cell.cell(), Fp::from(0))
+        })
+    }
+
+    // Word by word, `x` equals `y` where the flag is set: selecting `x` there has to give `y`
+    fn assert_equal_if(&self, layouter: &mut impl Layouter<Fp>, flag: &Word, x: &[Word], y: &[Word]) -> Result<(), Error> {
+        let selected = self.select(layouter, flag, x, y)?;
+        layouter.assign_region(|| "equal", |mut region| {
+            for (selected, y) in selected.iter().zip(y) {
+                region.constrain_equal(selected.cell.cell(), y.cell.cell())?;
+            }
+            Ok(())
+        })
+    }
+
+    // Constrains four words to be the big endian 128-bit value of `packed`
+    fn pack(&self, layouter: &mut impl Layouter<Fp>, words: &[Word], packed: &AssignedCell<Fp, Fp>) -> Result<(), Error> {
+        layouter.assign_region(|| "pack", |mut region| {
+            self.pack.enable(&mut region, 0)?;
+            packed.copy_advice(|| "packed", &mut region, self.z, 0)?;
+            for (offset, word) in words.iter().enumerate() {
+                word.cell.copy_advice(|| "word", &mut region, self.x, offset)?;
+            }
+            Ok(())
+        })
+    }
+}
+
+// SHA-256 round constants and initial hash value
+const ROUND_CONSTANTS: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+const INITIAL_STATE: [u32; 8] = [
+    0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+];
+
+const ROUNDS: usize = 64;
+// a, b, c, d (and e, f, g, h) are the last four values of a (and e)
+const STATE_ROWS: usize = 4;
+const COMPRESSION_ROWS: usize = ROUNDS + 2 * STATE_ROWS;
+
+#[derive(Clone, Copy)]
+enum Shift {
+    Rotate(usize),
+    Right(usize),
+}
+
+const BIG_SIGMA_0: [Shift; 3] = [Shift::Rotate(2), Shift::Rotate(13), Shift::Rotate(22)];
+const BIG_SIGMA_1: [Shift; 3] = [Shift::Rotate(6), Shift::Rotate(11), Shift::Rotate(25)];
+const SMALL_SIGMA_0: [Shift; 3] = [Shift::Rotate(7), Shift::Rotate(18), Shift::Right(3)];
+const SMALL_SIGMA_1: [Shift; 3] = [Shift::Rotate(17), Shift::Rotate(19), Shift::Right(10)];
+
+// Columns holding one word per row with each of its bits in a column of its own, so the
+// rotations and shifts of the sigma functions are a choice of columns
+#[derive(Clone, Debug)]
+struct WordColumns {
+    bits: [Column<Advice>; 32],
+    word: Column<Advice>,
+    decompose: Selector,
+}
+
+impl WordColumns {
+    fn configure(meta: &mut ConstraintSystem<Fp>) -> Self {
+        let bits = [(); 32].map(|_| meta.advice_column());
+        let word = meta.advice_column();
+        meta.enable_equality(word);
+        let decompose = meta.selector();
+
+        meta.create_gate("word bits", |meta| {
+            let selector = meta.query_selector(decompose);
+            let bits = bits.map(|bit| meta.query_advice(bit, Rotation::cur()));
+            let word = meta.query_advice(word, Rotation::cur());
+            let mut constraints: Vec<_> = bits.iter().map(|bit| selector.clone() * boolean(bit)).collect();
+            constraints.push(selector * (pack(&bits) - word));
+            constraints
+        });
+
+        Self { bits, word, decompose }
+    }
+
+    fn query_bits(&self, meta: &mut VirtualCells<'_, Fp>, rotation: i32) -> Vec<Expression<Fp>> {
+        self.bits.iter().map(|bit| meta.query_advice(*bit, Rotation(rotation))).collect()
+    }
+
+    fn query_word(&self, meta: &mut VirtualCells<'_, Fp>, rotation: i32) -> Expression<Fp> {
+        meta.query_advice(self.word, Rotation(rotation))
+    }
+
+    fn assign(&self, region: &mut Region<'_, Fp>, offset: usize, value: u32) -> Result<Word, Error> {
+        self.decompose.enable(region, offset)?;
+        assign_bits(region, &self.bits, offset, value as u64)?;
+        let cell = region.assign_advice(|| "word", self.word, offset, || Value::known(Fp::from(value as u64)))?;
+        Ok(Word { cell, value })
+    }
+}
+
+// One SHA-256 compression per region of COMPRESSION_ROWS rows. Rows 0..4 hold the input state,
+// round t is row t + 3 and writes the new a and e to the row below it, and rows 68..72 hold the
+// output state. Only a and e are computed, as b, c, d and f, g, h are the rows above them; the
+// message schedule word of a round sits in its row
+#[derive(Clone, Debug)]
+struct Sha256Config {
+    a: WordColumns,
+    e: WordColumns,
+    w: WordColumns,
+    // Carries out of the 32-bit sums, as bits
+    a_carry: [Column<Advice>; 3],
+    e_carry: [Column<Advice>; 3],
+    w_carry: [Column<Advice>; 2],
+    round_constant: Column<Fixed>,
+    round: Selector,
+    schedule: Selector,
+    feed_forward: Selector,
+}
+
+impl Sha256Config {
+    fn configure(meta: &mut ConstraintSystem<Fp>) -> Self {
+        let a = WordColumns::configure(meta);
+        let e = WordColumns::configure(meta);
+        let w = WordColumns::configure(meta);
+        let a_carry = [(); 3].map(|_| meta.advice_column());
+        let e_carry = [(); 3].map(|_| meta.advice_column());
+        let w_carry = [(); 2].map(|_| meta.advice_column());
+        let round_constant = meta.fixed_column();
+        let [round, schedule, feed_forward] = [(); 3].map(|_| meta.selector());
+
+        // new e = d + T1 and new a = T1 + T2, with
+        // T1 = h + Σ1(e) + Ch(e, f, g) + K[t] + W[t] and T2 = Σ0(a) + Maj(a, b, c)
+        meta.create_gate("round", |meta| {
+            let selector = meta.query_selector(round);
+            let [a_bits, b_bits, c_bits] = [0, -1, -2].map(|rotation| a.query_bits(meta, rotation));
+            let [e_bits, f_bits, g_bits] = [0, -1, -2].map(|rotation| e.query_bits(meta, rotation));
+            let d = a.query_word(meta, -3);
+            let h = e.query_word(meta, -3);
+            let new_a = a.query_word(meta, 1);
+            let new_e = e.query_word(meta, 1);
+            let a_carry = query_carry(meta, &a_carry);
+            let e_carry = query_carry(meta, &e_carry);
+            let round_constant = meta.query_fixed(round_constant);
+            let w = w.query_word(meta, 0);
+
+            let choose = pack(&(0..32)
+                .map(|i| e_bits[i].clone() * f_bits[i].clone() + (one() - e_bits[i].clone()) * g_bits[i].clone())
+                .collect::<Vec<_>>());
+            let majority = pack(&(0..32)
+                .map(|i| {
+                    let (a, b, c) = (a_bits[i].clone(), b_bits[i].clone(), c_bits[i].clone());
+                    a.clone() * b.clone() + a.clone() * c.clone() + b.clone() * c.clone() - a * b * c * Fp::from(2)
+                })
+                .collect::<Vec<_>>());
+            let t1 = h + sigma(&e_bits, &BIG_SIGMA_1) + choose + round_constant + w;
+            let t2 = sigma(&a_bits, &BIG_SIGMA_0) + majority;
+
+            let mut constraints = vec![
+                selector.clone() * (new_e + e_carry.value * word_modulus() - d - t1.clone()),
+                selector.clone() * (new_a + a_carry.value * word_modulus() - t1 - t2),
+            ];
+            constraints.extend(a_carry.bits.iter().chain(&e_carry.bits).map(|bit| selector.clone() * boolean(bit)));
+            constraints
+        });
+
+        // W[t] = σ1(W[t - 2]) + W[t - 7] + σ0(W[t - 15]) + W[t - 16]
+        meta.create_gate("message schedule", |meta| {
+            let selector = meta.query_selector(schedule);
+            let sum = sigma(&w.query_bits(meta, -2), &SMALL_SIGMA_1) + w.query_word(meta, -7)
+                + sigma(&w.query_bits(meta, -15), &SMALL_SIGMA_0) + w.query_word(meta, -16);
+            let new_w = w.query_word(meta, 0);
+            let carry = query_carry(meta, &w_carry);
+
+            let mut constraints = vec![selector.clone() * (new_w + carry.value * word_modulus() - sum)];
+            constraints.extend(carry.bits.iter().map(|bit| selector.clone() * boolean(bit)));
+            constraints
+        });
+
+        // Output state = input state + final state, word by word
+        meta.create_gate("feed forward", |meta| {
+            let selector = meta.query_selector(feed_forward);
+            let first = -(COMPRESSION_ROWS as i32 - STATE_ROWS as i32);
+            let mut constraints = Vec::new();
+            for (columns, carry) in [(&a, &a_carry[..]), (&e, &e_carry[..])] {
+                let carry = query_carry(meta, carry);
+                let sum = columns.query_word(meta, first) + columns.query_word(meta, -(STATE_ROWS as i32));
+                constraints.push(selector.clone() * (columns.query_word(meta, 0) + carry.value * word_modulus() - sum));
+                constraints.extend(carry.bits.iter().map(|bit| selector.clone() * boolean(bit)));
+            }
+            constraints
+        });
+
+        Self { a, e, w, a_carry, e_carry, w_carry, round_constant, round, schedule, feed_forward }
+    }
+
+    // Compresses one 16-word block into the state, both given and returned as H0..H7
+    fn compress(&self, layouter: &mut impl Layouter<Fp>, state: &[Word], block: &[Word]) -> Result<Vec<Word>, Error> {
+        let input: Vec<u32> = state.iter().map(|word| word.value).collect();
+        let message: Vec<u32> = block.iter().map(|word| word.value).collect();
+        let trace = CompressionTrace::new(&input, &message);
+
+        layouter.assign_region(|| "sha256 compression", |mut region| {
+            for row in 0..STATE_ROWS {
+                let a = self.a.assign(&mut region, row, trace.a[row])?;
+                region.constrain_equal(a.cell.cell(), state[STATE_ROWS - 1 - row].cell.cell())?;
+                let e = self.e.assign(&mut region, row, trace.e[row])?;
+                region.constrain_equal(e.cell.cell(), state[2 * STATE_ROWS - 1 - row].cell.cell())?;
+            }
+
+            for (t, constant) in ROUND_CONSTANTS.iter().enumerate() {
+                let row = t + STATE_ROWS - 1;
+                self.round.enable(&mut region, row)?;
+                region.assign_fixed(|| "round constant", self.round_constant, row, || Value::known(Fp::from(*constant as u64)))?;
+                let w = self.w.assign(&mut region, row, trace.w[t])?;
+                match block.get(t) {
+                    Some(word) => region.constrain_equal(w.cell.cell(), word.cell.cell())?,
+                    None => {
+                        self.schedule.enable(&mut region, row)?;
+                        assign_bits(&mut region, &self.w_carry, row, trace.w_carry[t])?;
+                    }
+                }
+                assign_bits(&mut region, &self.a_carry, row, trace.a_carry[t])?;
+                assign_bits(&mut region, &self.e_carry, row, trace.e_carry[t])?;
+                self.a.assign(&mut region, row + 1, trace.a[t + STATE_ROWS])?;
+                self.e.assign(&mut region, row + 1, trace.e[t + STATE_ROWS])?;
+            }
+
+            let mut output = vec![None; 2 * STATE_ROWS];
+            for row in 0..STATE_ROWS {
+                let offset = COMPRESSION_ROWS - STATE_ROWS + row;
+                self.feed_forward.enable(&mut region, offset)?;
+                for (columns, carry, sequence, position) in [
+                    (&self.a, &self.a_carry[..], &trace.a, STATE_ROWS - 1 - row),
+                    (&self.e, &self.e_carry[..], &trace.e, 2 * STATE_ROWS - 1 - row),
+                ] {
+                    let sum = sequence[row] as u64 + sequence[ROUNDS + row] as u64;
+                    assign_bits(&mut region, carry, offset, sum >> 32)?;
+                    output[position] = Some(columns.assign(&mut region, offset, sum as u32)?);
+                }
+            }
+            Ok(output.into_iter().flatten().collect())
+        })
+    }
+}
+
+// Values of a compression: a and e of every round, starting with the input state's d, c, b, a
+// and h, g, f, e, the message schedule, and the carries of their sums
+struct CompressionTrace {
+    a: Vec<u32>,
+    e: Vec<u32>,
+    w: Vec<u32>,
+    a_carry: Vec<u64>,
+    e_carry: Vec<u64>,
+    w_carry: Vec<u64>,
+}
+
+impl CompressionTrace {
+    fn new(state: &[u32], block: &[u32]) -> Self {
+        let mut w = block.to_vec();
+        let mut w_carry = vec![0; 16];
+        for t in 16..ROUNDS {
+            let sum = word_sigma(w[t - 2], &SMALL_SIGMA_1) as u64 + w[t - 7] as u64
+                + word_sigma(w[t - 15], &SMALL_SIGMA_0) as u64 + w[t - 16] as u64;
+            w.push(sum as u32);
+            w_carry.push(sum >> 32);
+        }
+
+        let mut a: Vec<u32> = state[..4].iter().rev().copied().collect();
+        let mut e: Vec<u32> = state[4..].iter().rev().copied().collect();
+        let (mut a_carry, mut e_carry) = (Vec::with_capacity(ROUNDS), Vec::with_capacity(ROUNDS));
+        for t in 0..ROUNDS {
+            let (a0, b, c, d) = (a[t + 3], a[t + 2], a[t + 1], a[t]);
+            let (e0, f, g, h) = (e[t + 3], e[t + 2], e[t + 1], e[t]);
+            let choose = (e0 & f) ^ (!e0 & g);
+            let majority = (a0 & b) ^ (a0 & c) ^ (b & c);
+            let t1 = h as u64 + word_sigma(e0, &BIG_SIGMA_1) as u64 + choose as u64 + ROUND_CONSTANTS[t] as u64 + w[t] as u64;
+            let t2 = word_sigma(a0, &BIG_SIGMA_0) as u64 + majority as u64;
+
+            let new_e = d as u64 + t1;
+            e.push(new_e as u32);
+            e_carry.push(new_e >> 32);
+            let new_a = t1 + t2;
+            a.push(new_a as u32);
+            a_carry.push(new_a >> 32);
+        }
+
+        Self { a, e, w, a_carry, e_carry, w_carry }
+    }
+}
+
+struct Carry {
+    bits: Vec<Expression<Fp>>,
+    value: Expression<Fp>,
+}
+
+fn query_carry(meta: &mut VirtualCells<'_, Fp>, columns: &[Column<Advice>]) -> Carry {
+    let bits: Vec<_> = columns.iter().map(|column| meta.query_advice(*column, Rotation::cur())).collect();
+    let value = pack(&bits);
+    Carry { bits, value }
+}
+
+fn assign_bits(region: &mut Region<'_, Fp>, columns: &[Column<Advice>], offset: usize, value: u64) -> Result<(), Error> {
+    for (i, column) in columns.iter().enumerate() {
+        region.assign_advice(|| "bit", *column, offset, || Value::known(Fp::from((value >> i) & 1)))?;
+    }
+    Ok(())
+}
+
+fn one() -> Expression<Fp> {
+    Expression::Constant(Fp::from(1))
+}
+
+fn word_modulus() -> Expression<Fp> {
+    Expression::Constant(Fp::from(1u64 << 32))
+}
+
+fn boolean(bit: &Expression<Fp>) -> Expression<Fp> {
+    bit.clone() * (one() - bit.clone())
+}
+
+// Little endian bits to their value
+fn pack(bits: &[Expression<Fp>]) -> Expression<Fp> {
+    bits.iter().enumerate().fold(Expression::Constant(Fp::from(0)), |sum, (i, bit)| {
+        sum + bit.clone() * Fp::from(1u64 << i)
+    })
+}
+
+fn xor(a: Expression<Fp>, b: Expression<Fp>) -> Expression<Fp> {
+    a.clone() + b.clone() - a * b * Fp::from(2)
+}
+
+// The exclusive or of the three shifts of a word, from its bits
+fn sigma(bits: &[Expression<Fp>], shifts: &[Shift; 3]) -> Expression<Fp> {
+    let bits: Vec<_> = (0..32)
+        .map(|i| {
+            shifts.iter()
+                .filter_map(|shift| match *shift {
+                    Shift::Rotate(n) => Some(bits[(i + n) % 32].clone()),
+                    Shift::Right(n) => bits.get(i + n).cloned(),
+                })
+                .reduce(xor)
+                .unwrap()
+        })
+        .collect();
+    pack(&bits)
+}
+
+fn word_sigma(word: u32, shifts: &[Shift; 3]) -> u32 {
+    shifts.iter().fold(0, |sigma, shift| sigma ^ match *shift {
+        Shift::Rotate(n) => word.rotate_right(n as u32),
+        Shift::Right(n) => word >> n,
+    })
+}
+
+// A digest as the big endian words SHA-256 outputs it in
+fn digest_words(hash: &[u8; 32]) -> Vec<u32> {
+    hash.chunks(4).map(|chunk| u32::from_be_bytes(chunk.try_into().unwrap())).collect()
+}
+
+// IPA commitments over the Pasta curves need no trusted setup: every node derives the same parameters
+pub struct PlonkBackend {
+    params: Params<EqAffine>,
+    block: CircuitKeys,
+}
+
+struct CircuitKeys {
+    // Only provers derive it
+    proving_key: Option<ProvingKey<EqAffine>>,
+    verifying_key: VerifyingKey<EqAffine>,
+    verifying_key_hash: [u8; 32],
+}
+
+impl CircuitKeys {
+    fn generate(params: &Params<EqAffine>, circuit: impl Circuit<Fp>, proving: bool) -> Result<Self> {
+        let verifying_key = keygen_vk(params, &circuit)
+            .map_err(|e| anyhow!("PLONK verifying key generation failed: {:?}", e))?;
+        let proving_key = match proving {
+            true => Some(keygen_pk(params, verifying_key.clone(), &circuit)
+                .map_err(|e| anyhow!("PLONK proving key generation failed: {:?}", e))?),
+            false => None,
+        };
+
+        // The pinned key covers the domain, constraint system and fixed commitments
+        let verifying_key_hash = Sha256::digest(format!("{:?}", verifying_key.pinned())).into();
+
+        Ok(Self {
+            proving_key,
+            verifying_key,
+            verifying_key_hash,
+        })
+    }
+
+    fn prove(&self, params: &Params<EqAffine>, circuit: impl Circuit<Fp>, inputs: &[Fp]) -> Result<Vec<u8>> {
+        let proving_key = match &self.proving_key {
+            Some(proving_key) => proving_key,
+            None => bail!("This node holds no PLONK proving key"),
+        };
+        let mut transcript = Blake2bWrite::<_, EqAffine, Challenge255<_>>::init(vec![]);
+        create_proof(
+            params,
+            proving_key,
+            &[circuit],
+            &[&[inputs]],
+            rand::rngs::OsRng,
+            &mut transcript,
+        ).map_err(|e| anyhow!("PLONK proving failed: {:?}", e))?;
+
+        Ok(transcript.finalize())
+    }
+
+    fn verify(&self, params: &Params<EqAffine>, inputs: Option<Vec<Fp>>, proof_data: &[u8]) -> bool {
+        let inputs = match inputs {
+            Some(inputs) => inputs,
+            None => return false,
+        };
+
+        let mut transcript = Blake2bRead::<_, EqAffine, Challenge255<_>>::init(proof_data);
+        let strategy = SingleVerifier::new(params);
+        verify_proof(params, &self.verifying_key, strategy, &[&[&inputs]], &mut transcript).is_ok()
+    }
+}
+
+impl PlonkBackend {
+    pub fn setup(keys: &KeySource) -> Result<Self> {
+        info!("🔐 Running PLONK setup for BlockValidationCircuit (max {} txs, {} account updates)",
+            MAX_CIRCUIT_TXS, MAX_STATE_UPDATES);
+
+        let params = Params::<EqAffine>::new(K);
+        let block = CircuitKeys::generate(&params, BlockValidationCircuit::blank(), keys.proving())?;
+
+        info!("✅ PLONK keys ready (vk {})", hex::encode(block.verifying_key_hash));
+
+        Ok(Self { params, block })
+    }
+}
+
+impl ProofBackend for PlonkBackend {
+    fn proof_type(&self) -> ProofType {
+        ProofType::Plonk
+    }
+
+    fn verifying_key_hash(&self) -> [u8; 32] {
+        self.block.verifying_key_hash
+    }
+
+    fn max_transactions(&self) -> usize {
+        MAX_CIRCUIT_TXS
+    }
+
+    fn state_capacity(&self) -> StateCapacity {
+        StateCapacity { updates: MAX_STATE_UPDATES, depth: MAX_STATE_DEPTH }
+    }
+
+    // The circuit recomputes both trees with SHA-256
+    fn prove(&self, block: &Block, inputs: &PublicInputs, state: &StateTransition) -> Result<Vec<u8>> {
+        if types::merkle_hash_function() != HashFunction::Sha256 {
+            bail!("PLONK block proofs require the sha256 merkle hash function, the chain uses {:?}",
+                types::merkle_hash_function());
+        }
+        self.block.prove(&self.params, BlockValidationCircuit::from_block(block, state)?, &public_input_elements(inputs))
+    }
+
+    fn verify(&self, inputs: &PublicInputs, proof_data: &[u8]) -> Result<bool> {
+        Ok(self.block.verify(&self.params, Some(public_input_elements(inputs)), proof_data))
+    }
+}
+
+fn public_input_elements(inputs: &PublicInputs) -> Vec<Fp> {
+    inputs.field_elements().into_iter().map(Fp::from_u128).collect()
+}
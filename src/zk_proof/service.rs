@@ -0,0 +1,186 @@
+use crate::config::ZkProofConfig;
+use crate::state::StateTransition;
+use crate::types::{Block, ZKProof};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tokio::sync::{oneshot, Mutex, RwLock};
+use tracing::{info, debug, warn};
+
+use super::{ProofError, PublicInputs, Result, ZKProofGenerator};
+
+pub type JobId = u64;
+
+// Finished jobs kept for status queries; older ones are forgotten
+const MAX_FINISHED_JOBS: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Done,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct JobInfo {
+    pub id: JobId,
+    pub block_number: u64,
+    pub status: JobStatus,
+    pub error: Option<String>,
+    pub queued_at: DateTime<Utc>,
+    pub started_at: Option<DateTime<Utc>>,
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+struct Job {
+    id: JobId,
+    block: Block,
+    inputs: PublicInputs,
+    state: StateTransition,
+    result_tx: oneshot::Sender<Result<ZKProof>>,
+}
+
+// A queued proof; dropping it before the result arrives does not cancel a running job
+pub struct ProofJob {
+    pub id: JobId,
+    result: oneshot::Receiver<Result<ZKProof>>,
+}
+
+impl ProofJob {
+    pub async fn wait(&mut self) -> Result<ZKProof> {
+        (&mut self.result).await.map_err(|_| ProofError::JobDropped(self.id))?
+    }
+}
+
+// Block proofs take seconds to minutes with real circuits, so they are generated on a pool of
+// blocking worker threads fed from a bounded queue instead of on the async runtime
+#[derive(Clone)]
+pub struct ProofService {
+    queue: mpsc::Sender<Job>,
+    jobs: Arc<RwLock<BTreeMap<JobId, JobInfo>>>,
+    next_id: Arc<AtomicU64>,
+}
+
+impl ProofService {
+    pub fn start(zk_generator: Arc<ZKProofGenerator>, config: &ZkProofConfig) -> Self {
+        let (queue, rx) = mpsc::channel(config.queue_size);
+        let rx = Arc::new(Mutex::new(rx));
+        let jobs = Arc::new(RwLock::new(BTreeMap::new()));
+
+        for worker in 0..config.workers {
+            tokio::spawn(run_worker(worker, zk_generator.clone(), rx.clone(), jobs.clone()));
+        }
+        info!("👷 Started {} proof workers (queue of {} jobs)", config.workers, config.queue_size);
+
+        Self {
+            queue,
+            jobs,
+            next_id: Arc::new(AtomicU64::new(1)),
+        }
+    }
+
+    // Fails right away instead of waiting when the queue is full
+    pub async fn submit(&self, block: Block, inputs: PublicInputs, state: StateTransition) -> Result<ProofJob> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (result_tx, result) = oneshot::channel();
+
+        // Tracked before queueing so a worker always finds the job it picks up
+        self.jobs.write().await.insert(id, JobInfo {
+            id,
+            block_number: block.header.block_number,
+            status: JobStatus::Queued,
+            error: None,
+            queued_at: Utc::now(),
+            started_at: None,
+            finished_at: None,
+        });
+
+        match self.queue.try_send(Job { id, block, inputs, state, result_tx }) {
+            Ok(()) => Ok(ProofJob { id, result }),
+            Err(e) => {
+                self.jobs.write().await.remove(&id);
+                match e {
+                    TrySendError::Full(_) => Err(ProofError::QueueFull),
+                    TrySendError::Closed(_) => Err(ProofError::Stopped),
+                }
+            }
+        }
+    }
+
+    pub async fn prove(&self, block: Block, inputs: PublicInputs, state: StateTransition) -> Result<ZKProof> {
+        self.submit(block, inputs, state).await?.wait().await
+    }
+
+    // Jobs waiting for a worker and jobs being proven
+    pub async fn backlog(&self) -> (usize, usize) {
+        let jobs = self.jobs.read().await;
+        let count = |status| jobs.values().filter(|info| info.status == status).count();
+        (count(JobStatus::Queued), count(JobStatus::Running))
+    }
+
+    // Newest first
+    pub async fn jobs(&self) -> Vec<JobInfo> {
+        self.jobs.read().await.values().rev().cloned().collect()
+    }
+}
+
+async fn run_worker(
+    worker: usize,
+    zk_generator: Arc<ZKProofGenerator>,
+    rx: Arc<Mutex<mpsc::Receiver<Job>>>,
+    jobs: Arc<RwLock<BTreeMap<JobId, JobInfo>>>,
+) {
+    loop {
+        // Idle workers take turns waiting on the queue
+        let job = match rx.lock().await.recv().await {
+            Some(job) => job,
+            None => return,
+        };
+
+        if let Some(info) = jobs.write().await.get_mut(&job.id) {
+            info.status = JobStatus::Running;
+            info.started_at = Some(Utc::now());
+        }
+        debug!("Proof worker {} proving block #{} (job {})", worker, job.block.header.block_number, job.id);
+
+        // Proving is CPU-bound; keep it off the async workers
+        let zk_generator = zk_generator.clone();
+        let (block, inputs, state) = (job.block, job.inputs, job.state);
+        let result = tokio::task::spawn_blocking(move || {
+            tokio::runtime::Handle::current().block_on(zk_generator.generate_proof(&block, &inputs, &state))
+        }).await.unwrap_or_else(|e| Err(ProofError::Prover(anyhow::anyhow!("Proof worker panicked: {}", e))));
+
+        finish(&jobs, job.id, &result).await;
+        if job.result_tx.send(result).is_err() {
+            debug!("Nobody is waiting for proof job {} anymore", job.id);
+        }
+    }
+}
+
+async fn finish(jobs: &RwLock<BTreeMap<JobId, JobInfo>>, id: JobId, result: &Result<ZKProof>) {
+    let mut jobs = jobs.write().await;
+    if let Some(info) = jobs.get_mut(&id) {
+        info.finished_at = Some(Utc::now());
+        match result {
+            Ok(_) => info.status = JobStatus::Done,
+            Err(e) => {
+                warn!("❌ Proof job {} for block #{} failed: {}", id, info.block_number, e);
+                info.status = JobStatus::Failed;
+                info.error = Some(e.to_string());
+            }
+        }
+    }
+
+    let finished: Vec<JobId> = jobs.values()
+        .filter(|info| info.finished_at.is_some())
+        .map(|info| info.id)
+        .collect();
+    for id in finished.iter().take(finished.len().saturating_sub(MAX_FINISHED_JOBS)) {
+        jobs.remove(id);
+    }
+}
@@ -0,0 +1,132 @@
+use super::keys::{CircuitKey, VerifyingKeys};
+use super::{mock_proof, parse_chain_inputs, ProofBackend, ProofError, PublicInputs, Result};
+use crate::types::{ProofType, ZKProof};
+use sha2::{Sha256, Digest};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tracing::warn;
+
+// Checks proofs of one proof system. Callers check first that the public inputs commit to the
+// block, header or chain the proof is taken for; a verifier only checks the proof holds for them
+// under the registered keys of the circuit version it names
+pub trait ProofVerifier: Send + Sync {
+    fn verify(&self, zk_proof: &ZKProof, key: &CircuitKey) -> Result<bool>;
+}
+
+// The verifier for each proof type this node can check. Proofs of any other type are
+// rejected with ProofError::Unsupported instead of being judged valid or invalid
+#[derive(Default)]
+pub struct VerifierRegistry {
+    verifiers: HashMap<ProofType, Box<dyn ProofVerifier>>,
+    keys: VerifyingKeys,
+}
+
+impl VerifierRegistry {
+    // Each backend verifies its own proof type. Without any backend, block and header proofs of
+    // both block proof systems get mock verification, and chain proofs get it until a Nova
+    // verifier replaces it
+    pub fn new(backends: &[Arc<dyn ProofBackend>]) -> Self {
+        let mut registry = Self::default();
+        for backend in backends {
+            registry.register(backend.proof_type(), Box::new(BackendVerifier(backend.clone())));
+        }
+        if backends.is_empty() {
+            registry.register(ProofType::Groth16, Box::new(MockVerifier));
+            registry.register(ProofType::Plonk, Box::new(MockVerifier));
+        }
+        registry.register(ProofType::Nova, Box::new(MockChainVerifier));
+        registry
+    }
+
+    // Replaces the verifier registered for the type, if any
+    pub fn register(&mut self, proof_type: ProofType, verifier: Box<dyn ProofVerifier>) {
+        self.verifiers.insert(proof_type, verifier);
+    }
+
+    pub fn supports(&self, proof_type: &ProofType) -> bool {
+        self.verifiers.contains_key(proof_type)
+    }
+
+    pub fn keys(&self) -> &VerifyingKeys {
+        &self.keys
+    }
+
+    // Replaces every registered key
+    pub fn set_keys(&mut self, keys: VerifyingKeys) {
+        self.keys = keys;
+    }
+
+    pub fn verify(&self, zk_proof: &ZKProof) -> Result<bool> {
+        let verifier = match self.verifiers.get(&zk_proof.proof_type) {
+            Some(verifier) => verifier,
+            None => return Err(ProofError::Unsupported(zk_proof.proof_type.clone())),
+        };
+        let key = match self.keys.get(&zk_proof.proof_type, zk_proof.circuit_version) {
+            Some(key) => key,
+            None => {
+                warn!("❌ {:?} proof names unknown circuit version {}", zk_proof.proof_type, zk_proof.circuit_version);
+                return Ok(false);
+            }
+        };
+        verifier.verify(zk_proof, key)
+    }
+}
+
+// Block proofs of a compiled-in backend. The backend only holds the keys it set up,
+// so proofs of circuit versions registered with other keys can not be checked and are invalid
+struct BackendVerifier(Arc<dyn ProofBackend>);
+
+impl ProofVerifier for BackendVerifier {
+    fn verify(&self, zk_proof: &ZKProof, key: &CircuitKey) -> Result<bool> {
+        let backend = &self.0;
+        let inputs = match PublicInputs::from_bytes(&zk_proof.public_inputs) {
+            Ok(inputs) => inputs,
+            Err(_) => return Ok(false),
+        };
+
+        Ok(key.key == backend.verifying_key_hash()
+            && backend.verify(&inputs, &zk_proof.proof_data).map_err(ProofError::Verifier)?)
+    }
+}
+
+// Mock block proofs only have to be well-formed
+struct MockVerifier;
+
+impl ProofVerifier for MockVerifier {
+    fn verify(&self, zk_proof: &ZKProof, _: &CircuitKey) -> Result<bool> {
+        Ok(PublicInputs::from_bytes(&zk_proof.public_inputs).is_ok()
+            && zk_proof.proof_data.len() >= 64) // Minimum proof size
+    }
+}
+
+// Mock chain proofs carry the hash chain accumulator and a proof recomputed from it
+struct MockChainVerifier;
+
+impl ProofVerifier for MockChainVerifier {
+    fn verify(&self, zk_proof: &ZKProof, _: &CircuitKey) -> Result<bool> {
+        let (height, acc) = match parse_chain_inputs(zk_proof) {
+            Some(position) => position,
+            None => return Ok(false),
+        };
+
+        Ok(height > 0
+            && acc != [0; 32]
+            && zk_proof.proof_data == mock_proof(&Sha256::digest(&zk_proof.public_inputs).into()))
+    }
+}
+
+#[cfg(feature = "nova")]
+pub struct NovaVerifier(pub Arc<super::nova::NovaBackend>);
+
+#[cfg(feature = "nova")]
+impl ProofVerifier for NovaVerifier {
+    fn verify(&self, zk_proof: &ZKProof, key: &CircuitKey) -> Result<bool> {
+        let (height, acc) = match parse_chain_inputs(zk_proof) {
+            Some(position) => position,
+            None => return Ok(false),
+        };
+
+        Ok(key.key == self.0.params_digest()
+            && self.0.verify(height, &acc, &zk_proof.proof_data).map_err(ProofError::Verifier)?)
+    }
+}
@@ -0,0 +1,62 @@
+use crate::state::{Account, AccountId, StateTransition};
+use crate::types::BlockHash;
+use anyhow::{bail, Result};
+
+// One account update of a state transition, laid out for a circuit of fixed shape. The paths
+// run from the root down: the old one to where the account is, or would be, and the new one to
+// its leaf. They are the same unless a missing account takes its neighbour's place, when the
+// new one goes on past the old one's end to where their ids first differ, with empty subtrees
+// beside it and the neighbour's leaf at the bottom
+#[derive(Debug, Clone, Default)]
+pub struct UpdateWitness {
+    // Padding updates leave the root as it is
+    pub used: bool,
+    pub account: AccountId,
+    // None when the account is missing
+    pub before: Option<Account>,
+    pub after: Account,
+    pub neighbour: Option<(AccountId, Account)>,
+    // Siblings of the old path, padded with empty subtrees
+    pub siblings: Vec<BlockHash>,
+    pub old_depth: usize,
+    pub new_depth: usize,
+}
+
+impl UpdateWitness {
+    pub fn padding(max_depth: usize) -> Self {
+        Self { siblings: vec![[0; 32]; max_depth], ..Self::default() }
+    }
+}
+
+// The transition's updates padded to `max_updates`, with paths of `max_depth` levels. Fails for
+// transitions the circuit can not take
+pub fn update_witnesses(state: &StateTransition, max_updates: usize, max_depth: usize) -> Result<Vec<UpdateWitness>> {
+    if state.updates.len() > max_updates {
+        bail!("Block changes {} accounts, circuit supports at most {}", state.updates.len(), max_updates);
+    }
+
+    let mut witnesses = Vec::with_capacity(max_updates);
+    for update in &state.updates {
+        let proof = &update.proof;
+        let new_depth = proof.updated_depth();
+        if new_depth > max_depth {
+            bail!("Account {} is {} levels deep in the state tree, circuit supports at most {}",
+                hex::encode(proof.account), new_depth, max_depth);
+        }
+
+        let mut siblings = proof.siblings.clone();
+        siblings.resize(max_depth, [0; 32]);
+        witnesses.push(UpdateWitness {
+            used: true,
+            account: proof.account,
+            before: proof.state,
+            after: update.state,
+            neighbour: proof.neighbour,
+            siblings,
+            old_depth: proof.siblings.len(),
+            new_depth,
+        });
+    }
+    witnesses.resize_with(max_updates, || UpdateWitness::padding(max_depth));
+    Ok(witnesses)
+}
@@ -0,0 +1,53 @@
+// Simulated networks of four validators, one of them faulty. The simulation itself fails when
+// two nodes finalize different blocks at the same height, which covers safety; the three honest
+// validators hold more than 2/3 of the stake, so they have to keep finalizing blocks too
+#![cfg(feature = "testing")]
+
+use std::path::PathBuf;
+use zk_consensus::testing::{simulate, SimulationConfig};
+
+const SEEDS: [u64; 3] = [1, 2, 3];
+// Virtual seconds per run; an honest network finalizes a block about every 12
+const DURATION: u64 = 120;
+// Highest block every node has to have finalized by the end of a run
+const MIN_FINALIZED: u64 = 5;
+
+fn db_dir(byzantine: &str, seed: u64) -> PathBuf {
+    std::env::temp_dir().join(format!("zk_consensus-{}-{}-{}", byzantine, seed, std::process::id()))
+}
+
+async fn run_with(byzantine: &str) {
+    for seed in SEEDS {
+        let config = SimulationConfig {
+            seed,
+            nodes: 4,
+            duration: DURATION,
+            max_delay_ms: 500,
+            drop_rate: 0.0,
+            byzantine: Some(byzantine.to_string()),
+            db_dir: db_dir(byzantine, seed),
+        };
+        let report = simulate(&config).await;
+        let _ = std::fs::remove_dir_all(&config.db_dir);
+
+        let report = match report {
+            Ok(report) => report,
+            Err(e) => panic!("seed {} with a validator that does {}: {:#}", seed, byzantine, e),
+        };
+        assert!(
+            report.finalized >= MIN_FINALIZED,
+            "seed {} with a validator that does {}: only block #{} was finalized in {}s",
+            seed, byzantine, report.finalized, DURATION,
+        );
+    }
+}
+
+#[tokio::test]
+async fn equivocating_validator_keeps_safety_and_liveness() {
+    run_with("equivocate").await;
+}
+
+#[tokio::test]
+async fn withholding_validator_keeps_safety_and_liveness() {
+    run_with("withhold-votes").await;
+}